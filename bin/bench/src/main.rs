@@ -0,0 +1,175 @@
+//! Renders a standardized stress scene along a fixed camera path for a set number of frames, and
+//! reports frame time percentiles, so that performance can be compared across commits.
+//!
+//! Usage: `bench <cubes|lights|post> [--frames N] [--warmup N] [--output report.csv|report.json]
+//! [--depth-prepass] [--occlusion-culling]`, along with the engine options of
+//! [`rose::platform::config`].
+
+use std::{
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use rose::{
+    prelude::*,
+    renderer::{stats::RenderFrameStats, Renderer, RendererConfig},
+};
+use violette::gl;
+
+use report::Report;
+use scenes::{Scene, SceneKind};
+
+mod report;
+mod scenes;
+
+/// Resolution of the benchmark; fixed so that results stay comparable.
+const SIZE: UVec2 = UVec2::new(1280, 720);
+
+#[derive(Debug, Clone)]
+struct Options {
+    scene: SceneKind,
+    /// Number of measured frames, which is also the length of the camera path.
+    frames: usize,
+    /// Frames rendered before measuring, to let shaders compile and auto-exposure settle.
+    warmup: usize,
+    output: Option<PathBuf>,
+    /// Configuration of the renderer, so that its options can be compared on the same scene.
+    renderer: RendererConfig,
+}
+
+impl Options {
+    /// Read the options from the launch configuration: the scene is the scene to load, and the
+    /// other options are application arguments.
+    fn from_config(config: &LaunchConfig) -> Result<Self> {
+        let scenes = SceneKind::ALL.map(SceneKind::name).join("|");
+        let scene = config
+            .scene
+            .as_ref()
+            .and_then(|scene| scene.to_str())
+            .ok_or_else(|| eyre::eyre!("Missing scene, expected one of {}", scenes))?
+            .parse()?;
+        let mut args = config.args.iter();
+        let mut frames = 600;
+        let mut warmup = 60;
+        let mut output = None;
+        let mut renderer = RendererConfig::default();
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| eyre::eyre!("Missing value for {}", arg))
+            };
+            match arg.as_str() {
+                "--frames" => frames = value()?.parse().context("Parsing --frames")?,
+                "--warmup" => warmup = value()?.parse().context("Parsing --warmup")?,
+                "--output" => output = Some(PathBuf::from(value()?)),
+                "--depth-prepass" => renderer.depth_prepass = true,
+                "--occlusion-culling" => renderer.occlusion_culling = true,
+                _ => eyre::bail!("Unexpected argument {:?}", arg),
+            }
+        }
+        Ok(Self {
+            scene,
+            frames: frames.max(1),
+            warmup,
+            output,
+            renderer,
+        })
+    }
+}
+
+struct App {
+    options: Options,
+    renderer: ThreadGuard<Renderer>,
+    scene: ThreadGuard<Scene>,
+    camera: Camera,
+    size: UVec2,
+    frame: usize,
+    frame_times: Vec<Duration>,
+    frame_stats: Vec<RenderFrameStats>,
+}
+
+impl Application for App {
+    fn window_features(wb: WindowBuilder) -> WindowBuilder {
+        wb.with_inner_size(PhysicalSize::new(SIZE.x, SIZE.y))
+            .with_resizable(false)
+            .with_visible(false)
+    }
+
+    fn new(size: WindowSize, config: &LaunchConfig) -> Result<Self> {
+        let options = Options::from_config(config)?;
+        let size = size.physical_vec();
+        let mut renderer =
+            Renderer::with_config(size, &config.asset_root, options.renderer.clone())?;
+        let scene = Scene::load(options.scene, &mut renderer)?;
+        let mut camera = Camera::default();
+        camera.projection.update(size.as_vec2());
+        tracing::info!(
+            scene = %options.scene,
+            instances = scene.instances(),
+            frames = options.frames,
+            warmup = options.warmup,
+            "Starting benchmark"
+        );
+        Ok(Self {
+            frame_times: Vec::with_capacity(options.frames),
+            frame_stats: Vec::with_capacity(options.frames),
+            options,
+            renderer: ThreadGuard::new(renderer),
+            scene: ThreadGuard::new(scene),
+            camera,
+            size,
+            frame: 0,
+        })
+    }
+
+    fn resize(&mut self, size: WindowSize) -> Result<()> {
+        self.size = size.physical_vec();
+        self.camera.projection.update(self.size.as_vec2());
+        self.renderer.resize(self.size)
+    }
+
+    #[tracing::instrument(target = "App::render", skip_all)]
+    fn render(&mut self, mut ctx: RenderContext) -> Result<()> {
+        // The camera follows the path by frame index rather than time, so that every run renders
+        // the exact same frames
+        let measured_frame = self.frame.saturating_sub(self.options.warmup);
+        let t = measured_frame as f32 / self.options.frames as f32;
+        self.camera.transform = self.scene.orbit.camera_transform(t);
+
+        let start = Instant::now();
+        self.renderer.begin_render(&self.camera)?;
+        self.scene.submit(&mut self.renderer);
+        let stats = self.renderer.flush(ctx.dt, Vec3::ZERO)?;
+        // Wait for the GPU so that the measured time covers the whole frame
+        unsafe { gl::Finish() };
+        let elapsed = start.elapsed();
+
+        if self.frame >= self.options.warmup {
+            self.frame_times.push(elapsed);
+            self.frame_stats.push(stats);
+        }
+        self.frame += 1;
+
+        if self.frame_times.len() == self.options.frames {
+            let report = Report::new(
+                self.options.scene.name(),
+                self.size,
+                &self.frame_times,
+                &self.frame_stats,
+            );
+            report.write(self.options.output.as_deref())?;
+            tracing::info!(
+                "{}: mean {:.2} ms, p99 {:.2} ms",
+                report.scene,
+                report.mean_ms,
+                report.p99_ms
+            );
+            ctx.quit();
+        }
+        Ok(())
+    }
+}
+
+fn main() -> Result<()> {
+    run::<App>("Benchmark")
+}
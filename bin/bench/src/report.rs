@@ -0,0 +1,119 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    path::Path,
+    time::Duration,
+};
+
+use rose::{prelude::*, renderer::stats::RenderFrameStats};
+use serde::Serialize;
+
+const CSV_HEADER: &str =
+    "scene,frames,width,height,mean_ms,p50_ms,p90_ms,p95_ms,p99_ms,max_ms,draw_calls,triangles";
+
+/// Frame time summary of a benchmark run.
+#[derive(Debug, Clone, Serialize)]
+pub struct Report {
+    pub scene: String,
+    pub frames: usize,
+    pub width: u32,
+    pub height: u32,
+    pub mean_ms: f64,
+    pub p50_ms: f64,
+    pub p90_ms: f64,
+    pub p95_ms: f64,
+    pub p99_ms: f64,
+    pub max_ms: f64,
+    /// Mean draw calls per frame.
+    pub draw_calls: f64,
+    /// Mean triangles per frame.
+    pub triangles: f64,
+}
+
+impl Report {
+    pub fn new(
+        scene: &str,
+        size: UVec2,
+        frame_times: &[Duration],
+        frame_stats: &[RenderFrameStats],
+    ) -> Self {
+        let mut times = frame_times
+            .iter()
+            .map(|time| time.as_secs_f64() * 1e3)
+            .collect::<Vec<_>>();
+        times.sort_by(f64::total_cmp);
+        let mean = |values: &mut dyn Iterator<Item = f64>| {
+            let (sum, count) = values.fold((0., 0), |(sum, count), x| (sum + x, count + 1));
+            sum / count.max(1) as f64
+        };
+        Self {
+            scene: scene.to_string(),
+            frames: times.len(),
+            width: size.x,
+            height: size.y,
+            mean_ms: mean(&mut times.iter().copied()),
+            p50_ms: percentile(&times, 50.),
+            p90_ms: percentile(&times, 90.),
+            p95_ms: percentile(&times, 95.),
+            p99_ms: percentile(&times, 99.),
+            max_ms: times.last().copied().unwrap_or_default(),
+            draw_calls: mean(&mut frame_stats.iter().map(|stats| stats.draw_calls as f64)),
+            triangles: mean(&mut frame_stats.iter().map(|stats| stats.triangles as f64)),
+        }
+    }
+
+    fn csv_row(&self) -> String {
+        format!(
+            "{},{},{},{},{:.3},{:.3},{:.3},{:.3},{:.3},{:.3},{:.1},{:.0}",
+            self.scene,
+            self.frames,
+            self.width,
+            self.height,
+            self.mean_ms,
+            self.p50_ms,
+            self.p90_ms,
+            self.p95_ms,
+            self.p99_ms,
+            self.max_ms,
+            self.draw_calls,
+            self.triangles,
+        )
+    }
+
+    /// Write the report as JSON if the path has a `json` extension, otherwise append it as a CSV
+    /// row, writing the header first if the file is new. Without a path, prints CSV to stdout.
+    pub fn write(&self, path: Option<&Path>) -> Result<()> {
+        let Some(path) = path else {
+            let mut stdout = io::stdout().lock();
+            writeln!(stdout, "{}", CSV_HEADER)?;
+            writeln!(stdout, "{}", self.csv_row())?;
+            return Ok(());
+        };
+        if path.extension().is_some_and(|ext| ext == "json") {
+            let file = File::create(path)
+                .with_context(|| format!("Creating report {}", path.display()))?;
+            serde_json::to_writer_pretty(file, self)?;
+            return Ok(());
+        }
+        let is_new = !path.exists();
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Opening report {}", path.display()))?;
+        if is_new {
+            writeln!(file, "{}", CSV_HEADER)?;
+        }
+        writeln!(file, "{}", self.csv_row())?;
+        Ok(())
+    }
+}
+
+/// Nearest-rank percentile of sorted values.
+fn percentile(sorted: &[f64], percent: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.;
+    }
+    let rank = (percent / 100. * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
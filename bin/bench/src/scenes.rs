@@ -0,0 +1,272 @@
+use std::{f32::consts::TAU, fmt, str::FromStr};
+
+use rose::{
+    core::light::Light,
+    prelude::*,
+    renderer::{
+        env::{SimpleSky, SimpleSkyParams},
+        BloomQuality, BloomResolution, MaterialHandle, Mesh, MeshHandle, Renderer,
+    },
+};
+
+/// Standardized stress scenes. Their content only depends on the scene kind, so that timings are
+/// comparable across commits.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SceneKind {
+    /// 10 000 cubes sharing a single mesh and material.
+    Cubes,
+    /// 1 000 point lights over a field of spheres.
+    Lights,
+    /// A light scene rendered through every post-processing effect at high quality.
+    Post,
+}
+
+impl SceneKind {
+    pub const ALL: [Self; 3] = [Self::Cubes, Self::Lights, Self::Post];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Cubes => "cubes",
+            Self::Lights => "lights",
+            Self::Post => "post",
+        }
+    }
+}
+
+impl fmt::Display for SceneKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for SceneKind {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|kind| kind.name() == s)
+            .ok_or_else(|| eyre::eyre!("Unknown scene {:?}", s))
+    }
+}
+
+/// Circular camera path around a target, looping once over the benchmark.
+#[derive(Debug, Copy, Clone)]
+pub struct Orbit {
+    pub target: Vec3,
+    pub radius: f32,
+    pub height: f32,
+}
+
+impl Orbit {
+    /// Camera transform at `t` in `0..1` along the path.
+    pub fn camera_transform(&self, t: f32) -> Transform {
+        let angle = t * TAU;
+        let offset = vec3(
+            angle.cos() * self.radius,
+            self.height,
+            angle.sin() * self.radius,
+        );
+        Transform::translation(self.target + offset).looking_at(self.target)
+    }
+}
+
+pub struct Scene {
+    pub kind: SceneKind,
+    pub orbit: Orbit,
+    instances: Vec<(MaterialHandle, Transformed<MeshHandle>)>,
+}
+
+impl Scene {
+    /// Create the meshes of the scene, and set up the lights and effects of the renderer.
+    pub fn load(kind: SceneKind, renderer: &mut Renderer) -> Result<Self> {
+        match kind {
+            SceneKind::Cubes => Self::cubes(renderer),
+            SceneKind::Lights => Self::lights(renderer),
+            SceneKind::Post => Self::post(renderer),
+        }
+    }
+
+    pub fn instances(&self) -> usize {
+        self.instances.len()
+    }
+
+    pub fn submit(&self, renderer: &mut Renderer) {
+        for (material, mesh) in &self.instances {
+            renderer.submit(*material, *mesh);
+        }
+    }
+
+    fn cubes(renderer: &mut Renderer) -> Result<Self> {
+        const SIDE: usize = 100;
+        const SPACING: f32 = 2.;
+
+        let mesh = renderer.register_mesh(cube_mesh()?);
+        let material = renderer.register_material_standard(material(vec3(0.8, 0.6, 0.4), 0.5, 0.)?);
+        let instances = grid(SIDE, SPACING)
+            .map(|position| {
+                let transform = Transform::translation(position);
+                (material, mesh.transformed(transform))
+            })
+            .collect();
+        renderer.add_lights([
+            Light::Directional {
+                color: Vec3::ONE * 8.,
+                dir: vec3(-1., -2., -1.).normalize(),
+            },
+            Light::Ambient {
+                color: Vec3::splat(0.1),
+            },
+        ])?;
+        Ok(Self {
+            kind: SceneKind::Cubes,
+            orbit: Orbit {
+                target: Vec3::ZERO,
+                radius: 60.,
+                height: 40.,
+            },
+            instances,
+        })
+    }
+
+    fn lights(renderer: &mut Renderer) -> Result<Self> {
+        const SPHERES_SIDE: usize = 20;
+        const LIGHTS_SIDE: usize = 32;
+
+        let floor = renderer.register_mesh(cube_mesh()?);
+        let sphere = renderer.register_mesh(sphere_mesh()?);
+        let floor_material =
+            renderer.register_material_standard(material(Vec3::splat(0.5), 0.8, 0.)?);
+        let sphere_material =
+            renderer.register_material_standard(material(Vec3::splat(0.9), 0.3, 1.)?);
+        let floor_transform = Transform::translation(-Vec3::Y).scaled(vec3(50., 0.5, 50.));
+        let instances = std::iter::once((floor_material, floor.transformed(floor_transform)))
+            .chain(grid(SPHERES_SIDE, 4.).map(|position| {
+                let transform = Transform::translation(position);
+                (sphere_material, sphere.transformed(transform))
+            }))
+            .collect();
+
+        let lights = grid(LIGHTS_SIDE, 2.5)
+            .take(1000)
+            .enumerate()
+            .map(|(ix, position)| Light::Point {
+                color: palette(ix) * 2.,
+                position: position + Vec3::Y,
+            })
+            .collect::<Vec<_>>();
+        renderer.add_lights(lights)?;
+        Ok(Self {
+            kind: SceneKind::Lights,
+            orbit: Orbit {
+                target: Vec3::ZERO,
+                radius: 45.,
+                height: 20.,
+            },
+            instances,
+        })
+    }
+
+    fn post(renderer: &mut Renderer) -> Result<Self> {
+        let sphere = renderer.register_mesh(sphere_mesh()?);
+        let instances = grid(10, 3.)
+            .enumerate()
+            .map(|(ix, position)| -> Result<_> {
+                let material = material(palette(ix), 0.2, 0.)?;
+                // Bright emissive spheres to give bloom and lens flares something to work on
+                if ix % 7 == 0 {
+                    material.update_uniforms(|uniforms| {
+                        uniforms.has_emission = true;
+                        uniforms.emission_factor = palette(ix);
+                        uniforms.emission_strength = 50.;
+                    })?;
+                }
+                let material = renderer.register_material_standard(material);
+                let transform = Transform::translation(position);
+                Ok((material, sphere.transformed(transform)))
+            })
+            .collect::<Result<_>>()?;
+        renderer.add_lights([
+            Light::Directional {
+                color: Vec3::ONE * 12.,
+                dir: vec3(1., -1., -0.5).normalize(),
+            },
+            Light::Ambient {
+                color: Vec3::splat(0.2),
+            },
+        ])?;
+        renderer.set_environment(|reload_watcher| {
+            SimpleSky::new(SimpleSkyParams::default(), reload_watcher).unwrap()
+        });
+        renderer.set_fog(Some(FogParams::default()));
+        let postprocess = renderer.post_process_interface();
+        postprocess.bloom.quality = BloomQuality {
+            resolution: BloomResolution::Half,
+            passes: 8,
+        };
+        postprocess.lens_flare.enabled = true;
+        postprocess.lens_flare.ghost_count = 8;
+        Ok(Self {
+            kind: SceneKind::Post,
+            orbit: Orbit {
+                target: Vec3::ZERO,
+                radius: 30.,
+                height: 6.,
+            },
+            instances,
+        })
+    }
+}
+
+/// Positions of a `side` by `side` grid on the XZ plane, centered on the origin.
+fn grid(side: usize, spacing: f32) -> impl Iterator<Item = Vec3> {
+    let offset = (side - 1) as f32 * spacing / 2.;
+    (0..side * side).map(move |ix| {
+        let (x, z) = ((ix % side) as f32, (ix / side) as f32);
+        vec3(x * spacing - offset, 0., z * spacing - offset)
+    })
+}
+
+/// Deterministic, well-spread color for the given index.
+fn palette(ix: usize) -> Vec3 {
+    const GOLDEN_RATIO: f32 = 0.618_034;
+    let hue = ix as f32 * GOLDEN_RATIO;
+    let channel = |offset: f32| (((hue + offset).fract() * 6. - 3.).abs() - 1.).clamp(0., 1.);
+    vec3(channel(0.), channel(2. / 3.), channel(1. / 3.))
+}
+
+fn material(color: Vec3, roughness: f32, metallic: f32) -> Result<MaterialInstance> {
+    let material = MaterialInstance::create(None, None, None, None)?;
+    material.update_uniforms(|uniforms| {
+        uniforms.color_factor = color;
+        uniforms.rough_metal_factor = vec2(roughness, metallic);
+    })?;
+    Ok(material)
+}
+
+fn sphere_mesh() -> Result<Mesh> {
+    MeshBuilder::new(Vertex::new).uv_sphere(1., 24, 48).upload()
+}
+
+/// Unit cube with flat normals.
+fn cube_mesh() -> Result<Mesh> {
+    let faces = [Vec3::X, -Vec3::X, Vec3::Y, -Vec3::Y, Vec3::Z, -Vec3::Z];
+    let mut vertices = Vec::with_capacity(24);
+    let mut indices = Vec::with_capacity(36);
+    for normal in faces {
+        let tangent = if normal.y.abs() > 0.5 {
+            Vec3::X
+        } else {
+            Vec3::Y.cross(normal)
+        };
+        let bitangent = normal.cross(tangent);
+        let base = vertices.len() as u32;
+        for uv in [vec2(0., 0.), vec2(1., 0.), vec2(1., 1.), vec2(0., 1.)] {
+            let corner = (uv - 0.5) * 2.;
+            let position = (normal + tangent * corner.x + bitangent * corner.y) / 2.;
+            vertices.push(Vertex::new(position, normal, uv));
+        }
+        indices.extend([base, base + 1, base + 2, base, base + 2, base + 3]);
+    }
+    Mesh::new(vertices, indices)
+}
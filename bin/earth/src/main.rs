@@ -1,24 +1,79 @@
-use std::any::Any;
+use std::hash::Hash;
+use std::rc::Rc;
+use std::sync::mpsc::{channel, Receiver, Sender};
 use std::time::Duration;
 
 use crevice::std140::AsStd140;
-use serde::Deserialize;
+use egui::Ui;
+use serde::{Deserialize, Serialize};
 
 use rose::{
-    core::{camera::ViewUniformBuffer, transform::*, utils::reload_watcher::*},
+    core::{
+        camera::DepthMode,
+        utils::{
+            reload_watcher::ReloadWatcher,
+            snapshot::{snapshot, SnapshotReader, SnapshotWriter},
+        },
+    },
+    ecs::systems::{extract::RenderWorld, ChangeKind, ChangeTracker},
     prelude::*,
-    renderer::{DrawMaterial, Mesh},
-};
-use violette::buffer::BufferUsageHint;
-use violette::shader::{FragmentShader, VertexShader};
-use violette::{
-    buffer::UniformBuffer,
-    framebuffer::Framebuffer,
-    program::{Program, UniformBlockIndex, UniformLocation},
-    FrontFace,
 };
+use violette::FrontFace;
+
+/// Radius of the planet, in meters.
+const EARTH_RADIUS: f32 = 6360e3;
+/// Patches along each side of a face of the cube the ground is projected from.
+const GROUND_PATCHES: usize = 8;
+/// Quads along each side of a ground patch.
+const GROUND_PATCH_QUADS: usize = 16;
+
+/// Tessellate the ground of the planet into patches of a sphere projected from a cube. Each patch
+/// is returned with its center, its vertices being relative to it, so that they keep their
+/// precision next to the radius of the planet once rendered relative to the camera.
+fn ground_patches() -> impl Iterator<Item = (Vec3, CpuMesh<Vertex, u32>)> {
+    // Normal of the face, then the directions along which the patches of the face are laid out
+    const FACES: [[Vec3; 3]; 6] = [
+        [Vec3::X, Vec3::NEG_Z, Vec3::Y],
+        [Vec3::NEG_X, Vec3::Z, Vec3::Y],
+        [Vec3::Y, Vec3::X, Vec3::NEG_Z],
+        [Vec3::NEG_Y, Vec3::X, Vec3::Z],
+        [Vec3::Z, Vec3::X, Vec3::Y],
+        [Vec3::NEG_Z, Vec3::NEG_X, Vec3::Y],
+    ];
+    let side = (GROUND_PATCHES * GROUND_PATCH_QUADS) as f64;
+    let patches = (0..GROUND_PATCHES).flat_map(|y| (0..GROUND_PATCHES).map(move |x| (x, y)));
+    FACES.into_iter().flat_map(move |[normal, right, up]| {
+        let [normal, right, up] = [normal, right, up].map(|axis| axis.as_dvec3());
+        let point = move |x: usize, y: usize| {
+            let u = 2. * x as f64 / side - 1.;
+            let v = 2. * y as f64 / side - 1.;
+            (normal + u * right + v * up).normalize()
+        };
+        patches.clone().map(move |(px, py)| {
+            let (x0, y0) = (px * GROUND_PATCH_QUADS, py * GROUND_PATCH_QUADS);
+            let half = GROUND_PATCH_QUADS / 2;
+            // Rounded first, so that neighbouring patches still meet once offset by their center
+            let center = (point(x0 + half, y0 + half) * EARTH_RADIUS as f64).as_vec3();
+            let vertices = (0..=GROUND_PATCH_QUADS)
+                .flat_map(|j| (0..=GROUND_PATCH_QUADS).map(move |i| (i, j)))
+                .map(|(i, j)| {
+                    let normal = point(x0 + i, y0 + j);
+                    let position = normal * EARTH_RADIUS as f64 - center.as_dvec3();
+                    let uv = vec2(i as f32, j as f32) / GROUND_PATCH_QUADS as f32;
+                    Vertex::new(position.as_vec3(), normal.as_vec3(), uv)
+                })
+                .collect();
+            let row = GROUND_PATCH_QUADS as u32 + 1;
+            let indices = (0..GROUND_PATCH_QUADS as u32)
+                .flat_map(|j| (0..GROUND_PATCH_QUADS as u32).map(move |i| j * row + i))
+                .flat_map(|a| [a, a + 1, a + row + 1, a, a + row + 1, a + row])
+                .collect();
+            (center, CpuMesh { vertices, indices })
+        })
+    })
+}
 
-#[derive(AsStd140, Deserialize)]
+#[derive(Debug, Clone, AsStd140, Deserialize, Serialize)]
 #[serde(default)]
 struct AtmosphereUniforms {
     center: Vec3,
@@ -33,8 +88,8 @@ impl Default for AtmosphereUniforms {
     fn default() -> Self {
         Self {
             center: Vec3::ZERO,
-            atm_radius: 1.1,
-            ground_radius: 1.,
+            atm_radius: 1.1 * EARTH_RADIUS,
+            ground_radius: EARTH_RADIUS,
             ground_albedo: Vec3::splat(0.1),
             sun_dir: Vec3::X,
             sun_color: Vec3::ZERO,
@@ -42,142 +97,61 @@ impl Default for AtmosphereUniforms {
     }
 }
 
-#[derive(Debug)]
-struct AtmosphereMaterial {
-    program: ThreadGuard<Program>,
-    uniform: ThreadGuard<UniformBuffer<Std140AtmosphereUniforms>>,
-    proxy: ReloadFileProxy,
-    u_block_view: UniformBlockIndex,
-    u_block_atm: UniformBlockIndex,
-    u_model: UniformLocation,
-}
+type AtmosphereMaterial = ShaderMaterial<AtmosphereUniforms>;
 
-impl DrawMaterial for AtmosphereMaterial {
-    fn draw<'a>(
-        &self,
-        frame: &Framebuffer,
-        view: &ViewUniformBuffer,
-        meshes: &mut dyn Iterator<Item = Transformed<&'a Mesh>>,
-    ) -> Result<()> {
-        violette::set_front_face(FrontFace::Clockwise);
-        self.program
-            .bind_block(&view.slice(0..=0), self.u_block_view, 0)?;
-        self.program
-            .bind_block(&self.uniform.slice(0..=0), self.u_block_atm, 1)?;
-        for mesh in meshes {
-            self.program
-                .set_uniform(self.u_model, mesh.transform.matrix())?;
-            mesh.draw(&self.program, frame, false)?;
-        }
-        violette::set_front_face(FrontFace::CounterClockwise);
-        Ok(())
-    }
-
-    fn eq_key(&self) -> usize {
-        self.uniform.id.get() as _
-    }
-
-    fn as_any(&self) -> &dyn Any {
-        self
-    }
+fn atmosphere_material(reload_watcher: &ReloadWatcher) -> Result<AtmosphereMaterial> {
+    ShaderMaterialBuilder::new("mesh/mesh.vert.glsl", "sky/atmosphere.frag.glsl")
+        .uniform_block("Atmosphere")
+        .front_face(FrontFace::Clockwise)
+        .build(AtmosphereUniforms::default(), reload_watcher)
 }
 
-impl AtmosphereMaterial {
-    fn new(reload_watcher: &ReloadWatcher) -> Result<Self> {
-        let vert_path = reload_watcher.base_path().join("mesh/mesh.vert.glsl");
-        let frag_path = reload_watcher.base_path().join("sky/atmosphere.frag.glsl");
-        let vert_glsl = glsl_preprocessor::load_and_parse(&vert_path)?;
-        let frag_glsl = glsl_preprocessor::load_and_parse(&frag_path)?;
-        let vert_shader = VertexShader::new_multiple(vert_glsl.iter().map(|(_, s)| s.as_str()))
-            .with_context(|| {
-                format!(
-                    "File map:{}",
-                    vert_glsl
-                        .iter()
-                        .map(|(p, _)| p.as_path())
-                        .enumerate()
-                        .map(|(i, path)| format!("\n\t{}: {}", i, path.display()))
-                        .reduce(|mut s, m| {
-                            s.push_str(&m);
-                            s
-                        })
-                        .unwrap_or_default()
-                )
-            })?;
-        let frag_shader = FragmentShader::new_multiple(frag_glsl.iter().map(|(_, s)| s.as_str()))
-            .with_context(|| {
-            format!(
-                "File map:{}",
-                frag_glsl
-                    .iter()
-                    .map(|(p, _)| p.as_path())
-                    .enumerate()
-                    .map(|(i, path)| format!("\n\t{}: {}", i, path.display()))
-                    .reduce(|mut s, m| {
-                        s.push_str(&m);
-                        s
-                    })
-                    .unwrap_or_default()
-            )
-        })?;
-        let program = Program::new()
-            .with_shader(vert_shader.id)
-            .with_shader(frag_shader.id)
-            .link()?;
-        let uniform = UniformBuffer::with_data(&[AtmosphereUniforms::default().as_std140()])?;
-
-        let u_block_view = program.uniform_block("View");
-        let u_block_atm = program.uniform_block("Atmosphere");
-        let u_model = program.uniform("model");
-
-        let proxy = reload_watcher.proxy([vert_path.as_path(), frag_path.as_path()]);
-        Ok(Self {
-            program: ThreadGuard::new(program),
-            uniform: ThreadGuard::new(uniform),
-            proxy,
-            u_block_atm,
-            u_block_view,
-            u_model,
-        })
-    }
-
+impl AtmosphereUniforms {
     fn update_material(world: &mut World) -> Result<()> {
         let (sun_dir, sun_color) = world
             .query::<(&GlobalTransform, &components::Light)>()
             .iter()
             .find_map(|(_, (tr, light))| {
                 if light.kind == LightKind::Directional {
-                    Some((
-                        tr.0.rotation.mul_vec3(Vec3::NEG_Z),
-                        light.color * light.power,
-                    ))
+                    Some((tr.0.rotation.mul_vec3(Vec3::NEG_Z), light.emitted_color()))
                 } else {
                     None
                 }
             })
             .unwrap_or((Vec3::X, Vec3::ZERO));
 
-        for (_, (uniforms, transform)) in world
-            .query::<(&mut AtmosphereUniforms, &GlobalTransform)>()
-            .iter()
-        {
+        for (_, (uniforms, transform)) in world.query::<(&mut Self, &GlobalTransform)>().iter() {
             uniforms.center = transform.0.position;
             uniforms.sun_dir = sun_dir.normalize();
             uniforms.sun_color = sun_color;
         }
 
-        for (_, (material, uniforms)) in world.query::<(&mut Self, &AtmosphereUniforms)>().iter() {
-            // material.uniform.slice(0..).set(0, &uniforms.as_std140())?;
-            material
-                .uniform
-                .set(&[uniforms.as_std140()], BufferUsageHint::Stream)?;
+        for (_, (material, uniforms)) in world
+            .query::<(&Handle<CustomMaterial<AtmosphereMaterial>>, &Self)>()
+            .iter()
+        {
+            material.read().set_uniforms(uniforms.clone())?;
         }
         Ok(())
     }
 }
 
+/// Angular velocity, in radians per second around each axis.
+#[derive(Debug, Default, Copy, Clone, Deserialize, Serialize)]
 struct Rotate(Vec3);
 
+impl NamedComponent for Rotate {
+    const NAME: &'static str = "Rotate";
+}
+
+impl InspectUi for Rotate {
+    fn inspect(&mut self, ui: &mut Ui) {
+        inspect_fields(ui, "component-rotate", |fields| {
+            fields.field("Speed", &mut self.0);
+        });
+    }
+}
+
 impl Rotate {
     fn update(world: &mut World, dt: Duration) {
         for (_, (transform, rotate)) in world.query::<(&mut Transform, &Self)>().iter() {
@@ -187,19 +161,110 @@ impl Rotate {
     }
 }
 
+/// Change of the rotating entities of the application, sent to [`RotateSimulation`].
+enum RotateEdit {
+    /// New rotating entity, or new speed of an existing one.
+    Set(Entity, Transform, Rotate),
+    Remove(Entity),
+}
+
+/// Rotates a copy of the rotating entities on the tick thread, without locking the application.
+/// The copy follows the entities gaining, changing or losing their [`Rotate`] in the application,
+/// while their transforms are owned by the simulation.
+struct RotateSimulation {
+    world: World,
+    edits: Receiver<RotateEdit>,
+    snapshot: SnapshotWriter<RenderWorld>,
+}
+
+impl Simulation for RotateSimulation {
+    fn tick(&mut self, ctx: TickContext) -> Result<()> {
+        for edit in self.edits.try_iter() {
+            match edit {
+                RotateEdit::Set(entity, _, rotate) if self.world.contains(entity) => {
+                    self.world.insert_one(entity, rotate)?;
+                }
+                RotateEdit::Set(entity, transform, rotate) => {
+                    self.world.spawn_at(entity, (transform, rotate));
+                }
+                RotateEdit::Remove(entity) => {
+                    let _ = self.world.despawn(entity);
+                }
+            }
+        }
+        Rotate::update(&mut self.world, ctx.dt);
+        self.snapshot.get_mut().extract(&self.world);
+        self.snapshot.publish();
+        Ok(())
+    }
+}
+
 struct EarthApp {
     core_systems: CoreSystems,
     pan_orbit_system: PanOrbitSystem,
     scene: Scene,
+    /// Inspector of the rotating entities.
+    ui_system: UiSystem,
+    /// Transforms of the rotating entities, ticked by [`RotateSimulation`].
+    render_world: Option<SnapshotReader<RenderWorld>>,
+    /// Changes of the rotating entities, sent to [`RotateSimulation`].
+    rotations: ChangeTracker,
+    edits: Option<Sender<RotateEdit>>,
+}
+
+impl EarthApp {
+    /// Send the rotating entities that changed since the last frame to the simulation. The
+    /// simulated entities keep the IDs of the rendered ones.
+    fn send_rotation_edits(tracker: &mut ChangeTracker, edits: &Sender<RotateEdit>, world: &World) {
+        let mut query = world.query::<&Rotate>();
+        let changes = tracker.update(world, &mut query, |rotate, hasher| {
+            rotate.0.to_array().map(f32::to_bits).hash(hasher)
+        });
+        for change in changes {
+            let edit = match change.kind {
+                ChangeKind::Added | ChangeKind::Modified => {
+                    let entity = change.entity;
+                    let (Ok(transform), Ok(rotate)) = (
+                        world.get::<&Transform>(entity),
+                        world.get::<&Rotate>(entity),
+                    ) else {
+                        continue;
+                    };
+                    RotateEdit::Set(entity, *transform, *rotate)
+                }
+                ChangeKind::Removed => RotateEdit::Remove(change.entity),
+            };
+            // The simulation is gone when the application exits
+            let _ = edits.send(edit);
+        }
+    }
 }
 
 impl Application for EarthApp {
-    fn new(size: PhysicalSize<f32>, scale_factor: f64) -> Result<Self> {
-        let sizeu = Vec2::from_array(size.into()).as_uvec2();
-        let mut core_systems = CoreSystems::new(sizeu)?;
+    fn new(size: WindowSize, config: &LaunchConfig) -> Result<Self> {
+        let mut core_systems =
+            CoreSystems::with_asset_root(size.physical_vec(), &config.asset_root)?;
+        // The planet is at its real scale, far beyond the precision of the default rendering
+        let renderer = &mut core_systems.render.renderer;
+        renderer.set_camera_relative(true);
+        renderer.set_depth_mode(DepthMode::ReversedInfinite);
         core_systems
             .render
             .register_custom_material::<AtmosphereMaterial>();
+        let atmosphere = Rc::new(atmosphere_material(
+            core_systems.render.renderer.reload_watcher(),
+        )?);
+        let mut ui_system = UiSystem::new();
+        ui_system
+            .register_inspectable::<Rotate>(&mut core_systems.persistence)
+            .register_component::<Transform>();
+        core_systems
+            .persistence
+            .register_component::<AtmosphereUniforms>()
+            .register_custom_material({
+                let atmosphere = atmosphere.clone();
+                move |_| Ok(CustomMaterial::from(atmosphere.clone()))
+            });
         let mut scene = Scene::new("assets")?;
 
         let cache = scene.asset_cache().as_any_cache();
@@ -207,52 +272,61 @@ impl Application for EarthApp {
             "prim:sphere",
             MeshBuilder::new(Vertex::new).uv_sphere(1., 64, 64).into(),
         );
+        let ground = cache.load::<assets::Material>("materials.white_diffuse")?;
+        let mut patches = ground_patches()
+            .enumerate()
+            .map(|(ix, (center, mesh))| {
+                let mut builder = EntityBuilder::new();
+                builder.add_bundle(ObjectBundle {
+                    transform: Transform::translation(center),
+                    material: ground,
+                    mesh: cache.get_or_insert(&format!("prim:earth.ground.{ix}"), mesh.into()),
+                    active: Active,
+                });
+                builder
+            })
+            .collect::<Vec<_>>();
         scene.with_world_mut(|world| {
             let entity = world.spawn((Transform::default(), Rotate(Vec3::Y / (6. * 60.))));
+            world.spawn_children(entity, &mut patches);
             world.spawn_children(
                 entity,
                 [
-                    EntityBuilder::new().add_bundle(ObjectBundle {
-                        // transform: Transform::default().scaled(Vec3::splat(6360e3)),
-                        transform: Transform::default(),
-                        material: cache.load::<assets::Material>("materials.white_diffuse")?,
-                        mesh: sphere,
-                        active: Active,
-                    }),
-                    EntityBuilder::new().add_bundle(ObjectBundle::<
-                        CustomMaterial<AtmosphereMaterial>,
-                    > {
-                        // transform: Transform::default().scaled(Vec3::splat(6460e3)),
-                        transform: Transform::default().scaled(Vec3::splat(2.)),
-                        material: cache.get_or_insert(
-                            "materials.earth.atmosphere",
-                            CustomMaterial::new(AtmosphereMaterial::new(
-                                core_systems.render.renderer.reload_watcher(),
-                            )?),
-                        ),
-                        mesh: sphere,
-                        active: Active,
-                    }),
+                    // The atmosphere is shaded from the distance to the center of the planet, and
+                    // does not need the precision of the ground
+                    EntityBuilder::new()
+                        .add_bundle(ObjectBundle::<CustomMaterial<AtmosphereMaterial>> {
+                            transform: Transform::default().scaled(Vec3::splat(2. * EARTH_RADIUS)),
+                            material: cache.get_or_insert(
+                                "materials.earth.atmosphere",
+                                CustomMaterial::from(atmosphere),
+                            ),
+                            mesh: sphere,
+                            active: Active,
+                        })
+                        .add(AtmosphereUniforms::default()),
                 ],
             );
             world.spawn(LightBundle {
                 transform: Transform::translation(Vec3::X).looking_at(Vec3::ZERO),
                 light: components::Light {
                     kind: LightKind::Directional,
-                    color: Vec3::ONE,
-                    power: 100.,
+                    intensity: 100.,
+                    unit: LightUnit::Lux,
+                    ..Default::default()
                 },
                 ..Default::default()
             });
             world.spawn(PanOrbitCameraBundle {
-                transform: Transform::translation(Vec3::splat(8.)).looking_at(Vec3::ZERO),
+                transform: Transform::translation(Vec3::splat(8. * EARTH_RADIUS))
+                    .looking_at(Vec3::ZERO),
                 pan_orbit: PanOrbitCamera {
-                    radius: 3.,
+                    radius: 3. * EARTH_RADIUS,
                     ..Default::default()
                 },
                 params: CameraParams {
                     fovy: 60f32.to_radians(),
-                    zrange: 1e-3..1e4,
+                    zrange: 1. ..1e9,
                 },
                 ..Default::default()
             });
@@ -261,15 +335,18 @@ impl Application for EarthApp {
 
         Ok(Self {
             core_systems,
-            pan_orbit_system: PanOrbitSystem::new(size.to_logical(scale_factor)),
+            pan_orbit_system: PanOrbitSystem::new(size.logical()),
             scene,
+            ui_system,
+            render_world: None,
+            rotations: ChangeTracker::new(),
+            edits: None,
         })
     }
 
-    fn resize(&mut self, size: PhysicalSize<u32>, scale_factor: f64) -> Result<()> {
-        self.core_systems.resize(size)?;
-        self.pan_orbit_system
-            .set_window_size(size.to_logical(scale_factor));
+    fn resize(&mut self, size: WindowSize) -> Result<()> {
+        self.core_systems.resize(size.physical)?;
+        self.pan_orbit_system.set_window_size(size.logical());
         Ok(())
     }
 
@@ -278,23 +355,50 @@ impl Application for EarthApp {
         Ok(())
     }
 
-    fn tick(&mut self, ctx: TickContext) -> Result<()> {
-        self.scene.with_world_mut(|world| {
-            Rotate::update(world, ctx.dt);
-            AtmosphereMaterial::update_material(world)?;
-            Ok::<_, eyre::Report>(())
-        })?;
-        Ok(())
+    fn simulation(&mut self) -> Option<Box<dyn Simulation>> {
+        let (writer, reader) = snapshot();
+        let (edits, receiver) = channel();
+        self.render_world = Some(reader);
+        // The rotating entities are all sent as added on the first frame
+        self.rotations = ChangeTracker::new();
+        self.edits = Some(edits);
+        Some(Box::new(RotateSimulation {
+            world: World::new(),
+            edits: receiver,
+            snapshot: writer,
+        }))
     }
 
     fn render(&mut self, ctx: RenderContext) -> Result<()> {
         self.core_systems.begin_frame();
         self.scene.with_world_mut(|world| {
+            if let Some(edits) = &self.edits {
+                Self::send_rotation_edits(&mut self.rotations, edits, world);
+            }
+            if let Some(render_world) = &mut self.render_world {
+                if render_world.has_new() {
+                    render_world.latest().apply(world);
+                }
+            }
             self.pan_orbit_system
                 .on_frame(&self.core_systems.input.input, world);
-        });
+            AtmosphereUniforms::update_material(world)
+        })?;
         self.core_systems.end_frame(Some(&mut self.scene), ctx.dt)
     }
+
+    fn ui(&mut self, ctx: UiContext) {
+        egui::Window::new("Inspector").show(ctx.egui, |ui| {
+            self.scene.with_world(|world, cmd| {
+                for (entity, ()) in world.query::<()>().with::<&Rotate>().iter() {
+                    ui.push_id(entity, |ui| {
+                        self.ui_system
+                            .components_ui(ui, world.entity(entity).unwrap(), cmd);
+                    });
+                }
+            });
+        });
+    }
 }
 
 fn main() -> Result<()> {
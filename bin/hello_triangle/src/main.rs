@@ -4,7 +4,7 @@ use glam::{vec2, vec3, Vec2, Vec3};
 
 use rose_core::mesh::Mesh;
 use rose_core::utils::thread_guard::ThreadGuard;
-use rose_platform::{Application, PhysicalSize, RenderContext, UiContext};
+use rose_platform::{config::LaunchConfig, Application, PhysicalSize, RenderContext, UiContext};
 use violette::{
     framebuffer::{ClearBuffer, Framebuffer},
     program::{Program, UniformLocation},
@@ -28,7 +28,7 @@ struct TriangleApp {
 }
 
 impl Application for TriangleApp {
-    fn new(size: PhysicalSize<f32>, _scale_factor: f64) -> Result<Self> {
+    fn new(size: WindowSize, _config: &LaunchConfig) -> Result<Self> {
         let vert_shader = VertexShader::load("../../../res/shaders/triangle.vert.glsl")?;
         let frag_shader = FragmentShader::load("../../../res/shaders/triangle.frag.glsl")?;
         let mat_program = Program::new()
@@ -58,12 +58,12 @@ impl Application for TriangleApp {
             mat_program: ThreadGuard::new(mat_program),
             uniform_scale,
             mesh: ThreadGuard::new(mesh),
-            size: size.cast(),
+            size: size.physical.cast(),
         })
     }
 
-    fn resize(&mut self, _size: PhysicalSize<u32>, _scale_factor: f64) -> Result<()> {
-        let size = _size.cast();
+    fn resize(&mut self, size: WindowSize) -> Result<()> {
+        let size = size.physical.cast();
         self.size = size;
         Framebuffer::viewport(0, 0, size.width, size.height);
         Ok(())
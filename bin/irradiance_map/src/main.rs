@@ -1,9 +1,6 @@
-use std::{
-    num::NonZeroU32,
-    path::{Path, PathBuf},
-};
+use std::{num::NonZeroU32, path::Path};
 
-use rose::{core::utils::reload_watcher::ReloadWatcher, prelude::*};
+use rose::{core::utils::reload_watcher::ReloadWatcher, platform::window, prelude::*};
 use violette::{
     framebuffer::{ClearBuffer, Framebuffer},
     program::UniformLocation,
@@ -57,8 +54,8 @@ impl Application for IrradianceMap {
             .with_resizable(false)
     }
 
-    fn new(_size: PhysicalSize<f32>, _scale_factor: f64) -> eyre::Result<Self> {
-        let reload_watcher = ReloadWatcher::new("res/shaders");
+    fn new(_size: WindowSize, config: &LaunchConfig) -> eyre::Result<Self> {
+        let reload_watcher = ReloadWatcher::new(config.asset_root.join("res/shaders"));
         let make_irradiance = ScreenDraw::load("screen/env/irradiance.glsl", &reload_watcher)?;
         let display_texture = ScreenDraw::load("blit.glsl", &reload_watcher)?;
         let fbo = Framebuffer::new();
@@ -74,15 +71,16 @@ impl Application for IrradianceMap {
             u_display_texture,
             reload_watcher,
         }));
-        if let Some(path) = std::env::args()
-            .nth(1)
-            .map(|s| s.parse::<PathBuf>().unwrap())
-        {
+        if let Some(path) = &config.scene {
             this.0.load_file(path)?;
         }
         Ok(this)
     }
 
+    fn accepts_dropped_file(&self, path: &Path) -> bool {
+        window::has_extension(path, EnvironmentMap::EXTENSIONS)
+    }
+
     fn interact(&mut self, _event: WindowEvent) -> eyre::Result<()> {
         match _event {
             WindowEvent::DroppedFile(path) => {
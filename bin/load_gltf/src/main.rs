@@ -1,6 +1,6 @@
-use std::path::PathBuf;
+use std::path::Path;
 
-use rose::prelude::*;
+use rose::{platform::window, prelude::*};
 
 struct App {
     core_systems: CoreSystems,
@@ -10,9 +10,9 @@ struct App {
 
 impl Application for App {
     #[tracing::instrument]
-    fn new(size: PhysicalSize<f32>, scale_factor: f64) -> Result<Self> {
-        let sizeu = UVec2::from_array(size.cast::<u32>().into());
-        let mut core_systems = CoreSystems::new(sizeu)?;
+    fn new(size: WindowSize, config: &LaunchConfig) -> Result<Self> {
+        let mut core_systems =
+            CoreSystems::with_asset_root(size.physical_vec(), &config.asset_root)?;
         core_systems
             .persistence
             .register_component::<GlobalTransform>();
@@ -26,9 +26,8 @@ impl Application for App {
                 )
                 .unwrap()
             });
-        let scene = if let Some(name) = std::env::args().nth(1) {
-            let path = PathBuf::from(name);
-            let mut scene: Scene = smol::block_on(rose::ecs::load_gltf::load_gltf_scene(&path))?;
+        let scene = if let Some(path) = &config.scene {
+            let mut scene: Scene = smol::block_on(rose::ecs::load_gltf::load_gltf_scene(path))?;
             scene.with_world(|world, cmd| {
                 cmd.spawn(PanOrbitCameraBundle {
                     pan_orbit: PanOrbitCamera {
@@ -39,15 +38,25 @@ impl Application for App {
                     ..Default::default()
                 });
                 HierarchicalSystem.update::<Transform>(world, cmd);
+                // Nothing moves in scenes without animations, merge their meshes to cut down on
+                // draw calls
+                if world.query::<&TransformAnimation>().iter().next().is_none() {
+                    for (entity, _) in world.query::<&Handle<MeshAsset>>().iter() {
+                        cmd.insert_one(entity, Static);
+                    }
+                }
             });
             scene.flush_commands();
+            let batches =
+                scene.with_world(|world, _| core_systems.render.build_static_batches(world))?;
+            tracing::info!("Merged static meshes into {} batches", batches);
             scene.set_path("assets/from_gltf.scene");
             core_systems.save_scene(&scene)?;
             scene
         } else {
             eyre::bail!("Need to provide a file to open");
         };
-        let pan_orbit_system = PanOrbitSystem::new(size.to_logical(scale_factor));
+        let pan_orbit_system = PanOrbitSystem::new(size.logical());
         Ok(Self {
             core_systems,
             scene,
@@ -55,12 +64,16 @@ impl Application for App {
         })
     }
 
-    fn resize(&mut self, size: PhysicalSize<u32>, scale_factor: f64) -> Result<()> {
-        self.core_systems.resize(size)?;
-        self.pan_orbit_system.resize(size.to_logical(scale_factor));
+    fn resize(&mut self, size: WindowSize) -> Result<()> {
+        self.core_systems.resize(size.physical)?;
+        self.pan_orbit_system.resize(size.logical());
         Ok(())
     }
 
+    fn accepts_dropped_file(&self, path: &Path) -> bool {
+        window::has_extension(path, EnvironmentMap::EXTENSIONS)
+    }
+
     #[tracing::instrument(skip_all)]
     fn interact(&mut self, event: WindowEvent) -> Result<()> {
         if let Some(event) = self.core_systems.on_event(event) {
@@ -96,6 +109,10 @@ impl Application for App {
         });
         self.core_systems.end_frame(Some(&mut self.scene), ctx.dt)
     }
+
+    fn hud_lines(&self) -> Vec<String> {
+        self.core_systems.render.renderer.frame_stats().hud_lines()
+    }
 }
 
 fn main() -> Result<()> {
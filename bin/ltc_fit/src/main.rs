@@ -0,0 +1,349 @@
+//! Fits the linearly transformed cosine tables used by the area lights, following "Real-Time
+//! Polygonal-Light Shading with Linearly Transformed Cosines" (Heitz et al. 2016).
+//!
+//! Run in release with the path of the table to write, by default the one the renderer embeds:
+//! `cargo run --release -p ltc_fit -- lib/rose-renderer/src/ltc_ggx.bin`
+
+use std::{env, f32::consts::PI, fs, path::PathBuf};
+
+use glam::{Mat3, Vec2, Vec3};
+
+/// Resolution of the table along roughness and incidence, must match `ltc::LTC_SIZE`.
+const SIZE: usize = 64;
+/// Samples per dimension for the integrals.
+const SAMPLES: usize = 32;
+const MIN_ALPHA: f32 = 1e-5;
+
+/// GGX microfacet BRDF with the height-correlated Smith shadowing-masking, in the local frame of
+/// the surface.
+struct Ggx {
+    alpha: f32,
+}
+
+impl Ggx {
+    fn lambda(&self, cos_theta: f32) -> f32 {
+        if cos_theta >= 1. {
+            return 0.;
+        }
+        let a = 1. / self.alpha / cos_theta.acos().tan();
+        0.5 * (-1. + (1. + 1. / (a * a)).sqrt())
+    }
+
+    /// BRDF times the cosine of the light direction, and the density of sampling that direction
+    /// with [`Self::sample`].
+    fn eval(&self, v: Vec3, l: Vec3) -> (f32, f32) {
+        if v.z <= 0. {
+            return (0., 0.);
+        }
+        let g2 = if l.z <= 0. {
+            0.
+        } else {
+            1. / (1. + self.lambda(v.z) + self.lambda(l.z))
+        };
+        let h = (v + l).normalize();
+        let slope = Vec2::new(h.x, h.y) / h.z;
+        let d = 1. / (1. + slope.length_squared() / (self.alpha * self.alpha));
+        let d = d * d / (PI * self.alpha * self.alpha * h.z.powi(4));
+        let pdf = (d * h.z / 4. / v.dot(h)).abs();
+        (d * g2 / 4. / v.z, pdf)
+    }
+
+    fn sample(&self, v: Vec3, u: Vec2) -> Vec3 {
+        let phi = 2. * PI * u.x;
+        let r = self.alpha * (u.y / (1. - u.y)).sqrt();
+        let n = Vec3::new(r * phi.cos(), r * phi.sin(), 1.).normalize();
+        -v + 2. * n * n.dot(v)
+    }
+}
+
+/// Clamped cosine distribution transformed by `basis * [[m11, 0, m13], [0, m22, 0], [0, 0, 1]]`.
+#[derive(Copy, Clone)]
+struct Ltc {
+    magnitude: f32,
+    fresnel: f32,
+    m11: f32,
+    m22: f32,
+    m13: f32,
+    basis: Mat3,
+    m: Mat3,
+    inv_m: Mat3,
+    det_m: f32,
+}
+
+impl Default for Ltc {
+    fn default() -> Self {
+        let mut ltc = Self {
+            magnitude: 1.,
+            fresnel: 1.,
+            m11: 1.,
+            m22: 1.,
+            m13: 0.,
+            basis: Mat3::IDENTITY,
+            m: Mat3::IDENTITY,
+            inv_m: Mat3::IDENTITY,
+            det_m: 1.,
+        };
+        ltc.update();
+        ltc
+    }
+}
+
+impl Ltc {
+    fn update(&mut self) {
+        self.m = self.basis
+            * Mat3::from_cols(
+                Vec3::new(self.m11, 0., 0.),
+                Vec3::new(0., self.m22, 0.),
+                Vec3::new(self.m13, 0., 1.),
+            );
+        self.inv_m = self.m.inverse();
+        self.det_m = self.m.determinant().abs();
+    }
+
+    fn eval(&self, l: Vec3) -> f32 {
+        let original = (self.inv_m * l).normalize();
+        let transformed = self.m * original;
+        let len = transformed.length();
+        let jacobian = self.det_m / (len * len * len);
+        self.magnitude * original.z.max(0.) / PI / jacobian
+    }
+
+    fn sample(&self, u: Vec2) -> Vec3 {
+        let theta = u.x.sqrt().acos();
+        let phi = 2. * PI * u.y;
+        let dir = Vec3::new(
+            theta.sin() * phi.cos(),
+            theta.sin() * phi.sin(),
+            theta.cos(),
+        );
+        (self.m * dir).normalize()
+    }
+}
+
+fn samples() -> impl Iterator<Item = Vec2> {
+    (0..SAMPLES).flat_map(|j| {
+        (0..SAMPLES).map(move |i| {
+            Vec2::new(
+                (i as f32 + 0.5) / SAMPLES as f32,
+                (j as f32 + 0.5) / SAMPLES as f32,
+            )
+        })
+    })
+}
+
+/// Integral of the BRDF, its Schlick Fresnel weight and its average direction.
+fn average_terms(brdf: &Ggx, v: Vec3) -> (f32, f32, Vec3) {
+    let mut norm = 0.;
+    let mut fresnel = 0.;
+    let mut direction = Vec3::ZERO;
+    for u in samples() {
+        let l = brdf.sample(v, u);
+        let (value, pdf) = brdf.eval(v, l);
+        if pdf > 0. {
+            let weight = value / pdf;
+            let h = (v + l).normalize();
+            norm += weight;
+            fresnel += weight * (1. - v.dot(h).max(0.)).powi(5);
+            direction += weight * l;
+        }
+    }
+    let count = (SAMPLES * SAMPLES) as f32;
+    direction.y = 0.;
+    (norm / count, fresnel / count, direction.normalize())
+}
+
+/// Cubed difference between the BRDF and the LTC, importance sampled from both.
+fn error(ltc: &Ltc, brdf: &Ggx, v: Vec3) -> f32 {
+    let mut error = 0.;
+    for u in samples() {
+        for l in [ltc.sample(u), brdf.sample(v, u)] {
+            let (value, pdf_brdf) = brdf.eval(v, l);
+            let ltc_value = ltc.eval(l);
+            let pdf_ltc = ltc_value / ltc.magnitude;
+            if pdf_ltc + pdf_brdf > 0. {
+                error += (value - ltc_value).abs().powi(3) / (pdf_ltc + pdf_brdf);
+            }
+        }
+    }
+    error / (SAMPLES * SAMPLES) as f32
+}
+
+fn set_params(ltc: &mut Ltc, params: [f32; 3], isotropic: bool) {
+    let m11 = params[0].max(1e-7);
+    let m22 = params[1].max(1e-7);
+    if isotropic {
+        ltc.m11 = m11;
+        ltc.m22 = m11;
+        ltc.m13 = 0.;
+    } else {
+        ltc.m11 = m11;
+        ltc.m22 = m22;
+        ltc.m13 = params[2];
+    }
+    ltc.update();
+}
+
+/// Downhill simplex minimization of `f`, starting from `start` with steps of `delta`.
+fn nelder_mead(
+    start: [f32; 3],
+    delta: f32,
+    tolerance: f32,
+    iterations: usize,
+    mut f: impl FnMut([f32; 3]) -> f32,
+) -> [f32; 3] {
+    let mut points = [start; 4];
+    for (i, point) in points.iter_mut().enumerate().skip(1) {
+        point[i - 1] += delta;
+    }
+    let mut values = points.map(&mut f);
+    let lerp = |a: [f32; 3], b: [f32; 3], t: f32| [0, 1, 2].map(|i| a[i] + t * (b[i] - a[i]));
+
+    let mut lo = 0;
+    for _ in 0..iterations {
+        lo = 0;
+        let mut hi = 0;
+        let mut next_hi = 0;
+        for i in 1..4 {
+            if values[i] < values[lo] {
+                lo = i;
+            }
+            if values[i] > values[hi] {
+                next_hi = hi;
+                hi = i;
+            } else if values[i] > values[next_hi] {
+                next_hi = i;
+            }
+        }
+        let (a, b) = (values[lo].abs(), values[hi].abs());
+        if 2. * (a - b).abs() < (a + b) * tolerance {
+            break;
+        }
+
+        let mut centroid = [0.; 3];
+        for (_, point) in points.iter().enumerate().filter(|(i, _)| *i != hi) {
+            for k in 0..3 {
+                centroid[k] += point[k] / 3.;
+            }
+        }
+
+        let reflected = lerp(centroid, points[hi], -1.);
+        let value = f(reflected);
+        if value < values[next_hi] {
+            if value < values[lo] {
+                let expanded = lerp(centroid, points[hi], -2.);
+                let expanded_value = f(expanded);
+                if expanded_value < value {
+                    points[hi] = expanded;
+                    values[hi] = expanded_value;
+                    continue;
+                }
+            }
+            points[hi] = reflected;
+            values[hi] = value;
+            continue;
+        }
+
+        let contracted = lerp(centroid, points[hi], 0.5);
+        let value = f(contracted);
+        if value < values[hi] {
+            points[hi] = contracted;
+            values[hi] = value;
+            continue;
+        }
+
+        for i in 0..4 {
+            if i != lo {
+                points[i] = lerp(points[lo], points[i], 0.5);
+                values[i] = f(points[i]);
+            }
+        }
+    }
+    points[lo]
+}
+
+/// Fitted transforms and their magnitude and Fresnel terms, indexed by roughness along X and by
+/// `sqrt(1 - cos(theta))` of the view direction along Y.
+fn fit_table() -> (Vec<Mat3>, Vec<[f32; 2]>) {
+    let mut transforms = vec![Mat3::IDENTITY; SIZE * SIZE];
+    let mut amplitudes = vec![[0.; 2]; SIZE * SIZE];
+    let mut ltc = Ltc::default();
+    // Starts from the roughest lobe, which is closest to a cosine, and uses every fit as the first
+    // guess of the next one
+    for a in (0..SIZE).rev() {
+        for t in 0..SIZE {
+            let x = t as f32 / (SIZE - 1) as f32;
+            let theta = (1. - x * x).acos().min(1.57);
+            let v = Vec3::new(theta.sin(), 0., theta.cos());
+            let roughness = a as f32 / (SIZE - 1) as f32;
+            let brdf = Ggx {
+                alpha: (roughness * roughness).max(MIN_ALPHA),
+            };
+
+            let (magnitude, fresnel, direction) = average_terms(&brdf, v);
+            ltc.magnitude = magnitude;
+            ltc.fresnel = fresnel;
+            // At normal incidence the lobe is rotationally symmetric around the normal
+            let isotropic = t == 0;
+            if isotropic {
+                ltc.basis = Mat3::IDENTITY;
+                if a == SIZE - 1 {
+                    ltc.m11 = 1.;
+                    ltc.m22 = 1.;
+                } else {
+                    let previous = transforms[a + 1 + t * SIZE];
+                    ltc.m11 = previous.x_axis.x;
+                    ltc.m22 = previous.y_axis.y;
+                }
+                ltc.m13 = 0.;
+            } else {
+                ltc.basis =
+                    Mat3::from_cols(Vec3::new(direction.z, 0., -direction.x), Vec3::Y, direction);
+            }
+            ltc.update();
+
+            let start = [ltc.m11, ltc.m22, ltc.m13];
+            let params = nelder_mead(start, 0.05, 1e-5, 100, |params| {
+                let mut candidate = ltc;
+                set_params(&mut candidate, params, isotropic);
+                error(&candidate, &brdf, v)
+            });
+            set_params(&mut ltc, params, isotropic);
+
+            transforms[a + t * SIZE] = ltc.m;
+            amplitudes[a + t * SIZE] = [ltc.magnitude, ltc.fresnel];
+        }
+        eprintln!("Fitted roughness {}/{}", SIZE - a, SIZE);
+    }
+    (transforms, amplitudes)
+}
+
+fn main() {
+    let path = env::args_os()
+        .nth(1)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("lib/rose-renderer/src/ltc_ggx.bin"));
+    let (transforms, amplitudes) = fit_table();
+
+    // The shader only needs the non-constant coefficients of the inverse, normalized so that its
+    // middle element is 1
+    let mut bytes = Vec::with_capacity(SIZE * SIZE * 6 * 4);
+    for transform in &transforms {
+        let inverse = transform.inverse();
+        let inverse = inverse * (1. / inverse.y_axis.y);
+        for value in [
+            inverse.x_axis.x,
+            inverse.x_axis.z,
+            inverse.z_axis.x,
+            inverse.z_axis.z,
+        ] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+    for amplitude in &amplitudes {
+        for value in amplitude {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+    fs::write(&path, bytes).unwrap_or_else(|err| panic!("Cannot write {}: {err}", path.display()));
+    eprintln!("Wrote {}", path.display());
+}
@@ -0,0 +1,161 @@
+use std::time::{Duration, Instant};
+
+use egui::{Align2, Color32, ComboBox, Context, RichText, ScrollArea, Sense, TextEdit, Ui};
+
+use rose::{
+    core::utils::shader_errors,
+    platform::log_capture::{self, LogEntry},
+    prelude::*,
+};
+
+const TOAST_DURATION: Duration = Duration::from_secs(5);
+
+const LEVELS: [tracing::Level; 5] = [
+    tracing::Level::ERROR,
+    tracing::Level::WARN,
+    tracing::Level::INFO,
+    tracing::Level::DEBUG,
+    tracing::Level::TRACE,
+];
+
+/// Console tab showing the captured tracing events.
+pub struct ConsolePanel {
+    /// Least severe level shown.
+    pub max_level: tracing::Level,
+    pub target_filter: String,
+    pub message_filter: String,
+    pub auto_scroll: bool,
+}
+
+impl Default for ConsolePanel {
+    fn default() -> Self {
+        Self {
+            max_level: tracing::Level::INFO,
+            target_filter: String::new(),
+            message_filter: String::new(),
+            auto_scroll: true,
+        }
+    }
+}
+
+impl ConsolePanel {
+    fn matches(&self, entry: &LogEntry) -> bool {
+        entry.level <= self.max_level
+            && entry.target.contains(self.target_filter.as_str())
+            && entry.message.contains(self.message_filter.as_str())
+    }
+
+    fn color(entry: &LogEntry) -> Color32 {
+        match entry.level {
+            tracing::Level::ERROR => Color32::from_rgb(255, 90, 90),
+            tracing::Level::WARN => Color32::from_rgb(255, 200, 80),
+            tracing::Level::INFO => Color32::LIGHT_GRAY,
+            _ => Color32::GRAY,
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            ComboBox::from_id_source("console-level")
+                .selected_text(self.max_level.as_str())
+                .show_ui(ui, |ui| {
+                    for level in LEVELS {
+                        ui.selectable_value(&mut self.max_level, level, level.as_str());
+                    }
+                });
+            ui.add(
+                TextEdit::singleline(&mut self.target_filter)
+                    .hint_text("Target")
+                    .desired_width(120.),
+            );
+            ui.add(
+                TextEdit::singleline(&mut self.message_filter)
+                    .hint_text("Message")
+                    .desired_width(160.),
+            );
+            ui.checkbox(&mut self.auto_scroll, "Auto-scroll");
+            if ui.button("Clear").clicked() {
+                log_capture::clear();
+            }
+        });
+        ui.separator();
+
+        let entries = log_capture::entries();
+        let entries = entries
+            .iter()
+            .filter(|entry| self.matches(entry))
+            .collect::<Vec<_>>();
+        let row_height = ui.text_style_height(&egui::TextStyle::Monospace);
+        ScrollArea::both()
+            .auto_shrink([false, false])
+            .stick_to_bottom(self.auto_scroll)
+            .show_rows(ui, row_height, entries.len(), |ui, range| {
+                for entry in &entries[range] {
+                    let mut text = RichText::new(entry.to_string())
+                        .monospace()
+                        .color(Self::color(entry));
+                    if entry.is_shader_reload_error() {
+                        text = text
+                            .background_color(Color32::from_rgb(90, 20, 20))
+                            .strong();
+                    }
+                    let response = ui
+                        .add(egui::Label::new(text).wrap(false).sense(Sense::click()))
+                        .on_hover_text("Click to copy");
+                    if response.clicked() {
+                        ui.output().copied_text = entry.to_string();
+                    }
+                }
+            });
+    }
+}
+
+/// Notification shown when shader hot-reloading fails, or succeeds again.
+#[derive(Default)]
+pub struct ShaderErrorToast {
+    generation: u64,
+    error_count: usize,
+    toast: Option<(String, bool, Instant)>,
+}
+
+impl ShaderErrorToast {
+    pub fn show(&mut self, ctx: &Context) {
+        let generation = shader_errors::generation();
+        if generation != self.generation {
+            let errors = shader_errors::errors();
+            let new_errors = errors
+                .iter()
+                .filter(|error| error.generation > self.generation)
+                .map(|error| error.location())
+                .collect::<Vec<_>>();
+            if !new_errors.is_empty() {
+                let text = format!("Shader failed to compile: {}", new_errors.join(", "));
+                self.toast = Some((text, true, Instant::now()));
+            } else if errors.len() < self.error_count {
+                self.toast = Some(("Shader compiled".to_string(), false, Instant::now()));
+            }
+            self.generation = generation;
+            self.error_count = errors.len();
+        }
+
+        let Some((text, is_error, shown_at)) = &self.toast else { return; };
+        if shown_at.elapsed() > TOAST_DURATION {
+            self.toast = None;
+            return;
+        }
+        let color = if *is_error {
+            Color32::from_rgb(255, 90, 90)
+        } else {
+            Color32::from_rgb(120, 220, 120)
+        };
+        egui::Area::new("shader-error-toast")
+            .anchor(Align2::CENTER_TOP, [0., 40.])
+            .interactable(false)
+            .show(ctx, |ui| {
+                egui::Frame::popup(ui.style()).show(ui, |ui| {
+                    ui.label(RichText::new(text).color(color).strong());
+                });
+            });
+        ctx.request_repaint_after(TOAST_DURATION);
+    }
+}
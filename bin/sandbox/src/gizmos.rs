@@ -0,0 +1,403 @@
+use egui::{Align2, Color32, FontId, Pos2, Rect, Response, Sense, Shape, Stroke, Ui};
+
+use rose::{ecs::components::Light, prelude::*};
+
+const ICON_RADIUS: f32 = 9.;
+const CIRCLE_SEGMENTS: usize = 32;
+/// Illuminance under which point lights are considered to have no effect, for their radius sphere.
+const POINT_LIGHT_CUTOFF: f32 = 0.05;
+/// Length of the drawn camera frustums, in world units.
+const FRUSTUM_LENGTH: f32 = 1.;
+const LIGHT_COLOR: Color32 = Color32::from_rgb(255, 210, 90);
+const CAMERA_COLOR: Color32 = Color32::from_rgb(120, 180, 255);
+const EMPTY_COLOR: Color32 = Color32::from_rgb(200, 200, 200);
+const SPLINE_COLOR: Color32 = Color32::from_rgb(120, 230, 140);
+const CLIP_PLANE_COLOR: Color32 = Color32::from_rgb(240, 120, 200);
+/// Half the side of the square drawn for clip planes, in world units before scaling.
+const CLIP_PLANE_EXTENT: f32 = 1.;
+const HANDLE_RADIUS: f32 = 5.;
+/// Lines per segment of the drawn splines.
+const SPLINE_SEGMENT_SAMPLES: usize = 16;
+
+/// Projection of world-space positions into the viewport.
+struct ViewportProjection {
+    view_proj: Mat4,
+    rect: Rect,
+}
+
+impl ViewportProjection {
+    fn new(camera: &Camera, rect: Rect) -> Self {
+        Self {
+            view_proj: camera.projection.matrix() * camera.transform.matrix(),
+            rect,
+        }
+    }
+
+    fn to_screen(&self, p: Vec3) -> Option<Pos2> {
+        let clip = self.view_proj * p.extend(1.);
+        if clip.w <= 0. {
+            return None;
+        }
+        let ndc = clip.truncate() / clip.w;
+        Some(egui::pos2(
+            self.rect.left() + (ndc.x + 1.) / 2. * self.rect.width(),
+            self.rect.top() + (1. - ndc.y) / 2. * self.rect.height(),
+        ))
+    }
+
+    /// Normalized device coordinates of the screen position, at the given NDC depth.
+    fn to_ndc(&self, pos: Pos2, depth: f32) -> Vec3 {
+        vec3(
+            (pos.x - self.rect.left()) / self.rect.width() * 2. - 1.,
+            1. - (pos.y - self.rect.top()) / self.rect.height() * 2.,
+            depth,
+        )
+    }
+
+    /// Point projected at `pos`, at the same depth as `reference`.
+    fn to_world(&self, pos: Pos2, reference: Vec3) -> Option<Vec3> {
+        let clip = self.view_proj * reference.extend(1.);
+        if clip.w <= 0. {
+            return None;
+        }
+        let ndc = self.to_ndc(pos, clip.z / clip.w);
+        Some(self.view_proj.inverse().project_point3(ndc))
+    }
+
+    /// Line through the points; skipped entirely when a point is behind the camera.
+    fn polyline(&self, points: impl IntoIterator<Item = Vec3>, stroke: Stroke) -> Option<Shape> {
+        let points = points
+            .into_iter()
+            .map(|p| self.to_screen(p))
+            .collect::<Option<Vec<_>>>()?;
+        Some(Shape::line(points, stroke))
+    }
+
+    fn closed_polyline(&self, points: Vec<Vec3>, stroke: Stroke) -> Option<Shape> {
+        let first = *points.first()?;
+        self.polyline(points.into_iter().chain([first]), stroke)
+    }
+}
+
+fn circle(center: Vec3, ex: Vec3, ey: Vec3) -> Vec<Vec3> {
+    (0..CIRCLE_SEGMENTS)
+        .map(|i| std::f32::consts::TAU * i as f32 / CIRCLE_SEGMENTS as f32)
+        .map(|angle| center + angle.cos() * ex + angle.sin() * ey)
+        .collect()
+}
+
+fn light_shapes(
+    proj: &ViewportProjection,
+    transform: &Transform,
+    light: &Light,
+    stroke: Stroke,
+) -> Vec<Shape> {
+    let center = transform.position;
+    match light.kind {
+        LightKind::Ambient => vec![],
+        LightKind::Point => {
+            let radius = light.point_radius(POINT_LIGHT_CUTOFF);
+            [(Vec3::X, Vec3::Y), (Vec3::X, Vec3::Z), (Vec3::Y, Vec3::Z)]
+                .into_iter()
+                .filter_map(|(ex, ey)| {
+                    proj.closed_polyline(circle(center, ex * radius, ey * radius), stroke)
+                })
+                .collect()
+        }
+        LightKind::Directional => {
+            let dir = transform.rotation * Vec3::NEG_Z;
+            arrow(proj, center, center + dir, stroke)
+        }
+        LightKind::Rect | LightKind::Disk => {
+            let size = light.size * transform.scale.truncate() / 2.;
+            let ex = transform.rotation * Vec3::X * size.x;
+            let ey = transform.rotation * Vec3::Y * size.y;
+            let outline = if light.kind == LightKind::Rect {
+                vec![
+                    center - ex - ey,
+                    center + ex - ey,
+                    center + ex + ey,
+                    center - ex + ey,
+                ]
+            } else {
+                circle(center, ex, ey)
+            };
+            let normal = transform.rotation * Vec3::Z * size.max_element();
+            let mut shapes = arrow(proj, center, center + normal, stroke);
+            shapes.extend(proj.closed_polyline(outline, stroke));
+            shapes
+        }
+    }
+}
+
+fn arrow(proj: &ViewportProjection, from: Vec3, to: Vec3, stroke: Stroke) -> Vec<Shape> {
+    let (Some(from), Some(to)) = (proj.to_screen(from), proj.to_screen(to)) else {
+        return vec![];
+    };
+    let mut shapes = vec![Shape::line_segment([from, to], stroke)];
+    let dir = (to - from).normalized();
+    if dir.x.is_finite() {
+        let side = dir.rot90() * 4.;
+        let back = to - dir * 8.;
+        shapes.push(Shape::line(vec![back + side, to, back - side], stroke));
+    }
+    shapes
+}
+
+/// Square in the plane, with an arrow towards the side which is kept.
+fn clip_plane_shapes(
+    proj: &ViewportProjection,
+    transform: &Transform,
+    plane: &ClipPlane,
+    stroke: Stroke,
+) -> Vec<Shape> {
+    let center = transform.position;
+    let extent = transform.scale * CLIP_PLANE_EXTENT;
+    let ex = transform.rotation * Vec3::X * extent.x;
+    let ez = transform.rotation * Vec3::Z * extent.z;
+    let normal = plane.equation(transform).truncate() * extent.y;
+    let mut shapes = arrow(proj, center, center + normal, stroke);
+    shapes.extend(proj.closed_polyline(
+        vec![
+            center - ex - ez,
+            center + ex - ez,
+            center + ex + ez,
+            center - ex + ez,
+        ],
+        stroke,
+    ));
+    shapes
+}
+
+/// Frustum of a camera entity, whose transform is its view matrix like [`Camera::transform`].
+fn camera_shapes(
+    proj: &ViewportProjection,
+    inv_view: Mat4,
+    params: &CameraParams,
+    aspect: f32,
+    stroke: Stroke,
+) -> Vec<Shape> {
+    let corners = |distance: f32| {
+        let half_height = distance * (params.fovy / 2.).tan();
+        let half_width = half_height * aspect;
+        [(-1., -1.), (1., -1.), (1., 1.), (-1., 1.)].map(|(x, y)| {
+            inv_view.transform_point3(vec3(x * half_width, y * half_height, -distance))
+        })
+    };
+    let near = corners(params.zrange.start);
+    let far = corners(FRUSTUM_LENGTH.min(params.zrange.end));
+    let mut shapes = vec![];
+    shapes.extend(proj.closed_polyline(near.to_vec(), stroke));
+    shapes.extend(proj.closed_polyline(far.to_vec(), stroke));
+    for (near, far) in near.into_iter().zip(far) {
+        shapes.extend(proj.polyline([near, far], stroke));
+    }
+    shapes
+}
+
+/// Draw wireframe gizmos and icons for lights, cameras, clip planes and empty entities, which have
+/// no visible geometry in the viewport. Returns the entity whose icon was clicked.
+pub fn draw_entity_gizmos(
+    ui: &Ui,
+    rect: Rect,
+    response: &Response,
+    camera: &Camera,
+    world: &World,
+    selected: Option<Entity>,
+) -> Option<Entity> {
+    let proj = ViewportProjection::new(camera, rect);
+    let aspect = camera.projection.width / camera.projection.height;
+    let painter = ui.painter_at(rect);
+    let mut icons = vec![];
+    let mut query = world.query::<(
+        &GlobalTransform,
+        Option<&Light>,
+        Option<&CameraParams>,
+        Option<&ClipPlane>,
+        Option<&Handle<'static, MeshAsset>>,
+    )>();
+    for (entity, (transform, light, camera_params, clip_plane, mesh)) in query.iter() {
+        let transform = &transform.0;
+        let (position, color, letter) = if let Some(params) = camera_params {
+            let inv_view = transform.matrix().inverse();
+            let stroke = Stroke::new(1.5, CAMERA_COLOR);
+            painter.extend(camera_shapes(&proj, inv_view, params, aspect, stroke));
+            (inv_view.transform_point3(Vec3::ZERO), CAMERA_COLOR, "C")
+        } else if let Some(light) = light {
+            let stroke = Stroke::new(1.5, LIGHT_COLOR);
+            painter.extend(light_shapes(&proj, transform, light, stroke));
+            (transform.position, LIGHT_COLOR, "L")
+        } else if let Some(plane) = clip_plane {
+            let stroke = Stroke::new(1.5, CLIP_PLANE_COLOR);
+            painter.extend(clip_plane_shapes(&proj, transform, plane, stroke));
+            (transform.position, CLIP_PLANE_COLOR, "P")
+        } else if mesh.is_none() {
+            (transform.position, EMPTY_COLOR, "E")
+        } else {
+            continue;
+        };
+        let Some(pos) = proj.to_screen(position) else {
+            continue;
+        };
+        let stroke_width = if selected == Some(entity) { 2.5 } else { 1. };
+        painter.circle(
+            pos,
+            ICON_RADIUS,
+            Color32::from_black_alpha(180),
+            Stroke::new(stroke_width, color),
+        );
+        painter.text(
+            pos,
+            Align2::CENTER_CENTER,
+            letter,
+            FontId::monospace(10.),
+            color,
+        );
+        icons.push((entity, pos));
+    }
+
+    if !response.clicked() {
+        return None;
+    }
+    let pointer = response.interact_pointer_pos()?;
+    icons
+        .into_iter()
+        .map(|(entity, pos)| (entity, pos.distance(pointer)))
+        .filter(|(_, distance)| *distance <= ICON_RADIUS)
+        .min_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(entity, _)| entity)
+}
+
+/// Entity whose mesh is under `pointer`, closest to the camera.
+pub fn pick_mesh(
+    rect: Rect,
+    pointer: Pos2,
+    renderer: &RenderSystem,
+    world: &World,
+) -> Option<Entity> {
+    let proj = ViewportProjection::new(&renderer.camera, rect);
+    let inv_view_proj = proj.view_proj.inverse();
+    let near = inv_view_proj.project_point3(proj.to_ndc(pointer, -1.));
+    let far = inv_view_proj.project_point3(proj.to_ndc(pointer, 1.));
+    let mut query = world
+        .query::<(&GlobalTransform, &Handle<'static, MeshAsset>)>()
+        .without::<&Inactive>()
+        .without::<&InactiveInHierarchy>();
+    query
+        .iter()
+        .filter_map(|(entity, (transform, mesh))| {
+            // Ray in the space of the mesh, spanning the view frustum so that the distances along
+            // it can be compared across entities
+            let inv_model = transform.0.matrix().inverse();
+            let origin = inv_model.transform_point3(near);
+            let direction = inv_model.transform_point3(far) - origin;
+            // Skip the triangles of meshes whose bounding sphere the ray misses
+            let radius = renderer
+                .mesh_radius(mesh)
+                .unwrap_or_else(|| mesh.read().bounding_radius());
+            let closest = (-origin.dot(direction) / direction.length_squared()).clamp(0., 1.);
+            if (origin + closest * direction).length_squared() > radius * radius {
+                return None;
+            }
+            let distance = mesh.read().raycast(origin, direction)?;
+            (distance <= 1.).then_some((entity, distance))
+        })
+        .min_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(entity, _)| entity)
+}
+
+/// Draw the spline followed by the selected entity, with handles to drag its control points in the
+/// plane of the screen. Returns whether a handle is being dragged.
+pub fn edit_spline_handles(
+    ui: &Ui,
+    rect: Rect,
+    camera: &Camera,
+    world: &World,
+    selected: Option<Entity>,
+) -> bool {
+    let Some(entity) = selected else {
+        return false;
+    };
+    let Ok(mut follow) = world.get::<&mut SplineFollow>(entity) else {
+        return false;
+    };
+    // Control points are in the space of the parent
+    let parent_matrix = world
+        .get::<&Parent>(entity)
+        .ok()
+        .and_then(|parent| world.get::<&GlobalTransform>(parent.0).ok())
+        .map_or(Mat4::IDENTITY, |transform| transform.0.matrix());
+    let inv_parent_matrix = parent_matrix.inverse();
+    let proj = ViewportProjection::new(camera, rect);
+    let painter = ui.painter_at(rect);
+
+    let spline = &follow.spline;
+    let steps = spline.segment_count() * SPLINE_SEGMENT_SAMPLES;
+    let curve = (0..=steps).map(|i| {
+        let t = i as f32 / SPLINE_SEGMENT_SAMPLES as f32;
+        parent_matrix.transform_point3(spline.sample(t))
+    });
+    painter.extend(proj.polyline(curve, Stroke::new(2., SPLINE_COLOR)));
+
+    let mut dragging = false;
+    let mut edit = None;
+    for i in 0..follow.spline.points.len() {
+        let point = parent_matrix.transform_point3(follow.spline.points[i]);
+        let Some(pos) = proj.to_screen(point) else {
+            continue;
+        };
+        let handle_rect = Rect::from_center_size(pos, egui::Vec2::splat(2. * HANDLE_RADIUS));
+        let id = ui.id().with(("spline-handle", entity, i));
+        let response = ui.interact(handle_rect, id, Sense::drag());
+        if response.dragged() {
+            dragging = true;
+            if let Some(moved) = proj.to_world(pos + response.drag_delta(), point) {
+                follow.spline.points[i] = inv_parent_matrix.transform_point3(moved);
+            }
+        }
+        let fill = if response.hovered() || response.dragged() {
+            SPLINE_COLOR
+        } else {
+            Color32::from_black_alpha(180)
+        };
+        painter.circle(pos, HANDLE_RADIUS, fill, Stroke::new(1.5, SPLINE_COLOR));
+        if follow.spline.is_knot(i) {
+            let segment = match follow.spline.kind {
+                SplineKind::CatmullRom => i,
+                SplineKind::Bezier => i / 3,
+            };
+            let has_next = segment < follow.spline.segment_count();
+            response.context_menu(|ui| {
+                if ui
+                    .add_enabled(has_next, egui::Button::new("Insert point after"))
+                    .clicked()
+                {
+                    edit = Some(SplineEdit::Split(segment));
+                    ui.close_menu();
+                }
+                if ui.button("Remove point").clicked() {
+                    edit = Some(SplineEdit::Remove(i));
+                    ui.close_menu();
+                }
+            });
+        }
+    }
+    match edit {
+        Some(SplineEdit::Split(segment)) => {
+            follow.spline.split_segment(segment);
+        }
+        Some(SplineEdit::Remove(i)) => {
+            follow.spline.remove_point(i);
+        }
+        None => {}
+    }
+    dragging
+}
+
+/// Change to the control points picked from the context menu of a handle.
+enum SplineEdit {
+    /// Split the segment starting at the handle.
+    Split(usize),
+    /// Remove the point of the handle.
+    Remove(usize),
+}
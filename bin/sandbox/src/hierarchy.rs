@@ -0,0 +1,263 @@
+//! Scene hierarchy tab, showing the entities as a tree following their [`Parent`] components.
+
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+};
+
+use egui::{CollapsingHeader, CursorIcon, RichText, Sense, TextEdit, Ui, WidgetText};
+
+use rose::prelude::*;
+
+use crate::views::EditorViews;
+
+/// Hierarchy tab state; entities are reparented by dragging them onto another entity, or onto the
+/// empty space below the tree to make them roots.
+#[derive(Debug, Default)]
+pub struct HierarchyPanel {
+    /// Only show entities whose name or one of whose components contains this text, along with
+    /// their ancestors.
+    pub filter: String,
+    dragged: Option<Entity>,
+}
+
+struct Tree<'a> {
+    world: &'a World,
+    cmd: &'a mut CommandBuffer,
+    selected_entity: &'a mut Option<Entity>,
+    children: HashMap<Entity, Vec<Entity>>,
+    /// Entities left by the filter, if there is one.
+    visible: Option<HashSet<Entity>>,
+    drop_target: Option<Entity>,
+}
+
+impl HierarchyPanel {
+    pub fn ui(
+        &mut self,
+        ui: &mut Ui,
+        world: &World,
+        cmd: &mut CommandBuffer,
+        components: &UiSystem,
+        selected_entity: &mut Option<Entity>,
+    ) {
+        ui.horizontal(|ui| {
+            ui.add(
+                TextEdit::singleline(&mut self.filter)
+                    .hint_text("Name or component")
+                    .desired_width(ui.available_width() - 20.),
+            );
+            if ui.button("x").clicked() {
+                self.filter.clear();
+            }
+        });
+
+        let mut children = HashMap::<_, Vec<_>>::new();
+        for (entity, parent) in world.query::<&Parent>().iter() {
+            children.entry(parent.0).or_default().push(entity);
+        }
+        let visible = (!self.filter.is_empty()).then(|| self.filtered(world, components));
+        let mut tree = Tree {
+            world,
+            cmd,
+            selected_entity,
+            children,
+            visible,
+            drop_target: None,
+        };
+        let mut roots = world
+            .query::<()>()
+            .without::<&Parent>()
+            .without::<&EditorViews>()
+            .without::<&RendererSettings>();
+        for (entity, _) in roots.iter() {
+            self.node(ui, &mut tree, entity);
+        }
+
+        let size = ui.available_size();
+        let (rect, response) = ui.allocate_exact_size(size, Sense::click());
+        if response
+            .context_menu(|ui| {
+                if ui.small_button("Add empty").clicked() {
+                    tree.cmd.spawn(());
+                    ui.close_menu();
+                }
+            })
+            .clicked()
+        {
+            tree.selected_entity.take();
+        }
+
+        if let Some(dragged) = self.dragged {
+            ui.output().cursor_icon = CursorIcon::Grabbing;
+            if ui.input().pointer.any_released() {
+                match tree.drop_target {
+                    Some(target) if !descends_from(world, target, dragged) => {
+                        tree.cmd.insert_one(dragged, Parent(target));
+                    }
+                    None if ui.rect_contains_pointer(rect) => {
+                        tree.cmd.remove_one::<Parent>(dragged);
+                    }
+                    _ => {}
+                }
+                self.dragged = None;
+            }
+        }
+    }
+
+    fn filtered(&self, world: &World, components: &UiSystem) -> HashSet<Entity> {
+        let filter = self.filter.to_lowercase();
+        let mut visible = HashSet::new();
+        for entity in world.iter() {
+            let matches = entity_name(entity).to_lowercase().contains(&filter)
+                || entity
+                    .component_types()
+                    .filter_map(|type_id| components.component_name(type_id))
+                    .any(|name| name.to_lowercase().contains(&filter));
+            if !matches {
+                continue;
+            }
+            let mut current = Some(entity.entity());
+            while let Some(entity) = current {
+                // Ancestors of visible entities are visible already
+                if !visible.insert(entity) {
+                    break;
+                }
+                current = world.get::<&Parent>(entity).ok().map(|parent| parent.0);
+            }
+        }
+        visible
+    }
+
+    fn node(&mut self, ui: &mut Ui, tree: &mut Tree, entity: Entity) {
+        if let Some(visible) = &tree.visible {
+            if !visible.contains(&entity) {
+                return;
+            }
+        }
+        let eref = tree.world.entity(entity).unwrap();
+        let selected = *tree.selected_entity == Some(entity);
+        let heading = WidgetText::RichText(RichText::new(entity_name(eref)));
+        let heading = if selected { heading.strong() } else { heading };
+        let heading = if eref.has::<InactiveInHierarchy>() {
+            heading.weak()
+        } else {
+            heading
+        };
+        let children = tree.children.get(&entity).cloned().unwrap_or_default();
+        let mut header = CollapsingHeader::new(heading).id_source(entity);
+        if children.is_empty() {
+            header = header.icon(|_, _, _| {});
+        }
+        if tree.visible.is_some() {
+            header = header.open(Some(true));
+        }
+        let header = header
+            .show(ui, |ui| {
+                for child in children {
+                    self.node(ui, tree, child);
+                }
+            })
+            .header_response;
+
+        let drag = ui.interact(header.rect, header.id.with("drag"), Sense::drag());
+        if drag.drag_started() {
+            self.dragged = Some(entity);
+        }
+        if self.dragged.is_some_and(|dragged| dragged != entity)
+            && ui.rect_contains_pointer(header.rect)
+        {
+            tree.drop_target = Some(entity);
+            ui.painter()
+                .rect_stroke(header.rect, 2., ui.visuals().selection.stroke);
+        }
+
+        let header = header.context_menu(|ui| Self::context_menu(ui, tree, eref));
+        if header.clicked() {
+            tree.selected_entity.replace(entity);
+        }
+    }
+
+    fn context_menu(ui: &mut Ui, tree: &mut Tree, entity: EntityRef) {
+        let Tree {
+            world,
+            cmd,
+            selected_entity,
+            ..
+        } = tree;
+        if let Some(mut name) = entity.get::<&mut String>() {
+            let name_label = ui.label("Name:").id;
+            ui.text_edit_singleline(&mut *name).labelled_by(name_label);
+        } else if ui.small_button("Add name").clicked() {
+            cmd.insert_one(entity.entity(), String::new());
+        }
+        let mut active = !entity.has::<Inactive>();
+        if ui.checkbox(&mut active, "Active").changed() {
+            if active {
+                cmd.remove_one::<Inactive>(entity.entity());
+            } else {
+                cmd.insert_one(entity.entity(), Inactive);
+            }
+        }
+        ui.separator();
+        if ui.small_button("Remove").clicked() {
+            cmd.despawn(entity.entity());
+            if **selected_entity == Some(entity.entity()) {
+                **selected_entity = None;
+            }
+            ui.close_menu();
+        }
+        ui.separator();
+        ui.menu_button("Parent to", |ui| {
+            thread_local! {static SEARCH: RefCell<String> = RefCell::new(String::new());}
+            SEARCH.with(|search_key| {
+                let mut q = world.query::<&String>();
+                let mut search = search_key.borrow_mut();
+                ui.text_edit_singleline(&mut *search);
+                egui::ScrollArea::new([false, true]).show(ui, |ui| {
+                    for (potential_parent, name) in q.iter() {
+                        if (search.is_empty() || name.contains(search.as_str()))
+                            && !descends_from(world, potential_parent, entity.entity())
+                            && ui.small_button(name.as_str()).clicked()
+                        {
+                            cmd.insert_one(entity.entity(), Parent(potential_parent));
+                        }
+                    }
+                });
+            });
+        });
+        if entity.has::<Parent>() && ui.small_button("Unparent").clicked() {
+            cmd.remove_one::<Parent>(entity.entity());
+            ui.close_menu();
+        }
+        if ui.small_button("Add child entity").clicked() {
+            cmd.spawn_child(entity.entity(), &mut EntityBuilder::new());
+            ui.close_menu();
+        }
+    }
+}
+
+fn entity_name(entity: EntityRef) -> String {
+    entity
+        .get::<&String>()
+        .map(|s| s.to_string())
+        .or_else(|| {
+            entity
+                .get::<&NamedObject>()
+                .map(|n| format!("[Object {:?}]", n.object.as_str()))
+        })
+        .unwrap_or("<Unnamed>".to_string())
+}
+
+/// Whether `entity` is `ancestor` or one of its descendants, in which case it cannot become the
+/// parent of `ancestor`.
+fn descends_from(world: &World, mut entity: Entity, ancestor: Entity) -> bool {
+    loop {
+        if entity == ancestor {
+            return true;
+        }
+        match world.get::<&Parent>(entity) {
+            Ok(parent) => entity = parent.0,
+            Err(_) => return false,
+        }
+    }
+}
@@ -1,44 +1,145 @@
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use egui_gizmo::GizmoMode;
 use rfd::FileDialog;
 
+use rose::core::color::linear_to_srgb;
+use rose::core::utils::thread_guard::ThreadGuard;
 use rose::ecs::load_gltf::load_gltf_scene;
-use rose::prelude::*;
+use rose::ui::viewport::ViewportTexture;
+use rose::{platform::window, prelude::*};
 use violette::framebuffer::{ClearBuffer, Framebuffer};
 
+use crate::missing_assets::MissingAssetsWindow;
+use crate::scene_browser::{save_thumbnail, SceneBrowser, SceneChoice};
+use crate::session::{Autosave, Session, AUTOSAVE_INTERVAL};
 use crate::ui::EditorUiSystem;
+use crate::views::EditorViews;
 
+pub mod console;
+pub mod gizmos;
+pub mod hierarchy;
+pub mod missing_assets;
+pub mod scene_browser;
+pub mod session;
+pub mod stats;
 pub mod ui;
+pub mod views;
+
+/// Choices of UI scale offered in the View menu.
+const UI_SCALES: [f32; 5] = [0.75, 1., 1.25, 1.5, 2.];
+/// Game time speeds offered in the toolbar.
+const TIME_SCALES: [f32; 6] = [0.1, 0.25, 0.5, 1., 2., 4.];
+/// Side of the cube faces captured for 360° screenshots, in pixels.
+const PANORAMA_RESOLUTION: u32 = 512;
 
 struct Sandbox {
     core_systems: CoreSystems,
     editor_cam_controller: PanOrbitCamera,
+    views: EditorViews,
     pan_orbit_system: PanOrbitSystem,
     ui_system: EditorUiSystem,
+    /// Texture the scene is rendered into, shown in the Viewport tab.
+    viewport: ThreadGuard<ViewportTexture>,
     editor_scene: Option<Scene>,
     active_scene: Option<Scene>,
+    session: Session,
+    scene_browser: SceneBrowser,
+    missing_assets: MissingAssetsWindow,
+    autosave_timer: Duration,
+    /// Autosave left by a previous session, waiting for the user to restore or discard it.
+    recovery: Option<Autosave>,
+    /// Where to save the 360° screenshot requested from the File menu, once captured.
+    panorama_path: Option<PathBuf>,
+    /// Frame of the viewport being read back to save as the thumbnail of the scene at the path.
+    pending_thumbnail: Option<(ThreadGuard<Readback>, PathBuf)>,
 }
 
 impl Sandbox {
     fn new_scene(&mut self) {
         self.active_scene.take();
         self.editor_scene.take();
-        if let Some(folder) = FileDialog::new().pick_folder() {
-            match Scene::new(folder) {
-                Ok(scene) => {
-                    self.editor_scene.replace(scene);
-                }
-                Err(err) => tracing::error!("Cannot create new scene: {}", err),
+        let folder = if self.core_systems.project.is_some() {
+            // New scenes use the project asset root
+            PathBuf::new()
+        } else if let Some(folder) = FileDialog::new().pick_folder() {
+            folder
+        } else {
+            return;
+        };
+        match self.core_systems.new_scene(folder) {
+            Ok(scene) => {
+                self.editor_scene.replace(scene);
+                self.views = EditorViews::default();
             }
+            Err(err) => tracing::error!("Cannot create new scene: {}", err),
+        }
+    }
+
+    fn open_project(&mut self) -> Result<()> {
+        let file = FileDialog::new()
+            .add_filter("Projects", &["toml"])
+            .set_directory(std::env::current_dir().unwrap())
+            .pick_file();
+        if let Some(file) = file {
+            self.do_open_project(file)?;
+        }
+        Ok(())
+    }
+
+    fn do_open_project(&mut self, path: impl AsRef<Path>) -> Result<()> {
+        self.active_scene.take();
+        self.editor_scene = self.core_systems.load_project(path)?;
+        self.views = self
+            .editor_scene
+            .as_ref()
+            .map(EditorViews::load)
+            .unwrap_or_default();
+        Ok(())
+    }
+
+    /// Resize the viewport texture and the renderer to the size the Viewport tab was laid out at.
+    fn resize_viewport(&mut self) -> Result<()> {
+        let size = self.ui_system.last_state.viewport_size;
+        if size.x == 0 || size.y == 0 {
+            return Ok(());
         }
+        if self.viewport.resize(size)? {
+            self.core_systems
+                .resize(PhysicalSize::new(size.x, size.y))?;
+        }
+        Ok(())
+    }
+
+    /// Title of the window, showing the open scene and whether it is playing.
+    fn window_title(&self) -> String {
+        let Some(scene) = &self.editor_scene else { return "Sandbox".to_string(); };
+        let name = scene
+            .path()
+            .file_name()
+            .map_or_else(|| "Untitled".into(), |name| name.to_string_lossy());
+        if self.active_scene.is_some() {
+            format!("{} (playing) - Sandbox", name)
+        } else {
+            format!("{} - Sandbox", name)
+        }
+    }
+
+    /// Directory file dialogs start in: the project root, or else the working directory.
+    fn dialog_directory(&self) -> PathBuf {
+        self.core_systems
+            .project
+            .as_ref()
+            .map(|project| project.root().to_path_buf())
+            .unwrap_or_else(|| std::env::current_dir().unwrap())
     }
 
     fn open_scene(&mut self) -> Result<()> {
         let file = FileDialog::new()
             .add_filter("Scenes", &["scene"])
             .add_filter("TOML files", &["toml"])
-            .set_directory(std::env::current_dir().unwrap())
+            .set_directory(self.dialog_directory())
             .pick_file();
         if let Some(file) = file {
             self.do_open_scene(file)?;
@@ -49,7 +150,7 @@ impl Sandbox {
     fn save_scene(&mut self) -> Result<()> {
         let file = FileDialog::new()
             .add_filter("Scenes", &["scene"])
-            .set_directory(std::env::current_dir().unwrap())
+            .set_directory(self.dialog_directory())
             .save_file();
         if let Some(file) = file {
             self.save_scene_as(file)?;
@@ -60,17 +161,131 @@ impl Sandbox {
     fn save_scene_as(&mut self, path: impl AsRef<Path>) -> Result<()> {
         if let Some(scene) = &mut self.editor_scene {
             scene.set_path(path);
+            self.views.store(scene);
+            self.core_systems.store_renderer_settings(scene);
             self.core_systems.save_scene(scene)?;
+            for (type_id, entities) in self.core_systems.persistence.skipped_components() {
+                let name = self
+                    .ui_system
+                    .component_name(type_id)
+                    .unwrap_or("Unregistered component");
+                tracing::warn!("{} of {} entities was not saved", name, entities);
+            }
+            match self.viewport.readback() {
+                Ok(readback) => {
+                    let path = scene.path().to_path_buf();
+                    self.pending_thumbnail = Some((ThreadGuard::new(readback), path));
+                }
+                Err(err) => tracing::warn!("Cannot save scene thumbnail: {}", err),
+            }
+            self.session.clear_autosave();
+            self.session.add_recent_scene(scene.path());
+            self.autosave_timer = Duration::ZERO;
         }
         Ok(())
     }
 
+    /// Save the thumbnail of the last saved scene once its frame has been read back.
+    fn save_pending_thumbnail(&mut self) {
+        let Some((readback, path)) = &mut self.pending_thumbnail else { return; };
+        let size = readback.size();
+        let Some(pixels) = readback.poll_pixels::<[f32; 3]>() else { return; };
+        if let Err(err) = save_thumbnail(&pixels, size, path) {
+            tracing::warn!("Cannot save scene thumbnail: {}", err);
+        }
+        self.scene_browser.invalidate(path);
+        self.pending_thumbnail = None;
+    }
+
+    /// Save the AOVs of the last frame as a multi-layer EXR file, in the directory of the dialogs.
+    fn export_aovs(&self) {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let path = self
+            .dialog_directory()
+            .join(format!("aovs-{}.exr", timestamp));
+        let result = self
+            .core_systems
+            .render
+            .renderer
+            .capture_aovs()
+            .and_then(|aovs| aovs.write_exr(&path));
+        match result {
+            Ok(()) => tracing::info!("Exported AOVs to {}", path.display()),
+            Err(err) => tracing::error!("Cannot export AOVs: {}", err),
+        }
+    }
+
+    fn tick_autosave(&mut self, dt: Duration) {
+        if self.active_scene.is_some() {
+            return;
+        }
+        let Some(scene) = &mut self.editor_scene else { return; };
+        self.autosave_timer += dt;
+        if self.autosave_timer < AUTOSAVE_INTERVAL {
+            return;
+        }
+        self.autosave_timer = Duration::ZERO;
+        self.views.store(scene);
+        self.core_systems.store_renderer_settings(scene);
+        if let Err(err) = self.session.autosave(&mut self.core_systems, scene) {
+            tracing::error!("Cannot autosave scene: {}", err);
+        }
+    }
+
+    fn restore_autosave(&mut self, autosave: Autosave) -> Result<()> {
+        let mut scene = self.core_systems.load_scene(&autosave.autosave)?;
+        scene.set_path(&autosave.scene);
+        self.core_systems.activate_scene(&scene)?;
+        self.views = EditorViews::load(&scene);
+        self.editor_scene.replace(scene);
+        self.active_scene.take();
+        // Keep the autosave around until the scene is saved
+        self.session.autosave = Some(autosave);
+        self.session.save();
+        Ok(())
+    }
+
+    fn recovery_ui(&mut self, ctx: &egui::Context) {
+        let Some(autosave) = self.recovery.clone() else { return; };
+        egui::Window::new("Recover autosave")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, [0., 0.])
+            .show(ctx, |ui| {
+                ui.label(format!(
+                    "The editor did not exit cleanly while editing {}.",
+                    autosave.scene.display()
+                ));
+                ui.label("Restore the autosaved scene?");
+                ui.horizontal(|ui| {
+                    if ui.button("Restore").clicked() {
+                        self.recovery.take();
+                        if let Err(err) = self.restore_autosave(autosave.clone()) {
+                            tracing::error!("Cannot restore autosave: {}", err);
+                        }
+                    }
+                    if ui.button("Discard").clicked() {
+                        self.recovery.take();
+                        if let Err(err) = std::fs::remove_file(&autosave.autosave) {
+                            tracing::warn!("Cannot remove autosave: {}", err);
+                        }
+                    }
+                });
+            });
+    }
+
     fn stop_active_scene(&mut self) {
         self.active_scene.take();
     }
 
     fn do_open_scene(&mut self, path: impl AsRef<Path>) -> Result<()> {
         let scene = self.core_systems.load_scene(path)?;
+        self.session.add_recent_scene(scene.path());
+        self.core_systems.activate_scene(&scene)?;
+        self.views = EditorViews::load(&scene);
         self.editor_scene.replace(scene);
         self.active_scene.take();
         Ok(())
@@ -81,6 +296,9 @@ impl Sandbox {
         if let Some(scene) = &self.editor_scene {
             match scene.reload(&mut self.core_systems.persistence) {
                 Ok(scene) => {
+                    if let Err(err) = self.core_systems.activate_scene(&scene) {
+                        tracing::error!("Cannot apply scene renderer settings: {}", err);
+                    }
                     self.active_scene.replace(scene);
                 }
                 Err(err) => {
@@ -94,38 +312,64 @@ impl Sandbox {
 impl Application for Sandbox {
     fn window_features(wb: WindowBuilder) -> WindowBuilder {
         wb.with_inner_size(LogicalSize::new(1600, 900))
+            .with_min_inner_size(LogicalSize::new(800, 450))
     }
 
-    fn new(size: PhysicalSize<f32>, scale_factor: f64) -> Result<Self> {
-        let logical_size = size.to_logical(scale_factor);
-        let size = Vec2::from_array(size.into()).as_uvec2();
-        let mut core_systems = CoreSystems::new(size)?;
-        let editor_scene = std::env::args().nth(1).and_then(|file| {
-            match Scene::load(&mut core_systems.persistence, file) {
-                Ok(scene) => Some(scene),
-                Err(err) => {
-                    tracing::error!("Cannot load scene: {}", err);
-                    None
-                }
-            }
+    fn new(size: WindowSize, config: &LaunchConfig) -> Result<Self> {
+        let mut core_systems =
+            CoreSystems::with_asset_root(size.physical_vec(), &config.asset_root)?;
+        core_systems.render.renderer.set_dirty_tracking(true);
+        let viewport = ViewportTexture::new(size.physical_vec())?;
+        core_systems.render.render_target = Some(ThreadGuard::new(viewport.framebuffer()));
+        core_systems.persistence.register_component::<EditorViews>();
+        let editor_scene = config.scene.as_deref().and_then(|path| {
+            let loaded = if path.is_dir() || path.ends_with(Project::MANIFEST_FILE) {
+                core_systems.load_project(path)
+            } else {
+                core_systems.load_scene(path).and_then(|scene| {
+                    core_systems.activate_scene(&scene)?;
+                    Ok(Some(scene))
+                })
+            };
+            loaded.unwrap_or_else(|err| {
+                tracing::error!("Cannot load {}: {}", path.display(), err);
+                None
+            })
         });
 
-        let ui_system = EditorUiSystem::new();
+        let views = editor_scene
+            .as_ref()
+            .map(EditorViews::load)
+            .unwrap_or_default();
+        let ui_system = EditorUiSystem::new(&mut core_systems.persistence);
+        let mut session = Session::load();
+        let recovery = session
+            .autosave
+            .take()
+            .filter(|autosave| autosave.autosave.exists());
 
         Ok(Self {
             editor_scene,
             active_scene: None,
             editor_cam_controller: PanOrbitCamera::default(),
+            views,
             core_systems,
-            pan_orbit_system: PanOrbitSystem::new(logical_size),
+            pan_orbit_system: PanOrbitSystem::new(size.logical()),
             ui_system,
+            viewport: ThreadGuard::new(viewport),
+            session,
+            scene_browser: SceneBrowser::default(),
+            missing_assets: MissingAssetsWindow::default(),
+            autosave_timer: Duration::ZERO,
+            recovery,
+            panorama_path: None,
+            pending_thumbnail: None,
         })
     }
 
-    fn resize(&mut self, size: PhysicalSize<u32>, scale_factor: f64) -> Result<()> {
-        self.core_systems.resize(size)?;
-        self.pan_orbit_system
-            .set_window_size(size.to_logical(scale_factor));
+    fn resize(&mut self, size: WindowSize) -> Result<()> {
+        // The renderer follows the size of the Viewport tab instead, see `Self::resize_viewport`
+        self.pan_orbit_system.set_window_size(size.logical());
         Ok(())
     }
 
@@ -149,10 +393,28 @@ impl Application for Sandbox {
         Ok(())
     }
 
-    fn render(&mut self, ctx: RenderContext) -> Result<()> {
+    fn on_focus_changed(&mut self, focused: bool) {
+        // Autosave on the next frame when switching away from the editor
+        if !focused {
+            self.autosave_timer = AUTOSAVE_INTERVAL;
+        }
+    }
+
+    fn accepts_dropped_file(&self, path: &Path) -> bool {
+        window::has_extension(path, EnvironmentMap::EXTENSIONS)
+    }
+
+    fn render(&mut self, mut ctx: RenderContext) -> Result<()> {
+        self.resize_viewport()?;
+        Framebuffer::clear_color(Vec3::splat(0.1).extend(1.).to_array());
+        Framebuffer::backbuffer().do_clear(ClearBuffer::COLOR);
         self.core_systems.begin_frame();
         if let Some(scene) = &mut self.active_scene {
             self.core_systems.manual_camera_update = false;
+            self.core_systems
+                .viewport_camera_mut()
+                .projection
+                .orthographic = None;
             scene.on_frame();
             scene.with_world_mut(|world| {
                 self.pan_orbit_system
@@ -168,23 +430,46 @@ impl Application for Sandbox {
             let win_size = win_size.width.min(win_size.height);
             self.pan_orbit_system.frame_one(
                 self.ui_system.last_state.mouse_delta / win_size,
-                self.ui_system.last_state.mouse_scroll * ctx.dt.as_secs_f32() * 20.,
+                self.ui_system.last_state.mouse_scroll * ctx.dt.as_secs_f32() * 20.
+                    + PanOrbitSystem::pinch_scroll(self.ui_system.last_state.touch_zoom),
                 self.ui_system.last_state.mouse_buttons,
                 &mut self.editor_cam_controller,
                 &mut self.core_systems.viewport_camera_mut().transform,
             );
-        } else {
-            Framebuffer::clear_color(Vec3::splat(0.1).extend(1.).to_array());
-            Framebuffer::backbuffer().do_clear(ClearBuffer::COLOR);
+            let projection = &mut self.core_systems.viewport_camera_mut().projection;
+            projection.orthographic = self
+                .views
+                .orthographic_height(&self.editor_cam_controller, projection.fovy);
         }
+        // Scenes follow the game time, paused and stepped from the toolbar
         self.core_systems.end_frame(
             self.active_scene.as_mut().or(self.editor_scene.as_mut()),
-            ctx.dt,
+            ctx.game.dt,
         )?;
+        self.tick_autosave(ctx.dt);
+        if let Some(cubemap) = self.core_systems.render.take_cubemap() {
+            if let Some(path) = self.panorama_path.take() {
+                if let Err(err) = save_panorama(&cubemap, &path) {
+                    tracing::error!("Cannot save 360° screenshot: {}", err);
+                }
+            }
+        }
+        self.save_pending_thumbnail();
+        ctx.set_title(&self.window_title());
         Ok(())
     }
 
     fn ui(&mut self, ctx: UiContext) {
+        if let Some(ui_scale) = self.session.ui_scale {
+            *ctx.ui_scale = ui_scale;
+        }
+        if !ctx.egui.wants_keyboard_input() && ctx.egui.input().key_pressed(egui::Key::F12) {
+            self.export_aovs();
+        }
+        if self.editor_scene.is_some() && self.active_scene.is_none() {
+            self.views
+                .handle_shortcuts(ctx.egui, &mut self.editor_cam_controller);
+        }
         egui::TopBottomPanel::top("menu").show(ctx.egui, |ui| {
             ui.horizontal(|ui| {
                 egui::widgets::global_dark_light_mode_switch(ui);
@@ -195,7 +480,30 @@ impl Application for Sandbox {
                         ui.close_menu();
                     }
                     if ui.small_button("Open...").clicked() {
-                        self.open_scene().unwrap();
+                        self.scene_browser.show(self.core_systems.project.as_ref());
+                        ui.close_menu();
+                    }
+                    ui.menu_button("Recent", |ui| {
+                        if self.session.recent_scenes.is_empty() {
+                            ui.weak("No recent scenes");
+                        }
+                        let mut opened = None;
+                        for path in &self.session.recent_scenes {
+                            if ui.small_button(path.display().to_string()).clicked() {
+                                opened = Some(path.clone());
+                                ui.close_menu();
+                            }
+                        }
+                        if let Some(path) = opened {
+                            if let Err(err) = self.do_open_scene(path) {
+                                tracing::error!("Cannot open scene: {}", err);
+                            }
+                        }
+                    });
+                    if ui.small_button("Open project...").clicked() {
+                        if let Err(err) = self.open_project() {
+                            tracing::error!("Cannot open project: {}", err);
+                        }
                         ui.close_menu();
                     }
                     if ui.small_button("Import GLTF").clicked() {
@@ -206,6 +514,7 @@ impl Application for Sandbox {
                             match smol::block_on(load_gltf_scene(file)) {
                                 Ok(scene) => {
                                     self.editor_scene.replace(scene);
+                                    self.views = EditorViews::default();
                                 }
                                 Err(err) => {
                                     tracing::error!("Cannot import scene: {}", err);
@@ -231,6 +540,46 @@ impl Application for Sandbox {
                     } else {
                         ui.weak("Save as ...");
                     }
+                    if ui
+                        .small_button("Export AOVs")
+                        .on_hover_text("Save the lit frame and its G-Buffer channels (F12)")
+                        .clicked()
+                    {
+                        self.export_aovs();
+                        ui.close_menu();
+                    }
+                    if ui.small_button("Save 360° screenshot...").clicked() {
+                        let opt_file = FileDialog::new()
+                            .add_filter("PNG image", &["png"])
+                            .set_directory(self.dialog_directory())
+                            .save_file();
+                        if let Some(file) = opt_file {
+                            let center = self.core_systems.viewport_camera().transform.position;
+                            self.core_systems
+                                .render
+                                .request_cubemap(center, PANORAMA_RESOLUTION);
+                            self.panorama_path = Some(file);
+                        }
+                        ui.close_menu();
+                    }
+                    ui.separator();
+                    if ui.small_button("Missing assets...").clicked() {
+                        self.missing_assets.show();
+                        ui.close_menu();
+                    }
+                });
+                ui.menu_button("View", |ui| {
+                    ui.menu_button("UI scale", |ui| {
+                        for ui_scale in UI_SCALES {
+                            let label = format!("{:.0}%", ui_scale * 100.);
+                            if ui.radio(*ctx.ui_scale == ui_scale, label).clicked() {
+                                *ctx.ui_scale = ui_scale;
+                                self.session.ui_scale = Some(ui_scale);
+                                self.session.save();
+                                ui.close_menu();
+                            }
+                        }
+                    });
                 });
                 if let Some(scene) = &mut self.editor_scene {
                     ui.menu_button("Entity", |ui| {
@@ -289,10 +638,9 @@ impl Application for Sandbox {
                             if let Some(file) = opt_file {
                                 let nested =
                                     match file.extension().unwrap().to_string_lossy().as_ref() {
-                                        "scene" | "toml" => Scene::load(
-                                            &mut self.core_systems.persistence,
-                                            file.as_path(),
-                                        ),
+                                        "scene" | "toml" => {
+                                            self.core_systems.load_scene(file.as_path())
+                                        }
                                         "gltf" | "glb" => {
                                             smol::block_on(load_gltf_scene(file.as_path()))
                                         }
@@ -310,6 +658,9 @@ impl Application for Sandbox {
                 } else {
                     ui.weak("Entity");
                 }
+                ui.menu_button("View", |ui| {
+                    self.views.ui(ui, &mut self.editor_cam_controller);
+                });
                 ui.separator();
                 ui.radio_value(
                     &mut self.ui_system.gizmo_mode,
@@ -318,6 +669,9 @@ impl Application for Sandbox {
                 );
                 ui.radio_value(&mut self.ui_system.gizmo_mode, GizmoMode::Rotate, "Rotate");
                 ui.radio_value(&mut self.ui_system.gizmo_mode, GizmoMode::Scale, "Scale");
+                ui.checkbox(&mut self.ui_system.snapping.enabled, "Snap")
+                    .on_hover_text("Hold Ctrl to snap temporarily");
+                ui.menu_button("Snapping", |ui| self.ui_system.snapping.ui(ui));
                 ui.separator();
                 if self.active_scene.is_some() {
                     if ui.small_button("Stop scene").clicked() {
@@ -326,6 +680,7 @@ impl Application for Sandbox {
                 } else if ui.small_button("Play").clicked() {
                     self.start_active_scene();
                 }
+                time_controls(ui, ctx.time);
             });
         });
         // egui::Window::new("Environment")
@@ -333,9 +688,83 @@ impl Application for Sandbox {
         //         let env = self.render_system.environment_mut();
         //         env.params.ui(ui);
         //     });
-        self.ui_system
-            .on_ui(ctx.egui, self.editor_scene.as_ref(), &mut self.core_systems);
+        self.recovery_ui(ctx.egui);
+        if let Some(scene) = &mut self.editor_scene {
+            self.missing_assets
+                .ui(ctx.egui, &mut self.core_systems.persistence, scene);
+        }
+        match self.scene_browser.ui(ctx.egui, &self.session.recent_scenes) {
+            Some(SceneChoice::Scene(path)) => {
+                if let Err(err) = self.do_open_scene(path) {
+                    tracing::error!("Cannot open scene: {}", err);
+                }
+            }
+            Some(SceneChoice::Browse) => {
+                if let Err(err) = self.open_scene() {
+                    tracing::error!("Cannot open scene: {}", err);
+                }
+            }
+            None => {}
+        }
+        self.ui_system.on_ui(
+            ctx.egui,
+            self.editor_scene.as_ref(),
+            &self.viewport,
+            &mut self.core_systems,
+        );
+    }
+
+    fn exit(&mut self) {
+        self.session.clear_autosave();
+        if let Some(recovery) = self.recovery.take() {
+            // Not dealt with yet, offer it again next time
+            self.session.autosave = Some(recovery);
+            self.session.save();
+        }
+    }
+}
+
+/// Pause, single frame step and speed of the game time.
+fn time_controls(ui: &mut egui::Ui, time: &TimeControl) {
+    let paused = time.is_paused();
+    if ui
+        .small_button(if paused { "Resume" } else { "Pause" })
+        .clicked()
+    {
+        time.toggle_pause();
+    }
+    if ui
+        .add_enabled(paused, egui::Button::new("Step").small())
+        .on_hover_text("Advance a single frame")
+        .clicked()
+    {
+        time.step();
     }
+    let mut time_scale = time.time_scale();
+    egui::ComboBox::from_id_source("time_scale")
+        .width(60.)
+        .selected_text(format!("{}x", time_scale))
+        .show_ui(ui, |ui| {
+            for scale in TIME_SCALES {
+                ui.selectable_value(&mut time_scale, scale, format!("{}x", scale));
+            }
+        });
+    if time_scale != time.time_scale() {
+        time.set_time_scale(time_scale);
+    }
+}
+
+/// Save the cubemap as an equirectangular PNG image, tone mapped from the linear lit colors.
+fn save_panorama(cubemap: &Cubemap, path: &Path) -> Result<()> {
+    let (width, height) = (4 * cubemap.resolution, 2 * cubemap.resolution);
+    let pixels = cubemap.to_equirectangular(width, height);
+    let image = image::RgbImage::from_fn(width, height, |x, y| {
+        let color = pixels[(y * width + x) as usize];
+        let tone_mapped = color.map(|c| c.max(0.) / (1. + c.max(0.)));
+        image::Rgb(tone_mapped.map(|c| (linear_to_srgb(c) * 255.).round() as u8))
+    });
+    image.save(path)?;
+    Ok(())
 }
 
 fn main() -> Result<()> {
@@ -0,0 +1,108 @@
+//! Report of the assets which could not be loaded with the scenes, replaced by placeholders, and
+//! relinking of their references to other asset files.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use egui::{Context, Grid, TextEdit};
+use rfd::FileDialog;
+
+use rose::prelude::*;
+
+#[derive(Debug, Default)]
+pub struct MissingAssetsWindow {
+    open: bool,
+    /// Number of missing assets last shown, to open the window when more go missing.
+    seen: usize,
+    /// Replacement file typed for each missing asset, relative to the asset root.
+    replacements: HashMap<(&'static str, String), String>,
+}
+
+impl MissingAssetsWindow {
+    pub fn show(&mut self) {
+        self.open = true;
+    }
+
+    pub fn ui(&mut self, ctx: &Context, persistence: &mut PersistenceSystem, scene: &mut Scene) {
+        let missing = persistence.missing_assets().to_vec();
+        if missing.len() > self.seen {
+            self.open = true;
+        }
+        self.seen = missing.len();
+        let mut open = self.open;
+        let mut relink = None;
+        egui::Window::new("Missing assets")
+            .open(&mut open)
+            .default_width(560.)
+            .show(ctx, |ui| {
+                if missing.is_empty() {
+                    ui.weak("All assets were found");
+                    return;
+                }
+                ui.label("Replaced by placeholders; saved scenes keep referencing them.");
+                Grid::new("missing-assets")
+                    .num_columns(4)
+                    .striped(true)
+                    .show(ui, |ui| {
+                        for asset in &missing {
+                            let kind = asset.kind.rsplit("::").next().unwrap_or(asset.kind);
+                            ui.label(kind);
+                            ui.monospace(&asset.id).on_hover_text(&asset.error);
+                            let replacement = self
+                                .replacements
+                                .entry((asset.kind, asset.id.clone()))
+                                .or_default();
+                            ui.add(
+                                TextEdit::singleline(replacement).hint_text("Path of the asset"),
+                            );
+                            ui.horizontal(|ui| {
+                                if ui.button("Browse...").clicked() {
+                                    if let Some(path) = pick_asset(scene.asset_root()) {
+                                        *replacement = path.display().to_string();
+                                    }
+                                }
+                                let enabled = !replacement.is_empty();
+                                if ui
+                                    .add_enabled(enabled, egui::Button::new("Relink"))
+                                    .clicked()
+                                {
+                                    relink =
+                                        Some((asset.kind, asset.id.clone(), replacement.clone()));
+                                }
+                            });
+                            ui.end_row();
+                        }
+                    });
+            });
+        self.open = open;
+
+        let Some((kind, old_id, path)) = relink else {
+            return;
+        };
+        let new_id = assets::asset_id(&path);
+        let cache = scene.asset_cache().as_any_cache();
+        let relinked = scene
+            .with_world_mut(|world| persistence.relink_asset(cache, world, kind, &old_id, &new_id));
+        match relinked {
+            Ok(entities) => {
+                tracing::info!("Relinked {} to {} on {} entities", old_id, new_id, entities);
+                self.replacements.remove(&(kind, old_id));
+                self.seen = persistence.missing_assets().len();
+            }
+            Err(err) => tracing::error!("Cannot relink {} to {}: {}", old_id, new_id, err),
+        }
+    }
+}
+
+/// Pick a file of the asset root, returning its path relative to the root.
+fn pick_asset(asset_root: &Path) -> Option<PathBuf> {
+    let file = FileDialog::new().set_directory(asset_root).pick_file()?;
+    let root = asset_root.canonicalize().ok()?;
+    match file.canonicalize().ok()?.strip_prefix(&root) {
+        Ok(relative) => Some(relative.to_path_buf()),
+        Err(_) => {
+            tracing::error!("{} is outside of the asset root", file.display());
+            None
+        }
+    }
+}
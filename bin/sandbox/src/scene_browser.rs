@@ -0,0 +1,182 @@
+//! Window to open scenes from, showing the recent and project scenes with their thumbnails.
+//!
+//! Thumbnails are small screenshots of the viewport, saved as PNG files next to the scenes.
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use egui::{ColorImage, Context, TextureHandle, TextureOptions, Ui};
+use image::{imageops, Rgb, RgbImage};
+
+use rose::prelude::*;
+
+/// Largest side of the thumbnails, in pixels.
+const THUMBNAIL_SIZE: u32 = 256;
+/// Size of the thumbnails in the browser, in points.
+const CARD_SIZE: egui::Vec2 = egui::vec2(160., 90.);
+
+/// `scene.thumbnail.png` for `scene.scene`.
+pub fn thumbnail_path(scene_path: &Path) -> PathBuf {
+    let stem = scene_path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy())
+        .unwrap_or_default();
+    scene_path.with_file_name(format!("{}.thumbnail.png", stem))
+}
+
+/// Save a downscaled copy of a frame of the viewport, read back from its texture, as the thumbnail
+/// of the scene.
+pub fn save_thumbnail(pixels: &[[f32; 3]], size: UVec2, scene_path: &Path) -> Result<()> {
+    let image = RgbImage::from_fn(size.x, size.y, |x, y| {
+        // OpenGL textures start at the bottom; colors are already gamma-corrected
+        let color = pixels[((size.y - 1 - y) * size.x + x) as usize];
+        Rgb(color.map(|c| (c.clamp(0., 1.) * 255.).round() as u8))
+    });
+    let scale = (THUMBNAIL_SIZE as f32 / size.max_element() as f32).min(1.);
+    let width = ((size.x as f32 * scale) as u32).max(1);
+    let height = ((size.y as f32 * scale) as u32).max(1);
+    imageops::thumbnail(&image, width, height).save(thumbnail_path(scene_path))?;
+    Ok(())
+}
+
+/// Scene picked in the browser.
+#[derive(Debug, Clone)]
+pub enum SceneChoice {
+    Scene(PathBuf),
+    /// Pick the scene with a file dialog instead.
+    Browse,
+}
+
+#[derive(Default)]
+pub struct SceneBrowser {
+    open: bool,
+    /// Scenes found in the project when the browser was opened.
+    project_scenes: Vec<PathBuf>,
+    /// Thumbnails loaded as UI textures, `None` for scenes without one.
+    thumbnails: HashMap<PathBuf, Option<TextureHandle>>,
+}
+
+impl SceneBrowser {
+    /// Open the browser, listing the scenes of the project if there is one.
+    pub fn show(&mut self, project: Option<&Project>) {
+        self.open = true;
+        self.project_scenes.clear();
+        if let Some(project) = project {
+            find_scenes(project.root(), &mut self.project_scenes);
+            self.project_scenes.sort();
+        }
+        // Thumbnails may have changed since the browser was last open
+        self.thumbnails.clear();
+    }
+
+    /// Forget the loaded thumbnail of the scene, after saving it again.
+    pub fn invalidate(&mut self, scene_path: &Path) {
+        self.thumbnails.remove(&canonical(scene_path));
+    }
+
+    pub fn ui(&mut self, ctx: &Context, recent_scenes: &[PathBuf]) -> Option<SceneChoice> {
+        let mut open = self.open;
+        let mut choice = None;
+        egui::Window::new("Open scene")
+            .open(&mut open)
+            .collapsible(false)
+            .default_size([720., 480.])
+            .show(ctx, |ui| {
+                if ui.button("Browse...").clicked() {
+                    choice = Some(SceneChoice::Browse);
+                }
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    ui.heading("Recent");
+                    if recent_scenes.is_empty() {
+                        ui.weak("No recent scenes");
+                    }
+                    choice = choice.or(self.scene_grid(ui, recent_scenes));
+                    if !self.project_scenes.is_empty() {
+                        ui.separator();
+                        ui.heading("Project");
+                        let scenes = std::mem::take(&mut self.project_scenes);
+                        choice = choice.or(self.scene_grid(ui, &scenes));
+                        self.project_scenes = scenes;
+                    }
+                });
+            });
+        self.open = open && choice.is_none();
+        choice
+    }
+
+    /// Cards of the scenes, returning the clicked one.
+    fn scene_grid(&mut self, ui: &mut Ui, scenes: &[PathBuf]) -> Option<SceneChoice> {
+        let mut choice = None;
+        ui.horizontal_wrapped(|ui| {
+            for path in scenes {
+                let name = path
+                    .file_stem()
+                    .map_or_else(|| "Untitled".into(), |stem| stem.to_string_lossy());
+                ui.vertical(|ui| {
+                    ui.set_width(CARD_SIZE.x);
+                    let response = match self.thumbnail(ui.ctx(), path) {
+                        Some(texture) => {
+                            let size =
+                                texture.size_vec2() * (CARD_SIZE / texture.size_vec2()).min_elem();
+                            ui.add_sized(CARD_SIZE, egui::ImageButton::new(texture.id(), size))
+                        }
+                        None => ui.add_sized(CARD_SIZE, egui::Button::new("No preview")),
+                    };
+                    if response.on_hover_text(path.display().to_string()).clicked() {
+                        choice = Some(SceneChoice::Scene(path.clone()));
+                    }
+                    ui.label(name);
+                });
+            }
+        });
+        choice
+    }
+
+    fn thumbnail(&mut self, ctx: &Context, scene_path: &Path) -> Option<&TextureHandle> {
+        let path = canonical(scene_path);
+        self.thumbnails
+            .entry(path)
+            .or_insert_with_key(|path| {
+                let thumbnail = thumbnail_path(path);
+                if !thumbnail.exists() {
+                    return None;
+                }
+                let image = match image::open(&thumbnail) {
+                    Ok(image) => image.to_rgb8(),
+                    Err(err) => {
+                        tracing::warn!("Cannot load thumbnail {}: {}", thumbnail.display(), err);
+                        return None;
+                    }
+                };
+                let size = [image.width() as _, image.height() as _];
+                let image = ColorImage::from_rgb(size, image.as_raw());
+                Some(ctx.load_texture(
+                    thumbnail.display().to_string(),
+                    image,
+                    TextureOptions::LINEAR,
+                ))
+            })
+            .as_ref()
+    }
+}
+
+fn canonical(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// Add the scene files of the directory and its subdirectories to `scenes`.
+fn find_scenes(dir: &Path, scenes: &mut Vec<PathBuf>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            find_scenes(&path, scenes);
+        } else if path.extension().is_some_and(|ext| ext == "scene") {
+            scenes.push(path);
+        }
+    }
+}
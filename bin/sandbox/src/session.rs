@@ -0,0 +1,113 @@
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+
+use rose::prelude::*;
+
+const MAX_RECENT_SCENES: usize = 10;
+pub const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(120);
+
+/// Autosave written for a scene.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Autosave {
+    pub scene: PathBuf,
+    pub autosave: PathBuf,
+}
+
+/// Editor state kept between sessions, in the user's configuration directory.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Session {
+    /// Recently opened scenes, most recent first.
+    pub recent_scenes: Vec<PathBuf>,
+    /// Autosave of the current session; left behind when the editor does not exit cleanly.
+    pub autosave: Option<Autosave>,
+    /// Scale of the editor UI, overriding the one of the launch configuration.
+    pub ui_scale: Option<f32>,
+}
+
+impl Session {
+    fn path() -> PathBuf {
+        std::env::var_os("XDG_CONFIG_HOME")
+            .or_else(|| std::env::var_os("APPDATA"))
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+            .unwrap_or_else(|| PathBuf::from("."))
+            .join("rose-sandbox")
+            .join("session.yaml")
+    }
+
+    pub fn load() -> Self {
+        let path = Self::path();
+        if !path.exists() {
+            return Self::default();
+        }
+        Self::read(&path).unwrap_or_else(|err| {
+            tracing::warn!("Cannot read session from {}: {}", path.display(), err);
+            Self::default()
+        })
+    }
+
+    pub fn save(&self) {
+        let path = Self::path();
+        if let Err(err) = self.write(&path) {
+            tracing::warn!("Cannot write session to {}: {}", path.display(), err);
+        }
+    }
+
+    fn read(path: &Path) -> Result<Self> {
+        Ok(serde_yaml::from_reader(BufReader::new(File::open(path)?))?)
+    }
+
+    fn write(&self, path: &Path) -> Result<()> {
+        std::fs::create_dir_all(path.parent().unwrap())?;
+        serde_yaml::to_writer(BufWriter::new(File::create(path)?), self)?;
+        Ok(())
+    }
+
+    pub fn add_recent_scene(&mut self, path: impl AsRef<Path>) {
+        let path = path.as_ref();
+        let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        self.recent_scenes.retain(|p| *p != path);
+        self.recent_scenes.insert(0, path);
+        self.recent_scenes.truncate(MAX_RECENT_SCENES);
+        self.save();
+    }
+
+    /// Write an autosave of the scene next to it, and remember it in case the editor crashes.
+    pub fn autosave(&mut self, core_systems: &mut CoreSystems, scene: &Scene) -> Result<()> {
+        let autosave = autosave_path(scene.path());
+        core_systems.save_scene_to(scene, &autosave)?;
+        tracing::info!("Autosaved scene to {}", autosave.display());
+        self.autosave = Some(Autosave {
+            scene: scene.path().to_path_buf(),
+            autosave,
+        });
+        self.save();
+        Ok(())
+    }
+
+    /// Remove the autosave, once the scene has been saved or the editor exits cleanly.
+    pub fn clear_autosave(&mut self) {
+        if let Some(autosave) = self.autosave.take() {
+            if let Err(err) = std::fs::remove_file(&autosave.autosave) {
+                tracing::warn!("Cannot remove autosave: {}", err);
+            }
+            self.save();
+        }
+    }
+}
+
+/// `scene.autosave.yaml` for `scene.scene`.
+pub fn autosave_path(scene_path: &Path) -> PathBuf {
+    let stem = scene_path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy())
+        .unwrap_or_default();
+    scene_path.with_file_name(format!("{}.autosave.yaml", stem))
+}
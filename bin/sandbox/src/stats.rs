@@ -0,0 +1,222 @@
+//! Statistics of the open scene, shown in the Scene statistics tab.
+
+use std::collections::HashMap;
+
+use egui::{Grid, Ui};
+
+use rose::{
+    ecs::{
+        assets::{Image, Material},
+        components::{Light, LightKind},
+    },
+    prelude::*,
+};
+
+const LIGHT_KINDS: [LightKind; 5] = [
+    LightKind::Ambient,
+    LightKind::Point,
+    LightKind::Directional,
+    LightKind::Rect,
+    LightKind::Disk,
+];
+
+/// Entities sharing the same set of components.
+#[derive(Debug, Clone)]
+pub struct ArchetypeStats {
+    pub entities: u32,
+    /// Names of the components registered in the editor, sorted.
+    pub components: Vec<&'static str>,
+    /// Number of components without a registered name.
+    pub unnamed: usize,
+}
+
+/// Asset referenced by entities of the scene.
+#[derive(Debug, Clone)]
+pub struct AssetReferences {
+    pub id: SharedString,
+    pub kind: &'static str,
+    pub entities: usize,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SceneStats {
+    pub entities: u32,
+    /// Non-empty archetypes, largest first.
+    pub archetypes: Vec<ArchetypeStats>,
+    pub meshes: usize,
+    pub vertices: usize,
+    pub indices: usize,
+    /// Size of the vertex and index buffers of the meshes.
+    pub mesh_bytes: usize,
+    pub materials: usize,
+    pub textures: usize,
+    /// Estimated size of the material textures in video memory.
+    pub texture_bytes: usize,
+    pub lights: [usize; LIGHT_KINDS.len()],
+    /// Assets by number of entities referencing them, most used first.
+    pub references: Vec<AssetReferences>,
+}
+
+impl SceneStats {
+    pub fn collect(world: &World, components: &UiSystem) -> Self {
+        let mut stats = Self::default();
+        for archetype in world.archetypes().filter(|archetype| !archetype.is_empty()) {
+            let mut names = vec![];
+            let mut unnamed = 0;
+            for type_id in archetype.component_types() {
+                match components.component_name(type_id) {
+                    Some(name) => names.push(name),
+                    None => unnamed += 1,
+                }
+            }
+            names.sort_unstable();
+            stats.entities += archetype.len();
+            stats.archetypes.push(ArchetypeStats {
+                entities: archetype.len(),
+                components: names,
+                unnamed,
+            });
+        }
+        stats.archetypes.sort_by(|a, b| b.entities.cmp(&a.entities));
+
+        let mut references = HashMap::<(SharedString, &'static str), usize>::new();
+        for (_, handle) in world.query::<&Handle<MeshAsset>>().iter() {
+            let count = references.entry((handle.id().clone(), "Mesh")).or_default();
+            if *count == 0 {
+                let mesh = handle.read();
+                stats.meshes += 1;
+                stats.vertices += mesh.vertices.len();
+                stats.indices += mesh.indices.len();
+                stats.mesh_bytes += std::mem::size_of_val(mesh.vertices.as_slice())
+                    + std::mem::size_of_val(mesh.indices.as_slice());
+            }
+            *count += 1;
+        }
+        for (_, handle) in world.query::<&Handle<Material>>().iter() {
+            let count = references
+                .entry((handle.id().clone(), "Material"))
+                .or_default();
+            if *count == 0 {
+                // Textures are uploaded for each material, even when shared between materials
+                let material = handle.read();
+                stats.materials += 1;
+                let textures = [
+                    (&material.color, 3),
+                    (&material.normal, 3),
+                    (&material.rough_metal, 2),
+                    (&material.emission, 3),
+                ];
+                for (image, channels) in textures {
+                    if let Some(image) = image {
+                        stats.textures += 1;
+                        stats.texture_bytes += texture_bytes(image, channels);
+                    }
+                }
+            }
+            *count += 1;
+        }
+        stats.references = references
+            .into_iter()
+            .map(|((id, kind), entities)| AssetReferences { id, kind, entities })
+            .collect();
+        stats.references.sort_by(|a, b| {
+            b.entities
+                .cmp(&a.entities)
+                .then_with(|| a.id.as_str().cmp(b.id.as_str()))
+        });
+
+        for (_, light) in world.query::<&Light>().iter() {
+            let index = LIGHT_KINDS.iter().position(|kind| *kind == light.kind);
+            stats.lights[index.unwrap()] += 1;
+        }
+        stats
+    }
+
+    pub fn ui(&self, ui: &mut Ui) {
+        ui.collapsing("Entities", |ui| {
+            ui.label(format!(
+                "{} entities in {} archetypes",
+                self.entities,
+                self.archetypes.len()
+            ));
+            Grid::new("scene-stats-archetypes")
+                .num_columns(2)
+                .striped(true)
+                .show(ui, |ui| {
+                    for archetype in &self.archetypes {
+                        ui.monospace(format!("{:5}", archetype.entities));
+                        let mut components = archetype.components.join(", ");
+                        if archetype.unnamed > 0 {
+                            if !components.is_empty() {
+                                components.push_str(", ");
+                            }
+                            components.push_str(&format!("{} other", archetype.unnamed));
+                        }
+                        ui.label(components);
+                        ui.end_row();
+                    }
+                });
+        });
+        ui.collapsing("Meshes", |ui| {
+            ui.label(format!(
+                "{} meshes | {} vertices | {} triangles",
+                self.meshes,
+                self.vertices,
+                self.indices / 3
+            ));
+            ui.label(format!("Buffers: {}", format_bytes(self.mesh_bytes)));
+        });
+        ui.collapsing("Textures", |ui| {
+            ui.label(format!(
+                "{} textures in {} materials",
+                self.textures, self.materials
+            ));
+            ui.label(format!(
+                "Estimated video memory: {}",
+                format_bytes(self.texture_bytes)
+            ));
+        });
+        ui.collapsing("Lights", |ui| {
+            Grid::new("scene-stats-lights")
+                .num_columns(2)
+                .show(ui, |ui| {
+                    for (kind, count) in LIGHT_KINDS.iter().zip(self.lights) {
+                        ui.label(format!("{:?}", kind));
+                        ui.monospace(count.to_string());
+                        ui.end_row();
+                    }
+                });
+        });
+        ui.collapsing("Asset references", |ui| {
+            Grid::new("scene-stats-references")
+                .num_columns(3)
+                .striped(true)
+                .show(ui, |ui| {
+                    for asset in &self.references {
+                        ui.monospace(format!("{:5}", asset.entities));
+                        ui.label(asset.kind);
+                        ui.monospace(asset.id.as_str());
+                        ui.end_row();
+                    }
+                });
+        });
+    }
+}
+
+/// Estimated size of the texture the renderer creates from the image: float texels, and a third
+/// more for the mipmaps.
+fn texture_bytes(image: &Image, channels: usize) -> usize {
+    let texels = image.width() as usize * image.height() as usize;
+    texels * channels * std::mem::size_of::<f32>() * 4 / 3
+}
+
+fn format_bytes(bytes: usize) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024. && unit < UNITS.len() - 1 {
+        size /= 1024.;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
@@ -1,4 +1,5 @@
 use std::{
+    any::TypeId,
     cell::RefCell,
     collections::HashSet,
     marker::PhantomData,
@@ -8,7 +9,7 @@ use std::{
 
 use color_eyre::owo_colors::OwoColorize;
 use egui::{
-    Align, Color32, Context, DragValue, Grid, Layout, PointerButton, RichText, Sense, TextEdit, Ui,
+    Align, Color32, Context, DragValue, Grid, Layout, PointerButton, Sense, TextEdit, Ui,
     WidgetText,
 };
 use egui_dock::{NodeIndex, TabViewer, Tree};
@@ -17,8 +18,14 @@ use egui_gizmo::{Gizmo, GizmoMode};
 use rose::{
     ecs::{assets::Material, components::Light},
     prelude::*,
+    ui::viewport::ViewportTexture,
 };
 
+use crate::console::{ConsolePanel, ShaderErrorToast};
+use crate::gizmos::{draw_entity_gizmos, edit_spline_handles, pick_mesh};
+use crate::hierarchy::HierarchyPanel;
+use crate::stats::SceneStats;
+
 #[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
 pub enum Tabs {
     SceneHierarchy,
@@ -29,10 +36,12 @@ pub enum Tabs {
     Postprocessing,
     CameraDebug,
     RendererDebug,
+    Console,
+    SceneStats,
 }
 
 impl Tabs {
-    pub const ALL: [Tabs; 8] = [
+    pub const ALL: [Tabs; 10] = [
         Self::SceneHierarchy,
         Self::Inspector,
         Self::Viewport,
@@ -41,6 +50,8 @@ impl Tabs {
         Self::Environment,
         Self::CameraDebug,
         Self::RendererDebug,
+        Self::Console,
+        Self::SceneStats,
     ];
 }
 
@@ -55,6 +66,8 @@ impl ToString for Tabs {
             Self::Postprocessing => "Post-processing".to_string(),
             Self::CameraDebug => "Camera debug".to_string(),
             Self::RendererDebug => "Renderer debug".to_string(),
+            Self::Console => "Console".to_string(),
+            Self::SceneStats => "Scene statistics".to_string(),
         }
     }
 }
@@ -62,53 +75,87 @@ impl ToString for Tabs {
 pub struct EditorUiSystem {
     pub last_state: UiState,
     pub gizmo_mode: GizmoMode,
+    pub snapping: GizmoSnapping,
+    pub console: ConsolePanel,
+    pub hierarchy: HierarchyPanel,
+    shader_toast: ShaderErrorToast,
     core_system: UiSystem,
     tabs: Arc<Mutex<Tree<Tabs>>>,
     selected_entity: Option<Entity>,
+    /// Entity whose mesh is under the cursor in the viewport.
+    hovered_entity: Option<Entity>,
     envmap_path: Option<PathBuf>,
 }
 
 impl EditorUiSystem {
-    pub fn new() -> Self {
+    /// Create the editor UI, registering the inspectable components in `persistence` as well.
+    pub fn new(persistence: &mut PersistenceSystem) -> Self {
         let mut tabs = Tree::new(vec![Tabs::Viewport]);
         let [main, left] = tabs.split_left(NodeIndex::root(), 0.2, vec![Tabs::SceneHierarchy]);
         tabs.split_right(main, 0.8, vec![Tabs::Assets]);
         tabs.split_below(left, 0.5, vec![Tabs::Inspector]);
         let mut core_system = UiSystem::new();
         core_system
-            .register_component::<Transform>()
-            .register_component::<Active>()
-            .register_component::<Inactive>()
-            .register_component::<CameraParams>()
-            .register_component::<PanOrbitCamera>()
+            .register_inspectable::<Transform>(persistence)
+            .register_inspectable::<Active>(persistence)
+            .register_inspectable::<Inactive>(persistence)
+            .register_inspectable::<Static>(persistence)
+            .register_inspectable::<Tags>(persistence)
+            .register_inspectable::<CameraParams>(persistence)
+            .register_inspectable::<PanOrbitCamera>(persistence)
+            .register_inspectable::<MorphWeights>(persistence)
+            .register_inspectable::<Light>(persistence)
+            .register_inspectable::<Fog>(persistence)
+            .register_inspectable::<ClipPlane>(persistence)
+            .register_inspectable::<Water>(persistence)
+            .register_inspectable::<Refractive>(persistence)
+            .register_inspectable::<Sprite>(persistence)
+            .register_inspectable::<Polyline>(persistence)
+            .register_inspectable::<ReflectionProbe>(persistence)
+            .register_inspectable::<IrradianceVolume>(persistence)
+            .register_inspectable::<AnimatedMaterial>(persistence)
+            .register_inspectable::<TransformAnimation>(persistence)
+            .register_inspectable::<SplineFollow>(persistence)
+            .register_inspectable::<LightAnimation>(persistence)
+            .register_inspectable::<Navigation>(persistence)
             .register_component::<Handle<'static, MeshAsset>>()
             .register_component::<Handle<'static, Material>>()
-            .register_component::<Light>()
+            .register_component::<Handle<'static, Image>>()
+            .register_component::<Handle<'static, CustomMaterial<SplatMaterial>>>()
             .register_component::<SceneId>()
-            .register_component::<Scene>()
-            .register_spawn::<Transform>()
-            .register_spawn::<Active>()
-            .register_spawn::<Inactive>()
-            .register_spawn::<CameraParams>()
-            .register_spawn::<PanOrbitCamera>()
-            .register_spawn::<Light>();
+            .register_component::<Scene>();
         Self {
             last_state: UiState::default(),
             gizmo_mode: GizmoMode::Translate,
+            snapping: GizmoSnapping::default(),
+            console: ConsolePanel::default(),
+            hierarchy: HierarchyPanel::default(),
+            shader_toast: ShaderErrorToast::default(),
             core_system,
             tabs: Arc::new(Mutex::new(tabs)),
             selected_entity: None,
+            hovered_entity: None,
             envmap_path: None,
         }
     }
 
-    pub fn on_ui(&mut self, ctx: &Context, scene: Option<&Scene>, core: &mut CoreSystems) {
+    pub fn on_ui(
+        &mut self,
+        ctx: &Context,
+        scene: Option<&Scene>,
+        viewport: &ViewportTexture,
+        core: &mut CoreSystems,
+    ) {
         if scene.is_none() {
             self.selected_entity.take();
         }
+        // Only hovering while the viewport is shown
+        self.hovered_entity.take();
+        self.shader_toast.show(ctx);
         let (state, new_nodes) = {
             let tabs = self.tabs.clone();
-            let mut state = UiStateLocal::new(scene, self, self.gizmo_mode, &mut core.render);
+            let mut state =
+                UiStateLocal::new(scene, self, viewport, self.gizmo_mode, &mut core.render);
             egui::CentralPanel::default()
                 .frame(egui::Frame::none())
                 .show(ctx, |ui| {
@@ -131,6 +178,95 @@ impl EditorUiSystem {
             tabs.push_to_focused_leaf(tab);
         }
         self.last_state = state;
+        if let Some(scene) = scene {
+            scene.with_world(|world, cmd| self.sync_selection(world, cmd));
+        }
+    }
+
+    /// Name of a component type registered in the inspector.
+    pub fn component_name(&self, type_id: TypeId) -> Option<&'static str> {
+        self.core_system.component_name(type_id)
+    }
+
+    /// Mirror the selected and hovered entities as [`Selected`] and [`Hovered`] components, so that
+    /// the renderer outlines them.
+    fn sync_selection(&self, world: &World, cmd: &mut CommandBuffer) {
+        sync_marker(world, cmd, self.selected_entity, Selected);
+        sync_marker(world, cmd, self.hovered_entity, Hovered);
+    }
+}
+
+/// Make `entity` the only one with the marker component.
+fn sync_marker<C: Component + Copy>(
+    world: &World,
+    cmd: &mut CommandBuffer,
+    entity: Option<Entity>,
+    marker: C,
+) {
+    for (marked, _) in world.query::<&C>().iter() {
+        if Some(marked) != entity {
+            cmd.remove_one::<C>(marked);
+        }
+    }
+    if let Some(entity) = entity {
+        if world.contains(entity) && world.get::<&C>(entity).is_err() {
+            cmd.insert_one(entity, marker);
+        }
+    }
+}
+
+/// Increments the transform gizmo snaps to, when enabled or while Ctrl is held.
+#[derive(Debug, Copy, Clone)]
+pub struct GizmoSnapping {
+    pub enabled: bool,
+    /// Grid size of translations, in world units.
+    pub distance: f32,
+    /// Rotation increments, in degrees.
+    pub angle: f32,
+    pub scale: f32,
+}
+
+impl Default for GizmoSnapping {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            distance: 0.5,
+            angle: 15.,
+            scale: 0.1,
+        }
+    }
+}
+
+impl GizmoSnapping {
+    pub fn ui(&mut self, ui: &mut Ui) {
+        Grid::new("gizmo-snapping").num_columns(2).show(ui, |ui| {
+            let distance_label = ui.label("Grid size").id;
+            ui.add(
+                DragValue::new(&mut self.distance)
+                    .clamp_range(0.001..=f32::MAX)
+                    .speed(0.01),
+            )
+            .labelled_by(distance_label);
+            ui.end_row();
+
+            let angle_label = ui.label("Angle").id;
+            ui.add(
+                DragValue::new(&mut self.angle)
+                    .clamp_range(0.1..=180.)
+                    .suffix(" °"),
+            )
+            .labelled_by(angle_label);
+            ui.end_row();
+
+            let scale_label = ui.label("Scale").id;
+            ui.add(
+                DragValue::new(&mut self.scale)
+                    .clamp_range(0.001..=f32::MAX)
+                    .speed(0.01),
+            )
+            .labelled_by(scale_label);
+            ui.end_row();
+        });
     }
 }
 
@@ -139,6 +275,10 @@ pub struct UiState {
     pub mouse_delta: Vec2,
     pub mouse_scroll: f32,
     pub mouse_buttons: (bool, bool),
+    /// Zoom of a two-finger pinch over the viewport during the frame, 1 without one.
+    pub touch_zoom: f32,
+    /// Size in pixels of the Viewport tab, zero when it is not shown.
+    pub viewport_size: UVec2,
 }
 
 impl Default for UiState {
@@ -147,6 +287,8 @@ impl Default for UiState {
             mouse_buttons: (false, false),
             mouse_scroll: 0.,
             mouse_delta: Vec2::ZERO,
+            touch_zoom: 1.,
+            viewport_size: UVec2::ZERO,
         }
     }
 }
@@ -156,6 +298,7 @@ struct UiStateLocal<'a> {
     system: &'a mut EditorUiSystem,
     new_nodes: Vec<(NodeIndex, Tabs)>,
     scene: Option<&'a Scene>,
+    viewport: &'a ViewportTexture,
     gizmo_mode: GizmoMode,
     renderer: &'a mut RenderSystem,
 }
@@ -164,6 +307,7 @@ impl<'a> UiStateLocal<'a> {
     fn new(
         scene: Option<&'a Scene>,
         system: &'a mut EditorUiSystem,
+        viewport: &'a ViewportTexture,
         gizmo_mode: GizmoMode,
         renderer: &'a mut RenderSystem,
     ) -> Self {
@@ -173,90 +317,12 @@ impl<'a> UiStateLocal<'a> {
             new_nodes: vec![],
             gizmo_mode,
             scene,
+            viewport,
             renderer,
         }
     }
 }
 
-fn scene_hierarchy_node(
-    ui: &mut Ui,
-    selected_entity: &mut Option<Entity>,
-    cmd: &mut CommandBuffer,
-    world: &World,
-    entity: EntityRef,
-) {
-    let name = entity
-        .get::<&String>()
-        .map(|s| s.to_string())
-        .or_else(|| {
-            entity
-                .get::<&NamedObject>()
-                .map(|n| format!("[Object {:?}]", n.object.as_str()))
-        })
-        .unwrap_or("<Unnamed>".to_string());
-    let selected = *selected_entity == Some(entity.entity());
-    let heading = WidgetText::RichText(RichText::new(name));
-    let heading = if selected { heading.strong() } else { heading };
-    let resp = ui
-        .collapsing(heading, |ui| {
-            let mut query = world.query::<&Parent>();
-            let children = query
-                .iter()
-                .filter_map(|(e, p)| (p.0 == entity.entity()).then_some(e));
-            for child in children {
-                scene_hierarchy_node(
-                    ui,
-                    selected_entity,
-                    cmd,
-                    world,
-                    world.entity(child).unwrap(),
-                );
-            }
-        })
-        .header_response;
-    let label_resp = resp.context_menu(|ui| {
-        if let Some(mut name) = entity.get::<&mut String>() {
-            let name_label = ui.label("Name:").id;
-            ui.text_edit_singleline(&mut *name).labelled_by(name_label);
-        } else if ui.small_button("Add name").clicked() {
-            cmd.insert_one(entity.entity(), String::new());
-        }
-        ui.separator();
-        if ui.small_button("Remove").clicked() {
-            cmd.despawn(entity.entity());
-            if selected {
-                *selected_entity = None;
-            }
-            ui.close_menu();
-        }
-        ui.separator();
-        ui.menu_button("Parent to", |ui| {
-            thread_local! {static SEARCH: RefCell<String> = RefCell::new(String::new());}
-            SEARCH.with(|search_key| {
-                let mut q = world.query::<&String>();
-                let mut search = search_key.borrow_mut();
-                ui.text_edit_singleline(&mut *search);
-                egui::ScrollArea::new([false, true]).show(ui, |ui| {
-                    for (potential_parent, name) in q.iter() {
-                        if (search.is_empty() || name.contains(search.as_str()))
-                            && ui.small_button(name.as_str()).clicked()
-                        {
-                            cmd.insert_one(entity.entity(), Parent(potential_parent));
-                        }
-                    }
-                });
-            });
-        });
-        if ui.small_button("Add child entity").clicked() {
-            cmd.spawn_child(entity.entity(), &mut EntityBuilder::new());
-            ui.close_menu();
-        }
-    });
-    if label_resp.clicked() {
-        selected_entity.replace(entity.entity());
-    }
-}
-
 impl<'a> TabViewer for UiStateLocal<'a> {
     type Tab = Tabs;
 
@@ -264,14 +330,62 @@ impl<'a> TabViewer for UiStateLocal<'a> {
         match tab {
             Tabs::Viewport => {
                 egui::Frame::none()
-                    .fill(Color32::TRANSPARENT)
                     .inner_margin(0.)
                     .outer_margin(0.)
                     .show(ui, |ui| {
                         if let Some(scene) = self.scene {
                             let size = ui.available_size_before_wrap();
-                            let (_rect, response) =
+                            let (rect, response) =
                                 ui.allocate_exact_size(size, Sense::click_and_drag());
+                            // The scene is rendered at this size for the next frames
+                            let pixels = rect.size() * ui.ctx().pixels_per_point();
+                            self.state.viewport_size = Vec2::new(pixels.x, pixels.y).as_uvec2();
+                            self.viewport.image(rect.size()).paint_at(ui, rect);
+                            let probe = response.hover_pos().map(|pointer| {
+                                let uv = (pointer - rect.min) / rect.size();
+                                Vec2::new(uv.x, uv.y)
+                            });
+                            self.renderer.renderer.set_luminance_probe(probe);
+                            if let Some(luminance) = self
+                                .renderer
+                                .renderer
+                                .probed_luminance()
+                                .filter(|_| probe.is_some())
+                            {
+                                egui::show_tooltip_at_pointer(
+                                    ui.ctx(),
+                                    egui::Id::new("luminance-readout"),
+                                    |ui| {
+                                        ui.monospace(format!(
+                                            "{:.3} nits\n{:+.2} EV",
+                                            luminance,
+                                            luminance.log2()
+                                        ));
+                                    },
+                                );
+                            }
+                            let clicked = scene.with_world(|world, _| {
+                                draw_entity_gizmos(
+                                    ui,
+                                    rect,
+                                    &response,
+                                    &self.renderer.camera,
+                                    world,
+                                    self.system.selected_entity,
+                                )
+                            });
+                            if let Some(entity) = clicked {
+                                self.system.selected_entity.replace(entity);
+                            }
+                            let editing_spline = scene.with_world(|world, _| {
+                                edit_spline_handles(
+                                    ui,
+                                    rect,
+                                    &self.renderer.camera,
+                                    world,
+                                    self.system.selected_entity,
+                                )
+                            });
                             let gizmo_interaction = if let Some(entity) =
                                 self.system.selected_entity
                             {
@@ -286,6 +400,7 @@ impl<'a> TabViewer for UiStateLocal<'a> {
                                     if let Some(mut tr) = eref.get::<&mut Transform>() {
                                         ui.scope(|ui| {
                                             let camera = &self.renderer.camera;
+                                            let snapping = self.system.snapping;
                                             let gizmo_interact =
                                                 Gizmo::new("selected-entity-gizmo")
                                                     // .viewport(rect)
@@ -303,6 +418,13 @@ impl<'a> TabViewer for UiStateLocal<'a> {
                                                             .to_cols_array_2d(),
                                                     )
                                                     .mode(self.gizmo_mode)
+                                                    .snapping(
+                                                        snapping.enabled
+                                                            || ui.input().modifiers.ctrl,
+                                                    )
+                                                    .snap_distance(snapping.distance)
+                                                    .snap_angle(snapping.angle.to_radians())
+                                                    .snap_scale(snapping.scale)
                                                     .interact(ui);
                                             if let Some(interact) = gizmo_interact {
                                                 *tr = Transform::from_matrix(
@@ -321,7 +443,18 @@ impl<'a> TabViewer for UiStateLocal<'a> {
                             } else {
                                 false
                             };
-                            if !gizmo_interaction {
+                            if !gizmo_interaction && !editing_spline {
+                                self.system.hovered_entity =
+                                    response.hover_pos().and_then(|pointer| {
+                                        scene.with_world(|world, _| {
+                                            pick_mesh(rect, pointer, self.renderer, world)
+                                        })
+                                    });
+                                if response.clicked() && clicked.is_none() {
+                                    if let Some(entity) = self.system.hovered_entity {
+                                        self.system.selected_entity.replace(entity);
+                                    }
+                                }
                                 let input = ui.input();
                                 let drag = input.pointer.delta();
                                 self.state.mouse_buttons = (
@@ -330,45 +463,32 @@ impl<'a> TabViewer for UiStateLocal<'a> {
                                 );
                                 self.state.mouse_delta = vec2(drag.x, drag.y);
                                 self.state.mouse_scroll = input.scroll_delta.y;
+                                self.state.touch_zoom = 1.;
+                                // Two fingers pan and zoom, one orbits as the primary button
+                                if let Some(touch) = input.multi_touch() {
+                                    let pan = touch.translation_delta;
+                                    self.state.mouse_buttons = (false, true);
+                                    self.state.mouse_delta = vec2(pan.x, pan.y);
+                                    self.state.touch_zoom = touch.zoom_delta;
+                                }
                             }
                         }
                     });
             }
             Tabs::SceneHierarchy => {
-                egui::Frame::none().show(ui, |ui| {
-                    if let Some(scene) = self.scene {
-                        ui.vertical(|ui| {
-                            scene.with_world(|world, cmd| {
-                                let mut q = world.query::<()>().without::<&Parent>();
-                                for (entity, _) in q.iter() {
-                                    let entity = world.entity(entity).unwrap();
-                                    scene_hierarchy_node(
-                                        ui,
-                                        &mut self.system.selected_entity,
-                                        cmd,
-                                        world,
-                                        entity,
-                                    );
-                                }
-                                let size = ui.available_size();
-                                let (_, response) = ui.allocate_exact_size(size, Sense::click());
-                                if response
-                                    .context_menu(|ui| {
-                                        if ui.small_button("Add empty").clicked() {
-                                            cmd.spawn(());
-                                            ui.close_menu();
-                                        }
-                                    })
-                                    .clicked()
-                                {
-                                    self.system.selected_entity.take();
-                                }
-                            });
-                        });
-                    } else {
-                        ui.monospace("No loaded scene");
-                    }
-                });
+                if let Some(scene) = self.scene {
+                    scene.with_world(|world, cmd| {
+                        self.system.hierarchy.ui(
+                            ui,
+                            world,
+                            cmd,
+                            &self.system.core_system,
+                            &mut self.system.selected_entity,
+                        );
+                    });
+                } else {
+                    ui.monospace("No loaded scene");
+                }
             }
             Tabs::Inspector => {
                 if let Some(scene) = self.scene {
@@ -538,10 +658,7 @@ impl<'a> TabViewer for UiStateLocal<'a> {
                             }
                             if ui.button("Change").clicked() {
                                 if let Some(new_path) = rfd::FileDialog::new()
-                                    .add_filter(
-                                        "Images",
-                                        &["jpg", "png", "bmp", "exr", "hdr", "tif", "tga"],
-                                    )
+                                    .add_filter("Images", EnvironmentMap::EXTENSIONS)
                                     .pick_file()
                                 {
                                     self.renderer.renderer.set_environment(|reload_watcher| {
@@ -554,10 +671,7 @@ impl<'a> TabViewer for UiStateLocal<'a> {
                         ui.end_row();
                     } else if ui.button("Open").clicked() {
                         if let Some(new_path) = rfd::FileDialog::new()
-                            .add_filter(
-                                "Images",
-                                &["jpg", "png", "bmp", "exr", "hdr", "tif", "tga"],
-                            )
+                            .add_filter("Images", EnvironmentMap::EXTENSIONS)
                             .pick_file()
                         {
                             self.renderer.renderer.set_environment(|reload_watcher| {
@@ -604,6 +718,18 @@ impl<'a> TabViewer for UiStateLocal<'a> {
                     self.renderer.renderer.ui_render_stats(ui);
                 });
             }
+            Tabs::Console => self.system.console.ui(ui),
+            Tabs::SceneStats => {
+                if let Some(scene) = self.scene {
+                    // Collected every frame the tab is shown, to follow the scene live
+                    let stats = scene.with_world(|world, _| {
+                        SceneStats::collect(world, &self.system.core_system)
+                    });
+                    stats.ui(ui);
+                } else {
+                    ui.monospace("No loaded scene");
+                }
+            }
         }
     }
 
@@ -619,10 +745,6 @@ impl<'a> TabViewer for UiStateLocal<'a> {
             }
         }
     }
-
-    fn clear_background(&self, tab: &Self::Tab) -> bool {
-        !matches!(tab, Tabs::Viewport)
-    }
 }
 
 struct AnyDirLoader;
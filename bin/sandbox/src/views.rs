@@ -0,0 +1,165 @@
+use std::f32::consts::FRAC_PI_2;
+
+use egui::{Context, Key, Ui};
+use serde::{Deserialize, Serialize};
+
+use rose::prelude::*;
+
+pub const BOOKMARK_KEYS: [Key; 9] = [
+    Key::Num1,
+    Key::Num2,
+    Key::Num3,
+    Key::Num4,
+    Key::Num5,
+    Key::Num6,
+    Key::Num7,
+    Key::Num8,
+    Key::Num9,
+];
+
+/// Saved state of the editor camera.
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub struct CameraBookmark {
+    pub controller: PanOrbitCamera,
+    pub orthographic: bool,
+}
+
+/// Editor views, saved in the scene file as a component of their own entity.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EditorViews {
+    pub bookmarks: [Option<CameraBookmark>; 9],
+    pub orthographic: bool,
+}
+
+impl EditorViews {
+    /// Read the views stored in the scene, if any.
+    pub fn load(scene: &Scene) -> Self {
+        scene.with_world(|world, _| {
+            world
+                .query::<&EditorViews>()
+                .iter()
+                .next()
+                .map(|(_, views)| views.clone())
+                .unwrap_or_default()
+        })
+    }
+
+    /// Write the views into the scene, so that they get saved along with it.
+    pub fn store(&self, scene: &mut Scene) {
+        scene.with_world_mut(|world| {
+            let existing = world.query_mut::<&mut EditorViews>().into_iter().next();
+            if let Some((_, views)) = existing {
+                *views = self.clone();
+            } else {
+                world.spawn((self.clone(),));
+            }
+        });
+    }
+
+    pub fn save_bookmark(&mut self, slot: usize, controller: &PanOrbitCamera) {
+        self.bookmarks[slot] = Some(CameraBookmark {
+            controller: *controller,
+            orthographic: self.orthographic,
+        });
+    }
+
+    /// Restore the bookmark in the given slot, returning whether there was one.
+    pub fn restore_bookmark(&mut self, slot: usize, controller: &mut PanOrbitCamera) -> bool {
+        let Some(bookmark) = self.bookmarks[slot] else { return false; };
+        *controller = bookmark.controller;
+        self.orthographic = bookmark.orthographic;
+        true
+    }
+
+    /// Number keys restore bookmarks and save them with Ctrl; Alt+1, Alt+3 and Alt+7 switch to
+    /// the front, right and top views, and Alt+5 toggles the orthographic projection.
+    pub fn handle_shortcuts(&mut self, ctx: &Context, controller: &mut PanOrbitCamera) {
+        if ctx.wants_keyboard_input() {
+            return;
+        }
+        let input = ctx.input();
+        for (slot, key) in BOOKMARK_KEYS.into_iter().enumerate() {
+            if !input.key_pressed(key) {
+                continue;
+            }
+            if input.modifiers.alt {
+                match key {
+                    Key::Num1 => ViewPreset::Front.apply(controller),
+                    Key::Num3 => ViewPreset::Right.apply(controller),
+                    Key::Num7 => ViewPreset::Top.apply(controller),
+                    Key::Num5 => self.orthographic = !self.orthographic,
+                    _ => {}
+                }
+            } else if input.modifiers.ctrl {
+                self.save_bookmark(slot, controller);
+            } else {
+                self.restore_bookmark(slot, controller);
+            }
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut Ui, controller: &mut PanOrbitCamera) {
+        for preset in ViewPreset::ALL {
+            if ui.small_button(preset.name()).clicked() {
+                preset.apply(controller);
+                ui.close_menu();
+            }
+        }
+        ui.checkbox(&mut self.orthographic, "Orthographic");
+        ui.separator();
+        ui.menu_button("Bookmarks", |ui| {
+            for slot in 0..self.bookmarks.len() {
+                ui.horizontal(|ui| {
+                    ui.label(format!("{}", slot + 1));
+                    if ui.small_button("Save").clicked() {
+                        self.save_bookmark(slot, controller);
+                    }
+                    ui.add_enabled_ui(self.bookmarks[slot].is_some(), |ui| {
+                        if ui.small_button("Restore").clicked() {
+                            self.restore_bookmark(slot, controller);
+                        }
+                        if ui.small_button("Clear").clicked() {
+                            self.bookmarks[slot] = None;
+                        }
+                    });
+                });
+            }
+        });
+    }
+
+    /// Vertical extent of the orthographic view matching what the perspective camera frames at the
+    /// focus point, or `None` when using a perspective projection.
+    pub fn orthographic_height(&self, controller: &PanOrbitCamera, fovy: f32) -> Option<f32> {
+        self.orthographic
+            .then(|| 2. * controller.radius * (fovy / 2.).tan())
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ViewPreset {
+    Front,
+    Right,
+    Top,
+}
+
+impl ViewPreset {
+    pub const ALL: [Self; 3] = [Self::Front, Self::Right, Self::Top];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Front => "Front",
+            Self::Right => "Right",
+            Self::Top => "Top",
+        }
+    }
+
+    /// Orbit the camera around its focus point to look along an axis.
+    pub fn apply(self, controller: &mut PanOrbitCamera) {
+        controller.target_rotation = match self {
+            Self::Front => Vec2::ZERO,
+            Self::Right => vec2(-FRAC_PI_2, 0.),
+            Self::Top => vec2(0., FRAC_PI_2),
+        };
+    }
+}
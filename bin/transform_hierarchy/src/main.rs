@@ -15,9 +15,9 @@ struct LocalCamera;
 
 impl Application for App {
     #[tracing::instrument]
-    fn new(size: PhysicalSize<f32>, scale_factor: f64) -> Result<Self> {
-        let sizeu = UVec2::from_array(size.cast::<u32>().into());
-        let mut core_systems = CoreSystems::new(sizeu)?;
+    fn new(size: WindowSize, config: &LaunchConfig) -> Result<Self> {
+        let mut core_systems =
+            CoreSystems::with_asset_root(size.physical_vec(), &config.asset_root)?;
         core_systems
             .persistence
             .register_component::<GlobalTransform>();
@@ -32,7 +32,7 @@ impl Application for App {
             //     light: components::Light {
             //         kind: LightKind::Directional,
             //         color: Vec3::ONE,
-            //         power: 10.,
+            //         intensity: 10.,
             //     },
             //     ..Default::default()
             // });
@@ -126,8 +126,8 @@ impl Application for App {
         })
     }
 
-    fn resize(&mut self, size: PhysicalSize<u32>, _scale_factor: f64) -> Result<()> {
-        self.core_systems.resize(size)?;
+    fn resize(&mut self, size: WindowSize) -> Result<()> {
+        self.core_systems.resize(size.physical)?;
         Ok(())
     }
 
@@ -1,10 +1,10 @@
-use std::{rc::Rc, time::Duration};
+use std::time::Duration;
 
 use camera_controller::OrbitCameraController;
 use rose::{
     core::light::Light,
     prelude::*,
-    renderer::{Mesh, Renderer},
+    renderer::{MaterialHandle, Mesh, MeshHandle, Renderer},
 };
 use violette::texture::Texture;
 
@@ -13,8 +13,8 @@ mod camera_controller;
 struct App {
     camera: Camera,
     renderer: ThreadGuard<Renderer>,
-    mesh: ThreadGuard<Rc<Mesh>>,
-    material: ThreadGuard<Rc<MaterialInstance>>,
+    mesh: MeshHandle,
+    material: MaterialHandle,
     transform: Transform,
     ctrl_pressed: bool,
     dragging: Option<MouseButton>,
@@ -28,15 +28,14 @@ impl Application for App {
     }
 
     #[tracing::instrument(target = "App::new")]
-    fn new(size: PhysicalSize<f32>, _scale_factor: f64) -> Result<Self> {
-        let base_dir = std::env::current_dir().unwrap();
-        let _sizef = Vec2::from_array(size.into());
-        let size = UVec2::from_array(size.cast::<u32>().into());
-        let mesh = MeshBuilder::new(Vertex::new)
+    fn new(size: WindowSize, config: &LaunchConfig) -> Result<Self> {
+        let base_dir = &config.asset_root;
+        let size = size.physical_vec();
+        let mesh: Mesh = MeshBuilder::new(Vertex::new)
             .uv_sphere(1.0, 32, 64)
             .upload()?
             .into();
-        let mut material = MaterialInstance::create(
+        let material = MaterialInstance::create(
             Texture::load_rgb32f("assets/textures/moon_color.png")?,
             Texture::load_rgb32f("assets/textures/moon_normal.png")?,
             None,
@@ -60,6 +59,8 @@ impl Application for App {
         let mut camera_controller = OrbitCameraController::default();
         let mut renderer = Renderer::new(size, base_dir)?;
         renderer.add_lights(lights)?;
+        let mesh = renderer.register_mesh(mesh);
+        let material = renderer.register_material_standard(material);
         camera_controller.update(Duration::default(), &mut camera);
 
         Ok(Self {
@@ -69,14 +70,14 @@ impl Application for App {
             ctrl_pressed: false,
             dragging: None,
             last_mouse_pos: Vec2::ZERO,
-            material: ThreadGuard::new(Rc::new(material)),
-            mesh: ThreadGuard::new(Rc::new(mesh)),
+            material,
+            mesh,
             transform: Transform::default(),
         })
     }
 
-    fn resize(&mut self, _size: PhysicalSize<u32>, _scale_factor: f64) -> Result<()> {
-        let size = UVec2::from_array(_size.into());
+    fn resize(&mut self, size: WindowSize) -> Result<()> {
+        let size = size.physical_vec();
         let sizef = size.as_vec2();
         self.camera.projection.update(sizef);
         self.renderer.resize(size)
@@ -133,14 +134,16 @@ impl Application for App {
     #[tracing::instrument(target = "App::render", skip_all)]
     fn render(&mut self, ctx: RenderContext) -> Result<()> {
         self.renderer.begin_render(&self.camera)?;
-        self.renderer.submit_mesh_standard(
-            Rc::clone(&self.material),
-            Rc::clone(&self.mesh).transformed(self.transform),
-        );
+        self.renderer
+            .submit(self.material, self.mesh.transformed(self.transform));
         self.renderer.flush(ctx.dt, Vec3::ZERO)?;
         Ok(())
     }
 
+    fn hud_lines(&self) -> Vec<String> {
+        self.renderer.frame_stats().hud_lines()
+    }
+
     fn ui(&mut self, ctx: UiContext) {
         egui::TopBottomPanel::top("top_menu").show(ctx.egui, |ui| {
             ui.horizontal(|ui| {
@@ -184,6 +187,15 @@ impl Application for App {
                 self.renderer.ui_toolbar(ui);
             });
         });
+        // The scene covers the window, so the probe follows the cursor unless it is over the UI
+        let over_ui = ctx.egui.wants_pointer_input();
+        let screen_size = ctx.egui.screen_rect().size();
+        let pointer = ctx.egui.input().pointer.hover_pos();
+        let probe = pointer.filter(|_| !over_ui).map(|pointer| {
+            let uv = pointer / screen_size;
+            Vec2::new(uv.x, uv.y)
+        });
+        self.renderer.set_luminance_probe(probe);
         self.camera_controller.ui(ctx.egui);
         self.renderer.ui(ctx.egui);
 
@@ -1,4 +1,5 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::sync::RwLock;
 use std::{
     io,
     path::{Path, PathBuf},
@@ -6,6 +7,17 @@ use std::{
 
 use lazy_regex::{lazy_regex, Lazy, Regex};
 
+static VIRTUAL_FILES: Lazy<RwLock<HashMap<PathBuf, String>>> = Lazy::new(Default::default);
+
+/// Provide the contents of `#include` directives naming this path instead of reading a file, e.g.
+/// to pass configuration defines to shaders. Virtual files cannot include other files.
+pub fn set_virtual_file(path: impl Into<PathBuf>, contents: impl Into<String>) {
+    VIRTUAL_FILES
+        .write()
+        .unwrap()
+        .insert(path.into(), contents.into());
+}
+
 fn parse_imports(source: &str) -> (String, Vec<PathBuf>) {
     static IMPORT_PRAGMA: Lazy<Regex> = lazy_regex!(r#"#include\s+[<"'](.*)[>"']"#);
     let mut source = source.to_string();
@@ -24,13 +36,24 @@ fn parse_imports(source: &str) -> (String, Vec<PathBuf>) {
 
 pub fn load_and_parse(path: impl AsRef<Path>) -> io::Result<Vec<(PathBuf, String)>> {
     let contents = std::fs::read_to_string(path.as_ref())?;
+    parse_source(path, &contents)
+}
+
+/// Same as [`load_and_parse`] with the source of the shader given instead of read from `path`,
+/// which still locates the files it includes. Lets generated shaders include files from disk.
+pub fn parse_source(path: impl AsRef<Path>, source: &str) -> io::Result<Vec<(PathBuf, String)>> {
     let dirname = path.as_ref().parent().unwrap();
     let mut paths = HashSet::new();
-    let (contents, imports) = parse_imports(&contents);
+    let (contents, imports) = parse_imports(source);
     Ok(imports
         .into_iter()
-        .map(|p| dirname.join(p))
-        .map(load_and_parse)
+        .map(|p| {
+            let contents = VIRTUAL_FILES.read().unwrap().get(&p).cloned();
+            match contents {
+                Some(contents) => Ok(vec![(p, contents)]),
+                None => load_and_parse(dirname.join(p)),
+            }
+        })
         .collect::<Result<Vec<_>, _>>()?
         .into_iter()
         .flatten()
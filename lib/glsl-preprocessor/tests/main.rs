@@ -1,6 +1,6 @@
 use std::path::PathBuf;
 
-use glsl_preprocessor::load_and_parse;
+use glsl_preprocessor::{load_and_parse, parse_source};
 
 #[test]
 fn test_process_file() {
@@ -11,3 +11,18 @@ fn test_process_file() {
     let unwrapped = load_and_parse(tests_files.join("shader.glsl")).unwrap();
     insta::assert_debug_snapshot!(unwrapped);
 }
+
+#[test]
+fn test_process_source() {
+    let tests_files = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("fixtures");
+    let source = "#include \"common.glsl\"\nvoid main() {}\n";
+    let unwrapped = parse_source(tests_files.join("generated.glsl"), source).unwrap();
+    let (path, contents) = unwrapped.last().unwrap();
+    assert_eq!(path, &tests_files.join("generated.glsl"));
+    assert_eq!(contents, "\nvoid main() {}\n");
+    assert!(unwrapped
+        .iter()
+        .any(|(path, _)| path == &tests_files.join("common.glsl")));
+}
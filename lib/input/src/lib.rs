@@ -1,34 +1,47 @@
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::hash::Hash;
+use std::time::{Duration, Instant};
 
-use glam::{vec3, Vec3};
-use winit::event::{ElementState, MouseButton, MouseScrollDelta, VirtualKeyCode, WindowEvent};
+use glam::{vec2, Vec2, Vec3};
+use winit::event::{
+    ElementState, Force, Ime, MouseButton, MouseScrollDelta, TouchPhase, VirtualKeyCode,
+    WindowEvent,
+};
+use winit::window::Window;
 
+/// Pressed state of keys or buttons, with the changes of the current frame.
+///
+/// A frame starts with [`Self::begin_frame`], and the events applied until the next call belong to
+/// it: values pressed or released during the frame are reported by [`Self::just_pressed`] and
+/// [`Self::just_released`] until then, including values both pressed and released within the
+/// frame.
 #[derive(Debug, Clone)]
 pub struct State<T> {
-    pressed: HashSet<T>,
+    /// Pressed values, with the time they were pressed at.
+    pressed: HashMap<T, Instant>,
     just_pressed: HashSet<T>,
     just_released: HashSet<T>,
+    frame_start: Option<Instant>,
 }
 
 impl<T> Default for State<T> {
     fn default() -> Self {
         Self {
-            pressed: HashSet::new(),
+            pressed: HashMap::new(),
             just_pressed: HashSet::new(),
             just_released: HashSet::new(),
+            frame_start: None,
         }
     }
 }
 
 impl<T: Copy + Eq + Hash> State<T> {
     pub fn begin_frame(&mut self) {
-        self.just_released.clear();
-        self.just_released.clear();
+        self.begin_frame_at(Instant::now());
     }
 
     pub fn is_pressed(&self, value: &T) -> bool {
-        self.pressed.contains(value)
+        self.pressed.contains_key(value)
     }
 
     pub fn just_pressed(&self, value: &T) -> bool {
@@ -39,34 +52,168 @@ impl<T: Copy + Eq + Hash> State<T> {
         self.just_released.contains(value)
     }
 
-    fn set(&mut self, value: T) {
-        self.pressed.insert(value);
+    pub fn any_pressed(&self) -> bool {
+        !self.pressed.is_empty()
+    }
+
+    /// Time the value has been held down for at the start of the frame, or `None` if it isn't
+    /// pressed. Values pressed during the frame have been held for zero seconds.
+    pub fn pressed_duration(&self, value: &T) -> Option<Duration> {
+        let pressed_at = *self.pressed.get(value)?;
+        let now = self.frame_start.unwrap_or_else(Instant::now);
+        Some(now.saturating_duration_since(pressed_at))
+    }
+
+    fn begin_frame_at(&mut self, now: Instant) {
+        self.just_pressed.clear();
+        self.just_released.clear();
+        self.frame_start = Some(now);
+    }
+
+    /// Press the value, returning false if it was already pressed.
+    fn set(&mut self, value: T, now: Instant) -> bool {
+        if self.pressed.contains_key(&value) {
+            return false;
+        }
+        self.pressed.insert(value, now);
         self.just_pressed.insert(value);
+        true
     }
 
     fn clear(&mut self, value: T) {
-        self.pressed.remove(&value);
-        self.just_pressed.remove(&value);
-        self.just_released.insert(value);
+        if self.pressed.remove(&value).is_some() {
+            self.just_released.insert(value);
+        }
+    }
+
+    fn clear_all(&mut self) {
+        self.just_released
+            .extend(self.pressed.drain().map(|(value, _)| value));
     }
 }
 
 #[derive(Debug, Default, Clone)]
 pub struct KeyboardInput {
     pub state: State<VirtualKeyCode>,
+    /// Characters typed during the frame in text input mode.
+    text: String,
+    /// Keys pressed during the frame, in order, including the repeats of keys held down.
+    typed: Vec<VirtualKeyCode>,
+    repeated: HashSet<VirtualKeyCode>,
+    /// Keys held down in text input mode, which are kept out of `state`.
+    text_held: HashSet<VirtualKeyCode>,
 }
 
 impl KeyboardInput {
     pub fn begin_frame(&mut self) {
         self.state.begin_frame();
+        self.text.clear();
+        self.typed.clear();
+        self.repeated.clear();
+    }
+
+    /// Text typed during the frame while in text input mode, without control characters.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Keys pressed or repeated by the system during the frame, in order. Text fields should use
+    /// these for editing keys such as backspace and arrows, so that holding them down repeats.
+    pub fn typed_keys(&self) -> &[VirtualKeyCode] {
+        &self.typed
+    }
+
+    /// Whether the key was pressed or repeated during the frame.
+    pub fn just_typed(&self, key: &VirtualKeyCode) -> bool {
+        self.typed.contains(key)
+    }
+
+    /// Whether the system repeated the key, held down, during the frame.
+    pub fn is_repeated(&self, key: &VirtualKeyCode) -> bool {
+        self.repeated.contains(key)
+    }
+
+    fn press(&mut self, key: VirtualKeyCode, text_input: bool, now: Instant) {
+        self.typed.push(key);
+        let newly_pressed = if text_input {
+            // Keys typed into text fields don't also control the application
+            self.text_held.insert(key)
+        } else {
+            self.state.set(key, now)
+        };
+        if !newly_pressed {
+            self.repeated.insert(key);
+        }
+    }
+
+    fn release(&mut self, key: VirtualKeyCode) {
+        if !self.text_held.remove(&key) {
+            self.state.clear(key);
+        }
+    }
+}
+
+/// Thresholds of the click and drag detection of [`MouseInput`].
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct GestureSettings {
+    /// Maximum time between two presses for them to count as consecutive clicks.
+    pub multi_click_time: Duration,
+    /// Maximum distance, in pixels, between two presses for them to count as consecutive clicks.
+    pub multi_click_distance: f32,
+    /// Distance, in pixels, the cursor has to move with a button held down to start dragging.
+    pub drag_threshold: f32,
+}
+
+impl Default for GestureSettings {
+    fn default() -> Self {
+        Self {
+            multi_click_time: Duration::from_millis(500),
+            multi_click_distance: 4.,
+            drag_threshold: 4.,
+        }
     }
 }
 
+/// Gesture detected by [`MouseInput`] during the frame. Positions are in window pixels.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum MouseGesture {
+    /// The button was pressed, `count` being the number of consecutive clicks, e.g. 2 for a
+    /// double-click.
+    Click {
+        button: MouseButton,
+        count: u32,
+        pos: Vec2,
+    },
+    /// The cursor moved past the drag threshold with the button held down.
+    DragStart { button: MouseButton, origin: Vec2 },
+    /// The button was released while dragging.
+    DragEnd {
+        button: MouseButton,
+        origin: Vec2,
+        pos: Vec2,
+    },
+}
+
+/// Click and drag state of a mouse button.
+#[derive(Debug, Clone, Default)]
+struct ButtonGesture {
+    /// Position of the cursor when the button was pressed, while it is held down.
+    press_pos: Option<Vec2>,
+    dragging: bool,
+    /// Cursor position the drag delta of the frame is measured from.
+    drag_from: Vec2,
+    /// Time, position and click count of the last press which could be followed by another click.
+    last_click: Option<(Instant, Vec2, u32)>,
+}
+
 #[derive(Debug, Clone)]
 pub struct MouseInput {
     pub state: State<MouseButton>,
     pub pos: Vec3,
     prev_pos: Vec3,
+    pub gesture_settings: GestureSettings,
+    buttons: HashMap<MouseButton, ButtonGesture>,
+    gestures: Vec<MouseGesture>,
 }
 
 impl Default for MouseInput {
@@ -75,6 +222,9 @@ impl Default for MouseInput {
             state: State::default(),
             pos: Vec3::ZERO,
             prev_pos: Vec3::ZERO,
+            gesture_settings: GestureSettings::default(),
+            buttons: HashMap::new(),
+            gestures: vec![],
         }
     }
 }
@@ -87,6 +237,230 @@ impl MouseInput {
     pub fn begin_frame(&mut self) {
         self.state.begin_frame();
         self.prev_pos = self.pos;
+        self.gestures.clear();
+        for gesture in self.buttons.values_mut() {
+            gesture.drag_from = self.pos.truncate();
+        }
+    }
+
+    /// Gestures detected during the frame, in order.
+    pub fn gestures(&self) -> &[MouseGesture] {
+        &self.gestures
+    }
+
+    /// Number of consecutive clicks if the button was pressed during the frame, 0 otherwise.
+    pub fn click_count(&self, button: &MouseButton) -> u32 {
+        self.gestures
+            .iter()
+            .filter_map(|gesture| match gesture {
+                MouseGesture::Click {
+                    button: b, count, ..
+                } if b == button => Some(*count),
+                _ => None,
+            })
+            .next_back()
+            .unwrap_or(0)
+    }
+
+    pub fn double_clicked(&self, button: &MouseButton) -> bool {
+        self.click_count(button) == 2
+    }
+
+    pub fn is_dragging(&self, button: &MouseButton) -> bool {
+        self.buttons
+            .get(button)
+            .is_some_and(|gesture| gesture.dragging)
+    }
+
+    /// Position the current drag of the button started from.
+    pub fn drag_origin(&self, button: &MouseButton) -> Option<Vec2> {
+        let gesture = self.buttons.get(button)?;
+        gesture.press_pos.filter(|_| gesture.dragging)
+    }
+
+    /// Movement of the cursor during the frame while dragging with the button. On the frame the
+    /// drag starts, this includes the movement from the position the button was pressed at, so
+    /// that the deltas add up to the whole drag.
+    pub fn drag_delta(&self, button: &MouseButton) -> Vec2 {
+        match self.buttons.get(button) {
+            Some(gesture) if gesture.dragging => self.pos.truncate() - gesture.drag_from,
+            _ => Vec2::ZERO,
+        }
+    }
+
+    fn press(&mut self, button: MouseButton, now: Instant) {
+        self.state.set(button, now);
+        let pos = self.pos.truncate();
+        let settings = self.gesture_settings;
+        let gesture = self.buttons.entry(button).or_default();
+        let count = match gesture.last_click {
+            Some((time, last_pos, count))
+                if now.duration_since(time) <= settings.multi_click_time
+                    && last_pos.distance(pos) <= settings.multi_click_distance =>
+            {
+                count + 1
+            }
+            _ => 1,
+        };
+        gesture.press_pos = Some(pos);
+        gesture.dragging = false;
+        gesture.last_click = Some((now, pos, count));
+        self.gestures
+            .push(MouseGesture::Click { button, count, pos });
+    }
+
+    fn release(&mut self, button: MouseButton) {
+        self.state.clear(button);
+        let Some(gesture) = self.buttons.get_mut(&button) else { return; };
+        if let Some(origin) = gesture.press_pos.take() {
+            if gesture.dragging {
+                self.gestures.push(MouseGesture::DragEnd {
+                    button,
+                    origin,
+                    pos: self.pos.truncate(),
+                });
+            }
+        }
+        gesture.dragging = false;
+    }
+
+    fn move_to(&mut self, pos: Vec2) {
+        self.pos = pos.extend(self.pos.z);
+        let threshold = self.gesture_settings.drag_threshold;
+        for (&button, gesture) in &mut self.buttons {
+            let Some(origin) = gesture.press_pos else { continue; };
+            if !gesture.dragging && origin.distance(pos) > threshold {
+                gesture.dragging = true;
+                gesture.drag_from = origin;
+                // Dragging interrupts click sequences
+                gesture.last_click = None;
+                self.gestures
+                    .push(MouseGesture::DragStart { button, origin });
+            }
+        }
+    }
+}
+
+/// Finger or pen in contact with a touch screen or tablet. Positions are in window pixels.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Touch {
+    pub pos: Vec2,
+    /// Position at the start of the frame, or where the touch started if it started during the
+    /// frame.
+    pub prev_pos: Vec2,
+    pub start_pos: Vec2,
+    /// Pressure between 0 and 1, for pens and pressure-sensitive screens.
+    pub pressure: Option<f32>,
+    /// Angle between a pen and the surface, in radians, from 0 when lying flat to π/2 when upright.
+    /// Only reported by some platforms.
+    pub altitude: Option<f32>,
+}
+
+impl Touch {
+    pub fn delta(&self) -> Vec2 {
+        self.pos - self.prev_pos
+    }
+
+    fn update_force(&mut self, force: Option<Force>) {
+        self.pressure = force.map(|force| force.normalized() as f32);
+        self.altitude = match force {
+            Some(Force::Calibrated { altitude_angle, .. }) => altitude_angle.map(|a| a as f32),
+            _ => None,
+        };
+    }
+}
+
+/// Movement of two fingers during the frame.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PinchGesture {
+    /// Movement of the point between the fingers, in pixels.
+    pub pan: Vec2,
+    /// Ratio of the distance between the fingers to the distance at the start of the frame,
+    /// greater than 1 when spreading them apart.
+    pub zoom: f32,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct TouchInput {
+    /// Touch IDs, which are reused by the system once touches end.
+    pub state: State<u64>,
+    touches: BTreeMap<u64, Touch>,
+}
+
+impl TouchInput {
+    pub fn begin_frame(&mut self) {
+        self.state.begin_frame();
+        for touch in self.touches.values_mut() {
+            touch.prev_pos = touch.pos;
+        }
+    }
+
+    /// Current touches, ordered by ID.
+    pub fn touches(&self) -> impl '_ + Iterator<Item = (u64, &Touch)> {
+        self.touches.iter().map(|(&id, touch)| (id, touch))
+    }
+
+    pub fn get(&self, id: u64) -> Option<&Touch> {
+        self.touches.get(&id)
+    }
+
+    pub fn count(&self) -> usize {
+        self.touches.len()
+    }
+
+    /// Pinch of the first two touches, when there are at least two.
+    pub fn pinch(&self) -> Option<PinchGesture> {
+        let mut touches = self.touches.values();
+        let (a, b) = (touches.next()?, touches.next()?);
+        let prev_distance = a.prev_pos.distance(b.prev_pos);
+        let zoom = if prev_distance > 1. {
+            a.pos.distance(b.pos) / prev_distance
+        } else {
+            1.
+        };
+        Some(PinchGesture {
+            pan: (a.delta() + b.delta()) / 2.,
+            zoom,
+        })
+    }
+
+    fn apply(&mut self, touch: winit::event::Touch, now: Instant) {
+        let pos = vec2(touch.location.x as _, touch.location.y as _);
+        match touch.phase {
+            TouchPhase::Started => {
+                let mut new_touch = Touch {
+                    pos,
+                    prev_pos: pos,
+                    start_pos: pos,
+                    pressure: None,
+                    altitude: None,
+                };
+                new_touch.update_force(touch.force);
+                self.touches.insert(touch.id, new_touch);
+                self.state.set(touch.id, now);
+            }
+            TouchPhase::Moved => {
+                if let Some(existing) = self.touches.get_mut(&touch.id) {
+                    existing.pos = pos;
+                    existing.update_force(touch.force);
+                }
+            }
+            TouchPhase::Ended | TouchPhase::Cancelled => {
+                self.touches.remove(&touch.id);
+                self.state.clear(touch.id);
+            }
+        }
+    }
+}
+
+/// Window the text is typed into, which has to allow input methods (IME) for them to compose text.
+pub trait TextInputWindow {
+    fn set_ime_allowed(&self, allowed: bool);
+}
+
+impl TextInputWindow for Window {
+    fn set_ime_allowed(&self, allowed: bool) {
+        Window::set_ime_allowed(self, allowed);
     }
 }
 
@@ -94,19 +468,41 @@ impl MouseInput {
 pub struct Input {
     pub keyboard: KeyboardInput,
     pub mouse: MouseInput,
+    pub touch: TouchInput,
+    text_input: bool,
 }
 
 impl Input {
     pub fn begin_frame(&mut self) {
         self.mouse.begin_frame();
         self.keyboard.begin_frame();
+        self.touch.begin_frame();
+    }
+
+    /// Whether typed characters are collected into [`KeyboardInput::text`].
+    pub fn text_input(&self) -> bool {
+        self.text_input
+    }
+
+    /// Enable or disable text input mode, allowing input methods on the window while enabled.
+    /// While enabled, typed characters are collected and key presses are only reported through
+    /// [`KeyboardInput::typed_keys`], so that typing doesn't also trigger key bindings. Keys held
+    /// down when enabling it are released.
+    pub fn set_text_input(&mut self, window: &impl TextInputWindow, enabled: bool) {
+        if enabled != self.text_input {
+            window.set_ime_allowed(enabled);
+        }
+        if enabled && !self.text_input {
+            self.keyboard.state.clear_all();
+        }
+        self.text_input = enabled;
     }
 
     pub fn apply_event<'ev>(&mut self, event: WindowEvent<'ev>) -> Option<WindowEvent<'ev>> {
         match event {
             WindowEvent::MouseInput { state, button, .. } => match state {
-                ElementState::Pressed => self.mouse.state.set(button),
-                ElementState::Released => self.mouse.state.clear(button),
+                ElementState::Pressed => self.mouse.press(button, Instant::now()),
+                ElementState::Released => self.mouse.release(button),
             },
             WindowEvent::KeyboardInput {
                 input:
@@ -117,9 +513,17 @@ impl Input {
                     },
                 ..
             } => match state {
-                ElementState::Pressed => self.keyboard.state.set(vk),
-                ElementState::Released => self.keyboard.state.clear(vk),
+                ElementState::Pressed => self.keyboard.press(vk, self.text_input, Instant::now()),
+                ElementState::Released => self.keyboard.release(vk),
             },
+            WindowEvent::ReceivedCharacter(c) if self.text_input => {
+                if !c.is_control() {
+                    self.keyboard.text.push(c);
+                }
+            }
+            WindowEvent::Ime(Ime::Commit(text)) if self.text_input => {
+                self.keyboard.text.push_str(&text);
+            }
             WindowEvent::MouseWheel { delta, .. } => {
                 self.mouse.pos.z += match delta {
                     MouseScrollDelta::LineDelta(_, y) => 10. * y,
@@ -127,10 +531,177 @@ impl Input {
                 }
             }
             WindowEvent::CursorMoved { position, .. } => {
-                self.mouse.pos = vec3(position.x as _, position.y as _, self.mouse.pos.z);
+                self.mouse.move_to(vec2(position.x as _, position.y as _));
             }
+            WindowEvent::Touch(touch) => self.touch.apply(touch, Instant::now()),
             event => return Some(event),
         }
         None
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_typed_as_text_are_not_released() {
+        let mut keyboard = KeyboardInput::default();
+        keyboard.press(VirtualKeyCode::A, true, Instant::now());
+        keyboard.release(VirtualKeyCode::A);
+        assert!(keyboard.just_typed(&VirtualKeyCode::A));
+        assert!(!keyboard.state.is_pressed(&VirtualKeyCode::A));
+        assert!(!keyboard.state.just_released(&VirtualKeyCode::A));
+    }
+
+    #[test]
+    fn keys_held_in_text_input_mode_repeat() {
+        let mut keyboard = KeyboardInput::default();
+        keyboard.press(VirtualKeyCode::Back, true, Instant::now());
+        assert!(!keyboard.is_repeated(&VirtualKeyCode::Back));
+        keyboard.press(VirtualKeyCode::Back, true, Instant::now());
+        assert!(keyboard.is_repeated(&VirtualKeyCode::Back));
+        assert_eq!(keyboard.typed_keys(), [VirtualKeyCode::Back; 2]);
+        keyboard.release(VirtualKeyCode::Back);
+        keyboard.begin_frame();
+        keyboard.press(VirtualKeyCode::Back, true, Instant::now());
+        assert!(!keyboard.is_repeated(&VirtualKeyCode::Back));
+    }
+
+    #[test]
+    fn text_input_mode_allows_ime() {
+        #[derive(Default)]
+        struct ImeWindow(std::cell::Cell<Option<bool>>);
+
+        impl TextInputWindow for ImeWindow {
+            fn set_ime_allowed(&self, allowed: bool) {
+                self.0.set(Some(allowed));
+            }
+        }
+
+        let window = ImeWindow::default();
+        let mut input = Input::default();
+        input.set_text_input(&window, true);
+        assert_eq!(window.0.take(), Some(true));
+        input.set_text_input(&window, true);
+        assert_eq!(window.0.take(), None);
+        input.set_text_input(&window, false);
+        assert_eq!(window.0.take(), Some(false));
+    }
+
+    #[test]
+    fn frame_changes_last_one_frame() {
+        let mut state = State::default();
+        let start = Instant::now();
+        state.begin_frame_at(start);
+        state.set(1, start);
+        assert!(state.just_pressed(&1) && state.is_pressed(&1));
+        state.begin_frame_at(start);
+        assert!(!state.just_pressed(&1) && state.is_pressed(&1));
+        state.clear(1);
+        assert!(state.just_released(&1) && !state.is_pressed(&1));
+        state.begin_frame_at(start);
+        assert!(!state.just_released(&1));
+    }
+
+    #[test]
+    fn taps_within_a_frame_are_reported() {
+        let mut state = State::default();
+        state.begin_frame_at(Instant::now());
+        state.set(1, Instant::now());
+        state.clear(1);
+        assert!(state.just_pressed(&1) && state.just_released(&1));
+        assert!(!state.any_pressed());
+        // Releasing a value which wasn't pressed doesn't count
+        state.clear(2);
+        assert!(!state.just_released(&2));
+    }
+
+    #[test]
+    fn measures_press_duration_at_frame_start() {
+        let mut state = State::default();
+        let start = Instant::now();
+        state.begin_frame_at(start);
+        assert_eq!(state.pressed_duration(&1), None);
+        state.set(1, start);
+        // Repeats don't restart the duration
+        assert!(!state.set(1, start + Duration::from_millis(100)));
+        assert!(state.any_pressed());
+        state.begin_frame_at(start + Duration::from_millis(250));
+        assert_eq!(state.pressed_duration(&1), Some(Duration::from_millis(250)));
+    }
+
+    #[test]
+    fn pinches_two_touches() {
+        let mut touch = TouchInput::default();
+        let mut a = Touch {
+            pos: vec2(100., 100.),
+            prev_pos: vec2(100., 100.),
+            start_pos: vec2(100., 100.),
+            pressure: None,
+            altitude: None,
+        };
+        let mut b = Touch {
+            pos: vec2(200., 100.),
+            prev_pos: vec2(200., 100.),
+            ..a
+        };
+        touch.touches.insert(0, a);
+        assert_eq!(touch.pinch(), None);
+        a.pos = vec2(60., 110.);
+        b.pos = vec2(260., 110.);
+        touch.touches.insert(0, a);
+        touch.touches.insert(1, b);
+        assert_eq!(
+            touch.pinch(),
+            Some(PinchGesture {
+                pan: vec2(10., 10.),
+                zoom: 2.,
+            })
+        );
+    }
+
+    #[test]
+    fn counts_consecutive_clicks() {
+        let mut mouse = MouseInput::default();
+        let start = Instant::now();
+        mouse.press(MouseButton::Left, start);
+        mouse.release(MouseButton::Left);
+        assert_eq!(mouse.click_count(&MouseButton::Left), 1);
+        mouse.begin_frame();
+        mouse.press(MouseButton::Left, start + Duration::from_millis(200));
+        assert!(mouse.double_clicked(&MouseButton::Left));
+        mouse.release(MouseButton::Left);
+        mouse.begin_frame();
+        assert_eq!(mouse.click_count(&MouseButton::Left), 0);
+        // Too late to continue the sequence
+        mouse.press(MouseButton::Left, start + Duration::from_secs(1));
+        assert_eq!(mouse.click_count(&MouseButton::Left), 1);
+    }
+
+    #[test]
+    fn drags_past_threshold() {
+        let mut mouse = MouseInput::default();
+        mouse.move_to(vec2(10., 10.));
+        mouse.press(MouseButton::Right, Instant::now());
+        mouse.move_to(vec2(12., 10.));
+        assert!(!mouse.is_dragging(&MouseButton::Right));
+        mouse.begin_frame();
+        mouse.move_to(vec2(20., 10.));
+        assert!(mouse.is_dragging(&MouseButton::Right));
+        assert_eq!(mouse.drag_delta(&MouseButton::Right), vec2(10., 0.));
+        mouse.begin_frame();
+        mouse.move_to(vec2(25., 15.));
+        assert_eq!(mouse.drag_delta(&MouseButton::Right), vec2(5., 5.));
+        mouse.release(MouseButton::Right);
+        assert_eq!(
+            mouse.gestures(),
+            [MouseGesture::DragEnd {
+                button: MouseButton::Right,
+                origin: vec2(10., 10.),
+                pos: vec2(25., 15.),
+            }]
+        );
+        assert!(!mouse.is_dragging(&MouseButton::Right));
+    }
+}
@@ -0,0 +1,349 @@
+//! Packing of rectangles into a texture atlas, so that many small images (sprites, icons, glyphs)
+//! share a single texture and are drawn together.
+//!
+//! The packer only tracks the layout of the atlas on the CPU; uploading the pixels is left to the
+//! owner of the texture. It uses the guillotine algorithm: each allocation is cut out of the free
+//! rectangle fitting it best, splitting the rest in two. Removed allocations give their space back,
+//! merged with the free rectangles next to them.
+
+use std::collections::HashMap;
+
+use glam::{UVec2, Vec4};
+
+/// Identifier of an allocation in an [`AtlasPacker`], never reused by the same packer.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct AtlasId(u64);
+
+/// Rectangle of texels of the atlas.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct AtlasRect {
+    pub min: UVec2,
+    pub size: UVec2,
+}
+
+impl AtlasRect {
+    pub fn new(min: UVec2, size: UVec2) -> Self {
+        Self { min, size }
+    }
+
+    pub fn max(&self) -> UVec2 {
+        self.min + self.size
+    }
+
+    pub fn area(&self) -> u64 {
+        self.size.x as u64 * self.size.y as u64
+    }
+
+    pub fn fits(&self, size: UVec2) -> bool {
+        size.cmple(self.size).all()
+    }
+
+    pub fn overlaps(&self, other: &Self) -> bool {
+        self.min.cmplt(other.max()).all() && other.min.cmplt(self.max()).all()
+    }
+
+    /// Texture coordinates of the rectangle in an atlas of `atlas_size` texels, as the minimum
+    /// coordinates followed by the maximum ones.
+    pub fn uv_rect(&self, atlas_size: UVec2) -> Vec4 {
+        let size = atlas_size.as_vec2();
+        let min = self.min.as_vec2() / size;
+        let max = self.max().as_vec2() / size;
+        Vec4::new(min.x, min.y, max.x, max.y)
+    }
+
+    /// Merge with `other` when together they form a rectangle, i.e. they share a whole side.
+    fn merge(&self, other: &Self) -> Option<Self> {
+        let same_columns = self.min.x == other.min.x && self.size.x == other.size.x;
+        let same_rows = self.min.y == other.min.y && self.size.y == other.size.y;
+        let (first, second) = if self.min.cmple(other.min).all() {
+            (self, other)
+        } else {
+            (other, self)
+        };
+        if same_columns && first.max().y == second.min.y {
+            Some(Self::new(
+                first.min,
+                UVec2::new(first.size.x, first.size.y + second.size.y),
+            ))
+        } else if same_rows && first.max().x == second.min.x {
+            Some(Self::new(
+                first.min,
+                UVec2::new(first.size.x + second.size.x, first.size.y),
+            ))
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Allocation {
+    /// Allocated space, including the padding.
+    slot: AtlasRect,
+    size: UVec2,
+    last_used: u64,
+}
+
+/// Layout of the allocations in an atlas, with runtime insertion and eviction of the least
+/// recently used allocations.
+#[derive(Debug, Clone)]
+pub struct AtlasPacker {
+    size: UVec2,
+    padding: u32,
+    free: Vec<AtlasRect>,
+    allocations: HashMap<AtlasId, Allocation>,
+    next_id: u64,
+    frame: u64,
+}
+
+impl AtlasPacker {
+    /// Empty atlas of `size` texels.
+    pub fn new(size: UVec2) -> Self {
+        Self {
+            size,
+            padding: 0,
+            free: vec![AtlasRect::new(UVec2::ZERO, size)],
+            allocations: HashMap::new(),
+            next_id: 0,
+            frame: 0,
+        }
+    }
+
+    /// Leave `padding` texels after each allocation, so that filtering does not bleed the texels
+    /// of neighbouring allocations.
+    pub fn with_padding(mut self, padding: u32) -> Self {
+        self.padding = padding;
+        self
+    }
+
+    pub fn size(&self) -> UVec2 {
+        self.size
+    }
+
+    pub fn len(&self) -> usize {
+        self.allocations.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.allocations.is_empty()
+    }
+
+    /// Allocate a rectangle of `size` texels, or return `None` if there is no room left for it.
+    pub fn insert(&mut self, size: UVec2) -> Option<(AtlasId, AtlasRect)> {
+        if size.cmpeq(UVec2::ZERO).any() {
+            return None;
+        }
+        let padded = size + self.padding;
+        // Best area fit, keeping the free rectangles as large as possible
+        let (index, free) = self
+            .free
+            .iter()
+            .copied()
+            .enumerate()
+            .filter(|(_, free)| free.fits(padded))
+            .min_by_key(|(_, free)| free.area())?;
+        self.free.swap_remove(index);
+        let slot = AtlasRect::new(free.min, padded);
+        let rest = free.size - padded;
+        // Split along the shorter leftover side, leaving the larger rectangle whole
+        let (right, below) = if rest.x < rest.y {
+            (
+                AtlasRect::new(
+                    UVec2::new(slot.max().x, free.min.y),
+                    UVec2::new(rest.x, padded.y),
+                ),
+                AtlasRect::new(
+                    UVec2::new(free.min.x, slot.max().y),
+                    UVec2::new(free.size.x, rest.y),
+                ),
+            )
+        } else {
+            (
+                AtlasRect::new(
+                    UVec2::new(slot.max().x, free.min.y),
+                    UVec2::new(rest.x, free.size.y),
+                ),
+                AtlasRect::new(
+                    UVec2::new(free.min.x, slot.max().y),
+                    UVec2::new(padded.x, rest.y),
+                ),
+            )
+        };
+        self.free
+            .extend([right, below].into_iter().filter(|rect| rect.area() > 0));
+
+        let id = AtlasId(self.next_id);
+        self.next_id += 1;
+        self.allocations.insert(
+            id,
+            Allocation {
+                slot,
+                size,
+                last_used: self.frame,
+            },
+        );
+        Some((id, AtlasRect::new(slot.min, size)))
+    }
+
+    /// Allocate a rectangle of `size` texels, evicting the least recently used allocations not
+    /// used this frame until it fits. Returns the evicted allocations along with the new one, or
+    /// `None` without evicting anything if it cannot fit.
+    pub fn insert_evicting(&mut self, size: UVec2) -> Option<(AtlasId, AtlasRect, Vec<AtlasId>)> {
+        if let Some((id, rect)) = self.insert(size) {
+            return Some((id, rect, vec![]));
+        }
+        let mut candidates = self
+            .allocations
+            .iter()
+            .filter(|(_, allocation)| allocation.last_used < self.frame)
+            .map(|(id, allocation)| (allocation.last_used, *id))
+            .collect::<Vec<_>>();
+        candidates.sort_unstable();
+
+        // Evict on a copy, to leave the atlas untouched when the rectangle never fits
+        let mut packer = self.clone();
+        let mut evicted = vec![];
+        for (_, id) in candidates {
+            packer.remove(id);
+            evicted.push(id);
+            if let Some((new_id, rect)) = packer.insert(size) {
+                *self = packer;
+                return Some((new_id, rect, evicted));
+            }
+        }
+        None
+    }
+
+    /// Free the allocation, returning its rectangle if it was allocated.
+    pub fn remove(&mut self, id: AtlasId) -> Option<AtlasRect> {
+        let allocation = self.allocations.remove(&id)?;
+        if self.allocations.is_empty() {
+            self.free = vec![AtlasRect::new(UVec2::ZERO, self.size)];
+        } else {
+            self.free_slot(allocation.slot);
+        }
+        Some(AtlasRect::new(allocation.slot.min, allocation.size))
+    }
+
+    pub fn clear(&mut self) {
+        self.allocations.clear();
+        self.free = vec![AtlasRect::new(UVec2::ZERO, self.size)];
+    }
+
+    pub fn get(&self, id: AtlasId) -> Option<AtlasRect> {
+        let allocation = self.allocations.get(&id)?;
+        Some(AtlasRect::new(allocation.slot.min, allocation.size))
+    }
+
+    /// Texture coordinates of the allocation, see [`AtlasRect::uv_rect`].
+    pub fn uv_rect(&self, id: AtlasId) -> Option<Vec4> {
+        Some(self.get(id)?.uv_rect(self.size))
+    }
+
+    /// Mark the allocation as used this frame, protecting it from eviction.
+    pub fn touch(&mut self, id: AtlasId) {
+        if let Some(allocation) = self.allocations.get_mut(&id) {
+            allocation.last_used = self.frame;
+        }
+    }
+
+    /// Start a new frame; allocations not touched since become evictable.
+    pub fn next_frame(&mut self) {
+        self.frame += 1;
+    }
+
+    /// Allocated rectangles, without their padding.
+    pub fn allocated(&self) -> impl '_ + Iterator<Item = (AtlasId, AtlasRect)> {
+        self.allocations
+            .iter()
+            .map(|(id, allocation)| (*id, AtlasRect::new(allocation.slot.min, allocation.size)))
+    }
+
+    /// Free rectangles, e.g. to show the fragmentation of the atlas.
+    pub fn free_rects(&self) -> &[AtlasRect] {
+        &self.free
+    }
+
+    /// Fraction of the atlas area taken by the allocations, without their padding.
+    pub fn occupancy(&self) -> f32 {
+        let used = self.allocated().map(|(_, rect)| rect.area()).sum::<u64>();
+        used as f32 / AtlasRect::new(UVec2::ZERO, self.size).area() as f32
+    }
+
+    fn free_slot(&mut self, mut rect: AtlasRect) {
+        // Grow the freed rectangle until no free neighbour shares a whole side with it
+        while let Some((index, merged)) = self
+            .free
+            .iter()
+            .enumerate()
+            .find_map(|(i, free)| Some((i, rect.merge(free)?)))
+        {
+            self.free.swap_remove(index);
+            rect = merged;
+        }
+        self.free.push(rect);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allocations_do_not_overlap_and_space_is_reclaimed() {
+        let mut packer = AtlasPacker::new(UVec2::splat(64)).with_padding(1);
+        let mut ids = vec![];
+        while let Some((id, rect)) = packer.insert(UVec2::new(15, 7)) {
+            assert!(rect.max().cmple(packer.size()).all());
+            ids.push(id);
+        }
+        assert_eq!(ids.len(), 32);
+        let rects = packer.allocated().map(|(_, rect)| rect).collect::<Vec<_>>();
+        for (i, a) in rects.iter().enumerate() {
+            assert!(rects[i + 1..].iter().all(|b| !a.overlaps(b)));
+        }
+
+        // Freeing two vertical neighbours makes room for a taller rectangle
+        let below = |a: &AtlasRect, b: &AtlasRect| a.min.x == b.min.x && a.max().y + 1 == b.min.y;
+        let (top, bottom) = ids
+            .iter()
+            .flat_map(|a| ids.iter().map(move |b| (*a, *b)))
+            .find(|(a, b)| below(&packer.get(*a).unwrap(), &packer.get(*b).unwrap()))
+            .unwrap();
+        assert_eq!(packer.insert(UVec2::new(15, 15)), None);
+        packer.remove(top);
+        packer.remove(bottom);
+        let (tall, _) = packer.insert(UVec2::new(15, 15)).unwrap();
+
+        for id in ids.into_iter().chain([tall]) {
+            packer.remove(id);
+        }
+        // The padding takes a texel on each axis
+        assert!(packer.insert(UVec2::splat(63)).is_some());
+        assert_eq!(packer.occupancy(), (63. * 63.) / (64. * 64.));
+        packer.clear();
+        assert_eq!(packer.occupancy(), 0.);
+    }
+
+    #[test]
+    fn evicts_least_recently_used_first() {
+        let mut packer = AtlasPacker::new(UVec2::new(32, 8));
+        let (a, _) = packer.insert(UVec2::splat(8)).unwrap();
+        let (b, _) = packer.insert(UVec2::splat(8)).unwrap();
+        let (c, _) = packer.insert(UVec2::new(16, 8)).unwrap();
+        packer.next_frame();
+        packer.touch(a);
+        packer.touch(c);
+        // Only `b` is evictable this frame, which is not enough room
+        assert_eq!(packer.insert_evicting(UVec2::new(16, 8)), None);
+        assert_eq!(packer.len(), 3);
+
+        packer.next_frame();
+        packer.touch(c);
+        let (_, rect, evicted) = packer.insert_evicting(UVec2::new(16, 8)).unwrap();
+        assert_eq!(rect.min, UVec2::ZERO);
+        assert_eq!(evicted.len(), 2);
+        assert!(evicted.contains(&a) && evicted.contains(&b));
+        assert_eq!(packer.uv_rect(c), Some(Vec4::new(0.5, 0., 1., 1.)));
+    }
+}
@@ -2,11 +2,60 @@ use std::ops::Range;
 
 use crevice::std140::AsStd140;
 use eyre::Result;
-use glam::{vec4, Mat4, Vec2, Vec3, Vec4};
+use glam::{vec2, vec4, DMat3, DMat4, Mat4, Vec2, Vec3, Vec4, Vec4Swizzles};
 
-use violette::buffer::{BufferUsageHint, UniformBuffer};
+use violette::{
+    buffer::{BufferUsageHint, UniformBuffer},
+    framebuffer::DepthTestFunction,
+};
 
-use crate::transform::Transform;
+use crate::{
+    gl_ext::{self, ClipDepth},
+    transform::Transform,
+};
+
+/// Mapping of distances to the depth buffer.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
+pub enum DepthMode {
+    /// OpenGL convention, from -1 at the near plane to 1 at the far plane in clip space. Most of
+    /// the precision is spent close to the near plane.
+    #[default]
+    Standard,
+    /// From 1 at the near plane to 0 at the far plane, which spreads the precision of float depth
+    /// buffers evenly over distances. Needs [`reversed_depth_supported`].
+    Reversed,
+    /// Reversed depth with the far plane at infinity, for perspective projections; orthographic
+    /// projections keep their far plane.
+    ReversedInfinite,
+}
+
+impl DepthMode {
+    pub fn is_reversed(self) -> bool {
+        !matches!(self, Self::Standard)
+    }
+}
+
+/// Whether the OpenGL context can render with reversed depth, which needs `glClipControl` (OpenGL
+/// 4.5 or `ARB_clip_control`).
+pub fn reversed_depth_supported() -> bool {
+    gl_ext::clip_control_supported()
+}
+
+/// Set the clip space depth range and the depth clear value for drawing with projections using
+/// reversed depth or not.
+pub fn set_depth_convention(reversed: bool) {
+    if !reversed_depth_supported() {
+        return;
+    }
+    if reversed {
+        gl_ext::set_clip_depth(ClipDepth::ZeroToOne);
+        gl_ext::set_clear_depth(0.);
+    } else {
+        gl_ext::set_clip_depth(ClipDepth::NegativeOneToOne);
+        gl_ext::set_clear_depth(1.);
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct Projection {
@@ -14,6 +63,10 @@ pub struct Projection {
     pub width: f32,
     pub height: f32,
     pub zrange: Range<f32>,
+    /// Vertical extent of the view when using an orthographic projection instead of a perspective
+    /// one; `fovy` is then ignored.
+    pub orthographic: Option<f32>,
+    pub depth: DepthMode,
 }
 
 impl Default for Projection {
@@ -23,6 +76,8 @@ impl Default for Projection {
             zrange: 0.001..1000.0,
             width: 1.,
             height: 1.,
+            orthographic: None,
+            depth: DepthMode::Standard,
         }
     }
 }
@@ -34,12 +89,29 @@ impl Projection {
     }
 
     pub fn matrix(&self) -> Mat4 {
-        Mat4::perspective_rh_gl(
-            self.fovy,
-            self.width / self.height,
-            self.zrange.start,
-            self.zrange.end,
-        )
+        let (near, far) = (self.zrange.start, self.zrange.end);
+        let aspect_ratio = self.width / self.height;
+        if let Some(height) = self.orthographic {
+            let half_height = height / 2.;
+            let half_width = half_height * aspect_ratio;
+            let (left, right, bottom, top) = (-half_width, half_width, -half_height, half_height);
+            return match self.depth {
+                DepthMode::Standard => {
+                    Mat4::orthographic_rh_gl(left, right, bottom, top, near, far)
+                }
+                // Swapping the planes of the [0, 1] projection reverses the depth
+                DepthMode::Reversed | DepthMode::ReversedInfinite => {
+                    Mat4::orthographic_rh(left, right, bottom, top, far, near)
+                }
+            };
+        }
+        match self.depth {
+            DepthMode::Standard => Mat4::perspective_rh_gl(self.fovy, aspect_ratio, near, far),
+            DepthMode::Reversed => Mat4::perspective_rh(self.fovy, aspect_ratio, far, near),
+            DepthMode::ReversedInfinite => {
+                Mat4::perspective_infinite_reverse_rh(self.fovy, aspect_ratio, near)
+            }
+        }
     }
 }
 
@@ -57,8 +129,15 @@ pub struct ViewUniform {
     pub mat_proj: Mat4,
     pub inv_view: Mat4,
     pub inv_proj: Mat4,
+    /// View matrix for positions relative to `origin`, see [`Self::set_origin`].
+    pub mat_view_relative: Mat4,
     pub viewport: Vec4,
+    /// Position of the camera, relative to `origin` like the positions of the G-Buffer.
     pub camera_pos: Vec3,
+    /// 1 when the projection uses reversed depth, 0 otherwise.
+    pub reverse_z: f32,
+    /// World position which model matrices, and the positions shaded from them, are relative to.
+    pub origin: Vec3,
 }
 
 impl ViewUniform {
@@ -69,7 +148,51 @@ impl ViewUniform {
         self.inv_view = self.mat_view.inverse();
         self.inv_proj = self.mat_proj.inverse();
         self.viewport = vec4(0., 0., camera.projection.width, camera.projection.height);
-        self.camera_pos = camera.transform.rotation * camera.transform.position;
+        self.reverse_z = reverse_z(&camera.projection);
+        self.set_origin(Vec3::ZERO);
+    }
+
+    /// Draw meshes with model matrices relative to `origin`, as given by
+    /// [`Transform::relative_to`], and shade them with positions relative to it. With an origin
+    /// close to the camera, vertices far from the world origin keep their precision. Call again
+    /// after changing the view matrix.
+    pub fn set_origin(&mut self, origin: Vec3) {
+        let view = self.mat_view.as_dmat4();
+        let rotation = DMat3::from_mat4(view);
+        // Only the offset of the camera from the origin, small next to both, reaches the GPU
+        let eye = -(rotation.inverse() * view.w_axis.truncate());
+        let relative_eye = eye - origin.as_dvec3();
+        let translation = -(rotation * relative_eye);
+        self.mat_view_relative = DMat4::from_cols(
+            view.x_axis,
+            view.y_axis,
+            view.z_axis,
+            translation.extend(1.),
+        )
+        .as_mat4();
+        self.camera_pos = relative_eye.as_vec3();
+        self.origin = origin;
+    }
+
+    pub fn reversed_depth(&self) -> bool {
+        self.reverse_z > 0.5
+    }
+
+    /// Depth test keeping the fragments closest to the camera.
+    pub fn depth_test(&self) -> DepthTestFunction {
+        if self.reversed_depth() {
+            DepthTestFunction::Greater
+        } else {
+            DepthTestFunction::Less
+        }
+    }
+}
+
+fn reverse_z(projection: &Projection) -> f32 {
+    if projection.depth.is_reversed() {
+        1.
+    } else {
+        0.
     }
 }
 
@@ -96,14 +219,19 @@ impl From<Camera> for ViewUniform {
     fn from(value: Camera) -> Self {
         let view = value.transform.matrix();
         let proj = value.projection.matrix();
-        Self {
+        let mut this = Self {
             mat_view: view,
             mat_proj: proj,
             inv_view: view.inverse(),
             inv_proj: proj.inverse(),
+            mat_view_relative: view,
             viewport: vec4(0., 0., value.projection.width, value.projection.height),
-            camera_pos: value.transform.position,
-        }
+            camera_pos: Vec3::ZERO,
+            reverse_z: reverse_z(&value.projection),
+            origin: Vec3::ZERO,
+        };
+        this.set_origin(Vec3::ZERO);
+        this
     }
 }
 
@@ -119,4 +247,211 @@ impl ViewUniform {
         slice.set(0, &self.as_std140())?;
         Ok(())
     }
+
+    /// Replace the projection matrix, keeping its inverse up to date.
+    pub fn set_projection(&mut self, mat_proj: Mat4) {
+        self.mat_proj = mat_proj;
+        self.inv_proj = mat_proj.inverse();
+    }
+
+    /// Shift the image by `offset` pixels, e.g. a sub-pixel offset from [`halton_jitter`] for
+    /// temporal anti-aliasing. Works for both perspective and orthographic projections.
+    pub fn jitter(&mut self, offset: Vec2) {
+        let ndc = 2. * offset / self.viewport.zw();
+        self.set_projection(Mat4::from_translation(ndc.extend(0.)) * self.mat_proj);
+    }
+
+    /// Offset in screen UVs of an image jittered by `offset` pixels; subtract it from the UVs to
+    /// sample the jittered image as if it was not jittered.
+    pub fn jitter_uv_offset(&self, offset: Vec2) -> Vec2 {
+        offset / self.viewport.zw()
+    }
+
+    /// Replace the near plane of the projection with a world space plane `(normal, d)`, clipping
+    /// everything on its negative side, e.g. objects under the surface of planar reflections.
+    pub fn clip_near_plane(&mut self, plane: Vec4) {
+        let view_plane = self.inv_view.transpose() * plane;
+        let mat_proj = if self.reversed_depth() {
+            oblique_projection_reversed(self.mat_proj, view_plane)
+        } else {
+            oblique_projection(self.mat_proj, view_plane)
+        };
+        self.set_projection(mat_proj);
+    }
+}
+
+/// Replace the near plane of the projection with the view-space clip plane, see "Oblique View
+/// Frustum Depth Projection and Clipping" by Eric Lengyel.
+pub fn oblique_projection(proj: Mat4, plane: Vec4) -> Mat4 {
+    let corner = proj.inverse() * Vec4::new(plane.x.signum(), plane.y.signum(), 1., 1.);
+    let plane = plane * (2. / plane.dot(corner));
+    let mut rows = proj.transpose();
+    rows.z_axis = plane - rows.w_axis;
+    rows.transpose()
+}
+
+/// [`oblique_projection`] for projections with reversed depth, where the near plane is at 1 and
+/// the far plane at 0 in clip space.
+pub fn oblique_projection_reversed(proj: Mat4, plane: Vec4) -> Mat4 {
+    let corner = proj.inverse() * Vec4::new(plane.x.signum(), plane.y.signum(), 0., 1.);
+    let plane = plane / plane.dot(corner);
+    let mut rows = proj.transpose();
+    rows.z_axis = rows.w_axis - plane;
+    rows.transpose()
+}
+
+/// Element `index` of the Halton low-discrepancy sequence in `base`, in `[0, 1)`.
+pub fn halton(mut index: u32, base: u32) -> f32 {
+    let mut fraction = 1.;
+    let mut result = 0.;
+    while index > 0 {
+        fraction /= base as f32;
+        result += fraction * (index % base) as f32;
+        index /= base;
+    }
+    result
+}
+
+/// Sub-pixel offset of `frame` in a jitter pattern repeating every `period` frames, within half a
+/// pixel of the center, from the Halton (2, 3) sequence. See [`ViewUniform::jitter`].
+pub fn halton_jitter(frame: u64, period: u32) -> Vec2 {
+    let index = (frame % period.max(1) as u64) as u32 + 1;
+    vec2(halton(index, 2), halton(index, 3)) - 0.5
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Quat;
+
+    use super::*;
+
+    fn view() -> ViewUniform {
+        ViewUniform::new(&Camera {
+            transform: Transform::translation(Vec3::new(0., 0., -5.)),
+            projection: Projection {
+                width: 200.,
+                height: 100.,
+                ..Default::default()
+            },
+        })
+    }
+
+    #[test]
+    fn jitter_shifts_the_image_by_pixels() {
+        let mut view = view();
+        let point = Vec3::new(0.3, -0.2, 1.);
+        let project = |view: &ViewUniform| {
+            let ndc = (view.mat_proj * view.mat_view).project_point3(point);
+            (ndc.truncate() + 1.) / 2. * view.viewport.zw()
+        };
+        let before = project(&view);
+        let offset = Vec2::new(0.25, -0.5);
+        view.jitter(offset);
+        assert!((project(&view) - before).abs_diff_eq(offset, 1e-3));
+        assert_eq!(view.jitter_uv_offset(offset), Vec2::new(0.00125, -0.005));
+    }
+
+    #[test]
+    fn clips_behind_the_near_plane() {
+        let mut view = view();
+        view.clip_near_plane(Vec4::new(0., 1., 0., 0.));
+        let ndc_z = |p: Vec3| (view.mat_proj * view.mat_view).project_point3(p).z;
+        assert!(ndc_z(Vec3::new(0., 0.5, 0.)).abs() < 1.);
+        assert!(ndc_z(Vec3::new(0., -0.5, 0.)) < -1.);
+    }
+
+    #[test]
+    fn clips_behind_the_near_plane_with_reversed_depth() {
+        for depth in [DepthMode::Reversed, DepthMode::ReversedInfinite] {
+            let mut view = ViewUniform::new(&Camera {
+                transform: Transform::translation(Vec3::new(0., 0., -5.)),
+                projection: Projection {
+                    width: 200.,
+                    height: 100.,
+                    depth,
+                    ..Default::default()
+                },
+            });
+            assert!(view.reversed_depth());
+            // The camera has to be on the clipped side of the plane
+            view.clip_near_plane(Vec4::new(0., 1., 0., -0.25));
+            let ndc_z = |p: Vec3| (view.mat_proj * view.mat_view).project_point3(p).z;
+            assert!((0. ..1.).contains(&ndc_z(Vec3::new(0., 0.5, 0.))));
+            assert!(ndc_z(Vec3::new(0., 0., 0.)) > 1.);
+        }
+    }
+
+    #[test]
+    fn relative_model_matrices_match_world_ones() {
+        let mut view = view();
+        view.set_origin(Vec3::new(100., -20., 3.));
+        let model = Transform::translation(Vec3::new(90., -15., 1.)).rotated(Vec3::Y);
+        let local = Vec3::new(0.5, 1., -2.);
+        let world = (view.mat_view * model.matrix()).transform_point3(local);
+        let relative = (view.mat_view_relative * model.relative_to(view.origin).matrix())
+            .transform_point3(local);
+        assert!(world.abs_diff_eq(relative, 1e-4));
+    }
+
+    #[test]
+    fn relative_view_follows_the_camera_far_from_the_world_origin() {
+        // On the surface of the Earth, where single precision only has half a meter steps
+        let rotation = Quat::from_rotation_y(0.7);
+        let local = Vec3::new(0.5, 1., -2.);
+        let model = Transform::translation(Vec3::new(6_400_010.5, 20., -7.5)).rotated(Vec3::Y);
+        let mut absolute_error = 0f64;
+        // Walk the camera across a boundary of the origin grid, moving the origin along
+        for step in 0..8 {
+            let eye = Vec3::new(6_400_510. + 2. * step as f32, 2., 5.);
+            let mut camera = Transform::rotation(rotation);
+            camera.position = -(rotation * eye);
+            let mut view = ViewUniform::new(&Camera {
+                transform: camera,
+                projection: Projection::default(),
+            });
+            let origin = (eye / 1024.).round() * 1024.;
+            view.set_origin(origin);
+            let expected_eye = view.mat_view.as_dmat4().inverse().w_axis.truncate();
+            let camera_pos = view.camera_pos.as_dvec3() + origin.as_dvec3();
+            assert!(camera_pos.distance(expected_eye) < 1e-3);
+
+            // Same matrices, multiplied in double precision
+            let expected = (view.mat_view.as_dmat4() * model.matrix().as_dmat4())
+                .transform_point3(local.as_dvec3());
+            let relative = (view.mat_view_relative * model.relative_to(view.origin).matrix())
+                .transform_point3(local);
+            assert!(
+                relative.as_dvec3().distance(expected) < 1e-3,
+                "step {}",
+                step
+            );
+            let absolute = (view.mat_view * model.matrix()).transform_point3(local);
+            absolute_error = absolute_error.max(absolute.as_dvec3().distance(expected));
+        }
+        assert!(absolute_error > 1e-3);
+    }
+
+    #[test]
+    fn reversed_depth_decreases_with_distance() {
+        let projection = Projection {
+            zrange: 0.1..100.,
+            depth: DepthMode::Reversed,
+            ..Default::default()
+        };
+        let ndc_z = |z: f32| projection.matrix().project_point3(Vec3::new(0., 0., -z)).z;
+        assert!((ndc_z(0.1) - 1.).abs() < 1e-5);
+        assert!(ndc_z(100.).abs() < 1e-5);
+        assert!(ndc_z(1.) > ndc_z(10.));
+    }
+
+    #[test]
+    fn halton_jitter_stays_within_the_pixel() {
+        assert_eq!(halton(1, 2), 0.5);
+        assert_eq!(halton(3, 3), 1. / 9.);
+        for frame in 0..16 {
+            let jitter = halton_jitter(frame, 8);
+            assert!(jitter.abs().max_element() < 0.5);
+            assert_eq!(jitter, halton_jitter(frame + 8, 8));
+        }
+    }
 }
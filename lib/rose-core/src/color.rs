@@ -0,0 +1,132 @@
+//! Color spaces of textures and framebuffers.
+//!
+//! Lighting happens in linear space, while 8-bit color textures and the display hold sRGB encoded
+//! values. Color textures are stored with the GL sRGB internal formats so that sampling them
+//! decodes to linear values (and mipmaps are filtered in linear space), and the final
+//! post-processing pass encodes its output to sRGB itself. The default framebuffer must therefore
+//! not encode it a second time, which [`backbuffer_encodes_srgb`] checks.
+
+use image::DynamicImage;
+use violette::{gl, texture::Texture};
+
+/// How the values of a texture are encoded.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serialize", serde(rename_all = "snake_case"))]
+pub enum ColorSpace {
+    /// Values are used as is, e.g. normals, roughness or HDR colors.
+    #[default]
+    Linear,
+    /// Colors encoded with the sRGB transfer function, as most 8-bit color images are.
+    Srgb,
+}
+
+impl ColorSpace {
+    /// Color space of the values stored with the GL internal format.
+    pub fn of_internal_format(format: u32) -> Self {
+        match format {
+            gl::SRGB | gl::SRGB8 | gl::SRGB_ALPHA | gl::SRGB8_ALPHA8 => Self::Srgb,
+            _ => Self::Linear,
+        }
+    }
+}
+
+/// Linear value of an sRGB encoded component.
+pub fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// sRGB encoded value of a linear component, the inverse of [`srgb_to_linear`].
+pub fn linear_to_srgb(c: f32) -> f32 {
+    if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1. / 2.4) - 0.055
+    }
+}
+
+/// Replace the base level of the texture with the 8-bit image, stored in an sRGB internal format.
+/// Mipmaps need to be generated again afterwards.
+pub fn set_srgb_image<F>(texture: &Texture<F>, image: &DynamicImage, alpha: bool) {
+    let (internal_format, format, pixels) = if alpha {
+        (gl::SRGB8_ALPHA8, gl::RGBA, image.to_rgba8().into_raw())
+    } else {
+        (gl::SRGB8, gl::RGB, image.to_rgb8().into_raw())
+    };
+    texture.bind();
+    unsafe {
+        // Rows of RGB bytes are not always a multiple of the default alignment of 4 bytes
+        gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+        gl::TexImage2D(
+            gl::TEXTURE_2D,
+            0,
+            internal_format as _,
+            image.width() as _,
+            image.height() as _,
+            0,
+            format,
+            gl::UNSIGNED_BYTE,
+            pixels.as_ptr() as _,
+        );
+        gl::PixelStorei(gl::UNPACK_ALIGNMENT, 4);
+    }
+    texture.unbind();
+}
+
+/// GL internal format of the base level of the texture.
+pub fn internal_format<F>(texture: &Texture<F>) -> u32 {
+    let mut format = 0;
+    texture.bind();
+    unsafe {
+        gl::GetTexLevelParameteriv(gl::TEXTURE_2D, 0, gl::TEXTURE_INTERNAL_FORMAT, &mut format);
+    }
+    texture.unbind();
+    format as _
+}
+
+/// Whether the internal format stores 8 bits or fewer per component, which is too little
+/// precision for linear colors and hints at sRGB data uploaded without its color space.
+pub fn is_low_precision(format: u32) -> bool {
+    matches!(
+        format,
+        gl::R8 | gl::RG8 | gl::RGB8 | gl::RGBA8 | gl::RED | gl::RG | gl::RGB | gl::RGBA
+    )
+}
+
+/// Whether writes to the default framebuffer are encoded to sRGB by GL, which would encode the
+/// already encoded output of the post-processing a second time.
+pub fn backbuffer_encodes_srgb() -> bool {
+    let mut encoding = 0;
+    unsafe {
+        if gl::IsEnabled(gl::FRAMEBUFFER_SRGB) == gl::FALSE {
+            return false;
+        }
+        gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+        gl::GetFramebufferAttachmentParameteriv(
+            gl::FRAMEBUFFER,
+            gl::BACK_LEFT,
+            gl::FRAMEBUFFER_ATTACHMENT_COLOR_ENCODING,
+            &mut encoding,
+        );
+    }
+    encoding as u32 == gl::SRGB
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transfer_functions_round_trip() {
+        for i in 0..=255 {
+            let c = i as f32 / 255.;
+            let round_trip = linear_to_srgb(srgb_to_linear(c));
+            assert!((round_trip - c).abs() < 1e-5, "{round_trip} != {c}");
+        }
+        assert!((srgb_to_linear(0.5) - 0.214).abs() < 1e-3);
+    }
+}
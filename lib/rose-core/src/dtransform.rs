@@ -0,0 +1,157 @@
+//! Double precision transforms, for worlds spanning distances where single precision positions
+//! are no longer accurate, such as planets at their real scale.
+//!
+//! They are never uploaded as-is: [`GlobalDTransform::relative_to`] converts them to single
+//! precision transforms relative to a point close to the camera, where the precision is needed.
+
+use std::ops::Mul;
+
+use glam::{DMat4, DQuat, DVec3};
+
+use crate::transform::Transform;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
+pub struct DTransform {
+    pub position: DVec3,
+    pub rotation: DQuat,
+    pub scale: DVec3,
+}
+
+impl Default for DTransform {
+    fn default() -> Self {
+        Self {
+            position: DVec3::ZERO,
+            rotation: DQuat::IDENTITY,
+            scale: DVec3::ONE,
+        }
+    }
+}
+
+impl DTransform {
+    pub fn translation(position: DVec3) -> Self {
+        Self {
+            position,
+            ..Default::default()
+        }
+    }
+
+    pub fn rotation(rotation: DQuat) -> Self {
+        Self {
+            rotation,
+            ..Default::default()
+        }
+    }
+
+    pub fn scaled(mut self, scale: DVec3) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    pub fn from_matrix(mat: DMat4) -> Self {
+        let (scale, rotation, position) = mat.to_scale_rotation_translation();
+        Self {
+            position,
+            rotation,
+            scale,
+        }
+    }
+
+    pub fn matrix(&self) -> DMat4 {
+        DMat4::from_scale_rotation_translation(self.scale, self.rotation.normalize(), self.position)
+    }
+
+    pub fn transform_point(&self, point: DVec3) -> DVec3 {
+        self.matrix().transform_point3(point)
+    }
+
+    /// Single precision transform with its position relative to `origin`, accurate as long as it
+    /// is close to the origin.
+    pub fn relative_to(&self, origin: DVec3) -> Transform {
+        Transform {
+            position: (self.position - origin).as_vec3(),
+            rotation: self.rotation.as_f32(),
+            scale: self.scale.as_vec3(),
+        }
+    }
+
+    /// [`Self::relative_to`] for view transforms, which map world positions into the view, as
+    /// used by cameras.
+    pub fn view_relative_to(&self, origin: DVec3) -> Transform {
+        Transform::from_matrix((self.matrix() * DMat4::from_translation(origin)).as_mat4())
+    }
+
+    /// World position of the eye of a view transform, the origin to use for the transforms
+    /// rendered from it.
+    pub fn view_origin(&self) -> DVec3 {
+        self.matrix().inverse().w_axis.truncate()
+    }
+}
+
+impl Mul<Self> for DTransform {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self::Output {
+        Self::from_matrix(self.matrix() * rhs.matrix())
+    }
+}
+
+impl From<Transform> for DTransform {
+    fn from(value: Transform) -> Self {
+        Self {
+            position: value.position.as_dvec3(),
+            rotation: value.rotation.as_f64(),
+            scale: value.scale.as_dvec3(),
+        }
+    }
+}
+
+/// World transform of an entity positioned with a [`DTransform`], composed with the ones of its
+/// parents.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
+#[repr(transparent)]
+pub struct GlobalDTransform(pub DTransform);
+
+impl GlobalDTransform {
+    /// Single precision world transform relative to the eye of `view`, to upload for rendering.
+    pub fn relative_to(&self, view: &GlobalDTransform) -> Transform {
+        self.0.relative_to(view.0.view_origin())
+    }
+
+    /// [`Self::relative_to`] for the view transform of a camera, only keeping its rotation.
+    pub fn view_relative_to(&self, view: &GlobalDTransform) -> Transform {
+        self.0.view_relative_to(view.0.view_origin())
+    }
+}
+
+impl From<GlobalDTransform> for DTransform {
+    fn from(val: GlobalDTransform) -> Self {
+        val.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec3;
+
+    use super::*;
+
+    #[test]
+    fn keeps_precision_relative_to_a_distant_origin() {
+        const RADIUS: f64 = 6360e3;
+        let parent = DTransform::translation(DVec3::new(RADIUS, 0., 0.));
+        let child = DTransform::translation(DVec3::new(1e-3, 2., 0.));
+        let relative = (parent * child).relative_to(DVec3::new(RADIUS, 0., 0.));
+        assert!(relative.position.abs_diff_eq(Vec3::new(1e-3, 2., 0.), 1e-6));
+    }
+
+    #[test]
+    fn view_relative_to_the_eye_only_rotates() {
+        let eye = DVec3::new(6360e3, 10., -4.);
+        let view = DTransform::from_matrix(DMat4::look_at_rh(eye, DVec3::ZERO, DVec3::Y));
+        assert!(view.view_origin().abs_diff_eq(eye, 1e-6));
+        let relative = view.view_relative_to(eye);
+        assert!(relative.position.abs_diff_eq(Vec3::ZERO, 1e-3));
+    }
+}
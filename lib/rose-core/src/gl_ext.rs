@@ -0,0 +1,238 @@
+//! OpenGL functionality violette does not wrap yet.
+//!
+//! The raw GL calls the engine cannot make through violette are kept here behind safe functions
+//! and types taking violette ones, instead of being spread across the crates, so that they can
+//! move into violette as it grows to cover them.
+
+use std::time::Duration;
+
+use glam::UVec2;
+
+use violette::{
+    gl::{self, types::GLsync},
+    texture::{Texture, TextureWrap},
+};
+
+/// Depth range of clip space, mapped to the `[0, 1]` range of the depth buffer.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ClipDepth {
+    /// OpenGL default, `[-1, 1]`.
+    NegativeOneToOne,
+    /// `[0, 1]`, as used with reversed depth to keep the precision of floating-point depth.
+    ZeroToOne,
+}
+
+/// Whether [`set_clip_depth`] is available, with OpenGL 4.5 or `ARB_clip_control`.
+pub fn clip_control_supported() -> bool {
+    gl::ClipControl::is_loaded()
+}
+
+/// Set the depth range of clip space, keeping the origin of the window at its lower left corner.
+/// Does nothing when [`clip_control_supported`] is false.
+pub fn set_clip_depth(depth: ClipDepth) {
+    if !clip_control_supported() {
+        return;
+    }
+    let depth = match depth {
+        ClipDepth::NegativeOneToOne => gl::NEGATIVE_ONE_TO_ONE,
+        ClipDepth::ZeroToOne => gl::ZERO_TO_ONE,
+    };
+    unsafe { gl::ClipControl(gl::LOWER_LEFT, depth) };
+}
+
+/// Value the depth buffer is cleared to.
+pub fn set_clear_depth(depth: f64) {
+    unsafe { gl::ClearDepth(depth) };
+}
+
+/// Enable or disable the clip distance `index` written by the vertex shaders in `gl_ClipDistance`.
+pub fn set_clip_distance(index: u32, enabled: bool) {
+    let cap = gl::CLIP_DISTANCE0 + index;
+    unsafe {
+        if enabled {
+            gl::Enable(cap);
+        } else {
+            gl::Disable(cap);
+        }
+    }
+}
+
+/// GL sampler object, overriding the filtering and wrapping of the texture bound to the texture
+/// unit it is bound to.
+#[derive(Debug)]
+pub struct SamplerObject(u32);
+
+impl SamplerObject {
+    /// Create a sampler with the GL minification and magnification filters, e.g.
+    /// `gl::LINEAR_MIPMAP_LINEAR`, and the wrapping of the S, T and R axes.
+    pub fn new(min_filter: u32, mag_filter: u32, wrap: [TextureWrap; 3]) -> Self {
+        let mut id = 0;
+        let [wrap_s, wrap_t, wrap_r] = wrap;
+        unsafe {
+            gl::GenSamplers(1, &mut id);
+            gl::SamplerParameteri(id, gl::TEXTURE_MIN_FILTER, min_filter as _);
+            gl::SamplerParameteri(id, gl::TEXTURE_MAG_FILTER, mag_filter as _);
+            gl::SamplerParameteri(id, gl::TEXTURE_WRAP_S, wrap_s as _);
+            gl::SamplerParameteri(id, gl::TEXTURE_WRAP_T, wrap_t as _);
+            gl::SamplerParameteri(id, gl::TEXTURE_WRAP_R, wrap_r as _);
+        }
+        Self(id)
+    }
+
+    pub fn bind(&self, unit: u32) {
+        unsafe { gl::BindSampler(unit, self.0) };
+    }
+
+    pub fn unbind(unit: u32) {
+        unsafe { gl::BindSampler(unit, 0) };
+    }
+}
+
+impl Drop for SamplerObject {
+    fn drop(&mut self) {
+        unsafe { gl::DeleteSamplers(1, &self.0) };
+    }
+}
+
+/// Allocate `levels` mipmap levels of `size` texels for the texture, storing 8-bit sRGB encoded
+/// colors with linear alpha. The storage is immutable: the texture cannot be resized afterwards.
+pub fn allocate_srgb_alpha<F>(texture: &Texture<F>, size: UVec2, levels: u32) {
+    texture.bind();
+    unsafe {
+        gl::TexStorage2D(
+            gl::TEXTURE_2D,
+            levels as _,
+            gl::SRGB8_ALPHA8,
+            size.x as _,
+            size.y as _,
+        );
+    }
+    texture.unbind();
+}
+
+/// Size of the mipmap level of the texture.
+pub fn texture_level_size<F>(texture: &Texture<F>, level: u32) -> UVec2 {
+    let (mut width, mut height) = (0, 0);
+    texture.bind();
+    unsafe {
+        gl::GetTexLevelParameteriv(gl::TEXTURE_2D, level as _, gl::TEXTURE_WIDTH, &mut width);
+        gl::GetTexLevelParameteriv(gl::TEXTURE_2D, level as _, gl::TEXTURE_HEIGHT, &mut height);
+    }
+    texture.unbind();
+    UVec2::new(width as _, height as _)
+}
+
+/// Buffer object the GPU copies pixels into, to be read on the CPU once the copy is done.
+#[derive(Debug)]
+pub struct PixelPackBuffer {
+    id: u32,
+    len: usize,
+}
+
+impl PixelPackBuffer {
+    /// Allocate `len` bytes, bound as the pixel pack buffer while `copy` queues the copy of the
+    /// pixels into it with [`Self::copy_texture`] or [`Self::copy_framebuffer`].
+    pub fn new(len: usize, copy: impl FnOnce(&Self)) -> Self {
+        let mut id = 0;
+        unsafe {
+            gl::GenBuffers(1, &mut id);
+            gl::BindBuffer(gl::PIXEL_PACK_BUFFER, id);
+            gl::BufferData(
+                gl::PIXEL_PACK_BUFFER,
+                len as _,
+                std::ptr::null(),
+                gl::STREAM_READ,
+            );
+            // Rows of RGB floats are not always a multiple of the default alignment of 4 bytes
+            gl::PixelStorei(gl::PACK_ALIGNMENT, 1);
+        }
+        let buffer = Self { id, len };
+        copy(&buffer);
+        unsafe {
+            gl::PixelStorei(gl::PACK_ALIGNMENT, 4);
+            gl::BindBuffer(gl::PIXEL_PACK_BUFFER, 0);
+        }
+        buffer
+    }
+
+    /// Copy the mipmap level of the texture, with the GL format and type of the pixels.
+    pub fn copy_texture<F>(&self, texture: &Texture<F>, level: u32, (format, ty): (u32, u32)) {
+        texture.bind();
+        unsafe { gl::GetTexImage(gl::TEXTURE_2D, level as _, format, ty, std::ptr::null_mut()) };
+        texture.unbind();
+    }
+
+    /// Copy the rectangle of the framebuffer bound for reading, with the GL format and type of
+    /// the pixels.
+    pub fn copy_framebuffer(&self, min: UVec2, size: UVec2, (format, ty): (u32, u32)) {
+        unsafe {
+            gl::ReadPixels(
+                min.x as _,
+                min.y as _,
+                size.x as _,
+                size.y as _,
+                format,
+                ty,
+                std::ptr::null_mut(),
+            );
+        }
+    }
+
+    /// Map the buffer and copy its contents out, waiting for the GPU when the copy into it is not
+    /// done yet.
+    pub fn read(&self) -> Option<Vec<u8>> {
+        let mut pixels = vec![0; self.len];
+        let mapped = unsafe {
+            gl::BindBuffer(gl::PIXEL_PACK_BUFFER, self.id);
+            let data =
+                gl::MapBufferRange(gl::PIXEL_PACK_BUFFER, 0, self.len as _, gl::MAP_READ_BIT);
+            let mapped = !data.is_null();
+            if mapped {
+                std::ptr::copy_nonoverlapping(data as *const u8, pixels.as_mut_ptr(), self.len);
+                gl::UnmapBuffer(gl::PIXEL_PACK_BUFFER);
+            }
+            gl::BindBuffer(gl::PIXEL_PACK_BUFFER, 0);
+            mapped
+        };
+        mapped.then_some(pixels)
+    }
+}
+
+impl Drop for PixelPackBuffer {
+    fn drop(&mut self) {
+        unsafe { gl::DeleteBuffers(1, &self.id) };
+    }
+}
+
+/// Sync object signaled once the GPU is done with the commands issued before its creation.
+#[derive(Debug)]
+pub struct Fence(GLsync);
+
+impl Fence {
+    pub fn new() -> Self {
+        Self(unsafe { gl::FenceSync(gl::SYNC_GPU_COMMANDS_COMPLETE, 0) })
+    }
+
+    /// Wait up to `timeout` for the fence to be signaled, returning whether it is.
+    pub fn wait(&self, timeout: Duration) -> bool {
+        let timeout = timeout.as_nanos().min(u64::MAX as u128) as u64;
+        let status = unsafe { gl::ClientWaitSync(self.0, gl::SYNC_FLUSH_COMMANDS_BIT, timeout) };
+        status == gl::ALREADY_SIGNALED || status == gl::CONDITION_SATISFIED
+    }
+
+    pub fn is_signaled(&self) -> bool {
+        self.wait(Duration::ZERO)
+    }
+}
+
+impl Default for Fence {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for Fence {
+    fn drop(&mut self) {
+        unsafe { gl::DeleteSync(self.0) };
+    }
+}
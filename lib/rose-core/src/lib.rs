@@ -1,18 +1,36 @@
 extern crate glam;
 
+pub mod atlas;
 pub mod camera;
+pub mod color;
+#[cfg(feature = "f64-transforms")]
+pub mod dtransform;
+pub mod gl_ext;
 pub mod light;
 pub mod mesh;
+pub mod navmesh;
+pub mod readback;
+pub mod sampler;
 pub mod screen_draw;
+pub mod spline;
 pub mod transform;
 pub mod utils;
 
 pub mod prelude {
+    pub use crate::atlas::{AtlasId, AtlasPacker};
     pub use crate::camera::{Camera, Projection};
-    pub use crate::light::{GpuLight, Light, LightBuffer};
+    pub use crate::color::ColorSpace;
+    #[cfg(feature = "f64-transforms")]
+    pub use crate::dtransform::DTransform;
+    pub use crate::light::{AreaShape, GpuLight, Light, LightBuffer};
     pub use crate::mesh::{CpuMesh, Mesh, MeshBuilder};
+    pub use crate::navmesh::{NavMesh, NavMeshSettings};
+    pub use crate::readback::{Readback, ReadbackFormat};
+    pub use crate::sampler::{SamplerCache, SamplerState};
     pub use crate::screen_draw::ScreenDraw;
+    pub use crate::spline::{ArcLength, Spline, SplineKind};
     pub use crate::transform::{Transform, TransformExt, Transformed};
     pub use crate::utils::reload_watcher::*;
+    pub use crate::utils::rng::EngineRng;
     pub use crate::utils::thread_guard::*;
 }
@@ -1,11 +1,20 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
 use crevice::std140::{self, AsStd140};
 use eyre::{Context, Result};
-use glam::Vec3;
+use glam::{Quat, Vec2, Vec3};
 use num_derive::FromPrimitive;
 use num_traits::FromPrimitive;
 
-use violette::buffer::{Buffer, BufferAccess, UniformBuffer};
+use violette::buffer::{Buffer, BufferAccess, BufferUsageHint, UniformBuffer};
 
+use crate::color::srgb_to_linear;
 use crate::transform::Transform;
 
 #[derive(Debug, Copy, Clone, FromPrimitive)]
@@ -14,13 +23,40 @@ pub enum LightType {
     Point = 0,
     Directional = 1,
     Ambient = 2,
+    Rect = 3,
+    Disk = 4,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum AreaShape {
+    Rect,
+    Disk,
 }
 
 #[derive(Debug, Copy, Clone)]
 pub enum Light {
-    Point { color: Vec3, position: Vec3 },
-    Directional { color: Vec3, dir: Vec3 },
-    Ambient { color: Vec3 },
+    Point {
+        color: Vec3,
+        position: Vec3,
+    },
+    Directional {
+        color: Vec3,
+        dir: Vec3,
+    },
+    Ambient {
+        color: Vec3,
+    },
+    /// Light emitted from the surface of a flat shape, facing its local +Z axis. `color` is the
+    /// luminance of the surface.
+    Area {
+        color: Vec3,
+        position: Vec3,
+        rotation: Quat,
+        shape: AreaShape,
+        /// Width and height of a rectangle, or diameter of a disk along each local axis.
+        size: Vec2,
+        two_sided: bool,
+    },
 }
 
 impl Light {
@@ -35,12 +71,65 @@ impl Light {
                 color,
                 dir: transform.forward().normalize(),
             },
+            Self::Area {
+                color,
+                shape,
+                size,
+                two_sided,
+                ..
+            } => Self::Area {
+                color,
+                position: transform.position,
+                rotation: transform.rotation,
+                shape,
+                size: size * transform.scale.truncate(),
+                two_sided,
+            },
+        }
+    }
+
+    /// Light with its position relative to `origin`, subtracted in double precision; directions
+    /// are left as they are.
+    pub fn relative_to(self, origin: Vec3) -> Self {
+        let relative = |position: Vec3| (position.as_dvec3() - origin.as_dvec3()).as_vec3();
+        match self {
+            Self::Point { color, position } => Self::Point {
+                color,
+                position: relative(position),
+            },
+            Self::Area {
+                color,
+                position,
+                rotation,
+                shape,
+                size,
+                two_sided,
+            } => Self::Area {
+                color,
+                position: relative(position),
+                rotation,
+                shape,
+                size,
+                two_sided,
+            },
+            light => light,
+        }
+    }
+
+    /// Half extents of an area light along its local X and Y axes, in world space.
+    pub fn area_extents(&self) -> Option<(Vec3, Vec3)> {
+        match self {
+            &Self::Area { rotation, size, .. } => Some((
+                rotation * Vec3::X * size.x / 2.,
+                rotation * Vec3::Y * size.y / 2.,
+            )),
+            _ => None,
         }
     }
 
     fn pos_dir(&self) -> Vec3 {
         match self {
-            &Self::Point { position, .. } => position,
+            &Self::Point { position, .. } | &Self::Area { position, .. } => position,
             &Self::Directional { dir, .. } => dir,
             Self::Ambient { .. } => Vec3::ZERO,
         }
@@ -51,6 +140,14 @@ impl Light {
             Self::Point { .. } => LightType::Point,
             Self::Directional { .. } => LightType::Directional,
             Self::Ambient { .. } => LightType::Ambient,
+            Self::Area {
+                shape: AreaShape::Rect,
+                ..
+            } => LightType::Rect,
+            Self::Area {
+                shape: AreaShape::Disk,
+                ..
+            } => LightType::Disk,
         }
     }
 
@@ -58,7 +155,8 @@ impl Light {
         match self {
             &Self::Directional { color, .. }
             | &Self::Point { color, .. }
-            | &Self::Ambient { color } => color,
+            | &Self::Ambient { color }
+            | &Self::Area { color, .. } => color,
         }
     }
 
@@ -66,11 +164,40 @@ impl Light {
         match self {
             Self::Directional { color, .. }
             | Self::Point { color, .. }
-            | Self::Ambient { color } => color,
+            | Self::Ambient { color }
+            | Self::Area { color, .. } => color,
         }
     }
 }
 
+/// Approximate linear RGB color of a black body at the given temperature, in Kelvin, normalized so
+/// that its largest component is 1. Temperatures are clamped to the 1000 K - 40000 K range.
+///
+/// Uses Tanner Helland's fit of the CIE 1964 black body colors.
+pub fn color_temperature(kelvin: f32) -> Vec3 {
+    let t = kelvin.clamp(1000., 40000.) / 100.;
+    let r = if t <= 66. {
+        255.
+    } else {
+        329.69873 * (t - 60.).powf(-0.13320476)
+    };
+    let g = if t <= 66. {
+        99.4708 * t.ln() - 161.11957
+    } else {
+        288.12216 * (t - 60.).powf(-0.07551485)
+    };
+    let b = if t >= 66. {
+        255.
+    } else if t <= 19. {
+        0.
+    } else {
+        138.51773 * (t - 10.).ln() - 305.04479
+    };
+    let srgb = (Vec3::new(r, g, b) / 255.).clamp(Vec3::ZERO, Vec3::ONE);
+    let linear = Vec3::from(srgb.to_array().map(srgb_to_linear));
+    linear / linear.max_element()
+}
+
 impl From<GpuLight> for Light {
     fn from(light: GpuLight) -> Self {
         let kind = LightType::from_u32(light.kind).unwrap();
@@ -86,6 +213,28 @@ impl From<GpuLight> for Light {
             LightType::Ambient => Self::Ambient {
                 color: from_std140vec3(light.color),
             },
+            LightType::Rect | LightType::Disk => {
+                let extent_x = from_std140vec3(light.extent_x);
+                let extent_y = from_std140vec3(light.extent_y);
+                let normal = extent_x.cross(extent_y).normalize();
+                let rotation = Quat::from_mat3(&glam::Mat3::from_cols(
+                    extent_x.normalize(),
+                    extent_y.normalize(),
+                    normal,
+                ));
+                Self::Area {
+                    color: from_std140vec3(light.color),
+                    position: from_std140vec3(light.pos_dir),
+                    rotation,
+                    shape: if matches!(kind, LightType::Rect) {
+                        AreaShape::Rect
+                    } else {
+                        AreaShape::Disk
+                    },
+                    size: Vec2::new(extent_x.length(), extent_y.length()) * 2.,
+                    two_sided: light.two_sided != 0,
+                }
+            }
         }
     }
 }
@@ -96,6 +245,9 @@ pub struct GpuLight {
     kind: u32,
     pos_dir: std140::Vec3,
     color: std140::Vec3,
+    extent_x: std140::Vec3,
+    extent_y: std140::Vec3,
+    two_sided: u32,
 }
 
 impl From<<GpuLight as AsStd140>::Output> for GpuLight {
@@ -104,16 +256,29 @@ impl From<<GpuLight as AsStd140>::Output> for GpuLight {
             kind: value.kind,
             pos_dir: value.pos_dir,
             color: value.color,
+            extent_x: value.extent_x,
+            extent_y: value.extent_y,
+            two_sided: value.two_sided,
         }
     }
 }
 
 impl From<Light> for GpuLight {
     fn from(l: Light) -> Self {
+        let (extent_x, extent_y) = l.area_extents().unwrap_or_default();
         Self {
             kind: l.kind() as _,
             pos_dir: to_std140vec3(l.pos_dir()),
             color: to_std140vec3(l.color()),
+            extent_x: to_std140vec3(extent_x),
+            extent_y: to_std140vec3(extent_y),
+            two_sided: matches!(
+                l,
+                Light::Area {
+                    two_sided: true,
+                    ..
+                }
+            ) as _,
         }
     }
 }
@@ -142,6 +307,166 @@ impl GpuLight {
 
 pub type LightBuffer = UniformBuffer<<GpuLight as AsStd140>::Output>;
 
+/// Handle to a light of [`Lights`], which stays valid when other lights are added or removed.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct LightHandle(u64);
+
+/// Allocates the handles of [`Lights`]. Clones share the same handles, so that other threads can
+/// reserve handles of lights inserted later with [`Lights::insert`].
+#[derive(Debug, Clone, Default)]
+pub struct LightHandleAllocator(Arc<AtomicU64>);
+
+impl LightHandleAllocator {
+    pub fn allocate(&self) -> LightHandle {
+        LightHandle(self.0.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+/// Lights kept in a [`LightBuffer`], updated in place instead of recreating the buffer.
+///
+/// The lights are packed at the start of the buffer: removing a light moves the last one into its
+/// slot. The buffer grows by doubling its capacity, so only the first [`Self::len`] elements of
+/// [`Self::buffer`] are lights. Their positions are uploaded relative to [`Self::origin`].
+#[derive(Debug)]
+pub struct Lights {
+    buffer: LightBuffer,
+    /// Lights in the order of the buffer.
+    lights: Vec<(LightHandle, Light)>,
+    indices: HashMap<LightHandle, usize>,
+    handles: LightHandleAllocator,
+    origin: Vec3,
+}
+
+impl Default for Lights {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Lights {
+    pub fn new() -> Self {
+        Self {
+            buffer: LightBuffer::new(),
+            lights: vec![],
+            indices: HashMap::new(),
+            handles: LightHandleAllocator::default(),
+            origin: Vec3::ZERO,
+        }
+    }
+
+    pub fn buffer(&self) -> &LightBuffer {
+        &self.buffer
+    }
+
+    /// World position the positions of the lights in the buffer are relative to.
+    pub fn origin(&self) -> Vec3 {
+        self.origin
+    }
+
+    /// Upload the positions of the lights relative to `origin`, the origin of the view (see
+    /// [`crate::camera::ViewUniform::set_origin`]), so that they are shaded with the precision of
+    /// the positions they light.
+    pub fn set_origin(&mut self, origin: Vec3) -> Result<()> {
+        if origin == self.origin {
+            return Ok(());
+        }
+        self.origin = origin;
+        for ix in 0..self.lights.len() {
+            self.write(ix)?;
+        }
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.lights.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lights.is_empty()
+    }
+
+    pub fn get(&self, handle: LightHandle) -> Option<&Light> {
+        let ix = *self.indices.get(&handle)?;
+        Some(&self.lights[ix].1)
+    }
+
+    pub fn iter(&self) -> impl '_ + Iterator<Item = (LightHandle, &Light)> {
+        self.lights.iter().map(|(handle, light)| (*handle, light))
+    }
+
+    pub fn handle_allocator(&self) -> LightHandleAllocator {
+        self.handles.clone()
+    }
+
+    pub fn add(&mut self, light: Light) -> Result<LightHandle> {
+        let handle = self.handles.allocate();
+        self.insert(handle, light)?;
+        Ok(handle)
+    }
+
+    /// Add a light with a handle reserved from [`Self::handle_allocator`], or update the light if
+    /// the handle is already in use.
+    pub fn insert(&mut self, handle: LightHandle, light: Light) -> Result<()> {
+        if self.indices.contains_key(&handle) {
+            return self.update(handle, light);
+        }
+        self.indices.insert(handle, self.lights.len());
+        self.lights.push((handle, light));
+        if self.lights.len() > self.buffer.len() {
+            self.grow()
+        } else {
+            self.write(self.lights.len() - 1)
+        }
+    }
+
+    pub fn update(&mut self, handle: LightHandle, light: Light) -> Result<()> {
+        let Some(&ix) = self.indices.get(&handle) else { eyre::bail!("Unknown light {:?}", handle); };
+        self.lights[ix].1 = light;
+        self.write(ix)
+    }
+
+    /// Remove the light, returning it if the handle was valid.
+    pub fn remove(&mut self, handle: LightHandle) -> Result<Option<Light>> {
+        let Some(ix) = self.indices.remove(&handle) else { return Ok(None); };
+        let (_, light) = self.lights.swap_remove(ix);
+        if let Some(&(moved, _)) = self.lights.get(ix) {
+            self.indices.insert(moved, ix);
+            self.write(ix)?;
+        }
+        Ok(Some(light))
+    }
+
+    /// Remove all lights, keeping the buffer allocated.
+    pub fn clear(&mut self) {
+        self.lights.clear();
+        self.indices.clear();
+    }
+
+    fn write(&mut self, ix: usize) -> Result<()> {
+        let light = GpuLight::from(self.lights[ix].1.relative_to(self.origin)).as_std140();
+        self.buffer
+            .at(ix)
+            .set(0, &light)
+            .context("Cannot update light buffer")
+    }
+
+    /// Reallocate the buffer with room for at least the current lights, uploading them all.
+    fn grow(&mut self) -> Result<()> {
+        let capacity = self.lights.len().next_power_of_two();
+        let unused = GpuLight::from(Light::Ambient { color: Vec3::ZERO }).as_std140();
+        let data = self
+            .lights
+            .iter()
+            .map(|(_, light)| GpuLight::from(light.relative_to(self.origin)).as_std140())
+            .chain(std::iter::repeat(unused))
+            .take(capacity)
+            .collect::<Vec<_>>();
+        self.buffer
+            .set(&data, BufferUsageHint::Dynamic)
+            .context("Cannot grow light buffer")
+    }
+}
+
 fn from_std140vec3(v: std140::Vec3) -> Vec3 {
     Vec3::from([v.x, v.y, v.z])
 }
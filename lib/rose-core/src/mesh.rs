@@ -11,6 +11,8 @@ use violette::{
     vertex::{DrawMode, VertexArray, VertexAttributes},
 };
 
+use crate::utils::draw_counters;
+
 #[derive(Debug)]
 pub struct Mesh<Vertex> {
     array: VertexArray,
@@ -80,6 +82,8 @@ where
                 0..self.indices.len() as i32,
             )
             .context("Cannot draw mesh")?;
+        let triangles = if wireframe { 0 } else { self.indices.len() / 3 };
+        draw_counters::record_draw(program, triangles);
         Ok(())
     }
 }
@@ -95,6 +99,15 @@ impl<V: VertexAttributes> CpuMesh<V, u32> {
     }
 }
 
+impl<V, I> CpuMesh<V, I> {
+    /// Transform every vertex of the mesh, e.g. to set per-vertex attributes not handled by the
+    /// vertex constructor of the [`MeshBuilder`].
+    pub fn map_vertices(mut self, func: impl FnMut(&mut V)) -> Self {
+        self.vertices.iter_mut().for_each(func);
+        self
+    }
+}
+
 pub struct MeshBuilder<Vertex, Ctor> {
     ctor: Ctor,
     __phantom: PhantomData<Vertex>,
@@ -0,0 +1,467 @@
+//! Navigation meshes generated from scene geometry, and path finding over them.
+//!
+//! Geometry is voxelized in the manner of Recast: triangles are rasterized into columns of cells
+//! [`NavMeshSettings::cell_size`] wide, each column keeping the heights of the surfaces crossing
+//! it. Surfaces flat enough and with room above them for the agent become the cells of the
+//! navmesh, connected to the neighboring cells they can step to. Geometry thinner than a cell,
+//! e.g. walls made of a single plane, does not block the way.
+
+use std::{cmp::Ordering, collections::BinaryHeap};
+
+use glam::{UVec2, Vec2, Vec3, Vec3Swizzles};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serialize", serde(default))]
+pub struct NavMeshSettings {
+    /// Width of the cells, in world units.
+    pub cell_size: f32,
+    /// Height of the agents; surfaces with less room above them are not walkable.
+    pub agent_height: f32,
+    /// Radius of the agents, which are kept this far from the edges of the navmesh.
+    pub agent_radius: f32,
+    /// Steepest walkable slope, in radians.
+    pub max_slope: f32,
+    /// Highest step the agents climb between neighboring cells.
+    pub max_step: f32,
+}
+
+impl Default for NavMeshSettings {
+    fn default() -> Self {
+        Self {
+            cell_size: 0.25,
+            agent_height: 1.8,
+            agent_radius: 0.3,
+            max_slope: 45f32.to_radians(),
+            max_step: 0.3,
+        }
+    }
+}
+
+/// Most columns of a navmesh. Cells are made wider than [`NavMeshSettings::cell_size`] for scenes
+/// which would need more.
+pub const MAX_COLUMNS: u32 = 1 << 22;
+
+/// Offsets of the neighbors of a column, in the order of [`NavCell::neighbors`].
+const NEIGHBORS: [(i32, i32); 4] = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct NavCell {
+    /// Column of the cell, along X and Z.
+    pub column: UVec2,
+    /// Height of the walkable surface of the cell.
+    pub height: f32,
+    /// Connected cells towards +X, +Z, -X and -Z.
+    pub neighbors: [Option<u32>; 4],
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct NavMesh {
+    /// Position of the corner of the first column, on the XZ plane.
+    origin: Vec2,
+    cell_size: f32,
+    size: UVec2,
+    cells: Vec<NavCell>,
+    /// Cells of each column, indexed by `z * size.x + x`.
+    columns: Vec<Vec<u32>>,
+}
+
+impl NavMesh {
+    /// Generate the navmesh of the triangles, given in world space.
+    pub fn build(
+        triangles: impl IntoIterator<Item = [Vec3; 3]>,
+        settings: &NavMeshSettings,
+    ) -> Self {
+        let triangles = triangles.into_iter().collect::<Vec<_>>();
+        let Some(first) = triangles.first() else {
+            return Self::default();
+        };
+        let (min, max) = triangles
+            .iter()
+            .flatten()
+            .fold((first[0].xz(), first[0].xz()), |(min, max), p| {
+                (min.min(p.xz()), max.max(p.xz()))
+            });
+        let (cell_size, size) = grid_size(max - min, settings.cell_size.max(1e-3));
+        if cell_size > settings.cell_size.max(1e-3) {
+            tracing::warn!(
+                requested = settings.cell_size,
+                cell_size,
+                "Scene too large for the navmesh cell size, using wider cells"
+            );
+        }
+        let mut navmesh = Self {
+            origin: min,
+            cell_size,
+            size,
+            cells: vec![],
+            columns: vec![vec![]; (size.x * size.y) as usize],
+        };
+
+        // Surfaces crossing each column, as their height and whether they are walkable
+        let mut surfaces = vec![Vec::<(f32, bool)>::new(); navmesh.columns.len()];
+        let min_normal_y = settings.max_slope.cos();
+        for [a, b, c] in triangles {
+            let normal = (b - a).cross(c - a).normalize_or_zero();
+            let walkable = normal.y.abs() >= min_normal_y;
+            navmesh.rasterize([a, b, c], |column, height| {
+                surfaces[column].push((height, walkable));
+            });
+        }
+
+        for (column, surfaces) in surfaces.iter_mut().enumerate() {
+            surfaces.sort_by(|a, b| a.0.total_cmp(&b.0));
+            // Merge the surfaces of triangles sharing an edge over the column
+            surfaces.dedup_by(|above, below| {
+                let same = above.0 - below.0 < 1e-3;
+                below.1 |= same && above.1;
+                same
+            });
+            for (i, &(height, walkable)) in surfaces.iter().enumerate() {
+                let clearance = surfaces.get(i + 1).map_or(f32::INFINITY, |s| s.0 - height);
+                if walkable && clearance >= settings.agent_height {
+                    let x = column as u32 % size.x;
+                    let z = column as u32 / size.x;
+                    navmesh.columns[column].push(navmesh.cells.len() as u32);
+                    navmesh.cells.push(NavCell {
+                        column: UVec2::new(x, z),
+                        height,
+                        neighbors: [None; 4],
+                    });
+                }
+            }
+        }
+        navmesh.link(settings.max_step);
+
+        let erosion = (settings.agent_radius / cell_size).ceil() as usize;
+        for _ in 0..erosion {
+            navmesh.retain(|cell| cell.neighbors.iter().all(Option::is_some));
+            navmesh.link(settings.max_step);
+        }
+        navmesh
+    }
+
+    /// Call `f` with the column index and height of the triangle at the center of every column
+    /// whose center is covered by the triangle.
+    fn rasterize(&self, [a, b, c]: [Vec3; 3], mut f: impl FnMut(usize, f32)) {
+        let (a2, b2, c2) = (a.xz(), b.xz(), c.xz());
+        let area = (b2 - a2).perp_dot(c2 - a2);
+        if area.abs() < f32::EPSILON {
+            return;
+        }
+        let lo = ((a2.min(b2).min(c2) - self.origin) / self.cell_size - 0.5).ceil();
+        let hi = ((a2.max(b2).max(c2) - self.origin) / self.cell_size - 0.5).floor();
+        let lo = lo.max(Vec2::ZERO).as_uvec2();
+        let hi = hi.min((self.size - 1).as_vec2()).as_uvec2();
+        for z in lo.y..=hi.y {
+            for x in lo.x..=hi.x {
+                let p = self.origin + (UVec2::new(x, z).as_vec2() + 0.5) * self.cell_size;
+                let u = (c2 - b2).perp_dot(p - b2) / area;
+                let v = (a2 - c2).perp_dot(p - c2) / area;
+                let w = 1. - u - v;
+                if u < -1e-5 || v < -1e-5 || w < -1e-5 {
+                    continue;
+                }
+                f((z * self.size.x + x) as usize, a.y * u + b.y * v + c.y * w);
+            }
+        }
+    }
+
+    /// Connect every cell to the closest cell in height of each neighboring column, when the step
+    /// between them is low enough.
+    fn link(&mut self, max_step: f32) {
+        for i in 0..self.cells.len() {
+            let NavCell { column, height, .. } = self.cells[i];
+            for (dir, (dx, dz)) in NEIGHBORS.into_iter().enumerate() {
+                let neighbor = self
+                    .column_cells(column.as_ivec2().x + dx, column.as_ivec2().y + dz)
+                    .iter()
+                    .copied()
+                    .map(|j| (j, (self.cells[j as usize].height - height).abs()))
+                    .filter(|(_, step)| *step <= max_step)
+                    .min_by(|a, b| a.1.total_cmp(&b.1))
+                    .map(|(j, _)| j);
+                self.cells[i].neighbors[dir] = neighbor;
+            }
+        }
+    }
+
+    /// Remove the cells not matching the predicate. Links need to be recomputed afterwards.
+    fn retain(&mut self, keep: impl Fn(&NavCell) -> bool) {
+        let keep = self.cells.iter().map(keep).collect::<Vec<_>>();
+        let mut cells = Vec::with_capacity(self.cells.len());
+        for column in &mut self.columns {
+            column.retain(|&i| keep[i as usize]);
+            for i in column.iter_mut() {
+                cells.push(self.cells[*i as usize].clone());
+                *i = cells.len() as u32 - 1;
+            }
+        }
+        self.cells = cells;
+    }
+
+    fn column_cells(&self, x: i32, z: i32) -> &[u32] {
+        if x < 0 || z < 0 || x >= self.size.x as i32 || z >= self.size.y as i32 {
+            return &[];
+        }
+        &self.columns[(z as u32 * self.size.x + x as u32) as usize]
+    }
+
+    pub fn cells(&self) -> &[NavCell] {
+        &self.cells
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+
+    /// Point at the center of the walkable surface of the cell.
+    pub fn cell_center(&self, cell: usize) -> Vec3 {
+        let cell = &self.cells[cell];
+        let xz = self.origin + (cell.column.as_vec2() + 0.5) * self.cell_size;
+        Vec3::new(xz.x, cell.height, xz.y)
+    }
+
+    /// Cell of the column of the point whose surface is the closest in height to it.
+    pub fn find_cell(&self, point: Vec3) -> Option<usize> {
+        let column = ((point.xz() - self.origin) / self.cell_size).floor();
+        self.column_cells(column.x as i32, column.y as i32)
+            .iter()
+            .map(|&i| i as usize)
+            .min_by(|&a, &b| {
+                let da = (self.cells[a].height - point.y).abs();
+                let db = (self.cells[b].height - point.y).abs();
+                da.total_cmp(&db)
+            })
+    }
+
+    /// Shortest path between the points over the navmesh, from `start` to `end` included, or `None`
+    /// when either point is off the navmesh or they are not connected.
+    pub fn find_path(&self, start: Vec3, end: Vec3) -> Option<Vec<Vec3>> {
+        let from = self.find_cell(start)?;
+        let to = self.find_cell(end)?;
+        let cells = self.find_cell_path(from, to)?;
+        // Only keep the cells where the path needs to turn
+        let mut points = vec![start];
+        let mut anchor = 0;
+        for i in 2..cells.len() {
+            if !self.straight_walk(cells[anchor], cells[i]) {
+                anchor = i - 1;
+                points.push(self.cell_center(cells[anchor]));
+            }
+        }
+        points.push(end);
+        Some(points)
+    }
+
+    /// A* search over the cells, returning the cells of the path from `from` to `to` included.
+    fn find_cell_path(&self, from: usize, to: usize) -> Option<Vec<usize>> {
+        let goal = self.cell_center(to);
+        let mut costs = vec![f32::INFINITY; self.cells.len()];
+        let mut came_from = vec![usize::MAX; self.cells.len()];
+        let mut open = BinaryHeap::new();
+        costs[from] = 0.;
+        open.push(OpenCell {
+            cell: from,
+            estimate: self.cell_center(from).distance(goal),
+        });
+        while let Some(OpenCell { cell, .. }) = open.pop() {
+            if cell == to {
+                let mut path = vec![to];
+                while let Some(&last) = path.last().filter(|&&c| c != from) {
+                    path.push(came_from[last]);
+                }
+                path.reverse();
+                return Some(path);
+            }
+            let center = self.cell_center(cell);
+            for neighbor in self.cells[cell].neighbors.into_iter().flatten() {
+                let neighbor = neighbor as usize;
+                let neighbor_center = self.cell_center(neighbor);
+                let cost = costs[cell] + center.distance(neighbor_center);
+                if cost < costs[neighbor] {
+                    costs[neighbor] = cost;
+                    came_from[neighbor] = cell;
+                    open.push(OpenCell {
+                        cell: neighbor,
+                        estimate: cost + neighbor_center.distance(goal),
+                    });
+                }
+            }
+        }
+        None
+    }
+
+    /// Whether the straight line between the centers of the cells only crosses connected cells,
+    /// without cutting corners.
+    fn straight_walk(&self, from: usize, to: usize) -> bool {
+        let start = self.cells[from].column.as_vec2() + 0.5;
+        let end = self.cells[to].column.as_vec2() + 0.5;
+        let steps = (start.distance(end) * 2.).ceil() as usize;
+        let mut cell = from;
+        for step in 1..=steps {
+            let column = start
+                .lerp(end, step as f32 / steps as f32)
+                .floor()
+                .as_ivec2();
+            let current = self.cells[cell].column.as_ivec2();
+            let (dx, dz) = ((column - current).x, (column - current).y);
+            let next = match (dx, dz) {
+                (0, 0) => Some(cell),
+                (_, 0) | (0, _) => self.step(cell, dx, dz),
+                // Diagonal moves need both ways around the corner to be free
+                _ => {
+                    let via_x = self.step(cell, dx, 0).and_then(|c| self.step(c, 0, dz));
+                    let via_z = self.step(cell, 0, dz).and_then(|c| self.step(c, dx, 0));
+                    via_x.filter(|c| Some(*c) == via_z)
+                }
+            };
+            match next {
+                Some(next) => cell = next,
+                None => return false,
+            }
+        }
+        cell == to
+    }
+
+    fn step(&self, cell: usize, dx: i32, dz: i32) -> Option<usize> {
+        let dir = NEIGHBORS.iter().position(|&d| d == (dx, dz))?;
+        self.cells[cell].neighbors[dir].map(|c| c as usize)
+    }
+
+    /// Segments of the boundary of the navmesh, raised by `offset`, e.g. to draw it over the
+    /// geometry it was generated from.
+    pub fn outline(&self, offset: f32) -> Vec<[Vec3; 2]> {
+        let half = self.cell_size / 2.;
+        let mut segments = vec![];
+        for (i, cell) in self.cells.iter().enumerate() {
+            let center = self.cell_center(i) + Vec3::Y * offset;
+            for (dir, (dx, dz)) in NEIGHBORS.into_iter().enumerate() {
+                if cell.neighbors[dir].is_some() {
+                    continue;
+                }
+                let normal = Vec3::new(dx as f32, 0., dz as f32) * half;
+                let tangent = Vec3::new(-dz as f32, 0., dx as f32) * half;
+                segments.push([center + normal - tangent, center + normal + tangent]);
+            }
+        }
+        segments
+    }
+}
+
+/// Cell of the open set of A*, ordered by lowest estimate first.
+#[derive(Debug, Copy, Clone)]
+struct OpenCell {
+    cell: usize,
+    estimate: f32,
+}
+
+impl PartialEq for OpenCell {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for OpenCell {}
+
+impl PartialOrd for OpenCell {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for OpenCell {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.estimate.total_cmp(&self.estimate)
+    }
+}
+
+/// Cell size and number of columns along X and Z of the grid covering `extent`, widening the
+/// cells to stay within [`MAX_COLUMNS`].
+fn grid_size(extent: Vec2, mut cell_size: f32) -> (f32, UVec2) {
+    let columns = |cell_size: f32| (extent / cell_size).ceil() + 1.;
+    let mut size = columns(cell_size);
+    while size.x * size.y > MAX_COLUMNS as f32 {
+        cell_size *= (size.x * size.y / MAX_COLUMNS as f32).sqrt().max(1.01);
+        size = columns(cell_size);
+    }
+    (cell_size, size.as_uvec2().max(UVec2::ONE))
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::vec3;
+
+    use super::*;
+
+    /// Two triangles covering the square from `min` to `max` on the XZ plane at height `y`.
+    fn quad(min: Vec2, max: Vec2, y: f32) -> [[Vec3; 3]; 2] {
+        let [a, b, c, d] = [
+            vec3(min.x, y, min.y),
+            vec3(max.x, y, min.y),
+            vec3(max.x, y, max.y),
+            vec3(min.x, y, max.y),
+        ];
+        [[a, c, b], [a, d, c]]
+    }
+
+    fn settings() -> NavMeshSettings {
+        NavMeshSettings {
+            cell_size: 0.5,
+            agent_radius: 0.,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn path_goes_around_obstacles() {
+        let mut triangles = quad(Vec2::ZERO, Vec2::splat(10.), 0.).to_vec();
+        // Top of a wall across the middle of the floor, leaving a gap at the far end
+        triangles.extend(quad(Vec2::new(4., 0.), Vec2::new(6., 8.), 1.));
+        let navmesh = NavMesh::build(triangles, &settings());
+        let start = vec3(1., 0., 1.);
+        let end = vec3(9., 0., 1.);
+        let path = navmesh.find_path(start, end).unwrap();
+        assert_eq!(path.first(), Some(&start));
+        assert_eq!(path.last(), Some(&end));
+        assert!(path.iter().any(|p| p.z > 8.));
+        let length = path.windows(2).map(|w| w[0].distance(w[1])).sum::<f32>();
+        assert!(length > 14. && length < 20.);
+        // The top of the wall is walkable, but out of reach
+        assert!(navmesh.find_path(start, vec3(5., 1., 4.)).is_none());
+    }
+
+    #[test]
+    fn straight_path_skips_cells() {
+        let navmesh = NavMesh::build(quad(Vec2::ZERO, Vec2::splat(10.), 0.), &settings());
+        let path = navmesh
+            .find_path(vec3(1., 0., 1.), vec3(9., 0., 7.))
+            .unwrap();
+        assert_eq!(path.len(), 2);
+    }
+
+    #[test]
+    fn agent_radius_erodes_edges() {
+        let floor = quad(Vec2::ZERO, Vec2::splat(4.), 0.);
+        let navmesh = NavMesh::build(floor, &settings());
+        assert_eq!(navmesh.cells().len(), 64);
+        let eroded = NavMesh::build(
+            floor,
+            &NavMeshSettings {
+                agent_radius: 0.5,
+                ..settings()
+            },
+        );
+        assert_eq!(eroded.cells().len(), 36);
+        assert!(eroded.find_cell(vec3(0.2, 0., 0.2)).is_none());
+        assert_eq!(eroded.outline(0.).len(), 24);
+    }
+
+    #[test]
+    fn large_scenes_get_wider_cells() {
+        assert_eq!(grid_size(Vec2::splat(4.), 0.5), (0.5, UVec2::splat(9)));
+        let (cell_size, size) = grid_size(Vec2::new(1e9, 2e9), 0.25);
+        assert!(size.x * size.y <= MAX_COLUMNS);
+        assert!(cell_size >= 2e9 / size.y as f32);
+    }
+}
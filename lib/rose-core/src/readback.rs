@@ -0,0 +1,165 @@
+//! Asynchronous reads of pixels from the GPU.
+//!
+//! Downloading a texture waits for the GPU to finish every command drawing into it, stalling the
+//! pipeline. A [`Readback`] instead has the GPU copy the pixels into a pixel buffer object, and is
+//! polled on later frames until a fence signals that the copy is done, at which point mapping the
+//! buffer no longer waits.
+
+use std::time::Duration;
+
+use eyre::Result;
+use glam::UVec2;
+
+use violette::{framebuffer::Framebuffer, gl, texture::Texture};
+
+use crate::gl_ext::{self, Fence, PixelPackBuffer};
+
+/// Layout of the pixels of a [`Readback`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ReadbackFormat {
+    /// Four floats per pixel.
+    Rgba32F,
+    /// Three floats per pixel.
+    Rgb32F,
+    /// One float per pixel.
+    R32F,
+    /// Four bytes per pixel.
+    Rgba8,
+}
+
+impl ReadbackFormat {
+    fn gl_format(self) -> (u32, u32) {
+        match self {
+            Self::Rgba32F => (gl::RGBA, gl::FLOAT),
+            Self::Rgb32F => (gl::RGB, gl::FLOAT),
+            Self::R32F => (gl::RED, gl::FLOAT),
+            Self::Rgba8 => (gl::RGBA, gl::UNSIGNED_BYTE),
+        }
+    }
+
+    pub fn bytes_per_pixel(self) -> usize {
+        match self {
+            Self::Rgba32F => 16,
+            Self::Rgb32F => 12,
+            Self::R32F => 4,
+            Self::Rgba8 => 4,
+        }
+    }
+}
+
+/// Pixels being copied from the GPU, row by row starting from the bottom.
+#[derive(Debug)]
+pub struct Readback {
+    buffer: PixelPackBuffer,
+    fence: Fence,
+    size: UVec2,
+    format: ReadbackFormat,
+    /// Whether the pixels were already returned by [`Self::poll`].
+    taken: bool,
+}
+
+impl Readback {
+    /// Start reading the mipmap level of the texture.
+    pub fn texture<F>(texture: &Texture<F>, level: u32, format: ReadbackFormat) -> Result<Self> {
+        let size = gl_ext::texture_level_size(texture, level);
+        Self::start(size, format, |buffer| {
+            buffer.copy_texture(texture, level, format.gl_format())
+        })
+    }
+
+    /// Start reading a rectangle of the mipmap level of the texture, e.g. the pixel under the
+    /// cursor, without copying the rest of the level.
+    pub fn texture_region<F>(
+        texture: &Texture<F>,
+        level: u32,
+        min: UVec2,
+        size: UVec2,
+        format: ReadbackFormat,
+    ) -> Result<Self> {
+        let mipmap = texture
+            .mipmap(level)
+            .ok_or_else(|| eyre::eyre!("Texture has no mipmap level {}", level))?;
+        let framebuffer = Framebuffer::new();
+        framebuffer.attach_color(0, mipmap)?;
+        framebuffer.bind();
+        let readback = Self::framebuffer(min, size, format);
+        framebuffer.unbind();
+        // Deletion of the framebuffer is deferred by the driver until the copy is done
+        readback
+    }
+
+    /// Start reading the rectangle of the framebuffer bound for reading, e.g. the backbuffer.
+    pub fn framebuffer(min: UVec2, size: UVec2, format: ReadbackFormat) -> Result<Self> {
+        Self::start(size, format, |buffer| {
+            buffer.copy_framebuffer(min, size, format.gl_format())
+        })
+    }
+
+    /// Copy into a new pixel buffer object with `copy`.
+    fn start(
+        size: UVec2,
+        format: ReadbackFormat,
+        copy: impl FnOnce(&PixelPackBuffer),
+    ) -> Result<Self> {
+        if size.cmpeq(UVec2::ZERO).any() {
+            eyre::bail!("Cannot read back an empty image");
+        }
+        let len = size.x as usize * size.y as usize * format.bytes_per_pixel();
+        let buffer = PixelPackBuffer::new(len, copy);
+        let fence = Fence::new();
+        Ok(Self {
+            buffer,
+            fence,
+            size,
+            format,
+            taken: false,
+        })
+    }
+
+    pub fn size(&self) -> UVec2 {
+        self.size
+    }
+
+    pub fn format(&self) -> ReadbackFormat {
+        self.format
+    }
+
+    /// Whether the copy is done, so that [`Self::poll`] returns the pixels without waiting.
+    pub fn is_ready(&self) -> bool {
+        if self.taken {
+            return false;
+        }
+        self.fence.is_signaled()
+    }
+
+    /// The pixels once the copy is done, only returned once.
+    pub fn poll(&mut self) -> Option<Vec<u8>> {
+        if !self.is_ready() {
+            return None;
+        }
+        self.taken = true;
+        Some(self.map())
+    }
+
+    /// [`Self::poll`] with the pixels cast to `T`, e.g. `[f32; 3]` for [`ReadbackFormat::Rgb32F`].
+    pub fn poll_pixels<T: bytemuck::Pod>(&mut self) -> Option<Vec<T>> {
+        Some(bytemuck::pod_collect_to_vec(&self.poll()?))
+    }
+
+    /// Wait for the copy to be done and return the pixels, stalling like a download would.
+    pub fn wait(mut self) -> Vec<u8> {
+        while !self.taken && !self.is_ready() {
+            self.fence.wait(Duration::from_millis(1));
+        }
+        self.taken = true;
+        self.map()
+    }
+
+    fn map(&self) -> Vec<u8> {
+        self.buffer.read().unwrap_or_else(|| {
+            tracing::warn!("Cannot map readback buffer");
+            let len = self.size.x as usize * self.size.y as usize * self.format.bytes_per_pixel();
+            vec![0; len]
+        })
+    }
+}
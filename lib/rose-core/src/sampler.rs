@@ -0,0 +1,158 @@
+//! GL sampler objects, which hold the filtering and wrapping of textures separately from the
+//! textures themselves. A sampler bound to a texture unit overrides the sampling parameters of the
+//! texture bound there, so the same texture can be sampled differently by different draws.
+
+use std::{
+    collections::HashMap,
+    hash::{Hash, Hasher},
+};
+
+use violette::{
+    gl,
+    texture::{SampleMode, TextureWrap},
+};
+
+use crate::gl_ext::SamplerObject;
+
+/// Filtering and wrapping of a sampler.
+#[derive(Debug, Copy, Clone)]
+pub struct SamplerState {
+    pub min: SampleMode,
+    /// Filtering between mipmap levels, or `None` to only sample the base level.
+    pub mipmap: Option<SampleMode>,
+    pub mag: SampleMode,
+    pub wrap_s: TextureWrap,
+    pub wrap_t: TextureWrap,
+    pub wrap_r: TextureWrap,
+}
+
+impl Default for SamplerState {
+    fn default() -> Self {
+        Self::linear()
+    }
+}
+
+impl SamplerState {
+    /// Trilinear filtering, clamping to the edges.
+    pub fn linear() -> Self {
+        Self {
+            min: SampleMode::Linear,
+            mipmap: Some(SampleMode::Linear),
+            mag: SampleMode::Linear,
+            wrap_s: TextureWrap::ClampEdge,
+            wrap_t: TextureWrap::ClampEdge,
+            wrap_r: TextureWrap::ClampEdge,
+        }
+    }
+
+    /// Nearest texel of the nearest mipmap level, e.g. for pixel art; clamps to the edges.
+    pub fn nearest() -> Self {
+        Self {
+            min: SampleMode::Nearest,
+            mipmap: Some(SampleMode::Nearest),
+            mag: SampleMode::Nearest,
+            ..Self::linear()
+        }
+    }
+
+    /// Use the wrapping mode on every axis.
+    pub fn with_wrap(mut self, wrap: TextureWrap) -> Self {
+        self.wrap_s = wrap;
+        self.wrap_t = wrap;
+        self.wrap_r = wrap;
+        self
+    }
+
+    fn min_filter(&self) -> u32 {
+        match (self.min, self.mipmap) {
+            (SampleMode::Nearest, None) => gl::NEAREST,
+            (SampleMode::Linear, None) => gl::LINEAR,
+            (SampleMode::Nearest, Some(SampleMode::Nearest)) => gl::NEAREST_MIPMAP_NEAREST,
+            (SampleMode::Nearest, Some(SampleMode::Linear)) => gl::NEAREST_MIPMAP_LINEAR,
+            (SampleMode::Linear, Some(SampleMode::Nearest)) => gl::LINEAR_MIPMAP_NEAREST,
+            (SampleMode::Linear, Some(SampleMode::Linear)) => gl::LINEAR_MIPMAP_LINEAR,
+        }
+    }
+
+    fn mag_filter(&self) -> u32 {
+        match self.mag {
+            SampleMode::Nearest => gl::NEAREST,
+            SampleMode::Linear => gl::LINEAR,
+        }
+    }
+
+    /// GL parameters of the sampler, which also identify it in the [`SamplerCache`]. The violette
+    /// wrapping modes hold their GL value.
+    fn key(&self) -> [u32; 5] {
+        [
+            self.min_filter(),
+            self.mag_filter(),
+            self.wrap_s as u32,
+            self.wrap_t as u32,
+            self.wrap_r as u32,
+        ]
+    }
+}
+
+impl PartialEq for SamplerState {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for SamplerState {}
+
+impl Hash for SamplerState {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key().hash(state);
+    }
+}
+
+/// GL sampler object.
+#[derive(Debug)]
+pub struct Sampler(SamplerObject);
+
+impl Sampler {
+    pub fn new(state: &SamplerState) -> Self {
+        let (min, mag) = (state.min_filter(), state.mag_filter());
+        let wrap = [state.wrap_s, state.wrap_t, state.wrap_r];
+        Self(SamplerObject::new(min, mag, wrap))
+    }
+
+    /// Override the sampling of the texture bound to the texture unit.
+    pub fn bind(&self, unit: u32) {
+        self.0.bind(unit);
+    }
+
+    /// Go back to the sampling parameters of the texture bound to the texture unit.
+    pub fn unbind(unit: u32) {
+        SamplerObject::unbind(unit);
+    }
+}
+
+/// Samplers created on demand and shared by every user of the same state, as there are only a
+/// handful of distinct states in practice.
+#[derive(Debug, Default)]
+pub struct SamplerCache {
+    samplers: HashMap<SamplerState, Sampler>,
+}
+
+impl SamplerCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&mut self, state: &SamplerState) -> &Sampler {
+        self.samplers
+            .entry(*state)
+            .or_insert_with(|| Sampler::new(state))
+    }
+
+    pub fn len(&self) -> usize {
+        self.samplers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samplers.is_empty()
+    }
+}
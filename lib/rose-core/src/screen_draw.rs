@@ -1,5 +1,5 @@
 use std::cell::{Ref, RefCell};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use eyre::{Context, Result};
 use once_cell::sync::Lazy;
@@ -13,7 +13,9 @@ use violette::{
 };
 
 use crate::utils::{
+    draw_counters,
     reload_watcher::{ReloadFileProxy, ReloadWatcher},
+    shader_errors::file_map,
     thread_guard::ThreadGuard,
 };
 
@@ -30,6 +32,8 @@ static SCREEN_VAO: Lazy<ThreadGuard<VertexArray>> = Lazy::new(|| {
 pub struct ScreenDraw {
     program: RefCell<Program>,
     reload_watcher: ReloadFileProxy,
+    /// Fragment shader file, which comes after its includes in the watched files.
+    main_file: PathBuf,
 }
 
 impl ScreenDraw {
@@ -44,9 +48,11 @@ impl ScreenDraw {
             .with_shader(frag_shader.id)
             .link()?;
         program.validate()?;
+        let main_file = reload_watcher.paths().pop().unwrap_or_default();
         Ok(Self {
             program: RefCell::new(program),
             reload_watcher,
+            main_file,
         })
     }
 
@@ -58,18 +64,7 @@ impl ScreenDraw {
             reload_watcher.proxy(files.iter().map(|(p, _)| p.as_path())),
         )
         .with_context(|| format!("Loading shader {}", filepath.display()))
-        .with_context(|| {
-            format!(
-                "File map:\n{}",
-                files
-                    .iter()
-                    .map(|(p, _)| p.as_path())
-                    .enumerate()
-                    .map(|(ix, p)| format!("\t{} => {}", ix, p.display()))
-                    .collect::<Vec<_>>()
-                    .join("\n")
-            )
-        })
+        .with_context(|| file_map(files.iter().map(|(p, _)| p)))
     }
 
     pub fn program(&self) -> Ref<Program> {
@@ -80,32 +75,25 @@ impl ScreenDraw {
     pub fn draw(&self, framebuffer: &Framebuffer) -> Result<()> {
         match self.program.try_borrow_mut() {
             Ok(mut program) => {
-                if self.reload_watcher.should_reload() {
-                    let mut paths = self.reload_watcher.paths();
-                    if let Some(frag_path) = paths.next() {
-                        let files = glsl_preprocessor::load_and_parse(frag_path)?;
-                        tracing::info!(message="Reloading screen-space shader", path=%frag_path.display());
-                        let new_program_result = (|| {
-                            let vs = VertexShader::new(SCREEN_VS)?;
-                            let fs = FragmentShader::new_multiple(
-                                files.iter().map(|(_, s)| s.as_str()),
-                            )?;
-                            let program = Program::new()
-                                .with_shader(vs.id)
-                                .with_shader(fs.id)
-                                .link()?;
-                            program.validate()?;
-                            Ok::<_, eyre::Report>(program)
-                        })();
-                        match new_program_result {
-                            Ok(new_program) => {
-                                let _ = std::mem::replace(&mut *program, new_program);
-                            }
-                            Err(err) => {
-                                tracing::warn!("Cannot reload shader: {}", err);
-                            }
-                        }
-                    }
+                let reloaded = self.reload_watcher.reload_shader(
+                    "screen-space shader",
+                    &self.main_file,
+                    || {
+                        let files = glsl_preprocessor::load_and_parse(&self.main_file)?;
+                        let vs = VertexShader::new(SCREEN_VS)?;
+                        let fs =
+                            FragmentShader::new_multiple(files.iter().map(|(_, s)| s.as_str()))
+                                .with_context(|| file_map(files.iter().map(|(p, _)| p)))?;
+                        let program = Program::new()
+                            .with_shader(vs.id)
+                            .with_shader(fs.id)
+                            .link()?;
+                        program.validate()?;
+                        Ok((program, files.into_iter().map(|(p, _)| p).collect()))
+                    },
+                );
+                if let Some(Ok(new_program)) = reloaded {
+                    *program = new_program;
                 }
             }
             Err(err) => {
@@ -113,12 +101,9 @@ impl ScreenDraw {
             }
         }
         Framebuffer::disable_depth_test();
-        framebuffer.draw_elements(
-            &self.program.borrow(),
-            &SCREEN_VAO,
-            DrawMode::Triangles,
-            0..6,
-        )?;
+        let program = self.program.borrow();
+        framebuffer.draw_elements(&program, &SCREEN_VAO, DrawMode::Triangles, 0..6)?;
+        draw_counters::record_draw(&program, 2);
         Ok(())
     }
 }
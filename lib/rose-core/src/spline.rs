@@ -0,0 +1,319 @@
+//! Curves through control points, and their parameterization by distance, e.g. for camera rails
+//! and moving platforms.
+//!
+//! Splines are parameterized by `t` going from 0 to [`Spline::segment_count`], each segment
+//! spanning a unit of `t`. The speed along the curve varies within segments; use [`ArcLength`]
+//! to move along the curve at a constant speed.
+
+use glam::Vec3;
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
+pub enum SplineKind {
+    /// Curve going through every control point.
+    #[default]
+    CatmullRom,
+    /// Cubic Bézier segments. The curve goes through every third point, the two points in between
+    /// pulling the curve towards them.
+    Bezier,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
+pub struct Spline {
+    pub kind: SplineKind,
+    pub points: Vec<Vec3>,
+    /// Connect the end of the curve back to its start.
+    pub closed: bool,
+}
+
+impl Spline {
+    pub fn catmull_rom(points: impl IntoIterator<Item = Vec3>) -> Self {
+        Self {
+            kind: SplineKind::CatmullRom,
+            points: points.into_iter().collect(),
+            closed: false,
+        }
+    }
+
+    pub fn bezier(points: impl IntoIterator<Item = Vec3>) -> Self {
+        Self {
+            kind: SplineKind::Bezier,
+            points: points.into_iter().collect(),
+            closed: false,
+        }
+    }
+
+    pub fn closed(mut self, closed: bool) -> Self {
+        self.closed = closed;
+        self
+    }
+
+    pub fn segment_count(&self) -> usize {
+        let len = self.points.len();
+        match (self.kind, self.closed) {
+            _ if len < 2 => 0,
+            (SplineKind::CatmullRom, false) => len - 1,
+            (SplineKind::CatmullRom, true) => len,
+            (SplineKind::Bezier, false) => (len - 1) / 3,
+            (SplineKind::Bezier, true) => len / 3,
+        }
+    }
+
+    /// Control points of the segment as a cubic Bézier curve.
+    fn segment(&self, i: usize) -> [Vec3; 4] {
+        let len = self.points.len();
+        let point = |i: isize| {
+            let i = if self.closed {
+                i.rem_euclid(len as isize)
+            } else {
+                i.clamp(0, len as isize - 1)
+            };
+            self.points[i as usize]
+        };
+        let i = i as isize;
+        match self.kind {
+            SplineKind::CatmullRom => {
+                let [p0, p1, p2, p3] = [i - 1, i, i + 1, i + 2].map(point);
+                [p1, p1 + (p2 - p0) / 6., p2 - (p3 - p1) / 6., p2]
+            }
+            SplineKind::Bezier => [3 * i, 3 * i + 1, 3 * i + 2, 3 * i + 3].map(point),
+        }
+    }
+
+    /// Segment index and parameter within the segment at `t`, clamped to the curve.
+    fn locate(&self, t: f32) -> (usize, f32) {
+        let count = self.segment_count();
+        let t = t.clamp(0., count as f32);
+        let i = (t.floor() as usize).min(count - 1);
+        (i, t - i as f32)
+    }
+
+    /// Point on the curve at `t`, see the [module documentation](self).
+    pub fn sample(&self, t: f32) -> Vec3 {
+        if self.segment_count() == 0 {
+            return self.points.first().copied().unwrap_or(Vec3::ZERO);
+        }
+        let (i, t) = self.locate(t);
+        let [b0, b1, b2, b3] = self.segment(i);
+        let u = 1. - t;
+        b0 * (u * u * u) + b1 * (3. * u * u * t) + b2 * (3. * u * t * t) + b3 * (t * t * t)
+    }
+
+    /// Derivative of the curve at `t`, pointing forward along the curve.
+    pub fn tangent(&self, t: f32) -> Vec3 {
+        if self.segment_count() == 0 {
+            return Vec3::ZERO;
+        }
+        let (i, t) = self.locate(t);
+        let [b0, b1, b2, b3] = self.segment(i);
+        let u = 1. - t;
+        (b1 - b0) * (3. * u * u) + (b2 - b1) * (6. * u * t) + (b3 - b2) * (3. * t * t)
+    }
+
+    /// Whether the control point is on the curve; Bézier curves only go through every third
+    /// point.
+    pub fn is_knot(&self, index: usize) -> bool {
+        match self.kind {
+            SplineKind::CatmullRom => true,
+            SplineKind::Bezier => index % 3 == 0,
+        }
+    }
+
+    /// Continue the curve to `point`. Bézier curves get a new segment, with straight handles.
+    pub fn push_point(&mut self, point: Vec3) {
+        let (Some(&first), Some(&last)) = (self.points.first(), self.points.last()) else {
+            self.points.push(point);
+            return;
+        };
+        match (self.kind, self.closed) {
+            (SplineKind::CatmullRom, _) => self.points.push(point),
+            // The handles after the last point lead back to the first one
+            (SplineKind::Bezier, true) => self.points.extend([
+                point,
+                point.lerp(first, 1. / 3.),
+                point.lerp(first, 2. / 3.),
+            ]),
+            (SplineKind::Bezier, false) => {
+                self.points
+                    .extend([last.lerp(point, 1. / 3.), last.lerp(point, 2. / 3.), point])
+            }
+        }
+    }
+
+    /// Split the segment in two at its middle, returning the index of the new point on the curve.
+    /// Bézier segments keep their shape.
+    pub fn split_segment(&mut self, segment: usize) -> Option<usize> {
+        if segment >= self.segment_count() {
+            return None;
+        }
+        match self.kind {
+            SplineKind::CatmullRom => {
+                let point = self.sample(segment as f32 + 0.5);
+                self.points.insert(segment + 1, point);
+                Some(segment + 1)
+            }
+            SplineKind::Bezier => {
+                // De Casteljau subdivision at the middle of the segment
+                let [b0, b1, b2, b3] = self.segment(segment);
+                let (l1, mid, r2) = ((b0 + b1) / 2., (b1 + b2) / 2., (b2 + b3) / 2.);
+                let (l2, r1) = ((l1 + mid) / 2., (mid + r2) / 2.);
+                let start = 3 * segment + 1;
+                self.points
+                    .splice(start..start + 2, [l1, l2, (l2 + r1) / 2., r1, r2]);
+                Some(start + 2)
+            }
+        }
+    }
+
+    /// Remove the point from the curve, along with its handles for Bézier curves. Returns whether
+    /// it was removed; handles of Bézier curves and points the curve cannot do without are not.
+    pub fn remove_point(&mut self, index: usize) -> bool {
+        let len = self.points.len();
+        if index >= len || !self.is_knot(index) {
+            return false;
+        }
+        match self.kind {
+            SplineKind::CatmullRom if len > 2 => {
+                self.points.remove(index);
+            }
+            SplineKind::Bezier if len > 4 => match (index, self.closed) {
+                // The in-handle of the first point is the last point, and the in-handle of the next
+                // point moves to the end to close the curve
+                (0, true) => {
+                    self.points.drain(0..2);
+                    self.points.pop();
+                    self.points.rotate_left(1);
+                }
+                (0, false) => {
+                    self.points.drain(0..3);
+                }
+                (i, false) if i == len - 1 => {
+                    self.points.truncate(len - 3);
+                }
+                (i, _) => {
+                    self.points.drain(i - 1..=i + 1);
+                }
+            },
+            _ => return false,
+        }
+        true
+    }
+
+    /// Measure the curve, approximating each segment with `samples_per_segment` straight lines.
+    pub fn arc_length(&self, samples_per_segment: usize) -> ArcLength {
+        let steps = self.segment_count() * samples_per_segment.max(1);
+        let mut params = vec![0.];
+        let mut distances = vec![0.];
+        let mut previous = self.sample(0.);
+        for step in 1..=steps {
+            let t = step as f32 / samples_per_segment.max(1) as f32;
+            let point = self.sample(t);
+            params.push(t);
+            distances.push(distances.last().unwrap() + previous.distance(point));
+            previous = point;
+        }
+        ArcLength { params, distances }
+    }
+}
+
+/// Table mapping distances along a [`Spline`] to its parameter, see [`Spline::arc_length`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ArcLength {
+    params: Vec<f32>,
+    distances: Vec<f32>,
+}
+
+impl ArcLength {
+    /// Total length of the curve.
+    pub fn length(&self) -> f32 {
+        self.distances.last().copied().unwrap_or(0.)
+    }
+
+    /// Parameter of the curve at the given distance from its start, clamped to the curve.
+    pub fn param_at(&self, distance: f32) -> f32 {
+        let i = self.distances.partition_point(|d| *d < distance);
+        if i == 0 {
+            return self.params.first().copied().unwrap_or(0.);
+        }
+        if i == self.distances.len() {
+            return self.params[i - 1];
+        }
+        let (d0, d1) = (self.distances[i - 1], self.distances[i]);
+        let k = (distance - d0) / (d1 - d0).max(f32::EPSILON);
+        self.params[i - 1] + (self.params[i] - self.params[i - 1]) * k
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::vec3;
+
+    use super::*;
+
+    #[test]
+    fn catmull_rom_goes_through_points() {
+        let points = [
+            Vec3::ZERO,
+            vec3(1., 2., 0.),
+            vec3(3., 0., 1.),
+            vec3(4., 1., 0.),
+        ];
+        let spline = Spline::catmull_rom(points);
+        assert_eq!(spline.segment_count(), 3);
+        for (i, point) in points.into_iter().enumerate() {
+            assert!(spline.sample(i as f32).abs_diff_eq(point, 1e-5));
+        }
+        let closed = spline.closed(true);
+        assert_eq!(closed.segment_count(), 4);
+        assert!(closed.sample(4.).abs_diff_eq(points[0], 1e-5));
+    }
+
+    #[test]
+    fn arc_length_gives_constant_speed() {
+        // Straight line whose control points bunch up the parameter towards the start
+        let spline = Spline::bezier([Vec3::ZERO, Vec3::ZERO, vec3(1., 0., 0.), vec3(4., 0., 0.)]);
+        let table = spline.arc_length(64);
+        assert!((table.length() - 4.).abs() < 1e-3);
+        let halfway = spline.sample(table.param_at(2.));
+        assert!(halfway.abs_diff_eq(vec3(2., 0., 0.), 1e-2));
+        assert_eq!(table.param_at(-1.), 0.);
+        assert_eq!(table.param_at(10.), 1.);
+    }
+
+    #[test]
+    fn editing_bezier_points_keeps_segments() {
+        let mut spline = Spline::bezier([Vec3::ZERO, Vec3::Y, vec3(1., 1., 0.), Vec3::X]);
+        let before: Vec<_> = (0..=8).map(|i| spline.sample(i as f32 / 8.)).collect();
+        assert_eq!(spline.split_segment(0), Some(3));
+        assert_eq!(spline.segment_count(), 2);
+        // Splitting keeps the shape of the curve
+        for (i, point) in before.into_iter().enumerate() {
+            assert!(spline.sample(i as f32 / 4.).abs_diff_eq(point, 1e-5));
+        }
+
+        spline.push_point(vec3(2., 0., 0.));
+        assert_eq!(spline.points.len(), 10);
+        assert!(
+            !spline.remove_point(4),
+            "handles are not removed on their own"
+        );
+        assert!(spline.remove_point(3));
+        assert_eq!(spline.points.len(), 7);
+        assert_eq!(spline.segment_count(), 2);
+        assert!(spline.remove_point(6));
+        assert!(!spline.remove_point(0), "a single segment is left");
+    }
+
+    #[test]
+    fn removing_the_first_point_of_a_closed_bezier_keeps_the_others() {
+        let points: Vec<_> = (0..9).map(|i| vec3(i as f32, 0., 0.)).collect();
+        let mut spline = Spline::bezier(points.clone()).closed(true);
+        assert_eq!(spline.segment_count(), 3);
+        assert!(spline.remove_point(0));
+        assert_eq!(spline.segment_count(), 2);
+        assert_eq!(spline.points, [3, 4, 5, 6, 7, 2].map(|i| points[i]));
+        // The curve now closes from the last point to the second one through their handles
+        assert_eq!(spline.segment(1), [6, 7, 2, 3].map(|i| points[i]));
+    }
+}
@@ -6,7 +6,7 @@ use std::{
 
 use glam::{EulerRot, Mat4, Quat, Vec3};
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 #[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
 pub struct Transform {
     pub position: Vec3,
@@ -87,6 +87,13 @@ impl Transform {
         self
     }
 
+    /// Transform with its position relative to `origin`, subtracted in double precision so that
+    /// objects far from the world origin keep their precision once uploaded to the GPU.
+    pub fn relative_to(mut self, origin: Vec3) -> Self {
+        self.position = (self.position.as_dvec3() - origin.as_dvec3()).as_vec3();
+        self
+    }
+
     pub fn matrix(&self) -> Mat4 {
         Mat4::from_scale_rotation_translation(self.scale, self.rotation.normalize(), self.position)
         // Mat4::from_translation(self.position) * Mat4::from_quat(self.rotation) * Mat4::from_scale(self.scale)
@@ -0,0 +1,64 @@
+//! Counters of the draw calls issued through [`crate::mesh::Mesh`] and
+//! [`crate::screen_draw::ScreenDraw`], read back once per frame by the renderer for its statistics.
+
+use std::cell::Cell;
+
+use violette::program::Program;
+
+/// Work submitted to the GPU since the counters were last taken.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct DrawCounters {
+    pub draw_calls: usize,
+    pub triangles: usize,
+    /// Textures bound to samplers through [`CountBind::counted`].
+    pub texture_binds: usize,
+    /// Draw calls using a different program than the previous one.
+    pub program_switches: usize,
+}
+
+thread_local! {
+    static COUNTERS: Cell<DrawCounters> = Cell::new(DrawCounters::default());
+    /// GL name of the last program drawn with, 0 being no program.
+    static LAST_PROGRAM: Cell<u32> = Cell::new(0);
+}
+
+fn update(f: impl FnOnce(&mut DrawCounters)) {
+    COUNTERS.with(|counters| {
+        let mut value = counters.get();
+        f(&mut value);
+        counters.set(value);
+    });
+}
+
+/// Record a draw call of `triangles` triangles with the program. Programs are told apart by their
+/// GL name, as the same program can be reached through different borrows.
+pub fn record_draw(program: &Program, triangles: usize) {
+    let id: u32 = program.id.into();
+    let program_switched = LAST_PROGRAM.with(|last| last.replace(id)) != id;
+    update(|counters| {
+        counters.draw_calls += 1;
+        counters.triangles += triangles;
+        counters.program_switches += program_switched as usize;
+    });
+}
+
+/// Counts the texture bind made by `Texture::as_uniform` at the place it is made, as
+/// `texture.as_uniform(unit).counted()?`.
+pub trait CountBind {
+    fn counted(self) -> Self;
+}
+
+impl<T, E> CountBind for Result<T, E> {
+    fn counted(self) -> Self {
+        if self.is_ok() {
+            update(|counters| counters.texture_binds += 1);
+        }
+        self
+    }
+}
+
+/// Return the counters accumulated on this thread and reset them.
+pub fn take() -> DrawCounters {
+    LAST_PROGRAM.with(|last| last.set(0));
+    COUNTERS.with(|counters| counters.take())
+}
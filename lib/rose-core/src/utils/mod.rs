@@ -1,2 +1,6 @@
+pub mod draw_counters;
 pub mod reload_watcher;
+pub mod rng;
+pub mod shader_errors;
+pub mod snapshot;
 pub mod thread_guard;
@@ -1,21 +1,103 @@
-use std::sync::atomic::{AtomicBool, Ordering};
+#[cfg(feature = "hot-reload")]
+use std::{
+    collections::{HashMap, HashSet},
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+    thread,
+};
 use std::{
-    collections::HashSet,
+    fmt,
     path::{Path, PathBuf},
     sync::{Arc, Mutex},
-    thread::{self},
 };
 
 #[cfg(feature = "hot-reload")]
-use notify::{recommended_watcher, EventKind, RecursiveMode, Watcher};
+use notify::{recommended_watcher, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+
+use crate::utils::shader_errors;
 
-#[derive(Debug)]
+/// Watches files for changes, to hot-reload shaders and assets. The base path is watched
+/// recursively; files outside of it get their directory watched when a proxy is made for them.
 pub struct ReloadWatcher {
+    shared: Arc<Shared>,
+    #[cfg(feature = "hot-reload")]
+    cancel_thread: Arc<AtomicBool>,
+}
+
+/// State shared between the watcher, its proxies and the thread receiving the events.
+struct Shared {
     base_path: PathBuf,
     #[cfg(feature = "hot-reload")]
-    to_reload: Arc<Mutex<HashSet<PathBuf>>>,
+    watcher: Option<Mutex<RecommendedWatcher>>,
     #[cfg(feature = "hot-reload")]
-    cancel_thread: Arc<AtomicBool>,
+    watched_dirs: Mutex<HashSet<PathBuf>>,
+    #[cfg(feature = "hot-reload")]
+    changes: Arc<Mutex<Changes>>,
+}
+
+impl Shared {
+    fn resolve<'p>(&self, paths: impl IntoIterator<Item = &'p Path>) -> Vec<PathBuf> {
+        let files = Vec::from_iter(paths.into_iter().map(|path| self.base_path.join(path)));
+        #[cfg(feature = "hot-reload")]
+        for path in &files {
+            self.watch_file(path);
+        }
+        files
+    }
+
+    /// Watch the directory containing the file, when it is outside of the base path.
+    #[cfg(feature = "hot-reload")]
+    fn watch_file(&self, path: &Path) {
+        let Some(watcher) = &self.watcher else {
+            return;
+        };
+        let Some(dir) = path.parent() else {
+            return;
+        };
+        if dir.starts_with(&self.base_path)
+            || !self.watched_dirs.lock().unwrap().insert(dir.to_path_buf())
+        {
+            return;
+        }
+        let mut watcher = watcher.lock().unwrap();
+        match watcher.watch(dir, RecursiveMode::NonRecursive) {
+            Ok(()) => tracing::info!("Watching {}", dir.display()),
+            Err(err) => tracing::warn!("Cannot watch {}: {}", dir.display(), err),
+        }
+    }
+}
+
+/// Generation of the last change of each modified file. Proxies remember the generation they last
+/// checked, so that a file shared by several of them (e.g. a shader include) reloads all of them.
+#[cfg(feature = "hot-reload")]
+#[derive(Debug, Default)]
+struct Changes {
+    generation: u64,
+    modified: HashMap<PathBuf, u64>,
+}
+
+#[cfg(feature = "hot-reload")]
+impl Changes {
+    fn record(&mut self, paths: impl IntoIterator<Item = PathBuf>) {
+        self.generation += 1;
+        for path in paths {
+            self.modified.insert(path, self.generation);
+        }
+    }
+
+    /// Whether any of the files changed after generation `seen`.
+    fn changed_since(&self, files: &[PathBuf], seen: u64) -> bool {
+        files
+            .iter()
+            .any(|path| matches!(self.modified.get(path), Some(gen) if *gen > seen))
+    }
+}
+
+impl fmt::Debug for ReloadWatcher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReloadWatcher")
+            .field("base_path", &self.shared.base_path)
+            .finish_non_exhaustive()
+    }
 }
 
 #[cfg(feature = "hot-reload")]
@@ -29,17 +111,24 @@ impl ReloadWatcher {
     #[cfg(feature = "hot-reload")]
     pub fn new(base_path: impl Into<PathBuf>) -> Self {
         let base_path = base_path.into();
-        let to_reload = Arc::new(Mutex::new(HashSet::new()));
+        let changes = Arc::new(Mutex::new(Changes::default()));
         let cancel_thread = Arc::new(AtomicBool::new(false));
+        let (tx, rx) = crossbeam_channel::unbounded();
+        let watcher = recommended_watcher(tx)
+            .and_then(|mut watcher| {
+                watcher.watch(&base_path, RecursiveMode::Recursive)?;
+                Ok(watcher)
+            })
+            .map_err(|err| tracing::warn!("Cannot watch {}: {}", base_path.display(), err))
+            .ok();
+        if watcher.is_some() {
+            tracing::info!("Watching {}", base_path.display());
+        }
         thread::spawn({
             let base_path = base_path.clone();
-            let to_reload = to_reload.clone();
+            let changes = changes.clone();
             let cancel_thread = cancel_thread.clone();
             move || {
-                let (tx, rx) = crossbeam_channel::unbounded();
-                let mut watcher = recommended_watcher(tx).unwrap();
-                watcher.watch(&base_path, RecursiveMode::Recursive).unwrap();
-                tracing::info!("Watching {}", base_path.display());
                 for res in rx {
                     if cancel_thread.load(Ordering::Relaxed) {
                         break;
@@ -47,9 +136,9 @@ impl ReloadWatcher {
 
                     match res {
                         Ok(event) => {
-                            let mut set = to_reload.lock().unwrap();
-                            if let EventKind::Modify(..) = event.kind {
-                                set.extend(
+                            // Some tools save by writing a new file over the old one
+                            if let EventKind::Modify(..) | EventKind::Create(..) = event.kind {
+                                changes.lock().unwrap().record(
                                     event
                                         .paths
                                         .into_iter()
@@ -76,8 +165,12 @@ impl ReloadWatcher {
         });
 
         Self {
-            base_path,
-            to_reload,
+            shared: Arc::new(Shared {
+                base_path,
+                watcher: watcher.map(Mutex::new),
+                watched_dirs: Mutex::new(HashSet::new()),
+                changes,
+            }),
             cancel_thread,
         }
     }
@@ -85,28 +178,18 @@ impl ReloadWatcher {
     #[cfg(not(feature = "hot-reload"))]
     pub fn new(base_path: impl AsRef<Path>) -> Self {
         Self {
-            base_path: base_path.as_ref().to_path_buf(),
+            shared: Arc::new(Shared {
+                base_path: base_path.as_ref().to_path_buf(),
+            }),
         }
     }
 
     pub fn base_path(&self) -> &Path {
-        &self.base_path
-    }
-
-    #[cfg(feature = "hot-reload")]
-    pub fn should_reload(&self, path: impl AsRef<Path>) -> bool {
-        self.to_reload
-            .lock()
-            .unwrap()
-            .remove(&self.base_path.join(path.as_ref()))
-    }
-
-    #[cfg(not(feature = "hot-reload"))]
-    #[inline(always)]
-    pub fn should_reload(&self, _path: impl AsRef<Path>) -> bool {
-        false
+        &self.shared.base_path
     }
 
+    /// Proxy to check for changes of the given files. Relative paths are relative to the base
+    /// path; use absolute paths for files elsewhere, e.g. assets.
     pub fn proxy<'p>(&self, files: impl IntoIterator<Item = &'p Path>) -> ReloadFileProxy {
         ReloadFileProxy::from_watcher(self, files)
     }
@@ -116,11 +199,21 @@ impl ReloadWatcher {
     }
 }
 
-#[derive(Debug)]
+/// Files watched together, e.g. a shader program and every file it includes. Changes to any of
+/// them are seen by every proxy watching it, as opposed to only the first one to check.
 pub struct ReloadFileProxy {
-    files: Vec<PathBuf>,
+    files: Mutex<Vec<PathBuf>>,
+    shared: Arc<Shared>,
     #[cfg(feature = "hot-reload")]
-    to_reload: Arc<Mutex<HashSet<PathBuf>>>,
+    seen: AtomicU64,
+}
+
+impl fmt::Debug for ReloadFileProxy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReloadFileProxy")
+            .field("files", &self.files.lock().unwrap())
+            .finish_non_exhaustive()
+    }
 }
 
 impl ReloadFileProxy {
@@ -128,21 +221,23 @@ impl ReloadFileProxy {
         watcher: &ReloadWatcher,
         paths: impl IntoIterator<Item = &'p Path>,
     ) -> Self {
+        let shared = watcher.shared.clone();
+        #[cfg(feature = "hot-reload")]
+        let seen = shared.changes.lock().unwrap().generation;
         Self {
-            files: Vec::from_iter(paths.into_iter().map(|path| watcher.base_path.join(path))),
+            files: Mutex::new(shared.resolve(paths)),
             #[cfg(feature = "hot-reload")]
-            to_reload: watcher.to_reload.clone(),
+            seen: AtomicU64::new(seen),
+            shared,
         }
     }
 
+    /// Whether any of the files changed since the last check.
     #[cfg(feature = "hot-reload")]
     pub fn should_reload(&self) -> bool {
-        let mut to_reload = self.to_reload.lock().unwrap();
-        let mut reload = false;
-        for path in &self.files {
-            reload |= to_reload.remove(path);
-        }
-        reload
+        let changes = self.shared.changes.lock().unwrap();
+        let seen = self.seen.swap(changes.generation, Ordering::Relaxed);
+        changes.changed_since(&self.files.lock().unwrap(), seen)
     }
 
     #[cfg(not(feature = "hot-reload"))]
@@ -151,7 +246,62 @@ impl ReloadFileProxy {
         false
     }
 
-    pub fn paths(&self) -> impl '_ + Iterator<Item = &Path> {
-        self.files.iter().map(|p| p.as_path())
+    /// Replace the watched files, e.g. with the include graph of a shader after reloading it, as
+    /// includes may have been added or removed.
+    pub fn set_paths<'p>(&self, paths: impl IntoIterator<Item = &'p Path>) {
+        *self.files.lock().unwrap() = self.shared.resolve(paths);
+    }
+
+    pub fn paths(&self) -> Vec<PathBuf> {
+        self.files.lock().unwrap().clone()
+    }
+
+    /// Load the shader named `name` again when any of its files changed, then watch the files it
+    /// was loaded from. Failures are logged and reported to [`shader_errors`] under `main_file`
+    /// until the shader loads again. Returns `None` when nothing changed.
+    pub fn reload_shader<T>(
+        &self,
+        name: &str,
+        main_file: &Path,
+        load: impl FnOnce() -> eyre::Result<(T, Vec<PathBuf>)>,
+    ) -> Option<eyre::Result<T>> {
+        if !self.should_reload() {
+            return None;
+        }
+        tracing::info!(message = "Reloading shader", %name, path = %main_file.display());
+        match load() {
+            Ok((shader, files)) => {
+                // Includes may have been added or removed
+                self.set_paths(files.iter().map(|p| p.as_path()));
+                shader_errors::resolve(main_file);
+                Some(Ok(shader))
+            }
+            Err(err) => {
+                tracing::warn!(shader_reload = true, "Cannot reload {}: {:?}", name, err);
+                shader_errors::report(main_file, &err);
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "hot-reload"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shared_includes_are_seen_by_every_program() {
+        let include = PathBuf::from("common/lighting.glsl");
+        let a = vec![include.clone(), PathBuf::from("a.glsl")];
+        let b = vec![include.clone(), PathBuf::from("b.glsl")];
+        let mut changes = Changes::default();
+        changes.record([PathBuf::from("unrelated.glsl")]);
+        let seen = changes.generation;
+        assert!(!changes.changed_since(&a, seen));
+
+        changes.record([include]);
+        assert!(changes.changed_since(&a, seen));
+        assert!(changes.changed_since(&b, seen));
+        assert!(!changes.changed_since(&b, changes.generation));
     }
 }
@@ -0,0 +1,139 @@
+//! Seedable random number generation, so that runs can be reproduced exactly.
+//!
+//! The engine seed is taken from the `ROSE_SEED` environment variable, or picked randomly and
+//! logged, and can be overridden with [`set_seed`], for example when replaying recorded input.
+//! Generators created with [`EngineRng::new`] all derive from it.
+
+use std::sync::Mutex;
+
+pub use rand::Rng;
+use rand::{RngCore, SeedableRng};
+
+/// Environment variable holding the engine seed.
+pub const SEED_ENV_VAR: &str = "ROSE_SEED";
+
+static SEED: Mutex<Option<u64>> = Mutex::new(None);
+
+/// Engine seed, initialized on first use.
+pub fn seed() -> u64 {
+    *SEED.lock().unwrap().get_or_insert_with(|| {
+        let from_env = std::env::var(SEED_ENV_VAR)
+            .ok()
+            .and_then(|s| s.parse().ok());
+        from_env.unwrap_or_else(|| {
+            let seed = rand::random();
+            tracing::info!(%seed, "Using random seed, set {} to reproduce", SEED_ENV_VAR);
+            seed
+        })
+    })
+}
+
+/// Override the engine seed. Only affects generators created afterwards.
+pub fn set_seed(seed: u64) {
+    *SEED.lock().unwrap() = Some(seed);
+}
+
+/// Small and fast generator (SplitMix64) whose output only depends on its seed, and is stable
+/// across versions of the engine and its dependencies.
+#[derive(Debug, Clone)]
+pub struct EngineRng {
+    seed: u64,
+    state: u64,
+}
+
+impl Default for EngineRng {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EngineRng {
+    /// Create a generator from the engine seed.
+    pub fn new() -> Self {
+        Self::with_seed(seed())
+    }
+
+    pub fn with_seed(seed: u64) -> Self {
+        Self { seed, state: seed }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Restart the sequence with a new seed.
+    pub fn reseed(&mut self, seed: u64) {
+        *self = Self::with_seed(seed);
+    }
+
+    /// Create an independent generator, deterministically derived from this one, e.g. to give each
+    /// subsystem its own sequence which does not shift when another one draws more numbers.
+    pub fn fork(&mut self) -> Self {
+        Self::with_seed(self.next_u64())
+    }
+}
+
+impl RngCore for EngineRng {
+    fn next_u32(&mut self) -> u32 {
+        (self.next_u64() >> 32) as u32
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        rand::impls::fill_bytes_via_next(self, dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+impl SeedableRng for EngineRng {
+    type Seed = [u8; 8];
+
+    fn from_seed(seed: Self::Seed) -> Self {
+        Self::with_seed(u64::from_le_bytes(seed))
+    }
+
+    fn seed_from_u64(seed: u64) -> Self {
+        Self::with_seed(seed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_seed_same_sequence() {
+        let mut a = EngineRng::with_seed(42);
+        let mut b = EngineRng::with_seed(42);
+        let a = (0..16).map(|_| a.gen::<u32>()).collect::<Vec<_>>();
+        let b = (0..16).map(|_| b.gen::<u32>()).collect::<Vec<_>>();
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn known_sequence() {
+        // Reference values of SplitMix64 for a zero seed
+        let mut rng = EngineRng::with_seed(0);
+        assert_eq!(rng.next_u64(), 0xe220_a839_7b1d_cdaf);
+        assert_eq!(rng.next_u64(), 0x6e78_9e6a_a1b9_65f4);
+    }
+
+    #[test]
+    fn fork_is_deterministic() {
+        let mut a = EngineRng::with_seed(7);
+        let mut b = EngineRng::with_seed(7);
+        assert_eq!(a.fork().next_u64(), b.fork().next_u64());
+        assert_eq!(a.next_u64(), b.next_u64());
+    }
+}
@@ -0,0 +1,159 @@
+//! Registry of shaders which failed to hot-reload, so that applications can display the errors
+//! until the shaders compile again.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+};
+
+use once_cell::sync::Lazy;
+
+static ERRORS: Lazy<Mutex<BTreeMap<PathBuf, ShaderError>>> = Lazy::new(Default::default);
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+
+/// Compilation or link error of a shader.
+#[derive(Debug, Clone)]
+pub struct ShaderError {
+    /// Main file of the shader, identifying it.
+    pub shader: PathBuf,
+    /// Full error message, including its causes.
+    pub message: String,
+    /// File the error is in, when it can be found out from the compiler output.
+    pub file: Option<PathBuf>,
+    pub line: Option<u32>,
+    /// Value of [`generation`] when this error was reported.
+    pub generation: u64,
+}
+
+impl ShaderError {
+    /// `file:line` location of the error, falling back to the shader path.
+    pub fn location(&self) -> String {
+        let file = self.file.as_deref().unwrap_or(&self.shader);
+        match self.line {
+            Some(line) => format!("{}:{}", file.display(), line),
+            None => file.display().to_string(),
+        }
+    }
+}
+
+/// Record an error for the shader, replacing any previous one.
+pub fn report(shader: impl Into<PathBuf>, err: &eyre::Report) {
+    let shader = shader.into();
+    let (file, line) = find_location(err);
+    let generation = GENERATION.fetch_add(1, Ordering::Relaxed) + 1;
+    let error = ShaderError {
+        message: format!("{:#}", err),
+        shader: shader.clone(),
+        file,
+        line,
+        generation,
+    };
+    ERRORS.lock().unwrap().insert(shader, error);
+}
+
+/// Remove the error of the shader, once it compiles again.
+pub fn resolve(shader: impl AsRef<Path>) {
+    if ERRORS.lock().unwrap().remove(shader.as_ref()).is_some() {
+        GENERATION.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+pub fn has_error(shader: impl AsRef<Path>) -> bool {
+    ERRORS.lock().unwrap().contains_key(shader.as_ref())
+}
+
+/// Current shader errors, sorted by shader path.
+pub fn errors() -> Vec<ShaderError> {
+    ERRORS.lock().unwrap().values().cloned().collect()
+}
+
+/// Counter incremented every time an error is reported or resolved.
+pub fn generation() -> u64 {
+    GENERATION.load(Ordering::Relaxed)
+}
+
+/// Error context listing the files passed to the compiler, from which error locations are
+/// resolved.
+pub fn file_map<P: AsRef<Path>>(files: impl IntoIterator<Item = P>) -> String {
+    format!(
+        "File map:\n{}",
+        files
+            .into_iter()
+            .enumerate()
+            .map(|(ix, p)| format!("\t{} => {}", ix, p.as_ref().display()))
+            .collect::<Vec<_>>()
+            .join("\n")
+    )
+}
+
+fn find_location(err: &eyre::Report) -> (Option<PathBuf>, Option<u32>) {
+    let messages = err
+        .chain()
+        .map(|cause| cause.to_string())
+        .collect::<Vec<_>>();
+    let files = messages
+        .iter()
+        .find_map(|message| message.strip_prefix("File map:"))
+        .map(parse_file_map)
+        .unwrap_or_default();
+    let Some((source, line)) = messages
+        .iter()
+        .flat_map(|message| message.lines())
+        .find_map(parse_log_location) else { return (None, None); };
+    (files.get(source).cloned(), Some(line))
+}
+
+fn parse_file_map(map: &str) -> Vec<PathBuf> {
+    map.lines()
+        .filter_map(|line| line.split_once(" => "))
+        .map(|(_, path)| PathBuf::from(path))
+        .collect()
+}
+
+/// Parse the source string index and line out of a line of a shader info log, as formatted by
+/// common drivers: `0(12) : error ...` or `ERROR: 0:12: ...`.
+fn parse_log_location(line: &str) -> Option<(usize, u32)> {
+    let line = line.trim_start();
+    let line = line.strip_prefix("ERROR:").unwrap_or(line).trim_start();
+    let source_end = line.find(|c: char| !c.is_ascii_digit())?;
+    let (source, rest) = line.split_at(source_end);
+    let rest = rest.strip_prefix('(').or_else(|| rest.strip_prefix(':'))?;
+    let line_end = rest.find(|c: char| !c.is_ascii_digit())?;
+    Some((source.parse().ok()?, rest[..line_end].parse().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_driver_log_locations() {
+        assert_eq!(
+            parse_log_location("0(12) : error C0000: syntax error"),
+            Some((0, 12))
+        );
+        assert_eq!(
+            parse_log_location("ERROR: 1:34: 'foo' : undeclared identifier"),
+            Some((1, 34))
+        );
+        assert_eq!(
+            parse_log_location("2:7(3): error: `bar' undeclared"),
+            Some((2, 7))
+        );
+        assert_eq!(parse_log_location("Cannot link program"), None);
+    }
+
+    #[test]
+    fn resolves_location_from_file_map() {
+        let err = eyre::eyre!("1(5) : error C0000: syntax error")
+            .wrap_err(file_map(["common.glsl", "main.glsl"]));
+        assert_eq!(
+            find_location(&err),
+            (Some(PathBuf::from("main.glsl")), Some(5))
+        );
+    }
+}
@@ -0,0 +1,99 @@
+//! State handed over from a producer thread to a consumer thread, e.g. from the simulation to the
+//! render loop, without either holding a lock for longer than a buffer swap.
+//!
+//! Both ends own a buffer, and swap it with a shared one: the writer when publishing a new
+//! snapshot, and the reader when it finds one it has not read yet. Buffers are reused, so writers
+//! should overwrite the whole state before publishing.
+
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug)]
+struct Shared<T> {
+    value: T,
+    fresh: bool,
+}
+
+/// Create the writing and reading ends of a snapshot, both starting with the default state.
+pub fn snapshot<T: Default>() -> (SnapshotWriter<T>, SnapshotReader<T>) {
+    let shared = Arc::new(Mutex::new(Shared {
+        value: T::default(),
+        fresh: false,
+    }));
+    (
+        SnapshotWriter {
+            back: T::default(),
+            shared: shared.clone(),
+        },
+        SnapshotReader {
+            front: T::default(),
+            shared,
+        },
+    )
+}
+
+#[derive(Debug)]
+pub struct SnapshotWriter<T> {
+    back: T,
+    shared: Arc<Mutex<Shared<T>>>,
+}
+
+impl<T> SnapshotWriter<T> {
+    /// State to write the next snapshot into. It holds an older snapshot, not the last published
+    /// one.
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.back
+    }
+
+    /// Publish the written state, replacing the previous snapshot if it was not read yet.
+    pub fn publish(&mut self) {
+        let mut shared = self.shared.lock().unwrap();
+        std::mem::swap(&mut shared.value, &mut self.back);
+        shared.fresh = true;
+    }
+}
+
+#[derive(Debug)]
+pub struct SnapshotReader<T> {
+    front: T,
+    shared: Arc<Mutex<Shared<T>>>,
+}
+
+impl<T> SnapshotReader<T> {
+    /// Latest published snapshot, which stays the same until another one is published.
+    pub fn latest(&mut self) -> &T {
+        let mut shared = self.shared.lock().unwrap();
+        if shared.fresh {
+            std::mem::swap(&mut shared.value, &mut self.front);
+            shared.fresh = false;
+        }
+        drop(shared);
+        &self.front
+    }
+
+    /// Whether a snapshot was published since the last call to [`Self::latest`].
+    pub fn has_new(&self) -> bool {
+        self.shared.lock().unwrap().fresh
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_latest_published() {
+        let (mut writer, mut reader) = snapshot::<u32>();
+        *writer.get_mut() = 1;
+        writer.publish();
+        *writer.get_mut() = 2;
+        writer.publish();
+        assert!(reader.has_new());
+        assert_eq!(*reader.latest(), 2);
+        assert!(!reader.has_new());
+
+        *writer.get_mut() = 3;
+        assert_eq!(*reader.latest(), 2);
+        writer.publish();
+        assert_eq!(*reader.latest(), 3);
+    }
+}
@@ -0,0 +1,1191 @@
+use std::{f32::consts::TAU, time::Duration};
+
+use assets_manager::{loader::TomlLoader, AnyCache, Asset};
+#[cfg(feature = "ui")]
+use egui::{DragValue, Grid, Ui};
+use glam::{Mat4, Quat, Vec3};
+use hecs::World;
+use serde::{Deserialize, Serialize};
+
+use rose_core::{
+    spline::{ArcLength, Spline},
+    transform::Transform,
+    utils::rng::{EngineRng, Rng},
+};
+use rose_renderer::material::MaterialUniforms;
+
+#[cfg(feature = "ui")]
+use crate::systems::ComponentUi;
+use crate::{
+    components::{CameraParams, Light, MorphWeights},
+    NamedComponent,
+};
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Keyframe {
+    pub time: f32,
+    pub value: Vec3,
+}
+
+impl Keyframe {
+    pub fn new(time: f32, value: Vec3) -> Self {
+        Self { time, value }
+    }
+}
+
+/// Value over time. Scalar parameters only use the first component of the value.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Curve {
+    Constant(Vec3),
+    /// Linear interpolation between keyframes, sorted by time.
+    Keyframes(Vec<Keyframe>),
+    /// `offset + amplitude * sin(2pi * (frequency * t + phase))`
+    Sine {
+        offset: Vec3,
+        amplitude: Vec3,
+        frequency: f32,
+        phase: f32,
+    },
+    /// Alternates between `low` and `high`, staying on `high` for the `duty` fraction of a period.
+    Square {
+        low: Vec3,
+        high: Vec3,
+        frequency: f32,
+        duty: f32,
+    },
+}
+
+impl Curve {
+    pub fn sample(&self, t: f32) -> Vec3 {
+        match self {
+            Self::Constant(value) => *value,
+            Self::Keyframes(keyframes) => {
+                let Some(first) = keyframes.first() else {
+                    return Vec3::ZERO;
+                };
+                if t <= first.time {
+                    return first.value;
+                }
+                for window in keyframes.windows(2) {
+                    let (a, b) = (window[0], window[1]);
+                    if t < b.time {
+                        let span = (b.time - a.time).max(f32::EPSILON);
+                        return a.value.lerp(b.value, (t - a.time) / span);
+                    }
+                }
+                keyframes.last().unwrap().value
+            }
+            &Self::Sine {
+                offset,
+                amplitude,
+                frequency,
+                phase,
+            } => offset + amplitude * (TAU * (frequency * t + phase)).sin(),
+            &Self::Square {
+                low,
+                high,
+                frequency,
+                duty,
+            } => {
+                if (frequency * t).rem_euclid(1.) < duty {
+                    high
+                } else {
+                    low
+                }
+            }
+        }
+    }
+
+    /// Duration of the curve, or zero for curves that are constant or repeat indefinitely.
+    pub fn duration(&self) -> f32 {
+        match self {
+            Self::Keyframes(keyframes) => keyframes.last().map(|k| k.time).unwrap_or(0.),
+            _ => 0.,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum MaterialParameter {
+    ColorFactor,
+    EmissionFactor,
+    EmissionStrength,
+    Roughness,
+    Metallic,
+}
+
+impl MaterialParameter {
+    pub fn apply(self, uniforms: &mut MaterialUniforms, value: Vec3) {
+        match self {
+            Self::ColorFactor => uniforms.color_factor = value,
+            Self::EmissionFactor => uniforms.emission_factor = value,
+            Self::EmissionStrength => uniforms.emission_strength = value.x,
+            Self::Roughness => uniforms.rough_metal_factor.x = value.x,
+            Self::Metallic => uniforms.rough_metal_factor.y = value.x,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MaterialTrack {
+    pub parameter: MaterialParameter,
+    pub curve: Curve,
+}
+
+/// Animates the parameters of the material of the entity over time.
+///
+/// Materials are shared assets, so every entity using the same material will show the animation.
+/// Parameters without a track keep the value they were loaded with.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AnimatedMaterial {
+    pub tracks: Vec<MaterialTrack>,
+    pub time: f32,
+    pub speed: f32,
+    pub looping: bool,
+    pub playing: bool,
+}
+
+impl Default for AnimatedMaterial {
+    fn default() -> Self {
+        Self {
+            tracks: vec![],
+            time: 0.,
+            speed: 1.,
+            looping: true,
+            playing: true,
+        }
+    }
+}
+
+impl AnimatedMaterial {
+    pub fn new(tracks: impl IntoIterator<Item = MaterialTrack>) -> Self {
+        Self {
+            tracks: tracks.into_iter().collect(),
+            ..Default::default()
+        }
+    }
+
+    pub fn with_track(mut self, parameter: MaterialParameter, curve: Curve) -> Self {
+        self.tracks.push(MaterialTrack { parameter, curve });
+        self
+    }
+
+    pub fn duration(&self) -> f32 {
+        self.tracks
+            .iter()
+            .map(|t| t.curve.duration())
+            .fold(0., f32::max)
+    }
+
+    pub fn advance(&mut self, dt: Duration) {
+        if !self.playing {
+            return;
+        }
+        self.time += dt.as_secs_f32() * self.speed;
+        let duration = self.duration();
+        if self.looping && duration > 0. {
+            self.time = self.time.rem_euclid(duration);
+        }
+    }
+
+    pub fn apply(&self, uniforms: &mut MaterialUniforms) {
+        for track in &self.tracks {
+            track
+                .parameter
+                .apply(uniforms, track.curve.sample(self.time));
+        }
+    }
+}
+
+#[cfg(feature = "ui")]
+impl ComponentUi for AnimatedMaterial {
+    fn ui(&mut self, ui: &mut Ui) {
+        Grid::new("animated-material")
+            .num_columns(2)
+            .show(ui, |ui| {
+                let playing_label = ui.label("Playing").id;
+                ui.checkbox(&mut self.playing, "")
+                    .labelled_by(playing_label);
+                ui.end_row();
+
+                let looping_label = ui.label("Looping").id;
+                ui.checkbox(&mut self.looping, "")
+                    .labelled_by(looping_label);
+                ui.end_row();
+
+                let time_label = ui.label("Time").id;
+                ui.add(DragValue::new(&mut self.time).speed(0.01).suffix(" s"))
+                    .labelled_by(time_label);
+                ui.end_row();
+
+                let speed_label = ui.label("Speed").id;
+                ui.add(DragValue::new(&mut self.speed).speed(0.01).suffix("x"))
+                    .labelled_by(speed_label);
+                ui.end_row();
+
+                let tracks_label = ui.label("Tracks").id;
+                ui.vertical(|ui| {
+                    for track in &self.tracks {
+                        ui.monospace(format!("{:?}", track.parameter));
+                    }
+                })
+                .response
+                .labelled_by(tracks_label);
+            });
+    }
+}
+
+impl NamedComponent for AnimatedMaterial {
+    const NAME: &'static str = "Animated Material";
+}
+
+/// Shape of the random variations of a flickering light.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum FlickerProfile {
+    /// Slow and smooth wavering.
+    Candle,
+    /// Fast variations over a slower wavering, as of open flames.
+    #[default]
+    Torch,
+    /// Steady light which drops out every now and then, as of a failing fluorescent tube.
+    Faulty,
+}
+
+/// Variation of the brightness of a light over time.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum LightEffect {
+    /// Random variations, from value noise which only depends on the seed and the time.
+    Flicker {
+        profile: FlickerProfile,
+        /// Rate of the variations, in changes per second.
+        speed: f32,
+        seed: u64,
+    },
+    /// Smooth fading in and out.
+    Pulse { frequency: f32 },
+    /// Alternates between full and dimmed brightness, staying on for the `duty` fraction of a
+    /// period.
+    Strobe { frequency: f32, duty: f32 },
+}
+
+impl Default for LightEffect {
+    fn default() -> Self {
+        Self::Flicker {
+            profile: FlickerProfile::default(),
+            speed: 8.,
+            seed: 0,
+        }
+    }
+}
+
+impl LightEffect {
+    /// Brightness of the effect at time `t`, from 0 at its dimmest to 1 at full brightness.
+    pub fn level(&self, t: f32) -> f32 {
+        match *self {
+            Self::Flicker {
+                profile,
+                speed,
+                seed,
+            } => {
+                let t = t * speed;
+                match profile {
+                    FlickerProfile::Candle => value_noise(seed, t * 0.5),
+                    FlickerProfile::Torch => {
+                        0.5 * value_noise(seed, t * 0.5)
+                            + 0.3 * value_noise(seed ^ 1, t)
+                            + 0.2 * value_noise(seed ^ 2, t * 2.)
+                    }
+                    FlickerProfile::Faulty => (value_noise(seed, t) < 0.8) as u8 as f32,
+                }
+            }
+            Self::Pulse { frequency } => 0.5 + 0.5 * (TAU * frequency * t).cos(),
+            Self::Strobe { frequency, duty } => {
+                ((frequency * t).rem_euclid(1.) < duty) as u8 as f32
+            }
+        }
+    }
+}
+
+/// Smooth noise in [0, 1], interpolating between random values at whole numbers.
+fn value_noise(seed: u64, t: f32) -> f32 {
+    let lattice = |k: f32| EngineRng::with_seed(seed.wrapping_add(k as i64 as u64)).gen::<f32>();
+    let k = t.floor();
+    let f = t - k;
+    let f = f * f * (3. - 2. * f);
+    lattice(k) + (lattice(k + 1.) - lattice(k)) * f
+}
+
+/// Animates the intensity and color of the [`Light`] of the entity, e.g. for torches and alarm
+/// lights.
+///
+/// The light component keeps its values; the animation is applied to the light sent to the
+/// renderer, see [`Self::apply`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LightAnimation {
+    pub effect: LightEffect,
+    /// Fraction of the intensity removed at the dimmest point of the effect.
+    pub depth: f32,
+    /// Factor applied to the light color at the dimmest point of the effect, e.g. to redden
+    /// dying flames.
+    pub dim_color: Vec3,
+    pub time: f32,
+    pub speed: f32,
+    pub playing: bool,
+}
+
+impl Default for LightAnimation {
+    fn default() -> Self {
+        Self {
+            effect: LightEffect::default(),
+            depth: 0.5,
+            dim_color: Vec3::ONE,
+            time: 0.,
+            speed: 1.,
+            playing: true,
+        }
+    }
+}
+
+impl LightAnimation {
+    pub fn new(effect: LightEffect, depth: f32) -> Self {
+        Self {
+            effect,
+            depth,
+            ..Default::default()
+        }
+    }
+
+    pub fn advance(&mut self, dt: Duration) {
+        if self.playing {
+            self.time += dt.as_secs_f32() * self.speed;
+        }
+    }
+
+    /// Apply the current state of the animation to the light.
+    pub fn apply(&self, light: &mut Light) {
+        let level = self.effect.level(self.time).clamp(0., 1.);
+        light.intensity *= 1. - self.depth * (1. - level);
+        light.color *= self.dim_color.lerp(Vec3::ONE, level);
+    }
+}
+
+#[cfg(feature = "ui")]
+impl ComponentUi for LightAnimation {
+    fn ui(&mut self, ui: &mut Ui) {
+        Grid::new("light-animation").num_columns(2).show(ui, |ui| {
+            let effect_label = ui.label("Effect").id;
+            ui.horizontal(|ui| {
+                let is_flicker = matches!(self.effect, LightEffect::Flicker { .. });
+                if ui.radio(is_flicker, "Flicker").clicked() && !is_flicker {
+                    self.effect = LightEffect::default();
+                }
+                let is_pulse = matches!(self.effect, LightEffect::Pulse { .. });
+                if ui.radio(is_pulse, "Pulse").clicked() && !is_pulse {
+                    self.effect = LightEffect::Pulse { frequency: 1. };
+                }
+                let is_strobe = matches!(self.effect, LightEffect::Strobe { .. });
+                if ui.radio(is_strobe, "Strobe").clicked() && !is_strobe {
+                    self.effect = LightEffect::Strobe {
+                        frequency: 2.,
+                        duty: 0.5,
+                    };
+                }
+            })
+            .response
+            .labelled_by(effect_label);
+            ui.end_row();
+
+            match &mut self.effect {
+                LightEffect::Flicker {
+                    profile,
+                    speed,
+                    seed,
+                } => {
+                    let profile_label = ui.label("Profile").id;
+                    ui.horizontal(|ui| {
+                        ui.selectable_value(profile, FlickerProfile::Candle, "Candle");
+                        ui.selectable_value(profile, FlickerProfile::Torch, "Torch");
+                        ui.selectable_value(profile, FlickerProfile::Faulty, "Faulty");
+                    })
+                    .response
+                    .labelled_by(profile_label);
+                    ui.end_row();
+
+                    let speed_label = ui.label("Rate").id;
+                    ui.add(
+                        DragValue::new(speed)
+                            .clamp_range(0.0..=100.)
+                            .speed(0.1)
+                            .suffix(" /s"),
+                    )
+                    .labelled_by(speed_label);
+                    ui.end_row();
+
+                    let seed_label = ui.label("Seed").id;
+                    ui.add(DragValue::new(seed)).labelled_by(seed_label);
+                    ui.end_row();
+                }
+                LightEffect::Pulse { frequency } => {
+                    let frequency_label = ui.label("Frequency").id;
+                    ui.add(
+                        DragValue::new(frequency)
+                            .clamp_range(0.0..=100.)
+                            .speed(0.01)
+                            .suffix(" Hz"),
+                    )
+                    .labelled_by(frequency_label);
+                    ui.end_row();
+                }
+                LightEffect::Strobe { frequency, duty } => {
+                    let frequency_label = ui.label("Frequency").id;
+                    ui.add(
+                        DragValue::new(frequency)
+                            .clamp_range(0.0..=100.)
+                            .speed(0.01)
+                            .suffix(" Hz"),
+                    )
+                    .labelled_by(frequency_label);
+                    ui.end_row();
+
+                    let duty_label = ui.label("Duty cycle").id;
+                    ui.add(DragValue::new(duty).clamp_range(0.0..=1.).speed(0.01))
+                        .labelled_by(duty_label);
+                    ui.end_row();
+                }
+            }
+
+            let depth_label = ui.label("Depth").id;
+            ui.add(
+                DragValue::new(&mut self.depth)
+                    .clamp_range(0.0..=1.)
+                    .speed(0.01),
+            )
+            .labelled_by(depth_label);
+            ui.end_row();
+
+            let dim_color_label = ui.label("Dim color").id;
+            ui.color_edit_button_rgb(self.dim_color.as_mut())
+                .labelled_by(dim_color_label);
+            ui.end_row();
+
+            let playing_label = ui.label("Playing").id;
+            ui.checkbox(&mut self.playing, "")
+                .labelled_by(playing_label);
+            ui.end_row();
+
+            let speed_label = ui.label("Speed").id;
+            ui.add(DragValue::new(&mut self.speed).speed(0.01).suffix("x"))
+                .labelled_by(speed_label);
+            ui.end_row();
+        });
+    }
+}
+
+impl NamedComponent for LightAnimation {
+    const NAME: &'static str = "Light Animation";
+}
+
+/// Advance every [`LightAnimation`]; the render system applies them when syncing the lights.
+pub fn update_light_animations(world: &World, dt: Duration) {
+    for (_, animation) in world.query::<&mut LightAnimation>().iter() {
+        animation.advance(dt);
+    }
+}
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Interpolation {
+    Step,
+    #[default]
+    Linear,
+}
+
+/// Values that can be interpolated between keyframes of a [`Track`].
+pub trait Interpolate: Copy {
+    fn interpolate(self, other: Self, t: f32) -> Self;
+}
+
+impl Interpolate for f32 {
+    fn interpolate(self, other: Self, t: f32) -> Self {
+        self + (other - self) * t
+    }
+}
+
+impl Interpolate for Vec3 {
+    fn interpolate(self, other: Self, t: f32) -> Self {
+        self.lerp(other, t)
+    }
+}
+
+impl Interpolate for Quat {
+    fn interpolate(self, other: Self, t: f32) -> Self {
+        self.slerp(other, t)
+    }
+}
+
+/// Keyframes of a single property, as `(time, value)` pairs sorted by time.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Track<T> {
+    #[serde(default)]
+    pub interpolation: Interpolation,
+    pub keys: Vec<(f32, T)>,
+}
+
+impl<T: Interpolate> Track<T> {
+    pub fn new(interpolation: Interpolation, keys: impl IntoIterator<Item = (f32, T)>) -> Self {
+        Self {
+            interpolation,
+            keys: keys.into_iter().collect(),
+        }
+    }
+
+    pub fn sample(&self, t: f32) -> Option<T> {
+        let (first_time, first) = *self.keys.first()?;
+        if t <= first_time {
+            return Some(first);
+        }
+        for window in self.keys.windows(2) {
+            let ((ta, a), (tb, b)) = (window[0], window[1]);
+            if t < tb {
+                return Some(match self.interpolation {
+                    Interpolation::Step => a,
+                    Interpolation::Linear => {
+                        a.interpolate(b, (t - ta) / (tb - ta).max(f32::EPSILON))
+                    }
+                });
+            }
+        }
+        self.keys.last().map(|(_, v)| *v)
+    }
+
+    pub fn duration(&self) -> f32 {
+        self.keys.last().map(|(t, _)| *t).unwrap_or(0.)
+    }
+}
+
+/// Animation of the transform of a single entity, and of the weights of its morph targets.
+/// Properties without a track are left untouched.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AnimationClip {
+    pub translation: Option<Track<Vec3>>,
+    pub rotation: Option<Track<Quat>>,
+    pub scale: Option<Track<Vec3>>,
+    /// One track per morph target, see [`MorphWeights`].
+    pub morph_weights: Vec<Track<f32>>,
+}
+
+impl Asset for AnimationClip {
+    const EXTENSION: &'static str = "toml";
+    type Loader = TomlLoader;
+}
+
+impl AnimationClip {
+    pub fn duration(&self) -> f32 {
+        [
+            self.translation.as_ref().map(Track::duration),
+            self.rotation.as_ref().map(Track::duration),
+            self.scale.as_ref().map(Track::duration),
+        ]
+        .into_iter()
+        .flatten()
+        .chain(self.morph_weights.iter().map(Track::duration))
+        .fold(0., f32::max)
+    }
+
+    /// Sample the morph target weights at the given time into `weights`, which is grown to fit
+    /// every track.
+    pub fn sample_morph_weights(&self, t: f32, weights: &mut Vec<f32>) {
+        if weights.len() < self.morph_weights.len() {
+            weights.resize(self.morph_weights.len(), 0.);
+        }
+        for (weight, track) in weights.iter_mut().zip(&self.morph_weights) {
+            if let Some(value) = track.sample(t) {
+                *weight = value;
+            }
+        }
+    }
+
+    /// Sample the clip at the given time, taking non-animated properties from `base`.
+    pub fn sample(&self, t: f32, base: Transform) -> Transform {
+        Transform {
+            position: self
+                .translation
+                .as_ref()
+                .and_then(|track| track.sample(t))
+                .unwrap_or(base.position),
+            rotation: self
+                .rotation
+                .as_ref()
+                .and_then(|track| track.sample(t))
+                .unwrap_or(base.rotation),
+            scale: self
+                .scale
+                .as_ref()
+                .and_then(|track| track.sample(t))
+                .unwrap_or(base.scale),
+        }
+    }
+}
+
+/// Playback state of an [`AnimationClip`], referenced by its asset id.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClipPlayback {
+    pub clip: String,
+    pub time: f32,
+    pub speed: f32,
+    pub looping: bool,
+    pub playing: bool,
+}
+
+impl Default for ClipPlayback {
+    fn default() -> Self {
+        Self {
+            clip: String::new(),
+            time: 0.,
+            speed: 1.,
+            looping: true,
+            playing: true,
+        }
+    }
+}
+
+impl ClipPlayback {
+    pub fn new(clip: impl Into<String>) -> Self {
+        Self {
+            clip: clip.into(),
+            ..Default::default()
+        }
+    }
+
+    pub fn advance(&mut self, dt: Duration, duration: f32) {
+        if !self.playing {
+            return;
+        }
+        self.time += dt.as_secs_f32() * self.speed;
+        if self.looping && duration > 0. {
+            self.time = self.time.rem_euclid(duration);
+        } else {
+            self.time = self.time.clamp(0., duration);
+        }
+    }
+}
+
+/// Crossfade from the current clip of a [`TransformAnimation`] to another one.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClipBlend {
+    pub target: ClipPlayback,
+    /// Weight of the target clip, going from 0 to 1 over `duration` seconds.
+    pub weight: f32,
+    pub duration: f32,
+}
+
+/// Plays [`AnimationClip`]s on the transform of the entity, optionally blending into another clip.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TransformAnimation {
+    pub current: ClipPlayback,
+    pub blend: Option<ClipBlend>,
+    /// Added to the animated positions, so that clips keep playing where the entity was moved by
+    /// origin rebasing, see [`crate::origin`].
+    pub offset: Vec3,
+}
+
+impl TransformAnimation {
+    pub fn new(clip: impl Into<String>) -> Self {
+        Self {
+            current: ClipPlayback::new(clip),
+            blend: None,
+            offset: Vec3::ZERO,
+        }
+    }
+
+    /// Start blending into the given clip, which replaces the current one after `duration` seconds.
+    pub fn crossfade_to(&mut self, clip: impl Into<String>, duration: f32) {
+        self.blend = Some(ClipBlend {
+            target: ClipPlayback::new(clip),
+            weight: 0.,
+            duration,
+        });
+    }
+
+    /// Advance playback, and return the transform to apply to the entity.
+    pub fn update(&mut self, cache: AnyCache, dt: Duration, base: Transform) -> Transform {
+        if self.current.clip.is_empty() {
+            return base;
+        }
+        let clip = match cache.load::<AnimationClip>(&self.current.clip) {
+            Ok(clip) => clip,
+            Err(err) => {
+                // Stop playback so that the error is only reported once
+                if self.current.playing {
+                    tracing::warn!(message = "Cannot load animation clip", id = %self.current.clip, %err);
+                    self.current.playing = false;
+                }
+                return base;
+            }
+        };
+        let offset = self.offset;
+        let sample = |clip: &AnimationClip, t| {
+            let mut transform = clip.sample(t, base);
+            if clip.translation.is_some() {
+                transform.position += offset;
+            }
+            transform
+        };
+        let clip = clip.read();
+        self.current.advance(dt, clip.duration());
+        let transform = sample(&clip, self.current.time);
+
+        let Some(blend) = &mut self.blend else {
+            return transform;
+        };
+        let target_clip = match cache.load::<AnimationClip>(&blend.target.clip) {
+            Ok(clip) => clip,
+            Err(err) => {
+                tracing::warn!(message = "Cannot load animation clip", id = %blend.target.clip, %err);
+                self.blend = None;
+                return transform;
+            }
+        };
+        let target_clip = target_clip.read();
+        blend.target.advance(dt, target_clip.duration());
+        blend.weight = if blend.duration > 0. {
+            (blend.weight + dt.as_secs_f32() / blend.duration).min(1.)
+        } else {
+            1.
+        };
+        let target = sample(&target_clip, blend.target.time);
+        let weight = blend.weight;
+        if weight >= 1. {
+            self.current = self.blend.take().unwrap().target;
+        }
+        Transform {
+            position: transform.position.lerp(target.position, weight),
+            rotation: transform.rotation.slerp(target.rotation, weight),
+            scale: transform.scale.lerp(target.scale, weight),
+        }
+    }
+}
+
+impl TransformAnimation {
+    /// Write the morph target weights animated by the clips at their current time into `weights`,
+    /// blending them like the transform. Call after [`Self::update`].
+    pub fn sample_morph_weights(&self, cache: AnyCache, weights: &mut Vec<f32>) {
+        let sample = |playback: &ClipPlayback, weights: &mut Vec<f32>| {
+            if playback.clip.is_empty() {
+                return;
+            }
+            if let Ok(clip) = cache.load::<AnimationClip>(&playback.clip) {
+                clip.read().sample_morph_weights(playback.time, weights);
+            }
+        };
+        sample(&self.current, weights);
+        if let Some(blend) = &self.blend {
+            let mut target = weights.clone();
+            sample(&blend.target, &mut target);
+            weights.resize(target.len(), 0.);
+            for (weight, target) in weights.iter_mut().zip(target) {
+                *weight = weight.interpolate(target, blend.weight);
+            }
+        }
+    }
+}
+
+#[cfg(feature = "ui")]
+impl ComponentUi for TransformAnimation {
+    fn ui(&mut self, ui: &mut Ui) {
+        Grid::new("transform-animation")
+            .num_columns(2)
+            .show(ui, |ui| {
+                let clip_label = ui.label("Clip").id;
+                ui.text_edit_singleline(&mut self.current.clip)
+                    .labelled_by(clip_label);
+                ui.end_row();
+
+                let playing_label = ui.label("Playing").id;
+                ui.checkbox(&mut self.current.playing, "")
+                    .labelled_by(playing_label);
+                ui.end_row();
+
+                let looping_label = ui.label("Looping").id;
+                ui.checkbox(&mut self.current.looping, "")
+                    .labelled_by(looping_label);
+                ui.end_row();
+
+                let time_label = ui.label("Time").id;
+                ui.add(
+                    DragValue::new(&mut self.current.time)
+                        .speed(0.01)
+                        .suffix(" s"),
+                )
+                .labelled_by(time_label);
+                ui.end_row();
+
+                let speed_label = ui.label("Speed").id;
+                ui.add(
+                    DragValue::new(&mut self.current.speed)
+                        .speed(0.01)
+                        .suffix("x"),
+                )
+                .labelled_by(speed_label);
+                ui.end_row();
+
+                if let Some(blend) = &self.blend {
+                    ui.label("Blending into");
+                    ui.label(format!(
+                        "{} ({:.0} %)",
+                        blend.target.clip,
+                        blend.weight * 100.
+                    ));
+                    ui.end_row();
+                }
+            });
+    }
+}
+
+impl NamedComponent for TransformAnimation {
+    const NAME: &'static str = "Transform Animation";
+}
+
+/// Advance every [`TransformAnimation`] and write the result into the entity's transform, and its
+/// [`MorphWeights`] if it has any.
+pub fn update_transform_animations(world: &World, cache: AnyCache, dt: Duration) {
+    for (_, (animation, transform, morph_weights)) in world
+        .query::<(
+            &mut TransformAnimation,
+            &mut Transform,
+            Option<&mut MorphWeights>,
+        )>()
+        .iter()
+    {
+        *transform = animation.update(cache, dt, *transform);
+        if let Some(morph_weights) = morph_weights {
+            animation.sample_morph_weights(cache, &mut morph_weights.0);
+        }
+    }
+}
+
+/// Moves the entity along a spline at a constant speed, e.g. for camera rails and moving
+/// platforms. Control points are in the space of the parent of the entity.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SplineFollow {
+    pub spline: Spline,
+    /// Speed along the spline, in units per second. Negative speeds go backwards.
+    pub speed: f32,
+    /// Distance travelled from the start of the spline.
+    pub distance: f32,
+    pub looping: bool,
+    pub playing: bool,
+    /// Turn the entity to look along the spline.
+    pub align: bool,
+    /// Measure of the spline it was computed for, recomputed when the spline is edited.
+    #[serde(skip)]
+    arc_length: Option<(Spline, ArcLength)>,
+}
+
+impl Default for SplineFollow {
+    fn default() -> Self {
+        Self {
+            spline: Spline::default(),
+            speed: 1.,
+            distance: 0.,
+            looping: true,
+            playing: true,
+            align: true,
+            arc_length: None,
+        }
+    }
+}
+
+impl SplineFollow {
+    const SAMPLES_PER_SEGMENT: usize = 32;
+
+    pub fn new(spline: Spline, speed: f32) -> Self {
+        Self {
+            spline,
+            speed,
+            ..Default::default()
+        }
+    }
+
+    /// Move the spline by the offset, keeping the entity where it is along it.
+    pub fn translate(&mut self, offset: Vec3) {
+        for spline in std::iter::once(&mut self.spline)
+            .chain(self.arc_length.as_mut().map(|(spline, _)| spline))
+        {
+            for point in &mut spline.points {
+                *point += offset;
+            }
+        }
+    }
+
+    pub fn arc_length(&mut self) -> &ArcLength {
+        match &self.arc_length {
+            Some((spline, _)) if *spline == self.spline => {}
+            _ => {
+                let table = self.spline.arc_length(Self::SAMPLES_PER_SEGMENT);
+                self.arc_length = Some((self.spline.clone(), table));
+            }
+        }
+        &self.arc_length.as_ref().unwrap().1
+    }
+
+    /// Advance along the spline, and return the position and, when aligned, the rotation of the
+    /// entity.
+    pub fn update(&mut self, dt: Duration) -> (Vec3, Option<Quat>) {
+        let length = self.arc_length().length();
+        if self.playing {
+            self.distance += self.speed * dt.as_secs_f32();
+            if self.looping && length > 0. {
+                self.distance = self.distance.rem_euclid(length);
+            } else {
+                self.distance = self.distance.clamp(0., length);
+            }
+        }
+        let t = self.arc_length().param_at(self.distance);
+        let position = self.spline.sample(t);
+        let tangent = self.spline.tangent(t) * self.speed.signum();
+        let rotation = (self.align && tangent.length_squared() > 1e-12).then(|| {
+            // Objects look down their -Z axis
+            let view = Mat4::look_to_rh(Vec3::ZERO, tangent.normalize(), Vec3::Y);
+            Quat::from_mat4(&view).inverse()
+        });
+        (position, rotation)
+    }
+}
+
+#[cfg(feature = "ui")]
+impl ComponentUi for SplineFollow {
+    fn ui(&mut self, ui: &mut Ui) {
+        use rose_core::spline::SplineKind;
+
+        let length = self.arc_length().length();
+        Grid::new("spline-follow").num_columns(2).show(ui, |ui| {
+            let kind_label = ui.label("Kind").id;
+            ui.horizontal(|ui| {
+                ui.radio_value(&mut self.spline.kind, SplineKind::CatmullRom, "Catmull-Rom");
+                ui.radio_value(&mut self.spline.kind, SplineKind::Bezier, "Bézier");
+            })
+            .response
+            .labelled_by(kind_label);
+            ui.end_row();
+
+            let closed_label = ui.label("Closed").id;
+            ui.checkbox(&mut self.spline.closed, "")
+                .labelled_by(closed_label);
+            ui.end_row();
+
+            ui.label("Points");
+            ui.horizontal(|ui| {
+                ui.label(format!("{} ({:.2} long)", self.spline.points.len(), length));
+                if ui.small_button("Add").clicked() {
+                    // Continue along the direction of the end of the curve
+                    let points = &self.spline.points;
+                    let point = match points.as_slice() {
+                        [] => Vec3::ZERO,
+                        [last] => *last + Vec3::X,
+                        [.., previous, last] => *last + (*last - *previous),
+                    };
+                    self.spline.push_point(point);
+                }
+            });
+            ui.end_row();
+
+            let playing_label = ui.label("Playing").id;
+            ui.checkbox(&mut self.playing, "")
+                .labelled_by(playing_label);
+            ui.end_row();
+
+            let looping_label = ui.label("Looping").id;
+            ui.checkbox(&mut self.looping, "")
+                .labelled_by(looping_label);
+            ui.end_row();
+
+            let align_label = ui.label("Align").id;
+            ui.checkbox(&mut self.align, "").labelled_by(align_label);
+            ui.end_row();
+
+            let distance_label = ui.label("Distance").id;
+            ui.add(
+                DragValue::new(&mut self.distance)
+                    .clamp_range(0.0..=length)
+                    .speed(0.01),
+            )
+            .labelled_by(distance_label);
+            ui.end_row();
+
+            let speed_label = ui.label("Speed").id;
+            ui.add(DragValue::new(&mut self.speed).speed(0.01).suffix(" /s"))
+                .labelled_by(speed_label);
+            ui.end_row();
+        });
+    }
+}
+
+impl NamedComponent for SplineFollow {
+    const NAME: &'static str = "Spline Follow";
+}
+
+/// Move every [`SplineFollow`] entity along its spline. Camera entities, whose transform is their
+/// view matrix, are moved so that the camera itself follows the spline.
+pub fn update_spline_followers(world: &World, dt: Duration) {
+    for (_, (follow, transform, camera)) in world
+        .query::<(&mut SplineFollow, &mut Transform, Option<&CameraParams>)>()
+        .iter()
+    {
+        let (position, rotation) = follow.update(dt);
+        if camera.is_some() {
+            let rotation = match rotation {
+                Some(rotation) => rotation.inverse(),
+                None => transform.rotation,
+            };
+            transform.rotation = rotation;
+            transform.position = -(rotation * position);
+        } else {
+            transform.position = position;
+            if let Some(rotation) = rotation {
+                transform.rotation = rotation;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keyframes_interpolate_and_clamp() {
+        let curve = Curve::Keyframes(vec![
+            Keyframe::new(0., Vec3::ZERO),
+            Keyframe::new(2., Vec3::splat(4.)),
+        ]);
+        assert_eq!(curve.sample(-1.), Vec3::ZERO);
+        assert_eq!(curve.sample(1.), Vec3::splat(2.));
+        assert_eq!(curve.sample(3.), Vec3::splat(4.));
+    }
+
+    #[test]
+    fn looping_wraps_around_duration() {
+        let mut anim = AnimatedMaterial::default().with_track(
+            MaterialParameter::EmissionStrength,
+            Curve::Keyframes(vec![
+                Keyframe::new(0., Vec3::ZERO),
+                Keyframe::new(1., Vec3::ONE),
+            ]),
+        );
+        anim.advance(Duration::from_secs_f32(1.25));
+        assert!((anim.time - 0.25).abs() < 1e-5);
+    }
+
+    #[test]
+    fn light_effects_stay_in_range() {
+        let effects = [
+            LightEffect::default(),
+            LightEffect::Flicker {
+                profile: FlickerProfile::Faulty,
+                speed: 3.,
+                seed: 7,
+            },
+            LightEffect::Pulse { frequency: 0.5 },
+            LightEffect::Strobe {
+                frequency: 2.,
+                duty: 0.25,
+            },
+        ];
+        for effect in effects {
+            for i in 0..200 {
+                let level = effect.level(i as f32 * 0.037);
+                assert!((0. ..=1.).contains(&level), "{:?}: {}", effect, level);
+            }
+        }
+        let other_seed = LightEffect::Flicker {
+            profile: FlickerProfile::Torch,
+            speed: 8.,
+            seed: 1,
+        };
+        assert_ne!(LightEffect::default().level(1.3), other_seed.level(1.3));
+        assert!(LightEffect::Pulse { frequency: 1. }.level(0.5) < 1e-6);
+    }
+
+    #[test]
+    fn light_animation_keeps_full_brightness_at_top_level() {
+        let mut light = Light {
+            intensity: 100.,
+            ..Default::default()
+        };
+        let animation = LightAnimation {
+            dim_color: Vec3::new(1., 0.5, 0.),
+            ..LightAnimation::new(LightEffect::Pulse { frequency: 1. }, 0.75)
+        };
+        animation.apply(&mut light);
+        assert_eq!(light.intensity, 100.);
+        assert_eq!(light.color, Light::default().color);
+        let dimmed = LightAnimation {
+            time: 0.5,
+            ..animation
+        };
+        let mut light = Light {
+            color: Vec3::ONE,
+            intensity: 100.,
+            ..Default::default()
+        };
+        dimmed.apply(&mut light);
+        assert!((light.intensity - 25.).abs() < 1e-4);
+        assert!(light.color.abs_diff_eq(Vec3::new(1., 0.5, 0.), 1e-5));
+    }
+
+    #[test]
+    fn step_and_linear_tracks() {
+        let keys = [(0., Vec3::ZERO), (1., Vec3::X)];
+        let linear = Track::new(Interpolation::Linear, keys);
+        let step = Track::new(Interpolation::Step, keys);
+        assert_eq!(linear.sample(0.5), Some(Vec3::new(0.5, 0., 0.)));
+        assert_eq!(step.sample(0.5), Some(Vec3::ZERO));
+        assert_eq!(step.sample(2.), Some(Vec3::X));
+        assert_eq!(
+            Track::<Vec3>::new(Interpolation::Linear, []).sample(0.),
+            None
+        );
+    }
+
+    #[test]
+    fn clip_keeps_untracked_properties() {
+        let clip = AnimationClip {
+            translation: Some(Track::new(
+                Interpolation::Linear,
+                [(0., Vec3::ZERO), (2., Vec3::Y * 2.)],
+            )),
+            ..Default::default()
+        };
+        let base = Transform {
+            position: Vec3::splat(5.),
+            rotation: Quat::from_rotation_y(1.),
+            scale: Vec3::splat(2.),
+        };
+        let sampled = clip.sample(1., base);
+        assert_eq!(sampled.position, Vec3::Y);
+        assert_eq!(sampled.rotation, base.rotation);
+        assert_eq!(sampled.scale, base.scale);
+        assert_eq!(clip.duration(), 2.);
+    }
+
+    #[test]
+    fn clip_samples_morph_weights() {
+        let clip = AnimationClip {
+            morph_weights: vec![Track::new(Interpolation::Linear, [(0., 0.), (2., 1.)])],
+            ..Default::default()
+        };
+        let mut weights = vec![];
+        clip.sample_morph_weights(1., &mut weights);
+        assert_eq!(weights, [0.5]);
+        let mut weights = vec![0.2, 0.7];
+        clip.sample_morph_weights(2., &mut weights);
+        assert_eq!(weights, [1., 0.7]);
+        assert_eq!(clip.duration(), 2.);
+    }
+}
@@ -1,14 +1,24 @@
+use std::num::NonZeroU32;
 use std::ops;
 use std::sync::Arc;
+use std::thread::JoinHandle;
 
 use assets_manager::{
     loader::{ImageLoader, LoadFrom, TomlLoader},
     AnyCache, Asset, BoxedError, Compound, SharedString,
 };
-use glam::{Vec2, Vec3};
+use glam::{UVec2, Vec2, Vec3};
 use serde::{Deserialize, Serialize};
 
-use violette::texture::{SampleMode, Texture, TextureWrap};
+use rose_core::{
+    color::{self, ColorSpace},
+    sampler::SamplerState,
+};
+use rose_renderer::{
+    material::{UvChannel, DEFAULT_EMISSION_STRENGTH},
+    streaming::level_size,
+};
+use violette::texture::{Dimension, SampleMode, Texture, TextureWrap};
 
 #[derive(Debug, Clone)]
 pub struct Image {
@@ -17,6 +27,9 @@ pub struct Image {
     pub sample_mag: SampleMode,
     pub wrap_u: TextureWrap,
     pub wrap_v: TextureWrap,
+    pub uv_channel: UvChannel,
+    /// Encoding of the colors of 8-bit images; images with more precision are always linear.
+    pub color_space: ColorSpace,
 }
 
 impl ops::Deref for Image {
@@ -40,13 +53,54 @@ impl From<image::DynamicImage> for Image {
             sample_mag: SampleMode::Linear,
             wrap_u: TextureWrap::ClampEdge,
             wrap_v: TextureWrap::ClampEdge,
+            uv_channel: UvChannel::Primary,
+            color_space: ColorSpace::Srgb,
         }
     }
 }
 
 impl Image {
+    /// Magenta and black checkerboard standing in for images which cannot be loaded.
+    pub fn placeholder() -> Self {
+        let checker = image::RgbImage::from_fn(8, 8, |x, y| {
+            if (x + y) % 2 == 0 {
+                image::Rgb([255, 0, 255])
+            } else {
+                image::Rgb([0, 0, 0])
+            }
+        });
+        let mut image = Self::from(image::DynamicImage::ImageRgb8(checker));
+        image.sample_mag = SampleMode::Nearest;
+        image
+    }
+
+    /// Tag the colors of the image, which is how the material slots using it interpret them.
+    pub fn with_color_space(mut self, color_space: ColorSpace) -> Self {
+        self.color_space = color_space;
+        self
+    }
+
+    /// Color space the texels of the image are stored in on the GPU.
+    pub fn texture_color_space(&self) -> ColorSpace {
+        use image::DynamicImage::*;
+        match *self.image {
+            ImageLuma8(_) | ImageLumaA8(_) | ImageRgb8(_) | ImageRgba8(_) => self.color_space,
+            _ => ColorSpace::Linear,
+        }
+    }
+
     pub(crate) fn create_texture_rgb(&self) -> eyre::Result<Texture<[f32; 3]>> {
-        let texture = Texture::<[f32; 3]>::from_dynamic_image((*self.image).clone())?;
+        // Uploaded once, straight into an sRGB format when the colors are encoded
+        let texture = match self.texture_color_space() {
+            ColorSpace::Srgb => {
+                let (width, height) = self.texture_size()?;
+                let texture =
+                    Texture::new(width, height, NonZeroU32::new(1).unwrap(), Dimension::D2);
+                color::set_srgb_image(&texture, &self.image, false);
+                texture
+            }
+            ColorSpace::Linear => Texture::from_dynamic_image((*self.image).clone())?,
+        };
         texture.generate_mipmaps()?;
         texture.wrap_s(self.wrap_u)?;
         texture.wrap_t(self.wrap_v)?;
@@ -64,6 +118,102 @@ impl Image {
         texture.filter_mag(self.sample_mag)?;
         Ok(texture)
     }
+
+    pub fn size(&self) -> UVec2 {
+        UVec2::new(self.image.width(), self.image.height())
+    }
+
+    fn texture_size(&self) -> eyre::Result<(NonZeroU32, NonZeroU32)> {
+        match (
+            NonZeroU32::new(self.image.width()),
+            NonZeroU32::new(self.image.height()),
+        ) {
+            (Some(width), Some(height)) => Ok((width, height)),
+            _ => eyre::bail!("Empty image"),
+        }
+    }
+
+    /// Copy of the image downscaled to the mipmap level, to upload only the levels resident with
+    /// texture streaming.
+    pub(crate) fn mip(&self, level: u32) -> Self {
+        if level == 0 {
+            return self.clone();
+        }
+        let size = level_size(self.size(), level);
+        let filter = image::imageops::FilterType::Triangle;
+        let image = match self.texture_color_space() {
+            // Averaging the encoded colors would darken them
+            ColorSpace::Srgb => {
+                let mut linear = self.image.to_rgba32f();
+                for pixel in linear.pixels_mut() {
+                    for c in &mut pixel.0[..3] {
+                        *c = color::srgb_to_linear(*c);
+                    }
+                }
+                let mut resized = image::imageops::resize(&linear, size.x, size.y, filter);
+                for pixel in resized.pixels_mut() {
+                    for c in &mut pixel.0[..3] {
+                        *c = color::linear_to_srgb(*c);
+                    }
+                }
+                let encoded = image::DynamicImage::ImageRgba32F(resized);
+                if self.image.color().has_alpha() {
+                    image::DynamicImage::ImageRgba8(encoded.to_rgba8())
+                } else {
+                    image::DynamicImage::ImageRgb8(encoded.to_rgb8())
+                }
+            }
+            ColorSpace::Linear => self.image.resize_exact(size.x, size.y, filter),
+        };
+        Self {
+            image: Arc::new(image),
+            ..self.clone()
+        }
+    }
+
+    /// Size and RGBA pixels of the image, rows first, with sRGB encoded colors and linear alpha,
+    /// when it has no side larger than `max_size`, to be packed into an atlas.
+    pub(crate) fn atlas_pixels(&self, max_size: u32) -> Option<(UVec2, Vec<[f32; 4]>)> {
+        let size = self.size();
+        if size.max_element() > max_size {
+            return None;
+        }
+        let linear = self.texture_color_space() == ColorSpace::Linear;
+        let pixels = self
+            .image
+            .to_rgba32f()
+            .pixels()
+            .map(|pixel| {
+                let [r, g, b, a] = pixel.0;
+                if linear {
+                    let [r, g, b] = [r, g, b].map(color::linear_to_srgb);
+                    [r, g, b, a]
+                } else {
+                    [r, g, b, a]
+                }
+            })
+            .collect();
+        Some((size, pixels))
+    }
+
+    pub(crate) fn create_texture_rgba(&self) -> eyre::Result<Texture<[f32; 4]>> {
+        let texture = match self.texture_color_space() {
+            ColorSpace::Srgb => {
+                let (width, height) = self.texture_size()?;
+                let texture =
+                    Texture::new(width, height, NonZeroU32::new(1).unwrap(), Dimension::D2);
+                color::set_srgb_image(&texture, &self.image, true);
+                texture
+            }
+            ColorSpace::Linear => Texture::from_dynamic_image((*self.image).clone())?,
+        };
+        texture.generate_mipmaps()?;
+        texture.wrap_s(self.wrap_u)?;
+        texture.wrap_t(self.wrap_v)?;
+        texture.filter_min_mipmap(self.sample_min.0, self.sample_min.1)?;
+        texture.filter_mag(self.sample_mag)?;
+        Ok(texture)
+    }
 }
 
 const fn default_normal_amount() -> f32 {
@@ -78,10 +228,75 @@ const fn default_emission_factor() -> Vec3 {
     Vec3::ZERO
 }
 
+const fn default_emission_strength() -> f32 {
+    DEFAULT_EMISSION_STRENGTH
+}
+
 const fn default_rough_metal() -> Vec2 {
     Vec2::ONE
 }
 
+/// Wrapping of a texture slot in a [`SamplerDesc`].
+#[derive(Debug, Default, Copy, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SamplerWrap {
+    #[default]
+    Clamp,
+    Repeat,
+    Mirror,
+}
+
+/// Sampling of a texture slot, overriding the filtering and wrapping of its image.
+#[derive(Debug, Default, Copy, Clone, Deserialize, Serialize)]
+pub struct SamplerDesc {
+    /// Sample the nearest texel instead of filtering, e.g. for pixel art.
+    #[serde(default)]
+    pub nearest: bool,
+    #[serde(default)]
+    pub wrap: SamplerWrap,
+}
+
+impl SamplerDesc {
+    pub fn state(&self) -> SamplerState {
+        let state = if self.nearest {
+            SamplerState::nearest()
+        } else {
+            SamplerState::linear()
+        };
+        state.with_wrap(match self.wrap {
+            SamplerWrap::Clamp => TextureWrap::ClampEdge,
+            SamplerWrap::Repeat => TextureWrap::Repeat,
+            SamplerWrap::Mirror => TextureWrap::MirroredRepeat,
+        })
+    }
+}
+
+/// Sampler overrides of the texture slots of a material, e.g. `[samplers.color]` in its TOML
+/// description.
+#[derive(Debug, Default, Copy, Clone, Deserialize, Serialize)]
+pub struct MaterialSamplers {
+    pub color: Option<SamplerDesc>,
+    pub normal: Option<SamplerDesc>,
+    pub rough_metal: Option<SamplerDesc>,
+    pub emission: Option<SamplerDesc>,
+    pub clearcoat: Option<SamplerDesc>,
+    pub anisotropy: Option<SamplerDesc>,
+}
+
+impl MaterialSamplers {
+    /// Overrides in the order of [`rose_renderer::material::TextureSlot::ALL`].
+    pub fn slots(&self) -> [Option<SamplerDesc>; 6] {
+        [
+            self.color,
+            self.normal,
+            self.rough_metal,
+            self.emission,
+            self.clearcoat,
+            self.anisotropy,
+        ]
+    }
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct MaterialDesc {
     #[serde(default)]
@@ -98,6 +313,28 @@ pub struct MaterialDesc {
     pub emission: Option<SharedString>,
     #[serde(default = "default_emission_factor")]
     pub emission_factor: Vec3,
+    #[serde(default = "default_emission_strength")]
+    pub emission_strength: f32,
+    #[serde(default)]
+    pub double_sided: bool,
+    /// Strength of a smooth dielectric coat over the material.
+    #[serde(default)]
+    pub clearcoat: f32,
+    #[serde(default)]
+    pub clearcoat_roughness: f32,
+    /// Stretching of the highlights along the anisotropy direction.
+    #[serde(default)]
+    pub anisotropy: f32,
+    /// Angle of the anisotropy direction from the U direction of the normal map, in radians.
+    #[serde(default)]
+    pub anisotropy_rotation: f32,
+    /// Clearcoat strength and roughness, multiplied with the factors, in the red and green
+    /// channels.
+    pub clearcoat_map: Option<SharedString>,
+    /// Anisotropy direction and strength, as in `KHR_materials_anisotropy`.
+    pub anisotropy_map: Option<SharedString>,
+    #[serde(default)]
+    pub samplers: MaterialSamplers,
 }
 
 impl Asset for MaterialDesc {
@@ -117,32 +354,145 @@ pub struct Material {
     pub rough_metal_factor: Vec2,
     pub emission: Option<Image>,
     pub emission_factor: Vec3,
+    pub emission_strength: f32,
+    pub double_sided: bool,
+    pub clearcoat: f32,
+    pub clearcoat_roughness: f32,
+    pub anisotropy: f32,
+    pub anisotropy_rotation: f32,
+    pub clearcoat_map: Option<Image>,
+    pub anisotropy_map: Option<Image>,
+    pub samplers: MaterialSamplers,
+}
+
+impl Material {
+    /// Glowing magenta material standing in for materials which cannot be loaded.
+    pub fn placeholder() -> Self {
+        let magenta = Vec3::new(1., 0., 1.);
+        Self {
+            transparent: false,
+            color: None,
+            color_factor: magenta,
+            normal: None,
+            normal_amount: 1.,
+            rough_metal: None,
+            rough_metal_factor: Vec2::ONE,
+            emission: None,
+            emission_factor: magenta,
+            emission_strength: DEFAULT_EMISSION_STRENGTH,
+            double_sided: true,
+            clearcoat: 0.,
+            clearcoat_roughness: 0.,
+            anisotropy: 0.,
+            anisotropy_rotation: 0.,
+            clearcoat_map: None,
+            anisotropy_map: None,
+            samplers: MaterialSamplers::default(),
+        }
+    }
+
+    /// Copy of the material with its images downscaled to the mipmap level, see [`Image::mip`].
+    pub(crate) fn mip(&self, level: u32) -> Self {
+        let mip = |image: &Option<Image>| image.as_ref().map(|image| image.mip(level));
+        Self {
+            color: mip(&self.color),
+            normal: mip(&self.normal),
+            rough_metal: mip(&self.rough_metal),
+            emission: mip(&self.emission),
+            clearcoat_map: mip(&self.clearcoat_map),
+            anisotropy_map: mip(&self.anisotropy_map),
+            ..self.clone()
+        }
+    }
+
+    /// Downscale the images on a separate thread, as resizing large images takes longer than a
+    /// frame.
+    pub(crate) fn mip_in_background(&self, level: u32) -> JoinHandle<Self> {
+        let material = self.clone();
+        std::thread::spawn(move || material.mip(level))
+    }
+
+    /// Size of the largest texture of the material, and the memory its textures take at full
+    /// resolution without mipmaps; `None` without textures.
+    pub fn texture_memory(&self) -> Option<(UVec2, u64)> {
+        let rgb = std::mem::size_of::<[f32; 3]>() as u64;
+        let rg = std::mem::size_of::<[f32; 2]>() as u64;
+        let textures = [
+            (&self.color, rgb),
+            (&self.normal, rgb),
+            (&self.rough_metal, rg),
+            (&self.emission, rgb),
+            (&self.clearcoat_map, rg),
+            (&self.anisotropy_map, rgb),
+        ]
+        .into_iter()
+        .filter_map(|(image, bytes_per_texel)| {
+            let image = image.as_ref()?;
+            // sRGB textures keep their 8-bit components
+            let bytes_per_texel = match image.texture_color_space() {
+                ColorSpace::Srgb => 3,
+                ColorSpace::Linear => bytes_per_texel,
+            };
+            Some((image.size(), bytes_per_texel))
+        })
+        .collect::<Vec<_>>();
+        let size = textures
+            .iter()
+            .map(|(size, _)| *size)
+            .max_by_key(|size| size.x * size.y)?;
+        let bytes = textures
+            .iter()
+            .map(|(size, bytes_per_texel)| size.x as u64 * size.y as u64 * bytes_per_texel)
+            .sum();
+        Some((size, bytes))
+    }
 }
 
 impl Compound for Material {
     fn load(cache: AnyCache, id: &SharedString) -> eyre::Result<Self, BoxedError> {
         tracing::debug!(message="Loading material", %id);
         let desc = cache.load::<MaterialDesc>(id)?.cloned();
+        // The slot an image is used in decides how its colors are interpreted
+        let load_image = |path: Option<SharedString>, color_space| match path {
+            Some(path) => cache
+                .load::<Image>(&path)
+                .map(|image| Some(image.cloned().with_color_space(color_space))),
+            None => Ok(None),
+        };
         Ok(Self {
             transparent: desc.transparent,
-            color: desc.color.map(|id| cache.load_owned(id.as_str()).unwrap()),
+            color: load_image(desc.color, ColorSpace::Srgb)?,
             color_factor: desc.color_factor,
-            normal: if let Some(path) = desc.normal {
-                Some(cache.load(&path)?.cloned())
-            } else {
-                None
-            },
+            normal: load_image(desc.normal, ColorSpace::Linear)?,
             normal_amount: desc.normal_amount,
-            rough_metal: desc
-                .rough_metal
-                .map(|id| cache.load_owned(id.as_str()).unwrap()),
+            rough_metal: load_image(desc.rough_metal, ColorSpace::Linear)?,
             rough_metal_factor: desc.rough_metal_factor,
-            emission: if let Some(path) = desc.emission {
-                Some(cache.load(&path)?.cloned())
-            } else {
-                None
-            },
+            emission: load_image(desc.emission, ColorSpace::Srgb)?,
             emission_factor: desc.emission_factor,
+            emission_strength: desc.emission_strength,
+            double_sided: desc.double_sided,
+            clearcoat: desc.clearcoat,
+            clearcoat_roughness: desc.clearcoat_roughness,
+            anisotropy: desc.anisotropy,
+            anisotropy_rotation: desc.anisotropy_rotation,
+            clearcoat_map: load_image(desc.clearcoat_map, ColorSpace::Linear)?,
+            anisotropy_map: load_image(desc.anisotropy_map, ColorSpace::Linear)?,
+            samplers: desc.samplers,
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn srgb_mips_are_averaged_in_linear_space() {
+        let halves = image::RgbImage::from_fn(2, 2, |x, _| image::Rgb([255 * x as u8; 3]));
+        let image = Image::from(image::DynamicImage::ImageRgb8(halves));
+        let mip = image.mip(1);
+        assert_eq!(mip.size(), UVec2::ONE);
+        // Half of the light, rather than the encoded 128
+        assert_eq!(mip.to_rgb8().get_pixel(0, 0).0, [188; 3]);
+    }
+}
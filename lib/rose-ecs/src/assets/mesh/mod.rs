@@ -5,7 +5,7 @@ use eyre::Result;
 use glam::{vec2, vec3, Quat, Vec2, Vec3};
 
 use rose_core::mesh::CpuMesh;
-use rose_renderer::material::Vertex;
+use rose_renderer::{material::Vertex, morph::MorphTarget};
 
 pub mod obj;
 
@@ -40,6 +40,7 @@ impl Loader<MeshAsset> for DynamicMeshLoader {
 pub struct MeshAsset {
     pub vertices: Vec<Vertex>,
     pub indices: Vec<u32>,
+    pub morph_targets: Vec<MorphTarget>,
 }
 
 fn quad(_center: Vec3, normal: Vec3) -> [Vertex; 4] {
@@ -64,6 +65,7 @@ impl From<CpuMesh<Vertex, u32>> for MeshAsset {
         Self {
             vertices: value.vertices,
             indices: value.indices,
+            morph_targets: vec![],
         }
     }
 }
@@ -88,7 +90,11 @@ impl MeshAsset {
             i += 4;
         }
 
-        Self { vertices, indices }
+        Self {
+            vertices,
+            indices,
+            morph_targets: vec![],
+        }
     }
 
     pub fn uv_sphere(radius: f32, nlon: usize, nlat: usize) -> Self {
@@ -145,6 +151,70 @@ impl MeshAsset {
         Self {
             vertices,
             indices: indices.into_iter().map(|i| i as _).collect(),
+            morph_targets: vec![],
         }
     }
+
+    /// Distance from the origin of the mesh to its furthest vertex.
+    pub fn bounding_radius(&self) -> f32 {
+        self.vertices
+            .iter()
+            .map(|vertex| vertex.position.length())
+            .fold(0., f32::max)
+    }
+
+    /// Distance along the ray to the closest triangle it hits, in units of `direction`. Both sides
+    /// of the triangles are hit.
+    pub fn raycast(&self, origin: Vec3, direction: Vec3) -> Option<f32> {
+        self.indices
+            .chunks_exact(3)
+            .filter_map(|triangle| {
+                let [a, b, c] = [0, 1, 2].map(|i| self.vertices[triangle[i] as usize].position);
+                // Möller-Trumbore intersection
+                let (ab, ac) = (b - a, c - a);
+                let p = direction.cross(ac);
+                let det = ab.dot(p);
+                if det.abs() < f32::EPSILON {
+                    return None;
+                }
+                let to_origin = origin - a;
+                let u = to_origin.dot(p) / det;
+                let q = to_origin.cross(ab);
+                let v = direction.dot(q) / det;
+                if u < 0. || v < 0. || u + v > 1. {
+                    return None;
+                }
+                let t = ac.dot(q) / det;
+                (t >= 0.).then_some(t)
+            })
+            .min_by(|a, b| a.total_cmp(b))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn raycast_hits_closest_triangle() {
+        let vertex = |x, y, z| Vertex::new(vec3(x, y, z), Vec3::Z, Vec2::ZERO);
+        let mesh = MeshAsset {
+            vertices: vec![
+                vertex(-1., -1., 1.),
+                vertex(1., -1., 1.),
+                vertex(0., 1., 1.),
+                vertex(-1., -1., -1.),
+                vertex(0., 1., -1.),
+                vertex(1., -1., -1.),
+            ],
+            indices: vec![0, 1, 2, 3, 4, 5],
+            morph_targets: vec![],
+        };
+        let hit = mesh.raycast(vec3(0., 0., 5.), Vec3::NEG_Z);
+        assert!((hit.unwrap() - 4.).abs() < 1e-5);
+        let between = mesh.raycast(Vec3::ZERO, Vec3::NEG_Z * 2.);
+        assert!((between.unwrap() - 0.5).abs() < 1e-5);
+        assert_eq!(mesh.raycast(vec3(3., 0., 5.), Vec3::NEG_Z), None);
+        assert_eq!(mesh.raycast(vec3(0., 0., 5.), Vec3::Z), None);
+    }
 }
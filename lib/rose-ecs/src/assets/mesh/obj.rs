@@ -11,6 +11,8 @@ pub struct WavefrontLoader {}
 
 impl Loader<MeshAsset> for WavefrontLoader {
     fn load(content: Cow<[u8]>, _ext: &str) -> Result<MeshAsset, BoxedError> {
+        // Vertex colors are a non-standard extension of the OBJ format that `obj-rs` does not
+        // parse, so vertices keep the default white color.
         let obj = obj::load_obj::<obj::TexturedVertex, _, u32>(Cursor::new(content))?;
         Ok(MeshAsset {
             vertices: obj
@@ -25,6 +27,7 @@ impl Loader<MeshAsset> for WavefrontLoader {
                 })
                 .collect(),
             indices: obj.indices,
+            morph_targets: vec![],
         })
     }
 }
@@ -81,6 +84,7 @@ impl Loader<ObjMesh> for WavefrontLoader {
                     MeshAsset {
                         vertices: vertex,
                         indices,
+                        morph_targets: vec![],
                     },
                 )
             })
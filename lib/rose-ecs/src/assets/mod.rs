@@ -1,3 +1,5 @@
+use std::path::{Component, Path};
+
 pub use assets_manager as manager;
 
 pub use material::*;
@@ -9,3 +11,16 @@ pub mod material;
 pub mod mesh;
 pub mod object;
 pub mod scene;
+
+/// ID of the asset stored at `path`, relative to the asset root: `meshes/tree.obj` is `meshes.tree`.
+pub fn asset_id(path: impl AsRef<Path>) -> String {
+    path.as_ref()
+        .with_extension("")
+        .components()
+        .filter_map(|component| match component {
+            Component::Normal(name) => Some(name.to_string_lossy()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join(".")
+}
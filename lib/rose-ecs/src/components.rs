@@ -2,15 +2,32 @@ use std::{
     f32::consts::PI,
     hash::{Hash, Hasher},
     ops::Range,
+    path::PathBuf,
 };
 
 use assets_manager::SharedString;
 use egui::{DragValue, Grid, Ui};
-use glam::{Vec2, Vec3};
+use glam::{UVec3, Vec2, Vec3, Vec4};
 use hecs::Bundle;
 use serde::{Deserialize, Serialize};
 
-use rose_core::{camera::Projection, transform::Transform};
+use rose_core::{
+    camera::{DepthMode, Projection},
+    light::color_temperature,
+    transform::Transform,
+};
+use rose_renderer::{
+    clip::plane_equation,
+    env::SimpleSkyParams,
+    fog::FogParams,
+    polyline::PolylinePoint,
+    probes::IrradianceProbeGrid,
+    reflection_probes::{ProbeInfluence, ReflectionProbe as ReflectionProbeParams},
+    refraction::RefractionParams,
+    sprites::{Billboard, Sprite as SpriteParams},
+    water::WaterParams,
+    PostprocessInterface,
+};
 
 #[cfg(feature = "ui")]
 use crate::systems::ComponentUi;
@@ -44,6 +61,32 @@ impl NamedComponent for Inactive {
     const NAME: &'static str = "Inactive";
 }
 
+/// Marks meshes which never move, to merge with the other static meshes sharing their material,
+/// see [`crate::systems::RenderSystem::build_static_batches`].
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct Static;
+
+#[cfg(feature = "ui")]
+impl ComponentUi for Static {
+    fn ui(&mut self, ui: &mut Ui) {
+        ui.weak("No associated component data");
+    }
+}
+
+impl NamedComponent for Static {
+    const NAME: &'static str = "Static";
+}
+
+/// Marks entities selected in an editor. Selected entities, along with their children, are drawn
+/// with an outline.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct Selected;
+
+/// Marks the entity under the cursor in an editor. Hovered entities, along with their children, are
+/// drawn with a subtle highlight.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct Hovered;
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(default)]
 pub struct CameraParams {
@@ -185,14 +228,135 @@ pub enum LightKind {
     Ambient,
     Point,
     Directional,
+    /// Rectangular area light, facing its local +Z axis.
+    Rect,
+    /// Disk-shaped area light, facing its local +Z axis.
+    Disk,
 }
 
-#[derive(Debug, Copy, Clone, Deserialize, Serialize)]
+impl LightKind {
+    /// Units the intensity of this kind of light can be given in; the first one is the default.
+    pub fn units(self) -> &'static [LightUnit] {
+        match self {
+            Self::Ambient => &[LightUnit::Nit],
+            Self::Point => &[LightUnit::Candela, LightUnit::Lumen],
+            Self::Directional => &[LightUnit::Lux],
+            Self::Rect | Self::Disk => &[LightUnit::Nit, LightUnit::Lumen],
+        }
+    }
+
+    pub fn is_area(self) -> bool {
+        matches!(self, Self::Rect | Self::Disk)
+    }
+
+    pub fn default_unit(self) -> LightUnit {
+        self.units()[0]
+    }
+}
+
+/// Photometric unit of a light's intensity.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Deserialize, Serialize)]
+pub enum LightUnit {
+    /// Luminous intensity of a point light, in lm/sr.
+    Candela,
+    /// Total luminous flux emitted by a point or area light.
+    Lumen,
+    /// Illuminance received from a directional light, in lm/m².
+    Lux,
+    /// Luminance of the surroundings for ambient light, or of the surface of an area light, in
+    /// cd/m².
+    Nit,
+}
+
+impl LightUnit {
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Self::Candela => "cd",
+            Self::Lumen => "lm",
+            Self::Lux => "lx",
+            Self::Nit => "nt",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 #[serde(default)]
 pub struct Light {
     pub kind: LightKind,
+    /// Color of the light. When a temperature is set, this tints the black body color instead.
     pub color: Vec3,
-    pub power: f32,
+    /// Color temperature, in Kelvin.
+    pub temperature: Option<f32>,
+    #[serde(alias = "power")]
+    pub intensity: f32,
+    /// Unit of [`Self::intensity`]; units that don't apply to the light kind are treated as its
+    /// default unit.
+    pub unit: LightUnit,
+    /// Size of area lights, in local units: width and height of rectangles, diameters of disks.
+    pub size: Vec2,
+    /// Whether area lights emit light from both faces.
+    pub two_sided: bool,
+    /// Asset id of an image modulating the light, or empty for none. Directional lights project
+    /// it along their direction; point lights take the six faces of a cube, see
+    /// [`rose_renderer::cookie`]. Other lights ignore it.
+    pub cookie: String,
+    /// World size over which the cookie of directional lights repeats.
+    pub cookie_size: Vec2,
+    /// Distance from the camera past which point and area lights stop being shaded, in world
+    /// units. Lights can also be culled once too small on screen, see
+    /// [`rose_renderer::light_culling`].
+    pub max_distance: Option<f32>,
+}
+
+impl Light {
+    /// Linear color of the light, with its intensity converted to the renderer's units.
+    pub fn emitted_color(&self) -> Vec3 {
+        let unit = if self.kind.units().contains(&self.unit) {
+            self.unit
+        } else {
+            self.kind.default_unit()
+        };
+        let color = match self.temperature {
+            Some(kelvin) => self.color * color_temperature(kelvin),
+            None => self.color,
+        };
+        color * self.intensity * self.renderer_scale(unit)
+    }
+
+    /// Change the unit of the intensity, converting the intensity value so that the emitted light
+    /// stays the same.
+    pub fn set_unit(&mut self, unit: LightUnit) {
+        self.intensity *= self.renderer_scale(self.unit) / self.renderer_scale(unit);
+        self.unit = unit;
+    }
+
+    /// Distance from a point light at which its illuminance falls below `threshold`, in lux.
+    pub fn point_radius(&self, threshold: f32) -> f32 {
+        (self.emitted_color().max_element() / threshold).sqrt()
+    }
+
+    /// Surface of area lights, in local units.
+    pub fn area(&self) -> f32 {
+        match self.kind {
+            LightKind::Rect => self.size.x * self.size.y,
+            LightKind::Disk => PI * self.size.x * self.size.y / 4.,
+            _ => 0.,
+        }
+    }
+
+    /// Factor converting an intensity in this unit into the renderer's light units, which are
+    /// candela for point lights, lux for directional lights and nits for ambient and area lights.
+    fn renderer_scale(&self, unit: LightUnit) -> f32 {
+        match (self.kind, unit) {
+            (LightKind::Point, LightUnit::Lumen) => 1. / (4. * PI),
+            // Lambertian emitter: flux = pi * luminance * area, per emitting face
+            (LightKind::Rect | LightKind::Disk, LightUnit::Lumen) => {
+                let faces = if self.two_sided { 2. } else { 1. };
+                1. / (PI * self.area().max(1e-6) * faces)
+            }
+            _ => 1.,
+        }
+    }
 }
 
 #[cfg(feature = "ui")]
@@ -204,9 +368,14 @@ impl ComponentUi for Light {
                 ui.radio_value(&mut self.kind, LightKind::Point, "Point");
                 ui.radio_value(&mut self.kind, LightKind::Directional, "Directional");
                 ui.radio_value(&mut self.kind, LightKind::Ambient, "Ambient");
+                ui.radio_value(&mut self.kind, LightKind::Rect, "Rectangle");
+                ui.radio_value(&mut self.kind, LightKind::Disk, "Disk");
             })
             .response
             .labelled_by(kind_label);
+            if !self.kind.units().contains(&self.unit) {
+                self.unit = self.kind.default_unit();
+            }
             ui.end_row();
 
             let color_label = ui.label("Color").id;
@@ -214,9 +383,138 @@ impl ComponentUi for Light {
                 .labelled_by(color_label);
             ui.end_row();
 
-            let power_label = ui.label("Power").id;
-            ui.add(DragValue::new(&mut self.power).suffix(" W"))
-                .labelled_by(power_label);
+            let temperature_label = ui.label("Temperature").id;
+            ui.horizontal(|ui| {
+                let mut enabled = self.temperature.is_some();
+                ui.checkbox(&mut enabled, "");
+                match (enabled, self.temperature) {
+                    (true, None) => self.temperature = Some(6500.),
+                    (false, Some(_)) => self.temperature = None,
+                    _ => {}
+                }
+                if let Some(kelvin) = &mut self.temperature {
+                    ui.add(
+                        DragValue::new(kelvin)
+                            .clamp_range(1000..=40000)
+                            .speed(10.)
+                            .suffix(" K"),
+                    );
+                    let [r, g, b] = color_temperature(*kelvin).to_array();
+                    let preview = egui::Rgba::from_rgb(r, g, b);
+                    let (rect, _) =
+                        ui.allocate_exact_size(egui::vec2(16., 16.), egui::Sense::hover());
+                    ui.painter().rect_filled(rect, 2., preview);
+                }
+            })
+            .response
+            .labelled_by(temperature_label);
+            ui.end_row();
+
+            let intensity_label = ui.label("Intensity").id;
+            ui.horizontal(|ui| {
+                ui.add(
+                    DragValue::new(&mut self.intensity)
+                        .clamp_range(0.0..=f32::INFINITY)
+                        .suffix(format!(" {}", self.unit.symbol())),
+                );
+                let units = self.kind.units();
+                if units.len() > 1 {
+                    let mut unit = self.unit;
+                    for &candidate in units {
+                        ui.selectable_value(&mut unit, candidate, candidate.symbol());
+                    }
+                    if unit != self.unit {
+                        self.set_unit(unit);
+                    }
+                }
+            })
+            .response
+            .labelled_by(intensity_label);
+
+            if self.kind.is_area() {
+                ui.end_row();
+
+                let size_label = ui.label("Size").id;
+                ui.horizontal(|ui| {
+                    ui.add(
+                        DragValue::new(&mut self.size.x)
+                            .clamp_range(1e-3..=f32::INFINITY)
+                            .speed(0.01),
+                    );
+                    ui.add(
+                        DragValue::new(&mut self.size.y)
+                            .clamp_range(1e-3..=f32::INFINITY)
+                            .speed(0.01),
+                    );
+                })
+                .response
+                .labelled_by(size_label);
+                ui.end_row();
+
+                let two_sided_label = ui.label("Two-sided").id;
+                ui.checkbox(&mut self.two_sided, "")
+                    .labelled_by(two_sided_label);
+            }
+
+            if matches!(self.kind, LightKind::Point | LightKind::Directional) {
+                ui.end_row();
+
+                let cookie_label = ui.label("Cookie").id;
+                ui.text_edit_singleline(&mut self.cookie)
+                    .on_hover_text(if self.kind == LightKind::Point {
+                        "Asset id of an image of the six faces of a cube, in 3 columns and 2 rows"
+                    } else {
+                        "Asset id of an image projected along the light"
+                    })
+                    .labelled_by(cookie_label);
+
+                if self.kind == LightKind::Directional && !self.cookie.is_empty() {
+                    ui.end_row();
+
+                    let cookie_size_label = ui.label("Cookie size").id;
+                    ui.horizontal(|ui| {
+                        ui.add(
+                            DragValue::new(&mut self.cookie_size.x)
+                                .clamp_range(1e-3..=f32::INFINITY)
+                                .speed(0.1),
+                        );
+                        ui.add(
+                            DragValue::new(&mut self.cookie_size.y)
+                                .clamp_range(1e-3..=f32::INFINITY)
+                                .speed(0.1),
+                        );
+                    })
+                    .response
+                    .labelled_by(cookie_size_label);
+                }
+            }
+
+            if matches!(
+                self.kind,
+                LightKind::Point | LightKind::Rect | LightKind::Disk
+            ) {
+                ui.end_row();
+
+                let max_distance_label = ui.label("Max. distance").id;
+                ui.horizontal(|ui| {
+                    let mut enabled = self.max_distance.is_some();
+                    ui.checkbox(&mut enabled, "");
+                    match (enabled, self.max_distance) {
+                        (true, None) => self.max_distance = Some(50.),
+                        (false, Some(_)) => self.max_distance = None,
+                        _ => {}
+                    }
+                    if let Some(distance) = &mut self.max_distance {
+                        ui.add(
+                            DragValue::new(distance)
+                                .clamp_range(0.0..=f32::INFINITY)
+                                .speed(0.1),
+                        );
+                    }
+                })
+                .response
+                .labelled_by(max_distance_label);
+            }
             // ui.end_row();
         });
     }
@@ -232,7 +530,18 @@ impl Hash for Light {
         for f in self.color.to_array() {
             f.to_bits().hash(state);
         }
-        self.power.to_bits().hash(state);
+        self.temperature.map(f32::to_bits).hash(state);
+        self.intensity.to_bits().hash(state);
+        self.unit.hash(state);
+        for f in self.size.to_array() {
+            f.to_bits().hash(state);
+        }
+        self.two_sided.hash(state);
+        self.cookie.hash(state);
+        for f in self.cookie_size.to_array() {
+            f.to_bits().hash(state);
+        }
+        self.max_distance.map(f32::to_bits).hash(state);
     }
 }
 
@@ -241,11 +550,650 @@ impl Default for Light {
         Self {
             kind: LightKind::Point,
             color: Vec3::ONE,
-            power: 1.,
+            temperature: None,
+            intensity: 1.,
+            unit: LightUnit::Candela,
+            size: Vec2::ONE,
+            two_sided: false,
+            cookie: String::new(),
+            cookie_size: Vec2::splat(10.),
+            max_distance: None,
+        }
+    }
+}
+
+/// Scene-wide exponential height fog. Only the first active entity with this component is used.
+pub type Fog = FogParams;
+
+#[cfg(feature = "ui")]
+impl ComponentUi for Fog {
+    fn ui(&mut self, ui: &mut Ui) {
+        FogParams::ui(self, ui);
+    }
+}
+
+impl NamedComponent for Fog {
+    const NAME: &'static str = "Fog";
+}
+
+/// Section plane through the entity, cutting away the meshes of the standard material on the side
+/// opposite to the local +Y axis of the entity, e.g. for cutaway views of models. Only the first
+/// [`rose_renderer::clip::MAX_CLIP_PLANES`] planes are used.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ClipPlane {
+    /// Cut away the side of the +Y axis instead.
+    pub flip: bool,
+}
+
+impl ClipPlane {
+    /// Plane equation in world space, given the global transform of the entity.
+    pub fn equation(&self, transform: &Transform) -> Vec4 {
+        let normal = transform.rotation * Vec3::Y;
+        plane_equation(transform.position, if self.flip { -normal } else { normal })
+    }
+}
+
+#[cfg(feature = "ui")]
+impl ComponentUi for ClipPlane {
+    fn ui(&mut self, ui: &mut Ui) {
+        ui.checkbox(&mut self.flip, "Flip")
+            .on_hover_text("Cut away the side of the +Y axis of the entity instead");
+    }
+}
+
+impl NamedComponent for ClipPlane {
+    const NAME: &'static str = "Clip Plane";
+}
+
+/// Draws the mesh of the entity as a water surface instead of with its material. The mesh should be
+/// a horizontal plane; reflections are rendered across the plane of the first reflective surface.
+#[derive(Debug, Copy, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Water {
+    pub color: Vec3,
+    pub wave_scale: f32,
+    pub wave_speed: Vec2,
+    pub wave_strength: f32,
+    pub shore_fade: f32,
+    pub clarity: f32,
+    pub distortion: f32,
+    pub reflections: bool,
+}
+
+impl Default for Water {
+    fn default() -> Self {
+        WaterParams::default().into()
+    }
+}
+
+impl From<WaterParams> for Water {
+    fn from(value: WaterParams) -> Self {
+        Self {
+            color: value.color,
+            wave_scale: value.wave_scale,
+            wave_speed: value.wave_speed,
+            wave_strength: value.wave_strength,
+            shore_fade: value.shore_fade,
+            clarity: value.clarity,
+            distortion: value.distortion,
+            reflections: value.reflections,
+        }
+    }
+}
+
+impl From<Water> for WaterParams {
+    fn from(value: Water) -> Self {
+        Self {
+            color: value.color,
+            wave_scale: value.wave_scale,
+            wave_speed: value.wave_speed,
+            wave_strength: value.wave_strength,
+            shore_fade: value.shore_fade,
+            clarity: value.clarity,
+            distortion: value.distortion,
+            reflections: value.reflections,
+        }
+    }
+}
+
+#[cfg(feature = "ui")]
+impl ComponentUi for Water {
+    fn ui(&mut self, ui: &mut Ui) {
+        let mut params = WaterParams::from(*self);
+        params.ui(ui);
+        *self = params.into();
+    }
+}
+
+impl NamedComponent for Water {
+    const NAME: &'static str = "Water";
+}
+
+/// Draws the mesh of the entity as a refractive surface, e.g. glass, instead of with its material.
+/// See [`rose_renderer::refraction`] for its limitations.
+#[derive(Debug, Copy, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Refractive {
+    pub tint: Vec3,
+    pub ior: f32,
+    pub roughness: f32,
+    pub thickness: f32,
+}
+
+impl Default for Refractive {
+    fn default() -> Self {
+        RefractionParams::default().into()
+    }
+}
+
+impl From<RefractionParams> for Refractive {
+    fn from(value: RefractionParams) -> Self {
+        Self {
+            tint: value.tint,
+            ior: value.ior,
+            roughness: value.roughness,
+            thickness: value.thickness,
+        }
+    }
+}
+
+impl From<Refractive> for RefractionParams {
+    fn from(value: Refractive) -> Self {
+        Self {
+            tint: value.tint,
+            ior: value.ior,
+            roughness: value.roughness,
+            thickness: value.thickness,
+        }
+    }
+}
+
+#[cfg(feature = "ui")]
+impl ComponentUi for Refractive {
+    fn ui(&mut self, ui: &mut Ui) {
+        let mut params = RefractionParams::from(*self);
+        params.ui(ui);
+        *self = params.into();
+    }
+}
+
+impl NamedComponent for Refractive {
+    const NAME: &'static str = "Refractive";
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Deserialize, Serialize)]
+pub enum SpriteBillboard {
+    /// Parallel to the screen.
+    FaceCamera,
+    /// Only rotating around the up axis of the entity, e.g. for trees and grass.
+    Upright,
+}
+
+/// Draws the image of the entity, given as a `Handle<Image>` component, as a sprite centered on
+/// the entity and turned towards the camera. Sprites are unlit.
+#[derive(Debug, Copy, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Sprite {
+    /// Width and height of the sprite, scaled by the entity scale.
+    pub size: Vec2,
+    /// Linear RGBA color multiplied with the image.
+    pub tint: Vec4,
+    pub billboard: SpriteBillboard,
+}
+
+impl Default for Sprite {
+    fn default() -> Self {
+        Self {
+            size: Vec2::ONE,
+            tint: Vec4::ONE,
+            billboard: SpriteBillboard::FaceCamera,
         }
     }
 }
 
+impl Sprite {
+    pub fn params(&self, transform: &Transform) -> SpriteParams {
+        SpriteParams {
+            position: transform.position,
+            size: self.size * transform.scale.truncate(),
+            tint: self.tint,
+            billboard: match self.billboard {
+                SpriteBillboard::FaceCamera => Billboard::FaceCamera,
+                SpriteBillboard::Upright => Billboard::Axis(transform.rotation * Vec3::Y),
+            },
+            uv_rect: SpriteParams::FULL_UV_RECT,
+        }
+    }
+}
+
+#[cfg(feature = "ui")]
+impl ComponentUi for Sprite {
+    fn ui(&mut self, ui: &mut Ui) {
+        Grid::new("component-sprite").num_columns(2).show(ui, |ui| {
+            let size_label = ui.label("Size").id;
+            ui.horizontal(|ui| {
+                for v in self.size.as_mut() {
+                    ui.add(
+                        DragValue::new(v)
+                            .clamp_range(0.0..=f32::INFINITY)
+                            .speed(1e-2),
+                    );
+                }
+            })
+            .response
+            .labelled_by(size_label);
+            ui.end_row();
+
+            let tint_label = ui.label("Tint").id;
+            let mut tint = self.tint.to_array();
+            ui.color_edit_button_rgba_unmultiplied(&mut tint)
+                .labelled_by(tint_label);
+            self.tint = Vec4::from_array(tint);
+            ui.end_row();
+
+            let billboard_label = ui.label("Billboard").id;
+            ui.horizontal(|ui| {
+                ui.radio_value(
+                    &mut self.billboard,
+                    SpriteBillboard::FaceCamera,
+                    "Face camera",
+                );
+                ui.radio_value(&mut self.billboard, SpriteBillboard::Upright, "Upright");
+            })
+            .response
+            .labelled_by(billboard_label);
+            ui.end_row();
+        });
+    }
+}
+
+impl NamedComponent for Sprite {
+    const NAME: &'static str = "Sprite";
+}
+
+/// Draws a line through the points, given in the space of the entity.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Polyline {
+    pub points: Vec<Vec3>,
+    /// Width of the line, in pixels.
+    pub width: f32,
+    /// Linear RGBA color of the line.
+    pub color: Vec4,
+}
+
+impl Default for Polyline {
+    fn default() -> Self {
+        Self {
+            points: vec![],
+            width: 2.,
+            color: Vec4::ONE,
+        }
+    }
+}
+
+impl Polyline {
+    pub fn new(points: impl IntoIterator<Item = Vec3>, width: f32, color: Vec4) -> Self {
+        Self {
+            points: points.into_iter().collect(),
+            width,
+            color,
+        }
+    }
+
+    /// Points of the line in world space.
+    pub fn world_points(&self, transform: &Transform) -> Vec<PolylinePoint> {
+        let matrix = transform.matrix();
+        self.points
+            .iter()
+            .map(|p| PolylinePoint::new(matrix.transform_point3(*p), self.width, self.color))
+            .collect()
+    }
+}
+
+#[cfg(feature = "ui")]
+impl ComponentUi for Polyline {
+    fn ui(&mut self, ui: &mut Ui) {
+        Grid::new("component-polyline")
+            .num_columns(2)
+            .show(ui, |ui| {
+                let width_label = ui.label("Width").id;
+                ui.add(
+                    DragValue::new(&mut self.width)
+                        .clamp_range(0.0..=f32::INFINITY)
+                        .speed(0.1)
+                        .suffix(" px"),
+                )
+                .labelled_by(width_label);
+                ui.end_row();
+
+                let color_label = ui.label("Color").id;
+                let mut color = self.color.to_array();
+                ui.color_edit_button_rgba_unmultiplied(&mut color)
+                    .labelled_by(color_label);
+                self.color = Vec4::from_array(color);
+                ui.end_row();
+
+                ui.label("Points");
+                ui.label(self.points.len().to_string());
+                ui.end_row();
+            });
+    }
+}
+
+impl NamedComponent for Polyline {
+    const NAME: &'static str = "Polyline";
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Deserialize, Serialize)]
+pub enum ReflectionProbeShape {
+    Box,
+    Sphere,
+}
+
+/// Captures the scene around the entity for reflections on nearby surfaces. The probe is captured
+/// when first added and then only when a bake is requested, so it does not follow moving objects.
+#[derive(Debug, Copy, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ReflectionProbe {
+    pub shape: ReflectionProbeShape,
+    /// Half extents of the box influence volume, in world units.
+    pub half_extents: Vec3,
+    /// Radius of the sphere influence volume, in world units.
+    pub radius: f32,
+    pub intensity: f32,
+    #[serde(skip)]
+    pub bake_requested: bool,
+}
+
+impl Default for ReflectionProbe {
+    fn default() -> Self {
+        Self {
+            shape: ReflectionProbeShape::Box,
+            half_extents: Vec3::splat(5.),
+            radius: 5.,
+            intensity: 1.,
+            bake_requested: false,
+        }
+    }
+}
+
+impl ReflectionProbe {
+    pub fn request_bake(&mut self) {
+        self.bake_requested = true;
+    }
+
+    pub fn params(&self, position: Vec3) -> ReflectionProbeParams {
+        let influence = match self.shape {
+            ReflectionProbeShape::Box => ProbeInfluence::Box {
+                half_extents: self.half_extents,
+            },
+            ReflectionProbeShape::Sphere => ProbeInfluence::Sphere {
+                radius: self.radius,
+            },
+        };
+        ReflectionProbeParams {
+            position,
+            influence,
+            intensity: self.intensity,
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(feature = "ui")]
+impl ComponentUi for ReflectionProbe {
+    fn ui(&mut self, ui: &mut Ui) {
+        Grid::new("component-reflection-probe")
+            .num_columns(2)
+            .show(ui, |ui| {
+                let shape_label = ui.label("Shape").id;
+                ui.horizontal(|ui| {
+                    ui.radio_value(&mut self.shape, ReflectionProbeShape::Box, "Box");
+                    ui.radio_value(&mut self.shape, ReflectionProbeShape::Sphere, "Sphere");
+                })
+                .response
+                .labelled_by(shape_label);
+                ui.end_row();
+
+                match self.shape {
+                    ReflectionProbeShape::Box => {
+                        let extents_label = ui.label("Half extents").id;
+                        ui.horizontal(|ui| {
+                            for v in self.half_extents.as_mut() {
+                                ui.add(DragValue::new(v).clamp_range(0.0..=f32::INFINITY));
+                            }
+                        })
+                        .response
+                        .labelled_by(extents_label);
+                    }
+                    ReflectionProbeShape::Sphere => {
+                        let radius_label = ui.label("Radius").id;
+                        ui.add(DragValue::new(&mut self.radius).clamp_range(0.0..=f32::INFINITY))
+                            .labelled_by(radius_label);
+                    }
+                }
+                ui.end_row();
+
+                let intensity_label = ui.label("Intensity").id;
+                ui.add(
+                    DragValue::new(&mut self.intensity)
+                        .clamp_range(0.0..=f32::INFINITY)
+                        .speed(1e-2),
+                )
+                .labelled_by(intensity_label);
+                ui.end_row();
+            });
+        if ui.button("Bake").clicked() {
+            self.request_bake();
+        }
+    }
+}
+
+impl NamedComponent for ReflectionProbe {
+    const NAME: &'static str = "Reflection probe";
+}
+
+/// Grid of irradiance probes filling the box around the entity, for the indirect lighting of the
+/// scene. Like [`ReflectionProbe`], the probes are baked when the volume is first added and then
+/// only when a bake is requested. The last baked volume lights the scene.
+#[derive(Debug, Copy, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct IrradianceVolume {
+    /// Half extents of the box covered by the probes, in world units.
+    pub half_extents: Vec3,
+    /// Number of probes along each axis.
+    pub probes: UVec3,
+    /// Resolution of the cubemaps captured around each probe.
+    pub capture_resolution: u32,
+    #[serde(skip)]
+    pub bake_requested: bool,
+}
+
+impl Default for IrradianceVolume {
+    fn default() -> Self {
+        Self {
+            half_extents: Vec3::splat(5.),
+            probes: UVec3::splat(4),
+            capture_resolution: 32,
+            bake_requested: false,
+        }
+    }
+}
+
+impl IrradianceVolume {
+    pub fn request_bake(&mut self) {
+        self.bake_requested = true;
+    }
+
+    pub fn grid(&self, position: Vec3) -> IrradianceProbeGrid {
+        IrradianceProbeGrid::new(
+            position - self.half_extents,
+            position + self.half_extents,
+            self.probes,
+        )
+    }
+}
+
+#[cfg(feature = "ui")]
+impl ComponentUi for IrradianceVolume {
+    fn ui(&mut self, ui: &mut Ui) {
+        Grid::new("component-irradiance-volume")
+            .num_columns(2)
+            .show(ui, |ui| {
+                let extents_label = ui.label("Half extents").id;
+                ui.horizontal(|ui| {
+                    for v in self.half_extents.as_mut() {
+                        ui.add(DragValue::new(v).clamp_range(0.0..=f32::INFINITY));
+                    }
+                })
+                .response
+                .labelled_by(extents_label);
+                ui.end_row();
+
+                let probes_label = ui.label("Probes").id;
+                ui.horizontal(|ui| {
+                    for v in self.probes.as_mut() {
+                        ui.add(DragValue::new(v).clamp_range(1..=16));
+                    }
+                })
+                .response
+                .labelled_by(probes_label);
+                ui.end_row();
+
+                let resolution_label = ui.label("Capture resolution").id;
+                ui.add(DragValue::new(&mut self.capture_resolution).clamp_range(8..=256))
+                    .labelled_by(resolution_label);
+                ui.end_row();
+            });
+        if ui.button("Bake").clicked() {
+            self.request_bake();
+        }
+    }
+}
+
+impl NamedComponent for IrradianceVolume {
+    const NAME: &'static str = "Irradiance volume";
+}
+
+/// Free-form labels used to find entities, e.g. with [`crate::scene::Scene::query_tagged`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(transparent)]
+pub struct Tags(pub Vec<String>);
+
+impl<S: Into<String>> FromIterator<S> for Tags {
+    fn from_iter<T: IntoIterator<Item = S>>(iter: T) -> Self {
+        let mut tags = Self::default();
+        for tag in iter {
+            tags.insert(tag);
+        }
+        tags
+    }
+}
+
+impl Tags {
+    pub fn contains(&self, tag: &str) -> bool {
+        self.0.iter().any(|t| t == tag)
+    }
+
+    pub fn insert(&mut self, tag: impl Into<String>) {
+        let tag = tag.into();
+        if !self.contains(&tag) {
+            self.0.push(tag);
+        }
+    }
+
+    pub fn remove(&mut self, tag: &str) {
+        self.0.retain(|t| t != tag);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &str> {
+        self.0.iter().map(|s| s.as_str()).filter(|s| !s.is_empty())
+    }
+}
+
+#[cfg(feature = "ui")]
+impl ComponentUi for Tags {
+    fn ui(&mut self, ui: &mut Ui) {
+        let mut removed = None;
+        for (ix, tag) in self.0.iter_mut().enumerate() {
+            ui.horizontal(|ui| {
+                ui.text_edit_singleline(tag);
+                if ui.small_button("x").clicked() {
+                    removed = Some(ix);
+                }
+            });
+        }
+        if let Some(ix) = removed {
+            self.0.remove(ix);
+        }
+        if ui.button("Add tag").clicked() {
+            self.0.push(String::new());
+        }
+    }
+}
+
+impl NamedComponent for Tags {
+    const NAME: &'static str = "Tags";
+}
+
+/// Weights of the morph targets of the meshes of the entity and its children, in the order of
+/// the targets. Entities with weights get their own copy of their meshes on the GPU.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(transparent)]
+pub struct MorphWeights(pub Vec<f32>);
+
+#[cfg(feature = "ui")]
+impl ComponentUi for MorphWeights {
+    fn ui(&mut self, ui: &mut Ui) {
+        Grid::new("morph-weights").num_columns(2).show(ui, |ui| {
+            for (ix, weight) in self.0.iter_mut().enumerate() {
+                let label = ui.label(format!("Target {}", ix)).id;
+                ui.add(egui::Slider::new(weight, 0.0..=1.0))
+                    .labelled_by(label);
+                ui.end_row();
+            }
+        });
+        ui.horizontal(|ui| {
+            if ui.button("Add target").clicked() {
+                self.0.push(0.);
+            }
+            if ui
+                .add_enabled(!self.0.is_empty(), egui::Button::new("Remove last"))
+                .clicked()
+            {
+                self.0.pop();
+            }
+        });
+    }
+}
+
+impl NamedComponent for MorphWeights {
+    const NAME: &'static str = "Morph Weights";
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub enum EnvironmentSettings {
+    SimpleSky(SimpleSkyParams),
+    /// Equirectangular environment map; relative paths are relative to the scene's asset root.
+    Map(PathBuf),
+}
+
+/// Renderer settings saved with the scene, applied when the scene becomes active with
+/// [`crate::CoreSystems::activate_scene`]. Only the first entity with this component is used.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct RendererSettings {
+    pub post_process: PostprocessInterface,
+    /// Environment of the scene; the current environment is kept when not set.
+    pub environment: Option<EnvironmentSettings>,
+    /// Depth buffer mapping, with reversed depth for scenes spanning large distances.
+    pub depth: DepthMode,
+    /// Render relative to the camera, for scenes spanning huge distances.
+    pub camera_relative: bool,
+}
+
 #[derive(Debug, Default, Bundle)]
 pub struct LightBundle {
     pub light: Light,
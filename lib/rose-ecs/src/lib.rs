@@ -11,50 +11,167 @@ use hecs::Component;
 
 use input::Input;
 use rose_core::camera::Camera;
+#[cfg(feature = "f64-transforms")]
+use rose_core::dtransform::{DTransform, GlobalDTransform};
 use rose_core::transform::Transform;
+use rose_core::utils::rng::EngineRng;
+use rose_platform::config::LaunchConfig;
 use rose_platform::events::WindowEvent;
 use rose_platform::PhysicalSize;
+use rose_renderer::{splat::SplatMaterial, RendererConfig};
 
-use crate::assets::{Material, MeshAsset};
-use crate::components::{Active, CameraParams, Inactive, Light, PanOrbitCamera};
+use crate::animation::{
+    update_light_animations, update_spline_followers, update_transform_animations,
+    AnimatedMaterial, LightAnimation, SplineFollow, TransformAnimation,
+};
+use crate::assets::{Image, Material, MeshAsset};
+use crate::components::{
+    Active, CameraParams, ClipPlane, Fog, Hovered, Inactive, IrradianceVolume, Light, MorphWeights,
+    PanOrbitCamera, Polyline, ReflectionProbe, Refractive, RendererSettings, Selected, Sprite,
+    Static, Tags, Water,
+};
+use crate::navigation::{update_navigation, Navigation};
+use crate::origin::OriginRebasing;
+use crate::project::Project;
 use crate::scene::Scene;
-use crate::systems::hierarchy::{HierarchicalSystem, Parent};
-use crate::systems::PersistenceSystem;
-use crate::systems::{input::InputSystem, render::RenderSystem};
+use crate::systems::hierarchy::{GlobalTransform, HierarchicalSystem, InactiveInHierarchy, Parent};
+use crate::systems::schedule::{labels, FrameContext, Schedule, Stage, SystemDesc};
+use crate::systems::{
+    input::InputSystem,
+    render::{CustomMaterial, RenderSystem},
+};
+use crate::systems::{EventBus, PersistenceSystem};
 
+pub mod animation;
 pub mod assets;
 pub mod components;
 pub mod load_gltf;
+pub mod navigation;
+pub mod origin;
 pub mod prelude;
+pub mod project;
 pub mod scene;
 pub mod systems;
 
 pub struct CoreSystems {
     pub render: RenderSystem,
     pub input: InputSystem,
+    /// Random number generator of the systems, created from the engine seed.
+    pub rng: EngineRng,
     pub persistence: PersistenceSystem,
+    /// Events shared by the systems, the UI and the application, updated at the end of every
+    /// frame.
+    pub events: EventBus,
+    /// Systems run at the end of every frame, see [`labels`] for the ones added by default.
+    pub schedule: Schedule,
+    /// Project scenes and assets are loaded from, if any.
+    pub project: Option<Project>,
     pub manual_camera_update: bool,
+    /// Shifting of the world back towards the origin, for worlds too large for single precision.
+    pub origin_rebasing: OriginRebasing,
+}
+
+/// Persistence system with the components and assets of the engine registered.
+pub(crate) fn builtin_persistence() -> PersistenceSystem {
+    let mut persistence = PersistenceSystem::new();
+    persistence
+        .register_component::<String>()
+        .register_component::<Parent>()
+        .register_component::<Active>()
+        .register_component::<Tags>()
+        .register_component::<MorphWeights>()
+        .register_component::<Inactive>()
+        .register_component::<Static>()
+        .register_component::<Transform>()
+        .register_component::<CameraParams>()
+        .register_component::<PanOrbitCamera>()
+        .register_component::<Light>()
+        .register_component::<Fog>()
+        .register_component::<ClipPlane>()
+        .register_component::<Water>()
+        .register_component::<Refractive>()
+        .register_component::<Sprite>()
+        .register_component::<Polyline>()
+        .register_component::<ReflectionProbe>()
+        .register_component::<IrradianceVolume>()
+        .register_component::<RendererSettings>()
+        .register_component::<AnimatedMaterial>()
+        .register_component::<TransformAnimation>()
+        .register_component::<SplineFollow>()
+        .register_component::<LightAnimation>()
+        .register_component::<Navigation>()
+        .register_asset_with_placeholder(MeshAsset::cube)
+        .register_asset_with_placeholder(Material::placeholder)
+        .register_asset_with_placeholder(Image::placeholder)
+        .register_custom_material::<SplatMaterial>(|id| {
+            Err(eyre::eyre!(
+                "Splat material {} must be inserted in the asset cache before loading the scene",
+                id
+            ))
+        })
+        // Maintained by the systems every frame
+        .skip_component::<GlobalTransform>()
+        .skip_component::<InactiveInHierarchy>()
+        .skip_component::<Selected>()
+        .skip_component::<Hovered>();
+    #[cfg(feature = "f64-transforms")]
+    persistence
+        .register_component::<DTransform>()
+        .skip_component::<GlobalDTransform>();
+    persistence
 }
 
 impl CoreSystems {
     pub fn new(size: UVec2) -> Result<Self> {
-        let mut persistence = PersistenceSystem::new();
-        persistence
-            .register_component::<String>()
-            .register_component::<Parent>()
-            .register_component::<Active>()
-            .register_component::<Inactive>()
-            .register_component::<Transform>()
-            .register_component::<CameraParams>()
-            .register_component::<PanOrbitCamera>()
-            .register_component::<Light>()
-            .register_asset::<MeshAsset>()
-            .register_asset::<Material>();
+        Self::with_asset_root(size, LaunchConfig::default_asset_root())
+    }
+
+    /// Create the core systems, loading the renderer resources from the `res/` directory of
+    /// `asset_root`, usually [`LaunchConfig::asset_root`].
+    pub fn with_asset_root(size: UVec2, asset_root: impl AsRef<Path>) -> Result<Self> {
+        Self::with_config(size, asset_root, RendererConfig::default())
+    }
+
+    /// Create the core systems with the given renderer configuration, see
+    /// [`Self::with_asset_root`].
+    pub fn with_config(
+        size: UVec2,
+        asset_root: impl AsRef<Path>,
+        renderer_config: RendererConfig,
+    ) -> Result<Self> {
+        let persistence = builtin_persistence();
+        let mut schedule = Schedule::new();
+        schedule
+            .add_system(SystemDesc::builtin(labels::ANIMATION))
+            .add_system(
+                SystemDesc::builtin(labels::ORIGIN)
+                    .in_stage(Stage::PostUpdate)
+                    .before(labels::HIERARCHY),
+            )
+            .add_system(SystemDesc::builtin(labels::HIERARCHY).in_stage(Stage::PostUpdate))
+            .add_system(
+                SystemDesc::builtin(labels::CAMERA)
+                    .in_stage(Stage::PostUpdate)
+                    .after(labels::HIERARCHY),
+            )
+            .add_system(
+                SystemDesc::builtin(labels::NAVIGATION)
+                    .in_stage(Stage::PostUpdate)
+                    .after(labels::HIERARCHY),
+            )
+            .add_system(SystemDesc::builtin(labels::RENDER).in_stage(Stage::Render));
+        let mut render = RenderSystem::with_base_dir(size, asset_root, renderer_config)?;
+        render.register_custom_material::<SplatMaterial>();
         Ok(Self {
-            render: RenderSystem::new(size)?,
+            render,
             input: InputSystem::default(),
+            rng: EngineRng::new(),
             persistence,
+            events: EventBus::new(),
+            schedule,
+            project: None,
             manual_camera_update: false,
+            origin_rebasing: OriginRebasing::default(),
         })
     }
 
@@ -76,18 +193,61 @@ impl CoreSystems {
 
     pub fn begin_frame(&mut self) {}
 
+    /// Add a system to run every frame, see [`Schedule`].
+    pub fn add_system(&mut self, system: SystemDesc) -> &mut Self {
+        self.schedule.add_system(system);
+        self
+    }
+
     pub fn end_frame(&mut self, scene: Option<&mut Scene>, dt: Duration) -> Result<()> {
         if let Some(scene) = scene {
-            scene.with_world(|world, cmd| {
-                HierarchicalSystem.update::<Transform>(world, cmd);
-                if !self.manual_camera_update {
-                    self.render.update_from_active_camera(world);
-                }
-                self.render.on_frame(dt, world)
+            let cache = scene.asset_cache().as_any_cache();
+            let input = &self.input.input;
+            let rng = &mut self.rng;
+            let events = &mut self.events;
+            let render = &mut self.render;
+            let manual_camera_update = self.manual_camera_update;
+            let origin_rebasing = &mut self.origin_rebasing;
+            let schedule = &mut self.schedule;
+            scene.with_world(|world, commands| {
+                let mut ctx = FrameContext {
+                    world,
+                    commands,
+                    cache,
+                    input,
+                    rng,
+                    events,
+                    dt,
+                };
+                schedule.run(&mut ctx, |label, ctx| {
+                    match label {
+                        labels::ANIMATION => {
+                            update_transform_animations(ctx.world, ctx.cache, ctx.dt);
+                            update_spline_followers(ctx.world, ctx.dt);
+                            update_light_animations(ctx.world, ctx.dt);
+                        }
+                        labels::ORIGIN => origin_rebasing.update(ctx.world, ctx.events),
+                        labels::HIERARCHY => {
+                            #[cfg(not(feature = "f64-transforms"))]
+                            HierarchicalSystem.update::<Transform>(ctx.world, ctx.commands);
+                            #[cfg(feature = "f64-transforms")]
+                            HierarchicalSystem.update_double_precision(ctx.world, ctx.commands);
+                            HierarchicalSystem.update_activity(ctx.world, ctx.commands);
+                        }
+                        labels::CAMERA if !manual_camera_update => {
+                            render.update_from_active_camera(ctx.world)
+                        }
+                        labels::NAVIGATION => update_navigation(ctx.world),
+                        labels::RENDER => render.on_frame(ctx.dt, ctx.world, ctx.cache)?,
+                        _ => {}
+                    }
+                    Ok(())
+                })
             })?;
             scene.flush_commands();
         }
         self.input.on_frame();
+        self.events.update();
         Ok(())
     }
 
@@ -95,12 +255,83 @@ impl CoreSystems {
         self.input.on_event(event)
     }
 
+    /// Load a project and apply its renderer settings. Returns its startup scene, if it has one.
+    pub fn load_project(&mut self, path: impl AsRef<Path>) -> Result<Option<Scene>> {
+        let project = Project::load(path)?;
+        self.render
+            .apply_renderer_settings(&project.manifest.settings, &project.asset_root())?;
+        let startup_scene = project.startup_scene();
+        self.project = Some(project);
+        let Some(path) = startup_scene else { return Ok(None); };
+        let scene = self.load_scene(path)?;
+        self.activate_scene(&scene)?;
+        Ok(Some(scene))
+    }
+
+    /// Load a scene. With a project loaded, relative paths are relative to the project root and
+    /// the scene assets are loaded from the project asset root.
     pub fn load_scene(&mut self, path: impl AsRef<Path>) -> Result<Scene> {
-        Scene::load(&mut self.persistence, path)
+        match &self.project {
+            Some(project) => Scene::load_with_asset_root(
+                &mut self.persistence,
+                project.resolve(path),
+                project.asset_root(),
+            ),
+            None => Scene::load(&mut self.persistence, path),
+        }
+    }
+
+    /// Create an empty scene, with its assets in the project asset root or else in `base_dir`.
+    pub fn new_scene(&self, base_dir: impl AsRef<Path>) -> Result<Scene> {
+        match &self.project {
+            Some(project) => Scene::new(project.asset_root()),
+            None => Scene::new(base_dir),
+        }
+    }
+
+    /// Apply the renderer settings saved in the scene, and batch its static meshes, for when it
+    /// becomes the active scene.
+    pub fn activate_scene(&mut self, scene: &Scene) -> Result<()> {
+        let settings = scene.with_world(|world, _| {
+            world
+                .query::<&RendererSettings>()
+                .iter()
+                .next()
+                .map(|(_, settings)| settings.clone())
+        });
+        if let Some(settings) = settings {
+            self.render
+                .apply_renderer_settings(&settings, scene.asset_root())?;
+        }
+        // The batches of the previous scene would hide the entities reusing their IDs
+        self.render.clear_static_batches();
+        scene.with_world(|world, _| self.render.build_static_batches(world))?;
+        Ok(())
+    }
+
+    /// Store the current renderer settings in the scene, so that they are saved along with it.
+    pub fn store_renderer_settings(&mut self, scene: &mut Scene) {
+        let settings = self.render.renderer_settings(scene.asset_root());
+        scene.with_world_mut(|world| {
+            let existing = world
+                .query_mut::<&mut RendererSettings>()
+                .into_iter()
+                .next();
+            if let Some((_, stored)) = existing {
+                *stored = settings;
+            } else {
+                world.spawn((String::from("Renderer settings"), settings));
+            }
+        });
     }
 
     pub fn save_scene(&mut self, scene: &Scene) -> Result<()> {
-        let mut ser = serde_yaml::Serializer::new(BufWriter::new(File::create(scene.path())?));
+        self.save_scene_to(scene, scene.path())
+    }
+
+    /// Save the scene to another file, without changing its path.
+    pub fn save_scene_to(&mut self, scene: &Scene, path: impl AsRef<Path>) -> Result<()> {
+        let mut ser = serde_yaml::Serializer::new(BufWriter::new(File::create(path)?));
         scene.with_world(|world, _| {
             self.persistence
                 .serialize_world(scene.asset_cache().as_any_cache(), &mut ser, world)
@@ -121,6 +352,19 @@ impl NamedComponent for Handle<'static, assets::Material> {
     const NAME: &'static str = "Material";
 }
 
+impl NamedComponent for Handle<'static, assets::Image> {
+    const NAME: &'static str = "Image";
+}
+
+impl NamedComponent for Handle<'static, CustomMaterial<SplatMaterial>> {
+    const NAME: &'static str = "Splat Material";
+}
+
 impl NamedComponent for Transform {
     const NAME: &'static str = "Transform";
 }
+
+#[cfg(feature = "f64-transforms")]
+impl NamedComponent for DTransform {
+    const NAME: &'static str = "Double Precision Transform";
+}
@@ -1,9 +1,10 @@
-use std::{path::PathBuf, sync::Arc};
+use std::{collections::HashMap, path::PathBuf, sync::Arc};
 
 use crossbeam_channel::Sender;
 use eyre::Result;
-use glam::{vec2, Mat4, UVec2, Vec2, Vec3, Vec4};
+use glam::{vec2, Mat4, Quat, UVec2, Vec2, Vec3, Vec4};
 use gltf::{
+    animation::{util::ReadOutputs, Interpolation as GltfInterpolation},
     buffer::Data as BufferData,
     camera::Projection as CamProjection,
     image::{Data as ImageData, Format},
@@ -13,18 +14,22 @@ use gltf::{
     Mesh, Node,
 };
 use image::{
-    buffer::ConvertBuffer, DynamicImage, GrayImage, ImageBuffer, Rgb, RgbImage, RgbaImage,
+    buffer::ConvertBuffer, imageops, DynamicImage, GrayImage, ImageBuffer, Rgb, RgbImage, RgbaImage,
 };
 use rayon::prelude::*;
 use tracing::Instrument;
 
-use rose_core::transform::Transform;
-use rose_renderer::material::Vertex;
+use rose_core::{color::ColorSpace, transform::Transform};
+use rose_renderer::{
+    material::{UvChannel, Vertex, DEFAULT_EMISSION_STRENGTH},
+    morph::MorphTarget,
+};
 use violette::texture::{SampleMode, TextureWrap};
 
+use crate::animation::{AnimationClip, Interpolation, Track, TransformAnimation};
 use crate::assets::Image;
 use crate::{
-    assets::{Material, MeshAsset},
+    assets::{Material, MaterialSamplers, MeshAsset},
     prelude::*,
 };
 
@@ -53,18 +58,33 @@ pub async fn load_gltf_scene(path: impl Into<PathBuf>) -> Result<Scene> {
         let reserved_entities = world.reserve_entities(num_nodes as u32).collect::<Vec<_>>();
         let (tx, rx) = crossbeam_channel::unbounded();
         gltf_scene.nodes().par_bridge().for_each(|node| {
-            gltf_load_node(&buffers, &images, cache, &reserved_entities, &tx, &node);
+            gltf_load_node(
+                &document,
+                &buffers,
+                &images,
+                cache,
+                &reserved_entities,
+                &tx,
+                &node,
+            );
         });
 
         drop(tx);
         for mut cmd in rx {
             cmd.run_on(world);
         }
+
+        for (entity, animation) in load_animations(&document, &buffers, cache, &reserved_entities) {
+            if let Err(err) = world.insert_one(entity, animation) {
+                tracing::warn!("Cannot attach animation: {}", err);
+            }
+        }
     });
     Ok(scene)
 }
 
 fn gltf_load_node(
+    document: &gltf::Document,
     buffers: &[BufferData],
     images: &[ImageData],
     cache: &'static AssetCache,
@@ -90,10 +110,14 @@ fn gltf_load_node(
         }
     }
 
+    if let Some(weights) = node.mesh().and_then(|mesh| morph_weights(node, &mesh)) {
+        entity.add(weights);
+    }
+
     cmd.insert(reserved_entities[node.index()], entity.build());
     let entity = reserved_entities[node.index()];
     if let Some(mesh) = node.mesh() {
-        load_node_mesh(buffers, images, cache, mesh)
+        load_node_mesh(document, buffers, images, cache, mesh)
             .into_par_iter()
             .fold(CommandBuffer::new, |mut cmd, mut builder| {
                 cmd.spawn_child(entity, &mut builder);
@@ -101,13 +125,22 @@ fn gltf_load_node(
             })
             .for_each(|cmd| tx.send(cmd).unwrap());
     }
-    node.children()
-        .par_bridge()
-        .for_each(|node| gltf_load_node(buffers, images, cache, reserved_entities, tx, &node));
+    node.children().par_bridge().for_each(|node| {
+        gltf_load_node(
+            document,
+            buffers,
+            images,
+            cache,
+            reserved_entities,
+            tx,
+            &node,
+        )
+    });
     tx.send(cmd).unwrap();
 }
 
 fn load_node_mesh(
+    document: &gltf::Document,
     buffers: &[BufferData],
     images: &[ImageData],
     cache: &'static AssetCache,
@@ -131,6 +164,8 @@ fn load_node_mesh(
             tracing::info!("\tPositions   : {}", reader.read_positions().is_some());
             tracing::info!("\tNormals     : {}", reader.read_normals().is_some());
             tracing::info!("\tTex coords 0: {}", reader.read_tex_coords(0).is_some());
+            tracing::info!("\tTex coords 1: {}", reader.read_tex_coords(1).is_some());
+            tracing::info!("\tColors 0    : {}", reader.read_colors(0).is_some());
             let data = reader.read_positions().and_then(|pos| {
                 reader.read_normals().and_then(|normals| {
                     reader
@@ -139,22 +174,45 @@ fn load_node_mesh(
                 })
             });
             if let Some((pos, norm, uv)) = data {
-                let vertices = pos
+                let mut vertices = pos
                     .map(Vec3::from)
                     .zip(norm.map(Vec3::from).zip(uv))
                     .map(|(pos, (norm, uv))| Vertex::new(pos, norm, uv))
                     .collect::<Vec<_>>();
+                if let Some(uv2) = reader.read_tex_coords(1) {
+                    for (vertex, uv2) in vertices.iter_mut().zip(coerce_gltf_uv(uv2)) {
+                        vertex.uv2 = uv2;
+                    }
+                }
+                if let Some(colors) = reader.read_colors(0) {
+                    for (vertex, color) in vertices.iter_mut().zip(colors.into_rgba_f32()) {
+                        vertex.color = Vec4::from(color);
+                    }
+                }
                 let indices: Vec<_> = reader
                     .read_indices()
                     .map(|ix| ix.into_u32().collect())
                     .unwrap_or_else(|| (0..vertices.len() as u32).collect());
+                let morph_targets = reader
+                    .read_morph_targets()
+                    .map(|(positions, normals, _)| MorphTarget {
+                        positions: positions.map_or(vec![], |p| p.map(Vec3::from).collect()),
+                        normals: normals.map_or(vec![], |n| n.map(Vec3::from).collect()),
+                    })
+                    .collect::<Vec<_>>();
                 let id = format!("{}.{:03}", mesh_name, prim.index());
                 tracing::info!(
-                    "Primitive mesh of {} vertices and {} indices",
+                    "Primitive mesh of {} vertices, {} indices and {} morph targets",
                     vertices.len(),
-                    indices.len()
+                    indices.len(),
+                    morph_targets.len()
                 );
-                let handle = cache.get_or_insert(&id, MeshAsset { indices, vertices });
+                let mesh = MeshAsset {
+                    indices,
+                    vertices,
+                    morph_targets,
+                };
+                let handle = cache.get_or_insert(&id, mesh);
                 child_entity.add(handle);
             }
             let pbr = prim.material().pbr_metallic_roughness();
@@ -168,6 +226,8 @@ fn load_node_mesh(
                     wrap_v: wrap2wrap(sampler.wrap_t()),
                     sample_min: filter_min2sample(sampler.min_filter()),
                     sample_mag: filter_mag2sample(sampler.mag_filter()),
+                    uv_channel: tex_coord2channel(tex.tex_coord()),
+                    color_space: ColorSpace::Srgb,
                 }
             });
             let rough_metal = pbr.metallic_roughness_texture().map(|tex| {
@@ -186,6 +246,8 @@ fn load_node_mesh(
                     wrap_v: wrap2wrap(sampler.wrap_t()),
                     sample_min: filter_min2sample(sampler.min_filter()),
                     sample_mag: filter_mag2sample(sampler.mag_filter()),
+                    uv_channel: tex_coord2channel(tex.tex_coord()),
+                    color_space: ColorSpace::Linear,
                 }
             });
             let (normal_amount, normal) = prim
@@ -201,6 +263,8 @@ fn load_node_mesh(
                         wrap_v: wrap2wrap(sampler.wrap_t()),
                         sample_min: filter_min2sample(sampler.min_filter()),
                         sample_mag: filter_mag2sample(sampler.mag_filter()),
+                        uv_channel: tex_coord2channel(tex.tex_coord()),
+                        color_space: ColorSpace::Linear,
                     };
                     (tex.scale(), Some(image))
                 })
@@ -215,8 +279,34 @@ fn load_node_mesh(
                     wrap_v: wrap2wrap(sampler.wrap_t()),
                     sample_min: filter_min2sample(sampler.min_filter()),
                     sample_mag: filter_mag2sample(sampler.mag_filter()),
+                    uv_channel: tex_coord2channel(tex.tex_coord()),
+                    color_space: ColorSpace::Srgb,
                 }
             });
+            let extension_factor = |extension: &str, factor: &str| {
+                prim.material()
+                    .extension_value(extension)
+                    .and_then(|value| value.get(factor)?.as_f64())
+                    .unwrap_or(0.) as f32
+            };
+            let extension_texture = |extension: &str, texture: &str| {
+                let info = prim.material().extension_value(extension)?.get(texture)?;
+                let texture = document
+                    .textures()
+                    .nth(info.get("index")?.as_u64()? as usize)?;
+                let tex_coord = info.get("texCoord").and_then(|t| t.as_u64()).unwrap_or(0);
+                Some((texture, tex_coord as u32))
+            };
+            let clearcoat_map = clearcoat_image(
+                images,
+                extension_texture("KHR_materials_clearcoat", "clearcoatTexture"),
+                extension_texture("KHR_materials_clearcoat", "clearcoatRoughnessTexture"),
+            );
+            let anisotropy_map = extension_texture("KHR_materials_anisotropy", "anisotropyTexture")
+                .map(|(texture, tex_coord)| {
+                    let image = image2image(&images[texture.source().index()]);
+                    texture_image(&texture, tex_coord, image, ColorSpace::Linear)
+                });
             let material = Material {
                 transparent: prim.material().alpha_mode() != AlphaMode::Opaque,
                 color,
@@ -227,6 +317,22 @@ fn load_node_mesh(
                 rough_metal_factor: vec2(pbr.roughness_factor(), pbr.metallic_factor()),
                 emission,
                 emission_factor: prim.material().emissive_factor().into(),
+                emission_strength: DEFAULT_EMISSION_STRENGTH
+                    * prim.material().emissive_strength().unwrap_or(1.),
+                double_sided: prim.material().double_sided(),
+                clearcoat: extension_factor("KHR_materials_clearcoat", "clearcoatFactor"),
+                clearcoat_roughness: extension_factor(
+                    "KHR_materials_clearcoat",
+                    "clearcoatRoughnessFactor",
+                ),
+                anisotropy: extension_factor("KHR_materials_anisotropy", "anisotropyStrength"),
+                anisotropy_rotation: extension_factor(
+                    "KHR_materials_anisotropy",
+                    "anisotropyRotation",
+                ),
+                clearcoat_map,
+                anisotropy_map,
+                samplers: MaterialSamplers::default(),
             };
             child_entity
                 .add(cache.get_or_insert(&format!("prim.{:03}.material", prim.index()), material));
@@ -235,6 +341,166 @@ fn load_node_mesh(
         .collect()
 }
 
+/// Initial weights of the morph targets of the mesh of the node, if its primitives have targets.
+fn morph_weights(node: &Node, mesh: &Mesh) -> Option<MorphWeights> {
+    let targets = mesh
+        .primitives()
+        .map(|prim| prim.morph_targets().count())
+        .max()?;
+    if targets == 0 {
+        return None;
+    }
+    let weights = node
+        .weights()
+        .or_else(|| mesh.weights())
+        .map_or_else(|| vec![0.; targets], |weights| weights.to_vec());
+    Some(MorphWeights(weights))
+}
+
+/// Import node animations as one [`AnimationClip`] per animation and animated node, and play the
+/// first animation targeting each node on its entity.
+fn load_animations(
+    document: &gltf::Document,
+    buffers: &[BufferData],
+    cache: &'static AssetCache,
+    reserved_entities: &[Entity],
+) -> Vec<(Entity, TransformAnimation)> {
+    let mut playing = HashMap::new();
+    for animation in document.animations() {
+        let animation_name = animation
+            .name()
+            .map(|s| s.to_string())
+            .unwrap_or_else(|| format!("animation.{:03}", animation.index()));
+        tracing::info!("Got animation {:?}", animation_name);
+        let mut clips = HashMap::<usize, AnimationClip>::new();
+        for channel in animation.channels() {
+            let node = channel.target().node().index();
+            let reader = channel.reader(|buffer| Some(&buffers[buffer.index()]));
+            let Some(inputs) = reader.read_inputs() else { continue; };
+            let Some(outputs) = reader.read_outputs() else { continue; };
+            let times = inputs.collect::<Vec<_>>();
+            let (interpolation, cubic) = match channel.sampler().interpolation() {
+                GltfInterpolation::Step => (Interpolation::Step, false),
+                GltfInterpolation::Linear => (Interpolation::Linear, false),
+                GltfInterpolation::CubicSpline => (Interpolation::Linear, true),
+            };
+            let clip = clips.entry(node).or_default();
+            match outputs {
+                ReadOutputs::Translations(values) => {
+                    let keys = keyframes(&times, values.map(Vec3::from), cubic);
+                    clip.translation = Some(Track::new(interpolation, keys));
+                }
+                ReadOutputs::Rotations(values) => {
+                    let keys = keyframes(&times, values.into_f32().map(Quat::from_array), cubic);
+                    clip.rotation = Some(Track::new(interpolation, keys));
+                }
+                ReadOutputs::Scales(values) => {
+                    let keys = keyframes(&times, values.map(Vec3::from), cubic);
+                    clip.scale = Some(Track::new(interpolation, keys));
+                }
+                ReadOutputs::MorphTargetWeights(values) => {
+                    let values = values.into_f32().collect::<Vec<_>>();
+                    let stride = if cubic { 3 } else { 1 };
+                    let targets = values.len() / (times.len() * stride).max(1);
+                    if targets == 0 {
+                        continue;
+                    }
+                    let keys = keyframes(&times, values.chunks_exact(targets), cubic);
+                    clip.morph_weights = (0..targets)
+                        .map(|target| {
+                            let keys = keys.iter().map(|(time, weights)| (*time, weights[target]));
+                            Track::new(interpolation, keys)
+                        })
+                        .collect();
+                }
+            }
+        }
+        for (node, clip) in clips {
+            let id = format!("{}.node{:03}", animation_name, node);
+            cache.get_or_insert(&id, clip);
+            playing
+                .entry(node)
+                .or_insert_with(|| TransformAnimation::new(id));
+        }
+    }
+    playing
+        .into_iter()
+        .map(|(node, animation)| (reserved_entities[node], animation))
+        .collect()
+}
+
+/// Pair keyframe times with their values. Cubic spline samplers store an in-tangent, the value and
+/// an out-tangent for each keyframe; only the value is kept and interpolated linearly.
+fn keyframes<T>(times: &[f32], values: impl Iterator<Item = T>, cubic: bool) -> Vec<(f32, T)> {
+    let (skip, step) = if cubic { (1, 3) } else { (0, 1) };
+    times
+        .iter()
+        .copied()
+        .zip(values.skip(skip).step_by(step))
+        .collect()
+}
+
+/// Image sampled with the sampler of the texture, from the UV set `tex_coord`.
+fn texture_image(
+    texture: &gltf::Texture,
+    tex_coord: u32,
+    image: DynamicImage,
+    color_space: ColorSpace,
+) -> Image {
+    let sampler = texture.sampler();
+    Image {
+        image: Arc::new(image),
+        wrap_u: wrap2wrap(sampler.wrap_s()),
+        wrap_v: wrap2wrap(sampler.wrap_t()),
+        sample_min: filter_min2sample(sampler.min_filter()),
+        sample_mag: filter_mag2sample(sampler.mag_filter()),
+        uv_channel: tex_coord2channel(tex_coord),
+        color_space,
+    }
+}
+
+/// Pack the clearcoat strength, from the red channel of its texture, and the clearcoat roughness,
+/// from the green channel of its own, into the red and green channels of a single image. The
+/// roughness is resized to the strength texture, whose sampler and UV set are used.
+fn clearcoat_image(
+    images: &[ImageData],
+    strength: Option<(gltf::Texture, u32)>,
+    roughness: Option<(gltf::Texture, u32)>,
+) -> Option<Image> {
+    let load = |(texture, _): &(gltf::Texture, u32)| {
+        image2image(&images[texture.source().index()]).into_rgb32f()
+    };
+    let strength_image = strength.as_ref().map(load);
+    let mut roughness_image = roughness.as_ref().map(load);
+    let (width, height) = strength_image
+        .as_ref()
+        .or(roughness_image.as_ref())?
+        .dimensions();
+    if let Some(image) = &mut roughness_image {
+        if image.dimensions() != (width, height) {
+            *image = imageops::resize(image, width, height, imageops::FilterType::Triangle);
+        }
+    }
+    let data = (0..height)
+        .flat_map(|y| (0..width).map(move |x| (x, y)))
+        .flat_map(|(x, y)| {
+            let strength = strength_image.as_ref().map_or(1., |i| i.get_pixel(x, y)[0]);
+            let roughness = roughness_image
+                .as_ref()
+                .map_or(1., |i| i.get_pixel(x, y)[1]);
+            [strength, roughness, 0.]
+        })
+        .collect::<Vec<_>>();
+    let image = DynamicImage::ImageRgb32F(ImageBuffer::from_raw(width, height, data).unwrap());
+    let (texture, tex_coord) = strength.or(roughness)?;
+    Some(texture_image(
+        &texture,
+        tex_coord,
+        image,
+        ColorSpace::Linear,
+    ))
+}
+
 fn filter_min2sample(filter: Option<MinFilter>) -> (SampleMode, SampleMode) {
     match filter {
         Some(MinFilter::Linear | MinFilter::LinearMipmapLinear) | None => {
@@ -343,6 +609,17 @@ fn image2image(texture: &ImageData) -> DynamicImage {
     image.flipv()
 }
 
+fn tex_coord2channel(tex_coord: u32) -> UvChannel {
+    match tex_coord {
+        0 => UvChannel::Primary,
+        1 => UvChannel::Secondary,
+        other => {
+            tracing::warn!("Unsupported UV set TEXCOORD_{}, using TEXCOORD_0", other);
+            UvChannel::Primary
+        }
+    }
+}
+
 fn coerce_gltf_uv(uv: ReadTexCoords) -> impl Iterator<Item = Vec2> {
     let data: Vec<_> = match uv {
         ReadTexCoords::F32(v) => v.map(Vec2::from).collect(),
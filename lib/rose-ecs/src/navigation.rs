@@ -0,0 +1,170 @@
+//! Navigation over the geometry of the scene, see [`rose_core::navmesh`].
+
+use std::sync::Arc;
+
+use assets_manager::Handle;
+#[cfg(feature = "ui")]
+use egui::{DragValue, Grid, Ui};
+use glam::Vec3;
+use hecs::World;
+use serde::{Deserialize, Serialize};
+
+use rose_core::navmesh::{NavMesh, NavMeshSettings};
+
+#[cfg(feature = "ui")]
+use crate::systems::ComponentUi;
+use crate::{
+    assets::MeshAsset,
+    components::{Inactive, Water},
+    systems::hierarchy::{GlobalTransform, InactiveInHierarchy},
+    NamedComponent,
+};
+
+/// Navmesh of the scene, generated from the meshes of the active entities once the component is
+/// added, and again when its settings change or [`Self::rebuild`] is called.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Navigation {
+    pub settings: NavMeshSettings,
+    /// Draw the boundary of the navmesh over the scene.
+    pub show_outline: bool,
+    /// Navmesh along with the settings it was generated with.
+    #[serde(skip)]
+    navmesh: Option<(NavMeshSettings, Arc<NavMesh>)>,
+    #[serde(skip)]
+    outline: Vec<[Vec3; 2]>,
+}
+
+impl Navigation {
+    pub fn new(settings: NavMeshSettings) -> Self {
+        Self {
+            settings,
+            ..Default::default()
+        }
+    }
+
+    pub fn navmesh(&self) -> Option<&NavMesh> {
+        self.navmesh.as_ref().map(|(_, navmesh)| navmesh.as_ref())
+    }
+
+    /// Generate the navmesh again on the next update, e.g. after the geometry of the scene changed.
+    pub fn rebuild(&mut self) {
+        self.navmesh = None;
+    }
+
+    /// Path between the points, see [`NavMesh::find_path`]. Always `None` until the navmesh is
+    /// generated.
+    pub fn find_path(&self, start: Vec3, end: Vec3) -> Option<Vec<Vec3>> {
+        self.navmesh()?.find_path(start, end)
+    }
+
+    /// Segments of the boundary of the navmesh, slightly above it.
+    pub fn outline(&self) -> &[[Vec3; 2]] {
+        &self.outline
+    }
+
+    fn is_up_to_date(&self) -> bool {
+        matches!(&self.navmesh, Some((settings, _)) if *settings == self.settings)
+    }
+}
+
+#[cfg(feature = "ui")]
+impl ComponentUi for Navigation {
+    fn ui(&mut self, ui: &mut Ui) {
+        Grid::new("navigation").num_columns(2).show(ui, |ui| {
+            let settings = &mut self.settings;
+            let cell_size_label = ui.label("Cell size").id;
+            ui.add(
+                DragValue::new(&mut settings.cell_size)
+                    .clamp_range(0.05..=2.)
+                    .speed(0.01),
+            )
+            .labelled_by(cell_size_label);
+            ui.end_row();
+
+            let height_label = ui.label("Agent height").id;
+            ui.add(
+                DragValue::new(&mut settings.agent_height)
+                    .clamp_range(0.0..=10.)
+                    .speed(0.01),
+            )
+            .labelled_by(height_label);
+            ui.end_row();
+
+            let radius_label = ui.label("Agent radius").id;
+            ui.add(
+                DragValue::new(&mut settings.agent_radius)
+                    .clamp_range(0.0..=5.)
+                    .speed(0.01),
+            )
+            .labelled_by(radius_label);
+            ui.end_row();
+
+            let slope_label = ui.label("Max slope").id;
+            ui.drag_angle(&mut settings.max_slope)
+                .labelled_by(slope_label);
+            ui.end_row();
+
+            let step_label = ui.label("Max step").id;
+            ui.add(
+                DragValue::new(&mut settings.max_step)
+                    .clamp_range(0.0..=2.)
+                    .speed(0.01),
+            )
+            .labelled_by(step_label);
+            ui.end_row();
+
+            let outline_label = ui.label("Show outline").id;
+            ui.checkbox(&mut self.show_outline, "")
+                .labelled_by(outline_label);
+            ui.end_row();
+
+            ui.label("Cells");
+            match self.navmesh() {
+                Some(navmesh) => ui.label(navmesh.cells().len().to_string()),
+                None => ui.weak("Not generated"),
+            };
+            ui.end_row();
+        });
+        if ui.button("Rebuild").clicked() {
+            self.rebuild();
+        }
+    }
+}
+
+impl NamedComponent for Navigation {
+    const NAME: &'static str = "Navigation";
+}
+
+/// Triangles of the meshes of the active entities, in world space. Water surfaces are left out.
+pub fn scene_triangles(world: &World) -> Vec<[Vec3; 3]> {
+    let mut triangles = vec![];
+    for (_, (mesh, transform)) in world
+        .query::<(&Handle<MeshAsset>, &GlobalTransform)>()
+        .without::<&Water>()
+        .without::<&Inactive>()
+        .without::<&InactiveInHierarchy>()
+        .iter()
+    {
+        let mesh = mesh.read();
+        let matrix = transform.0.matrix();
+        triangles.extend(mesh.indices.chunks_exact(3).map(|triangle| {
+            [0, 1, 2].map(|i| matrix.transform_point3(mesh.vertices[triangle[i] as usize].position))
+        }));
+    }
+    triangles
+}
+
+/// Generate the navmeshes of the [`Navigation`] components which are not up to date.
+pub fn update_navigation(world: &World) {
+    for (_, navigation) in world.query::<&mut Navigation>().iter() {
+        if navigation.is_up_to_date() {
+            continue;
+        }
+        let triangles = scene_triangles(world);
+        let navmesh = NavMesh::build(triangles, &navigation.settings);
+        tracing::info!(message = "Generated navmesh", cells = navmesh.cells().len());
+        navigation.outline = navmesh.outline(0.02);
+        navigation.navmesh = Some((navigation.settings, Arc::new(navmesh)));
+    }
+}
@@ -0,0 +1,302 @@
+//! Origin rebasing, for streamed worlds larger than single precision positions can cover.
+//!
+//! When the active camera strays further than [`OriginRebasing::threshold`] from the origin along
+//! any axis, the whole world is shifted back by a multiple of the threshold so that the camera is
+//! close to the origin again. Root transforms, global transforms, cameras, pan-orbit controllers,
+//! the splines of [`SplineFollow`] and the clips of [`TransformAnimation`] on root entities are
+//! shifted, as are double precision root transforms and the height of [`Fog`], and navmeshes are
+//! generated again. An [`OriginShifted`] event is then sent so that
+//! systems keeping positions of their own, such as physics engines, can shift them as well.
+
+use glam::{DVec3, Vec3, Vec4Swizzles};
+use hecs::World;
+
+#[cfg(feature = "f64-transforms")]
+use rose_core::dtransform::{DTransform, GlobalDTransform};
+use rose_core::transform::Transform;
+
+use crate::{
+    animation::{SplineFollow, TransformAnimation},
+    components::{Active, CameraParams, Fog, Inactive, PanOrbitCamera},
+    navigation::Navigation,
+    systems::{
+        events::EventBus,
+        hierarchy::{GlobalTransform, InactiveInHierarchy, Parent},
+    },
+};
+
+/// Sent when the world has been shifted by [`OriginRebasing`].
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct OriginShifted {
+    /// Offset added to every position in the world.
+    pub offset: Vec3,
+    /// Position of the new origin in the coordinates the world started with.
+    pub origin: DVec3,
+}
+
+#[derive(Debug, Clone)]
+pub struct OriginRebasing {
+    /// Disabled by default, as it moves entities away from where they were placed.
+    pub enabled: bool,
+    /// Distance from the origin along any axis past which the world gets shifted, and the size of
+    /// the steps it is shifted by.
+    pub threshold: f32,
+    origin: DVec3,
+}
+
+impl Default for OriginRebasing {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            threshold: 4096.,
+            origin: DVec3::ZERO,
+        }
+    }
+}
+
+impl OriginRebasing {
+    /// Position of the current origin in the coordinates the world started with.
+    pub fn origin(&self) -> DVec3 {
+        self.origin
+    }
+
+    /// Position in the coordinates the world started with, e.g. to find which chunk of the world
+    /// to stream in.
+    pub fn world_position(&self, position: Vec3) -> DVec3 {
+        self.origin + position.as_dvec3()
+    }
+
+    /// Shift the world back towards the origin if the active camera is too far from it.
+    #[tracing::instrument(skip_all)]
+    pub fn update(&mut self, world: &World, events: &mut EventBus) {
+        if !self.enabled {
+            return;
+        }
+        let eye = {
+            let mut q = world
+                .query::<&GlobalTransform>()
+                .with::<&CameraParams>()
+                .with::<&Active>()
+                .without::<&Inactive>()
+                .without::<&InactiveInHierarchy>();
+            let Some((_, camera)) = q.iter().next() else { return; };
+            camera.0.matrix().inverse().w_axis.xyz()
+        };
+        let Some(offset) = rebase_offset(eye, self.threshold) else { return; };
+        tracing::debug!(?offset, "Shifting world origin");
+        shift_world(world, offset);
+        self.origin -= offset.as_dvec3();
+        events.send(OriginShifted {
+            offset,
+            origin: self.origin,
+        });
+    }
+}
+
+/// Offset bringing the eye back within the threshold of the origin, in steps of the threshold.
+fn rebase_offset(eye: Vec3, threshold: f32) -> Option<Vec3> {
+    if threshold <= 0. || eye.abs().max_element() <= threshold {
+        return None;
+    }
+    Some(-(eye / threshold).round() * threshold)
+}
+
+/// Add the offset to the positions of the world. Children are positioned relative to their parent
+/// and move along with it.
+fn shift_world(world: &World, offset: Vec3) {
+    for (_, (transform, camera, pan_orbit, animation)) in world
+        .query::<(
+            &mut Transform,
+            Option<&CameraParams>,
+            Option<&mut PanOrbitCamera>,
+            Option<&mut TransformAnimation>,
+        )>()
+        .without::<&Parent>()
+        .iter()
+    {
+        if let Some(pan_orbit) = pan_orbit {
+            // The controller places the view translation at its focus
+            pan_orbit.focus -= transform.rotation * offset;
+        }
+        let position = transform.position;
+        shift(transform, offset, camera.is_some());
+        if let Some(animation) = animation {
+            animation.offset += transform.position - position;
+        }
+    }
+    // Control points are in the space of the parent, only root splines are in the world
+    for (_, follow) in world
+        .query::<&mut SplineFollow>()
+        .without::<&Parent>()
+        .iter()
+    {
+        follow.translate(offset);
+    }
+    // Globals are only updated from the transforms later in the frame
+    for (_, (global, camera)) in world
+        .query::<(&mut GlobalTransform, Option<&CameraParams>)>()
+        .iter()
+    {
+        shift(&mut global.0, offset, camera.is_some());
+    }
+    #[cfg(feature = "f64-transforms")]
+    shift_double_precision(world, offset.as_dvec3());
+    for (_, fog) in world.query::<&mut Fog>().iter() {
+        fog.height += offset.y;
+    }
+    for (_, navigation) in world.query::<&mut Navigation>().iter() {
+        navigation.rebuild();
+    }
+}
+
+/// [`shift_world`] for the root and global double precision transforms.
+#[cfg(feature = "f64-transforms")]
+fn shift_double_precision(world: &World, offset: DVec3) {
+    let shift_double = |transform: &mut DTransform, is_view: bool| {
+        if is_view {
+            transform.position -= transform.matrix().transform_vector3(offset);
+        } else {
+            transform.position += offset;
+        }
+    };
+    for (_, (transform, camera)) in world
+        .query::<(&mut DTransform, Option<&CameraParams>)>()
+        .without::<&Parent>()
+        .iter()
+    {
+        shift_double(transform, camera.is_some());
+    }
+    for (_, (global, camera)) in world
+        .query::<(&mut GlobalDTransform, Option<&CameraParams>)>()
+        .iter()
+    {
+        shift_double(&mut global.0, camera.is_some());
+    }
+}
+
+/// Shift the transform by the offset. Camera transforms map the world into the view, and move by
+/// the offset brought into the view instead.
+fn shift(transform: &mut Transform, offset: Vec3, is_view: bool) {
+    if is_view {
+        transform.position -= transform.matrix().transform_vector3(offset);
+    } else {
+        transform.position += offset;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use hecs::CommandBuffer;
+    use rose_core::spline::Spline;
+
+    use crate::animation::update_spline_followers;
+    use crate::systems::hierarchy::HierarchicalSystem;
+
+    use super::*;
+
+    #[test]
+    fn rebases_in_steps_of_the_threshold() {
+        assert_eq!(rebase_offset(Vec3::new(900., 0., -1000.), 1000.), None);
+        assert_eq!(
+            rebase_offset(Vec3::new(1600., 20., -2400.), 1000.),
+            Some(Vec3::new(-2000., 0., 2000.))
+        );
+    }
+
+    #[test]
+    fn shifted_camera_keeps_its_view_of_the_world() {
+        let view = Transform::from_matrix(glam::Mat4::look_at_rh(
+            Vec3::new(5000., 10., 0.),
+            Vec3::new(5000., 0., 100.),
+            Vec3::Y,
+        ));
+        let object = Transform::translation(Vec3::new(5000., 0., 100.));
+        let offset = Vec3::new(-5000., 0., 0.);
+        let before = view.matrix().transform_point3(object.position);
+
+        let mut shifted_view = view;
+        let mut shifted_object = object;
+        shift(&mut shifted_view, offset, true);
+        shift(&mut shifted_object, offset, false);
+        let after = shifted_view
+            .matrix()
+            .transform_point3(shifted_object.position);
+        assert!(after.abs_diff_eq(before, 1e-3));
+    }
+
+    #[test]
+    fn shifts_the_world_once_for_a_camera_following_a_spline() {
+        let mut world = World::new();
+        let points = (0..4).map(|i| Vec3::new(5000. + 100. * i as f32, 0., 0.));
+        let mut follow = SplineFollow::new(Spline::catmull_rom(points), 10.);
+        follow.align = false;
+        let camera = world.spawn((
+            Transform::default(),
+            CameraParams::default(),
+            Active,
+            follow,
+        ));
+        let object = world.spawn((
+            Transform::translation(Vec3::new(5000., 0., 10.)),
+            TransformAnimation::default(),
+        ));
+        let child = world.spawn((Transform::translation(Vec3::X), Parent(object)));
+        let mut rebasing = OriginRebasing {
+            enabled: true,
+            threshold: 1000.,
+            ..Default::default()
+        };
+        let mut events = EventBus::new();
+        let mut shifted = events.reader::<OriginShifted>();
+
+        // The camera has no global transform on the first frame, and is shifted on the second
+        for _ in 0..3 {
+            update_spline_followers(&world, Duration::from_millis(100));
+            rebasing.update(&world, &mut events);
+            let mut cmd = CommandBuffer::new();
+            HierarchicalSystem.update::<Transform>(&world, &mut cmd);
+            cmd.run_on(&mut world);
+        }
+
+        let events = events.read(&mut shifted).copied().collect::<Vec<_>>();
+        assert_eq!(
+            events,
+            [OriginShifted {
+                offset: Vec3::new(-5000., 0., 0.),
+                origin: DVec3::new(5000., 0., 0.),
+            }]
+        );
+        let eye = world.get::<&Transform>(camera).unwrap().matrix().inverse();
+        assert!(eye.w_axis.xyz().abs_diff_eq(Vec3::new(3., 0., 0.), 0.1));
+        let object = world.query_one_mut::<(&Transform, &TransformAnimation)>(object);
+        let (transform, animation) = object.unwrap();
+        assert_eq!(transform.position, Vec3::new(0., 0., 10.));
+        assert_eq!(animation.offset, Vec3::new(-5000., 0., 0.));
+        assert_eq!(world.get::<&Transform>(child).unwrap().position, Vec3::X);
+    }
+
+    #[test]
+    fn shifts_the_height_of_fog() {
+        let mut world = World::new();
+        let fog = world.spawn((Fog {
+            height: 10.,
+            ..Default::default()
+        },));
+        shift_world(&world, Vec3::new(-5000., -2000., 0.));
+        assert_eq!(world.get::<&Fog>(fog).unwrap().height, -1990.);
+    }
+
+    #[cfg(feature = "f64-transforms")]
+    #[test]
+    fn shifts_double_precision_roots() {
+        let mut world = World::new();
+        let root = world.spawn((DTransform::translation(DVec3::new(5000., 0., 1e-3)),));
+        let child = world.spawn((DTransform::translation(DVec3::X), Parent(root)));
+        shift_world(&world, Vec3::new(-5000., 0., 0.));
+        let position = world.get::<&DTransform>(root).unwrap().position;
+        assert!(position.abs_diff_eq(DVec3::new(0., 0., 1e-3), 1e-9));
+        assert_eq!(world.get::<&DTransform>(child).unwrap().position, DVec3::X);
+    }
+}
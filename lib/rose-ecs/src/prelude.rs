@@ -1,17 +1,25 @@
 #[cfg(feature = "ui")]
 pub use crate::systems::ui::*;
 pub use crate::{
+    animation::*,
     assets::{self, *},
     components::{self, *},
+    navigation::*,
+    origin::*,
+    project::{Project, ProjectManifest},
     scene::Scene,
     systems::{
         camera::*,
+        changes::*,
+        events::*,
         hierarchy::{MakeChild, MakeChildren, *},
         input::*,
+        lookup::*,
         persistence::{SerializableComponent, *},
         render::*,
+        schedule::*,
     },
-    CoreSystems,
+    CoreSystems, NamedComponent,
 };
 
 pub use assets_manager::{
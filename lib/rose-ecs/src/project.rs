@@ -0,0 +1,132 @@
+use std::path::{Path, PathBuf};
+
+use eyre::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::components::RendererSettings;
+
+/// Contents of a project manifest.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ProjectManifest {
+    pub name: String,
+    /// Directory assets are loaded from, relative to the project root. Asset ids in scenes are
+    /// relative to it.
+    pub asset_root: PathBuf,
+    /// Scene opened when loading the project, relative to the project root.
+    pub startup_scene: Option<PathBuf>,
+    /// Renderer settings applied when loading the project, before the ones of the scenes.
+    pub settings: RendererSettings,
+}
+
+impl Default for ProjectManifest {
+    fn default() -> Self {
+        Self {
+            name: String::from("Untitled"),
+            asset_root: PathBuf::from("."),
+            startup_scene: None,
+            settings: RendererSettings::default(),
+        }
+    }
+}
+
+/// Project loaded from a `project.toml` manifest, which scene and asset paths are resolved against.
+#[derive(Debug, Clone)]
+pub struct Project {
+    root: PathBuf,
+    pub manifest: ProjectManifest,
+}
+
+impl Project {
+    pub const MANIFEST_FILE: &'static str = "project.toml";
+
+    /// Load the project from its manifest, or from the directory containing it.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let manifest_path = if path.is_dir() {
+            path.join(Self::MANIFEST_FILE)
+        } else {
+            path.to_path_buf()
+        };
+        let contents = std::fs::read_to_string(&manifest_path)
+            .with_context(|| format!("Cannot read {}", manifest_path.display()))?;
+        let manifest = toml::from_str(&contents)
+            .with_context(|| format!("Cannot parse {}", manifest_path.display()))?;
+        let root = manifest_path
+            .parent()
+            .unwrap_or(Path::new("."))
+            .to_path_buf();
+        Ok(Self { root, manifest })
+    }
+
+    pub fn save(&self) -> Result<()> {
+        std::fs::write(
+            self.manifest_path(),
+            toml::to_string_pretty(&self.manifest)?,
+        )?;
+        Ok(())
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn manifest_path(&self) -> PathBuf {
+        self.root.join(Self::MANIFEST_FILE)
+    }
+
+    pub fn asset_root(&self) -> PathBuf {
+        self.resolve(&self.manifest.asset_root)
+    }
+
+    pub fn startup_scene(&self) -> Option<PathBuf> {
+        self.manifest
+            .startup_scene
+            .as_ref()
+            .map(|path| self.resolve(path))
+    }
+
+    /// Resolve a path relative to the project root; absolute paths are returned as-is.
+    pub fn resolve(&self, path: impl AsRef<Path>) -> PathBuf {
+        self.root.join(path)
+    }
+
+    /// Path relative to the project root, when it is inside of the project.
+    pub fn relative(&self, path: impl AsRef<Path>) -> Option<PathBuf> {
+        path.as_ref()
+            .strip_prefix(&self.root)
+            .ok()
+            .map(Path::to_path_buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_paths_against_root() {
+        let manifest: ProjectManifest = toml::from_str(
+            r#"
+            name = "Test"
+            asset_root = "assets"
+            startup_scene = "scenes/main.scene"
+            "#,
+        )
+        .unwrap();
+        let project = Project {
+            root: PathBuf::from("/projects/test"),
+            manifest,
+        };
+        assert_eq!(project.asset_root(), Path::new("/projects/test/assets"));
+        assert_eq!(
+            project.startup_scene().as_deref(),
+            Some(Path::new("/projects/test/scenes/main.scene"))
+        );
+        assert_eq!(
+            project.relative("/projects/test/scenes/other.scene"),
+            Some(PathBuf::from("scenes/other.scene"))
+        );
+        assert_eq!(project.relative("/elsewhere/other.scene"), None);
+    }
+}
@@ -4,6 +4,10 @@ use std::io::{BufReader, BufWriter};
 use std::{
     fmt::{self, Formatter},
     path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        RwLock, RwLockReadGuard,
+    },
 };
 
 use assets_manager::source::{FileSystem, Source};
@@ -11,9 +15,10 @@ use assets_manager::AssetCache;
 use crossbeam_channel::{Receiver, Sender, TryRecvError};
 use egui::Ui;
 use eyre::Result;
-use hecs::{CommandBuffer, EntityBuilder, World};
+use hecs::{CommandBuffer, Entity, EntityBuilder, World};
 
 use crate::prelude::{MakeChild, Parent};
+use crate::systems::lookup::EntityIndex;
 use crate::systems::persistence::PersistenceSystem;
 use crate::systems::ComponentUi;
 use crate::NamedComponent;
@@ -21,7 +26,11 @@ use crate::NamedComponent;
 pub struct Scene<FS: 'static = FileSystem> {
     assets: &'static AssetCache<FS>,
     world: World,
+    index: RwLock<EntityIndex>,
+    /// Set when components may have been changed in place through [`Scene::with_world`].
+    index_stale: AtomicBool,
     scene_path: PathBuf,
+    asset_root: PathBuf,
     command_queue: (Sender<CommandBuffer>, Receiver<CommandBuffer>),
 }
 
@@ -48,28 +57,45 @@ impl Scene {
         Ok(Self {
             assets,
             scene_path: base_dir.join("unknown.scene"),
+            asset_root: base_dir.to_path_buf(),
             world: World::new(),
+            index: RwLock::default(),
+            index_stale: AtomicBool::new(false),
             command_queue: crossbeam_channel::bounded(16),
         })
     }
 
+    /// Load the scene, with its assets relative to the directory of the scene file.
     pub fn load(persistence: &mut PersistenceSystem, scene_path: impl AsRef<Path>) -> Result<Self> {
         let scene_path = scene_path.as_ref();
-        let base_path = scene_path.parent().unwrap();
-        let assets = Box::leak(Box::new(AssetCache::new(base_path)?));
+        Self::load_with_asset_root(persistence, scene_path, scene_path.parent().unwrap())
+    }
+
+    /// Load the scene, with its assets relative to `asset_root`.
+    pub fn load_with_asset_root(
+        persistence: &mut PersistenceSystem,
+        scene_path: impl AsRef<Path>,
+        asset_root: impl AsRef<Path>,
+    ) -> Result<Self> {
+        let scene_path = scene_path.as_ref();
+        let asset_root = asset_root.as_ref();
+        let assets = Box::leak(Box::new(AssetCache::new(asset_root)?));
         assets.enhance_hot_reloading();
         let de = serde_yaml::Deserializer::from_reader(BufReader::new(File::open(scene_path)?));
         let world = persistence.deserialize_world(assets.as_any_cache(), de)?;
         Ok(Self {
             assets,
             scene_path: scene_path.into(),
+            asset_root: asset_root.into(),
+            index: RwLock::new(EntityIndex::new(&world)),
+            index_stale: AtomicBool::new(false),
             world,
             command_queue: crossbeam_channel::bounded(16),
         })
     }
 
     pub fn reload(&self, persistence: &mut PersistenceSystem) -> Result<Self> {
-        Self::load(persistence, self.scene_path.as_path())
+        Self::load_with_asset_root(persistence, &self.scene_path, &self.asset_root)
     }
 
     pub fn add_nested(&mut self, mut nested: Scene) -> Result<()> {
@@ -123,10 +149,17 @@ impl<FS> Scene<FS> {
         self.scene_path.as_path()
     }
 
+    /// Directory the assets of the scene are loaded from.
+    pub fn asset_root(&self) -> &Path {
+        self.asset_root.as_path()
+    }
+
     #[inline]
     pub fn with_world<R>(&self, runner: impl FnOnce(&World, &mut CommandBuffer) -> R) -> R {
         let mut command_buffer = CommandBuffer::new();
         let ret = runner(&self.world, &mut command_buffer);
+        // Names and tags can be changed in place through the shared world
+        self.index_stale.store(true, Ordering::Release);
         match self.command_queue.0.try_send(command_buffer) {
             Ok(_) => {}
             Err(err) => {
@@ -138,14 +171,33 @@ impl<FS> Scene<FS> {
 
     #[inline]
     pub fn with_world_mut<R>(&mut self, runner: impl FnOnce(&mut World) -> R) -> R {
-        runner(&mut self.world)
+        let ret = runner(&mut self.world);
+        self.update_index();
+        ret
+    }
+
+    /// First entity with the given name. Entities spawned from command buffers can only be found
+    /// once the commands have been flushed.
+    pub fn find_by_name(&self, name: &str) -> Option<Entity> {
+        self.index().find_by_name(&self.world, name)
+    }
+
+    /// Entity at the given path of names from a root entity, e.g. `"Root/Arm/Hand"`.
+    pub fn find_by_path(&self, path: &str) -> Option<Entity> {
+        self.index().find_by_path(&self.world, path)
+    }
+
+    pub fn query_tagged(&self, tag: &str) -> Vec<Entity> {
+        self.index().tagged(&self.world, tag)
     }
 
     pub fn flush_commands(&mut self) {
+        let mut changed = false;
         loop {
             match self.command_queue.1.try_recv() {
                 Ok(mut cmd) => {
                     cmd.run_on(&mut self.world);
+                    changed = true;
                 }
                 Err(TryRecvError::Empty) => {
                     break;
@@ -156,6 +208,22 @@ impl<FS> Scene<FS> {
                 }
             }
         }
+        if changed {
+            self.update_index();
+        }
+    }
+
+    fn update_index(&mut self) {
+        self.index.get_mut().unwrap().update(&self.world);
+        *self.index_stale.get_mut() = false;
+    }
+
+    /// Entity index, updated first if the world was accessed through [`Self::with_world`] since.
+    fn index(&self) -> RwLockReadGuard<EntityIndex> {
+        if self.index_stale.swap(false, Ordering::AcqRel) {
+            self.index.write().unwrap().update(&self.world);
+        }
+        self.index.read().unwrap()
     }
 }
 
@@ -195,3 +263,23 @@ impl<FS: Send + Sync> ComponentUi for Scene<FS> {
             });
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lookups_see_renames_through_with_world() {
+        let dir = std::env::temp_dir().join("rose-scene-index-test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut scene = Scene::new(dir).unwrap();
+        let entity = scene.with_world_mut(|world| world.spawn(("Before".to_string(),)));
+        assert_eq!(scene.find_by_name("Before"), Some(entity));
+
+        scene.with_world(|world, _| {
+            *world.get::<&mut String>(entity).unwrap() = "After".to_string();
+        });
+        assert_eq!(scene.find_by_name("After"), Some(entity));
+        assert_eq!(scene.find_by_name("Before"), None);
+    }
+}
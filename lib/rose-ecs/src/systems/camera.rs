@@ -9,6 +9,9 @@ use rose_platform::{events::MouseButton, LogicalSize};
 
 use crate::components::PanOrbitCamera;
 
+/// Relative change of the orbit radius per unit of scroll.
+const SCROLL_ZOOM: f32 = 0.2;
+
 #[derive(Debug)]
 pub struct PanOrbitSystem {
     pub mouse_sensitivity: f32,
@@ -39,6 +42,7 @@ impl PanOrbitSystem {
             input.mouse.state.is_pressed(&MouseButton::Left),
             input.mouse.state.is_pressed(&MouseButton::Right),
         );
+        let (delta, scroll, buttons) = self.touch_input(input).unwrap_or((delta, scroll, buttons));
         for (_, (transform, pan_orbit)) in world
             .query::<(&mut Transform, &mut PanOrbitCamera)>()
             .iter()
@@ -71,9 +75,36 @@ impl PanOrbitSystem {
             input.mouse.state.is_pressed(&MouseButton::Left),
             input.mouse.state.is_pressed(&MouseButton::Right),
         );
+        let (delta, scroll, buttons) = self.touch_input(input).unwrap_or((delta, scroll, buttons));
         self.frame_one(delta, scroll, buttons, controller, cam_transform);
     }
 
+    /// Scroll amount of [`Self::frame_one`] scaling the radius like a pinch of the given zoom.
+    pub fn pinch_scroll(zoom: f32) -> f32 {
+        (1. - zoom.recip()) / SCROLL_ZOOM
+    }
+
+    /// Input of a frame from the touch screen, orbiting with one finger and panning and zooming
+    /// with two, or `None` when nothing touches the screen.
+    fn touch_input(&self, input: &Input) -> Option<(Vec2, f32, (bool, bool))> {
+        let aspect_ratio = self.logical_window_size.x / self.logical_window_size.y;
+        let scale = |delta: Vec2| {
+            vec2(aspect_ratio, 1.) * delta / self.logical_window_size * self.mouse_sensitivity
+        };
+        match input.touch.count() {
+            0 => None,
+            1 => {
+                let (_, touch) = input.touch.touches().next()?;
+                Some((scale(touch.delta()), 0., (true, false)))
+            }
+            _ => {
+                let pinch = input.touch.pinch()?;
+                let scroll = Self::pinch_scroll(pinch.zoom);
+                Some((scale(pinch.pan), scroll, (false, true)))
+            }
+        }
+    }
+
     pub fn frame_one(
         &self,
         delta: Vec2,
@@ -96,7 +127,7 @@ impl PanOrbitSystem {
         }
         controller.target_rotation.y = controller.target_rotation.y.clamp(-FRAC_PI_2, FRAC_PI_2);
 
-        controller.radius -= 0.2 * controller.radius * scroll;
+        controller.radius -= SCROLL_ZOOM * controller.radius * scroll;
         controller.radius = f32::max(0.05, controller.radius);
         // cam_transform.rotation = Quat::from_euler(
         //     EulerRot::XYZ,
@@ -0,0 +1,173 @@
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+};
+
+use hecs::{Component, Entity, Query, QueryBorrow, QueryItem, World};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Modified,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Change {
+    pub entity: Entity,
+    pub kind: ChangeKind,
+}
+
+/// Hashes of the tracked values of the entities of one archetype.
+#[derive(Debug, Default)]
+struct ArchetypeHashes {
+    /// Hash of the entities and of their values, in the order of the archetype.
+    combined: u64,
+    values: Vec<(Entity, u64)>,
+}
+
+/// Detects entities being added, removed or modified in a query between two updates. Only hashes
+/// of the values are kept, grouped by archetype: archetypes whose entities and values hash the same
+/// as in the previous update are skipped as a whole, and only the entities of the others are
+/// compared. Systems keep one tracker per kind of data they react to, and update it once per frame.
+#[derive(Debug, Default)]
+pub struct ChangeTracker {
+    /// Indexed like [`World::archetypes`], which only ever grows.
+    archetypes: Vec<ArchetypeHashes>,
+    changes: Vec<Change>,
+}
+
+impl ChangeTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Compare the entities matched by `query` against the ones of the previous update, with the
+    /// values hashed by `hash`. The changes of the previous update are discarded.
+    pub fn update<'q, Q: Query>(
+        &mut self,
+        world: &World,
+        query: &'q mut QueryBorrow<Q>,
+        mut hash: impl FnMut(QueryItem<'q, Q>, &mut DefaultHasher),
+    ) -> &[Change] {
+        self.changes.clear();
+        let mut before = HashMap::new();
+        let mut after = vec![];
+        // Queries go through the archetypes in the same order as `World::archetypes`
+        let mut items = query.iter();
+        for (ix, archetype) in world.archetypes().enumerate() {
+            if ix == self.archetypes.len() {
+                self.archetypes.push(ArchetypeHashes::default());
+            }
+            let mut combined = DefaultHasher::new();
+            let mut values = vec![];
+            if archetype.access::<Q>().is_some() {
+                for &id in archetype.ids() {
+                    let Some((entity, item)) = items.next() else { break; };
+                    debug_assert_eq!(entity.id(), id);
+                    let mut hasher = DefaultHasher::new();
+                    hash(item, &mut hasher);
+                    let value = hasher.finish();
+                    (entity, value).hash(&mut combined);
+                    values.push((entity, value));
+                }
+            }
+            let combined = combined.finish();
+            let previous = &mut self.archetypes[ix];
+            if previous.combined == combined && previous.values.len() == values.len() {
+                continue;
+            }
+            before.extend(previous.values.drain(..));
+            after.extend(values.iter().copied());
+            *previous = ArchetypeHashes { combined, values };
+        }
+
+        // Entities moving to another archetype show up on both sides
+        for (entity, value) in after {
+            let kind = match before.remove(&entity) {
+                None => ChangeKind::Added,
+                Some(previous) if previous != value => ChangeKind::Modified,
+                Some(_) => continue,
+            };
+            self.changes.push(Change { entity, kind });
+        }
+        self.changes.extend(before.into_keys().map(|entity| Change {
+            entity,
+            kind: ChangeKind::Removed,
+        }));
+        &self.changes
+    }
+
+    /// Track every `C` component in the world.
+    pub fn track<C: Component + Hash>(&mut self, world: &World) -> &[Change] {
+        let mut query = world.query::<&C>();
+        self.update(world, &mut query, |value, hasher| value.hash(hasher))
+    }
+
+    /// Changes found by the last update.
+    pub fn changes(&self) -> &[Change] {
+        &self.changes
+    }
+
+    pub fn has_changes(&self) -> bool {
+        !self.changes.is_empty()
+    }
+
+    pub fn added(&self) -> impl '_ + Iterator<Item = Entity> {
+        self.of_kind(ChangeKind::Added)
+    }
+
+    pub fn removed(&self) -> impl '_ + Iterator<Item = Entity> {
+        self.of_kind(ChangeKind::Removed)
+    }
+
+    pub fn modified(&self) -> impl '_ + Iterator<Item = Entity> {
+        self.of_kind(ChangeKind::Modified)
+    }
+
+    fn of_kind(&self, kind: ChangeKind) -> impl '_ + Iterator<Item = Entity> {
+        self.changes
+            .iter()
+            .filter(move |change| change.kind == kind)
+            .map(|change| change.entity)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_added_modified_and_removed() {
+        let mut world = World::new();
+        let a = world.spawn((1u32,));
+        let b = world.spawn((2u32,));
+        let mut tracker = ChangeTracker::new();
+        assert_eq!(tracker.track::<u32>(&world).len(), 2);
+        assert!(tracker.track::<u32>(&world).is_empty());
+
+        *world.get::<&mut u32>(a).unwrap() = 3;
+        world.despawn(b).unwrap();
+        let c = world.spawn((4u32,));
+        tracker.track::<u32>(&world);
+        assert_eq!(tracker.modified().collect::<Vec<_>>(), vec![a]);
+        assert_eq!(tracker.removed().collect::<Vec<_>>(), vec![b]);
+        assert_eq!(tracker.added().collect::<Vec<_>>(), vec![c]);
+    }
+
+    #[test]
+    fn entities_changing_archetype_are_not_readded() {
+        let mut world = World::new();
+        let a = world.spawn((1u32,));
+        let mut tracker = ChangeTracker::new();
+        tracker.track::<u32>(&world);
+
+        world.insert_one(a, "moved").unwrap();
+        assert!(tracker.track::<u32>(&world).is_empty());
+        world.insert_one(a, 2u32).unwrap();
+        world.remove_one::<&str>(a).unwrap();
+        tracker.track::<u32>(&world);
+        assert_eq!(tracker.modified().collect::<Vec<_>>(), vec![a]);
+        assert_eq!(tracker.changes().len(), 1);
+    }
+}
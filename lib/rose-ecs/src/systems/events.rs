@@ -0,0 +1,248 @@
+use std::{
+    any::{Any, TypeId},
+    collections::HashMap,
+    fmt,
+    marker::PhantomData,
+};
+
+/// Queue of events of one type, double buffered: events sent during a frame stay readable until
+/// the end of the next frame, so systems running before the sender still see them once.
+#[derive(Debug)]
+pub struct Events<T> {
+    previous: Vec<T>,
+    current: Vec<T>,
+    /// Id of the first event of `previous`; ids count every event ever sent.
+    previous_start: usize,
+}
+
+impl<T> Default for Events<T> {
+    fn default() -> Self {
+        Self {
+            previous: vec![],
+            current: vec![],
+            previous_start: 0,
+        }
+    }
+}
+
+impl<T> Events<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn send(&mut self, event: T) {
+        self.current.push(event);
+    }
+
+    pub fn send_batch(&mut self, events: impl IntoIterator<Item = T>) {
+        self.current.extend(events);
+    }
+
+    /// Reader of the events sent from now on.
+    pub fn reader(&self) -> EventReader<T> {
+        EventReader {
+            next: self.end(),
+            marker: PhantomData,
+        }
+    }
+
+    /// Events not read yet by the reader, in the order they were sent. Events dropped before the
+    /// reader got to them are skipped.
+    pub fn read<'a>(&'a self, reader: &mut EventReader<T>) -> impl 'a + Iterator<Item = &'a T> {
+        let start = reader.next.max(self.previous_start) - self.previous_start;
+        reader.next = self.end();
+        self.previous.iter().chain(self.current.iter()).skip(start)
+    }
+
+    /// Every event currently held, oldest first.
+    pub fn iter(&self) -> impl '_ + Iterator<Item = &T> {
+        self.previous.iter().chain(self.current.iter())
+    }
+
+    pub fn len(&self) -> usize {
+        self.previous.len() + self.current.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Drop the events of the previous frame, and start buffering new ones. Called once per
+    /// frame.
+    pub fn update(&mut self) {
+        self.previous_start += self.previous.len();
+        self.previous.clear();
+        std::mem::swap(&mut self.previous, &mut self.current);
+    }
+
+    pub fn clear(&mut self) {
+        self.previous_start = self.end();
+        self.previous.clear();
+        self.current.clear();
+    }
+
+    fn end(&self) -> usize {
+        self.previous_start + self.len()
+    }
+}
+
+/// Cursor of a consumer of [`Events`], so that each consumer sees every event once. Readers
+/// created with [`Default`] also see the events already sent.
+pub struct EventReader<T> {
+    next: usize,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Default for EventReader<T> {
+    fn default() -> Self {
+        Self {
+            next: 0,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T> Clone for EventReader<T> {
+    fn clone(&self) -> Self {
+        Self {
+            next: self.next,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T> fmt::Debug for EventReader<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EventReader")
+            .field("next", &self.next)
+            .finish()
+    }
+}
+
+trait AnyEvents: Any {
+    fn update(&mut self);
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<T: 'static> AnyEvents for Events<T> {
+    fn update(&mut self) {
+        Events::update(self)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// Event queues of every type, shared by the systems, the UI and the application to communicate
+/// without their own channels. The queues are updated at the end of every frame by
+/// [`crate::CoreSystems`].
+#[derive(Default)]
+pub struct EventBus {
+    queues: HashMap<TypeId, Box<dyn AnyEvents>>,
+}
+
+impl fmt::Debug for EventBus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EventBus")
+            .field("queues", &self.queues.len())
+            .finish()
+    }
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn send<T: 'static>(&mut self, event: T) {
+        self.events_mut::<T>().send(event);
+    }
+
+    /// Queue of the events of type `T`, if any was sent or read before.
+    pub fn events<T: 'static>(&self) -> Option<&Events<T>> {
+        self.queues
+            .get(&TypeId::of::<T>())
+            .and_then(|queue| queue.as_any().downcast_ref())
+    }
+
+    pub fn events_mut<T: 'static>(&mut self) -> &mut Events<T> {
+        self.queues
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Box::new(Events::<T>::new()))
+            .as_any_mut()
+            .downcast_mut()
+            .unwrap()
+    }
+
+    /// Reader of the events of type `T` sent from now on.
+    pub fn reader<T: 'static>(&self) -> EventReader<T> {
+        self.events::<T>().map(Events::reader).unwrap_or_default()
+    }
+
+    /// Events of type `T` not read yet by the reader, see [`Events::read`].
+    pub fn read<'a, T: 'static>(
+        &'a self,
+        reader: &mut EventReader<T>,
+    ) -> impl 'a + Iterator<Item = &'a T> {
+        self.events::<T>()
+            .map(|events| events.read(reader))
+            .into_iter()
+            .flatten()
+    }
+
+    /// Move every queue to the next frame, see [`Events::update`].
+    pub fn update(&mut self) {
+        for queue in self.queues.values_mut() {
+            queue.update();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn events_are_read_once_and_dropped_after_two_updates() {
+        let mut events = Events::new();
+        let mut early = EventReader::default();
+        events.send(1);
+        events.send(2);
+        let mut late = events.reader();
+        assert_eq!(events.read(&mut early).copied().collect::<Vec<_>>(), [1, 2]);
+        assert_eq!(events.read(&mut early).count(), 0);
+
+        events.update();
+        events.send(3);
+        assert_eq!(events.read(&mut early).copied().collect::<Vec<_>>(), [3]);
+        assert_eq!(events.read(&mut late).copied().collect::<Vec<_>>(), [3]);
+
+        events.update();
+        events.update();
+        events.send(4);
+        let mut stale = EventReader::default();
+        assert_eq!(events.read(&mut stale).copied().collect::<Vec<_>>(), [4]);
+        assert_eq!(events.len(), 1);
+    }
+
+    #[test]
+    fn bus_keeps_one_queue_per_type() {
+        let mut bus = EventBus::new();
+        let mut numbers = bus.reader::<u32>();
+        let mut names = bus.reader::<&str>();
+        assert_eq!(bus.read(&mut numbers).count(), 0);
+        bus.send(5u32);
+        bus.send("spawn");
+        assert_eq!(bus.read(&mut numbers).copied().collect::<Vec<_>>(), [5]);
+        assert_eq!(bus.read(&mut names).copied().collect::<Vec<_>>(), ["spawn"]);
+        bus.update();
+        bus.update();
+        assert!(bus.events::<u32>().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,84 @@
+//! Copy of the transforms, lights and cameras of a world ticked by a
+//! [`rose_platform::Simulation`], handed over to the render thread through a
+//! [`rose_core::utils::snapshot`] and applied onto the rendered world.
+//!
+//! The ticked world mirrors the entities of the rendered one, spawning them with
+//! [`World::spawn_at`] so that they keep the same IDs.
+
+use hecs::{Entity, World};
+
+use rose_core::transform::Transform;
+
+use crate::components::{CameraParams, Light};
+
+#[derive(Debug, Clone, Default)]
+pub struct RenderWorld {
+    pub transforms: Vec<(Entity, Transform)>,
+    pub lights: Vec<(Entity, Light)>,
+    pub cameras: Vec<(Entity, CameraParams)>,
+}
+
+impl RenderWorld {
+    /// Replace the contents with the components of the world, reusing the allocations.
+    pub fn extract(&mut self, world: &World) {
+        self.transforms.clear();
+        self.transforms
+            .extend(world.query::<&Transform>().iter().map(|(e, t)| (e, *t)));
+        self.lights.clear();
+        self.lights
+            .extend(world.query::<&Light>().iter().map(|(e, l)| (e, l.clone())));
+        self.cameras.clear();
+        self.cameras.extend(
+            world
+                .query::<&CameraParams>()
+                .iter()
+                .map(|(e, c)| (e, c.clone())),
+        );
+    }
+
+    /// Write the components onto the entities of the world with the same IDs. Entities missing
+    /// from the world, or without the component, are skipped.
+    pub fn apply(&self, world: &mut World) {
+        for (entity, transform) in &self.transforms {
+            if let Ok(mut current) = world.get::<&mut Transform>(*entity) {
+                *current = *transform;
+            }
+        }
+        for (entity, light) in &self.lights {
+            if let Ok(mut current) = world.get::<&mut Light>(*entity) {
+                *current = light.clone();
+            }
+        }
+        for (entity, camera) in &self.cameras {
+            if let Ok(mut current) = world.get::<&mut CameraParams>(*entity) {
+                *current = camera.clone();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec3;
+
+    use super::*;
+
+    #[test]
+    fn applies_onto_entities_with_the_same_ids() {
+        let mut rendered = World::new();
+        let moved = rendered.spawn((Transform::default(),));
+        let untouched = rendered.spawn((Transform::translation(Vec3::Y),));
+
+        let mut ticked = World::new();
+        ticked.spawn_at(moved, (Transform::translation(Vec3::X),));
+        let mut render_world = RenderWorld::default();
+        render_world.extract(&ticked);
+        render_world.apply(&mut rendered);
+
+        assert_eq!(rendered.get::<&Transform>(moved).unwrap().position, Vec3::X);
+        assert_eq!(
+            rendered.get::<&Transform>(untouched).unwrap().position,
+            Vec3::Y
+        );
+    }
+}
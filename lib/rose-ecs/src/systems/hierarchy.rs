@@ -1,11 +1,19 @@
 use std::collections::HashMap;
 
+#[cfg(feature = "f64-transforms")]
+use glam::DVec3;
 use hecs::{CommandBuffer, Component, Entity, EntityBuilder, World};
 use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
 
+#[cfg(feature = "f64-transforms")]
+use rose_core::dtransform::{DTransform, GlobalDTransform};
 use rose_core::transform::Transform;
 
+use crate::components::Inactive;
+#[cfg(feature = "f64-transforms")]
+use crate::components::{Active, CameraParams};
+
 #[derive(Debug, Copy, Clone, Deserialize, Serialize)]
 pub struct Parent(pub Entity);
 
@@ -21,6 +29,21 @@ pub struct HierarchicalSystem;
 impl HierarchicalSystem {
     #[tracing::instrument(skip_all)]
     pub fn update<H: Hierarchical>(&self, world: &World, command_buffer: &mut CommandBuffer) {
+        for (e, global) in self.globals::<H>(world) {
+            command_buffer.insert_one(e, global);
+        }
+        self.remove_dangling_parents(world, command_buffer);
+    }
+
+    fn remove_dangling_parents(&self, world: &World, command_buffer: &mut CommandBuffer) {
+        for (entity, parent) in world.query::<&Parent>().iter() {
+            if world.entity(parent.0).is_err() {
+                command_buffer.remove_one::<Parent>(entity);
+            }
+        }
+    }
+
+    fn globals<H: Hierarchical>(&self, world: &World) -> HashMap<Entity, H::Global> {
         let curspan = tracing::span::Span::current();
         let mut q = world.query::<Option<&Parent>>().with::<&H>();
         let hierarchy = q
@@ -73,14 +96,32 @@ impl HierarchicalSystem {
             tracing::debug!("To insert: {}", insert_len);
             built_globals.extend(to_insert);
         }
+        built_globals
+    }
+}
 
-        for (e, global) in built_globals {
-            command_buffer.insert_one(e, global);
-        }
+/// Marks entities that are disabled because they or one of their ancestors are [`Inactive`].
+/// Maintained by [`HierarchicalSystem::update_activity`], so it lags one frame behind changes.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct InactiveInHierarchy;
 
-        for (entity, parent) in world.query::<&Parent>().iter() {
-            if world.entity(parent.0).is_err() {
-                command_buffer.remove_one::<Parent>(entity);
+impl HierarchicalSystem {
+    #[tracing::instrument(skip_all)]
+    pub fn update_activity(&self, world: &World, command_buffer: &mut CommandBuffer) {
+        let is_inactive = |mut entity: Entity| loop {
+            if world.get::<&Inactive>(entity).is_ok() {
+                break true;
+            }
+            match world.get::<&Parent>(entity) {
+                Ok(parent) => entity = parent.0,
+                Err(_) => break false,
+            }
+        };
+        for (entity, marked) in world.query::<Option<&InactiveInHierarchy>>().iter() {
+            match (is_inactive(entity), marked.is_some()) {
+                (true, false) => command_buffer.insert_one(entity, InactiveInHierarchy),
+                (false, true) => command_buffer.remove_one::<InactiveInHierarchy>(entity),
+                _ => {}
             }
         }
     }
@@ -158,3 +199,170 @@ impl Hierarchical for Transform {
         GlobalTransform(parent.0 * *self)
     }
 }
+
+#[cfg(feature = "f64-transforms")]
+impl Hierarchical for DTransform {
+    type Global = GlobalDTransform;
+
+    fn make_root(&self) -> Self::Global {
+        GlobalDTransform(*self)
+    }
+
+    fn make_child(&self, parent: &Self::Global) -> Self::Global {
+        GlobalDTransform(parent.0 * *self)
+    }
+}
+
+#[cfg(feature = "f64-transforms")]
+impl HierarchicalSystem {
+    /// Update the [`GlobalDTransform`] of entities positioned in double precision, and set their
+    /// [`GlobalTransform`] relative to the position of the active camera, so that rendering them
+    /// in single precision stays accurate. The camera needs a [`DTransform`] as well, and children
+    /// of entities positioned in double precision need one too.
+    ///
+    /// Replaces [`Self::update`] for [`Transform`]: the [`GlobalTransform`] of entities only
+    /// positioned with one is moved relative to the camera too, so that everything is rendered in
+    /// the same space; they keep the precision of their world position. Without an active camera
+    /// positioned in double precision, all [`GlobalTransform`]s are in world space.
+    #[tracing::instrument(skip_all)]
+    pub fn update_double_precision(&self, world: &World, command_buffer: &mut CommandBuffer) {
+        let globals = self.globals::<DTransform>(world);
+        let mut q = world
+            .query::<()>()
+            .with::<&CameraParams>()
+            .with::<&Active>()
+            .without::<&Inactive>()
+            .without::<&InactiveInHierarchy>();
+        let view = q.iter().find_map(|(entity, ())| globals.get(&entity));
+        let origin = view.map_or(DVec3::ZERO, |view| view.0.view_origin());
+        let relative = |entity: Entity, global: &DTransform| {
+            if world.get::<&CameraParams>(entity).is_ok() {
+                global.view_relative_to(origin)
+            } else {
+                global.relative_to(origin)
+            }
+        };
+        for (entity, global) in self.globals::<Transform>(world) {
+            if globals.contains_key(&entity) {
+                continue;
+            }
+            let global = match view {
+                Some(_) => GlobalTransform(relative(entity, &global.0.into())),
+                None => global,
+            };
+            command_buffer.insert_one(entity, global);
+        }
+        for (entity, global) in globals {
+            command_buffer.insert_one(entity, GlobalTransform(relative(entity, &global.0)));
+            command_buffer.insert_one(entity, global);
+        }
+        self.remove_dangling_parents(world, command_buffer);
+    }
+}
+
+#[cfg(all(test, feature = "f64-transforms"))]
+mod tests {
+    use glam::{DMat4, DVec3, Vec3};
+
+    use super::*;
+    use crate::scene::Scene;
+
+    const RADIUS: f64 = 6360e3;
+
+    #[test]
+    fn relative_globals_are_set_on_the_first_frame() {
+        let mut world = World::new();
+        let eye = DVec3::new(RADIUS, 2., 5.);
+        let view = DMat4::look_at_rh(eye, DVec3::new(RADIUS, 0., 0.), DVec3::Y);
+        let camera = world.spawn((
+            DTransform::from_matrix(view),
+            CameraParams::default(),
+            Active,
+        ));
+        let parent = world.spawn((DTransform::translation(DVec3::new(RADIUS, 0., 0.)),));
+        let child = world.spawn((
+            DTransform::translation(DVec3::new(1e-3, 2., 0.)),
+            Parent(parent),
+        ));
+
+        let mut cmd = CommandBuffer::new();
+        HierarchicalSystem.update_double_precision(&world, &mut cmd);
+        cmd.run_on(&mut world);
+
+        assert!(world.get::<&GlobalDTransform>(child).is_ok());
+        let child = world.get::<&GlobalTransform>(child).unwrap();
+        assert!(child.0.position.abs_diff_eq(Vec3::new(1e-3, 0., -5.), 1e-5));
+        let camera = world.get::<&GlobalTransform>(camera).unwrap();
+        assert!(camera.0.position.abs_diff_eq(Vec3::ZERO, 1e-3));
+    }
+
+    #[test]
+    fn single_precision_entities_are_moved_relative_to_the_camera() {
+        let mut world = World::new();
+        let eye = DVec3::new(RADIUS, 2., 5.);
+        let view = DMat4::look_at_rh(eye, DVec3::new(RADIUS, 0., 0.), DVec3::Y);
+        let light = world.spawn((Transform::translation(Vec3::new(RADIUS as f32, 10., 5.)),));
+        let object = world.spawn((DTransform::translation(DVec3::new(RADIUS, 0., 0.)),));
+
+        // Without a camera positioned in double precision, everything stays in world space
+        let mut cmd = CommandBuffer::new();
+        HierarchicalSystem.update_double_precision(&world, &mut cmd);
+        cmd.run_on(&mut world);
+        let position = world.get::<&GlobalTransform>(object).unwrap().0.position;
+        assert_eq!(position, Vec3::new(RADIUS as f32, 0., 0.));
+        let position = world.get::<&GlobalTransform>(light).unwrap().0.position;
+        assert_eq!(position, Vec3::new(RADIUS as f32, 10., 5.));
+
+        world.spawn((
+            DTransform::from_matrix(view),
+            CameraParams::default(),
+            Active,
+        ));
+        HierarchicalSystem.update_double_precision(&world, &mut cmd);
+        cmd.run_on(&mut world);
+        let position = world.get::<&GlobalTransform>(object).unwrap().0.position;
+        assert!(position.abs_diff_eq(Vec3::new(0., -2., -5.), 1e-5));
+        let position = world.get::<&GlobalTransform>(light).unwrap().0.position;
+        assert!(position.abs_diff_eq(Vec3::new(0., 8., 0.), 1e-5));
+    }
+
+    #[test]
+    fn saves_double_precision_transforms_without_their_globals() {
+        let dir = std::env::temp_dir().join(format!(
+            "rose-dtransform-persistence-test-{}",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        // Scenes hold the cache for the lifetime of the program, as persistence needs
+        let scene = Scene::new(&dir).unwrap();
+        let cache = scene.asset_cache();
+        let mut persistence = crate::builtin_persistence();
+        let transform = DTransform::translation(DVec3::new(RADIUS, 1e-3, 0.));
+        let mut world = World::new();
+        world.spawn((transform, GlobalDTransform(transform)));
+        let mut saved = vec![];
+        persistence
+            .serialize_world(
+                cache.as_any_cache(),
+                &mut serde_json::Serializer::new(&mut saved),
+                &world,
+            )
+            .unwrap();
+        assert_eq!(persistence.skipped_components().count(), 0);
+
+        let mut world = persistence
+            .deserialize_world(
+                cache.as_any_cache(),
+                &mut serde_json::Deserializer::from_slice(&saved),
+            )
+            .unwrap();
+        let (_, (loaded, global)) = world
+            .query_mut::<(&DTransform, Option<&GlobalDTransform>)>()
+            .into_iter()
+            .next()
+            .unwrap();
+        assert!(loaded.position.abs_diff_eq(transform.position, 1e-6));
+        assert!(global.is_none());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,220 @@
+use std::collections::{hash_map::Entry, HashMap};
+use std::hash::Hash;
+
+use hecs::{Component, Entity, World};
+
+use crate::components::Tags;
+use crate::systems::changes::{ChangeKind, ChangeTracker};
+use crate::systems::hierarchy::Parent;
+
+/// Name of an entity, stored as a `String` component.
+fn name_of(world: &World, entity: Entity) -> Option<String> {
+    world
+        .get::<&String>(entity)
+        .ok()
+        .map(|name| (*name).clone())
+}
+
+fn parent_of(world: &World, entity: Entity) -> Option<Entity> {
+    world.get::<&Parent>(entity).ok().map(|parent| parent.0)
+}
+
+/// Whether the entity is reached by following `segments` (a path split on `/`) from a root entity.
+fn matches_path(world: &World, entity: Entity, segments: &[&str]) -> bool {
+    let mut current = Some(entity);
+    for segment in segments.iter().rev() {
+        let Some(entity) = current else { return false; };
+        if name_of(world, entity).as_deref() != Some(*segment) {
+            return false;
+        }
+        current = parent_of(world, entity);
+    }
+    current.is_none()
+}
+
+fn split_path(path: &str) -> Vec<&str> {
+    path.split('/').filter(|s| !s.is_empty()).collect()
+}
+
+/// Lookup of entities by name, hierarchy path or tag, by going through every entity.
+/// [`crate::scene::Scene`] keeps an [`EntityIndex`] to avoid the scans.
+pub trait WorldLookupExt {
+    /// First entity with the given name.
+    fn find_by_name(&self, name: &str) -> Option<Entity>;
+    /// Entity at the given path of names from a root entity, e.g. `"Root/Arm/Hand"`.
+    fn find_by_path(&self, path: &str) -> Option<Entity>;
+    fn query_tagged(&self, tag: &str) -> Vec<Entity>;
+}
+
+impl WorldLookupExt for World {
+    fn find_by_name(&self, name: &str) -> Option<Entity> {
+        self.query::<&String>()
+            .iter()
+            .find_map(|(entity, n)| (n == name).then_some(entity))
+    }
+
+    fn find_by_path(&self, path: &str) -> Option<Entity> {
+        let segments = split_path(path);
+        let last = *segments.last()?;
+        self.query::<&String>()
+            .iter()
+            .filter(|(_, name)| *name == last)
+            .map(|(entity, _)| entity)
+            .find(|entity| matches_path(self, *entity, &segments))
+    }
+
+    fn query_tagged(&self, tag: &str) -> Vec<Entity> {
+        self.query::<&Tags>()
+            .iter()
+            .filter_map(|(entity, tags)| tags.contains(tag).then_some(entity))
+            .collect()
+    }
+}
+
+/// Entities by the keys taken from one of their components, kept up to date from the changes of
+/// that component.
+#[derive(Debug, Default)]
+struct KeyIndex {
+    entities: HashMap<String, Vec<Entity>>,
+    keys: HashMap<Entity, Vec<String>>,
+    changes: ChangeTracker,
+}
+
+impl KeyIndex {
+    fn update<C: Component + Hash>(&mut self, world: &World, keys: impl Fn(&C) -> Vec<String>) {
+        for change in self.changes.track::<C>(world) {
+            for key in self.keys.remove(&change.entity).into_iter().flatten() {
+                let Entry::Occupied(mut entry) = self.entities.entry(key) else { continue; };
+                entry.get_mut().retain(|entity| *entity != change.entity);
+                if entry.get().is_empty() {
+                    entry.remove();
+                }
+            }
+            if change.kind == ChangeKind::Removed {
+                continue;
+            }
+            let Ok(component) = world.get::<&C>(change.entity) else { continue; };
+            let keys = keys(&component);
+            for key in &keys {
+                self.entities
+                    .entry(key.clone())
+                    .or_default()
+                    .push(change.entity);
+            }
+            self.keys.insert(change.entity, keys);
+        }
+    }
+
+    fn get(&self, key: &str) -> impl '_ + Iterator<Item = Entity> {
+        self.entities.get(key).into_iter().flatten().copied()
+    }
+}
+
+/// Entities indexed by name and tag. [`Self::update`] only goes through the entities spawned,
+/// despawned, renamed or retagged since the last update; lookups check that the entities still
+/// match.
+#[derive(Debug, Default)]
+pub struct EntityIndex {
+    names: KeyIndex,
+    tags: KeyIndex,
+}
+
+impl EntityIndex {
+    pub fn new(world: &World) -> Self {
+        let mut index = Self::default();
+        index.update(world);
+        index
+    }
+
+    #[tracing::instrument(skip_all)]
+    pub fn update(&mut self, world: &World) {
+        self.names.update(world, |name: &String| vec![name.clone()]);
+        self.tags.update(world, |tags: &Tags| {
+            tags.iter().map(str::to_string).collect()
+        });
+    }
+
+    pub fn find_by_name(&self, world: &World, name: &str) -> Option<Entity> {
+        self.named(world, name).next()
+    }
+
+    pub fn find_by_path(&self, world: &World, path: &str) -> Option<Entity> {
+        let segments = split_path(path);
+        self.named(world, segments.last()?)
+            .find(|entity| matches_path(world, *entity, &segments))
+    }
+
+    pub fn tagged(&self, world: &World, tag: &str) -> Vec<Entity> {
+        self.tags
+            .get(tag)
+            .filter(|entity| {
+                world
+                    .get::<&Tags>(*entity)
+                    .is_ok_and(|tags| tags.contains(tag))
+            })
+            .collect()
+    }
+
+    fn named<'a>(&'a self, world: &'a World, name: &'a str) -> impl 'a + Iterator<Item = Entity> {
+        self.names
+            .get(name)
+            .filter(move |entity| name_of(world, *entity).as_deref() == Some(name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn build_world() -> (World, Entity) {
+        let mut world = World::new();
+        let root = world.spawn(("Root".to_string(),));
+        let arm = world.spawn(("Arm".to_string(), Parent(root)));
+        let hand = world.spawn((
+            "Hand".to_string(),
+            Parent(arm),
+            Tags::from_iter(["grabbable"]),
+        ));
+        // Same name elsewhere in the hierarchy
+        world.spawn(("Hand".to_string(),));
+        (world, hand)
+    }
+
+    #[test]
+    fn finds_by_path() {
+        let (world, hand) = build_world();
+        let index = EntityIndex::new(&world);
+        assert_eq!(world.find_by_path("Root/Arm/Hand"), Some(hand));
+        assert_eq!(index.find_by_path(&world, "/Root/Arm/Hand"), Some(hand));
+        assert_eq!(index.find_by_path(&world, "Arm/Hand"), None);
+        assert_ne!(index.find_by_path(&world, "Hand"), Some(hand));
+    }
+
+    #[test]
+    fn stale_entries_are_skipped() {
+        let (mut world, hand) = build_world();
+        let index = EntityIndex::new(&world);
+        assert_eq!(index.tagged(&world, "grabbable"), vec![hand]);
+        assert_eq!(world.query_tagged("grabbable"), vec![hand]);
+        world.despawn(hand).unwrap();
+        assert!(index.tagged(&world, "grabbable").is_empty());
+        assert_eq!(index.find_by_path(&world, "Root/Arm/Hand"), None);
+    }
+
+    #[test]
+    fn updates_follow_renames_and_despawns() {
+        let (mut world, hand) = build_world();
+        let mut index = EntityIndex::new(&world);
+        *world.get::<&mut String>(hand).unwrap() = "Claw".to_string();
+        world.get::<&mut Tags>(hand).unwrap().insert("sharp");
+        let other = world.find_by_name("Hand").unwrap();
+        world.despawn(other).unwrap();
+        let spawned = world.spawn(("Hand".to_string(),));
+        index.update(&world);
+
+        assert_eq!(index.find_by_name(&world, "Claw"), Some(hand));
+        assert_eq!(index.find_by_name(&world, "Hand"), Some(spawned));
+        assert_eq!(index.tagged(&world, "sharp"), vec![hand]);
+        assert_eq!(index.names.get("Hand").count(), 1);
+    }
+}
@@ -1,15 +1,25 @@
 pub use camera::*;
+pub use changes::*;
+pub use events::*;
+pub use extract::*;
+pub use lookup::*;
 pub use persistence::*;
 pub use render::*;
+pub use schedule::*;
 #[cfg(feature = "ui")]
 pub use ui::*;
 
 pub use self::input::*;
 
 pub mod camera;
+pub mod changes;
+pub mod events;
+pub mod extract;
 pub mod input;
+pub mod lookup;
 pub mod persistence;
 pub mod render;
+pub mod schedule;
 
 pub mod hierarchy;
 #[cfg(feature = "ui")]
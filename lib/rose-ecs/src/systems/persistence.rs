@@ -1,5 +1,9 @@
 use std::any::TypeId;
-use std::{any::type_name, collections::HashMap};
+use std::{
+    any::type_name,
+    collections::{HashMap, HashSet},
+    marker::PhantomData,
+};
 
 use assets_manager::{AnyCache, Compound, Handle};
 use eyre::Result;
@@ -16,6 +20,8 @@ use serde::{
 
 use rose_core::utils::thread_guard::ThreadGuard;
 
+use crate::systems::render::CustomMaterial;
+
 pub trait SerializableComponent:
     Component + serde::Serialize + serde::Deserialize<'static>
 {
@@ -43,19 +49,21 @@ fn deserialize<C: SerializableComponent>(
     Ok(())
 }
 
-#[derive(Copy, Clone)]
+type SerializeFn = dyn Fn(&EntityRef<'_>) -> Result<Option<serde_json::Value>>;
+type DeserializeFn = dyn Fn(&mut EntityBuilder, serde_json::Value) -> Result<()>;
+
 struct DynPersistence {
     name: &'static str,
-    serialize: &'static dyn Fn(&EntityRef<'_>) -> Result<Option<serde_json::Value>>,
-    deserialize: &'static dyn Fn(&mut EntityBuilder, serde_json::Value) -> Result<()>,
+    serialize: Box<SerializeFn>,
+    deserialize: Box<DeserializeFn>,
 }
 
 impl DynPersistence {
     fn new<C: SerializableComponent>() -> Self {
         Self {
             name: type_name::<C>(),
-            serialize: &serialize::<C>,
-            deserialize: &deserialize::<C>,
+            serialize: Box::new(serialize::<C>),
+            deserialize: Box::new(deserialize::<C>),
         }
     }
 }
@@ -69,33 +77,95 @@ fn load_asset<A: Compound>(
     Ok(())
 }
 
+/// Point the handles to the asset `old` to the asset `new`, returning the number of entities
+/// changed. Fails without changing anything when `new` cannot be loaded.
+fn relink_asset<A: Compound>(
+    cache: AnyCache<'static>,
+    world: &mut World,
+    old: &str,
+    new: &str,
+) -> Result<usize> {
+    let handle = cache.load::<A>(new)?;
+    let mut relinked = vec![];
+    for (entity, (current, placeholder)) in
+        world.query_mut::<(&mut Handle<'static, A>, Option<&Placeholder<A>>)>()
+    {
+        let id = placeholder.map_or(current.id().as_str(), |placeholder| &placeholder.id);
+        if id == old {
+            *current = handle;
+            relinked.push(entity);
+        }
+    }
+    for &entity in &relinked {
+        world.remove_one::<Placeholder<A>>(entity).ok();
+    }
+    Ok(relinked.len())
+}
+
 fn get_id<A: Compound>(entity: &EntityRef<'_>) -> Option<String> {
+    if let Some(placeholder) = entity.get::<&Placeholder<A>>() {
+        return Some(placeholder.id.clone());
+    }
     entity
         .get::<&Handle<'static, A>>()
         .map(|r| r.id().to_string())
 }
 
+/// ID of the missing asset an entity references, while its handle points to the placeholder.
+struct Placeholder<A> {
+    id: String,
+    __phantom: PhantomData<fn() -> A>,
+}
+
+type LoadAssetFn = dyn Fn(AnyCache<'static>, &mut EntityBuilder, &str) -> Result<()>;
+type PlaceholderFn = dyn Fn(AnyCache<'static>, &mut EntityBuilder, &str);
+type RelinkFn = dyn Fn(AnyCache<'static>, &mut World, &str, &str) -> Result<usize>;
+
 struct DynAsset {
     name: &'static str,
-    load: &'static dyn Fn(AnyCache<'static>, &mut EntityBuilder, &str) -> Result<()>,
-    get_id: &'static dyn Fn(&EntityRef<'_>) -> Option<String>,
+    load: Box<LoadAssetFn>,
+    /// Adds a handle to a stand-in asset when loading fails; loading scenes fails instead when
+    /// there is none.
+    placeholder: Option<Box<PlaceholderFn>>,
+    relink: Box<RelinkFn>,
+    get_id: Box<dyn Fn(&EntityRef<'_>) -> Option<String>>,
 }
 
 impl DynAsset {
     pub fn new<A: Compound>() -> Self {
         Self {
             name: type_name::<A>(),
-            load: &load_asset::<A>,
-            get_id: &get_id::<A>,
+            load: Box::new(load_asset::<A>),
+            placeholder: None,
+            relink: Box::new(relink_asset::<A>),
+            get_id: Box::new(get_id::<A>),
         }
     }
 }
 
+/// Asset referenced by a loaded scene which could not be loaded, and was replaced by a placeholder.
+/// Scenes keep referencing the missing asset when saved.
+#[derive(Debug, Clone)]
+pub struct MissingAsset {
+    /// Type of the asset, as saved in scenes.
+    pub kind: &'static str,
+    pub id: String,
+    /// Why the asset could not be loaded.
+    pub error: String,
+}
+
 pub struct PersistenceSystem {
     asset_cache: Option<ThreadGuard<AnyCache<'static>>>,
     registry: HashMap<TypeId, ThreadGuard<DynPersistence>>,
     asset_types: HashMap<TypeId, ThreadGuard<DynAsset>>,
     type_map: HashMap<&'static str, TypeId>,
+    /// Components saved with scenes, or deliberately left out of them.
+    known_components: HashSet<TypeId>,
+    /// Unknown components found while saving the last world, with the number of entities having
+    /// them.
+    skipped: HashMap<TypeId, usize>,
+    /// Assets replaced by placeholders while loading the last scene, until relinked.
+    missing: Vec<MissingAsset>,
 }
 
 impl PersistenceSystem {
@@ -105,24 +175,149 @@ impl PersistenceSystem {
             registry: HashMap::new(),
             asset_types: HashMap::new(),
             type_map: HashMap::new(),
+            known_components: HashSet::new(),
+            skipped: HashMap::new(),
+            missing: vec![],
         }
     }
 
     pub fn register_component<C: SerializableComponent>(&mut self) -> &mut Self {
+        self.insert_component::<C>(DynPersistence::new::<C>())
+    }
+
+    /// Register a component which does not implement the serde traits, with its own conversions
+    /// from and to JSON values.
+    pub fn register_component_with<C: Component>(
+        &mut self,
+        serialize: impl 'static + Fn(&C) -> Result<serde_json::Value>,
+        deserialize: impl 'static + Fn(serde_json::Value) -> Result<C>,
+    ) -> &mut Self {
+        self.insert_component::<C>(DynPersistence {
+            name: type_name::<C>(),
+            serialize: Box::new(move |entity: &EntityRef<'_>| {
+                entity
+                    .get::<&C>()
+                    .map(|component| serialize(&component))
+                    .transpose()
+            }),
+            deserialize: Box::new(move |builder: &mut EntityBuilder, value| {
+                builder.add(deserialize(value)?);
+                Ok(())
+            }),
+        })
+    }
+
+    pub fn register_asset<A: Compound>(&mut self) -> &mut Self {
+        self.insert_asset::<A>(DynAsset::new::<A>())
+    }
+
+    /// Register an asset type, loading scenes which reference missing or broken assets of this type
+    /// with handles to the `placeholder` asset instead, see [`Self::missing_assets`].
+    pub fn register_asset_with_placeholder<A: Compound>(
+        &mut self,
+        placeholder: impl 'static + Fn() -> A,
+    ) -> &mut Self {
+        let mut dyn_asset = DynAsset::new::<A>();
+        dyn_asset.placeholder = Some(Box::new(
+            move |cache: AnyCache<'static>, entity: &mut EntityBuilder, id: &str| {
+                // Cached under its own ID so that the missing one can still be loaded once the
+                // asset exists; saving keeps the reference through the `Placeholder` component
+                let key = format!("placeholder:{}", type_name::<A>());
+                let handle = match cache.get_cached::<A>(&key) {
+                    Some(handle) => handle,
+                    None => cache.get_or_insert(&key, placeholder()),
+                };
+                entity.add(handle).add(Placeholder::<A> {
+                    id: id.to_string(),
+                    __phantom: PhantomData,
+                });
+            },
+        ));
+        self.known_components.insert(TypeId::of::<Placeholder<A>>());
+        self.insert_asset::<A>(dyn_asset)
+    }
+
+    /// Save handles to custom materials by their asset ID. Custom materials cannot be loaded from
+    /// asset files, so `create` provides the material of IDs missing from the asset cache when
+    /// loading a scene.
+    pub fn register_custom_material<M: 'static>(
+        &mut self,
+        create: impl 'static + Fn(&str) -> Result<CustomMaterial<M>>,
+    ) -> &mut Self {
+        let load = move |cache: AnyCache<'static>, entity: &mut EntityBuilder, id: &str| {
+            let handle = match cache.get_cached::<CustomMaterial<M>>(id) {
+                Some(handle) => handle,
+                None => cache.get_or_insert(id, create(id)?),
+            };
+            entity.add(handle);
+            Ok(())
+        };
+        self.insert_asset::<CustomMaterial<M>>(DynAsset {
+            name: type_name::<CustomMaterial<M>>(),
+            load: Box::new(load),
+            placeholder: None,
+            relink: Box::new(relink_asset::<CustomMaterial<M>>),
+            get_id: Box::new(get_id::<CustomMaterial<M>>),
+        })
+    }
+
+    /// Leave a component out of saved scenes without warning, for components that systems derive
+    /// from other components.
+    pub fn skip_component<C: Component>(&mut self) -> &mut Self {
+        self.known_components.insert(TypeId::of::<C>());
+        self
+    }
+
+    /// Components of the last saved world which were not saved because they are not registered,
+    /// with the number of entities having them.
+    pub fn skipped_components(&self) -> impl '_ + Iterator<Item = (TypeId, usize)> {
+        self.skipped
+            .iter()
+            .map(|(type_id, entities)| (*type_id, *entities))
+    }
+
+    /// Assets replaced by placeholders in the last loaded scene, which have not been relinked.
+    pub fn missing_assets(&self) -> &[MissingAsset] {
+        &self.missing
+    }
+
+    /// Point the handles of the world to the missing asset `old_id` to the asset `new_id` instead,
+    /// returning the number of entities changed. `kind` is the one of the [`MissingAsset`].
+    pub fn relink_asset(
+        &mut self,
+        cache: AnyCache<'static>,
+        world: &mut World,
+        kind: &str,
+        old_id: &str,
+        new_id: &str,
+    ) -> Result<usize> {
+        let asset = self
+            .type_map
+            .get(kind)
+            .and_then(|type_id| self.asset_types.get(type_id));
+        let Some(asset) = asset else { eyre::bail!("Unknown asset type {:?}", kind); };
+        let relinked = (asset.relink)(cache, world, old_id, new_id)?;
+        self.missing
+            .retain(|missing| missing.kind != kind || missing.id != old_id);
+        Ok(relinked)
+    }
+
+    fn insert_component<C: Component>(&mut self, dyn_persistence: DynPersistence) -> &mut Self {
         let type_id = TypeId::of::<C>();
-        let dyn_persistence = DynPersistence::new::<C>();
         self.type_map.insert(dyn_persistence.name, type_id);
         self.registry
             .insert(type_id, ThreadGuard::new(dyn_persistence));
+        self.known_components.insert(type_id);
         self
     }
 
-    pub fn register_asset<A: Compound>(&mut self) -> &mut Self {
+    fn insert_asset<A: Compound>(&mut self, dyn_asset: DynAsset) -> &mut Self {
         let type_id = TypeId::of::<A>();
-        let dyn_asset = DynAsset::new::<A>();
         self.type_map.insert(dyn_asset.name, type_id);
         self.asset_types
             .insert(type_id, ThreadGuard::new(dyn_asset));
+        self.known_components
+            .insert(TypeId::of::<Handle<'static, A>>());
         self
     }
 
@@ -135,6 +330,7 @@ impl PersistenceSystem {
         D::Error: 'static + Send + Sync,
     {
         self.asset_cache.replace(ThreadGuard::new(cache));
+        self.missing.clear();
         Ok(row::deserialize(self, de)?)
     }
 
@@ -148,7 +344,15 @@ impl PersistenceSystem {
         S::Error: 'static + Send + Sync,
     {
         self.asset_cache.replace(ThreadGuard::new(cache));
+        self.skipped.clear();
         row::serialize(world, self, ser)?;
+        if !self.skipped.is_empty() {
+            tracing::warn!(
+                message = "Components not registered for persistence were not saved",
+                types = self.skipped.len(),
+                entities = self.skipped.values().sum::<usize>(),
+            );
+        }
         Ok(())
     }
 }
@@ -168,12 +372,30 @@ impl DeserializeContext for PersistenceSystem {
                 let value = map.next_value::<serde_json::Value>()?;
                 (pers.deserialize)(entity, value).map_err(de::Error::custom)?;
             } else if let Some(asset) = self.asset_types.get(type_id) {
-                (asset.load)(
-                    *self.asset_cache.unwrap(),
-                    entity,
-                    &map.next_value::<String>()?,
-                )
-                .map_err(de::Error::custom)?;
+                let cache = *self.asset_cache.unwrap();
+                let id = map.next_value::<String>()?;
+                let Err(err) = (asset.load)(cache, entity, &id) else { continue; };
+                let Some(placeholder) = &asset.placeholder else {
+                    return Err(de::Error::custom(err));
+                };
+                tracing::warn!(
+                    message = "Replacing missing asset with a placeholder",
+                    kind = asset.name,
+                    %id,
+                    %err
+                );
+                placeholder(cache, entity, &id);
+                let known = self
+                    .missing
+                    .iter()
+                    .any(|missing| missing.kind == asset.name && missing.id == id);
+                if !known {
+                    self.missing.push(MissingAsset {
+                        kind: asset.name,
+                        id,
+                        error: format!("{:#}", err),
+                    });
+                }
             }
         }
         Ok(())
@@ -197,6 +419,82 @@ impl SerializeContext for PersistenceSystem {
             let Some(id) = (asset.get_id)(&entity) else { continue; };
             map.serialize_entry::<String, String>(&asset.name.to_string(), &id)?;
         }
+        for type_id in entity.component_types() {
+            if !self.known_components.contains(&type_id) {
+                *self.skipped.entry(type_id).or_default() += 1;
+            }
+        }
         map.end()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use assets_manager::AssetCache;
+
+    use super::*;
+
+    #[test]
+    fn placeholders_keep_the_missing_id_and_relink() {
+        let dir = std::env::temp_dir().join("rose-persistence-placeholder-test");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("present.txt"), "present").unwrap();
+        let mut persistence = PersistenceSystem::new();
+        persistence.register_asset_with_placeholder(|| String::from("placeholder"));
+
+        // Saved with a cache where the asset exists, loaded with one where it is missing
+        let saving_cache: &'static _ = Box::leak(Box::new(AssetCache::new(&dir).unwrap()));
+        let mut world = World::new();
+        world.spawn((saving_cache.get_or_insert::<String>("gone", "gone".into()),));
+        let mut saved = vec![];
+        persistence
+            .serialize_world(
+                saving_cache.as_any_cache(),
+                &mut serde_json::Serializer::new(&mut saved),
+                &world,
+            )
+            .unwrap();
+
+        let cache: &'static _ = Box::leak(Box::new(AssetCache::new(&dir).unwrap()));
+        let mut world = persistence
+            .deserialize_world(
+                cache.as_any_cache(),
+                &mut serde_json::Deserializer::from_slice(&saved),
+            )
+            .unwrap();
+        assert_eq!(persistence.missing_assets().len(), 1);
+        assert_eq!(persistence.missing_assets()[0].id, "gone");
+        assert!(cache.get_cached::<String>("gone").is_none());
+        let (entity, handle) = world
+            .query_mut::<&Handle<'static, String>>()
+            .into_iter()
+            .map(|(entity, handle)| (entity, *handle))
+            .next()
+            .unwrap();
+        assert_eq!(*handle.read(), "placeholder");
+
+        let mut resaved = vec![];
+        persistence
+            .serialize_world(
+                cache.as_any_cache(),
+                &mut serde_json::Serializer::new(&mut resaved),
+                &world,
+            )
+            .unwrap();
+        assert_eq!(resaved, saved);
+
+        let relinked = persistence
+            .relink_asset(
+                cache.as_any_cache(),
+                &mut world,
+                type_name::<String>(),
+                "gone",
+                "present",
+            )
+            .unwrap();
+        assert_eq!(relinked, 1);
+        assert!(persistence.missing_assets().is_empty());
+        let handle = world.get::<&Handle<'static, String>>(entity).unwrap();
+        assert_eq!(*handle.read(), "present");
+    }
+}
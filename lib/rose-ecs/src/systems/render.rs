@@ -1,32 +1,57 @@
 use std::{
-    collections::hash_map::DefaultHasher,
-    hash::{Hash, Hasher},
-    path::PathBuf,
+    any::TypeId,
+    collections::{hash_map::DefaultHasher, HashMap, HashSet},
+    hash::Hash,
+    ops::Deref,
+    path::Path,
     rc::Rc,
+    thread::JoinHandle,
     time::Duration,
 };
 
-use assets_manager::{AnyCache, BoxedError, Compound, Handle, SharedString};
-use dashmap::DashMap;
+use assets_manager::{AnyCache, AssetGuard, BoxedError, Compound, Handle, SharedString};
 use eyre::Result;
-use glam::{UVec2, Vec2, Vec3};
-use hecs::World;
+use glam::{Mat4, UVec2, UVec4, Vec2, Vec3, Vec4};
+use hecs::{Component, Entity, QueryBorrow, QueryItem, With, Without, World};
 
 use rose_core::{
+    atlas::AtlasId,
     camera::Camera,
-    light::{GpuLight, Light},
-    transform::{Transform, TransformExt},
+    light::{AreaShape, Light, LightHandle},
+    transform::{Transform, TransformExt, Transformed},
     utils::thread_guard::ThreadGuard,
 };
-use rose_platform::PhysicalSize;
-use rose_renderer::{material::MaterialInstance, DrawMaterial, Mesh, Renderer};
+use rose_platform::{config::LaunchConfig, PhysicalSize};
+use rose_renderer::{
+    batching::merge_meshes,
+    cookie::{CookieProjection, LightCookie},
+    cubemap::Cubemap,
+    env::{EnvironmentMap, SimpleSky},
+    material::{MaterialInstance, TextureSlot, DEFAULT_EMISSION_STRENGTH},
+    morph::MorphTargets,
+    polyline::PolylinePoint,
+    probes::IrradianceProbeGrid,
+    reflection_probes::ReflectionProbeId,
+    streaming::{StreamingSettings, TextureStreaming},
+    DrawMaterial, MaterialHandle, Mesh, MeshHandle, Renderer, RendererConfig, TextureHandle,
+};
+use violette::{framebuffer::Framebuffer, texture::Texture};
 
 use crate::{
+    animation::{AnimatedMaterial, LightAnimation},
     assets::*,
     components::{Light as LightComponent, *},
-    systems::hierarchy::GlobalTransform,
+    navigation::Navigation,
+    systems::{
+        changes::{ChangeKind, ChangeTracker},
+        hierarchy::{GlobalTransform, InactiveInHierarchy, Parent},
+    },
 };
 
+/// Largest side of the sprite images packed into the sprite atlas; larger images get a texture of
+/// their own.
+const MAX_ATLAS_IMAGE_SIZE: u32 = 256;
+
 pub struct CustomMaterial<M>(ThreadGuard<Rc<M>>);
 
 impl<M> CustomMaterial<M> {
@@ -35,20 +60,91 @@ impl<M> CustomMaterial<M> {
     }
 }
 
+/// Share a material between custom material assets, e.g. in the `create` function of
+/// [`crate::systems::PersistenceSystem::register_custom_material`].
+impl<M> From<Rc<M>> for CustomMaterial<M> {
+    fn from(inner: Rc<M>) -> Self {
+        Self(ThreadGuard::new(inner))
+    }
+}
+
+impl<M> Deref for CustomMaterial<M> {
+    type Target = M;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
 impl<M: 'static> Compound for CustomMaterial<M> {
     fn load(_cache: AnyCache, _id: &SharedString) -> std::result::Result<Self, BoxedError> {
         Err(eyre::eyre!("Cannot load a CustomMaterial from assets, it must be provided").into())
     }
 }
 
+/// Textures of the slots of a material.
+struct MaterialTextures {
+    color: Option<Texture<[f32; 3]>>,
+    normal_map: Option<Texture<[f32; 3]>>,
+    rough_metal: Option<Texture<[f32; 2]>>,
+    emission: Option<Texture<[f32; 3]>>,
+    clearcoat: Option<Texture<[f32; 2]>>,
+    anisotropy: Option<Texture<[f32; 3]>>,
+}
+
+impl MaterialTextures {
+    fn create(mat: &Material) -> Result<Self> {
+        let rgb = |image: &Option<Image>| image.as_ref().map(Image::create_texture_rgb).transpose();
+        let rg = |image: &Option<Image>| image.as_ref().map(Image::create_texture_rg).transpose();
+        Ok(Self {
+            color: rgb(&mat.color)?,
+            normal_map: rgb(&mat.normal)?,
+            rough_metal: rg(&mat.rough_metal)?,
+            emission: rgb(&mat.emission)?,
+            clearcoat: rg(&mat.clearcoat_map)?,
+            anisotropy: rgb(&mat.anisotropy_map)?,
+        })
+    }
+}
+
 pub struct RenderSystem {
     pub clear_color: Vec3,
     pub camera: Camera,
     pub renderer: ThreadGuard<Renderer>,
-    meshes_map: DashMap<SharedString, ThreadGuard<Rc<Mesh>>>,
-    materials_map: DashMap<SharedString, ThreadGuard<Rc<MaterialInstance>>>,
+    /// Framebuffer to render into instead of the window, e.g. a viewport texture of the UI. It
+    /// needs to be the size given to [`Self::resize`].
+    pub render_target: Option<ThreadGuard<Rc<Framebuffer>>>,
+    meshes_map: HashMap<SharedString, MeshHandle>,
+    /// Copies of the meshes of the entities with [`MorphWeights`], which are set per mesh.
+    morphed_meshes: HashMap<Entity, (SharedString, MeshHandle)>,
+    materials_map: HashMap<SharedString, MaterialHandle>,
+    /// Resident mipmap levels of the textures of each material.
+    texture_streaming: TextureStreaming<SharedString>,
+    /// Materials whose images are being downscaled to their new resident level.
+    streamed_materials: HashMap<SharedString, (u32, JoinHandle<Material>)>,
+    /// Distance from the origin of each mesh to its furthest vertex, to estimate how large
+    /// materials appear on screen.
+    mesh_radii: HashMap<SharedString, f32>,
+    custom_materials_map: HashMap<(TypeId, SharedString), MaterialHandle>,
+    textures_map: HashMap<SharedString, TextureHandle>,
+    /// Sprite images packed into the sprite atlas instead of [`Self::textures_map`].
+    atlas_images: HashMap<SharedString, AtlasId>,
     custom_materials_query: Vec<&'static (dyn Send + Sync + Fn(&mut Self, &World))>,
-    lights_hash: u64,
+    light_changes: ChangeTracker,
+    lights: HashMap<Entity, LightHandle>,
+    /// Textures of the light cookies by image ID, shared by the lights using the same image.
+    cookie_textures: HashMap<String, Rc<Texture<[f32; 3]>>>,
+    reflection_probes: HashMap<Entity, ReflectionProbeId>,
+    /// Irradiance volumes baked since they were added.
+    irradiance_volumes: HashSet<Entity>,
+    /// Merged static meshes, with the ID of the material they are drawn with.
+    static_batches: Vec<(SharedString, MeshHandle)>,
+    batched_entities: HashSet<Entity>,
+    /// Center and resolution of the cubemap to capture on the next frame.
+    cubemap_request: Option<(Vec3, u32)>,
+    captured_cubemap: Option<Cubemap>,
+    /// Irradiance probes to capture on the next frame, with the resolution of their cubemaps.
+    irradiance_bake_request: Option<(IrradianceProbeGrid, u32)>,
 }
 
 impl RenderSystem {
@@ -56,7 +152,8 @@ impl RenderSystem {
         let mut q = world
             .query::<(&GlobalTransform, &CameraParams)>()
             .with::<&Active>()
-            .without::<&Inactive>();
+            .without::<&Inactive>()
+            .without::<&InactiveInHierarchy>();
         let Some((_, (tr, camera))) = q.iter().next() else {
             tracing::warn!("No active camera. Make sure you have a camera set up using the CameraBundle, or by having GlobalTransform, CameraParams and the Active components on the entity.");
             return;
@@ -79,6 +176,15 @@ impl RenderSystem {
                 rough_metal_factor: Vec2::ONE,
                 emission: None,
                 emission_factor: Vec3::ZERO,
+                emission_strength: DEFAULT_EMISSION_STRENGTH,
+                double_sided: false,
+                clearcoat: 0.,
+                clearcoat_roughness: 0.,
+                anisotropy: 0.,
+                anisotropy_rotation: 0.,
+                clearcoat_map: None,
+                anisotropy_map: None,
+                samplers: MaterialSamplers::default(),
             },
         )
     }
@@ -91,6 +197,63 @@ impl RenderSystem {
         cache.get_or_insert("prim:sphere", MeshAsset::uv_sphere(1., 24, 48))
     }
 
+    /// Current settings of the renderer. Environment map paths are made relative to `base_dir`
+    /// when they are inside of it.
+    pub fn renderer_settings(&mut self, base_dir: &Path) -> RendererSettings {
+        let environment = if let Some(sky) = self.renderer.environment::<SimpleSky>() {
+            Some(EnvironmentSettings::SimpleSky(sky.params))
+        } else {
+            self.renderer
+                .environment::<EnvironmentMap>()
+                .and_then(|map| map.path())
+                .map(|path| {
+                    let path = path.strip_prefix(base_dir).unwrap_or(path);
+                    EnvironmentSettings::Map(path.to_path_buf())
+                })
+        };
+        RendererSettings {
+            post_process: *self.renderer.post_process_interface(),
+            environment,
+            depth: self.renderer.depth_mode(),
+            camera_relative: self.renderer.camera_relative(),
+        }
+    }
+
+    pub fn apply_renderer_settings(
+        &mut self,
+        settings: &RendererSettings,
+        base_dir: &Path,
+    ) -> Result<()> {
+        *self.renderer.post_process_interface() = settings.post_process;
+        self.renderer.set_depth_mode(settings.depth);
+        self.renderer.set_camera_relative(settings.camera_relative);
+        match &settings.environment {
+            Some(EnvironmentSettings::SimpleSky(params)) => {
+                if let Some(sky) = self.renderer.environment_mut::<SimpleSky>() {
+                    sky.params = *params;
+                } else {
+                    let sky = SimpleSky::new(*params, self.renderer.reload_watcher())?;
+                    self.renderer.set_environment(|_| sky);
+                }
+            }
+            Some(EnvironmentSettings::Map(path)) => {
+                let path = base_dir.join(path);
+                let already_loaded = self
+                    .renderer
+                    .environment::<EnvironmentMap>()
+                    .and_then(|map| map.path())
+                    == Some(path.as_path());
+                if !already_loaded {
+                    let map = EnvironmentMap::load(&path, self.renderer.reload_watcher())?;
+                    self.renderer.set_environment(|_| map);
+                }
+            }
+            None => {}
+        }
+        self.renderer.mark_dirty();
+        Ok(())
+    }
+
     pub fn resize(&mut self, size: PhysicalSize<u32>) -> Result<()> {
         let sizef = size.cast();
         self.camera.projection.width = sizef.width;
@@ -100,20 +263,47 @@ impl RenderSystem {
     }
 
     pub fn new(size: UVec2) -> Result<Self> {
-        let base_dir = std::env::var("CARGO_PROJECT_DIR")
-            .map(PathBuf::from)
-            .or_else(|_| std::env::current_dir())
-            .unwrap();
+        Self::with_config(size, RendererConfig::default())
+    }
+
+    pub fn with_config(size: UVec2, config: RendererConfig) -> Result<Self> {
+        Self::with_base_dir(size, LaunchConfig::default_asset_root(), config)
+    }
+
+    /// Create the render system, loading the renderer resources from `base_dir`.
+    pub fn with_base_dir(
+        size: UVec2,
+        base_dir: impl AsRef<Path>,
+        config: RendererConfig,
+    ) -> Result<Self> {
+        let base_dir = base_dir.as_ref();
         tracing::info!("Base resources directory: {}", base_dir.display());
-        let renderer = Renderer::new(size, &base_dir)?;
+        let renderer = Renderer::with_config(size, base_dir, config)?;
         Ok(Self {
             clear_color: Vec3::ZERO,
             camera: Camera::default(),
             renderer: ThreadGuard::new(renderer),
-            meshes_map: DashMap::new(),
-            materials_map: DashMap::new(),
+            render_target: None,
+            meshes_map: HashMap::new(),
+            morphed_meshes: HashMap::new(),
+            materials_map: HashMap::new(),
+            texture_streaming: TextureStreaming::new(StreamingSettings::default()),
+            streamed_materials: HashMap::new(),
+            mesh_radii: HashMap::new(),
+            custom_materials_map: HashMap::new(),
+            textures_map: HashMap::new(),
+            atlas_images: HashMap::new(),
             custom_materials_query: vec![],
-            lights_hash: DefaultHasher::new().finish(),
+            light_changes: ChangeTracker::new(),
+            lights: HashMap::new(),
+            cookie_textures: HashMap::new(),
+            reflection_probes: HashMap::new(),
+            irradiance_volumes: HashSet::new(),
+            static_batches: vec![],
+            batched_entities: HashSet::new(),
+            cubemap_request: None,
+            captured_cubemap: None,
+            irradiance_bake_request: None,
         })
     }
 
@@ -123,156 +313,921 @@ impl RenderSystem {
         self
     }
 
-    pub fn on_frame(&mut self, dt: Duration, world: &World) -> Result<()> {
-        self.handle_mesh_assets(world)?;
-        self.handle_material_assets(world)?;
-        self.handle_lights(world)?;
+    /// Resident mipmap levels of the material textures, streamed in as they appear larger on
+    /// screen. How large is estimated on the CPU from the bounding sphere of the meshes drawn
+    /// with them, so occlusion and the texel density of their UVs are not taken into account.
+    pub fn texture_streaming(&self) -> &TextureStreaming<SharedString> {
+        &self.texture_streaming
+    }
+
+    /// Change the video memory budget and pacing of the material textures.
+    pub fn texture_streaming_settings_mut(&mut self) -> &mut StreamingSettings {
+        self.texture_streaming.settings_mut()
+    }
+
+    /// Distance from the origin of the mesh to its furthest vertex, once it has been loaded.
+    pub fn mesh_radius(&self, mesh: &Handle<MeshAsset>) -> Option<f32> {
+        self.mesh_radii.get(mesh.id()).copied()
+    }
+
+    /// Capture the scene around `center` on the next frame, e.g. for a 360° screenshot. See
+    /// [`Renderer::render_cubemap`]; the capture is then returned by [`Self::take_cubemap`].
+    pub fn request_cubemap(&mut self, center: Vec3, resolution: u32) {
+        self.cubemap_request = Some((center, resolution));
+    }
+
+    pub fn take_cubemap(&mut self) -> Option<Cubemap> {
+        self.captured_cubemap.take()
+    }
+
+    /// Capture the irradiance probes of the grid on the next frame, then light the scene with them.
+    /// See [`Renderer::bake_irradiance_probes`].
+    pub fn request_irradiance_bake(&mut self, grid: IrradianceProbeGrid, resolution: u32) {
+        self.irradiance_bake_request = Some((grid, resolution));
+    }
+
+    pub fn on_frame(&mut self, dt: Duration, world: &World, cache: AnyCache) -> Result<()> {
+        let meshes_changed = self.handle_mesh_assets(world)?;
+        let materials_changed = self.handle_material_assets(world)?;
+        let materials_streamed = self.handle_texture_streaming(world)?;
+        let textures_changed = self.handle_sprite_textures(world)?;
+        let animations_playing = self.handle_animated_materials(dt, world)?;
+        if meshes_changed
+            || materials_changed
+            || materials_streamed
+            || textures_changed
+            || animations_playing
+        {
+            self.renderer.mark_dirty();
+        }
+        self.handle_lights(world, cache)?;
+        self.handle_fog(world);
+        self.handle_clip_planes(world);
+        self.handle_reflection_probes(world)?;
+        self.handle_irradiance_volumes(world);
 
         self.renderer.begin_render(&self.camera)?;
+        self.submit_morph_weights(world);
         self.submit_meshes(world);
         for custom in self.custom_materials_query.clone() {
             (custom)(self, world);
         }
-        self.renderer.flush(dt, self.clear_color)?;
+        self.submit_water(world);
+        self.submit_refractive(world);
+        self.submit_sprites(world);
+        self.submit_polylines(world);
+        self.submit_outlines(world);
+        if let Some((center, resolution)) = self.cubemap_request.take() {
+            self.captured_cubemap = Some(self.renderer.render_cubemap(center, resolution)?);
+        }
+        if let Some((mut grid, resolution)) = self.irradiance_bake_request.take() {
+            self.renderer
+                .bake_irradiance_probes(&mut grid, resolution)?;
+        }
+        match &self.render_target {
+            Some(target) => self.renderer.flush_to(target, dt, self.clear_color)?,
+            None => self.renderer.flush(dt, self.clear_color)?,
+        };
         Ok(())
     }
 
+    /// Merge the active meshes marked [`Static`] into one mesh per material, drawn instead of the
+    /// individual meshes, and return the number of merged meshes. Called when a scene becomes the
+    /// active scene, see [`crate::CoreSystems::activate_scene`]: static meshes moved, deactivated
+    /// or reloaded afterwards are only updated when the batches are built again.
+    pub fn build_static_batches(&mut self, world: &World) -> Result<usize> {
+        self.clear_static_batches();
+        for (material, parts) in static_mesh_groups(world) {
+            let merged =
+                merge_meshes(parts.iter().map(|(_, mesh, transform)| {
+                    (&mesh.vertices[..], &mesh.indices[..], *transform)
+                }));
+            tracing::info!(message="Batching static meshes", %material, meshes=parts.len(), vertices=merged.vertices.len());
+            let mesh = Mesh::new(merged.vertices, merged.indices)?;
+            let mesh = self.renderer.register_mesh(mesh);
+            self.static_batches.push((material, mesh));
+            self.batched_entities
+                .extend(parts.iter().map(|(entity, ..)| *entity));
+        }
+        self.renderer.mark_dirty();
+        Ok(self.static_batches.len())
+    }
+
+    /// Remove the merged static meshes, drawing the individual meshes again.
+    pub fn clear_static_batches(&mut self) {
+        for (_, mesh) in self.static_batches.drain(..) {
+            self.renderer.unregister_mesh(mesh);
+        }
+        self.batched_entities.clear();
+        self.renderer.mark_dirty();
+    }
+
     fn submit_meshes(&mut self, world: &World) {
-        for (_, (mesh_handle, material_handle, transform)) in world
+        for (material_id, mesh) in &self.static_batches {
+            let Some(&material) = self.materials_map.get(material_id) else { continue; };
+            // Batches span the scene, their textures are always seen up close somewhere
+            self.texture_streaming.request(material_id, 0);
+            self.renderer
+                .submit(material, mesh.transformed(Transform::default()));
+        }
+        for (entity, (mesh_handle, material_handle, transform)) in world
             .query::<(&Handle<MeshAsset>, &Handle<Material>, &GlobalTransform)>()
+            .without::<&Water>()
+            .without::<&Refractive>()
+            .without::<&Inactive>()
+            .without::<&InactiveInHierarchy>()
             .iter()
         {
+            if self.batched_entities.contains(&entity) {
+                continue;
+            }
             let transform = transform.into();
             tracing::trace!(message="Submitting mesh", mesh=%mesh_handle.id(), material=%material_handle.id());
-            let mesh = self.meshes_map.get(mesh_handle.id()).unwrap();
-            let material = self.materials_map.get(material_handle.id()).unwrap();
-            self.renderer.submit_mesh_standard(
-                Rc::clone(&material),
-                Rc::clone(&mesh).transformed(transform),
-            );
+            if let Some(&radius) = self.mesh_radii.get(mesh_handle.id()) {
+                let coverage = self.screen_coverage(radius, &transform);
+                self.texture_streaming
+                    .request_coverage(material_handle.id(), coverage);
+            }
+            let mesh = self.entity_mesh(entity, mesh_handle);
+            let material = self.materials_map[material_handle.id()];
+            self.renderer.submit(material, mesh.transformed(transform));
+        }
+    }
+
+    fn submit_water(&mut self, world: &World) {
+        for (_, (mesh_handle, water, transform)) in world
+            .query::<(&Handle<MeshAsset>, &Water, &GlobalTransform)>()
+            .without::<&Inactive>()
+            .without::<&InactiveInHierarchy>()
+            .iter()
+        {
+            let Some(&mesh) = self.meshes_map.get(mesh_handle.id()) else { continue; };
+            self.renderer
+                .submit_water(mesh.transformed(transform.into()), (*water).into());
+        }
+    }
+
+    fn submit_refractive(&mut self, world: &World) {
+        for (_, (mesh_handle, refractive, transform)) in world
+            .query::<(&Handle<MeshAsset>, &Refractive, &GlobalTransform)>()
+            .without::<&Inactive>()
+            .without::<&InactiveInHierarchy>()
+            .iter()
+        {
+            let Some(&mesh) = self.meshes_map.get(mesh_handle.id()) else { continue; };
+            self.renderer
+                .submit_refractive(mesh.transformed(transform.into()), (*refractive).into());
+        }
+    }
+
+    fn submit_sprites(&mut self, world: &World) {
+        for (_, (image_handle, sprite, transform)) in world
+            .query::<(&Handle<Image>, &Sprite, &GlobalTransform)>()
+            .without::<&Inactive>()
+            .without::<&InactiveInHierarchy>()
+            .iter()
+        {
+            let params = sprite.params(&transform.0);
+            if let Some(&image) = self.atlas_images.get(image_handle.id()) {
+                self.renderer.submit_atlas_sprite(image, params);
+                continue;
+            }
+            let Some(&texture) = self.textures_map.get(image_handle.id()) else { continue; };
+            self.renderer.submit_sprite(texture, params);
+        }
+    }
+
+    fn submit_polylines(&mut self, world: &World) {
+        for (_, (polyline, transform)) in world
+            .query::<(&Polyline, &GlobalTransform)>()
+            .without::<&Inactive>()
+            .without::<&InactiveInHierarchy>()
+            .iter()
+        {
+            self.renderer
+                .submit_polyline(&polyline.world_points(&transform.0));
+        }
+        let color = Vec4::new(0.1, 0.8, 1., 1.);
+        for (_, navigation) in world.query::<&Navigation>().iter() {
+            if !navigation.show_outline {
+                continue;
+            }
+            for [a, b] in navigation.outline() {
+                self.renderer.submit_polyline(&[
+                    PolylinePoint::new(*a, 2., color),
+                    PolylinePoint::new(*b, 2., color),
+                ]);
+            }
+        }
+    }
+
+    fn submit_outlines(&mut self, world: &World) {
+        for mesh in self.marked_meshes::<Selected>(world) {
+            self.renderer.submit_outline(mesh);
+        }
+        for mesh in self.marked_meshes::<Hovered>(world) {
+            self.renderer.submit_hover_outline(mesh);
         }
     }
 
+    /// Meshes of the entities with the marker component, or whose ancestor has it.
+    fn marked_meshes<C: Component>(&self, world: &World) -> Vec<Transformed<MeshHandle>> {
+        let is_marked = |mut entity: Entity| loop {
+            if world.get::<&C>(entity).is_ok() {
+                break true;
+            }
+            match world.get::<&Parent>(entity) {
+                Ok(parent) => entity = parent.0,
+                Err(_) => break false,
+            }
+        };
+        if world.query::<&C>().iter().next().is_none() {
+            return vec![];
+        }
+        let mut meshes = vec![];
+        for (entity, (mesh_handle, transform)) in world
+            .query::<(&Handle<MeshAsset>, &GlobalTransform)>()
+            .without::<&Inactive>()
+            .without::<&InactiveInHierarchy>()
+            .iter()
+        {
+            if !is_marked(entity) {
+                continue;
+            }
+            let Some(&mesh) = self.meshes_map.get(mesh_handle.id()) else { continue; };
+            meshes.push(mesh.transformed(transform.into()));
+        }
+        meshes
+    }
+
     fn submit_meshes_custom<M: DrawMaterial>(&mut self, world: &World) {
-        for (_, (transform, material_handle, mesh_handle)) in world
+        for (entity, (transform, material_handle, mesh_handle)) in world
             .query::<(
                 &GlobalTransform,
                 &Handle<CustomMaterial<M>>,
                 &Handle<MeshAsset>,
             )>()
+            .without::<&Inactive>()
+            .without::<&InactiveInHierarchy>()
             .iter()
         {
             let transform = transform.into();
             tracing::trace!(message="Submitting mesh (custom material)", mesh=%mesh_handle.id(), material=%material_handle.id(), mat_name=%std::any::type_name::<M>());
-            let material = Rc::clone(&material_handle.read().0);
-            let mesh = self.meshes_map.get(mesh_handle.id()).unwrap();
-            self.renderer
-                .submit_mesh(material, Rc::clone(&mesh).transformed(transform));
+            let key = (TypeId::of::<M>(), material_handle.id().clone());
+            let material = match self.custom_materials_map.get(&key).copied() {
+                Some(material) if !material_handle.reloaded_global() => material,
+                previous => {
+                    if let Some(previous) = previous {
+                        self.renderer.unregister_material(previous);
+                    }
+                    let material = Rc::clone(&material_handle.read().0);
+                    let material = self.renderer.register_material::<M>(material);
+                    self.custom_materials_map.insert(key, material);
+                    material
+                }
+            };
+            let mesh = self.entity_mesh(entity, mesh_handle);
+            self.renderer.submit(material, mesh.transformed(transform));
         }
     }
 
-    fn handle_mesh_assets(&self, world: &World) -> Result<()> {
+    /// Mesh drawn for the entity: its own copy when it has morph weights, the shared one otherwise.
+    fn entity_mesh(&self, entity: Entity, handle: &Handle<MeshAsset>) -> MeshHandle {
+        match self.morphed_meshes.get(&entity) {
+            Some((_, mesh)) => *mesh,
+            None => self.meshes_map[handle.id()],
+        }
+    }
+
+    fn submit_morph_weights(&mut self, world: &World) {
+        for (&entity, (_, mesh)) in &self.morphed_meshes {
+            if let Some(weights) = morph_weights(world, entity) {
+                self.renderer.set_morph_weights(*mesh, &weights.0);
+            }
+        }
+    }
+
+    /// Returns whether any mesh was (re)loaded.
+    fn handle_mesh_assets(&mut self, world: &World) -> Result<bool> {
+        let mut changed = false;
         for (_, handle) in world.query::<&Handle<MeshAsset>>().iter() {
             if handle.reloaded_global() || !self.meshes_map.contains_key(handle.id()) {
-                let mesh = handle.read();
+                changed = true;
                 tracing::info!(message="Loading mesh", handle=%handle.id());
-                self.meshes_map.insert(
-                    handle.id().clone(),
-                    ThreadGuard::new(Rc::new(Mesh::new(
-                        mesh.vertices.iter().copied(),
-                        mesh.indices.iter().copied(),
-                    )?)),
-                );
+                let asset = handle.read();
+                self.mesh_radii
+                    .insert(handle.id().clone(), asset.bounding_radius());
+                let mesh = self.renderer.register_mesh(upload_mesh(&asset)?);
+                if let Some(previous) = self.meshes_map.insert(handle.id().clone(), mesh) {
+                    self.renderer.unregister_mesh(previous);
+                }
             }
         }
-        Ok(())
+        changed |= self.handle_morphed_meshes(world)?;
+        Ok(changed)
     }
 
-    fn handle_material_assets(&self, world: &World) -> Result<()> {
+    /// Give their own copy of their mesh to the entities with morph weights whose mesh has morph
+    /// targets, and drop the copies of the others. Returns whether any copy changed.
+    fn handle_morphed_meshes(&mut self, world: &World) -> Result<bool> {
+        let mut changed = false;
+        let mut morphed = HashSet::new();
+        for (entity, handle) in world.query::<&Handle<MeshAsset>>().iter() {
+            if handle.read().morph_targets.is_empty() || morph_weights(world, entity).is_none() {
+                continue;
+            }
+            morphed.insert(entity);
+            let up_to_date = matches!(
+                self.morphed_meshes.get(&entity),
+                Some((id, _)) if id == handle.id() && !handle.reloaded_global()
+            );
+            if up_to_date {
+                continue;
+            }
+            changed = true;
+            let mesh = self.renderer.register_mesh(upload_mesh(&handle.read())?);
+            let previous = self
+                .morphed_meshes
+                .insert(entity, (handle.id().clone(), mesh));
+            if let Some((_, previous)) = previous {
+                self.renderer.unregister_mesh(previous);
+            }
+        }
+        let removed = self
+            .morphed_meshes
+            .keys()
+            .filter(|entity| !morphed.contains(entity))
+            .copied()
+            .collect::<Vec<_>>();
+        for entity in removed {
+            changed = true;
+            let (_, mesh) = self.morphed_meshes.remove(&entity).unwrap();
+            self.renderer.unregister_mesh(mesh);
+        }
+        Ok(changed)
+    }
+
+    /// Returns whether any sprite image was (re)loaded.
+    fn handle_sprite_textures(&mut self, world: &World) -> Result<bool> {
+        let mut changed = false;
+        // Keep the images of the sprites drawn this frame from being evicted by the loaded ones
+        for (_, handle) in world
+            .query::<&Handle<Image>>()
+            .with::<&Sprite>()
+            .without::<&Inactive>()
+            .without::<&InactiveInHierarchy>()
+            .iter()
+        {
+            if let Some(&image) = self.atlas_images.get(handle.id()) {
+                self.renderer.sprite_atlas_mut().touch(image);
+            }
+        }
+        for (_, handle) in world.query::<&Handle<Image>>().with::<&Sprite>().iter() {
+            let in_atlas = self
+                .atlas_images
+                .get(handle.id())
+                .is_some_and(|image| self.renderer.sprite_atlas().contains(*image));
+            if !handle.reloaded_global()
+                && (in_atlas || self.textures_map.contains_key(handle.id()))
+            {
+                continue;
+            }
+            changed = true;
+            tracing::info!(message="Loading sprite image", handle=%handle.id());
+            if let Some(previous) = self.textures_map.remove(handle.id()) {
+                self.renderer.unregister_texture(previous);
+            }
+            if let Some(previous) = self.atlas_images.remove(handle.id()) {
+                self.renderer.sprite_atlas_mut().remove(previous);
+            }
+            let image = handle.read();
+            if let Some((size, pixels)) = image.atlas_pixels(MAX_ATLAS_IMAGE_SIZE) {
+                if let Some((id, evicted)) =
+                    self.renderer.sprite_atlas_mut().insert(size, &pixels)?
+                {
+                    self.atlas_images
+                        .retain(|_, image| !evicted.contains(image));
+                    self.atlas_images.insert(handle.id().clone(), id);
+                    continue;
+                }
+            }
+            let texture = self.renderer.register_texture(image.create_texture_rgba()?);
+            self.textures_map.insert(handle.id().clone(), texture);
+        }
+        Ok(changed)
+    }
+
+    /// Returns whether any material was (re)loaded.
+    fn handle_material_assets(&mut self, world: &World) -> Result<bool> {
+        let mut changed = false;
         for (_, handle) in world.query::<&Handle<Material>>().iter() {
             if handle.reloaded_global() || !self.materials_map.contains_key(handle.id()) {
+                changed = true;
                 tracing::info!(message="Loading material", handle=%handle.id());
                 let mat = handle.read();
-                let color_slot = if let Some(color) = &mat.color {
-                    Some(color.create_texture_rgb()?)
-                } else {
-                    None
-                };
-                let normal_map = if let Some(normal) = &mat.normal {
-                    Some(normal.create_texture_rgb()?)
-                } else {
-                    None
-                };
-                let rough_metal = if let Some(rough_metal) = &mat.rough_metal {
-                    Some(rough_metal.create_texture_rg()?)
-                } else {
-                    None
-                };
-                let emission = if let Some(emission) = &mat.emission {
-                    Some(emission.create_texture_rgb()?)
-                } else {
-                    None
+                // Downscaled from the previous version of the asset
+                self.streamed_materials.remove(handle.id());
+                let level = match mat.texture_memory() {
+                    Some((size, bytes)) => {
+                        self.texture_streaming
+                            .insert(handle.id().clone(), size, bytes)
+                    }
+                    None => {
+                        self.texture_streaming.remove(handle.id());
+                        0
+                    }
                 };
-                let mut inst =
-                    MaterialInstance::create(color_slot, normal_map, rough_metal, emission)?;
-                inst.update_uniforms(|uniforms| {
-                    uniforms.color_factor = mat.color_factor;
-                    uniforms.normal_amount = mat.normal_amount;
-                    uniforms.rough_metal_factor = mat.rough_metal_factor;
-                    uniforms.emission_factor = mat.emission_factor;
-                })?;
-                self.materials_map
-                    .insert(handle.id().clone(), ThreadGuard::new(Rc::new(inst)));
+                self.load_material(handle.id(), &mat, level)?;
+            }
+        }
+        Ok(changed)
+    }
+
+    /// Downscale the textures of the materials whose resident level changed since the last frame,
+    /// and upload the ones downscaled since. Returns whether any material changed.
+    fn handle_texture_streaming(&mut self, world: &World) -> Result<bool> {
+        let mut levels = HashMap::<_, _>::from_iter(self.texture_streaming.update());
+        for (_, handle) in world.query::<&Handle<Material>>().iter() {
+            if levels.is_empty() {
+                break;
+            }
+            let Some(level) = levels.remove(handle.id()) else { continue; };
+            tracing::debug!(message="Streaming material textures", handle=%handle.id(), level);
+            // Replaces the downscaling to a level which is not resident anymore
+            let resized = handle.read().mip_in_background(level);
+            self.streamed_materials
+                .insert(handle.id().clone(), (level, resized));
+        }
+
+        let finished = self
+            .streamed_materials
+            .iter()
+            .filter(|(_, (_, resized))| resized.is_finished())
+            .map(|(id, _)| id.clone())
+            .collect::<Vec<_>>();
+        for id in &finished {
+            let (level, resized) = self.streamed_materials.remove(id).unwrap();
+            let Ok(mat) = resized.join() else {
+                tracing::error!(message="Cannot downscale material textures", %id, level);
+                continue;
+            };
+            let textures = MaterialTextures::create(&mat)?;
+            let instance = self
+                .materials_map
+                .get(id)
+                .and_then(|&material| self.renderer.material_instance_mut(material));
+            match instance {
+                Some(instance) => {
+                    instance.color = textures.color;
+                    instance.normal_map = textures.normal_map;
+                    instance.roughness_metal = textures.rough_metal;
+                    instance.emission = textures.emission;
+                    instance.set_layer_maps(textures.clearcoat, textures.anisotropy)?;
+                }
+                None => self.load_material(id, &mat, 0)?,
             }
         }
+        Ok(!finished.is_empty())
+    }
+
+    /// Create the instance of the material with its textures from the mipmap level, replacing the
+    /// previous one.
+    fn load_material(&mut self, id: &SharedString, mat: &Material, level: u32) -> Result<()> {
+        let textures = MaterialTextures::create(&mat.mip(level))?;
+        let mut inst = MaterialInstance::create(
+            textures.color,
+            textures.normal_map,
+            textures.rough_metal,
+            textures.emission,
+        )?;
+        inst.set_layer_maps(textures.clearcoat, textures.anisotropy)?;
+        inst.update_uniforms(|uniforms| {
+            uniforms.color_factor = mat.color_factor;
+            uniforms.normal_amount = mat.normal_amount;
+            uniforms.rough_metal_factor = mat.rough_metal_factor;
+            uniforms.emission_factor = mat.emission_factor;
+            uniforms.emission_strength = mat.emission_strength;
+            uniforms.double_sided = mat.double_sided;
+            uniforms.clearcoat = mat.clearcoat;
+            uniforms.clearcoat_roughness = mat.clearcoat_roughness;
+            uniforms.anisotropy = mat.anisotropy;
+            uniforms.anisotropy_rotation = mat.anisotropy_rotation;
+            let uv_channel =
+                |image: &Option<Image>| image.as_ref().map_or(0, |image| image.uv_channel as u32);
+            uniforms.uv_channels = UVec4::new(
+                uv_channel(&mat.color),
+                uv_channel(&mat.normal),
+                uv_channel(&mat.rough_metal),
+                uv_channel(&mat.emission),
+            );
+            uniforms.layer_uv_channels = UVec2::new(
+                uv_channel(&mat.clearcoat_map),
+                uv_channel(&mat.anisotropy_map),
+            );
+        })?;
+        for (slot, sampler) in TextureSlot::ALL.into_iter().zip(mat.samplers.slots()) {
+            inst.set_sampler(slot, sampler.map(|sampler| sampler.state()));
+        }
+        let material = self.renderer.register_material_standard(inst);
+        if let Some(previous) = self.materials_map.insert(id.clone(), material) {
+            self.renderer.unregister_material(previous);
+        }
         Ok(())
     }
 
-    fn handle_lights(&mut self, world: &World) -> Result<()> {
-        let light_hash = self.hash_lights(world);
-        if light_hash != self.lights_hash {
-            tracing::info!(message="Rebuilding lights", hash=%light_hash);
-            self.lights_hash = light_hash;
-            let new_lights = self
-                .iter_active_lights(world)
-                .into_iter()
-                .inspect(|(transform, light)| {
-                    tracing::debug!(message = "Light", ?transform, ?light)
-                })
-                .map(|(transform, light)| {
-                    let color = light.power * light.color;
-                    match light.kind {
-                        LightKind::Directional => Light::Directional {
-                            color,
-                            dir: transform.rotation.mul_vec3(Vec3::NEG_Z),
-                        },
-                        LightKind::Point => Light::Point {
-                            color,
-                            position: transform.position,
-                        },
-                        LightKind::Ambient => Light::Ambient { color },
-                    }
-                });
+    /// Pixels across the screen covered by a mesh of `radius` around its origin, estimated from its
+    /// bounding sphere.
+    fn screen_coverage(&self, radius: f32, transform: &Transform) -> f32 {
+        let projection = &self.camera.projection;
+        let radius = radius * transform.scale.max_element();
+        let extent = match projection.orthographic {
+            Some(extent) => extent,
+            None => {
+                let distance = transform
+                    .position
+                    .distance(self.camera.transform.position)
+                    .max(radius)
+                    .max(projection.zrange.start);
+                2. * distance * (projection.fovy / 2.).tan()
+            }
+        };
+        2. * radius / extent * projection.height
+    }
+
+    /// Returns whether any material animation is playing.
+    fn handle_animated_materials(&self, dt: Duration, world: &World) -> Result<bool> {
+        let mut playing = false;
+        for (_, (animated, handle)) in world
+            .query::<(&mut AnimatedMaterial, &Handle<Material>)>()
+            .iter()
+        {
+            playing |= animated.playing;
+            animated.advance(dt);
+            let instance = self
+                .materials_map
+                .get(handle.id())
+                .and_then(|&material| self.renderer.material_instance(material));
+            if let Some(instance) = instance {
+                instance.update_uniforms(|uniforms| animated.apply(uniforms))?;
+            }
+        }
+        Ok(playing)
+    }
+
+    /// Sync the renderer lights with the active light components, only touching the lights of the
+    /// entities whose light or transform changed.
+    fn handle_lights(&mut self, world: &World, cache: AnyCache) -> Result<()> {
+        let lights = self.iter_active_lights(world);
+        let reloaded_cookies = lights
+            .iter()
+            .filter(|(_, (_, light))| {
+                !light.cookie.is_empty()
+                    && matches!(cache.load::<Image>(&light.cookie), Ok(image) if image.reloaded_global())
+            })
+            .map(|(entity, (_, light))| (*entity, light.cookie.clone()))
+            .collect::<HashMap<_, _>>();
+        for cookie in reloaded_cookies.values() {
+            self.cookie_textures.remove(cookie);
+        }
+        let mut query = Self::active_lights_query(world);
+        let changes = self.light_changes.update(world, &mut query, hash_light);
+        if changes.is_empty() && reloaded_cookies.is_empty() {
+            return Ok(());
+        }
+        tracing::debug!(message = "Updating lights", changes = changes.len());
+        let lights = lights.into_iter().collect::<HashMap<_, _>>();
+        for change in changes {
+            if change.kind == ChangeKind::Removed {
+                if let Some(handle) = self.lights.remove(&change.entity) {
+                    self.renderer.remove_light(handle)?;
+                }
+                continue;
+            }
+            let (transform, light) = &lights[&change.entity];
+            tracing::debug!(message = "Light", ?transform, ?light);
+            let handle = match self.lights.get(&change.entity) {
+                Some(&handle) => {
+                    self.renderer
+                        .update_light(handle, Self::renderer_light(transform, light))?;
+                    handle
+                }
+                None => {
+                    let handle = self
+                        .renderer
+                        .add_light(Self::renderer_light(transform, light))?;
+                    self.lights.insert(change.entity, handle);
+                    handle
+                }
+            };
+            let cookie = self.light_cookie(cache, transform, light);
+            self.renderer.set_light_cookie(handle, cookie);
             self.renderer
-                .set_light_buffer(GpuLight::create_buffer(new_lights)?);
+                .set_light_max_distance(handle, light.max_distance);
+        }
+        for entity in reloaded_cookies.into_keys() {
+            let Some(&handle) = self.lights.get(&entity) else { continue; };
+            let (transform, light) = &lights[&entity];
+            let cookie = self.light_cookie(cache, transform, light);
+            self.renderer.set_light_cookie(handle, cookie);
         }
+        // Only kept alive by this cache once no light uses them anymore
+        self.cookie_textures
+            .retain(|_, texture| Rc::strong_count(texture) > 1);
         Ok(())
     }
 
-    fn hash_lights(&self, world: &World) -> u64 {
-        let mut hasher = DefaultHasher::new();
-        for (transform, light) in self.iter_active_lights(world) {
-            transform.hash(&mut hasher);
-            light.hash(&mut hasher);
+    /// Cookie of the light, with the texture of its image uploaded on first use and shared with
+    /// the other lights using it. Errors are logged rather than returned, leaving the light without
+    /// a cookie.
+    fn light_cookie(
+        &mut self,
+        cache: AnyCache,
+        transform: &Transform,
+        light: &LightComponent,
+    ) -> Option<LightCookie> {
+        if light.cookie.is_empty() {
+            return None;
+        }
+        let projection = match light.kind {
+            LightKind::Directional => CookieProjection::Planar {
+                size: light.cookie_size,
+            },
+            LightKind::Point => CookieProjection::Cube,
+            _ => return None,
+        };
+        if let Some(texture) = self.cookie_textures.get(&light.cookie) {
+            return Some(LightCookie {
+                texture: texture.clone(),
+                projection,
+                rotation: transform.rotation,
+            });
+        }
+        let texture = cache
+            .load::<Image>(&light.cookie)
+            .map_err(eyre::Report::from)
+            .and_then(|image| image.read().create_texture_rgb());
+        match texture {
+            Ok(texture) => Some(LightCookie {
+                texture: self
+                    .cookie_textures
+                    .entry(light.cookie.clone())
+                    .or_insert(Rc::new(texture))
+                    .clone(),
+                projection,
+                rotation: transform.rotation,
+            }),
+            Err(err) => {
+                tracing::warn!("Cannot load light cookie {:?}: {:?}", light.cookie, err);
+                None
+            }
         }
-        hasher.finish()
     }
 
-    fn iter_active_lights(&self, world: &World) -> Vec<(Transform, LightComponent)> {
+    fn renderer_light(transform: &Transform, light: &LightComponent) -> Light {
+        let color = light.emitted_color();
+        match light.kind {
+            LightKind::Directional => Light::Directional {
+                color,
+                dir: transform.rotation.mul_vec3(Vec3::NEG_Z),
+            },
+            LightKind::Point => Light::Point {
+                color,
+                position: transform.position,
+            },
+            LightKind::Ambient => Light::Ambient { color },
+            LightKind::Rect | LightKind::Disk => Light::Area {
+                color,
+                position: transform.position,
+                rotation: transform.rotation,
+                shape: if light.kind == LightKind::Rect {
+                    AreaShape::Rect
+                } else {
+                    AreaShape::Disk
+                },
+                size: light.size * transform.scale.truncate(),
+                two_sided: light.two_sided,
+            },
+        }
+    }
+
+    fn handle_fog(&mut self, world: &World) {
+        let fog = world
+            .query::<&Fog>()
+            .with::<&Active>()
+            .without::<&Inactive>()
+            .without::<&InactiveInHierarchy>()
+            .iter()
+            .next()
+            .map(|(_, fog)| *fog);
+        self.renderer.set_fog(fog);
+    }
+
+    fn handle_clip_planes(&mut self, world: &World) {
+        let planes = world
+            .query::<(&ClipPlane, &GlobalTransform)>()
+            .without::<&Inactive>()
+            .without::<&InactiveInHierarchy>()
+            .iter()
+            .map(|(_, (plane, transform))| plane.equation(&transform.0))
+            .collect::<Vec<_>>();
+        self.renderer.set_clip_planes(&planes);
+    }
+
+    /// Keep the renderer's reflection probes in sync with the active `ReflectionProbe` components.
+    fn handle_reflection_probes(&mut self, world: &World) -> Result<()> {
+        let mut query = world
+            .query::<(&GlobalTransform, &mut ReflectionProbe)>()
+            .with::<&Active>()
+            .without::<&Inactive>()
+            .without::<&InactiveInHierarchy>();
+        let mut active = Vec::new();
+        for (entity, (transform, probe)) in query.iter() {
+            let params = probe.params(Transform::from(transform).position);
+            if let Some(&id) = self.reflection_probes.get(&entity) {
+                self.renderer.update_reflection_probe(id, params)?;
+                if std::mem::take(&mut probe.bake_requested) {
+                    self.renderer.bake_reflection_probe(id)?;
+                }
+            } else {
+                let id = self.renderer.add_reflection_probe(params)?;
+                probe.bake_requested = false;
+                self.reflection_probes.insert(entity, id);
+            }
+            active.push(entity);
+        }
+        let renderer = &mut self.renderer;
+        self.reflection_probes.retain(|entity, id| {
+            let keep = active.contains(entity);
+            if !keep {
+                renderer.remove_reflection_probe(*id);
+            }
+            keep
+        });
+        Ok(())
+    }
+
+    /// Request a bake of the active `IrradianceVolume` components when they are added or a bake is
+    /// requested, and stop lighting the scene with probes once none is left.
+    fn handle_irradiance_volumes(&mut self, world: &World) {
         let mut query = world
-            .query::<(&GlobalTransform, &LightComponent)>()
+            .query::<(&GlobalTransform, &mut IrradianceVolume)>()
             .with::<&Active>()
-            .without::<&Inactive>();
-        query.iter().map(|(_, (t, l))| (t.into(), *l)).collect()
+            .without::<&Inactive>()
+            .without::<&InactiveInHierarchy>();
+        let mut active = HashSet::new();
+        for (entity, (transform, volume)) in query.iter() {
+            let added = !self.irradiance_volumes.contains(&entity);
+            if std::mem::take(&mut volume.bake_requested) || added {
+                let grid = volume.grid(Transform::from(transform).position);
+                self.request_irradiance_bake(grid, volume.capture_resolution);
+            }
+            active.insert(entity);
+        }
+        if active.is_empty() && !self.irradiance_volumes.is_empty() {
+            self.renderer.clear_irradiance_probes();
+        }
+        self.irradiance_volumes = active;
+    }
+
+    fn active_lights_query(world: &World) -> QueryBorrow<'_, ActiveLights<'_>> {
+        world
+            .query::<(&GlobalTransform, &LightComponent, Option<&LightAnimation>)>()
+            .with::<&Active>()
+            .without::<&Inactive>()
+            .without::<&InactiveInHierarchy>()
+    }
+
+    fn iter_active_lights(&self, world: &World) -> Vec<(Entity, (Transform, LightComponent))> {
+        Self::active_lights_query(world)
+            .iter()
+            .map(|(e, (t, l, animation))| (e, (t.into(), animated_light(l, animation))))
+            .collect()
+    }
+}
+
+type ActiveLights<'a> = Without<
+    Without<
+        With<
+            (
+                &'a GlobalTransform,
+                &'a LightComponent,
+                Option<&'a LightAnimation>,
+            ),
+            &'a Active,
+        >,
+        &'a Inactive,
+    >,
+    &'a InactiveInHierarchy,
+>;
+
+/// Hash of what the renderer light is made of, for [`ChangeTracker`].
+fn hash_light((transform, light, animation): QueryItem<ActiveLights>, hasher: &mut DefaultHasher) {
+    let Transform {
+        position,
+        rotation,
+        scale,
+    } = transform.0;
+    let floats = position.to_array().into_iter().chain(rotation.to_array());
+    for f in floats.chain(scale.to_array()) {
+        f.to_bits().hash(hasher);
+    }
+    animated_light(light, animation).hash(hasher);
+}
+
+/// The light with its animation applied, if any.
+fn animated_light(light: &LightComponent, animation: Option<&LightAnimation>) -> LightComponent {
+    let mut light = light.clone();
+    if let Some(animation) = animation {
+        animation.apply(&mut light);
+    }
+    light
+}
+
+fn upload_mesh(asset: &MeshAsset) -> Result<Mesh> {
+    let mut mesh = Mesh::new(
+        asset.vertices.iter().copied(),
+        asset.indices.iter().copied(),
+    )?;
+    if !asset.morph_targets.is_empty() {
+        let morph = MorphTargets::new(asset.vertices.len(), &asset.morph_targets)?;
+        mesh.morph_targets = Some(morph);
+    }
+    Ok(mesh)
+}
+
+/// Morph weights of the entity, or of its closest ancestor with some, as glTF nodes hold the
+/// weights of the meshes of their primitives.
+fn morph_weights(world: &World, mut entity: Entity) -> Option<hecs::Ref<'_, MorphWeights>> {
+    loop {
+        if let Ok(weights) = world.get::<&MorphWeights>(entity) {
+            return Some(weights);
+        }
+        entity = world.get::<&Parent>(entity).ok()?.0;
+    }
+}
+
+/// Active meshes marked [`Static`] of the world, with their transform, grouped by the ID of their
+/// material.
+fn static_mesh_groups(
+    world: &World,
+) -> HashMap<SharedString, Vec<(Entity, AssetGuard<'static, MeshAsset>, Mat4)>> {
+    let mut query = world
+        .query::<(&Handle<MeshAsset>, &Handle<Material>, &GlobalTransform)>()
+        .with::<&Static>()
+        .without::<&Water>()
+        .without::<&Refractive>()
+        .without::<&Inactive>()
+        .without::<&InactiveInHierarchy>();
+    let mut groups = HashMap::<_, Vec<_>>::new();
+    for (entity, (mesh_handle, material_handle, transform)) in query.iter() {
+        groups
+            .entry(material_handle.id().clone())
+            .or_default()
+            .push((entity, mesh_handle.read(), transform.0.matrix()));
+    }
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::scene::Scene;
+
+    use super::*;
+
+    fn spawn_mesh(scene: &mut Scene, is_static: bool) -> Entity {
+        let cache = scene.asset_cache().as_any_cache();
+        let mesh = cache.get_or_insert("prim:cube", MeshAsset::cube());
+        let material = cache.get_or_insert("prim:material", Material::placeholder());
+        scene.with_world_mut(|world| {
+            let entity = world.spawn((mesh, material, GlobalTransform(Transform::default())));
+            if is_static {
+                world.insert_one(entity, Static).unwrap();
+            }
+            entity
+        })
+    }
+
+    fn batched_entities(scene: &Scene) -> Vec<Entity> {
+        scene.with_world(|world, _| {
+            static_mesh_groups(world)
+                .into_values()
+                .flatten()
+                .map(|(entity, ..)| entity)
+                .collect()
+        })
+    }
+
+    #[test]
+    fn switching_scenes_batches_only_the_new_scene() {
+        let dir = env!("CARGO_MANIFEST_DIR");
+        let mut previous = Scene::new(dir).unwrap();
+        let mut next = Scene::new(dir).unwrap();
+        let batched = spawn_mesh(&mut previous, true);
+        // Worlds hand out the same IDs: batches kept from the previous scene would hide this mesh
+        let reused = spawn_mesh(&mut next, false);
+        assert_eq!(batched, reused);
+
+        assert_eq!(batched_entities(&previous), vec![batched]);
+        assert!(batched_entities(&next).is_empty());
     }
 }
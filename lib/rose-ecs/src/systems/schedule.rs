@@ -0,0 +1,266 @@
+use std::{fmt, time::Duration};
+
+use assets_manager::AnyCache;
+use eyre::Result;
+use hecs::{CommandBuffer, World};
+
+use input::Input;
+use rose_core::utils::rng::EngineRng;
+
+use crate::systems::events::EventBus;
+
+/// Labels of the systems run by [`crate::CoreSystems`], to order custom systems against.
+pub mod labels {
+    /// Playback of transform and light animations, and entities following splines.
+    pub const ANIMATION: &str = "animation";
+    /// World shifted back towards the origin when the camera strays too far, see
+    /// [`crate::origin::OriginRebasing`].
+    pub const ORIGIN: &str = "origin";
+    /// Global transforms and activity propagated down the hierarchy.
+    pub const HIERARCHY: &str = "hierarchy";
+    /// Viewport camera following the active camera entity.
+    pub const CAMERA: &str = "camera";
+    /// Navmeshes generated from the scene geometry.
+    pub const NAVIGATION: &str = "navigation";
+    /// Light, mesh and probe uploads to the renderer.
+    pub const RENDER: &str = "render";
+}
+
+/// Coarse ordering of systems; systems of a stage all run before the ones of the next stage.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Stage {
+    PreUpdate,
+    #[default]
+    Update,
+    PostUpdate,
+    Render,
+}
+
+/// Data available to systems while they run.
+pub struct FrameContext<'a> {
+    pub world: &'a World,
+    /// Commands are applied to the world once every system has run.
+    pub commands: &'a mut CommandBuffer,
+    pub cache: AnyCache<'static>,
+    pub input: &'a Input,
+    /// Engine random number generator, seeded for reproducible runs.
+    pub rng: &'a mut EngineRng,
+    /// Events sent between systems, the UI and the application.
+    pub events: &'a mut EventBus,
+    pub dt: Duration,
+}
+
+type SystemFn = Box<dyn FnMut(&mut FrameContext) -> Result<()>>;
+
+/// A system to add to a [`Schedule`], along with where it runs.
+pub struct SystemDesc {
+    label: &'static str,
+    stage: Stage,
+    before: Vec<&'static str>,
+    after: Vec<&'static str>,
+    run: Option<SystemFn>,
+}
+
+impl fmt::Debug for SystemDesc {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SystemDesc")
+            .field("label", &self.label)
+            .field("stage", &self.stage)
+            .field("before", &self.before)
+            .field("after", &self.after)
+            .finish_non_exhaustive()
+    }
+}
+
+impl SystemDesc {
+    pub fn new(
+        label: &'static str,
+        run: impl 'static + FnMut(&mut FrameContext) -> Result<()>,
+    ) -> Self {
+        Self {
+            run: Some(Box::new(run)),
+            ..Self::builtin(label)
+        }
+    }
+
+    /// System without a function, which the owner of the schedule runs itself.
+    pub(crate) fn builtin(label: &'static str) -> Self {
+        Self {
+            label,
+            stage: Stage::default(),
+            before: vec![],
+            after: vec![],
+            run: None,
+        }
+    }
+
+    pub fn in_stage(mut self, stage: Stage) -> Self {
+        self.stage = stage;
+        self
+    }
+
+    pub fn before(mut self, label: &'static str) -> Self {
+        self.before.push(label);
+        self
+    }
+
+    pub fn after(mut self, label: &'static str) -> Self {
+        self.after.push(label);
+        self
+    }
+}
+
+/// Ordered list of systems run every frame. Systems run by stage, then following their `before`
+/// and `after` constraints, and otherwise in the order they were added.
+#[derive(Debug, Default)]
+pub struct Schedule {
+    systems: Vec<SystemDesc>,
+    order: Option<Vec<usize>>,
+}
+
+impl Schedule {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_system(&mut self, system: SystemDesc) -> &mut Self {
+        self.order = None;
+        self.systems.push(system);
+        self
+    }
+
+    /// Remove the system with the given label, returning whether it existed.
+    pub fn remove_system(&mut self, label: &str) -> bool {
+        let len = self.systems.len();
+        self.systems.retain(|s| s.label != label);
+        self.order = None;
+        len != self.systems.len()
+    }
+
+    /// Labels of the systems in the order they run.
+    pub fn labels(&mut self) -> Result<Vec<&'static str>> {
+        self.ensure_order()?;
+        let order = self.order.as_ref().unwrap();
+        Ok(order.iter().map(|ix| self.systems[*ix].label).collect())
+    }
+
+    /// Run every system in order. Systems without a function are passed to `builtin`.
+    pub fn run(
+        &mut self,
+        ctx: &mut FrameContext,
+        mut builtin: impl FnMut(&'static str, &mut FrameContext) -> Result<()>,
+    ) -> Result<()> {
+        self.ensure_order()?;
+        for ix in self.order.as_ref().unwrap() {
+            let system = &mut self.systems[*ix];
+            let _span = tracing::debug_span!("system", label = system.label).entered();
+            match &mut system.run {
+                Some(run) => run(ctx)?,
+                None => builtin(system.label, ctx)?,
+            }
+        }
+        Ok(())
+    }
+
+    fn ensure_order(&mut self) -> Result<()> {
+        if self.order.is_none() {
+            self.order = Some(self.compute_order()?);
+        }
+        Ok(())
+    }
+
+    /// Topological sort of the systems, picking the earliest stage and then the earliest added
+    /// system among the ones whose dependencies already ran.
+    fn compute_order(&self) -> Result<Vec<usize>> {
+        let index_of = |label: &str| self.systems.iter().position(|s| s.label == label);
+        let mut dependencies = vec![vec![]; self.systems.len()];
+        for (ix, system) in self.systems.iter().enumerate() {
+            for label in &system.after {
+                match index_of(label) {
+                    Some(dep) => dependencies[ix].push(dep),
+                    None => tracing::warn!(
+                        "System {:?} runs after unknown system {:?}",
+                        system.label,
+                        label
+                    ),
+                }
+            }
+            for label in &system.before {
+                match index_of(label) {
+                    Some(dependent) => dependencies[dependent].push(ix),
+                    None => tracing::warn!(
+                        "System {:?} runs before unknown system {:?}",
+                        system.label,
+                        label
+                    ),
+                }
+            }
+        }
+
+        let mut order = Vec::with_capacity(self.systems.len());
+        let mut done = vec![false; self.systems.len()];
+        while order.len() < self.systems.len() {
+            let next = (0..self.systems.len())
+                .filter(|ix| !done[*ix] && dependencies[*ix].iter().all(|dep| done[*dep]))
+                .min_by_key(|ix| (self.systems[*ix].stage, *ix));
+            let Some(next) = next else {
+                let remaining = (0..self.systems.len())
+                    .filter(|ix| !done[*ix])
+                    .map(|ix| self.systems[ix].label)
+                    .collect::<Vec<_>>();
+                eyre::bail!("Cyclic dependencies between systems {:?}", remaining);
+            };
+            if let Some(&previous) = order.last() {
+                let previous: &SystemDesc = &self.systems[previous];
+                eyre::ensure!(
+                    previous.stage <= self.systems[next].stage,
+                    "System {:?} must run before {:?}, which is in an earlier stage",
+                    previous.label,
+                    self.systems[next].label
+                );
+            }
+            done[next] = true;
+            order.push(next);
+        }
+        Ok(order)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn noop(_: &mut FrameContext) -> Result<()> {
+        Ok(())
+    }
+
+    #[test]
+    fn orders_by_stage_then_dependencies() {
+        let mut schedule = Schedule::new();
+        schedule
+            .add_system(SystemDesc::builtin("render").in_stage(Stage::Render))
+            .add_system(SystemDesc::new("b", noop))
+            .add_system(SystemDesc::new("a", noop).before("b"))
+            .add_system(SystemDesc::new("late", noop).in_stage(Stage::PostUpdate))
+            .add_system(SystemDesc::new("early", noop).in_stage(Stage::PreUpdate));
+        assert_eq!(
+            schedule.labels().unwrap(),
+            vec!["early", "a", "b", "late", "render"]
+        );
+    }
+
+    #[test]
+    fn rejects_cycles_and_backwards_stages() {
+        let mut schedule = Schedule::new();
+        schedule
+            .add_system(SystemDesc::new("a", noop).after("b"))
+            .add_system(SystemDesc::new("b", noop).after("a"));
+        assert!(schedule.labels().is_err());
+
+        let mut schedule = Schedule::new();
+        schedule
+            .add_system(SystemDesc::new("a", noop).in_stage(Stage::Render))
+            .add_system(SystemDesc::new("b", noop).after("a"));
+        assert!(schedule.labels().is_err());
+    }
+}
@@ -1,30 +1,161 @@
 use std::any::TypeId;
 use std::collections::HashMap;
 use std::f32::consts::PI;
+use std::hash::Hash;
 
 use assets_manager::Handle;
-use egui::{DragValue, Grid, RichText, Ui};
-use glam::{vec3, EulerRot, Quat, Vec3};
+use egui::{DragValue, Grid, Response, RichText, Ui};
+use glam::{vec3, EulerRot, Quat, Vec2, Vec3};
 use hecs::{CommandBuffer, Component, EntityRef};
 
 use rose_core::transform::Transform;
+use rose_renderer::splat::SplatMaterial;
 
+use crate::assets::{Image, Material, MeshAsset};
+use crate::systems::persistence::{PersistenceSystem, SerializableComponent};
+use crate::systems::render::CustomMaterial;
 use crate::NamedComponent;
 
 pub trait ComponentUi: NamedComponent + Component {
     fn ui(&mut self, ui: &mut Ui);
 }
 
-impl<A> ComponentUi for Handle<'static, A>
-where
-    Self: NamedComponent,
-{
+/// Component defined outside of the engine, editable in the inspector and saved with scenes. All
+/// of it is registered at once with [`UiSystem::register_inspectable`].
+///
+/// ```ignore
+/// #[derive(Default, Serialize, Deserialize)]
+/// struct Rotate(Vec3);
+///
+/// impl NamedComponent for Rotate {
+///     const NAME: &'static str = "Rotate";
+/// }
+///
+/// impl InspectUi for Rotate {
+///     fn inspect(&mut self, ui: &mut Ui) {
+///         inspect_fields(ui, "rotate", |fields| fields.field("Speed", &mut self.0));
+///     }
+/// }
+/// ```
+pub trait InspectUi: NamedComponent + SerializableComponent + Default {
+    fn inspect(&mut self, ui: &mut Ui);
+}
+
+/// Built-in components are inspected with their [`ComponentUi`].
+impl<C: ComponentUi + SerializableComponent + Default> InspectUi for C {
+    fn inspect(&mut self, ui: &mut Ui) {
+        self.ui(ui);
+    }
+}
+
+/// Value editable with a single widget, for use with [`Fields::field`].
+pub trait InspectValue {
+    fn inspect_value(&mut self, ui: &mut Ui) -> Response;
+}
+
+macro_rules! inspect_drag_value {
+    ($($t:ty),*) => {
+        $(
+        impl InspectValue for $t {
+            fn inspect_value(&mut self, ui: &mut Ui) -> Response {
+                ui.add(DragValue::new(self))
+            }
+        }
+        )*
+    };
+}
+
+inspect_drag_value!(f32, f64, i32, i64, u32, u64, usize);
+
+impl InspectValue for bool {
+    fn inspect_value(&mut self, ui: &mut Ui) -> Response {
+        ui.checkbox(self, "")
+    }
+}
+
+impl InspectValue for String {
+    fn inspect_value(&mut self, ui: &mut Ui) -> Response {
+        ui.text_edit_singleline(self)
+    }
+}
+
+impl InspectValue for Vec2 {
+    fn inspect_value(&mut self, ui: &mut Ui) -> Response {
+        ui.horizontal(|ui| {
+            ui.add(DragValue::new(&mut self.x).prefix("X: ").speed(0.01))
+                | ui.add(DragValue::new(&mut self.y).prefix("Y: ").speed(0.01))
+        })
+        .inner
+    }
+}
+
+impl InspectValue for Vec3 {
+    fn inspect_value(&mut self, ui: &mut Ui) -> Response {
+        ui.horizontal(|ui| {
+            ui.add(DragValue::new(&mut self.x).prefix("X: ").speed(0.01))
+                | ui.add(DragValue::new(&mut self.y).prefix("Y: ").speed(0.01))
+                | ui.add(DragValue::new(&mut self.z).prefix("Z: ").speed(0.01))
+        })
+        .inner
+    }
+}
+
+/// Labelled fields laid out in a grid by [`inspect_fields`].
+pub struct Fields<'ui> {
+    pub ui: &'ui mut Ui,
+}
+
+impl<'ui> Fields<'ui> {
+    pub fn field(&mut self, label: &str, value: &mut impl InspectValue) -> Response {
+        let label = self.ui.label(label).id;
+        let response = value.inspect_value(self.ui).labelled_by(label);
+        self.ui.end_row();
+        response
+    }
+}
+
+/// Show labelled fields in a two-column grid.
+pub fn inspect_fields<R>(
+    ui: &mut Ui,
+    id_source: impl Hash,
+    add_fields: impl FnOnce(&mut Fields) -> R,
+) -> R {
+    Grid::new(id_source)
+        .num_columns(2)
+        .show(ui, |ui| add_fields(&mut Fields { ui }))
+        .inner
+}
+
+fn handle_ui<A>(ui: &mut Ui, handle: &Handle<'static, A>) {
+    Grid::new("material-handle").num_columns(2).show(ui, |ui| {
+        let handle_label = ui.label("Handle").id;
+        ui.label(RichText::new(handle.id().as_str()).strong().monospace())
+            .labelled_by(handle_label);
+    });
+}
+
+impl ComponentUi for Handle<'static, MeshAsset> {
+    fn ui(&mut self, ui: &mut Ui) {
+        handle_ui(ui, self);
+    }
+}
+
+impl ComponentUi for Handle<'static, Material> {
+    fn ui(&mut self, ui: &mut Ui) {
+        handle_ui(ui, self);
+    }
+}
+
+impl ComponentUi for Handle<'static, Image> {
+    fn ui(&mut self, ui: &mut Ui) {
+        handle_ui(ui, self);
+    }
+}
+
+impl ComponentUi for Handle<'static, CustomMaterial<SplatMaterial>> {
     fn ui(&mut self, ui: &mut Ui) {
-        Grid::new("material-handle").num_columns(2).show(ui, |ui| {
-            let handle_label = ui.label("Handle").id;
-            ui.label(RichText::new(self.id().as_str()).strong().monospace())
-                .labelled_by(handle_label);
-        });
+        handle_ui(ui, self);
+        self.read().ui(ui);
     }
 }
 
@@ -85,6 +216,7 @@ impl ComponentUi for Transform {
 
 pub struct UiSystem {
     component_ui_registry: HashMap<TypeId, DynComponentUi>,
+    component_names: HashMap<TypeId, &'static str>,
     spawner_registry: Vec<DynInsertComponent>,
 }
 
@@ -92,6 +224,7 @@ impl UiSystem {
     pub fn new() -> Self {
         Self {
             component_ui_registry: HashMap::new(),
+            component_names: HashMap::new(),
             spawner_registry: vec![],
         }
     }
@@ -99,14 +232,35 @@ impl UiSystem {
     pub fn register_component<C: ComponentUi>(&mut self) -> &mut Self {
         self.component_ui_registry
             .insert(TypeId::of::<C>(), &component_ui::<C>);
+        self.component_names.insert(TypeId::of::<C>(), C::NAME);
+        self
+    }
+
+    /// Register the inspector UI of a component, allow adding it to entities, and register it in
+    /// `persistence` to save it with scenes.
+    pub fn register_inspectable<C: InspectUi>(
+        &mut self,
+        persistence: &mut PersistenceSystem,
+    ) -> &mut Self {
+        self.component_ui_registry
+            .insert(TypeId::of::<C>(), &inspect_ui::<C>);
+        self.component_names.insert(TypeId::of::<C>(), C::NAME);
+        self.spawner_registry.push(&insert_component::<C>);
+        persistence.register_component::<C>();
         self
     }
 
     pub fn register_spawn<C: NamedComponent + Default>(&mut self) -> &mut Self {
+        self.component_names.insert(TypeId::of::<C>(), C::NAME);
         self.spawner_registry.push(&insert_component::<C>);
         self
     }
 
+    /// Name of a component type registered in this system.
+    pub fn component_name(&self, type_id: TypeId) -> Option<&'static str> {
+        self.component_names.get(&type_id).copied()
+    }
+
     pub fn components_ui(&self, ui: &mut Ui, entity: EntityRef, cmdbuf: &mut CommandBuffer) {
         for cmp_ui in self.component_ui_registry.values() {
             cmp_ui(ui, entity, cmdbuf);
@@ -126,8 +280,21 @@ impl UiSystem {
 }
 
 pub fn component_ui<T: ComponentUi>(ui: &mut Ui, entity: EntityRef, cmd: &mut CommandBuffer) {
+    component_section::<T>(ui, entity, cmd, T::ui);
+}
+
+pub fn inspect_ui<T: InspectUi>(ui: &mut Ui, entity: EntityRef, cmd: &mut CommandBuffer) {
+    component_section::<T>(ui, entity, cmd, T::inspect);
+}
+
+fn component_section<T: NamedComponent>(
+    ui: &mut Ui,
+    entity: EntityRef,
+    cmd: &mut CommandBuffer,
+    body: impl FnOnce(&mut T, &mut Ui),
+) {
     if let Some(mut component) = entity.get::<&mut T>() {
-        ui.collapsing(T::NAME, |ui| component.ui(ui))
+        ui.collapsing(T::NAME, |ui| body(&mut component, ui))
             .header_response
             .context_menu(|ui| {
                 if ui.small_button("Remove").clicked() {
@@ -34,6 +34,10 @@ impl<T> CircBuffer<T> {
         self.storage.capacity()
     }
 
+    pub fn clear(&mut self) {
+        self.storage.clear();
+    }
+
     pub fn iter(&self) -> impl Iterator<Item = &T> {
         self.storage.iter()
     }
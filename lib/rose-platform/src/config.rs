@@ -0,0 +1,291 @@
+//! Launch configuration of the engine, parsed from the command line and the environment.
+//!
+//! Command line options take precedence over the environment variables:
+//!
+//! | Option                  | Variable           | Effect                                      |
+//! |-------------------------|--------------------|---------------------------------------------|
+//! | `--size WxH`            | `ROSE_WINDOW_SIZE` | Initial inner size of the window            |
+//! | `--vsync`/`--no-vsync`  | `ROSE_VSYNC`       | Synchronize presentation with the display   |
+//! | `--fullscreen`          | `ROSE_FULLSCREEN`  | Start in borderless fullscreen              |
+//! | `--asset-root PATH`     | `ROSE_ASSET_ROOT`  | Directory holding `res/` and the assets     |
+//! | `--config-dir PATH`     | `ROSE_CONFIG_DIR`  | Directory the user preferences are saved to |
+//! | `--scene PATH`, `PATH`  | `ROSE_SCENE`       | Scene (or file) for the application to load |
+//! | `--headless`            | `ROSE_HEADLESS`    | Hidden window, frames rendered back-to-back |
+//! | `--ui-scale SCALE`      | `ROSE_UI_SCALE`    | Scale of the UI on top of the display's     |
+//! | `--background-fps N\|off` | `ROSE_BACKGROUND_FPS` | Frame rate while unfocused, 10 by default |
+//! | `--log stdout\|none\|PATH` | `ROSE_LOG`        | Destination of the log, stdout by default   |
+//! | `--log-json`            | `ROSE_LOG_JSON`    | Write the log as JSON lines                 |
+//!
+//! The scene can be given as the first positional argument, before any application argument.
+//! Other arguments are left in [`LaunchConfig::args`] for the application to parse.
+
+use std::{ffi::OsString, path::PathBuf};
+
+use eyre::{eyre, Context, Result};
+use winit::dpi::PhysicalSize;
+
+/// Destination of the engine log.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LogTarget {
+    Stdout,
+    File(PathBuf),
+    None,
+}
+
+impl From<&str> for LogTarget {
+    fn from(s: &str) -> Self {
+        match s {
+            "stdout" => Self::Stdout,
+            "none" => Self::None,
+            path => Self::File(path.into()),
+        }
+    }
+}
+
+/// Logging set up by [`crate::run`]; events are filtered with `RUST_LOG`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoggingConfig {
+    pub target: LogTarget,
+    pub json: bool,
+    /// Install the engine subscriber and error report handler. Disable to install your own before
+    /// running the application, optionally with [`crate::log_capture::CaptureLayer`].
+    pub install: bool,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            target: LogTarget::Stdout,
+            json: false,
+            install: true,
+        }
+    }
+}
+
+/// Launch configuration, given to [`crate::Application::new`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LaunchConfig {
+    /// Initial inner size of the window, overriding the one set by
+    /// [`crate::Application::window_features`].
+    pub window_size: Option<PhysicalSize<u32>>,
+    pub vsync: bool,
+    pub fullscreen: bool,
+    /// Directory holding the engine resources (`res/`) and the assets.
+    pub asset_root: PathBuf,
+    /// Per-user directory the preferences, such as the display mode, are saved to, or none to not
+    /// save them.
+    pub config_dir: Option<PathBuf>,
+    /// Scene, or other file, the application should load on startup.
+    pub scene: Option<PathBuf>,
+    /// Run without showing the window, rendering frames as fast as possible.
+    pub headless: bool,
+    /// Scale of the UI, multiplying the scale factor of the display.
+    pub ui_scale: f32,
+    /// Frame rate to throttle rendering to while the window is unfocused, if any.
+    pub background_fps: Option<f32>,
+    pub logging: LoggingConfig,
+    /// Arguments not recognized as engine options.
+    pub args: Vec<String>,
+}
+
+impl Default for LaunchConfig {
+    fn default() -> Self {
+        Self {
+            window_size: None,
+            vsync: true,
+            fullscreen: false,
+            asset_root: Self::default_asset_root(),
+            config_dir: Self::default_config_dir(),
+            scene: None,
+            headless: false,
+            ui_scale: 1.,
+            background_fps: Some(10.),
+            logging: LoggingConfig::default(),
+            args: vec![],
+        }
+    }
+}
+
+impl LaunchConfig {
+    /// `CARGO_PROJECT_DIR` if set, else the working directory.
+    pub fn default_asset_root() -> PathBuf {
+        std::env::var("CARGO_PROJECT_DIR")
+            .map(PathBuf::from)
+            .or_else(|_| std::env::current_dir())
+            .unwrap()
+    }
+
+    /// Directory named after the executable in the configuration directory of the user, e.g.
+    /// `~/.config/rose/<executable>` on Linux.
+    pub fn default_config_dir() -> Option<PathBuf> {
+        let executable = std::env::current_exe().ok()?;
+        let name = executable.file_stem()?;
+        Some(dirs::config_dir()?.join("rose").join(name))
+    }
+
+    /// Parse the configuration from the process environment and command line.
+    pub fn from_env() -> Result<Self> {
+        Self::parse(
+            |name| std::env::var_os(name),
+            std::env::args().skip(1).collect(),
+        )
+    }
+
+    /// Parse the configuration from the given variable lookup and arguments, without the program
+    /// name.
+    pub fn parse(var: impl Fn(&str) -> Option<OsString>, args: Vec<String>) -> Result<Self> {
+        let mut config = Self::default();
+        let var = |name: &str| var(name).map(|value| value.to_string_lossy().into_owned());
+        if let Some(size) = var("ROSE_WINDOW_SIZE") {
+            config.window_size = Some(parse_size(&size).context("Parsing ROSE_WINDOW_SIZE")?);
+        }
+        if let Some(vsync) = var("ROSE_VSYNC") {
+            config.vsync = parse_bool(&vsync).context("Parsing ROSE_VSYNC")?;
+        }
+        if let Some(fullscreen) = var("ROSE_FULLSCREEN") {
+            config.fullscreen = parse_bool(&fullscreen).context("Parsing ROSE_FULLSCREEN")?;
+        }
+        if let Some(asset_root) = var("ROSE_ASSET_ROOT") {
+            config.asset_root = asset_root.into();
+        }
+        if let Some(config_dir) = var("ROSE_CONFIG_DIR") {
+            config.config_dir = Some(config_dir.into());
+        }
+        config.scene = var("ROSE_SCENE").map(PathBuf::from);
+        if let Some(headless) = var("ROSE_HEADLESS") {
+            config.headless = parse_bool(&headless).context("Parsing ROSE_HEADLESS")?;
+        }
+        if let Some(ui_scale) = var("ROSE_UI_SCALE") {
+            config.ui_scale = parse_scale(&ui_scale).context("Parsing ROSE_UI_SCALE")?;
+        }
+        if let Some(fps) = var("ROSE_BACKGROUND_FPS") {
+            config.background_fps = parse_fps(&fps).context("Parsing ROSE_BACKGROUND_FPS")?;
+        }
+        if let Some(target) = var("ROSE_LOG") {
+            config.logging.target = target.as_str().into();
+        }
+        if let Some(json) = var("ROSE_LOG_JSON") {
+            config.logging.json = parse_bool(&json).context("Parsing ROSE_LOG_JSON")?;
+        }
+
+        let mut args = args.into_iter();
+        // The scene can be given positionally, as long as it comes before application arguments
+        let mut expect_scene = true;
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| eyre!("Missing value for {}", arg))
+            };
+            match arg.as_str() {
+                "--size" => config.window_size = Some(parse_size(&value()?)?),
+                "--vsync" => config.vsync = true,
+                "--no-vsync" => config.vsync = false,
+                "--fullscreen" => config.fullscreen = true,
+                "--asset-root" => config.asset_root = value()?.into(),
+                "--config-dir" => config.config_dir = Some(value()?.into()),
+                "--scene" => {
+                    config.scene = Some(value()?.into());
+                    expect_scene = false;
+                }
+                "--headless" => config.headless = true,
+                "--ui-scale" => config.ui_scale = parse_scale(&value()?)?,
+                "--background-fps" => config.background_fps = parse_fps(&value()?)?,
+                "--log" => config.logging.target = value()?.as_str().into(),
+                "--log-json" => config.logging.json = true,
+                _ if expect_scene && !arg.starts_with('-') => {
+                    config.scene = Some(arg.into());
+                    expect_scene = false;
+                }
+                _ => {
+                    config.args.push(arg);
+                    expect_scene = false;
+                }
+            }
+        }
+        Ok(config)
+    }
+}
+
+fn parse_size(s: &str) -> Result<PhysicalSize<u32>> {
+    let (width, height) = s
+        .split_once('x')
+        .ok_or_else(|| eyre!("Expected a size as WIDTHxHEIGHT, got {:?}", s))?;
+    Ok(PhysicalSize::new(
+        width.trim().parse()?,
+        height.trim().parse()?,
+    ))
+}
+
+fn parse_scale(s: &str) -> Result<f32> {
+    match s.trim().parse::<f32>()? {
+        scale if scale > 0. && scale.is_finite() => Ok(scale),
+        scale => Err(eyre!("Expected a positive scale, got {}", scale)),
+    }
+}
+
+fn parse_fps(s: &str) -> Result<Option<f32>> {
+    match s.trim() {
+        "off" | "none" => Ok(None),
+        fps => parse_scale(fps)
+            .map(Some)
+            .with_context(|| format!("Expected a frame rate or \"off\", got {:?}", s)),
+    }
+}
+
+fn parse_bool(s: &str) -> Result<bool> {
+    match s.to_ascii_lowercase().as_str() {
+        "1" | "true" | "yes" | "on" => Ok(true),
+        "0" | "false" | "no" | "off" => Ok(false),
+        _ => Err(eyre!("Expected a boolean, got {:?}", s)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn args_override_env() {
+        let env = |name: &str| -> Option<OsString> {
+            match name {
+                "ROSE_WINDOW_SIZE" => Some("800x600".into()),
+                "ROSE_VSYNC" => Some("off".into()),
+                "ROSE_SCENE" => Some("env.scene".into()),
+                "ROSE_LOG" => Some("none".into()),
+                _ => None,
+            }
+        };
+        let config = LaunchConfig::parse(
+            env,
+            args(&["--size", "1280x720", "main.scene", "--frames", "10"]),
+        )
+        .unwrap();
+        assert_eq!(config.window_size, Some(PhysicalSize::new(1280, 720)));
+        assert!(!config.vsync);
+        assert_eq!(config.scene, Some(PathBuf::from("main.scene")));
+        assert_eq!(config.logging.target, LogTarget::None);
+        assert_eq!(config.args, args(&["--frames", "10"]));
+    }
+
+    #[test]
+    fn invalid_size() {
+        assert!(LaunchConfig::parse(|_| None, args(&["--size", "big"])).is_err());
+    }
+
+    #[test]
+    fn background_fps() {
+        let parse = |fps| LaunchConfig::parse(|_| None, args(&["--background-fps", fps]));
+        assert_eq!(parse("off").unwrap().background_fps, None);
+        assert_eq!(parse("5").unwrap().background_fps, Some(5.));
+        assert!(parse("-1").is_err());
+    }
+
+    #[test]
+    fn invalid_ui_scale() {
+        assert!(LaunchConfig::parse(|_| None, args(&["--ui-scale", "0"])).is_err());
+    }
+}
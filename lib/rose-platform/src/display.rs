@@ -0,0 +1,314 @@
+//! Display modes of the window: windowed, borderless fullscreen or exclusive fullscreen with a
+//! chosen video mode, on any monitor.
+//!
+//! The mode chosen with F11 or [`crate::RenderContext::set_display_mode`] is saved to
+//! [`PREFERENCE_FILE`] in the configuration directory of the user, and restored on the next run
+//! unless fullscreen is forced by the [`crate::config::LaunchConfig`]. Monitors are identified by
+//! name, falling back to the current monitor when the saved one is not connected.
+
+use std::path::{Path, PathBuf};
+
+use eyre::{Context, Result};
+use serde::{Deserialize, Serialize};
+use winit::{
+    dpi::{PhysicalPosition, PhysicalSize},
+    monitor::{MonitorHandle, VideoMode},
+    window::{Fullscreen, Window},
+};
+
+/// File name of the saved display preference, in [`crate::config::LaunchConfig::config_dir`].
+pub const PREFERENCE_FILE: &str = "display.json";
+
+/// Video mode of a monitor, usable in exclusive fullscreen.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct VideoModeInfo {
+    pub width: u32,
+    pub height: u32,
+    pub refresh_rate_millihertz: u32,
+    pub bit_depth: u16,
+}
+
+impl VideoModeInfo {
+    fn of(mode: &VideoMode) -> Self {
+        let size = mode.size();
+        Self {
+            width: size.width,
+            height: size.height,
+            refresh_rate_millihertz: mode.refresh_rate_millihertz(),
+            bit_depth: mode.bit_depth(),
+        }
+    }
+
+    pub fn size(&self) -> PhysicalSize<u32> {
+        PhysicalSize::new(self.width, self.height)
+    }
+
+    pub fn refresh_rate(&self) -> f32 {
+        self.refresh_rate_millihertz as f32 / 1e3
+    }
+}
+
+impl std::fmt::Display for VideoModeInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}x{} @ {:.2} Hz, {} bpp",
+            self.width,
+            self.height,
+            self.refresh_rate(),
+            self.bit_depth
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MonitorInfo {
+    pub name: Option<String>,
+    pub size: PhysicalSize<u32>,
+    pub position: PhysicalPosition<i32>,
+    pub scale_factor: f64,
+    /// Video modes, from the largest and fastest.
+    pub video_modes: Vec<VideoModeInfo>,
+}
+
+impl MonitorInfo {
+    fn of(monitor: &MonitorHandle) -> Self {
+        let mut video_modes = monitor
+            .video_modes()
+            .map(|mode| VideoModeInfo::of(&mode))
+            .collect::<Vec<_>>();
+        video_modes.sort_by_key(|mode| {
+            std::cmp::Reverse((
+                mode.width * mode.height,
+                mode.refresh_rate_millihertz,
+                mode.bit_depth,
+            ))
+        });
+        video_modes.dedup();
+        Self {
+            name: monitor.name(),
+            size: monitor.size(),
+            position: monitor.position(),
+            scale_factor: monitor.scale_factor(),
+            video_modes,
+        }
+    }
+}
+
+/// Monitors connected to the system.
+pub fn monitors(window: &Window) -> Vec<MonitorInfo> {
+    window
+        .available_monitors()
+        .map(|monitor| MonitorInfo::of(&monitor))
+        .collect()
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum DisplayMode {
+    #[default]
+    Windowed,
+    /// Borderless window covering the named monitor, or the current one.
+    Borderless { monitor: Option<String> },
+    /// Exclusive fullscreen on the named monitor, or the current one, switching it to the closest
+    /// video mode.
+    Exclusive {
+        monitor: Option<String>,
+        video_mode: VideoModeInfo,
+    },
+}
+
+impl DisplayMode {
+    pub fn is_fullscreen(&self) -> bool {
+        !matches!(self, Self::Windowed)
+    }
+
+    /// Fullscreen setting of the window for the mode. Exclusive fullscreen falls back to
+    /// borderless when the monitor has no video mode of that size.
+    pub fn fullscreen(&self, window: &Window) -> Option<Fullscreen> {
+        match self {
+            Self::Windowed => None,
+            Self::Borderless { monitor } => Some(Fullscreen::Borderless(find_monitor(
+                window,
+                monitor.as_deref(),
+            ))),
+            Self::Exclusive {
+                monitor,
+                video_mode,
+            } => {
+                let handle = find_monitor(window, monitor.as_deref())?;
+                let modes = handle.video_modes().collect::<Vec<_>>();
+                let infos = modes.iter().map(VideoModeInfo::of).collect::<Vec<_>>();
+                match closest_video_mode(&infos, video_mode) {
+                    Some(ix) => Some(Fullscreen::Exclusive(modes[ix].clone())),
+                    None => {
+                        tracing::warn!(
+                            "Monitor has no {}x{} video mode, using borderless fullscreen",
+                            video_mode.width,
+                            video_mode.height
+                        );
+                        Some(Fullscreen::Borderless(Some(handle)))
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Monitor with the given name, or the current monitor of the window.
+fn find_monitor(window: &Window, name: Option<&str>) -> Option<MonitorHandle> {
+    if let Some(name) = name {
+        let found = window
+            .available_monitors()
+            .find(|monitor| monitor.name().as_deref() == Some(name));
+        if found.is_some() {
+            return found;
+        }
+        tracing::warn!("Monitor {:?} not found, using the current monitor", name);
+    }
+    window.current_monitor()
+}
+
+/// Index of the video mode of the same size as the wanted one, with the closest refresh rate and
+/// then the closest bit depth.
+fn closest_video_mode(modes: &[VideoModeInfo], wanted: &VideoModeInfo) -> Option<usize> {
+    modes
+        .iter()
+        .enumerate()
+        .filter(|(_, mode)| mode.size() == wanted.size())
+        .min_by_key(|(_, mode)| {
+            (
+                mode.refresh_rate_millihertz
+                    .abs_diff(wanted.refresh_rate_millihertz),
+                mode.bit_depth.abs_diff(wanted.bit_depth),
+            )
+        })
+        .map(|(ix, _)| ix)
+}
+
+/// Display mode chosen by the user, saved whenever it changes.
+#[derive(Debug, Default)]
+pub struct DisplayPreference {
+    mode: DisplayMode,
+    /// Last fullscreen mode, which F11 switches back to.
+    last_fullscreen: Option<DisplayMode>,
+    path: Option<PathBuf>,
+}
+
+impl DisplayPreference {
+    /// Load the preference saved at the path, falling back to windowed if there is none.
+    pub fn load(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        let mode = match read_mode(&path) {
+            Ok(mode) => mode.unwrap_or_default(),
+            Err(err) => {
+                tracing::warn!("Cannot load display preference: {:#}", err);
+                DisplayMode::default()
+            }
+        };
+        Self {
+            last_fullscreen: Some(mode.clone()).filter(DisplayMode::is_fullscreen),
+            mode,
+            path: Some(path),
+        }
+    }
+
+    pub fn mode(&self) -> &DisplayMode {
+        &self.mode
+    }
+
+    pub fn apply(&self, window: &Window) {
+        window.set_fullscreen(self.mode.fullscreen(window));
+    }
+
+    /// Switch the window to the mode and save it.
+    pub fn set(&mut self, window: &Window, mode: DisplayMode) {
+        if mode.is_fullscreen() {
+            self.last_fullscreen = Some(mode.clone());
+        }
+        self.mode = mode;
+        self.apply(window);
+        if let Some(path) = &self.path {
+            if let Err(err) = write_mode(path, &self.mode) {
+                tracing::error!("Cannot save display preference: {:#}", err);
+            }
+        }
+    }
+
+    /// Switch between windowed and the last fullscreen mode, borderless by default.
+    pub fn toggle_fullscreen(&mut self, window: &Window) {
+        let mode = if window.fullscreen().is_some() {
+            DisplayMode::Windowed
+        } else {
+            self.last_fullscreen
+                .clone()
+                .unwrap_or(DisplayMode::Borderless { monitor: None })
+        };
+        self.set(window, mode);
+    }
+}
+
+fn read_mode(path: &Path) -> Result<Option<DisplayMode>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let contents =
+        std::fs::read_to_string(path).with_context(|| format!("Reading {}", path.display()))?;
+    let mode =
+        serde_json::from_str(&contents).with_context(|| format!("Parsing {}", path.display()))?;
+    Ok(Some(mode))
+}
+
+fn write_mode(path: &Path, mode: &DisplayMode) -> Result<()> {
+    let contents = serde_json::to_string_pretty(mode)?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).with_context(|| format!("Creating {}", dir.display()))?;
+    }
+    std::fs::write(path, contents).with_context(|| format!("Writing {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mode(width: u32, height: u32, refresh_rate: u32, bit_depth: u16) -> VideoModeInfo {
+        VideoModeInfo {
+            width,
+            height,
+            refresh_rate_millihertz: refresh_rate * 1000,
+            bit_depth,
+        }
+    }
+
+    #[test]
+    fn picks_closest_video_mode_of_the_same_size() {
+        let modes = [
+            mode(2560, 1440, 144, 32),
+            mode(1920, 1080, 144, 32),
+            mode(1920, 1080, 60, 24),
+            mode(1920, 1080, 60, 32),
+        ];
+        assert_eq!(
+            closest_video_mode(&modes, &mode(1920, 1080, 59, 32)),
+            Some(3)
+        );
+        assert_eq!(
+            closest_video_mode(&modes, &mode(1920, 1080, 120, 32)),
+            Some(1)
+        );
+        assert_eq!(closest_video_mode(&modes, &mode(1280, 720, 60, 32)), None);
+    }
+
+    #[test]
+    fn display_mode_roundtrips() {
+        let exclusive = DisplayMode::Exclusive {
+            monitor: Some("DP-1".to_string()),
+            video_mode: mode(1920, 1080, 60, 32),
+        };
+        let json = serde_json::to_string(&exclusive).unwrap();
+        assert_eq!(
+            serde_json::from_str::<DisplayMode>(&json).unwrap(),
+            exclusive
+        );
+    }
+}
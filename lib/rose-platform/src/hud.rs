@@ -0,0 +1,268 @@
+//! Heads-up display of the frame rate, recent frame times and statistics provided by the
+//! application, toggled with F3.
+//!
+//! Text is rasterized on the CPU with a built-in 5x7 bitmap font, so that the display works
+//! without the `ui` feature. The image is only redrawn a few times per second, and is blitted over
+//! the top-left corner of the window.
+
+use std::time::{Duration, Instant};
+
+use eyre::{Context, Result};
+use image::{DynamicImage, Rgba, RgbaImage};
+use winit::dpi::PhysicalSize;
+
+use rose_core::utils::draw_counters::CountBind;
+use rose_core::{screen_draw::ScreenDraw, utils::reload_watcher::ReloadWatcher};
+use violette::{
+    framebuffer::{Blend, BlendFunction, Framebuffer},
+    program::UniformLocation,
+    texture::{SampleMode, Texture},
+};
+
+use crate::circbuffer::CircBuffer;
+use crate::RenderStats;
+
+/// Interval between redraws of the display.
+const UPDATE_INTERVAL: Duration = Duration::from_millis(250);
+/// Frames shown in the frame time graph, one pixel column each.
+const GRAPH_FRAMES: usize = 120;
+const GRAPH_HEIGHT: u32 = 32;
+/// Frame time at the top of the graph, in milliseconds.
+const GRAPH_MAX_MS: f32 = 50.;
+const GLYPH_WIDTH: u32 = 5;
+const GLYPH_HEIGHT: u32 = 7;
+const ADVANCE: u32 = GLYPH_WIDTH + 1;
+const LINE_HEIGHT: u32 = GLYPH_HEIGHT + 2;
+const PADDING: u32 = 4;
+/// Distance of the display from the corner of the window, in unscaled pixels.
+const MARGIN: u32 = 4;
+
+const BACKGROUND: Rgba<u8> = Rgba([0, 0, 0, 160]);
+const TEXT: Rgba<u8> = Rgba([255, 255, 255, 255]);
+const GRID: Rgba<u8> = Rgba([96, 96, 96, 255]);
+
+/// Rows of the glyphs, from top to bottom, with the leftmost pixel in the fifth bit. Sorted by
+/// character; lowercase letters use the uppercase glyphs.
+const GLYPHS: &[(u8, [u8; 7])] = &[
+    (b' ', [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]),
+    (b'%', [0x18, 0x19, 0x02, 0x04, 0x08, 0x13, 0x03]),
+    (b'(', [0x02, 0x04, 0x08, 0x08, 0x08, 0x04, 0x02]),
+    (b')', [0x08, 0x04, 0x02, 0x02, 0x02, 0x04, 0x08]),
+    (b'+', [0x00, 0x04, 0x04, 0x1f, 0x04, 0x04, 0x00]),
+    (b',', [0x00, 0x00, 0x00, 0x00, 0x0c, 0x04, 0x08]),
+    (b'-', [0x00, 0x00, 0x00, 0x1f, 0x00, 0x00, 0x00]),
+    (b'.', [0x00, 0x00, 0x00, 0x00, 0x00, 0x0c, 0x0c]),
+    (b'/', [0x00, 0x01, 0x02, 0x04, 0x08, 0x10, 0x00]),
+    (b'0', [0x0e, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0e]),
+    (b'1', [0x04, 0x0c, 0x04, 0x04, 0x04, 0x04, 0x0e]),
+    (b'2', [0x0e, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1f]),
+    (b'3', [0x1f, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0e]),
+    (b'4', [0x02, 0x06, 0x0a, 0x12, 0x1f, 0x02, 0x02]),
+    (b'5', [0x1f, 0x10, 0x1e, 0x01, 0x01, 0x11, 0x0e]),
+    (b'6', [0x06, 0x08, 0x10, 0x1e, 0x11, 0x11, 0x0e]),
+    (b'7', [0x1f, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08]),
+    (b'8', [0x0e, 0x11, 0x11, 0x0e, 0x11, 0x11, 0x0e]),
+    (b'9', [0x0e, 0x11, 0x11, 0x0f, 0x01, 0x02, 0x0c]),
+    (b':', [0x00, 0x0c, 0x0c, 0x00, 0x0c, 0x0c, 0x00]),
+    (b'=', [0x00, 0x00, 0x1f, 0x00, 0x1f, 0x00, 0x00]),
+    (b'?', [0x0e, 0x11, 0x01, 0x02, 0x04, 0x00, 0x04]),
+    (b'A', [0x0e, 0x11, 0x11, 0x1f, 0x11, 0x11, 0x11]),
+    (b'B', [0x1e, 0x11, 0x11, 0x1e, 0x11, 0x11, 0x1e]),
+    (b'C', [0x0e, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0e]),
+    (b'D', [0x1c, 0x12, 0x11, 0x11, 0x11, 0x12, 0x1c]),
+    (b'E', [0x1f, 0x10, 0x10, 0x1e, 0x10, 0x10, 0x1f]),
+    (b'F', [0x1f, 0x10, 0x10, 0x1e, 0x10, 0x10, 0x10]),
+    (b'G', [0x0e, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0f]),
+    (b'H', [0x11, 0x11, 0x11, 0x1f, 0x11, 0x11, 0x11]),
+    (b'I', [0x0e, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0e]),
+    (b'J', [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0c]),
+    (b'K', [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11]),
+    (b'L', [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1f]),
+    (b'M', [0x11, 0x1b, 0x15, 0x15, 0x11, 0x11, 0x11]),
+    (b'N', [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11]),
+    (b'O', [0x0e, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0e]),
+    (b'P', [0x1e, 0x11, 0x11, 0x1e, 0x10, 0x10, 0x10]),
+    (b'Q', [0x0e, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0d]),
+    (b'R', [0x1e, 0x11, 0x11, 0x1e, 0x14, 0x12, 0x11]),
+    (b'S', [0x0f, 0x10, 0x10, 0x0e, 0x01, 0x01, 0x1e]),
+    (b'T', [0x1f, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04]),
+    (b'U', [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0e]),
+    (b'V', [0x11, 0x11, 0x11, 0x11, 0x11, 0x0a, 0x04]),
+    (b'W', [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0a]),
+    (b'X', [0x11, 0x11, 0x0a, 0x04, 0x0a, 0x11, 0x11]),
+    (b'Y', [0x11, 0x11, 0x0a, 0x04, 0x04, 0x04, 0x04]),
+    (b'Z', [0x1f, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1f]),
+];
+
+#[derive(Debug)]
+pub struct Hud {
+    visible: bool,
+    /// Times between frames, in milliseconds.
+    frame_times: CircBuffer<f32>,
+    draw: ScreenDraw,
+    u_texture: UniformLocation,
+    texture: Option<Texture<[f32; 4]>>,
+    last_update: Option<Instant>,
+}
+
+impl Hud {
+    pub fn new(reload_watcher: &ReloadWatcher) -> Result<Self> {
+        let draw =
+            ScreenDraw::load("hud.glsl", reload_watcher).context("Cannot load HUD shader")?;
+        let u_texture = draw.program().uniform("in_texture");
+        Ok(Self {
+            visible: false,
+            frame_times: CircBuffer::new(GRAPH_FRAMES),
+            draw,
+            u_texture,
+            texture: None,
+            last_update: None,
+        })
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    pub fn toggle(&mut self) {
+        self.visible = !self.visible;
+        self.last_update = None;
+    }
+
+    pub fn record_frame(&mut self, dt: Duration) {
+        self.frame_times.add(dt.as_secs_f32() * 1e3);
+    }
+
+    /// Draw the display into the backbuffer, calling `app_lines` for the lines of statistics of
+    /// the application when the display is redrawn.
+    pub fn draw(
+        &mut self,
+        window_size: PhysicalSize<u32>,
+        scale_factor: f64,
+        stats: &RenderStats,
+        app_lines: impl FnOnce() -> Vec<String>,
+    ) -> Result<()> {
+        let outdated = !self
+            .last_update
+            .is_some_and(|last| last.elapsed() < UPDATE_INTERVAL);
+        if outdated || self.texture.is_none() {
+            let frame_times = self.frame_times.iter().copied().collect::<Vec<_>>();
+            let average = frame_times.iter().sum::<f32>() / frame_times.len().max(1) as f32;
+            // No frame was recorded in the statistics on the first frames
+            let cpu = match stats.fps_history().next() {
+                Some(_) => format!("CPU: {:.1} ms", 1e3 / stats.fps_average()),
+                None => "CPU: -".to_string(),
+            };
+            let mut lines = vec![
+                format!("FPS: {:.0} ({:.1} ms)", 1e3 / average.max(1e-3), average),
+                cpu,
+            ];
+            lines.extend(app_lines());
+            let image = rasterize(&lines, &frame_times);
+            let texture = Texture::from_dynamic_image(DynamicImage::ImageRgba8(image))?;
+            texture.filter_min(SampleMode::Nearest)?;
+            texture.filter_mag(SampleMode::Nearest)?;
+            self.texture = Some(texture);
+            self.last_update = Some(Instant::now());
+        }
+        let Some(texture) = &self.texture else { return Ok(()); };
+
+        let scale = (2. * scale_factor).round().max(1.) as u32;
+        let size = texture.size_vec().truncate() * scale;
+        let margin = MARGIN * scale;
+        // Viewports start at the bottom-left corner
+        let y = window_size.height as i32 - (margin + size.y) as i32;
+        Framebuffer::viewport(margin as _, y, size.x as _, size.y as _);
+        violette::culling(None);
+        Framebuffer::disable_depth_test();
+        Framebuffer::blend_equation(BlendFunction::Add);
+        Framebuffer::enable_blending(Blend::One, Blend::OneMinusSrcAlpha);
+        self.draw
+            .program()
+            .set_uniform(self.u_texture, texture.as_uniform(0).counted()?)?;
+        self.draw.draw(&Framebuffer::backbuffer())?;
+        Framebuffer::disable_blending();
+        Framebuffer::viewport(0, 0, window_size.width as _, window_size.height as _);
+        Ok(())
+    }
+}
+
+/// Draw the lines of text over a graph of the frame times, in milliseconds.
+fn rasterize(lines: &[String], frame_times: &[f32]) -> RgbaImage {
+    let columns = lines.iter().map(|line| line.len()).max().unwrap_or(0) as u32;
+    let width = (columns * ADVANCE).max(GRAPH_FRAMES as u32) + 2 * PADDING;
+    let text_height = lines.len() as u32 * LINE_HEIGHT;
+    let height = text_height + GRAPH_HEIGHT + 3 * PADDING;
+    let mut image = RgbaImage::from_pixel(width, height, BACKGROUND);
+
+    for (row, line) in lines.iter().enumerate() {
+        let y = PADDING + row as u32 * LINE_HEIGHT;
+        for (column, c) in line.bytes().enumerate() {
+            let x = PADDING + column as u32 * ADVANCE;
+            draw_glyph(&mut image, x, y, c);
+        }
+    }
+
+    let graph_bottom = height - PADDING - 1;
+    let ms_to_height = |ms: f32| (ms / GRAPH_MAX_MS * GRAPH_HEIGHT as f32).round() as u32;
+    // 60 and 30 FPS
+    for ms in [1e3 / 60., 1e3 / 30.] {
+        let y = graph_bottom - ms_to_height(ms).min(GRAPH_HEIGHT - 1);
+        for x in PADDING..PADDING + GRAPH_FRAMES as u32 {
+            image.put_pixel(x, y, GRID);
+        }
+    }
+    for (column, &ms) in frame_times.iter().enumerate() {
+        let color = if ms <= 1e3 / 55. {
+            Rgba([96, 220, 96, 255])
+        } else if ms <= 1e3 / 28. {
+            Rgba([230, 200, 64, 255])
+        } else {
+            Rgba([230, 64, 64, 255])
+        };
+        let x = PADDING + column as u32;
+        for dy in 0..ms_to_height(ms).clamp(1, GRAPH_HEIGHT) {
+            image.put_pixel(x, graph_bottom - dy, color);
+        }
+    }
+    image
+}
+
+fn draw_glyph(image: &mut RgbaImage, x: u32, y: u32, c: u8) {
+    let c = c.to_ascii_uppercase();
+    let glyph = GLYPHS
+        .binary_search_by_key(&c, |(c, _)| *c)
+        .or_else(|_| GLYPHS.binary_search_by_key(&b'?', |(c, _)| *c))
+        .map(|ix| GLYPHS[ix].1)
+        .unwrap();
+    for (dy, bits) in glyph.into_iter().enumerate() {
+        for dx in 0..GLYPH_WIDTH {
+            if bits & (1 << (GLYPH_WIDTH - 1 - dx)) != 0 {
+                image.put_pixel(x + dx, y + dy as u32, TEXT);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glyphs_are_sorted() {
+        assert!(GLYPHS.windows(2).all(|w| w[0].0 < w[1].0));
+    }
+
+    #[test]
+    fn rasterizes_text_and_graph() {
+        let image = rasterize(&["Fps: 60".to_string()], &[10., 40.]);
+        assert_eq!(image.width(), GRAPH_FRAMES as u32 + 2 * PADDING);
+        assert_eq!(image.height(), LINE_HEIGHT + GRAPH_HEIGHT + 3 * PADDING);
+        // Top-left pixel of the F
+        assert_eq!(*image.get_pixel(PADDING, PADDING), TEXT);
+        let bottom = image.height() - PADDING - 1;
+        assert_ne!(*image.get_pixel(PADDING, bottom), BACKGROUND);
+        assert_ne!(*image.get_pixel(PADDING + 1, bottom - 20), BACKGROUND);
+        assert_eq!(*image.get_pixel(PADDING + 2, bottom), BACKGROUND);
+    }
+}
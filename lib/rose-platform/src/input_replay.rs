@@ -0,0 +1,356 @@
+//! Recording of the input events received by the application, and their replay, so that bugs and
+//! benchmarks can be reproduced exactly.
+//!
+//! Set `ROSE_RECORD_INPUT` to a file path to record a session, and `ROSE_REPLAY_INPUT` to replay
+//! it. Recordings are JSON lines: the engine seed (see [`rose_core::utils::rng`]), then for each
+//! frame the input events received by the window followed by the frame timing. Events are recorded
+//! before the UI filters them, and replayed events go through the UI before reaching
+//! [`crate::Application::interact`], as live ones do. During replay, live input is ignored, frames
+//! get the recorded time step, and the application exits at the end of the recording.
+//! [`crate::Application::tick`] runs on its own clock and is not replayed.
+
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::Path,
+    time::Duration,
+};
+
+use eyre::{Context, Report, Result};
+use serde::{Deserialize, Serialize};
+use winit::{
+    dpi::PhysicalPosition,
+    event::{
+        DeviceId, ElementState, Force, KeyboardInput, ModifiersState, MouseButton,
+        MouseScrollDelta, Touch, TouchPhase, VirtualKeyCode, WindowEvent,
+    },
+};
+
+use rose_core::utils::rng;
+
+/// Environment variable holding the path to record input to.
+pub const RECORD_ENV_VAR: &str = "ROSE_RECORD_INPUT";
+/// Environment variable holding the path of the recording to replay.
+pub const REPLAY_ENV_VAR: &str = "ROSE_REPLAY_INPUT";
+
+/// Serializable subset of [`WindowEvent`] covering user input.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum RecordedEvent {
+    Focused(bool),
+    ReceivedCharacter(char),
+    KeyboardInput {
+        scancode: u32,
+        state: ElementState,
+        virtual_keycode: Option<VirtualKeyCode>,
+    },
+    /// Bits of the [`ModifiersState`].
+    ModifiersChanged(u32),
+    CursorMoved([f64; 2]),
+    CursorEntered,
+    CursorLeft,
+    MouseWheelLines([f32; 2]),
+    MouseWheelPixels([f64; 2]),
+    MouseInput {
+        state: ElementState,
+        button: MouseButton,
+    },
+    /// Touch with its normalized pressure, if any.
+    Touch {
+        id: u64,
+        phase: TouchPhase,
+        position: [f64; 2],
+        pressure: Option<f64>,
+    },
+}
+
+impl RecordedEvent {
+    /// Convert the event, if it is an input event.
+    pub fn from_event(event: &WindowEvent) -> Option<Self> {
+        Some(match event {
+            WindowEvent::Focused(focused) => Self::Focused(*focused),
+            WindowEvent::ReceivedCharacter(c) => Self::ReceivedCharacter(*c),
+            WindowEvent::KeyboardInput { input, .. } => Self::KeyboardInput {
+                scancode: input.scancode,
+                state: input.state,
+                virtual_keycode: input.virtual_keycode,
+            },
+            WindowEvent::ModifiersChanged(state) => Self::ModifiersChanged(state.bits()),
+            WindowEvent::CursorMoved { position, .. } => {
+                Self::CursorMoved([position.x, position.y])
+            }
+            WindowEvent::CursorEntered { .. } => Self::CursorEntered,
+            WindowEvent::CursorLeft { .. } => Self::CursorLeft,
+            WindowEvent::MouseWheel {
+                delta: MouseScrollDelta::LineDelta(x, y),
+                ..
+            } => Self::MouseWheelLines([*x, *y]),
+            WindowEvent::MouseWheel {
+                delta: MouseScrollDelta::PixelDelta(delta),
+                ..
+            } => Self::MouseWheelPixels([delta.x, delta.y]),
+            WindowEvent::MouseInput { state, button, .. } => Self::MouseInput {
+                state: *state,
+                button: *button,
+            },
+            WindowEvent::Touch(touch) => Self::Touch {
+                id: touch.id,
+                phase: touch.phase,
+                position: [touch.location.x, touch.location.y],
+                pressure: touch.force.map(|force| force.normalized()),
+            },
+            _ => return None,
+        })
+    }
+
+    #[allow(deprecated)]
+    pub fn to_event(&self) -> WindowEvent<'static> {
+        // Safety: the dummy device ID is only compared against other device IDs
+        let device_id = unsafe { DeviceId::dummy() };
+        let modifiers = ModifiersState::empty();
+        match *self {
+            Self::Focused(focused) => WindowEvent::Focused(focused),
+            Self::ReceivedCharacter(c) => WindowEvent::ReceivedCharacter(c),
+            Self::KeyboardInput {
+                scancode,
+                state,
+                virtual_keycode,
+            } => WindowEvent::KeyboardInput {
+                device_id,
+                input: KeyboardInput {
+                    scancode,
+                    state,
+                    virtual_keycode,
+                    modifiers,
+                },
+                is_synthetic: false,
+            },
+            Self::ModifiersChanged(bits) => {
+                WindowEvent::ModifiersChanged(ModifiersState::from_bits_truncate(bits))
+            }
+            Self::CursorMoved([x, y]) => WindowEvent::CursorMoved {
+                device_id,
+                position: PhysicalPosition::new(x, y),
+                modifiers,
+            },
+            Self::CursorEntered => WindowEvent::CursorEntered { device_id },
+            Self::CursorLeft => WindowEvent::CursorLeft { device_id },
+            Self::MouseWheelLines([x, y]) => WindowEvent::MouseWheel {
+                device_id,
+                delta: MouseScrollDelta::LineDelta(x, y),
+                phase: TouchPhase::Moved,
+                modifiers,
+            },
+            Self::MouseWheelPixels([x, y]) => WindowEvent::MouseWheel {
+                device_id,
+                delta: MouseScrollDelta::PixelDelta(PhysicalPosition::new(x, y)),
+                phase: TouchPhase::Moved,
+                modifiers,
+            },
+            Self::MouseInput { state, button } => WindowEvent::MouseInput {
+                device_id,
+                state,
+                button,
+                modifiers,
+            },
+            Self::Touch {
+                id,
+                phase,
+                position: [x, y],
+                pressure,
+            } => WindowEvent::Touch(Touch {
+                device_id,
+                phase,
+                location: PhysicalPosition::new(x, y),
+                force: pressure.map(Force::Normalized),
+                id,
+            }),
+        }
+    }
+}
+
+/// Line of a recording.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ReplayEntry {
+    /// Engine seed of the recorded session, first entry of the recording.
+    Seed(u64),
+    Event {
+        elapsed: Duration,
+        event: RecordedEvent,
+    },
+    /// End of a frame, after the events delivered before it was rendered.
+    Frame { elapsed: Duration, dt: Duration },
+}
+
+/// Writes the input events and frame timings of the session to a file.
+pub struct InputRecorder {
+    writer: BufWriter<File>,
+}
+
+impl InputRecorder {
+    pub fn create(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let file = File::create(path)
+            .with_context(|| format!("Creating input recording {}", path.display()))?;
+        let mut recorder = Self {
+            writer: BufWriter::new(file),
+        };
+        recorder.write(&ReplayEntry::Seed(rng::seed()))?;
+        tracing::info!(path = %path.display(), "Recording input");
+        Ok(recorder)
+    }
+
+    fn write(&mut self, entry: &ReplayEntry) -> Result<()> {
+        serde_json::to_writer(&mut self.writer, entry)?;
+        writeln!(self.writer)?;
+        Ok(())
+    }
+
+    /// Record the event if it is an input event.
+    pub fn record_event(&mut self, elapsed: Duration, event: &WindowEvent) -> Result<()> {
+        let Some(event) = RecordedEvent::from_event(event) else { return Ok(()); };
+        self.write(&ReplayEntry::Event { elapsed, event })
+    }
+
+    /// Record the timing of the frame being rendered, closing the events recorded since the last
+    /// frame.
+    pub fn end_frame(&mut self, elapsed: Duration, dt: Duration) -> Result<()> {
+        self.write(&ReplayEntry::Frame { elapsed, dt })?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Events to deliver before rendering a replayed frame, and the timing of the frame.
+#[derive(Debug)]
+pub struct ReplayFrame {
+    pub events: Vec<WindowEvent<'static>>,
+    pub elapsed: Duration,
+    pub dt: Duration,
+}
+
+/// Replays a recording made with [`InputRecorder`] frame by frame.
+#[derive(Debug)]
+pub struct InputReplay {
+    entries: std::vec::IntoIter<ReplayEntry>,
+}
+
+impl InputReplay {
+    /// Load the recording and set the engine seed to the recorded one.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let file = File::open(path)
+            .with_context(|| format!("Opening input recording {}", path.display()))?;
+        let entries = BufReader::new(file)
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !matches!(line, Ok(line) if line.trim().is_empty()))
+            .map(|(ix, line)| -> Result<ReplayEntry> {
+                serde_json::from_str(&line?)
+                    .with_context(|| format!("Parsing {}:{}", path.display(), ix + 1))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if let Some(ReplayEntry::Seed(seed)) = entries.first() {
+            rng::set_seed(*seed);
+        }
+        tracing::info!(path = %path.display(), "Replaying input");
+        Ok(Self {
+            entries: entries.into_iter(),
+        })
+    }
+
+    /// Next recorded frame, or `None` when the recording is over.
+    pub fn next_frame(&mut self) -> Option<ReplayFrame> {
+        let mut events = vec![];
+        for entry in self.entries.by_ref() {
+            match entry {
+                ReplayEntry::Seed(_) => {}
+                ReplayEntry::Event { event, .. } => events.push(event.to_event()),
+                ReplayEntry::Frame { elapsed, dt } => {
+                    return Some(ReplayFrame {
+                        events,
+                        elapsed,
+                        dt,
+                    })
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Source of the input of the session, selected from the environment.
+pub enum InputSession {
+    Live,
+    Record(InputRecorder),
+    Replay(InputReplay),
+}
+
+impl InputSession {
+    /// Replay if [`REPLAY_ENV_VAR`] is set, else record if [`RECORD_ENV_VAR`] is set. Needs to be
+    /// called before generators are created from the engine seed.
+    pub fn from_env() -> Result<Self> {
+        if let Some(path) = std::env::var_os(REPLAY_ENV_VAR) {
+            Ok(Self::Replay(InputReplay::load(path)?))
+        } else if let Some(path) = std::env::var_os(RECORD_ENV_VAR) {
+            Ok(Self::Record(InputRecorder::create(path)?))
+        } else {
+            Ok(Self::Live)
+        }
+    }
+
+    /// Record the event when recording. A failure to write is logged and ends the recording, the
+    /// session going on live.
+    pub fn record_event(&mut self, elapsed: Duration, event: &WindowEvent) {
+        if let Self::Record(recorder) = self {
+            if let Err(err) = recorder.record_event(elapsed, event) {
+                self.stop_recording(err);
+            }
+        }
+    }
+
+    /// Record the timing of the frame when recording, see [`Self::record_event`] for failures.
+    pub fn end_frame(&mut self, elapsed: Duration, dt: Duration) {
+        if let Self::Record(recorder) = self {
+            if let Err(err) = recorder.end_frame(elapsed, dt) {
+                self.stop_recording(err);
+            }
+        }
+    }
+
+    fn stop_recording(&mut self, err: Report) {
+        tracing::error!("Cannot record input, stopping the recording: {:#}", err);
+        *self = Self::Live;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn events_round_trip() {
+        let events = [
+            RecordedEvent::KeyboardInput {
+                scancode: 17,
+                state: ElementState::Pressed,
+                virtual_keycode: Some(VirtualKeyCode::W),
+            },
+            RecordedEvent::ModifiersChanged(ModifiersState::CTRL.bits()),
+            RecordedEvent::CursorMoved([12.5, 40.]),
+            RecordedEvent::MouseWheelLines([0., -1.]),
+            RecordedEvent::MouseInput {
+                state: ElementState::Released,
+                button: MouseButton::Other(4),
+            },
+        ];
+        for event in events {
+            let entry = ReplayEntry::Event {
+                elapsed: Duration::from_millis(16),
+                event: event.clone(),
+            };
+            let line = serde_json::to_string(&entry).unwrap();
+            assert_eq!(serde_json::from_str::<ReplayEntry>(&line).unwrap(), entry);
+            assert_eq!(RecordedEvent::from_event(&event.to_event()), Some(event));
+        }
+    }
+}
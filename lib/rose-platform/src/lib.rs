@@ -1,4 +1,5 @@
-use std::path::PathBuf;
+use std::num::NonZeroU32;
+use std::path::Path;
 use std::sync::RwLock;
 use std::{
     ffi::CString,
@@ -12,7 +13,7 @@ use glutin::{
     context::{ContextApi, ContextAttributesBuilder, Version},
     display::GetGlDisplay,
     prelude::*,
-    surface::{SurfaceAttributesBuilder, WindowSurface},
+    surface::{SurfaceAttributesBuilder, SwapInterval, WindowSurface},
 };
 use glutin_winit::DisplayBuilder;
 use histo::Histogram;
@@ -31,15 +32,30 @@ use winit::{
 use rose_core::utils::reload_watcher::ReloadWatcher;
 
 use crate::circbuffer::CircBuffer;
+use crate::config::LaunchConfig;
+use crate::display::{DisplayMode, DisplayPreference};
+use crate::hud::Hud;
+use crate::input_replay::InputSession;
+use crate::time::{GameTime, TimeControl};
+use crate::window::WindowSize;
 
 pub mod circbuffer;
+pub mod config;
+pub mod display;
+pub mod hud;
+pub mod input_replay;
+pub mod log_capture;
 pub mod prelude;
+pub mod time;
 mod tracing_hook;
+pub mod window;
 
 #[derive(Debug, Copy, Clone)]
 pub struct TickContext {
     pub dt: Duration,
     pub elapsed: Duration,
+    /// Time of the simulation, scaled and paused by the [`TimeControl`] of the application.
+    pub game: GameTime,
 }
 
 #[derive(Debug, Clone)]
@@ -76,14 +92,38 @@ pub struct RenderContext<'a> {
     pub elapsed: Duration,
     pub stats: &'a RenderStats,
     pub dt: Duration,
+    /// Time of the simulation, scaled and paused by [`Self::time`].
+    pub game: GameTime,
+    pub time: &'a TimeControl,
     pub window: &'a Window,
     control_flow: &'a mut ControlFlow,
+    title: &'a mut String,
+    display: &'a mut DisplayPreference,
 }
 
 impl<'a> RenderContext<'a> {
     pub fn quit(&mut self) {
         self.control_flow.set_exit();
     }
+
+    /// Change the title of the window. Cheap to call every frame, the window is only updated when
+    /// the title changes.
+    pub fn set_title(&mut self, title: &str) {
+        if self.title.as_str() != title {
+            self.window.set_title(title);
+            title.clone_into(self.title);
+        }
+    }
+
+    pub fn display_mode(&self) -> &DisplayMode {
+        self.display.mode()
+    }
+
+    /// Switch the window to the display mode, remembering it for the next runs. See
+    /// [`display::monitors`] for the available monitors and video modes.
+    pub fn set_display_mode(&mut self, mode: DisplayMode) {
+        self.display.set(self.window, mode);
+    }
 }
 
 #[cfg(not(feature = "ui"))]
@@ -91,6 +131,7 @@ pub struct UiContext<'stats> {
     pub elapsed: Duration,
     pub dt: Duration,
     pub stats: &'stats RenderStats,
+    pub time: &'stats TimeControl,
 }
 
 #[cfg(feature = "ui")]
@@ -98,7 +139,19 @@ pub struct UiContext<'stats, 'ui> {
     pub elapsed: Duration,
     pub dt: Duration,
     pub stats: &'stats RenderStats,
+    /// Controls of the game time, e.g. for pause and step buttons.
+    pub time: &'stats TimeControl,
     pub egui: &'ui egui::Context,
+    /// Scale of the UI on top of the window scale factor, initially [`LaunchConfig::ui_scale`].
+    pub ui_scale: &'ui mut f32,
+}
+
+/// Part of an application ticked on its own thread, without locking the application, so that long
+/// ticks do not stall rendering. It hands the state rendering needs over to the application with a
+/// [`rose_core::utils::snapshot`], published after each tick.
+pub trait Simulation: Send {
+    /// /!\ Does not run on the main thread. OpenGL calls are unsafe here.
+    fn tick(&mut self, ctx: TickContext) -> Result<()>;
 }
 
 #[allow(unused_variables)]
@@ -106,25 +159,58 @@ pub trait Application: Sized + Send + Sync {
     fn window_features(wb: WindowBuilder) -> WindowBuilder {
         wb
     }
-    fn new(size: PhysicalSize<f32>, scale_factor: f64) -> Result<Self>;
-    fn resize(&mut self, _size: PhysicalSize<u32>, scale_factor: f64) -> Result<()> {
+    fn new(size: WindowSize, config: &LaunchConfig) -> Result<Self>;
+    /// Called when the window is resized, or its scale factor changes.
+    fn resize(&mut self, size: WindowSize) -> Result<()> {
         Ok(())
     }
     fn interact(&mut self, _event: WindowEvent) -> Result<()> {
         Ok(())
     }
+    /// Called when the window gains or loses focus. While unfocused, rendering is throttled to
+    /// [`LaunchConfig::background_fps`].
+    fn on_focus_changed(&mut self, focused: bool) {}
+    /// Whether files hovered or dropped onto the window at this path are delivered to
+    /// [`Self::interact`], see [`window::has_extension`].
+    fn accepts_dropped_file(&self, path: &Path) -> bool {
+        true
+    }
     /// /!\ Does not run on the main thread. OpenGL calls are unsafe here.
     fn tick(&mut self, ctx: TickContext) -> Result<()> {
         Ok(())
     }
+    /// Simulation to tick instead of [`Self::tick`], called once after creating the application.
+    fn simulation(&mut self) -> Option<Box<dyn Simulation>> {
+        None
+    }
     fn render(&mut self, ctx: RenderContext) -> Result<()>;
     #[cfg(feature = "ui")]
     fn ui(&mut self, ctx: UiContext) {}
+    /// Lines of statistics shown under the frame rate in the heads-up display toggled with F3.
+    fn hud_lines(&self) -> Vec<String> {
+        vec![]
+    }
+    /// Called once when the application exits normally.
+    fn exit(&mut self) {}
 }
 
+/// Run the application, configured from the command line and environment (see [`config`]).
 pub fn run<App: 'static + Application>(title: &str) -> Result<()> {
-    tracing_hook::enable()?;
+    let config = LaunchConfig::from_env()?;
+    tracing_hook::enable(&config.logging)?;
+    run_app::<App>(title, config)
+}
+
+/// Run the application with the given launch configuration.
+pub fn run_with_config<App: 'static + Application>(
+    title: &str,
+    config: LaunchConfig,
+) -> Result<()> {
+    tracing_hook::enable(&config.logging)?;
+    run_app::<App>(title, config)
+}
 
+fn run_app<App: 'static + Application>(title: &str, config: LaunchConfig) -> Result<()> {
     let event_loop = EventLoopBuilder::new().build();
     // The template will match only the configurations supporting rendering to
     // windows.
@@ -132,9 +218,17 @@ pub fn run<App: 'static + Application>(title: &str) -> Result<()> {
         .with_alpha_size(8)
         .with_transparency(true);
 
-    let display_builder = DisplayBuilder::new().with_window_builder(Some(
-        App::window_features(WindowBuilder::new()).with_title(title),
-    ));
+    let mut window_builder = App::window_features(WindowBuilder::new()).with_title(title);
+    if config.headless {
+        window_builder = window_builder.with_visible(false);
+    }
+    if let Some(size) = config.window_size {
+        window_builder = window_builder.with_inner_size(size);
+    }
+    if config.fullscreen {
+        window_builder = window_builder.with_fullscreen(Some(Fullscreen::Borderless(None)));
+    }
+    let display_builder = DisplayBuilder::new().with_window_builder(Some(window_builder));
 
     let (window, gl_config) = display_builder
         .build(&event_loop, template, |configs| {
@@ -199,6 +293,14 @@ pub fn run<App: 'static + Application>(title: &str) -> Result<()> {
         gl_display.get_proc_address(sym.as_c_str()).cast()
     });
     violette::debug::hook_gl_to_tracing();
+    let swap_interval = if config.vsync && !config.headless {
+        SwapInterval::Wait(NonZeroU32::new(1).unwrap())
+    } else {
+        SwapInterval::DontWait
+    };
+    if let Err(err) = gl_surface.set_swap_interval(&context, swap_interval) {
+        tracing::warn!("Cannot set swap interval: {}", err);
+    }
 
     let gl_version =
         violette::get_string(violette::gl::VERSION).unwrap_or_else(|_| "<None>".to_string());
@@ -210,35 +312,40 @@ pub fn run<App: 'static + Application>(title: &str) -> Result<()> {
         .unwrap_or_else(|_| "<None>".to_string());
     tracing::info!(target: "gl", version=%gl_version, vendor=%gl_vendor, render=%gl_renderer, shading_language=%gl_shading_language_version);
 
-    let app = App::new(inner_size.cast(), window.scale_factor()).context("Cannot run app")?;
+    // Replaying sets the engine seed, which the application may use on creation
+    let mut input_session = InputSession::from_env()?;
+    let mut app = App::new(WindowSize::of(&window), &config).context("Cannot run app")?;
+    let mut simulation = app.simulation();
     let app = Arc::new(Mutex::new(app));
 
+    let reload_watcher = ReloadWatcher::new(config.asset_root.join("res/shaders"));
     #[cfg(feature = "ui")]
-    let (_reload_watcher, mut ui) = {
-        let base_path = std::env::var("CARGO_PROJECT_DIR")
-            .map(PathBuf::from)
-            .or_else(|_| std::env::current_dir())
-            .unwrap();
-        let reload_watcher = ReloadWatcher::new(base_path.join("res/shaders"));
-        let ui = rose_ui::Ui::new(&event_loop, &window, &reload_watcher)?;
-        (reload_watcher, ui)
-    };
+    let mut ui = rose_ui::Ui::new(&event_loop, &window, &reload_watcher, config.ui_scale)?;
+    // The application still runs without the display if its shader is missing
+    let mut hud = Hud::new(&reload_watcher)
+        .map_err(|err| tracing::warn!("Cannot create statistics display: {:#}", err))
+        .ok();
 
+    let time = TimeControl::new();
     let start = Instant::now();
     std::thread::spawn({
         let app = app.clone();
+        let mut clock = time.clock();
         move || {
             let mut last_tick = Instant::now();
             loop {
                 let _span = tracing::trace_span!("loop_tick").entered();
                 let tick_start = Instant::now();
-                app.lock()
-                    .unwrap()
-                    .tick(TickContext {
-                        elapsed: start.elapsed(),
-                        dt: last_tick.elapsed(),
-                    })
-                    .unwrap();
+                let dt = last_tick.elapsed();
+                let ctx = TickContext {
+                    elapsed: start.elapsed(),
+                    dt,
+                    game: clock.advance(dt),
+                };
+                match &mut simulation {
+                    Some(simulation) => simulation.tick(ctx).unwrap(),
+                    None => app.lock().unwrap().tick(ctx).unwrap(),
+                }
                 let tick_duration = tick_start.elapsed().as_secs_f32();
                 last_tick = Instant::now();
                 tracing::debug!(%tick_duration);
@@ -252,29 +359,86 @@ pub fn run<App: 'static + Application>(title: &str) -> Result<()> {
         fps_hist: Histogram::with_buckets(100),
     }));
 
+    let headless = config.headless;
+    let background_frame_time = config
+        .background_fps
+        .map(|fps| Duration::from_secs_f32(fps.recip()));
+    let mut focused = true;
+    // Rendering is suspended while the window is minimized, or hidden behind other windows
+    let mut minimized = false;
+    let mut occluded = false;
+    let mut window_title = title.to_string();
+    let mut display = match &config.config_dir {
+        Some(dir) if !headless => DisplayPreference::load(dir.join(display::PREFERENCE_FILE)),
+        _ => DisplayPreference::default(),
+    };
+    if !headless && !config.fullscreen {
+        display.apply(&window);
+    }
+    let mut clock = time.clock();
     let mut last_frame_time = Instant::now();
     let mut next_frame_time = Instant::now() + Duration::from_nanos(16_666_667);
     event_loop.run(move |event, _, control_flow| {
-        control_flow.set_wait_until(next_frame_time);
+        if minimized || occluded {
+            control_flow.set_wait();
+        } else {
+            control_flow.set_wait_until(next_frame_time);
+        }
 
         match event {
+            Event::RedrawRequested(_) if minimized || occluded => {}
             Event::RedrawRequested(_) => {
+                let replay_frame = match &mut input_session {
+                    InputSession::Replay(replay) => match replay.next_frame() {
+                        Some(frame) => Some(frame),
+                        None => {
+                            tracing::info!("Input replay finished");
+                            control_flow.set_exit();
+                            return;
+                        }
+                    },
+                    _ => None,
+                };
+                let (elapsed, dt) = match &replay_frame {
+                    Some(frame) => (frame.elapsed, frame.dt),
+                    None => (start.elapsed(), last_frame_time.elapsed()),
+                };
+                // Replayed events take the same path as live ones, through the UI first
+                for event in replay_frame.into_iter().flat_map(|frame| frame.events) {
+                    #[cfg(feature = "ui")]
+                    let result = dispatch_input(&mut ui, &window, &mut *app.lock().unwrap(), event);
+                    #[cfg(not(feature = "ui"))]
+                    let result = app.lock().unwrap().interact(event);
+                    result.unwrap();
+                }
+
                 #[cfg(feature = "ui")]
                 let next_run = {
                     let _span = tracing::debug_span!("ui").entered();
-                    ui.run(&window, {
-                        let app = app.clone();
-                        let render_stats = render_stats.clone();
-                        move |cx| {
-                            app.lock().unwrap().ui(UiContext {
-                                elapsed: start.elapsed(),
-                                dt: last_frame_time.elapsed(),
-                                stats: &render_stats.read().unwrap(),
-                                egui: cx,
-                            })
-                        }
-                    })
-                    .min(Duration::from_nanos(16_666_667))
+                    let mut ui_scale = ui.ui_scale();
+                    let next_run = ui
+                        .run(&window, {
+                            let app = app.clone();
+                            let render_stats = render_stats.clone();
+                            let time = &time;
+                            let ui_scale = &mut ui_scale;
+                            move |cx| {
+                                app.lock().unwrap().ui(UiContext {
+                                    elapsed,
+                                    dt,
+                                    stats: &render_stats.read().unwrap(),
+                                    time,
+                                    egui: cx,
+                                    ui_scale,
+                                })
+                            }
+                        })
+                        .min(Duration::from_nanos(16_666_667));
+                    if ui_scale != ui.ui_scale() {
+                        ui.set_ui_scale(ui_scale);
+                        window.request_redraw();
+                    }
+                    next_run
                 };
                 #[cfg(not(feature = "ui"))]
                 let next_run = Duration::from_nanos(16_666_667);
@@ -282,81 +446,174 @@ pub fn run<App: 'static + Application>(title: &str) -> Result<()> {
                 let mut app = app.lock().unwrap();
                 let frame_start = Instant::now();
                 app.render(RenderContext {
-                    elapsed: start.elapsed(),
-                    dt: last_frame_time.elapsed(),
+                    elapsed,
+                    dt,
+                    game: clock.advance(dt),
+                    time: &time,
                     stats: &render_stats.read().unwrap(),
                     window: &window,
                     control_flow,
+                    title: &mut window_title,
+                    display: &mut display,
                 })
                 .unwrap();
+                if let Some(hud) = &mut hud {
+                    hud.record_frame(dt);
+                    if hud.is_visible() {
+                        let result = hud.draw(
+                            window.inner_size(),
+                            window.scale_factor(),
+                            &render_stats.read().unwrap(),
+                            || app.hud_lines(),
+                        );
+                        if let Err(err) = result {
+                            tracing::error!("Cannot draw statistics display: {:#}", err);
+                        }
+                    }
+                }
                 #[cfg(feature = "ui")]
                 {
                     ui.draw(&window).unwrap();
                 }
                 gl_surface.swap_buffers(&context).unwrap();
+                input_session.end_frame(elapsed, dt);
                 let frame_time = frame_start.elapsed().as_secs_f32();
                 render_stats
                     .write()
                     .unwrap()
                     .add_frame_time(frame_time.recip());
                 tracing::debug!(%frame_time);
-                next_frame_time = frame_start + next_run;
+                // Headless runs render frames back-to-back
+                if !headless {
+                    let next_run = match background_frame_time {
+                        Some(frame_time) if !focused => next_run.max(frame_time),
+                        _ => next_run,
+                    };
+                    next_frame_time = frame_start + next_run;
+                }
                 last_frame_time = Instant::now();
             }
-            Event::WindowEvent { event, .. } => match event {
-                WindowEvent::CloseRequested
-                | WindowEvent::KeyboardInput {
-                    input:
-                        KeyboardInput {
-                            virtual_keycode: Some(VirtualKeyCode::Escape),
-                            ..
-                        },
-                    ..
-                } => control_flow.set_exit(),
-                WindowEvent::KeyboardInput {
-                    input:
-                        KeyboardInput {
-                            virtual_keycode: Some(VirtualKeyCode::F11),
-                            state: ElementState::Pressed,
-                            ..
-                        },
-                    ..
-                } => {
-                    if window.fullscreen().is_some() {
-                        window.set_fullscreen(None)
-                    } else {
-                        window.set_fullscreen(Some(Fullscreen::Borderless(None)))
-                    }
+            Event::WindowEvent { event, .. } => {
+                if let WindowEvent::Focused(is_focused) = event {
+                    focused = is_focused;
+                    app.lock().unwrap().on_focus_changed(focused);
                 }
-                WindowEvent::Resized(new_size) => {
+                let resize = |size: WindowSize| {
                     gl_surface.resize(
                         &context,
-                        new_size.width.try_into().unwrap(),
-                        new_size.height.try_into().unwrap(),
+                        size.physical.width.try_into().unwrap(),
+                        size.physical.height.try_into().unwrap(),
                     );
-                    app.lock()
-                        .unwrap()
-                        .resize(new_size, window.scale_factor())
-                        .unwrap();
+                    app.lock().unwrap().resize(size).unwrap();
                     window.request_redraw();
-                }
-                event => {
-                    #[cfg(feature = "ui")]
-                    {
-                        let response = ui.on_event(&event);
-                        if !response.consumed {
-                            app.lock().unwrap().interact(event).unwrap();
+                };
+                // Escape goes to text fields, where it also cancels IME composition
+                #[cfg(feature = "ui")]
+                let ui_wants_keyboard = ui.wants_keyboard_input();
+                #[cfg(not(feature = "ui"))]
+                let ui_wants_keyboard = false;
+                match event {
+                    WindowEvent::CloseRequested => control_flow.set_exit(),
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                virtual_keycode: Some(VirtualKeyCode::Escape),
+                                ..
+                            },
+                        ..
+                    } if !ui_wants_keyboard => control_flow.set_exit(),
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                virtual_keycode: Some(VirtualKeyCode::F11),
+                                state: ElementState::Pressed,
+                                ..
+                            },
+                        ..
+                    } => display.toggle_fullscreen(&window),
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                virtual_keycode: Some(VirtualKeyCode::F3),
+                                state: ElementState::Pressed,
+                                ..
+                            },
+                        ..
+                    } => {
+                        if let Some(hud) = &mut hud {
+                            hud.toggle();
+                            window.request_redraw();
                         }
-                        if response.repaint {
+                    }
+                    // Minimized windows are resized to zero on some platforms
+                    WindowEvent::Resized(new_size)
+                        if new_size.width == 0 || new_size.height == 0 =>
+                    {
+                        tracing::debug!("Window minimized, suspending rendering");
+                        minimized = true;
+                    }
+                    WindowEvent::Resized(new_size) => {
+                        minimized = false;
+                        resize(WindowSize::new(new_size, window.scale_factor()))
+                    }
+                    // Hidden headless windows may be reported as occluded
+                    WindowEvent::Occluded(is_occluded) => {
+                        occluded = is_occluded && !headless;
+                        if !occluded {
                             window.request_redraw();
                         }
                     }
-                    #[cfg(not(feature = "ui"))]
-                    app.lock().unwrap().interact(event).unwrap();
+                    WindowEvent::ScaleFactorChanged {
+                        scale_factor,
+                        new_inner_size,
+                    } => {
+                        #[cfg(feature = "ui")]
+                        ui.set_scale_factor(scale_factor as _);
+                        resize(WindowSize::new(*new_inner_size, scale_factor));
+                    }
+                    WindowEvent::HoveredFile(path) | WindowEvent::DroppedFile(path)
+                        if !app.lock().unwrap().accepts_dropped_file(&path) =>
+                    {
+                        tracing::debug!(
+                            path = %path.display(),
+                            "Ignoring file dropped on the window"
+                        );
+                    }
+                    // Live input is ignored while replaying
+                    _ if matches!(input_session, InputSession::Replay(_)) => {}
+                    event => {
+                        // Recorded before the UI filters them, as the replay goes through the UI
+                        input_session.record_event(start.elapsed(), &event);
+                        #[cfg(feature = "ui")]
+                        let result =
+                            dispatch_input(&mut ui, &window, &mut *app.lock().unwrap(), event);
+                        #[cfg(not(feature = "ui"))]
+                        let result = app.lock().unwrap().interact(event);
+                        result.unwrap();
+                    }
                 }
-            },
+            }
             Event::NewEvents(StartCause::ResumeTimeReached { .. }) => window.request_redraw(),
+            Event::LoopDestroyed => app.lock().unwrap().exit(),
             _ => {}
         }
     });
 }
+
+/// Deliver an input event to the UI, then to the application when the UI does not consume it.
+#[cfg(feature = "ui")]
+fn dispatch_input(
+    ui: &mut rose_ui::Ui,
+    window: &Window,
+    app: &mut impl Application,
+    event: WindowEvent,
+) -> Result<()> {
+    let response = ui.on_event(&event);
+    if response.repaint {
+        window.request_redraw();
+    }
+    if response.consumed {
+        return Ok(());
+    }
+    app.interact(event)
+}
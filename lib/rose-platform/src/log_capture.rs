@@ -0,0 +1,121 @@
+use std::{
+    fmt::{self, Write},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use once_cell::sync::Lazy;
+use tracing::{
+    field::{Field, Visit},
+    Event, Level, Subscriber,
+};
+use tracing_subscriber::{layer::Context, Layer};
+
+use crate::circbuffer::CircBuffer;
+
+/// Number of log entries kept in memory; older entries are dropped first.
+pub const CAPACITY: usize = 4096;
+
+static START: Lazy<Instant> = Lazy::new(Instant::now);
+static ENTRIES: Lazy<Mutex<CircBuffer<Arc<LogEntry>>>> =
+    Lazy::new(|| Mutex::new(CircBuffer::new(CAPACITY)));
+
+/// Tracing event captured for display in the application, e.g. in a console panel.
+#[derive(Debug, Clone)]
+pub struct LogEntry {
+    /// Time since logging was enabled.
+    pub time: Duration,
+    pub level: Level,
+    pub target: String,
+    pub message: String,
+    /// Other fields of the event, in declaration order.
+    pub fields: Vec<(&'static str, String)>,
+}
+
+impl LogEntry {
+    pub fn field(&self, name: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(field, _)| *field == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Whether this entry reports a failure to hot-reload a shader, which are logged with a
+    /// `shader_reload` field.
+    pub fn is_shader_reload_error(&self) -> bool {
+        self.field("shader_reload").is_some()
+    }
+}
+
+impl fmt::Display for LogEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "[{:>9.3}s {:>5} {}] {}",
+            self.time.as_secs_f32(),
+            self.level,
+            self.target,
+            self.message
+        )?;
+        for (name, value) in &self.fields {
+            write!(f, " {}={}", name, value)?;
+        }
+        Ok(())
+    }
+}
+
+/// Snapshot of the captured log entries, oldest first.
+pub fn entries() -> Vec<Arc<LogEntry>> {
+    ENTRIES.lock().unwrap().iter().cloned().collect()
+}
+
+pub fn clear() {
+    ENTRIES.lock().unwrap().clear();
+}
+
+/// Layer keeping the last [`CAPACITY`] events in memory. Installed by default, filtered with
+/// `RUST_LOG` like the log output; add it to custom subscribers to keep [`entries`] working,
+/// preferably behind a filter as it records every event it is given.
+pub struct CaptureLayer;
+
+impl CaptureLayer {
+    pub fn new() -> Self {
+        Lazy::force(&START);
+        Self
+    }
+}
+
+impl<S: Subscriber> Layer<S> for CaptureLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+        let mut entry = LogEntry {
+            time: START.elapsed(),
+            level: *metadata.level(),
+            target: metadata.target().to_string(),
+            message: String::new(),
+            fields: vec![],
+        };
+        event.record(&mut EntryVisitor(&mut entry));
+        ENTRIES.lock().unwrap().add(Arc::new(entry));
+    }
+}
+
+struct EntryVisitor<'a>(&'a mut LogEntry);
+
+impl<'a> Visit for EntryVisitor<'a> {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.0.message.push_str(value);
+        } else {
+            self.0.fields.push((field.name(), value.to_string()));
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            let _ = write!(self.0.message, "{:?}", value);
+        } else {
+            self.0.fields.push((field.name(), format!("{:?}", value)));
+        }
+    }
+}
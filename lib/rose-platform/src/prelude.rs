@@ -4,7 +4,14 @@ pub use winit::{
     window::WindowBuilder,
 };
 
-pub use crate::run;
 #[cfg(feature = "ui")]
 pub use crate::UiContext;
-pub use crate::{circbuffer::CircBuffer, Application, RenderContext, RenderStats, TickContext};
+pub use crate::{
+    circbuffer::CircBuffer, Application, RenderContext, RenderStats, Simulation, TickContext,
+};
+pub use crate::{
+    config::LaunchConfig,
+    run, run_with_config,
+    time::{GameTime, TimeControl},
+    window::{WindowBuilderExt, WindowSize},
+};
@@ -0,0 +1,150 @@
+//! Game time, which can be slowed down, paused and stepped frame by frame independently of the
+//! wall clock, e.g. to inspect a scene while it is running.
+//!
+//! A [`TimeControl`] is shared by the tick thread, the render loop and the application; each of
+//! them advances its own [`GameClock`] from it.
+
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+/// Game time of a frame, given to [`crate::TickContext`] and [`crate::RenderContext`].
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct GameTime {
+    /// Scaled time since the previous frame, zero while paused.
+    pub dt: Duration,
+    /// Scaled time since the start, not advancing while paused.
+    pub elapsed: Duration,
+    pub time_scale: f32,
+    pub paused: bool,
+}
+
+#[derive(Debug, Copy, Clone)]
+struct TimeState {
+    time_scale: f32,
+    paused: bool,
+    /// Number of single frame steps requested so far.
+    steps: u64,
+}
+
+/// Shared controls of the game time. Clones control the same time.
+#[derive(Debug, Clone)]
+pub struct TimeControl(Arc<Mutex<TimeState>>);
+
+impl Default for TimeControl {
+    fn default() -> Self {
+        Self(Arc::new(Mutex::new(TimeState {
+            time_scale: 1.,
+            paused: false,
+            steps: 0,
+        })))
+    }
+}
+
+impl TimeControl {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn time_scale(&self) -> f32 {
+        self.0.lock().unwrap().time_scale
+    }
+
+    /// Set how fast game time passes relative to the wall clock, 1 being real time. Negative
+    /// scales are clamped to zero.
+    pub fn set_time_scale(&self, time_scale: f32) {
+        self.0.lock().unwrap().time_scale = time_scale.max(0.);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.0.lock().unwrap().paused
+    }
+
+    pub fn set_paused(&self, paused: bool) {
+        self.0.lock().unwrap().paused = paused;
+    }
+
+    pub fn toggle_pause(&self) {
+        let mut state = self.0.lock().unwrap();
+        state.paused = !state.paused;
+    }
+
+    /// Advance the game time by a single frame, pausing it if it is running.
+    pub fn step(&self) {
+        let mut state = self.0.lock().unwrap();
+        state.paused = true;
+        state.steps += 1;
+    }
+
+    /// Clock of the game time starting now, ignoring the steps requested before.
+    pub fn clock(&self) -> GameClock {
+        GameClock {
+            control: self.clone(),
+            elapsed: Duration::ZERO,
+            steps: self.0.lock().unwrap().steps,
+        }
+    }
+}
+
+/// Game time as seen by one consumer of a [`TimeControl`], so that every consumer advances by one
+/// frame per step.
+#[derive(Debug, Clone)]
+pub struct GameClock {
+    control: TimeControl,
+    elapsed: Duration,
+    steps: u64,
+}
+
+impl GameClock {
+    /// Advance the clock by a frame lasting `dt` on the wall clock.
+    pub fn advance(&mut self, dt: Duration) -> GameTime {
+        let state = *self.control.0.lock().unwrap();
+        let running = if state.paused && self.steps < state.steps {
+            self.steps += 1;
+            true
+        } else {
+            self.steps = state.steps;
+            !state.paused
+        };
+        let dt = if running {
+            dt.mul_f32(state.time_scale)
+        } else {
+            Duration::ZERO
+        };
+        self.elapsed += dt;
+        GameTime {
+            dt,
+            elapsed: self.elapsed,
+            time_scale: state.time_scale,
+            paused: state.paused,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FRAME: Duration = Duration::from_millis(10);
+
+    #[test]
+    fn scales_pauses_and_steps_each_clock() {
+        let control = TimeControl::new();
+        let mut tick = control.clock();
+        let mut render = control.clock();
+        control.set_time_scale(0.5);
+        assert_eq!(tick.advance(FRAME).dt, Duration::from_millis(5));
+
+        control.set_paused(true);
+        assert_eq!(tick.advance(FRAME).dt, Duration::ZERO);
+        control.step();
+        control.step();
+        for clock in [&mut tick, &mut render] {
+            assert_eq!(clock.advance(FRAME).dt, Duration::from_millis(5));
+            assert_eq!(clock.advance(FRAME).dt, Duration::from_millis(5));
+            assert_eq!(clock.advance(FRAME).dt, Duration::ZERO);
+        }
+        assert_eq!(tick.advance(FRAME).elapsed, Duration::from_millis(15));
+    }
+}
@@ -1,27 +1,25 @@
-use std::fs::File;
+use std::{fs::File, io};
 
-use eyre::Result;
+use eyre::{Context, Result};
 use tracing_error::ErrorLayer;
+use tracing_subscriber::fmt::{format::FmtSpan, MakeWriter};
 use tracing_subscriber::util::SubscriberInitExt;
-use tracing_subscriber::{fmt::format::FmtSpan, layer::SubscriberExt, EnvFilter, Layer};
+use tracing_subscriber::{layer::SubscriberExt, EnvFilter, Layer, Registry};
 
-pub fn enable() -> Result<()> {
+use crate::config::{LogTarget, LoggingConfig};
+use crate::log_capture::CaptureLayer;
+
+type BoxedLayer = Box<dyn Layer<Registry> + Send + Sync>;
+
+pub fn enable(config: &LoggingConfig) -> Result<()> {
+    if !config.install {
+        return Ok(());
+    }
     color_eyre::install()?;
-    let fmt_layer =
-        tracing_subscriber::fmt::Layer::default().with_filter(EnvFilter::from_default_env());
-    let json_layer = tracing_subscriber::fmt::Layer::default()
-        .json()
-        .with_file(true)
-        .with_level(true)
-        .with_line_number(true)
-        .with_thread_names(true)
-        .with_thread_ids(true)
-        .with_span_events(FmtSpan::ENTER | FmtSpan::EXIT)
-        .with_writer(File::create("log.jsonl").unwrap());
     let registry = tracing_subscriber::registry()
+        .with(output_layer(config)?)
         .with(ErrorLayer::default())
-        .with(fmt_layer)
-        .with(json_layer);
+        .with(CaptureLayer::new().with_filter(EnvFilter::from_default_env()));
     #[cfg(feature = "tracy")]
     let registry = {
         let tracy_layer = tracing_tracy::TracyLayer::new();
@@ -30,3 +28,42 @@ pub fn enable() -> Result<()> {
     registry.init();
     Ok(())
 }
+
+fn output_layer(config: &LoggingConfig) -> Result<Option<BoxedLayer>> {
+    let layer: BoxedLayer = match &config.target {
+        LogTarget::None => return Ok(None),
+        LogTarget::Stdout if config.json => Box::new(json_layer(io::stdout)),
+        LogTarget::Stdout => Box::new(tracing_subscriber::fmt::Layer::default()),
+        LogTarget::File(path) => {
+            let file = File::create(path)
+                .with_context(|| format!("Creating log file {}", path.display()))?;
+            if config.json {
+                Box::new(json_layer(file))
+            } else {
+                Box::new(
+                    tracing_subscriber::fmt::Layer::default()
+                        .with_ansi(false)
+                        .with_writer(file),
+                )
+            }
+        }
+    };
+    Ok(Some(Box::new(
+        layer.with_filter(EnvFilter::from_default_env()),
+    )))
+}
+
+fn json_layer<W>(writer: W) -> impl Layer<Registry> + Send + Sync
+where
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    tracing_subscriber::fmt::Layer::default()
+        .json()
+        .with_file(true)
+        .with_level(true)
+        .with_line_number(true)
+        .with_thread_names(true)
+        .with_thread_ids(true)
+        .with_span_events(FmtSpan::ENTER | FmtSpan::EXIT)
+        .with_writer(writer)
+}
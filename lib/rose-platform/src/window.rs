@@ -0,0 +1,88 @@
+//! Window size and configuration helpers complementing [`WindowBuilder`], which already sets the
+//! size limits (`with_min_inner_size`, `with_max_inner_size`) and decorations (`with_decorations`)
+//! from [`crate::Application::window_features`].
+
+use std::path::Path;
+
+use eyre::{Context, Result};
+use glam::{UVec2, Vec2};
+use winit::{
+    dpi::{LogicalSize, PhysicalSize},
+    window::{Icon, Window, WindowBuilder},
+};
+
+/// Size of the window, given to [`crate::Application::new`] and [`crate::Application::resize`]
+/// whenever the window is resized or moved to a display with a different scale factor.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct WindowSize {
+    /// Size of the framebuffer, in pixels.
+    pub physical: PhysicalSize<u32>,
+    /// Scale factor of the display the window is on.
+    pub scale_factor: f64,
+}
+
+impl WindowSize {
+    pub fn new(physical: PhysicalSize<u32>, scale_factor: f64) -> Self {
+        Self {
+            physical,
+            scale_factor,
+        }
+    }
+
+    /// Current size of the window.
+    pub fn of(window: &Window) -> Self {
+        Self::new(window.inner_size(), window.scale_factor())
+    }
+
+    /// Size of the window in logical units, used for input and UI.
+    pub fn logical(&self) -> LogicalSize<f32> {
+        self.physical.to_logical(self.scale_factor)
+    }
+
+    pub fn physical_vec(&self) -> UVec2 {
+        UVec2::from_array(self.physical.into())
+    }
+
+    pub fn logical_vec(&self) -> Vec2 {
+        Vec2::from_array(self.logical().into())
+    }
+}
+
+/// Load a window icon from an image file.
+pub fn load_icon(path: impl AsRef<Path>) -> Result<Icon> {
+    let path = path.as_ref();
+    let image = image::open(path)
+        .with_context(|| format!("Loading window icon {}", path.display()))?
+        .into_rgba8();
+    let (width, height) = image.dimensions();
+    Ok(Icon::from_rgba(image.into_raw(), width, height)?)
+}
+
+/// Whether the path has one of the extensions, ignoring case; for use in
+/// [`crate::Application::accepts_dropped_file`].
+pub fn has_extension(path: &Path, extensions: &[&str]) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| {
+            extensions
+                .iter()
+                .any(|candidate| candidate.eq_ignore_ascii_case(ext))
+        })
+}
+
+pub trait WindowBuilderExt {
+    /// Set the window icon from an image file, logging a warning if it cannot be loaded.
+    fn with_icon_file(self, path: impl AsRef<Path>) -> Self;
+}
+
+impl WindowBuilderExt for WindowBuilder {
+    fn with_icon_file(self, path: impl AsRef<Path>) -> Self {
+        match load_icon(path) {
+            Ok(icon) => self.with_window_icon(Some(icon)),
+            Err(err) => {
+                tracing::warn!("Cannot set window icon: {:?}", err);
+                self
+            }
+        }
+    }
+}
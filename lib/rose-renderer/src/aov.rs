@@ -0,0 +1,121 @@
+//! Arbitrary output variables (AOVs): the lit scene along with the G-Buffer channels it was shaded
+//! from, downloaded from the GPU for lookdev comparisons and post work in external tools. See
+//! [`crate::Renderer::capture_aovs`].
+
+use std::path::Path;
+
+use eyre::Result;
+use glam::{UVec2, Vec3};
+
+/// Lit scene and G-Buffer channels of a frame, row by row starting from the top.
+#[derive(Debug, Clone)]
+pub struct Aovs {
+    pub size: UVec2,
+    /// Lit scene in linear HDR, before post-processing.
+    pub color: Vec<[f32; 3]>,
+    /// Linear base color.
+    pub albedo: Vec<[f32; 3]>,
+    /// World-space normal, zero where nothing was drawn.
+    pub normal: Vec<[f32; 3]>,
+    /// Distance from the camera plane, infinite where nothing was drawn.
+    pub depth: Vec<f32>,
+    pub roughness: Vec<f32>,
+    pub metallic: Vec<f32>,
+}
+
+impl Aovs {
+    /// Channels of each AOV, named after the multi-layer EXR convention of `layer.channel`; the
+    /// lit scene is the unnamed main layer.
+    pub fn channels(&self) -> Vec<(&'static str, Vec<f32>)> {
+        let component = |pixels: &[[f32; 3]], i: usize| pixels.iter().map(|p| p[i]).collect();
+        vec![
+            ("R", component(&self.color, 0)),
+            ("G", component(&self.color, 1)),
+            ("B", component(&self.color, 2)),
+            ("albedo.R", component(&self.albedo, 0)),
+            ("albedo.G", component(&self.albedo, 1)),
+            ("albedo.B", component(&self.albedo, 2)),
+            ("normal.X", component(&self.normal, 0)),
+            ("normal.Y", component(&self.normal, 1)),
+            ("normal.Z", component(&self.normal, 2)),
+            ("depth.Z", self.depth.clone()),
+            ("roughness.Y", self.roughness.clone()),
+            ("metallic.Y", self.metallic.clone()),
+        ]
+    }
+
+    /// Write every AOV into a single multi-layer EXR file.
+    pub fn write_exr(&self, path: impl AsRef<Path>) -> Result<()> {
+        use exr::prelude::*;
+
+        let channels = self
+            .channels()
+            .into_iter()
+            .map(|(name, samples)| AnyChannel::new(name, FlatSamples::F32(samples)))
+            .collect::<Vec<_>>();
+        let layer = Layer::new(
+            (self.size.x as usize, self.size.y as usize),
+            LayerAttributes::default(),
+            Encoding::FAST_LOSSLESS,
+            AnyChannels::sort(channels.into()),
+        );
+        Image::from_layer(layer).write().to_file(path)?;
+        Ok(())
+    }
+}
+
+/// Normal stored in the G-Buffer, the inverse of `encode_normal` in `common/gbuffer_normal.glsl`.
+pub fn decode_normal(encoded: Vec3, octahedral: bool) -> Vec3 {
+    if !octahedral {
+        return (encoded * 2. - 1.).normalize_or_zero();
+    }
+    let f = encoded.truncate() * 2. - 1.;
+    let mut n = Vec3::new(f.x, f.y, 1. - f.x.abs() - f.y.abs());
+    let t = (-n.z).max(0.);
+    n.x += if n.x >= 0. { -t } else { t };
+    n.y += if n.y >= 0. { -t } else { t };
+    n.normalize_or_zero()
+}
+
+/// Reorder the rows of a downloaded texture, which start from the bottom, to start from the top.
+pub(crate) fn flip_rows<T: Copy>(pixels: Vec<T>, width: usize) -> Vec<T> {
+    pixels.chunks(width).rev().flatten().copied().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec2;
+
+    use super::*;
+
+    fn encode_octahedral(n: Vec3) -> Vec3 {
+        let n = n / (n.x.abs() + n.y.abs() + n.z.abs());
+        let e = if n.z >= 0. {
+            n.truncate()
+        } else {
+            let sign = Vec2::new(n.x.signum(), n.y.signum());
+            (1. - Vec2::new(n.y.abs(), n.x.abs())) * sign
+        };
+        (e * 0.5 + 0.5).extend(0.)
+    }
+
+    #[test]
+    fn normals_round_trip() {
+        for n in [
+            Vec3::new(0.3, -0.5, 0.8),
+            Vec3::new(-0.6, 0.2, -0.7),
+            Vec3::NEG_Z,
+        ] {
+            let n = n.normalize();
+            let plain = decode_normal(n * 0.5 + 0.5, false);
+            assert!(plain.abs_diff_eq(n, 1e-5), "{plain} != {n}");
+            let octahedral = decode_normal(encode_octahedral(n), true);
+            assert!(octahedral.abs_diff_eq(n, 1e-5), "{octahedral} != {n}");
+        }
+    }
+
+    #[test]
+    fn rows_start_from_the_top() {
+        assert_eq!(flip_rows(vec![1, 2, 3, 4, 5, 6], 2), [5, 6, 3, 4, 1, 2]);
+    }
+}
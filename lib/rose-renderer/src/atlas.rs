@@ -0,0 +1,216 @@
+//! Texture atlas of the sprites, packing small images into a single texture so that their sprites
+//! are drawn in a single draw call, see [`crate::Renderer::submit_atlas_sprite`].
+
+use std::{collections::HashMap, num::NonZeroU32};
+
+use eyre::Result;
+use glam::{IVec2, UVec2, Vec4};
+
+use rose_core::{
+    atlas::{AtlasId, AtlasPacker, AtlasRect},
+    gl_ext,
+};
+use violette::texture::{Dimension, SampleMode, Texture};
+
+/// Side of the sprite atlas, in texels.
+pub const SPRITE_ATLAS_SIZE: u32 = 2048;
+/// Mipmap levels of the atlas. Allocations are aligned to the texels of the smallest level, so
+/// that downsampling never mixes the texels of two images.
+const ATLAS_MIP_LEVELS: u32 = 3;
+const ATLAS_ALIGNMENT: u32 = 1 << (ATLAS_MIP_LEVELS - 1);
+/// Copies of the edge texels around each image, so that filtering does not bleed the texels of
+/// neighbouring images nor fade to the padding.
+const ATLAS_PADDING: u32 = ATLAS_ALIGNMENT;
+
+/// Images packed into a texture, with the least recently used ones evicted to make room.
+#[derive(Debug)]
+pub struct TextureAtlas {
+    packer: AtlasPacker,
+    /// Allocated with the first image, as most scenes have no sprites.
+    texture: Option<Texture<[f32; 4]>>,
+    /// Rectangles of the images in the allocations, without the padding.
+    images: HashMap<AtlasId, AtlasRect>,
+    /// Whether images were uploaded since the mipmaps were last generated.
+    mipmaps_outdated: bool,
+}
+
+impl TextureAtlas {
+    pub fn new(size: UVec2) -> Self {
+        Self {
+            packer: AtlasPacker::new(size),
+            texture: None,
+            images: HashMap::new(),
+            mipmaps_outdated: false,
+        }
+    }
+
+    /// Texture of the atlas, once an image was inserted.
+    pub fn texture(&self) -> Option<&Texture<[f32; 4]>> {
+        self.texture.as_ref()
+    }
+
+    pub fn packer(&self) -> &AtlasPacker {
+        &self.packer
+    }
+
+    /// Upload the image of `size` texels, given as sRGB encoded colors with linear alpha, into the
+    /// atlas, evicting images not used this frame if needed. Returns the id of the image along
+    /// with the evicted ones, or `None` when there is no room for it.
+    pub fn insert(
+        &mut self,
+        size: UVec2,
+        pixels: &[[f32; 4]],
+    ) -> Result<Option<(AtlasId, Vec<AtlasId>)>> {
+        // Rounded up to the alignment, keeping the allocations aligned as they tile the atlas
+        let padded = size + 2 * ATLAS_PADDING;
+        let padded = (padded + ATLAS_ALIGNMENT - 1) / ATLAS_ALIGNMENT * ATLAS_ALIGNMENT;
+        let Some((id, rect, evicted)) = self.packer.insert_evicting(padded) else {
+            return Ok(None);
+        };
+        if !evicted.is_empty() {
+            tracing::debug!(
+                message = "Evicted images from the atlas",
+                count = evicted.len()
+            );
+        }
+        for evicted in &evicted {
+            self.images.remove(evicted);
+        }
+        if self.texture.is_none() {
+            self.texture = Some(create_texture(self.packer.size())?);
+        }
+        let min = rect.min.as_ivec2();
+        let padded_size = rect.size.as_ivec2();
+        let padded_pixels = pad_edges(size, pixels, rect.size);
+        self.texture.as_ref().unwrap().set_sub_data_2d(
+            0,
+            min.x,
+            min.y,
+            padded_size.x,
+            padded_size.y,
+            &padded_pixels,
+        )?;
+        self.images
+            .insert(id, AtlasRect::new(rect.min + ATLAS_PADDING, size));
+        self.mipmaps_outdated = true;
+        Ok(Some((id, evicted)))
+    }
+
+    pub fn remove(&mut self, id: AtlasId) -> bool {
+        self.images.remove(&id);
+        self.packer.remove(id).is_some()
+    }
+
+    pub fn contains(&self, id: AtlasId) -> bool {
+        self.images.contains_key(&id)
+    }
+
+    /// Mark the image as used this frame, protecting it from eviction by the images inserted
+    /// before it is drawn.
+    pub fn touch(&mut self, id: AtlasId) {
+        self.packer.touch(id);
+    }
+
+    /// Texture coordinates of the image, marking it as used this frame.
+    pub fn use_image(&mut self, id: AtlasId) -> Option<Vec4> {
+        self.packer.touch(id);
+        Some(self.images.get(&id)?.uv_rect(self.packer.size()))
+    }
+
+    /// Start a new frame, generating the mipmaps of the images uploaded since the last one.
+    pub(crate) fn next_frame(&mut self) -> Result<()> {
+        self.packer.next_frame();
+        if let Some(texture) = self.texture.as_ref().filter(|_| self.mipmaps_outdated) {
+            texture.generate_mipmaps()?;
+        }
+        self.mipmaps_outdated = false;
+        Ok(())
+    }
+
+    /// Occupancy of the atlas, drawing the images in green and the free space in outlines.
+    #[cfg(feature = "debug-ui")]
+    pub fn ui(&self, ui: &mut egui::Ui) {
+        use egui::{vec2, Color32, Rect, Stroke};
+
+        ui.label(format!(
+            "{} images, {:.1} % occupied",
+            self.packer.len(),
+            self.packer.occupancy() * 100.
+        ));
+        const SIDE: f32 = 256.;
+        let (rect, _) = ui.allocate_exact_size(vec2(SIDE, SIDE), egui::Sense::hover());
+        let scale = SIDE / self.packer.size().max_element() as f32;
+        let to_screen = |min: UVec2, size: UVec2| {
+            let min = min.as_vec2() * scale;
+            let size = size.as_vec2() * scale;
+            Rect::from_min_size(rect.min + vec2(min.x, min.y), vec2(size.x, size.y))
+        };
+        let painter = ui.painter_at(rect);
+        painter.rect_filled(rect, 0., Color32::from_gray(16));
+        for (_, allocated) in self.packer.allocated() {
+            painter.rect_filled(
+                to_screen(allocated.min, allocated.size),
+                0.,
+                Color32::from_rgb(64, 160, 64),
+            );
+        }
+        for free in self.packer.free_rects() {
+            painter.rect_stroke(
+                to_screen(free.min, free.size).shrink(0.5),
+                0.,
+                Stroke::new(1., Color32::from_gray(96)),
+            );
+        }
+        painter.rect_stroke(rect, 0., Stroke::new(1., Color32::GRAY));
+    }
+}
+
+fn create_texture(size: UVec2) -> Result<Texture<[f32; 4]>> {
+    let texture = Texture::new(
+        NonZeroU32::new(size.x).unwrap(),
+        NonZeroU32::new(size.y).unwrap(),
+        NonZeroU32::new(1).unwrap(),
+        Dimension::D2,
+    );
+    gl_ext::allocate_srgb_alpha(&texture, size, ATLAS_MIP_LEVELS);
+    texture.filter_min_mipmap(SampleMode::Linear, SampleMode::Linear)?;
+    texture.filter_mag(SampleMode::Linear)?;
+    Ok(texture)
+}
+
+/// Fill `padded` texels with the image of `size` texels offset by [`ATLAS_PADDING`], and copies of
+/// its closest edge texel around it.
+fn pad_edges(size: UVec2, pixels: &[[f32; 4]], padded: UVec2) -> Vec<[f32; 4]> {
+    let max = size.as_ivec2() - 1;
+    (0..padded.y as i32)
+        .flat_map(|y| (0..padded.x as i32).map(move |x| IVec2::new(x, y)))
+        .map(|texel| {
+            let source = (texel - ATLAS_PADDING as i32).clamp(IVec2::ZERO, max);
+            pixels[(source.y * size.x as i32 + source.x) as usize]
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn padding_repeats_the_edge_texels() {
+        let pixels = [[0.; 4], [1.; 4], [2.; 4], [3.; 4]];
+        let padded = pad_edges(
+            UVec2::new(2, 2),
+            &pixels,
+            UVec2::splat(2 + 2 * ATLAS_PADDING),
+        );
+        let side = 2 + 2 * ATLAS_PADDING as usize;
+        let at = |x: usize, y: usize| padded[y * side + x][0];
+        let p = ATLAS_PADDING as usize;
+        assert_eq!(at(0, 0), 0.);
+        assert_eq!(at(p, p), 0.);
+        assert_eq!(at(p + 1, p), 1.);
+        assert_eq!(at(side - 1, 0), 1.);
+        assert_eq!(at(0, side - 1), 2.);
+        assert_eq!(at(side - 1, side - 1), 3.);
+    }
+}
@@ -0,0 +1,75 @@
+//! Static batching: merging meshes which never move and share a material into a single mesh, so
+//! that they are drawn with one draw call instead of one per mesh.
+//!
+//! Transforms are baked into the merged vertices, which are then drawn with an identity
+//! transform. Merged meshes are culled as a whole, so only meshes close to each other should be
+//! merged when culling matters.
+
+use glam::{Mat3, Mat4};
+
+use rose_core::mesh::CpuMesh;
+
+use crate::material::Vertex;
+
+/// Merge meshes into one, transforming their vertices by the matrix given with each of them.
+pub fn merge_meshes<'a>(
+    meshes: impl IntoIterator<Item = (&'a [Vertex], &'a [u32], Mat4)>,
+) -> CpuMesh<Vertex, u32> {
+    let mut merged = CpuMesh {
+        vertices: vec![],
+        indices: vec![],
+    };
+    for (vertices, indices, transform) in meshes {
+        let base = merged.vertices.len() as u32;
+        let normal_matrix = Mat3::from_mat4(transform).inverse().transpose();
+        merged.vertices.extend(vertices.iter().map(|v| {
+            let mut v = *v;
+            v.position = transform.transform_point3(v.position);
+            v.normal = (normal_matrix * v.normal).normalize_or_zero();
+            v
+        }));
+        // Mirroring transforms flip the winding of the triangles, which needs to be undone for
+        // backface culling
+        if transform.determinant() < 0. {
+            merged.indices.extend(
+                indices
+                    .chunks_exact(3)
+                    .flat_map(|tri| [tri[0], tri[2], tri[1]])
+                    .map(|i| base + i),
+            );
+        } else {
+            merged.indices.extend(indices.iter().map(|i| base + i));
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{vec2, Vec3};
+
+    use super::*;
+
+    #[test]
+    fn bakes_transforms() {
+        let vertices = [
+            Vertex::new(Vec3::ZERO, Vec3::Z, vec2(0., 0.)),
+            Vertex::new(Vec3::X, Vec3::Z, vec2(1., 0.)),
+            Vertex::new(Vec3::Y, Vec3::Z, vec2(0., 1.)),
+        ];
+        let indices = [0, 1, 2];
+        let merged = merge_meshes([
+            (&vertices[..], &indices[..], Mat4::IDENTITY),
+            (
+                &vertices[..],
+                &indices[..],
+                Mat4::from_translation(Vec3::X) * Mat4::from_scale(Vec3::new(1., 1., -2.)),
+            ),
+        ]);
+        assert_eq!(merged.vertices.len(), 6);
+        assert_eq!(merged.indices, [0, 1, 2, 3, 5, 4]);
+        assert_eq!(merged.vertices[4].position, Vec3::new(2., 0., 0.));
+        assert_eq!(merged.vertices[4].normal, Vec3::NEG_Z);
+        assert_eq!(merged.vertices[4].uv, vec2(1., 0.));
+    }
+}
@@ -1,32 +1,58 @@
+//! Bone hierarchies deforming meshes on the GPU.
+//!
+//! The bones of a mesh are uploaded each time it is drawn, as a palette of skinning transforms in
+//! the order of [`Bone::traverse`], which is the order vertices refer to bones in. Vertices are
+//! influenced by up to 4 bones, see [`crate::material::Vertex::with_influences`].
+
 use std::cell::{Cell, RefCell};
 use std::rc::{Rc, Weak};
 
 use crevice::std140::AsStd140;
-use glam::Mat4;
+use glam::{Mat4, Quat, Vec4};
 
 use violette::buffer::{BufferUsageHint, UniformBuffer};
 
+/// Size of the bone palette of the mesh vertex shader; bones past it are not uploaded.
+pub const MAX_BONES: usize = 32;
+
+/// How the transforms of the bones influencing a vertex are blended.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash)]
+pub enum Skinning {
+    /// Blend the matrices; cheap, but joints twisting or bending far collapse the mesh.
+    #[default]
+    Linear,
+    /// Blend the rotations and translations as dual quaternions, which preserves volume at joints.
+    /// The scale of the bones is ignored.
+    DualQuaternion,
+}
+
 #[derive(Debug, Clone)]
 pub struct Bone {
     parent: RefCell<Weak<Bone>>,
     pub children: RefCell<Vec<Rc<Bone>>>,
     local_transform: Cell<Mat4>,
+    /// Transform from the space of the mesh to the space of the bone in the bind pose.
+    inverse_bind: Cell<Mat4>,
 }
 
 impl Bone {
     pub fn new(transform: Mat4) -> Rc<Self> {
+        Self::with_inverse_bind(transform, Mat4::IDENTITY)
+    }
+
+    /// Create a bone whose mesh was modelled with the bone at the pose of `inverse_bind`'s inverse,
+    /// in the space of the mesh.
+    pub fn with_inverse_bind(transform: Mat4, inverse_bind: Mat4) -> Rc<Self> {
         Rc::new(Self {
             parent: RefCell::new(Weak::new()),
             children: RefCell::new(vec![]),
             local_transform: Cell::new(transform),
+            inverse_bind: Cell::new(inverse_bind),
         })
     }
 
-    fn as_std140(&self) -> Std140GpuBone {
-        Std140GpuBone {
-            transform: self.global_transform().as_std140(),
-            _pad0: Default::default(),
-        }
+    fn as_std140(&self, skinning: Skinning) -> Std140GpuBone {
+        GpuBone::new(self.skinning_transform(), skinning).as_std140()
     }
 
     pub fn add_child(self: &Rc<Self>, child: Rc<Self>) {
@@ -50,16 +76,30 @@ impl Bone {
         self.local_transform.set(update(self.local_transform.get()));
     }
 
+    pub fn set_inverse_bind(&self, inverse_bind: Mat4) {
+        self.inverse_bind.set(inverse_bind);
+    }
+
+    /// Upload the palette of the hierarchy under this bone.
     pub fn update_buffer(
         self: &Rc<Self>,
         buffer: &mut UniformBuffer<Std140GpuBone>,
+        skinning: Skinning,
     ) -> eyre::Result<()> {
         let gpu_data = self
             .traverse()
-            .map(|bone| bone.as_std140())
+            .map(|bone| bone.as_std140(skinning))
             .collect::<Vec<_>>();
-        tracing::debug!(message = "Updating bone data", len = gpu_data.len());
-        buffer.set(&gpu_data, BufferUsageHint::Stream)?;
+        if gpu_data.len() > MAX_BONES {
+            tracing::warn!(
+                message = "Too many bones, ignoring the last ones",
+                len = gpu_data.len(),
+                max = MAX_BONES
+            );
+        }
+        let gpu_data = &gpu_data[..gpu_data.len().min(MAX_BONES)];
+        tracing::trace!(message = "Updating bone data", len = gpu_data.len());
+        buffer.set(gpu_data, BufferUsageHint::Stream)?;
         Ok(())
     }
 }
@@ -72,17 +112,80 @@ impl Bone {
             self.local_transform.get()
         }
     }
+
+    /// Transform of the vertices attached to the bone, from the bind pose to the current pose.
+    pub fn skinning_transform(&self) -> Mat4 {
+        self.global_transform() * self.inverse_bind.get()
+    }
+}
+
+/// Unit dual quaternion of the rotation and translation of the transform, as its real and dual
+/// parts.
+pub fn dual_quaternion(transform: Mat4) -> (Quat, Quat) {
+    let (_, rotation, translation) = transform.to_scale_rotation_translation();
+    let real = rotation.normalize();
+    let dual = Quat::from_xyzw(translation.x, translation.y, translation.z, 0.) * real * 0.5;
+    (real, dual)
 }
 
 #[derive(Debug, Copy, Clone, AsStd140)]
 pub struct GpuBone {
     transform: Mat4,
+    real: Vec4,
+    dual: Vec4,
+    /// Non-zero to blend the dual quaternions instead of the matrices. Shared by all the bones
+    /// of a palette, which saves the vertex shaders a uniform.
+    dual_quaternion: u32,
 }
 
-impl From<Bone> for GpuBone {
-    fn from(value: Bone) -> Self {
+impl GpuBone {
+    pub fn new(transform: Mat4, skinning: Skinning) -> Self {
+        let (real, dual) = dual_quaternion(transform);
         Self {
-            transform: value.global_transform(),
+            transform,
+            real: Vec4::from(real),
+            dual: Vec4::from(dual),
+            dual_quaternion: (skinning == Skinning::DualQuaternion) as u32,
         }
     }
 }
+
+impl From<Bone> for GpuBone {
+    fn from(value: Bone) -> Self {
+        Self::new(value.skinning_transform(), Skinning::Linear)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{vec3, Vec3};
+
+    use super::*;
+
+    #[test]
+    fn dual_quaternion_transforms_like_matrix() {
+        let transform = Mat4::from_rotation_translation(
+            Quat::from_rotation_y(1.2) * Quat::from_rotation_x(-0.4),
+            vec3(1., -2., 3.),
+        );
+        let (real, dual) = dual_quaternion(transform);
+        // Translation is recovered as 2 * dual * conjugate(real)
+        let translation = Vec3::from((dual * real.conjugate()) * 2.);
+        assert!(translation.abs_diff_eq(vec3(1., -2., 3.), 1e-5));
+        let point = vec3(0.5, 1., -1.);
+        let expected = transform.transform_point3(point);
+        assert!((real * point + translation).abs_diff_eq(expected, 1e-5));
+    }
+
+    #[test]
+    fn skinning_transform_is_identity_in_bind_pose() {
+        let pose = Mat4::from_translation(Vec3::Y);
+        let root = Bone::new(Mat4::IDENTITY);
+        let child = Bone::with_inverse_bind(pose, pose.inverse());
+        root.add_child(child.clone());
+        assert!(child.skinning_transform().abs_diff_eq(Mat4::IDENTITY, 1e-6));
+        child.update_transform(|_| Mat4::from_translation(Vec3::X));
+        let moved = child.skinning_transform().transform_point3(Vec3::Y);
+        assert!(moved.abs_diff_eq(Vec3::X, 1e-6));
+    }
+}
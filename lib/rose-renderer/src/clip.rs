@@ -0,0 +1,92 @@
+//! User clip planes, cutting meshes drawn with the standard vertex shader for section views.
+//!
+//! Planes are given as equations `(normal, d)` in world space: points `p` where
+//! `dot(normal, p) + d` is negative are clipped away. The mesh vertex shader writes the distances
+//! to the planes in `gl_ClipDistance`, from positions relative to the origin of the view: planes
+//! are moved there with [`relative_planes`].
+
+use eyre::Result;
+use glam::{Vec3, Vec4};
+
+use rose_core::gl_ext;
+use violette::program::{Program, UniformLocation};
+
+/// Number of planes the mesh vertex shader clips against; planes past it are ignored. Same as
+/// `MAX_CLIP_PLANES` in the shader.
+pub const MAX_CLIP_PLANES: usize = 4;
+
+/// Equation of the plane through `point`, keeping the side `normal` points to.
+pub fn plane_equation(point: Vec3, normal: Vec3) -> Vec4 {
+    let normal = normal.normalize();
+    normal.extend(-normal.dot(point))
+}
+
+/// Plane equations for positions relative to `origin`, offset in double precision.
+pub fn relative_planes(planes: &[Vec4], origin: Vec3) -> Vec<Vec4> {
+    planes
+        .iter()
+        .map(|plane| {
+            let normal = plane.truncate();
+            let d = plane.w as f64 + normal.as_dvec3().dot(origin.as_dvec3());
+            normal.extend(d as f32)
+        })
+        .collect()
+}
+
+/// Enable the first `count` clip distances written by the vertex shaders, disabling the others.
+pub(crate) fn enable_clip_distances(count: usize) {
+    for i in 0..MAX_CLIP_PLANES {
+        gl_ext::set_clip_distance(i as u32, i < count);
+    }
+}
+
+/// Uniforms of the mesh vertex shader holding the clip planes.
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct ClipLocations {
+    count: UniformLocation,
+    planes: [UniformLocation; MAX_CLIP_PLANES],
+}
+
+impl ClipLocations {
+    pub(crate) fn new(program: &Program) -> Self {
+        Self {
+            count: program.uniform("clip_plane_count"),
+            planes: std::array::from_fn(|i| program.uniform(&format!("clip_planes[{}]", i))),
+        }
+    }
+
+    pub(crate) fn set(&self, program: &Program, planes: &[Vec4]) -> Result<()> {
+        let planes = &planes[..planes.len().min(MAX_CLIP_PLANES)];
+        program.set_uniform(self.count, planes.len() as i32)?;
+        for (location, plane) in self.planes.iter().zip(planes) {
+            program.set_uniform(*location, *plane)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plane_keeps_the_side_of_the_normal() {
+        let plane = plane_equation(Vec3::new(0., 2., 0.), Vec3::Y * 3.);
+        assert_eq!(plane, Vec4::new(0., 1., 0., -2.));
+        let distance = |p: Vec3| plane.dot(p.extend(1.));
+        assert!(distance(Vec3::new(5., 3., -1.)) > 0.);
+        assert!(distance(Vec3::new(5., 1., -1.)) < 0.);
+    }
+
+    #[test]
+    fn relative_planes_clip_the_same_points() {
+        let plane = plane_equation(Vec3::new(1., 2., 3.), Vec3::new(1., 1., 0.));
+        let origin = Vec3::new(100., -50., 20.);
+        let relative = relative_planes(&[plane], origin)[0];
+        for point in [Vec3::new(2., 3., 0.), Vec3::new(0., 1., 5.)] {
+            let world = plane.dot(point.extend(1.));
+            let shifted = relative.dot((point - origin).extend(1.));
+            assert!((world - shifted).abs() < 1e-4);
+        }
+    }
+}
@@ -0,0 +1,91 @@
+//! Light cookies: textures modulating the light of a light source, e.g. the shadow of a window
+//! frame or of foliage cast by the sun, or a patterned lamp shade around a bulb.
+//!
+//! Directional lights project planar cookies along their direction, and point lights take cube
+//! cookies laid out like the faces of [`crate::cubemap`] captures. Cookies are set per light with
+//! [`crate::Renderer::set_light_cookie`], and multiply the color of the light in the lighting pass.
+
+use std::{collections::HashMap, rc::Rc};
+
+use glam::{Mat4, Quat, Vec2};
+
+use rose_core::light::{Light, LightHandle};
+use violette::texture::Texture;
+
+/// Cookies of the lights which have one.
+pub type LightCookies = HashMap<LightHandle, LightCookie>;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum CookieProjection {
+    /// Projected along the direction of a directional light, repeating every `size` world units.
+    Planar { size: Vec2 },
+    /// Six faces of a cube around a point light, laid out in 3 columns and 2 rows in the order
+    /// of [`crate::cubemap::CUBE_FACES`].
+    Cube,
+}
+
+impl CookieProjection {
+    /// Whether cookies of this projection can be used with the light. Cookies of other lights
+    /// are ignored.
+    pub fn applies_to(&self, light: &Light) -> bool {
+        matches!(
+            (self, light),
+            (Self::Planar { .. }, Light::Directional { .. }) | (Self::Cube, Light::Point { .. })
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LightCookie {
+    pub texture: Rc<Texture<[f32; 3]>>,
+    pub projection: CookieProjection,
+    /// Orientation of the cookie in world space. Planar cookies are projected along its -Z axis,
+    /// which should be the direction of the light.
+    pub rotation: Quat,
+}
+
+impl LightCookie {
+    /// Kind of the cookie in the lighting shader: 1 for planar and 2 for cube cookies.
+    pub(crate) fn shader_kind(&self) -> i32 {
+        match self.projection {
+            CookieProjection::Planar { .. } => 1,
+            CookieProjection::Cube => 2,
+        }
+    }
+
+    /// Size of a repetition of planar cookies, in world units.
+    pub(crate) fn size(&self) -> Vec2 {
+        match self.projection {
+            CookieProjection::Planar { size } => size.max(Vec2::splat(1e-4)),
+            CookieProjection::Cube => Vec2::ONE,
+        }
+    }
+
+    /// Matrix turning world space directions into the space of the cookie.
+    pub(crate) fn world_to_cookie(&self) -> Mat4 {
+        Mat4::from_quat(self.rotation.inverse())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec3;
+
+    use super::*;
+
+    #[test]
+    fn cookies_only_apply_to_lights_of_their_projection() {
+        let point = Light::Point {
+            color: Vec3::ONE,
+            position: Vec3::ZERO,
+        };
+        let sun = Light::Directional {
+            color: Vec3::ONE,
+            dir: Vec3::NEG_Y,
+        };
+        let planar = CookieProjection::Planar { size: Vec2::ONE };
+        assert!(planar.applies_to(&sun) && !planar.applies_to(&point));
+        assert!(CookieProjection::Cube.applies_to(&point));
+        assert!(!CookieProjection::Cube.applies_to(&sun));
+    }
+}
@@ -0,0 +1,171 @@
+//! Captures of the scene into the six faces of a cube around a point, see
+//! [`crate::Renderer::render_cubemap`]. Shared by the reflection probes, and used for irradiance
+//! capture and 360° screenshots.
+
+use std::f32::consts::{FRAC_PI_2, PI};
+
+use glam::{vec4, Mat4, Vec3};
+
+use rose_core::camera::ViewUniform;
+
+/// Near and far planes of the cameras of [`crate::Renderer::render_cubemap`].
+pub const CUBEMAP_NEAR: f32 = 0.05;
+pub const CUBEMAP_FAR: f32 = 1000.;
+
+/// Forward and up vectors of each cube face, in the order of the OpenGL cubemap faces (+X, -X,
+/// +Y, -Y, +Z, -Z).
+pub const CUBE_FACES: [(Vec3, Vec3); 6] = [
+    (Vec3::X, Vec3::Y),
+    (Vec3::NEG_X, Vec3::Y),
+    (Vec3::Y, Vec3::NEG_Z),
+    (Vec3::NEG_Y, Vec3::Z),
+    (Vec3::Z, Vec3::Y),
+    (Vec3::NEG_Z, Vec3::Y),
+];
+
+/// View of the camera capturing a cube face of `resolution` pixels from `center`.
+pub fn face_view(center: Vec3, face: usize, near: f32, far: f32, resolution: u32) -> ViewUniform {
+    let (forward, up) = CUBE_FACES[face];
+    let mat_view = Mat4::look_at_rh(center, center + forward, up);
+    let mat_proj = Mat4::perspective_rh_gl(FRAC_PI_2, 1., near, far);
+    let res = resolution as f32;
+    ViewUniform {
+        mat_view,
+        mat_proj,
+        inv_view: mat_view.inverse(),
+        inv_proj: mat_proj.inverse(),
+        mat_view_relative: mat_view,
+        viewport: vec4(0., 0., res, res),
+        camera_pos: center,
+        reverse_z: 0.,
+        origin: Vec3::ZERO,
+    }
+}
+
+/// Lit scene as seen from a point, downloaded from the GPU.
+#[derive(Debug, Clone)]
+pub struct Cubemap {
+    /// Side of each face, in pixels.
+    pub resolution: u32,
+    /// Pixels of each face in the order of [`CUBE_FACES`], row by row starting from the bottom.
+    pub faces: [Vec<[f32; 3]>; 6],
+}
+
+impl Cubemap {
+    /// Color seen in the direction, from the nearest pixel.
+    pub fn sample(&self, direction: Vec3) -> Vec3 {
+        let (face, x, y) = self.texel(direction);
+        Vec3::from_array(self.faces[face][(y * self.resolution + x) as usize])
+    }
+
+    /// Cosine-weighted average of the colors over the hemisphere around `normal`, i.e. the
+    /// irradiance divided by π, which is the color of a white diffuse surface lit by the cubemap.
+    pub fn irradiance(&self, normal: Vec3) -> Vec3 {
+        let normal = normal.normalize();
+        let res = self.resolution as f32;
+        let mut sum = Vec3::ZERO;
+        for (face, pixels) in self.faces.iter().enumerate() {
+            let (forward, up) = CUBE_FACES[face];
+            let right = forward.cross(up);
+            for (i, pixel) in pixels.iter().enumerate() {
+                let (x, y) = (i as u32 % self.resolution, i as u32 / self.resolution);
+                let u = (x as f32 + 0.5) / res * 2. - 1.;
+                let v = (y as f32 + 0.5) / res * 2. - 1.;
+                let direction = forward + right * u + up * v;
+                let length = direction.length();
+                let cos = normal.dot(direction) / length;
+                if cos <= 0. {
+                    continue;
+                }
+                // Solid angle of the pixel, up to the constant area of the pixels on the face
+                let solid_angle = 1. / (length * length * length);
+                sum += Vec3::from_array(*pixel) * cos * solid_angle;
+            }
+        }
+        let pixel_area = (2. / res) * (2. / res);
+        sum * pixel_area / PI
+    }
+
+    /// Equirectangular projection of the cubemap, e.g. to save as a 360° screenshot. Pixels are
+    /// row by row starting from the top, with -Z in the middle of the image.
+    pub fn to_equirectangular(&self, width: u32, height: u32) -> Vec<[f32; 3]> {
+        (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| {
+                let longitude = ((x as f32 + 0.5) / width as f32 * 2. - 1.) * PI;
+                let latitude = (0.5 - (y as f32 + 0.5) / height as f32) * PI;
+                let direction = Vec3::new(
+                    latitude.cos() * longitude.sin(),
+                    latitude.sin(),
+                    -latitude.cos() * longitude.cos(),
+                );
+                self.sample(direction).to_array()
+            })
+            .collect()
+    }
+
+    /// Face and pixel seen in the direction.
+    fn texel(&self, direction: Vec3) -> (usize, u32, u32) {
+        let face = (0..6)
+            .max_by(|a, b| {
+                let da = CUBE_FACES[*a].0.dot(direction);
+                let db = CUBE_FACES[*b].0.dot(direction);
+                da.total_cmp(&db)
+            })
+            .unwrap();
+        let (forward, up) = CUBE_FACES[face];
+        let right = forward.cross(up);
+        let depth = forward.dot(direction);
+        let to_pixel = |ndc: f32| {
+            let pixel = (ndc * 0.5 + 0.5) * self.resolution as f32;
+            (pixel as u32).min(self.resolution - 1)
+        };
+        (
+            face,
+            to_pixel(right.dot(direction) / depth),
+            to_pixel(up.dot(direction) / depth),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cubemap(resolution: u32, color: impl Fn(usize, u32, u32) -> [f32; 3]) -> Cubemap {
+        let faces = std::array::from_fn(|face| {
+            (0..resolution * resolution)
+                .map(|i| color(face, i % resolution, i / resolution))
+                .collect()
+        });
+        Cubemap { resolution, faces }
+    }
+
+    #[test]
+    fn directions_sample_the_face_they_look_at() {
+        let cubemap = cubemap(4, |face, x, y| [face as f32, x as f32, y as f32]);
+        for (face, (forward, _)) in CUBE_FACES.iter().enumerate() {
+            assert_eq!(cubemap.sample(*forward).x, face as f32);
+        }
+        // Up and to the right on the +Z face, whose right is -X
+        assert_eq!(
+            cubemap.sample(Vec3::new(-0.6, 0.6, 1.)),
+            Vec3::new(4., 3., 3.)
+        );
+        // The projected view agrees on which pixel is on the right of the screen
+        let view = face_view(Vec3::ZERO, 4, 0.1, 10., 4);
+        let clip = view.mat_proj * view.mat_view * Vec3::new(-0.6, 0.6, 1.).extend(1.);
+        assert!(clip.x > 0. && clip.y > 0.);
+    }
+
+    #[test]
+    fn uniform_cubemap_has_uniform_irradiance() {
+        let cubemap = cubemap(16, |_, _, _| [1., 0.5, 0.]);
+        let irradiance = cubemap.irradiance(Vec3::new(1., 2., 3.));
+        assert!(irradiance.abs_diff_eq(Vec3::new(1., 0.5, 0.), 1e-2));
+
+        let panorama = cubemap.to_equirectangular(8, 4);
+        assert_eq!(panorama.len(), 32);
+        assert!(panorama.iter().all(|pixel| *pixel == [1., 0.5, 0.]));
+    }
+}
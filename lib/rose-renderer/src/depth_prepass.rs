@@ -0,0 +1,153 @@
+use std::{path::PathBuf, rc::Rc};
+
+use eyre::{Context, Result};
+use glam::Vec4;
+
+use rose_core::{
+    camera::ViewUniformBuffer,
+    transform::Transformed,
+    utils::reload_watcher::{ReloadFileProxy, ReloadWatcher},
+};
+use violette::{
+    buffer::UniformBuffer,
+    framebuffer::{DepthTestFunction, Framebuffer},
+    gl,
+    program::{Program, UniformBlockIndex, UniformLocation},
+    Cull,
+};
+
+use crate::{
+    bones::Std140GpuBone, clip::ClipLocations, morph::MorphLocations,
+    shader_material::link_program, Mesh,
+};
+
+/// Depth-only pass laying down the depth of opaque meshes before the G-Buffer is filled, so that
+/// materials only shade the visible fragments when drawn with [`DepthTestFunction::Equal`].
+///
+/// Meshes are transformed by the same vertex shader as the standard material, which declares
+/// `gl_Position` invariant so that both passes produce the same depth.
+#[derive(Debug)]
+pub struct DepthPrepass {
+    program: Program,
+    u_view: UniformBlockIndex,
+    u_bones: UniformBlockIndex,
+    u_model: UniformLocation,
+    u_morph: MorphLocations,
+    u_clip: ClipLocations,
+    /// Clip planes relative to the origin of the view, the same as the standard material's.
+    clip_planes: Vec<Vec4>,
+    bones_uniform: UniformBuffer<Std140GpuBone>,
+    reload_watcher: ReloadFileProxy,
+    vert_path: PathBuf,
+    frag_path: PathBuf,
+}
+
+impl DepthPrepass {
+    pub fn new(reload_watcher: &ReloadWatcher) -> Result<Self> {
+        let vert_path = reload_watcher.base_path().join("mesh/mesh.vert.glsl");
+        let frag_path = reload_watcher.base_path().join("mesh/depth.frag.glsl");
+        let (program, files) =
+            link_program(&vert_path, &frag_path).context("Loading depth pre-pass program")?;
+        Ok(Self {
+            u_view: program.uniform_block("View"),
+            u_bones: program.uniform_block("Bones"),
+            u_model: program.uniform("model"),
+            u_morph: MorphLocations::new(&program),
+            u_clip: ClipLocations::new(&program),
+            clip_planes: vec![],
+            program,
+            bones_uniform: UniformBuffer::new(),
+            reload_watcher: reload_watcher.proxy(files.iter().map(|p| p.as_path())),
+            vert_path,
+            frag_path,
+        })
+    }
+
+    /// Draw the depth of the meshes into the framebuffer with the depth test of the view, leaving
+    /// its color attachments untouched. Meshes are given in groups along with whether they are
+    /// double-sided, which disables culling for them.
+    #[tracing::instrument(skip_all)]
+    pub fn draw<'a>(
+        &mut self,
+        frame: &Framebuffer,
+        view: &ViewUniformBuffer,
+        depth_test: DepthTestFunction,
+        groups: impl IntoIterator<Item = (bool, &'a [Transformed<Rc<Mesh>>])>,
+    ) -> Result<()> {
+        Framebuffer::enable_depth_test(depth_test);
+        unsafe { gl::ColorMask(gl::FALSE, gl::FALSE, gl::FALSE, gl::FALSE) };
+        let result = self.draw_groups(frame, view, groups);
+        unsafe { gl::ColorMask(gl::TRUE, gl::TRUE, gl::TRUE, gl::TRUE) };
+        result
+    }
+
+    fn draw_groups<'a>(
+        &mut self,
+        frame: &Framebuffer,
+        view: &ViewUniformBuffer,
+        groups: impl IntoIterator<Item = (bool, &'a [Transformed<Rc<Mesh>>])>,
+    ) -> Result<()> {
+        self.bind(view)?;
+        for (double_sided, meshes) in groups {
+            if double_sided {
+                violette::culling(None);
+            }
+            for mesh in meshes {
+                self.draw_mesh(frame, mesh)?;
+            }
+            if double_sided {
+                violette::culling(Some(Cull::Back));
+            }
+        }
+        Ok(())
+    }
+
+    /// Reload the program if needed and bind the view uniform; call before [`Self::draw_mesh`].
+    pub(crate) fn bind(&mut self, view: &ViewUniformBuffer) -> Result<()> {
+        self.reload_if_needed();
+        self.program
+            .bind_block(&view.slice(0..=0), self.u_view, 0)?;
+        self.program
+            .bind_block(&self.bones_uniform.slice(..), self.u_bones, 2)?;
+        self.u_clip.set(&self.program, &self.clip_planes)?;
+        Ok(())
+    }
+
+    pub(crate) fn draw_mesh(
+        &mut self,
+        frame: &Framebuffer,
+        mesh: &Transformed<Rc<Mesh>>,
+    ) -> Result<()> {
+        if let Some(root_bone) = &mesh.root_bone {
+            root_bone.update_buffer(&mut self.bones_uniform, mesh.skinning)?;
+        }
+        self.program
+            .set_uniform(self.u_model, mesh.transform.matrix())?;
+        self.u_morph.bind(&self.program, mesh)?;
+        mesh.draw(&self.program, frame, false)?;
+        Ok(())
+    }
+
+    /// Clip the meshes drawn next against the planes, relative to the origin of the view (see
+    /// [`crate::clip::relative_planes`]).
+    pub fn set_clip_planes(&mut self, planes: &[Vec4]) {
+        self.clip_planes.clear();
+        self.clip_planes.extend_from_slice(planes);
+    }
+
+    fn reload_if_needed(&mut self) {
+        let reloaded =
+            self.reload_watcher
+                .reload_shader("depth pre-pass shader", &self.frag_path, || {
+                    link_program(&self.vert_path, &self.frag_path)
+                });
+        if let Some(Ok(program)) = reloaded {
+            self.u_view = program.uniform_block("View");
+            self.u_bones = program.uniform_block("Bones");
+            self.u_model = program.uniform("model");
+            self.u_morph = MorphLocations::new(&program);
+            self.u_clip = ClipLocations::new(&program);
+            self.program = program;
+        }
+    }
+}
@@ -1,10 +1,15 @@
 use std::num::NonZeroU32;
-use std::{any::Any, fmt, path::Path};
+use std::{
+    any::Any,
+    fmt,
+    path::{Path, PathBuf},
+};
 
 use eyre::{Context, Report, Result};
 use glam::{vec3, Vec3};
 
-use rose_core::utils::reload_watcher::ReloadWatcher;
+use rose_core::utils::draw_counters::CountBind;
+use rose_core::utils::reload_watcher::{ReloadFileProxy, ReloadWatcher};
 use rose_core::{camera::ViewUniformBuffer, screen_draw::ScreenDraw};
 use violette::texture::Dimension;
 use violette::{
@@ -19,6 +24,9 @@ pub struct MaterialInfo<'a> {
     pub albedo: &'a Texture<[f32; 3]>,
     pub normal_coverage: &'a Texture<[f32; 4]>,
     pub roughness_metal: &'a Texture<[f32; 2]>,
+    /// Weight of the reflection probes over the environment reflections, which environments
+    /// scale their specular contribution by `1 - weight` with.
+    pub reflection_weight: &'a Texture<f32>,
 }
 
 pub trait Environment: fmt::Debug + Any {
@@ -29,11 +37,23 @@ pub trait Environment: fmt::Debug + Any {
         mat_info: MaterialInfo,
     ) -> Result<()>;
 
+    /// Average color of the environment near the horizon, used as the inscattered color of fog.
+    fn fog_color(&self) -> Option<Vec3> {
+        None
+    }
+
+    /// Reload the environment when the files it was loaded from changed, returning whether it did.
+    fn reload_if_needed(&mut self, _reload_watcher: &ReloadWatcher) -> bool {
+        false
+    }
+
     fn as_any(&self) -> &dyn Any;
     fn as_any_mut(&mut self) -> &mut dyn Any;
 }
 
 #[derive(Debug, Copy, Clone)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serialize", serde(default))]
 pub struct SimpleSkyParams {
     pub horizon_color: Vec3,
     pub zenith_color: Vec3,
@@ -83,6 +103,7 @@ pub struct SimpleSky {
     u_ground_color: UniformLocation,
     u_albedo: UniformLocation,
     u_normal: UniformLocation,
+    u_reflection_weight: UniformLocation,
 }
 
 impl Environment for SimpleSky {
@@ -98,13 +119,22 @@ impl Environment for SimpleSky {
             draw.set_uniform(self.u_horizon_color, self.params.horizon_color)?;
             draw.set_uniform(self.u_ground_color, self.params.ground_color)?;
             draw.set_uniform(self.u_zenith_color, self.params.zenith_color)?;
-            draw.set_uniform(self.u_albedo, mat_info.albedo.as_uniform(0)?)?;
-            draw.set_uniform(self.u_normal, mat_info.normal_coverage.as_uniform(1)?)?;
+            draw.set_uniform(self.u_albedo, mat_info.albedo.as_uniform(0).counted()?)?;
+            draw.set_uniform(
+                self.u_normal,
+                mat_info.normal_coverage.as_uniform(1).counted()?,
+            )?;
+            let reflection_weight = mat_info.reflection_weight.as_uniform(2).counted()?;
+            draw.set_uniform(self.u_reflection_weight, reflection_weight)?;
         }
         self.draw.draw(frame)?;
         Ok(())
     }
 
+    fn fog_color(&self) -> Option<Vec3> {
+        Some(self.params.horizon_color)
+    }
+
     fn as_any(&self) -> &dyn Any {
         self
     }
@@ -125,6 +155,7 @@ impl SimpleSky {
         let u_ground_color = program.uniform("ground_color");
         let u_albedo = program.uniform("albedo");
         let u_normal = program.uniform("normal_map");
+        let u_reflection_weight = program.uniform("reflection_weight");
         drop(program);
         Ok(Self {
             params,
@@ -135,6 +166,7 @@ impl SimpleSky {
             u_ground_color,
             u_albedo,
             u_normal,
+            u_reflection_weight,
         })
     }
 }
@@ -152,6 +184,9 @@ pub struct EnvironmentMap {
     u_normal: UniformLocation,
     u_rough_metal: UniformLocation,
     u_specular: UniformLocation,
+    u_reflection_weight: UniformLocation,
+    path: Option<PathBuf>,
+    reload: Option<ReloadFileProxy>,
 }
 
 impl Environment for EnvironmentMap {
@@ -164,17 +199,47 @@ impl Environment for EnvironmentMap {
         {
             let draw = self.draw.program();
             draw.bind_block(&camera.slice(0..=0), self.u_view, 0)?;
-            draw.set_uniform(self.u_albedo, mat_info.albedo.as_uniform(0)?)?;
-            draw.set_uniform(self.u_normal, mat_info.normal_coverage.as_uniform(1)?)?;
-            draw.set_uniform(self.u_rough_metal, mat_info.roughness_metal.as_uniform(2)?)?;
-            draw.set_uniform(self.u_sampler, self.map.as_uniform(3)?)?;
-            draw.set_uniform(self.u_irradiance, self.irradiance_texture.as_uniform(4)?)?;
-            draw.set_uniform(self.u_specular, self.specular_ibl.as_uniform(5)?)?;
+            draw.set_uniform(self.u_albedo, mat_info.albedo.as_uniform(0).counted()?)?;
+            draw.set_uniform(
+                self.u_normal,
+                mat_info.normal_coverage.as_uniform(1).counted()?,
+            )?;
+            draw.set_uniform(
+                self.u_rough_metal,
+                mat_info.roughness_metal.as_uniform(2).counted()?,
+            )?;
+            draw.set_uniform(self.u_sampler, self.map.as_uniform(3).counted()?)?;
+            draw.set_uniform(
+                self.u_irradiance,
+                self.irradiance_texture.as_uniform(4).counted()?,
+            )?;
+            draw.set_uniform(self.u_specular, self.specular_ibl.as_uniform(5).counted()?)?;
+            let reflection_weight = mat_info.reflection_weight.as_uniform(6).counted()?;
+            draw.set_uniform(self.u_reflection_weight, reflection_weight)?;
         }
         self.draw.draw(frame)?;
         Ok(())
     }
 
+    fn reload_if_needed(&mut self, reload_watcher: &ReloadWatcher) -> bool {
+        let Some(reload) = &self.reload else { return false; };
+        let Some(path) = self.path.clone() else { return false; };
+        if !reload.should_reload() {
+            return false;
+        }
+        tracing::info!(message="Reloading environment map", path=%path.display());
+        match Self::load(&path, reload_watcher) {
+            Ok(env) => {
+                *self = env;
+                true
+            }
+            Err(err) => {
+                tracing::warn!("Cannot reload environment map: {:?}", err);
+                false
+            }
+        }
+    }
+
     fn as_any(&self) -> &dyn Any {
         self
     }
@@ -185,10 +250,26 @@ impl Environment for EnvironmentMap {
 }
 
 impl EnvironmentMap {
+    /// Extensions of the image files environment maps can be loaded from.
+    pub const EXTENSIONS: &'static [&'static str] =
+        &["jpg", "png", "bmp", "exr", "hdr", "tif", "tga"];
+
     pub fn load(filepath: impl AsRef<Path>, reload_watcher: &ReloadWatcher) -> Result<Self> {
         let filepath = filepath.as_ref();
         let map = Texture::load_rgb32f(filepath)?;
-        Self::new(map, reload_watcher)
+        let mut env = Self::new(map, reload_watcher)?;
+        env.path = Some(filepath.to_path_buf());
+        // Watched files are relative to the shaders directory otherwise
+        let watched_path = filepath
+            .canonicalize()
+            .unwrap_or_else(|_| filepath.to_path_buf());
+        env.reload = Some(reload_watcher.proxy_single(watched_path));
+        Ok(env)
+    }
+
+    /// File the map was loaded from, if any.
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
     }
 
     fn new(
@@ -205,6 +286,7 @@ impl EnvironmentMap {
         let u_normal = draw.uniform("frame_normal");
         let u_rough_metal = draw.uniform("frame_rough_metal");
         let u_specular = draw.uniform("specular_map");
+        let u_reflection_weight = draw.uniform("reflection_weight");
         drop(draw);
 
         let irradiance_texture = Self::build_irradiance_texture(
@@ -237,6 +319,9 @@ impl EnvironmentMap {
             u_normal,
             u_rough_metal,
             u_specular,
+            u_reflection_weight,
+            path: None,
+            reload: None,
         })
     }
 
@@ -259,7 +344,7 @@ impl EnvironmentMap {
         let make_irradiance = ScreenDraw::load("screen/env/irradiance.glsl", reload_watcher)?;
         make_irradiance.program().set_uniform(
             make_irradiance.program().uniform("env_map"),
-            map.as_uniform(0)?,
+            map.as_uniform(0).counted()?,
         )?;
         Framebuffer::viewport(0, 0, width.get() as _, height.get() as _);
         make_irradiance.draw(&irradiance_fbo)?;
@@ -296,7 +381,8 @@ impl EnvironmentMap {
             Framebuffer::viewport(0, 0, mw.get() as _, mh.get() as _);
             let roughness = mip as f32 / (mipmaps as f32 - 1.);
             draw.program().set_uniform(u_roughness, roughness)?;
-            draw.program().set_uniform(u_env_map, map.as_uniform(0)?)?;
+            draw.program()
+                .set_uniform(u_env_map, map.as_uniform(0).counted()?)?;
             draw.draw(&specibl_fbo)?;
         }
 
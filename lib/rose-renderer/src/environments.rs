@@ -0,0 +1,277 @@
+//! Named environments, switched at runtime with a cross-fade, e.g. for day/night cycles or going
+//! from outdoors to indoors.
+
+use std::{any::Any, time::Duration};
+
+use eyre::Result;
+use glam::Vec3;
+
+use rose_core::{camera::ViewUniformBuffer, utils::reload_watcher::ReloadWatcher};
+use violette::{
+    framebuffer::{Blend, Framebuffer},
+    gl,
+};
+
+use crate::env::{Environment, MaterialInfo};
+
+/// Name of the environment set with [`crate::Renderer::set_environment`] when none was registered.
+pub const DEFAULT_ENVIRONMENT: &str = "default";
+
+#[derive(Debug, Copy, Clone)]
+struct Transition {
+    from: usize,
+    elapsed: Duration,
+    duration: Duration,
+}
+
+impl Transition {
+    /// Progress of the transition, from 0 to 1.
+    fn amount(&self) -> f32 {
+        (self.elapsed.as_secs_f32() / self.duration.as_secs_f32()).min(1.)
+    }
+}
+
+/// Registry of named environments, showing one at a time. Switching environments blends both
+/// over the duration of the transition; it is itself an [`Environment`] drawing the blend.
+#[derive(Debug, Default)]
+pub struct Environments {
+    entries: Vec<(String, Box<dyn Environment>)>,
+    current: Option<usize>,
+    transition: Option<Transition>,
+    /// Duration of the transitions started from the UI.
+    #[cfg(feature = "debug-ui")]
+    ui_duration: f32,
+}
+
+impl Environments {
+    /// Add an environment, replacing the one with the same name. The first environment added is
+    /// shown right away.
+    pub fn insert(&mut self, name: impl Into<String>, env: Box<dyn Environment>) {
+        let name = name.into();
+        match self.position(&name) {
+            Some(ix) => self.entries[ix].1 = env,
+            None => {
+                self.entries.push((name, env));
+                self.current.get_or_insert(self.entries.len() - 1);
+            }
+        }
+    }
+
+    /// Remove the environment. Removing the current environment leaves none shown.
+    pub fn remove(&mut self, name: &str) -> Option<Box<dyn Environment>> {
+        let ix = self.position(name)?;
+        self.transition = None;
+        self.current = match self.current {
+            Some(current) if current == ix => None,
+            Some(current) if current > ix => Some(current - 1),
+            current => current,
+        };
+        Some(self.entries.remove(ix).1)
+    }
+
+    pub fn names(&self) -> impl '_ + Iterator<Item = &str> {
+        self.entries.iter().map(|(name, _)| name.as_str())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&dyn Environment> {
+        let ix = self.position(name)?;
+        Some(&*self.entries[ix].1)
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut dyn Environment> {
+        let ix = self.position(name)?;
+        Some(&mut *self.entries[ix].1)
+    }
+
+    pub fn current_name(&self) -> Option<&str> {
+        Some(self.entries[self.current?].0.as_str())
+    }
+
+    /// Environment shown, or being transitioned to.
+    pub fn current(&self) -> Option<&dyn Environment> {
+        Some(&*self.entries[self.current?].1)
+    }
+
+    pub fn current_mut(&mut self) -> Option<&mut dyn Environment> {
+        Some(&mut *self.entries[self.current?].1)
+    }
+
+    /// Show the environment, cross-fading from the current one over `duration`. Switching during
+    /// a transition starts a new one from the environment being transitioned to.
+    pub fn switch_to(&mut self, name: &str, duration: Duration) -> Result<()> {
+        let Some(ix) = self.position(name) else { eyre::bail!("Unknown environment {:?}", name); };
+        if self.current == Some(ix) {
+            return Ok(());
+        }
+        self.transition = match self.current {
+            Some(from) if !duration.is_zero() => Some(Transition {
+                from,
+                elapsed: Duration::ZERO,
+                duration,
+            }),
+            _ => None,
+        };
+        self.current = Some(ix);
+        Ok(())
+    }
+
+    pub fn is_transitioning(&self) -> bool {
+        self.transition.is_some()
+    }
+
+    /// Advance the transition, returning whether one is running.
+    pub fn advance(&mut self, dt: Duration) -> bool {
+        let Some(transition) = &mut self.transition else { return false; };
+        transition.elapsed += dt;
+        if transition.elapsed >= transition.duration {
+            self.transition = None;
+        }
+        true
+    }
+
+    fn position(&self, name: &str) -> Option<usize> {
+        self.entries.iter().position(|(n, _)| n == name)
+    }
+
+    #[cfg(feature = "debug-ui")]
+    /// Returns whether a switch to another environment was started.
+    pub fn ui(&mut self, ui: &mut egui::Ui) -> bool {
+        let current = self.current_name().unwrap_or("<None>").to_string();
+        let mut selected = None;
+        egui::ComboBox::from_label("Environment")
+            .selected_text(&current)
+            .show_ui(ui, |ui| {
+                for name in self.names() {
+                    if ui.selectable_label(name == current, name).clicked() {
+                        selected = Some(name.to_string());
+                    }
+                }
+            });
+        ui.add(
+            egui::Slider::new(&mut self.ui_duration, 0.0..=10.0)
+                .text("Transition")
+                .suffix(" s"),
+        );
+        let Some(name) = selected else { return false; };
+        let duration = Duration::from_secs_f32(self.ui_duration);
+        match self.switch_to(&name, duration) {
+            Ok(()) => true,
+            Err(err) => {
+                tracing::error!("Cannot switch environment: {}", err);
+                false
+            }
+        }
+    }
+}
+
+/// Draw the environment with its contribution scaled by `weight`, on top of additive blending.
+fn draw_weighted(
+    env: &mut dyn Environment,
+    weight: f32,
+    frame: &Framebuffer,
+    camera: &ViewUniformBuffer,
+    mat_info: MaterialInfo,
+) -> Result<()> {
+    unsafe {
+        gl::BlendColor(weight, weight, weight, weight);
+        gl::BlendFunc(gl::CONSTANT_COLOR, gl::ONE);
+    }
+    let result = env.draw(frame, camera, mat_info);
+    Framebuffer::enable_blending(Blend::One, Blend::One);
+    result
+}
+
+impl Environment for Environments {
+    fn draw(
+        &mut self,
+        frame: &Framebuffer,
+        camera: &ViewUniformBuffer,
+        mat_info: MaterialInfo,
+    ) -> Result<()> {
+        let Some(current) = self.current else { return Ok(()); };
+        match self.transition {
+            Some(transition) => {
+                let amount = transition.amount();
+                let from = &mut *self.entries[transition.from].1;
+                draw_weighted(from, 1. - amount, frame, camera, mat_info)?;
+                let to = &mut *self.entries[current].1;
+                draw_weighted(to, amount, frame, camera, mat_info)
+            }
+            None => self.entries[current].1.draw(frame, camera, mat_info),
+        }
+    }
+
+    fn fog_color(&self) -> Option<Vec3> {
+        let to = self.current()?.fog_color();
+        let Some(transition) = self.transition else { return to; };
+        match (self.entries[transition.from].1.fog_color(), to) {
+            (Some(from), Some(to)) => Some(from.lerp(to, transition.amount())),
+            (from, to) => to.or(from),
+        }
+    }
+
+    fn reload_if_needed(&mut self, reload_watcher: &ReloadWatcher) -> bool {
+        let mut reloaded = false;
+        for (_, env) in &mut self.entries {
+            reloaded |= env.reload_if_needed(reload_watcher);
+        }
+        reloaded
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug)]
+    struct Flat(Vec3);
+
+    impl Environment for Flat {
+        fn draw(&mut self, _: &Framebuffer, _: &ViewUniformBuffer, _: MaterialInfo) -> Result<()> {
+            Ok(())
+        }
+
+        fn fog_color(&self) -> Option<Vec3> {
+            Some(self.0)
+        }
+
+        fn as_any(&self) -> &dyn Any {
+            self
+        }
+
+        fn as_any_mut(&mut self) -> &mut dyn Any {
+            self
+        }
+    }
+
+    #[test]
+    fn cross_fades_fog_color() {
+        let mut envs = Environments::default();
+        envs.insert("day", Box::new(Flat(Vec3::ONE)));
+        envs.insert("night", Box::new(Flat(Vec3::ZERO)));
+        assert_eq!(envs.current_name(), Some("day"));
+        assert!(envs.switch_to("dusk", Duration::ZERO).is_err());
+
+        envs.switch_to("night", Duration::from_secs(2)).unwrap();
+        assert!(envs.advance(Duration::from_secs(1)));
+        assert_eq!(envs.fog_color(), Some(Vec3::splat(0.5)));
+        assert!(envs.advance(Duration::from_secs(1)));
+        assert!(!envs.is_transitioning());
+        assert_eq!(envs.fog_color(), Some(Vec3::ZERO));
+
+        envs.remove("day");
+        assert_eq!(envs.current_name(), Some("night"));
+    }
+}
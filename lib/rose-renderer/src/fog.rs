@@ -0,0 +1,160 @@
+use eyre::{Context, Result};
+use glam::Vec3;
+
+use rose_core::utils::draw_counters::CountBind;
+use rose_core::{
+    camera::ViewUniformBuffer, screen_draw::ScreenDraw, utils::reload_watcher::ReloadWatcher,
+};
+use violette::{
+    framebuffer::{Blend, Framebuffer},
+    program::{UniformBlockIndex, UniformLocation},
+    texture::{DepthStencil, Texture},
+};
+
+/// Exponential height fog, where the density decreases exponentially with altitude.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serialize", serde(default))]
+pub struct FogParams {
+    /// Density of the fog at `height`, in inverse world units.
+    pub density: f32,
+    /// World-space height at which the fog has the given density.
+    pub height: f32,
+    /// How fast the density decreases with height; zero gives a uniform fog.
+    pub height_falloff: f32,
+    /// Color of the light scattered towards the camera by the fog.
+    pub color: Vec3,
+    /// Take the inscattered color from the environment when it provides one, instead of `color`.
+    pub color_from_environment: bool,
+    /// Distance at which pixels without geometry, such as the sky, are fogged.
+    pub max_distance: f32,
+}
+
+impl Default for FogParams {
+    fn default() -> Self {
+        Self {
+            density: 0.02,
+            height: 0.,
+            height_falloff: 0.2,
+            color: Vec3::new(0.6, 0.7, 0.8),
+            color_from_environment: false,
+            max_distance: 1000.,
+        }
+    }
+}
+
+impl FogParams {
+    #[cfg(feature = "debug-ui")]
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        use egui::{DragValue, Grid};
+
+        Grid::new("fog-params").num_columns(2).show(ui, |ui| {
+            let density_label = ui.label("Density").id;
+            ui.add(
+                DragValue::new(&mut self.density)
+                    .clamp_range(0.0..=10.)
+                    .speed(1e-3),
+            )
+            .labelled_by(density_label);
+            ui.end_row();
+
+            let height_label = ui.label("Height").id;
+            ui.add(DragValue::new(&mut self.height).speed(0.1))
+                .labelled_by(height_label);
+            ui.end_row();
+
+            let falloff_label = ui.label("Height falloff").id;
+            ui.add(
+                DragValue::new(&mut self.height_falloff)
+                    .clamp_range(0.0..=10.)
+                    .speed(1e-2),
+            )
+            .labelled_by(falloff_label);
+            ui.end_row();
+
+            let color_label = ui.label("Color").id;
+            ui.horizontal(|ui| {
+                ui.color_edit_button_rgb(self.color.as_mut());
+                ui.checkbox(&mut self.color_from_environment, "From environment");
+            })
+            .response
+            .labelled_by(color_label);
+            ui.end_row();
+
+            let distance_label = ui.label("Sky distance").id;
+            ui.add(
+                DragValue::new(&mut self.max_distance)
+                    .clamp_range(1.0..=f32::INFINITY)
+                    .speed(1.),
+            )
+            .labelled_by(distance_label);
+            ui.end_row();
+        });
+    }
+}
+
+/// Fullscreen pass applying height fog over the lit scene, using positions reconstructed from the
+/// depth buffer.
+#[derive(Debug)]
+pub struct Fog {
+    draw: ScreenDraw,
+    u_view: UniformBlockIndex,
+    u_depth: UniformLocation,
+    u_density: UniformLocation,
+    u_height: UniformLocation,
+    u_height_falloff: UniformLocation,
+    u_color: UniformLocation,
+    u_max_distance: UniformLocation,
+}
+
+impl Fog {
+    pub fn new(reload_watcher: &ReloadWatcher) -> Result<Self> {
+        let draw =
+            ScreenDraw::load("screen/fog.glsl", reload_watcher).context("Loading fog shader")?;
+        let program = draw.program();
+        let u_view = program.uniform_block("View");
+        let u_depth = program.uniform("depth");
+        let u_density = program.uniform("density");
+        let u_height = program.uniform("height");
+        let u_height_falloff = program.uniform("height_falloff");
+        let u_color = program.uniform("color");
+        let u_max_distance = program.uniform("max_distance");
+        drop(program);
+        Ok(Self {
+            draw,
+            u_view,
+            u_depth,
+            u_density,
+            u_height,
+            u_height_falloff,
+            u_color,
+            u_max_distance,
+        })
+    }
+
+    /// Blend the fog over `frame`. `color` is the inscattered color to use.
+    #[tracing::instrument(skip_all)]
+    pub fn draw(
+        &self,
+        frame: &Framebuffer,
+        view: &ViewUniformBuffer,
+        depth: &Texture<DepthStencil<f32, ()>>,
+        params: &FogParams,
+        color: Vec3,
+    ) -> Result<()> {
+        {
+            let program = self.draw.program();
+            program.bind_block(&view.slice(0..=0), self.u_view, 0)?;
+            program.set_uniform(self.u_depth, depth.as_uniform(0).counted()?)?;
+            program.set_uniform(self.u_density, params.density)?;
+            program.set_uniform(self.u_height, params.height)?;
+            program.set_uniform(self.u_height_falloff, params.height_falloff)?;
+            program.set_uniform(self.u_color, color)?;
+            program.set_uniform(self.u_max_distance, params.max_distance)?;
+        }
+        Framebuffer::enable_blending(Blend::SrcAlpha, Blend::OneMinusSrcAlpha);
+        self.draw.draw(frame)?;
+        Framebuffer::disable_blending();
+        Ok(())
+    }
+}
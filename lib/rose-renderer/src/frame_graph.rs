@@ -0,0 +1,705 @@
+//! Frame graph of the render passes inserted between lighting and the forward passes, also used
+//! by the post processing for the bloom.
+//!
+//! Passes declare which named resources they read and write, and which transient textures they
+//! create. Every frame, the graph orders the passes so that each resource is written before it is
+//! read, skips passes whose results are never used, and allocates the transient textures, sharing
+//! one texture between transients of the same size and format whose lifetimes do not overlap.
+//! Transient textures are kept across frames, so that passes do not manage framebuffers or their
+//! lifetimes themselves.
+//!
+//! The renderer imports the lit scene as `lit`, which passes can draw into, along with the G-Buffer
+//! attachments (`position`, `albedo`, `normal`, `rough_metal`, `emission`, `coat_anisotropy`) and
+//! the `depth` buffer, which are read-only. Passes are only run when they write `lit`, a transient
+//! exported with [`FrameGraph::export`], or a transient read by a pass which is run. Exported
+//! transients stay alive until the next frame, for the renderer to read them after the passes ran.
+
+use std::{
+    any::Any,
+    collections::{BTreeSet, HashMap},
+    fmt,
+    num::NonZeroU32,
+    rc::Rc,
+};
+
+use eyre::{Context, Result};
+use glam::UVec2;
+
+use rose_core::camera::{ViewUniform, ViewUniformBuffer};
+use rose_core::utils::draw_counters::CountBind;
+use violette::{
+    framebuffer::Framebuffer,
+    program::{Program, UniformLocation},
+    texture::{DepthStencil, Dimension, SampleMode, Texture, TextureWrap},
+};
+
+use crate::gpu_profiler::GpuProfiler;
+
+/// Size of a transient texture.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum TextureSize {
+    /// Render size scaled by the factor, e.g. 0.5 for half resolution.
+    Scaled(f32),
+    Absolute(UVec2),
+}
+
+impl TextureSize {
+    pub fn resolve(self, render_size: UVec2) -> UVec2 {
+        match self {
+            Self::Scaled(scale) => (render_size.as_vec2() * scale).ceil().as_uvec2(),
+            Self::Absolute(size) => size,
+        }
+        .max(UVec2::ONE)
+    }
+}
+
+/// Format of a transient texture, following the component type of the texture passes get from
+/// [`PassContext::texture`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum TransientFormat {
+    /// [`GraphTexture::R`]
+    R32F,
+    /// [`GraphTexture::Rg`]
+    Rg32F,
+    /// [`GraphTexture::Rgb`]
+    Rgb32F,
+    /// [`GraphTexture::Rgba`]
+    Rgba32F,
+}
+
+/// Description of a transient texture, created by a pass for itself and the passes after it.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TextureDesc {
+    pub size: TextureSize,
+    pub format: TransientFormat,
+}
+
+impl TextureDesc {
+    /// Texture of the render size.
+    pub fn full(format: TransientFormat) -> Self {
+        Self {
+            size: TextureSize::Scaled(1.),
+            format,
+        }
+    }
+
+    /// Texture of the render size scaled by the factor.
+    pub fn scaled(scale: f32, format: TransientFormat) -> Self {
+        Self {
+            size: TextureSize::Scaled(scale),
+            format,
+        }
+    }
+}
+
+/// Texture of a resource, for passes to sample.
+#[derive(Debug, Copy, Clone)]
+pub enum GraphTexture<'a> {
+    R(&'a Texture<f32>),
+    Rg(&'a Texture<[f32; 2]>),
+    Rgb(&'a Texture<[f32; 3]>),
+    Rgba(&'a Texture<[f32; 4]>),
+    Depth(&'a Texture<DepthStencil<f32, ()>>),
+}
+
+impl<'a> GraphTexture<'a> {
+    /// Bind the texture to the texture unit and set it as the sampler uniform of the program.
+    pub fn set_uniform(
+        self,
+        program: &Program,
+        location: UniformLocation,
+        unit: u32,
+    ) -> Result<()> {
+        match self {
+            Self::R(texture) => {
+                program.set_uniform(location, texture.as_uniform(unit).counted()?)?
+            }
+            Self::Rg(texture) => {
+                program.set_uniform(location, texture.as_uniform(unit).counted()?)?
+            }
+            Self::Rgb(texture) => {
+                program.set_uniform(location, texture.as_uniform(unit).counted()?)?
+            }
+            Self::Rgba(texture) => {
+                program.set_uniform(location, texture.as_uniform(unit).counted()?)?
+            }
+            Self::Depth(texture) => {
+                program.set_uniform(location, texture.as_uniform(unit).counted()?)?
+            }
+        }
+        Ok(())
+    }
+
+    pub fn size(self) -> UVec2 {
+        match self {
+            Self::R(texture) => texture.size_vec().truncate(),
+            Self::Rg(texture) => texture.size_vec().truncate(),
+            Self::Rgb(texture) => texture.size_vec().truncate(),
+            Self::Rgba(texture) => texture.size_vec().truncate(),
+            Self::Depth(texture) => texture.size_vec().truncate(),
+        }
+    }
+}
+
+/// Resource owned outside of the graph, e.g. a G-Buffer attachment.
+#[derive(Debug, Copy, Clone)]
+pub struct ImportedResource<'a> {
+    pub texture: GraphTexture<'a>,
+    /// Framebuffer drawing into the texture, for resources which passes can write.
+    pub framebuffer: Option<&'a Framebuffer>,
+}
+
+/// Resources a pass uses, declared in [`GraphPass::setup`].
+#[derive(Debug, Clone, Default)]
+pub struct PassBuilder {
+    render_size: UVec2,
+    reads: Vec<String>,
+    writes: Vec<String>,
+    creates: Vec<(String, TextureDesc)>,
+}
+
+impl PassBuilder {
+    /// Size of the frame, which [`TextureSize::Scaled`] transients are relative to.
+    pub fn render_size(&self) -> UVec2 {
+        self.render_size
+    }
+
+    /// Sample the resource, written by the passes before this one.
+    pub fn read(&mut self, name: impl Into<String>) -> &mut Self {
+        self.reads.push(name.into());
+        self
+    }
+
+    /// Draw into the resource, after the passes writing it before this one.
+    pub fn write(&mut self, name: impl Into<String>) -> &mut Self {
+        self.writes.push(name.into());
+        self
+    }
+
+    /// Create a transient texture, which this pass writes first.
+    pub fn create(&mut self, name: impl Into<String>, desc: TextureDesc) -> &mut Self {
+        self.creates.push((name.into(), desc));
+        self
+    }
+
+    fn is_writing(&self, name: &str) -> bool {
+        self.writes.iter().any(|n| n == name) || self.creates.iter().any(|(n, _)| n == name)
+    }
+
+    fn is_reading(&self, name: &str) -> bool {
+        self.reads.iter().any(|n| n == name)
+    }
+
+    fn resources(&self) -> impl Iterator<Item = &str> {
+        self.reads
+            .iter()
+            .chain(&self.writes)
+            .chain(self.creates.iter().map(|(n, _)| n))
+            .map(|n| n.as_str())
+    }
+}
+
+/// Render pass of the [`FrameGraph`].
+pub trait GraphPass: 'static + fmt::Debug {
+    fn name(&self) -> &str;
+
+    /// Declare the resources the pass uses this frame.
+    fn setup(&mut self, builder: &mut PassBuilder);
+
+    /// Draw the pass, with the resources it declared.
+    fn execute(&mut self, ctx: &PassContext) -> Result<()>;
+
+    fn as_any(&self) -> &dyn Any;
+
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+/// Access of a pass to the resources it declared, while it executes.
+pub struct PassContext<'a> {
+    resources: HashMap<&'a str, ImportedResource<'a>>,
+    builder: &'a PassBuilder,
+    pub render_size: UVec2,
+    pub view: &'a ViewUniformBuffer,
+    pub view_uniform: &'a ViewUniform,
+}
+
+impl<'a> PassContext<'a> {
+    /// Texture of a resource the pass reads or writes.
+    pub fn texture(&self, name: &str) -> Result<GraphTexture<'a>> {
+        match self.resources.get(name) {
+            Some(resource) => Ok(resource.texture),
+            None => eyre::bail!("Resource `{}` was not declared by the pass", name),
+        }
+    }
+
+    /// Framebuffer drawing into a resource the pass writes, setting the viewport to its size.
+    pub fn target(&self, name: &str) -> Result<&'a Framebuffer> {
+        if !self.builder.is_writing(name) {
+            eyre::bail!(
+                "Resource `{}` was not declared as written by the pass",
+                name
+            );
+        }
+        let resource = self.resources[name];
+        let Some(framebuffer) = resource.framebuffer else {
+            eyre::bail!("Resource `{}` cannot be drawn into", name);
+        };
+        let size = resource.texture.size();
+        Framebuffer::viewport(0, 0, size.x as _, size.y as _);
+        Ok(framebuffer)
+    }
+}
+
+/// Execution plan of a frame, computed from the declarations of the passes.
+#[derive(Debug, Clone, PartialEq)]
+struct Schedule {
+    /// Indices of the passes to run, in order.
+    order: Vec<usize>,
+    /// Size and format of the textures backing the transients.
+    slots: Vec<(UVec2, TransientFormat)>,
+    /// Texture of each transient.
+    slot_of: HashMap<String, usize>,
+}
+
+/// Order the passes and allocate the transients. `imported` maps the names of the imported
+/// resources to whether passes can write them.
+fn compile(
+    passes: &[PassBuilder],
+    imported: &HashMap<&str, bool>,
+    exports: &[String],
+    render_size: UVec2,
+) -> Result<Schedule> {
+    let mut creators = HashMap::new();
+    for (ix, pass) in passes.iter().enumerate() {
+        for (name, _) in &pass.creates {
+            if imported.contains_key(name.as_str()) {
+                eyre::bail!("Pass #{} creates imported resource `{}`", ix, name);
+            }
+            if creators.insert(name.as_str(), ix).is_some() {
+                eyre::bail!("Transient `{}` is created by several passes", name);
+            }
+        }
+    }
+    for (ix, pass) in passes.iter().enumerate() {
+        for name in pass.resources() {
+            match imported.get(name) {
+                Some(false) if pass.is_writing(name) => {
+                    eyre::bail!("Pass #{} writes read-only resource `{}`", ix, name)
+                }
+                Some(_) => {}
+                None if creators.contains_key(name) => {}
+                None => eyre::bail!("Pass #{} uses unknown resource `{}`", ix, name),
+            }
+        }
+    }
+    for name in exports {
+        if !creators.contains_key(name.as_str()) {
+            eyre::bail!("Exported resource `{}` is not created by any pass", name);
+        }
+    }
+
+    // Writers of each resource, in order: the creator of transients first, then the others in
+    // the order the passes were added
+    let writers = |name: &str| -> Vec<usize> {
+        let creator = creators.get(name).copied();
+        creator
+            .into_iter()
+            .chain(
+                (0..passes.len()).filter(|&ix| Some(ix) != creator && passes[ix].is_writing(name)),
+            )
+            .collect()
+    };
+
+    // Passes writing imported or exported resources are run, along with the passes writing what
+    // they use
+    let is_exported = |name: &str| exports.iter().any(|n| n == name);
+    let mut alive = vec![false; passes.len()];
+    let mut stack = (0..passes.len())
+        .filter(|&ix| {
+            let pass = &passes[ix];
+            pass.resources().any(|name| {
+                (imported.contains_key(name) || is_exported(name)) && pass.is_writing(name)
+            })
+        })
+        .collect::<Vec<_>>();
+    while let Some(ix) = stack.pop() {
+        if std::mem::replace(&mut alive[ix], true) {
+            continue;
+        }
+        for name in passes[ix].resources() {
+            if creators.contains_key(name) {
+                stack.extend(writers(name).into_iter().filter(|&w| !alive[w]));
+            }
+        }
+    }
+
+    // Writers of a resource run one after the other, and before the passes only reading it
+    let mut dependencies = vec![BTreeSet::new(); passes.len()];
+    let names = passes
+        .iter()
+        .flat_map(|pass| pass.resources())
+        .collect::<BTreeSet<_>>();
+    for &name in &names {
+        let writers = writers(name)
+            .into_iter()
+            .filter(|&ix| alive[ix])
+            .collect::<Vec<_>>();
+        for pair in writers.windows(2) {
+            dependencies[pair[1]].insert(pair[0]);
+        }
+        let Some(&last_writer) = writers.last() else { continue; };
+        for (ix, pass) in passes.iter().enumerate() {
+            if alive[ix] && pass.is_reading(name) && !pass.is_writing(name) {
+                dependencies[ix].insert(last_writer);
+            }
+        }
+    }
+    let mut order = vec![];
+    let mut done = vec![false; passes.len()];
+    let alive_count = alive.iter().filter(|&&a| a).count();
+    while order.len() < alive_count {
+        // Passes run in the order they were added, unless they depend on a later one
+        let next = (0..passes.len())
+            .find(|&ix| alive[ix] && !done[ix] && dependencies[ix].iter().all(|&dep| done[dep]));
+        let Some(next) = next else { eyre::bail!("Cycle between frame graph passes"); };
+        done[next] = true;
+        order.push(next);
+    }
+
+    // Transients live from their creator to the last pass using them, or until the end of the
+    // frame when exported; textures are shared between transients of the same size and format
+    // which are not alive at the same time
+    let mut slots: Vec<(UVec2, TransientFormat)> = vec![];
+    let mut slot_of = HashMap::new();
+    let mut free = vec![];
+    let last_use = |name: &str| {
+        if is_exported(name) {
+            return order.len();
+        }
+        order
+            .iter()
+            .rposition(|&ix| passes[ix].resources().any(|n| n == name))
+            .unwrap()
+    };
+    for (position, &ix) in order.iter().enumerate() {
+        for (name, desc) in &passes[ix].creates {
+            let key = (desc.size.resolve(render_size), desc.format);
+            let slot = match free.iter().position(|&slot| slots[slot] == key) {
+                Some(free_ix) => free.swap_remove(free_ix),
+                None => {
+                    slots.push(key);
+                    slots.len() - 1
+                }
+            };
+            slot_of.insert(name.clone(), slot);
+        }
+        free.extend(
+            slot_of
+                .iter()
+                .filter(|(name, _)| last_use(name) == position)
+                .map(|(_, &slot)| slot),
+        );
+    }
+    Ok(Schedule {
+        order,
+        slots,
+        slot_of,
+    })
+}
+
+#[derive(Debug)]
+enum TransientStorage {
+    R(Texture<f32>),
+    Rg(Texture<[f32; 2]>),
+    Rgb(Texture<[f32; 3]>),
+    Rgba(Texture<[f32; 4]>),
+}
+
+#[derive(Debug)]
+struct TransientTexture {
+    size: UVec2,
+    format: TransientFormat,
+    storage: TransientStorage,
+    framebuffer: Framebuffer,
+}
+
+impl TransientTexture {
+    fn new(size: UVec2, format: TransientFormat) -> Result<Self> {
+        let Some(width) = NonZeroU32::new(size.x) else { eyre::bail!("Zero width transient"); };
+        let Some(height) = NonZeroU32::new(size.y) else { eyre::bail!("Zero height transient"); };
+        let framebuffer = Framebuffer::new();
+        // The storage follows the component type of the texture
+        macro_rules! allocate {
+            ($variant:ident, $component:ty) => {{
+                let depth = NonZeroU32::new(1).unwrap();
+                let texture = Texture::<$component>::new(width, height, depth, Dimension::D2);
+                texture.filter_min(SampleMode::Linear)?;
+                texture.filter_mag(SampleMode::Linear)?;
+                texture.wrap_s(TextureWrap::ClampEdge)?;
+                texture.wrap_t(TextureWrap::ClampEdge)?;
+                texture.reserve_memory()?;
+                framebuffer.attach_color(0, texture.mipmap(0).unwrap())?;
+                TransientStorage::$variant(texture)
+            }};
+        }
+        let storage = match format {
+            TransientFormat::R32F => allocate!(R, f32),
+            TransientFormat::Rg32F => allocate!(Rg, [f32; 2]),
+            TransientFormat::Rgb32F => allocate!(Rgb, [f32; 3]),
+            TransientFormat::Rgba32F => allocate!(Rgba, [f32; 4]),
+        };
+        framebuffer.enable_buffers([0])?;
+        framebuffer.assert_complete()?;
+        Ok(Self {
+            size,
+            format,
+            storage,
+            framebuffer,
+        })
+    }
+
+    fn texture(&self) -> GraphTexture {
+        match &self.storage {
+            TransientStorage::R(texture) => GraphTexture::R(texture),
+            TransientStorage::Rg(texture) => GraphTexture::Rg(texture),
+            TransientStorage::Rgb(texture) => GraphTexture::Rgb(texture),
+            TransientStorage::Rgba(texture) => GraphTexture::Rgba(texture),
+        }
+    }
+}
+
+/// Passes run by the renderer between lighting and the forward passes, see the
+/// [module documentation](self).
+#[derive(Debug, Default)]
+pub struct FrameGraph {
+    passes: Vec<Box<dyn GraphPass>>,
+    /// Textures of the transients of the last frame, reused when the same sizes and formats are
+    /// needed again.
+    textures: Vec<TransientTexture>,
+    exports: Vec<String>,
+    /// Textures of the exported transients written on the last frame.
+    exported: HashMap<String, usize>,
+}
+
+impl FrameGraph {
+    pub fn len(&self) -> usize {
+        self.passes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.passes.is_empty()
+    }
+
+    /// Add a pass to the graph. Passes run in the order they were added, unless the resources they
+    /// use require otherwise.
+    pub fn push(&mut self, pass: Box<dyn GraphPass>) {
+        self.passes.push(pass);
+    }
+
+    /// Remove the pass with the given name.
+    pub fn remove(&mut self, name: &str) -> Option<Box<dyn GraphPass>> {
+        let ix = self.passes.iter().position(|pass| pass.name() == name)?;
+        Some(self.passes.remove(ix))
+    }
+
+    pub fn clear(&mut self) {
+        self.passes.clear();
+        self.textures.clear();
+        self.exports.clear();
+        self.exported.clear();
+    }
+
+    /// Keep the transient alive after the passes ran, so that it can be read with
+    /// [`Self::exported`]. The passes writing it are run even if no other pass reads it.
+    pub fn export(&mut self, name: impl Into<String>) {
+        self.exports.push(name.into());
+    }
+
+    /// Texture of an exported transient, as written by the last call to [`Self::execute`].
+    pub fn exported(&self, name: &str) -> Option<GraphTexture> {
+        let &slot = self.exported.get(name)?;
+        Some(self.textures[slot].texture())
+    }
+
+    pub fn get<P: GraphPass>(&self) -> Option<&P> {
+        self.passes.iter().find_map(|p| p.as_any().downcast_ref())
+    }
+
+    pub fn get_mut<P: GraphPass>(&mut self) -> Option<&mut P> {
+        self.passes
+            .iter_mut()
+            .find_map(|p| p.as_any_mut().downcast_mut())
+    }
+
+    /// Run the passes of the frame with the imported resources.
+    #[tracing::instrument(skip_all, fields(passes = self.passes.len()))]
+    pub fn execute(
+        &mut self,
+        render_size: UVec2,
+        imported: &HashMap<&str, ImportedResource>,
+        view: &ViewUniformBuffer,
+        view_uniform: &ViewUniform,
+        profiler: &Rc<GpuProfiler>,
+    ) -> Result<()> {
+        self.exported.clear();
+        if self.passes.is_empty() {
+            return Ok(());
+        }
+        let builders = self
+            .passes
+            .iter_mut()
+            .map(|pass| {
+                let mut builder = PassBuilder {
+                    render_size,
+                    ..Default::default()
+                };
+                pass.setup(&mut builder);
+                builder
+            })
+            .collect::<Vec<_>>();
+        let writable = imported
+            .iter()
+            .map(|(&name, resource)| (name, resource.framebuffer.is_some()))
+            .collect();
+        let schedule = compile(&builders, &writable, &self.exports, render_size)?;
+
+        let mut previous = std::mem::take(&mut self.textures);
+        for &(size, format) in &schedule.slots {
+            let reused = previous
+                .iter()
+                .position(|t| t.size == size && t.format == format)
+                .map(|ix| previous.swap_remove(ix));
+            let texture = match reused {
+                Some(texture) => texture,
+                None => TransientTexture::new(size, format)?,
+            };
+            self.textures.push(texture);
+        }
+        // Textures left over are not needed anymore, e.g. after a resize, and are freed
+        drop(previous);
+
+        for &ix in &schedule.order {
+            let builder = &builders[ix];
+            let resources = builder
+                .resources()
+                .map(|name| {
+                    let resource = match schedule.slot_of.get(name) {
+                        Some(&slot) => {
+                            let transient = &self.textures[slot];
+                            ImportedResource {
+                                texture: transient.texture(),
+                                framebuffer: Some(&transient.framebuffer),
+                            }
+                        }
+                        None => imported[name],
+                    };
+                    (name, resource)
+                })
+                .collect();
+            let ctx = PassContext {
+                resources,
+                builder,
+                render_size,
+                view,
+                view_uniform,
+            };
+            let pass = &mut self.passes[ix];
+            let _zone = profiler.zone(pass.name());
+            pass.execute(&ctx)
+                .with_context(|| format!("Executing frame graph pass `{}`", pass.name()))?;
+        }
+        self.exported = self
+            .exports
+            .iter()
+            .filter_map(|name| Some((name.clone(), *schedule.slot_of.get(name)?)))
+            .collect();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pass(reads: &[&str], writes: &[&str], creates: &[&str]) -> PassBuilder {
+        let mut builder = PassBuilder::default();
+        for name in reads {
+            builder.read(*name);
+        }
+        for name in writes {
+            builder.write(*name);
+        }
+        for name in creates {
+            builder.create(*name, TextureDesc::full(TransientFormat::Rgba32F));
+        }
+        builder
+    }
+
+    fn imported() -> HashMap<&'static str, bool> {
+        HashMap::from([("lit", true), ("depth", false)])
+    }
+
+    #[test]
+    fn passes_run_after_the_writers_of_what_they_read() {
+        // The composite pass is added before the pass creating what it reads
+        let passes = [pass(&["ao"], &["lit"], &[]), pass(&["depth"], &[], &["ao"])];
+        let schedule = compile(&passes, &imported(), &[], UVec2::splat(64)).unwrap();
+        assert_eq!(schedule.order, [1, 0]);
+    }
+
+    #[test]
+    fn unused_passes_are_skipped() {
+        let passes = [pass(&["depth"], &[], &["unused"]), pass(&[], &["lit"], &[])];
+        let schedule = compile(&passes, &imported(), &[], UVec2::splat(64)).unwrap();
+        assert_eq!(schedule.order, [1]);
+        assert!(schedule.slots.is_empty());
+    }
+
+    #[test]
+    fn transients_share_textures_when_their_lifetimes_do_not_overlap() {
+        let passes = [
+            pass(&["depth"], &[], &["a"]),
+            pass(&["a"], &["lit"], &[]),
+            pass(&["depth"], &[], &["b"]),
+            pass(&["b"], &["lit"], &[]),
+        ];
+        let schedule = compile(&passes, &imported(), &[], UVec2::splat(64)).unwrap();
+        assert_eq!(schedule.order, [0, 1, 2, 3]);
+        assert_eq!(schedule.slots.len(), 1);
+
+        let passes = [
+            pass(&["depth"], &[], &["a"]),
+            pass(&["depth"], &[], &["b"]),
+            pass(&["a", "b"], &["lit"], &[]),
+        ];
+        let schedule = compile(&passes, &imported(), &[], UVec2::splat(64)).unwrap();
+        assert_eq!(schedule.slots.len(), 2);
+    }
+
+    #[test]
+    fn exported_transients_are_kept_until_the_end_of_the_frame() {
+        let passes = [
+            pass(&["depth"], &[], &["bloom"]),
+            pass(&["depth"], &[], &["a"]),
+            pass(&["a"], &["lit"], &[]),
+        ];
+        let exports = ["bloom".to_string()];
+        let schedule = compile(&passes, &imported(), &exports, UVec2::splat(64)).unwrap();
+        // The exported transient is only written, yet its pass runs and keeps its own texture
+        assert_eq!(schedule.order, [0, 1, 2]);
+        assert_ne!(schedule.slot_of["bloom"], schedule.slot_of["a"]);
+
+        let missing = ["missing".to_string()];
+        assert!(compile(&passes, &imported(), &missing, UVec2::ONE).is_err());
+    }
+
+    #[test]
+    fn invalid_graphs_are_rejected() {
+        let read_only = [pass(&[], &["depth"], &[])];
+        assert!(compile(&read_only, &imported(), &[], UVec2::ONE).is_err());
+        let unknown = [pass(&["missing"], &["lit"], &[])];
+        assert!(compile(&unknown, &imported(), &[], UVec2::ONE).is_err());
+        let cycle = [pass(&["b"], &["lit"], &["a"]), pass(&["a"], &[], &["b"])];
+        assert!(compile(&cycle, &imported(), &[], UVec2::ONE).is_err());
+    }
+}
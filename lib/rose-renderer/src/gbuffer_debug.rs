@@ -0,0 +1,350 @@
+//! Visualization of the G-Buffer attachments and of the depth buffer in the renderer debug panel.
+//!
+//! Attachments are shown with their values mapped from a configurable range to black and white,
+//! optionally isolating one channel; depth is linearized into view distances between a near and a
+//! far display distance, and normals are decoded and remapped to colors. Hovering a view reads back
+//! the values of the pixel under the cursor, in the units of the attachment.
+
+use eyre::{Context, Result};
+use glam::{Mat4, UVec2, Vec2, Vec4};
+
+use rose_core::{
+    camera::ViewUniform, readback::Readback, screen_draw::ScreenDraw,
+    utils::reload_watcher::ReloadWatcher,
+};
+use violette::{framebuffer::Framebuffer, program::UniformLocation};
+
+use crate::{aov::decode_normal, gbuffers::GeometryBuffers};
+
+/// Image shown by the G-Buffer viewer.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum DebugSource {
+    /// The lit scene, before post processing.
+    Lit,
+    Position,
+    Albedo,
+    Normal,
+    RoughMetal,
+    Emission,
+    CoatAnisotropy,
+    Depth,
+}
+
+impl DebugSource {
+    pub const ALL: [Self; 8] = [
+        Self::Lit,
+        Self::Position,
+        Self::Albedo,
+        Self::Normal,
+        Self::RoughMetal,
+        Self::Emission,
+        Self::CoatAnisotropy,
+        Self::Depth,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Lit => "Lit",
+            Self::Position => "Position",
+            Self::Albedo => "Albedo",
+            Self::Normal => "Normal",
+            Self::RoughMetal => "Roughness/Metal",
+            Self::Emission => "Emission",
+            Self::CoatAnisotropy => "Clearcoat/Anisotropy",
+            Self::Depth => "Depth",
+        }
+    }
+
+    /// Kind of the source in the viewer shader: 1 for depth, 2 for encoded normals and 0 for plain
+    /// values.
+    fn shader_kind(self) -> i32 {
+        match self {
+            Self::Depth => 1,
+            Self::Normal => 2,
+            _ => 0,
+        }
+    }
+}
+
+/// Channels of the source shown by the G-Buffer viewer; single channels are shown in grayscale.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash)]
+pub enum DebugChannel {
+    #[default]
+    Rgb,
+    R,
+    G,
+    B,
+    A,
+}
+
+impl DebugChannel {
+    pub const ALL: [Self; 5] = [Self::Rgb, Self::R, Self::G, Self::B, Self::A];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Rgb => "RGB",
+            Self::R => "R",
+            Self::G => "G",
+            Self::B => "B",
+            Self::A => "A",
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct GBufferViewParams {
+    pub source: DebugSource,
+    /// Second source shown next to the first one, with the same settings.
+    pub compare: Option<DebugSource>,
+    pub channel: DebugChannel,
+    /// Values shown as black and white, everything in between being scaled linearly.
+    pub range: (f32, f32),
+    /// Show depth as the distance to the camera rather than the raw depth buffer value.
+    pub linearize_depth: bool,
+    /// Distances to the camera shown as black and white when linearizing depth.
+    pub depth_range: (f32, f32),
+    /// Show normals remapped from [-1, 1] to [0, 1].
+    pub remap_normals: bool,
+}
+
+impl Default for GBufferViewParams {
+    fn default() -> Self {
+        Self {
+            source: DebugSource::Albedo,
+            compare: None,
+            channel: DebugChannel::Rgb,
+            range: (0., 1.),
+            linearize_depth: true,
+            depth_range: (0.1, 100.),
+            remap_normals: true,
+        }
+    }
+}
+
+impl GBufferViewParams {
+    #[cfg(feature = "debug-ui")]
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        use egui::{ComboBox, DragValue, Grid};
+
+        Grid::new("gbuffer-view-params")
+            .num_columns(2)
+            .show(ui, |ui| {
+                let source_label = ui.label("Source").id;
+                ComboBox::from_id_source("gbuffer-view-source")
+                    .selected_text(self.source.name())
+                    .show_ui(ui, |ui| {
+                        for source in DebugSource::ALL {
+                            ui.selectable_value(&mut self.source, source, source.name());
+                        }
+                    })
+                    .response
+                    .labelled_by(source_label);
+                ui.end_row();
+
+                let compare_label = ui.label("Compare with").id;
+                ComboBox::from_id_source("gbuffer-view-compare")
+                    .selected_text(self.compare.map_or("<None>", DebugSource::name))
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut self.compare, None, "<None>");
+                        for source in DebugSource::ALL {
+                            ui.selectable_value(&mut self.compare, Some(source), source.name());
+                        }
+                    })
+                    .response
+                    .labelled_by(compare_label);
+                ui.end_row();
+
+                let channel_label = ui.label("Channel").id;
+                ui.horizontal(|ui| {
+                    for channel in DebugChannel::ALL {
+                        ui.selectable_value(&mut self.channel, channel, channel.name());
+                    }
+                })
+                .response
+                .labelled_by(channel_label);
+                ui.end_row();
+
+                let range_label = ui.label("Value range").id;
+                ui.horizontal(|ui| {
+                    ui.add(DragValue::new(&mut self.range.0).speed(1e-2));
+                    ui.add(DragValue::new(&mut self.range.1).speed(1e-2));
+                })
+                .response
+                .labelled_by(range_label);
+                ui.end_row();
+
+                let linearize_label = ui.label("Linear depth").id;
+                ui.checkbox(&mut self.linearize_depth, "")
+                    .labelled_by(linearize_label);
+                ui.end_row();
+
+                let depth_range_label = ui.label("Depth range").id;
+                ui.horizontal(|ui| {
+                    let (near, far) = &mut self.depth_range;
+                    ui.add(
+                        DragValue::new(near)
+                            .clamp_range(0.0..=*far)
+                            .speed(1e-1)
+                            .suffix(" m"),
+                    );
+                    ui.add(
+                        DragValue::new(far)
+                            .clamp_range(*near..=f32::MAX)
+                            .speed(1.)
+                            .suffix(" m"),
+                    );
+                })
+                .response
+                .labelled_by(depth_range_label);
+                ui.end_row();
+
+                let remap_label = ui.label("Remap normals").id;
+                ui.checkbox(&mut self.remap_normals, "")
+                    .labelled_by(remap_label);
+                ui.end_row();
+            });
+    }
+}
+
+/// Values of a pixel read back by the G-Buffer viewer.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct InspectedPixel {
+    pub source: DebugSource,
+    /// Pixel of the G-Buffer, from the top-left corner.
+    pub pixel: UVec2,
+    /// Values of the pixel: the distance to the camera for depth, and the decoded normal along
+    /// with the coverage for normals.
+    pub value: Vec4,
+}
+
+#[derive(Debug)]
+pub struct GBufferViewer {
+    draw: ScreenDraw,
+    u_texture: UniformLocation,
+    u_source_kind: UniformLocation,
+    u_channel: UniformLocation,
+    u_range_min: UniformLocation,
+    u_range_max: UniformLocation,
+    u_linearize_depth: UniformLocation,
+    u_depth_near: UniformLocation,
+    u_depth_far: UniformLocation,
+    u_inv_proj: UniformLocation,
+    u_reverse_z: UniformLocation,
+    u_remap_normals: UniformLocation,
+    readback: Option<(DebugSource, UVec2, Readback)>,
+    inspected: Option<InspectedPixel>,
+}
+
+impl GBufferViewer {
+    pub fn new(reload_watcher: &ReloadWatcher) -> Result<Self> {
+        let draw = ScreenDraw::load("screen/gbuffer_debug.glsl", reload_watcher)
+            .context("Cannot load G-Buffer viewer shader")?;
+        let program = draw.program();
+        let u_texture = program.uniform("in_texture");
+        let u_source_kind = program.uniform("source_kind");
+        let u_channel = program.uniform("channel");
+        let u_range_min = program.uniform("range_min");
+        let u_range_max = program.uniform("range_max");
+        let u_linearize_depth = program.uniform("linearize_depth");
+        let u_depth_near = program.uniform("depth_near");
+        let u_depth_far = program.uniform("depth_far");
+        let u_inv_proj = program.uniform("inv_proj");
+        let u_reverse_z = program.uniform("reverse_z");
+        let u_remap_normals = program.uniform("remap_normals");
+        drop(program);
+        Ok(Self {
+            draw,
+            u_texture,
+            u_source_kind,
+            u_channel,
+            u_range_min,
+            u_range_max,
+            u_linearize_depth,
+            u_depth_near,
+            u_depth_far,
+            u_inv_proj,
+            u_reverse_z,
+            u_remap_normals,
+            readback: None,
+            inspected: None,
+        })
+    }
+
+    /// Draw the source of the G-Buffer into the current viewport of `frame`, with the projection
+    /// of the camera it was rendered from and whether it used reversed depth.
+    pub fn draw(
+        &self,
+        frame: &Framebuffer,
+        gbuffers: &GeometryBuffers,
+        source: DebugSource,
+        params: &GBufferViewParams,
+        inv_proj: Mat4,
+        reverse_z: bool,
+    ) -> Result<()> {
+        let program = self.draw.program();
+        gbuffers.set_debug_texture(&program, self.u_texture, source)?;
+        program.set_uniform(self.u_source_kind, source.shader_kind())?;
+        program.set_uniform(self.u_channel, params.channel as i32)?;
+        program.set_uniform(self.u_range_min, params.range.0)?;
+        program.set_uniform(self.u_range_max, params.range.1)?;
+        program.set_uniform(self.u_linearize_depth, params.linearize_depth as i32)?;
+        program.set_uniform(self.u_depth_near, params.depth_range.0)?;
+        program.set_uniform(self.u_depth_far, params.depth_range.1)?;
+        program.set_uniform(self.u_inv_proj, inv_proj)?;
+        program.set_uniform(self.u_reverse_z, reverse_z as i32)?;
+        program.set_uniform(self.u_remap_normals, params.remap_normals as i32)?;
+        drop(program);
+        Framebuffer::disable_blending();
+        self.draw.draw(frame)?;
+        Ok(())
+    }
+
+    /// Collect the pixel read back on a previous frame, and start reading the pixel of the source
+    /// at `uv`, from (0, 0) at the top-left to (1, 1) at the bottom-right corner, once it arrived.
+    pub fn inspect(
+        &mut self,
+        gbuffers: &GeometryBuffers,
+        view: &ViewUniform,
+        target: Option<(DebugSource, Vec2)>,
+    ) -> Result<()> {
+        if let Some((source, pixel, readback)) = &mut self.readback {
+            let Some(pixels) = readback.poll_pixels::<[f32; 4]>() else { return Ok(()); };
+            let value = Vec4::from_array(pixels[0]);
+            let value = match source {
+                // Read from the positions, which hold the same surfaces as the depth buffer
+                DebugSource::Depth => {
+                    Vec4::splat(-view.mat_view.transform_point3(value.truncate()).z)
+                }
+                DebugSource::Normal if value.w > 0. => {
+                    let octahedral = gbuffers.settings().octahedral_normals;
+                    decode_normal(value.truncate(), octahedral).extend(value.w)
+                }
+                _ => value,
+            };
+            self.inspected = Some(InspectedPixel {
+                source: *source,
+                pixel: *pixel,
+                value,
+            });
+            self.readback = None;
+        }
+        let Some((source, uv)) = target else {
+            self.inspected = None;
+            return Ok(());
+        };
+        let size = gbuffers.size();
+        let pixel = (uv.clamp(Vec2::ZERO, Vec2::ONE) * size.as_vec2())
+            .as_uvec2()
+            .min(size - 1);
+        // Textures start at the bottom row
+        let gl_pixel = UVec2::new(pixel.x, size.y - 1 - pixel.y);
+        let readback = gbuffers.read_debug_pixel(source, gl_pixel)?;
+        self.readback = Some((source, pixel, readback));
+        Ok(())
+    }
+
+    /// Values of the last pixel read back while hovering a view.
+    pub fn inspected(&self) -> Option<InspectedPixel> {
+        self.inspected
+    }
+}
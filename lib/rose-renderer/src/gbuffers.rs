@@ -1,20 +1,287 @@
-use std::num::NonZeroU32;
+use std::{collections::HashMap, num::NonZeroU32};
 
 use eyre::{Context, Result};
-use glam::UVec2;
+use glam::{UVec2, Vec3};
 
 use rose_core::{
-    camera::ViewUniformBuffer, light::LightBuffer, screen_draw::ScreenDraw,
-    utils::reload_watcher::ReloadWatcher,
+    camera::{ViewUniform, ViewUniformBuffer},
+    light::Lights,
+    readback::{Readback, ReadbackFormat},
+    screen_draw::ScreenDraw,
+    utils::{draw_counters::CountBind, reload_watcher::ReloadWatcher},
 };
 use violette::{
     base::resource::Resource,
     framebuffer::{Blend, ClearBuffer, Framebuffer},
-    program::{UniformBlockIndex, UniformLocation},
+    gl::{self, types::GLenum},
+    program::{Program, UniformBlockIndex, UniformLocation},
     texture::{DepthStencil, Dimension, SampleMode, Texture},
 };
 
-use crate::env::{Environment, MaterialInfo};
+use crate::{
+    aov::{decode_normal, flip_rows, Aovs},
+    cookie::LightCookies,
+    env::{Environment, MaterialInfo},
+    frame_graph::{GraphTexture, ImportedResource},
+    gbuffer_debug::DebugSource,
+    light_culling::LightCulling,
+    ltc::LtcTables,
+    probes::IrradianceProbes,
+    reflection_probes::ReflectionProbes,
+};
+
+/// Color attachments of the G-Buffer, in attachment order. Materials drawing into
+/// [`GeometryBuffers::framebuffer`] must write every one of them, which the
+/// `common/gbuffer.glsl` shader include declares.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum GBufferAttachment {
+    /// Position relative to the origin of the view, see [`ViewUniform::set_origin`].
+    Position,
+    /// Base color, linear.
+    Albedo,
+    /// World-space normal in RGB, coverage in alpha (0 leaves the pixel unlit). Normals are
+    /// encoded by `encode_normal` in `common/gbuffer_normal.glsl`.
+    NormalCoverage,
+    /// Roughness in R, metallic in G.
+    RoughMetal,
+    /// Emitted radiance, in nits.
+    Emission,
+    /// Clearcoat strength in R and roughness in G, anisotropy strength in B and direction in A,
+    /// encoded by `encode_anisotropy_direction` in `common/gbuffer_normal.glsl`.
+    CoatAnisotropy,
+}
+
+impl GBufferAttachment {
+    pub const ALL: [Self; 6] = [
+        Self::Position,
+        Self::Albedo,
+        Self::NormalCoverage,
+        Self::RoughMetal,
+        Self::Emission,
+        Self::CoatAnisotropy,
+    ];
+
+    /// Color attachment index, which is also the fragment output location.
+    pub fn index(self) -> u32 {
+        self as u32
+    }
+
+    /// Number of float components stored in the attachment.
+    pub fn components(self) -> usize {
+        match self {
+            Self::Position | Self::Albedo | Self::Emission => 3,
+            Self::NormalCoverage | Self::CoatAnisotropy => 4,
+            Self::RoughMetal => 2,
+        }
+    }
+
+    /// Name of the fragment output declared in `common/gbuffer.glsl`.
+    pub fn output_name(self) -> &'static str {
+        match self {
+            Self::Position => "frame_position",
+            Self::Albedo => "frame_albedo",
+            Self::NormalCoverage => "frame_normal",
+            Self::RoughMetal => "frame_rough_metal",
+            Self::Emission => "frame_emission",
+            Self::CoatAnisotropy => "frame_coat_aniso",
+        }
+    }
+
+    pub fn glsl_type(self) -> &'static str {
+        match self.components() {
+            2 => "vec2",
+            3 => "vec3",
+            4 => "vec4",
+            _ => unreachable!(),
+        }
+    }
+
+    /// OpenGL internal format of the attachment texture at the given precision.
+    pub fn internal_format(self, precision: AttachmentPrecision) -> GLenum {
+        use AttachmentPrecision::*;
+        match (self, precision) {
+            (Self::Position | Self::Albedo | Self::Emission, Full) => gl::RGB32F,
+            (Self::Position | Self::Albedo | Self::Emission, Half) => gl::RGB16F,
+            (Self::Position, Packed) => gl::RGB16F,
+            (Self::Albedo, Packed) => gl::RGB8,
+            (Self::Emission, Packed) => gl::R11F_G11F_B10F,
+            (Self::NormalCoverage, Full) => gl::RGBA32F,
+            (Self::NormalCoverage, Half) => gl::RGBA16F,
+            (Self::NormalCoverage, Packed) => gl::RGB10_A2,
+            (Self::RoughMetal, Full) => gl::RG32F,
+            (Self::RoughMetal, Half) => gl::RG16F,
+            (Self::RoughMetal, Packed) => gl::RG8,
+            (Self::CoatAnisotropy, Full) => gl::RGBA32F,
+            (Self::CoatAnisotropy, Half) => gl::RGBA16F,
+            (Self::CoatAnisotropy, Packed) => gl::RGBA8,
+        }
+    }
+}
+
+/// Storage precision of a G-Buffer attachment.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
+pub enum AttachmentPrecision {
+    /// 32-bit floats.
+    #[default]
+    Full,
+    /// 16-bit floats.
+    Half,
+    /// Smallest format fitting the attachment: 8-bit albedo, roughness/metallic and
+    /// clearcoat/anisotropy, 10-bit normals, 11/11/10-bit float emission. Positions fall back to
+    /// half floats.
+    Packed,
+}
+
+/// Formats of the G-Buffer attachments, selected at renderer creation.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serialize", serde(default))]
+pub struct GBufferSettings {
+    pub position: AttachmentPrecision,
+    pub albedo: AttachmentPrecision,
+    pub normal: AttachmentPrecision,
+    pub rough_metal: AttachmentPrecision,
+    pub emission: AttachmentPrecision,
+    pub coat_anisotropy: AttachmentPrecision,
+    /// Octahedron-encode normals into two components, which keeps them accurate in packed
+    /// formats.
+    pub octahedral_normals: bool,
+}
+
+impl GBufferSettings {
+    /// Path of the virtual shader include holding the G-Buffer configuration defines.
+    pub const SHADER_CONFIG_INCLUDE: &'static str = "gbuffer_config.glsl";
+
+    /// Settings reducing the G-Buffer bandwidth for small GPUs, at the cost of precision.
+    pub fn low_bandwidth() -> Self {
+        Self {
+            position: AttachmentPrecision::Half,
+            albedo: AttachmentPrecision::Packed,
+            normal: AttachmentPrecision::Packed,
+            rough_metal: AttachmentPrecision::Packed,
+            emission: AttachmentPrecision::Packed,
+            coat_anisotropy: AttachmentPrecision::Packed,
+            octahedral_normals: true,
+        }
+    }
+
+    pub fn precision(&self, attachment: GBufferAttachment) -> AttachmentPrecision {
+        match attachment {
+            GBufferAttachment::Position => self.position,
+            GBufferAttachment::Albedo => self.albedo,
+            GBufferAttachment::NormalCoverage => self.normal,
+            GBufferAttachment::RoughMetal => self.rough_metal,
+            GBufferAttachment::Emission => self.emission,
+            GBufferAttachment::CoatAnisotropy => self.coat_anisotropy,
+        }
+    }
+
+    /// GLSL source of the `gbuffer_config.glsl` include.
+    pub fn shader_config(&self) -> String {
+        let mut source = String::from("// Generated from `GBufferSettings`\n");
+        if self.octahedral_normals {
+            source.push_str("#define GBUFFER_OCTAHEDRAL_NORMALS\n");
+        }
+        source
+    }
+
+    /// Make the configuration available to shaders. Must be called before loading shaders that
+    /// read or write the G-Buffer.
+    pub fn register_shader_config(&self) {
+        glsl_preprocessor::set_virtual_file(Self::SHADER_CONFIG_INCLUDE, self.shader_config());
+    }
+}
+
+/// Replace the storage of a G-Buffer texture with the attachment format.
+fn set_attachment_storage<F>(texture: &Texture<F>, format: GLenum, size: UVec2) {
+    texture.bind();
+    unsafe {
+        gl::TexImage2D(
+            gl::TEXTURE_2D,
+            0,
+            format as _,
+            size.x as _,
+            size.y as _,
+            0,
+            gl::RGBA,
+            gl::FLOAT,
+            std::ptr::null(),
+        );
+    }
+    texture.unbind();
+}
+
+/// Check that the fragment outputs declared in the given GLSL sources match the G-Buffer layout:
+/// every attachment is written with an explicit location and the right vector type.
+pub fn validate_gbuffer_outputs<'s>(sources: impl IntoIterator<Item = &'s str>) -> Result<()> {
+    let outputs = sources
+        .into_iter()
+        .flat_map(|source| source.lines())
+        .filter_map(parse_fragment_output)
+        .collect::<Vec<_>>();
+    for (location, ty, name) in &outputs {
+        let Some(location) = location else {
+            eyre::bail!(
+                "Fragment output `{}` needs an explicit layout location to write into the G-Buffer",
+                name
+            );
+        };
+        let Some(attachment) = GBufferAttachment::ALL.get(*location as usize) else {
+            eyre::bail!(
+                "Fragment output `{}` has location {}, but the G-Buffer only has {} attachments",
+                name,
+                location,
+                GBufferAttachment::ALL.len()
+            );
+        };
+        eyre::ensure!(
+            *ty == attachment.glsl_type(),
+            "Fragment output `{}` at location {} is a {}, but the G-Buffer attachment {:?} expects a {}",
+            name,
+            location,
+            ty,
+            attachment,
+            attachment.glsl_type()
+        );
+    }
+    let missing = GBufferAttachment::ALL
+        .into_iter()
+        .filter(|a| !outputs.iter().any(|(loc, _, _)| *loc == Some(a.index())))
+        .map(|a| format!("{:?} (location {}, {})", a, a.index(), a.glsl_type()))
+        .collect::<Vec<_>>();
+    eyre::ensure!(
+        missing.is_empty(),
+        "Fragment shader does not write G-Buffer attachments: {}. Include `common/gbuffer.glsl` to declare them.",
+        missing.join(", ")
+    );
+    Ok(())
+}
+
+/// Parse a `[layout(location = N)] out TYPE NAME;` declaration into its location, type and name.
+fn parse_fragment_output(line: &str) -> Option<(Option<u32>, &str, &str)> {
+    let line = line.split("//").next()?.trim();
+    let (location, rest) = match line.strip_prefix("layout") {
+        Some(rest) => {
+            let (qualifiers, rest) = rest.trim_start().strip_prefix('(')?.split_once(')')?;
+            let location = qualifiers.split(',').find_map(|q| {
+                let (key, value) = q.split_once('=')?;
+                (key.trim() == "location")
+                    .then(|| value.trim().parse().ok())
+                    .flatten()
+            });
+            (location, rest.trim_start())
+        }
+        None => (None, line),
+    };
+    let mut tokens = rest
+        .strip_prefix("out")?
+        .trim_end_matches(';')
+        .split_whitespace();
+    let ty = tokens.next()?;
+    let name = tokens.next()?;
+    Some((location, ty, name))
+}
 
 #[derive(Debug)]
 pub struct GeometryBuffers {
@@ -22,26 +289,45 @@ pub struct GeometryBuffers {
     blit: ScreenDraw,
     deferred_fbo: Framebuffer,
     output_fbo: Framebuffer,
+    /// Lit scene along with the weight of the reflection probes, written by the probes.
+    reflection_fbo: Framebuffer,
     size: UVec2,
+    settings: GBufferSettings,
     pos: Texture<[f32; 3]>,
     albedo: Texture<[f32; 3]>,
     normal_coverage: Texture<[f32; 4]>,
     rough_metal: Texture<[f32; 2]>,
     emission: Texture<[f32; 3]>,
+    coat_aniso: Texture<[f32; 4]>,
     out_color: Texture<[f32; 3]>,
+    reflection_weight: Texture<f32>,
     out_depth: Texture<DepthStencil<f32, ()>>,
     uniform_frame_pos: UniformLocation,
     uniform_frame_albedo: UniformLocation,
     uniform_frame_normal: UniformLocation,
     uniform_frame_rough_metal: UniformLocation,
     uniform_frame_emission: UniformLocation,
+    uniform_frame_coat_aniso: UniformLocation,
     uniform_block_light: UniformBlockIndex,
+    uniform_cookie: UniformLocation,
+    uniform_cookie_kind: UniformLocation,
+    uniform_cookie_rotation: UniformLocation,
+    uniform_cookie_size: UniformLocation,
+    ltc: LtcTables,
+    uniform_ltc_inverse: UniformLocation,
+    uniform_ltc_amplitude: UniformLocation,
     uniform_block_view: UniformBlockIndex,
     uniform_blit_source: UniformLocation,
 }
 
 impl GeometryBuffers {
-    pub fn new(size: UVec2, reload_watcher: &ReloadWatcher) -> Result<Self> {
+    /// Create the G-Buffer with the attachment formats of `settings`. Its normal encoding is
+    /// configured separately by [`GBufferSettings::register_shader_config`].
+    pub fn new(
+        size: UVec2,
+        settings: GBufferSettings,
+        reload_watcher: &ReloadWatcher,
+    ) -> Result<Self> {
         let Some(width) = NonZeroU32::new(size.x) else { eyre::bail!("Zero width resize"); };
         let Some(height) = NonZeroU32::new(size.y) else { eyre::bail!("Zero height resize"); };
         let nonzero_one = NonZeroU32::new(1).unwrap();
@@ -70,30 +356,48 @@ impl GeometryBuffers {
         emission.filter_mag(SampleMode::Linear)?;
         emission.reserve_memory()?;
 
+        let coat_aniso = Texture::new(width, height, nonzero_one, Dimension::D2);
+        coat_aniso.filter_min(SampleMode::Linear)?;
+        coat_aniso.filter_mag(SampleMode::Linear)?;
+        coat_aniso.reserve_memory()?;
+
         let out_color = Texture::new(width, height, nonzero_one, Dimension::D2);
         out_color.filter_min(SampleMode::Linear)?;
         out_color.filter_mag(SampleMode::Linear)?;
         out_color.reserve_memory()?;
 
+        let reflection_weight = Texture::new(width, height, nonzero_one, Dimension::D2);
+        reflection_weight.filter_min(SampleMode::Linear)?;
+        reflection_weight.filter_mag(SampleMode::Linear)?;
+        reflection_weight.reserve_memory()?;
+
         let out_depth = Texture::new(width, height, nonzero_one, Dimension::D2);
         out_depth.filter_min(SampleMode::Linear)?;
         out_depth.filter_mag(SampleMode::Linear)?;
         out_depth.reserve_memory()?;
 
         let deferred_fbo = Framebuffer::new();
+        // Attachment indices must follow `GBufferAttachment`
         deferred_fbo.attach_color(0, pos.mipmap(0).unwrap())?;
         deferred_fbo.attach_color(1, albedo.mipmap(0).unwrap())?;
         deferred_fbo.attach_color(2, normal_coverage.mipmap(0).unwrap())?;
         deferred_fbo.attach_color(3, rough_metal.mipmap(0).unwrap())?;
         deferred_fbo.attach_color(4, emission.mipmap(0).unwrap())?;
+        deferred_fbo.attach_color(5, coat_aniso.mipmap(0).unwrap())?;
         deferred_fbo.attach_depth(&out_depth)?;
-        deferred_fbo.enable_buffers([0, 1, 2, 3, 4])?;
+        deferred_fbo.enable_buffers([0, 1, 2, 3, 4, 5])?;
         deferred_fbo.assert_complete()?;
 
         let output_fbo = Framebuffer::new();
         output_fbo.attach_color(0, out_color.mipmap(0).unwrap())?;
         output_fbo.assert_complete()?;
 
+        let reflection_fbo = Framebuffer::new();
+        reflection_fbo.attach_color(0, out_color.mipmap(0).unwrap())?;
+        reflection_fbo.attach_color(1, reflection_weight.mipmap(0).unwrap())?;
+        reflection_fbo.enable_buffers([0, 1])?;
+        reflection_fbo.assert_complete()?;
+
         let screen_pass = ScreenDraw::load("screen/deferred.glsl", reload_watcher)
             .context("Cannot load screen shader pass")?;
         let blit =
@@ -106,20 +410,31 @@ impl GeometryBuffers {
         let uniform_frame_normal = pass_program.uniform("frame_normal");
         let uniform_frame_rough_metal = pass_program.uniform("frame_rough_metal");
         let uniform_frame_emission = pass_program.uniform("frame_emission");
+        let uniform_frame_coat_aniso = pass_program.uniform("frame_coat_aniso");
         let uniform_block_light = pass_program.uniform_block("Light");
+        let uniform_cookie = pass_program.uniform("cookie");
+        let uniform_cookie_kind = pass_program.uniform("cookie_kind");
+        let uniform_cookie_rotation = pass_program.uniform("cookie_rotation");
+        let uniform_cookie_size = pass_program.uniform("cookie_size");
+        let uniform_ltc_inverse = pass_program.uniform("ltc_inverse");
+        let uniform_ltc_amplitude = pass_program.uniform("ltc_amplitude");
         let uniform_block_view = pass_program.uniform_block("View");
         drop(pass_program);
 
-        Ok(Self {
+        let this = Self {
             deferred_fbo,
             output_fbo,
+            reflection_fbo,
             size,
+            settings,
             pos,
             albedo,
             normal_coverage,
             rough_metal,
             emission,
+            coat_aniso,
             out_color,
+            reflection_weight,
             out_depth,
             uniform_blit_source: debug_uniform_in_texture,
             uniform_frame_pos,
@@ -127,17 +442,147 @@ impl GeometryBuffers {
             uniform_frame_normal,
             uniform_frame_rough_metal,
             uniform_frame_emission,
+            uniform_frame_coat_aniso,
             uniform_block_light,
+            uniform_cookie,
+            uniform_cookie_kind,
+            uniform_cookie_rotation,
+            uniform_cookie_size,
+            ltc: LtcTables::new().context("Cannot create area light tables")?,
+            uniform_ltc_inverse,
+            uniform_ltc_amplitude,
             uniform_block_view,
             screen_pass,
             blit,
-        })
+        };
+        this.apply_formats();
+        Ok(this)
+    }
+
+    pub fn settings(&self) -> &GBufferSettings {
+        &self.settings
+    }
+
+    /// Re-specify the attachment textures with their configured formats, which textures created
+    /// from the 32-bit float component types do not follow.
+    fn apply_formats(&self) {
+        let format = |attachment: GBufferAttachment| {
+            attachment.internal_format(self.settings.precision(attachment))
+        };
+        set_attachment_storage(&self.pos, format(GBufferAttachment::Position), self.size);
+        set_attachment_storage(&self.albedo, format(GBufferAttachment::Albedo), self.size);
+        set_attachment_storage(
+            &self.normal_coverage,
+            format(GBufferAttachment::NormalCoverage),
+            self.size,
+        );
+        set_attachment_storage(
+            &self.rough_metal,
+            format(GBufferAttachment::RoughMetal),
+            self.size,
+        );
+        set_attachment_storage(
+            &self.emission,
+            format(GBufferAttachment::Emission),
+            self.size,
+        );
+        set_attachment_storage(
+            &self.coat_aniso,
+            format(GBufferAttachment::CoatAnisotropy),
+            self.size,
+        );
     }
 
     pub fn framebuffer(&self) -> &Framebuffer {
         &self.deferred_fbo
     }
 
+    /// Framebuffer holding the lit scene returned by [`Self::process`].
+    pub fn output_framebuffer(&self) -> &Framebuffer {
+        &self.output_fbo
+    }
+
+    /// Lit scene of the last call to [`Self::process`].
+    pub fn output(&self) -> &Texture<[f32; 3]> {
+        &self.out_color
+    }
+
+    pub fn depth(&self) -> &Texture<DepthStencil<f32, ()>> {
+        &self.out_depth
+    }
+
+    /// Attachments and lit scene imported into the frame graph, see [`crate::frame_graph`].
+    pub fn graph_resources(&self) -> HashMap<&'static str, ImportedResource> {
+        let read_only = [
+            ("position", GraphTexture::Rgb(&self.pos)),
+            ("albedo", GraphTexture::Rgb(&self.albedo)),
+            ("normal", GraphTexture::Rgba(&self.normal_coverage)),
+            ("rough_metal", GraphTexture::Rg(&self.rough_metal)),
+            ("emission", GraphTexture::Rgb(&self.emission)),
+            ("coat_anisotropy", GraphTexture::Rgba(&self.coat_aniso)),
+            ("depth", GraphTexture::Depth(&self.out_depth)),
+        ];
+        let mut resources = read_only
+            .into_iter()
+            .map(|(name, texture)| {
+                let resource = ImportedResource {
+                    texture,
+                    framebuffer: None,
+                };
+                (name, resource)
+            })
+            .collect::<HashMap<_, _>>();
+        resources.insert(
+            "lit",
+            ImportedResource {
+                texture: GraphTexture::Rgb(&self.out_color),
+                framebuffer: Some(&self.output_fbo),
+            },
+        );
+        resources
+    }
+
+    /// Download the lit scene and the G-Buffer channels of the last frame, rendered from `view`.
+    pub fn download_aovs(&self, view: &ViewUniform) -> Result<Aovs> {
+        let width = self.size.x as usize;
+        let position = flip_rows(self.pos.mipmap(0).unwrap().download()?, width);
+        let normal_coverage = flip_rows(self.normal_coverage.mipmap(0).unwrap().download()?, width);
+        let rough_metal = flip_rows(self.rough_metal.mipmap(0).unwrap().download()?, width);
+        let covered = |i: usize| normal_coverage[i][3] > 0.;
+        let normal = normal_coverage
+            .iter()
+            .enumerate()
+            .map(|(i, [x, y, z, _])| {
+                if !covered(i) {
+                    return [0.; 3];
+                }
+                decode_normal(Vec3::new(*x, *y, *z), self.settings.octahedral_normals).to_array()
+            })
+            .collect();
+        let depth = position
+            .iter()
+            .enumerate()
+            .map(|(i, position)| {
+                if !covered(i) {
+                    return f32::INFINITY;
+                }
+                -view
+                    .mat_view_relative
+                    .transform_point3(Vec3::from_array(*position))
+                    .z
+            })
+            .collect();
+        Ok(Aovs {
+            size: self.size,
+            color: flip_rows(self.out_color.mipmap(0).unwrap().download()?, width),
+            albedo: flip_rows(self.albedo.mipmap(0).unwrap().download()?, width),
+            normal,
+            depth,
+            roughness: rough_metal.iter().map(|[r, _]| *r).collect(),
+            metallic: rough_metal.iter().map(|[_, m]| *m).collect(),
+        })
+    }
+
     #[cfg(never)]
     #[tracing::instrument(skip_all)]
     pub fn draw_meshes<MC: std::ops::Deref<Target = Mesh>>(
@@ -154,49 +599,69 @@ impl GeometryBuffers {
         Ok(())
     }
 
-    pub fn debug_position(&self, frame: &Framebuffer) -> Result<()> {
-        let unit = self.pos.as_uniform(0)?;
-        self.blit
-            .program()
-            .set_uniform(self.uniform_blit_source, unit)?;
-        self.blit.draw(frame)?;
-        Ok(())
-    }
-
-    pub fn debug_albedo(&self, frame: &Framebuffer) -> Result<()> {
-        let unit = self.albedo.as_uniform(0)?;
-        self.blit
-            .program()
-            .set_uniform(self.uniform_blit_source, unit)?;
-        self.blit.draw(frame)?;
-        Ok(())
-    }
-
-    pub fn debug_normal(&self, frame: &Framebuffer) -> Result<()> {
-        let unit = self.normal_coverage.as_uniform(0)?;
-        self.blit
-            .program()
-            .set_uniform(self.uniform_blit_source, unit)?;
-        self.blit.draw(frame)?;
-        Ok(())
-    }
-
-    pub fn debug_rough_metal(&self, frame: &Framebuffer) -> Result<()> {
-        let unit = self.rough_metal.as_uniform(0)?;
-        self.blit
-            .program()
-            .set_uniform(self.uniform_blit_source, unit)?;
-        self.blit.draw(frame)?;
+    /// Set the texture of the debug source as the sampler uniform of the program, on unit 0.
+    pub fn set_debug_texture(
+        &self,
+        program: &Program,
+        location: UniformLocation,
+        source: DebugSource,
+    ) -> Result<()> {
+        match source {
+            DebugSource::Lit => {
+                program.set_uniform(location, self.out_color.as_uniform(0).counted()?)?
+            }
+            DebugSource::Position => {
+                program.set_uniform(location, self.pos.as_uniform(0).counted()?)?
+            }
+            DebugSource::Albedo => {
+                program.set_uniform(location, self.albedo.as_uniform(0).counted()?)?
+            }
+            DebugSource::Normal => {
+                program.set_uniform(location, self.normal_coverage.as_uniform(0).counted()?)?
+            }
+            DebugSource::RoughMetal => {
+                program.set_uniform(location, self.rough_metal.as_uniform(0).counted()?)?
+            }
+            DebugSource::Emission => {
+                program.set_uniform(location, self.emission.as_uniform(0).counted()?)?
+            }
+            DebugSource::CoatAnisotropy => {
+                program.set_uniform(location, self.coat_aniso.as_uniform(0).counted()?)?
+            }
+            DebugSource::Depth => {
+                program.set_uniform(location, self.out_depth.as_uniform(0).counted()?)?
+            }
+        }
         Ok(())
     }
 
-    pub fn debug_emission(&self, frame: &Framebuffer) -> Result<()> {
-        let unit = self.emission.as_uniform(0)?;
-        self.blit
-            .program()
-            .set_uniform(self.uniform_blit_source, unit)?;
-        self.blit.draw(frame)?;
-        Ok(())
+    /// Start reading the raw values of a pixel of the debug source, as RGBA floats. Depth is read
+    /// from the positions, as depth textures cannot be read as colors.
+    pub fn read_debug_pixel(&self, source: DebugSource, pixel: UVec2) -> Result<Readback> {
+        let format = ReadbackFormat::Rgba32F;
+        match source {
+            DebugSource::Lit => {
+                Readback::texture_region(&self.out_color, 0, pixel, UVec2::ONE, format)
+            }
+            DebugSource::Position | DebugSource::Depth => {
+                Readback::texture_region(&self.pos, 0, pixel, UVec2::ONE, format)
+            }
+            DebugSource::Albedo => {
+                Readback::texture_region(&self.albedo, 0, pixel, UVec2::ONE, format)
+            }
+            DebugSource::Normal => {
+                Readback::texture_region(&self.normal_coverage, 0, pixel, UVec2::ONE, format)
+            }
+            DebugSource::RoughMetal => {
+                Readback::texture_region(&self.rough_metal, 0, pixel, UVec2::ONE, format)
+            }
+            DebugSource::Emission => {
+                Readback::texture_region(&self.emission, 0, pixel, UVec2::ONE, format)
+            }
+            DebugSource::CoatAnisotropy => {
+                Readback::texture_region(&self.coat_aniso, 0, pixel, UVec2::ONE, format)
+            }
+        }
     }
 
     pub fn size(&self) -> UVec2 {
@@ -204,40 +669,63 @@ impl GeometryBuffers {
     }
 
     #[tracing::instrument(skip_all)]
+    /// Light the G-Buffer, whose positions are relative to the origin of the view, which the
+    /// positions of `lights` must also be relative to.
     pub fn process(
         &self,
         cam_uniform: &ViewUniformBuffer,
-        lights: &LightBuffer,
+        lights: &Lights,
+        cookies: &LightCookies,
+        culling: Option<&LightCulling>,
         mut env: Option<&mut dyn Environment>,
+        probes: Option<&IrradianceProbes>,
+        reflections: Option<&ReflectionProbes>,
     ) -> Result<&Texture<[f32; 3]>> {
         Framebuffer::enable_blending(Blend::One, Blend::One);
         Framebuffer::clear_color([0., 0., 0., 1.]);
-        self.output_fbo.do_clear(ClearBuffer::COLOR);
+        // Also clears the weight of the reflection probes
+        self.reflection_fbo.do_clear(ClearBuffer::COLOR);
 
         {
             let program = self.blit.program();
-            program.set_uniform(self.uniform_blit_source, self.emission.as_uniform(3)?)?;
+            program.set_uniform(
+                self.uniform_blit_source,
+                self.emission.as_uniform(3).counted()?,
+            )?;
         }
         self.blit.draw(&self.output_fbo)?;
+        let mat_info = MaterialInfo {
+            position: &self.pos,
+            albedo: &self.albedo,
+            normal_coverage: &self.normal_coverage,
+            roughness_metal: &self.rough_metal,
+            reflection_weight: &self.reflection_weight,
+        };
+        // Reflection probes go first, so the environment knows how much of its own reflections
+        // they replace
+        if let Some(reflections) = reflections {
+            reflections.draw(&self.reflection_fbo, cam_uniform, mat_info, lights.origin())?;
+        }
         if let Some(env) = &mut env {
-            let mat_info = MaterialInfo {
-                position: &self.pos,
-                albedo: &self.albedo,
-                normal_coverage: &self.normal_coverage,
-                roughness_metal: &self.rough_metal,
-            };
             env.draw(&self.output_fbo, cam_uniform, mat_info)?;
         }
+        if let Some(probes) = probes {
+            probes.draw(&self.output_fbo, mat_info, lights.origin())?;
+        }
 
         if lights.is_empty() {
             return Ok(&self.out_color);
         }
 
-        let unit_pos = self.pos.as_uniform(0)?;
-        let unit_albedo = self.albedo.as_uniform(1)?;
-        let unit_normal = self.normal_coverage.as_uniform(2)?;
-        let unit_rough_metal = self.rough_metal.as_uniform(3)?;
-        let unit_emission = self.emission.as_uniform(3)?;
+        let unit_pos = self.pos.as_uniform(0).counted()?;
+        let unit_albedo = self.albedo.as_uniform(1).counted()?;
+        let unit_normal = self.normal_coverage.as_uniform(2).counted()?;
+        let unit_rough_metal = self.rough_metal.as_uniform(3).counted()?;
+        let unit_emission = self.emission.as_uniform(4).counted()?;
+        let unit_coat_aniso = self.coat_aniso.as_uniform(5).counted()?;
+        // Unit 6 is taken by light cookies
+        let unit_ltc_inverse = self.ltc.inverse.as_uniform(7).counted()?;
+        let unit_ltc_amplitude = self.ltc.amplitude.as_uniform(8).counted()?;
         {
             let pass_program = self.screen_pass.program();
             pass_program.set_uniform(self.uniform_frame_pos, unit_pos)?;
@@ -245,14 +733,39 @@ impl GeometryBuffers {
             pass_program.set_uniform(self.uniform_frame_normal, unit_normal)?;
             pass_program.set_uniform(self.uniform_frame_rough_metal, unit_rough_metal)?;
             pass_program.set_uniform(self.uniform_frame_emission, unit_emission)?;
+            pass_program.set_uniform(self.uniform_frame_coat_aniso, unit_coat_aniso)?;
+            pass_program.set_uniform(self.uniform_ltc_inverse, unit_ltc_inverse)?;
+            pass_program.set_uniform(self.uniform_ltc_amplitude, unit_ltc_amplitude)?;
         }
 
-        for light_ix in 0..lights.len() {
-            self.screen_pass.program().bind_block(
-                &lights.slice(light_ix..=light_ix),
-                self.uniform_block_light,
-                0,
-            )?;
+        for (light_ix, (handle, light)) in lights.iter().enumerate() {
+            if matches!(culling, Some(culling) if culling.is_culled(handle)) {
+                continue;
+            }
+            let cookie = cookies
+                .get(&handle)
+                .filter(|cookie| cookie.projection.applies_to(light));
+            {
+                let pass_program = self.screen_pass.program();
+                pass_program.bind_block(
+                    &lights.buffer().slice(light_ix..=light_ix),
+                    self.uniform_block_light,
+                    0,
+                )?;
+                pass_program.set_uniform(
+                    self.uniform_cookie_kind,
+                    cookie.map_or(0, |cookie| cookie.shader_kind()),
+                )?;
+                if let Some(cookie) = cookie {
+                    pass_program.set_uniform(
+                        self.uniform_cookie,
+                        cookie.texture.as_uniform(6).counted()?,
+                    )?;
+                    pass_program
+                        .set_uniform(self.uniform_cookie_rotation, cookie.world_to_cookie())?;
+                    pass_program.set_uniform(self.uniform_cookie_size, cookie.size())?;
+                }
+            }
             self.screen_pass.draw(&self.output_fbo)?;
         }
 
@@ -270,8 +783,44 @@ impl GeometryBuffers {
             .clear_resize(width, height, nonzero_one)?;
         self.rough_metal.clear_resize(width, height, nonzero_one)?;
         self.emission.clear_resize(width, height, nonzero_one)?;
+        self.coat_aniso.clear_resize(width, height, nonzero_one)?;
         self.out_color.clear_resize(width, height, nonzero_one)?;
+        self.reflection_weight
+            .clear_resize(width, height, nonzero_one)?;
         self.out_depth.clear_resize(width, height, nonzero_one)?;
+        self.size = size;
+        self.apply_formats();
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gbuffer_include_is_valid() {
+        let source = include_str!("../../../res/shaders/common/gbuffer.glsl");
+        validate_gbuffer_outputs([source]).unwrap();
+    }
+
+    #[test]
+    fn reports_wrong_type_and_missing_outputs() {
+        let source = "layout(location = 2) out vec3 frame_normal;";
+        let err = validate_gbuffer_outputs([source]).unwrap_err().to_string();
+        assert!(err.contains("expects a vec4"), "{err}");
+
+        let source = "layout(location=0) out vec3 frame_position; // comment";
+        let err = validate_gbuffer_outputs([source]).unwrap_err().to_string();
+        assert!(err.contains("Albedo"), "{err}");
+        assert!(!err.contains("Position"), "{err}");
+    }
+
+    #[test]
+    fn shader_config_defines_normal_encoding() {
+        let config = GBufferSettings::default().shader_config();
+        assert!(!config.contains("GBUFFER_OCTAHEDRAL_NORMALS"));
+        let config = GBufferSettings::low_bandwidth().shader_config();
+        assert!(config.contains("#define GBUFFER_OCTAHEDRAL_NORMALS"));
+    }
+}
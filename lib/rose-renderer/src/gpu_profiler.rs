@@ -0,0 +1,314 @@
+//! GPU timeline of the render passes, sent to Tracy when the `tracy` feature is enabled.
+//!
+//! Zones are measured with timestamp queries which are read back frames later, without stalling,
+//! and uploaded to the GPU context of Tracy. Without the feature, zones compile down to nothing.
+
+use std::{fmt, rc::Rc};
+
+#[cfg(feature = "tracy")]
+use std::{cell::RefCell, collections::VecDeque, panic::Location};
+
+#[cfg(feature = "tracy")]
+use glam::UVec2;
+#[cfg(feature = "tracy")]
+use rose_core::readback::{Readback, ReadbackFormat};
+#[cfg(feature = "tracy")]
+use tracy_client::{Client, GpuContext, GpuContextType, GpuSpan};
+#[cfg(feature = "tracy")]
+use violette::gl;
+
+/// Maximum width of the frame images sent to Tracy.
+#[cfg(feature = "tracy")]
+const FRAME_IMAGE_WIDTH: u32 = 320;
+/// Frame images read back at once; Tracy only places images up to a few frames late.
+#[cfg(feature = "tracy")]
+const MAX_PENDING_FRAME_IMAGES: usize = 3;
+
+#[derive(Default)]
+pub struct GpuProfiler {
+    #[cfg(feature = "tracy")]
+    tracy: RefCell<Option<TracyGpu>>,
+}
+
+impl fmt::Debug for GpuProfiler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GpuProfiler").finish_non_exhaustive()
+    }
+}
+
+impl GpuProfiler {
+    /// Create the profiler, connecting to Tracy when the `tracy` feature is enabled. Needs a
+    /// current OpenGL context.
+    pub fn new() -> Self {
+        #[cfg(feature = "tracy")]
+        {
+            let tracy = TracyGpu::new()
+                .map_err(|err| tracing::warn!("Cannot create Tracy GPU context: {}", err))
+                .ok();
+            Self {
+                tracy: RefCell::new(tracy),
+            }
+        }
+        #[cfg(not(feature = "tracy"))]
+        Self::default()
+    }
+
+    /// Start a GPU zone, which ends when the returned guard is dropped. Zones must be dropped in
+    /// reverse order of creation.
+    #[track_caller]
+    #[cfg_attr(not(feature = "tracy"), allow(unused_variables))]
+    pub fn zone(self: &Rc<Self>, name: &str) -> GpuZone {
+        #[cfg(feature = "tracy")]
+        if let Some(tracy) = &mut *self.tracy.borrow_mut() {
+            tracy.begin_zone(name, Location::caller());
+        }
+        GpuZone {
+            #[cfg(feature = "tracy")]
+            profiler: self.clone(),
+        }
+    }
+
+    /// Upload the zones whose timings are available, and send the frame image if enabled. Call
+    /// once per frame, with the backbuffer holding the rendered frame.
+    #[cfg_attr(not(feature = "tracy"), allow(unused_variables))]
+    pub fn end_frame(&self, backbuffer_size: glam::UVec2) {
+        #[cfg(feature = "tracy")]
+        if let Some(tracy) = &mut *self.tracy.borrow_mut() {
+            tracy.collect();
+            if let Some(capture) = &mut tracy.frame_images {
+                capture.send(backbuffer_size);
+            }
+        }
+    }
+
+    /// Send a downscaled image of every frame to Tracy. Reading back the frames stalls the GPU.
+    #[cfg(feature = "tracy")]
+    pub fn set_frame_images(&self, enabled: bool) {
+        if let Some(tracy) = &mut *self.tracy.borrow_mut() {
+            match (enabled, &tracy.frame_images) {
+                (true, None) => tracy.frame_images = Some(FrameImageCapture::default()),
+                (false, Some(_)) => tracy.frame_images = None,
+                _ => {}
+            }
+        }
+    }
+
+    #[cfg(feature = "tracy")]
+    pub fn frame_images(&self) -> bool {
+        self.tracy
+            .borrow()
+            .as_ref()
+            .is_some_and(|tracy| tracy.frame_images.is_some())
+    }
+}
+
+/// Guard of a zone started with [`GpuProfiler::zone`].
+#[must_use = "The zone ends when the guard is dropped"]
+pub struct GpuZone {
+    #[cfg(feature = "tracy")]
+    profiler: Rc<GpuProfiler>,
+}
+
+impl Drop for GpuZone {
+    fn drop(&mut self) {
+        #[cfg(feature = "tracy")]
+        if let Some(tracy) = &mut *self.profiler.tracy.borrow_mut() {
+            tracy.end_zone();
+        }
+    }
+}
+
+#[cfg(feature = "tracy")]
+struct Zone {
+    span: GpuSpan,
+    start: u32,
+    end: u32,
+}
+
+#[cfg(feature = "tracy")]
+struct TracyGpu {
+    context: GpuContext,
+    free_queries: Vec<u32>,
+    /// Zones started and not ended yet, innermost last; `None` for zones Tracy refused.
+    open: Vec<Option<(GpuSpan, u32)>>,
+    /// Ended zones waiting for their query results, oldest first.
+    pending: VecDeque<Zone>,
+    frame_images: Option<FrameImageCapture>,
+}
+
+#[cfg(feature = "tracy")]
+impl TracyGpu {
+    fn new() -> eyre::Result<Self> {
+        let mut timestamp = 0;
+        unsafe { gl::GetInteger64v(gl::TIMESTAMP, &mut timestamp) };
+        let context = Client::start()
+            .new_gpu_context(Some("OpenGL"), GpuContextType::OpenGL, timestamp, 1.)
+            .map_err(|err| eyre::eyre!("{:?}", err))?;
+        Ok(Self {
+            context,
+            free_queries: vec![],
+            open: vec![],
+            pending: VecDeque::new(),
+            frame_images: None,
+        })
+    }
+
+    fn query(&mut self) -> u32 {
+        self.free_queries.pop().unwrap_or_else(|| {
+            let mut id = 0;
+            unsafe { gl::GenQueries(1, &mut id) };
+            id
+        })
+    }
+
+    fn begin_zone(&mut self, name: &str, location: &Location) {
+        let span = match self
+            .context
+            .span_alloc(name, "", location.file(), location.line())
+        {
+            Ok(span) => span,
+            Err(err) => {
+                tracing::warn!("Cannot create GPU zone {}: {:?}", name, err);
+                self.open.push(None);
+                return;
+            }
+        };
+        let start = self.query();
+        unsafe { gl::QueryCounter(start, gl::TIMESTAMP) };
+        self.open.push(Some((span, start)));
+    }
+
+    fn end_zone(&mut self) {
+        let Some(Some((mut span, start))) = self.open.pop() else { return; };
+        span.end_zone();
+        let end = self.query();
+        unsafe { gl::QueryCounter(end, gl::TIMESTAMP) };
+        self.pending.push_back(Zone { span, start, end });
+    }
+
+    fn collect(&mut self) {
+        while let Some(zone) = self.pending.front() {
+            let mut available = 0;
+            unsafe { gl::GetQueryObjectiv(zone.end, gl::QUERY_RESULT_AVAILABLE, &mut available) };
+            if available == 0 {
+                break;
+            }
+            let zone = self.pending.pop_front().unwrap();
+            let (mut start, mut end) = (0, 0);
+            unsafe {
+                gl::GetQueryObjecti64v(zone.start, gl::QUERY_RESULT, &mut start);
+                gl::GetQueryObjecti64v(zone.end, gl::QUERY_RESULT, &mut end);
+            }
+            zone.span.upload_timestamp(start, end);
+            self.free_queries.extend([zone.start, zone.end]);
+        }
+    }
+}
+
+#[cfg(feature = "tracy")]
+impl Drop for TracyGpu {
+    fn drop(&mut self) {
+        let queries = self
+            .pending
+            .drain(..)
+            .flat_map(|zone| [zone.start, zone.end])
+            .chain(self.open.drain(..).flatten().map(|(_, start)| start))
+            .chain(self.free_queries.drain(..))
+            .collect::<Vec<_>>();
+        unsafe { gl::DeleteQueries(queries.len() as _, queries.as_ptr()) };
+    }
+}
+
+/// Downscales the backbuffer into a renderbuffer and reads it back for Tracy, without stalling.
+#[cfg(feature = "tracy")]
+#[derive(Default)]
+struct FrameImageCapture {
+    framebuffer: u32,
+    renderbuffer: u32,
+    size: UVec2,
+    /// Images being read back, oldest first, with the frame they were captured on.
+    pending: VecDeque<(Readback, u64)>,
+    frame: u64,
+}
+
+#[cfg(feature = "tracy")]
+impl FrameImageCapture {
+    /// Size of the image sent for the backbuffer size; Tracy needs multiples of 4.
+    fn image_size(backbuffer_size: UVec2) -> UVec2 {
+        let width = backbuffer_size.x.min(FRAME_IMAGE_WIDTH);
+        let height = backbuffer_size.y * width / backbuffer_size.x.max(1);
+        UVec2::new(width, height) / 4 * 4
+    }
+
+    fn send(&mut self, backbuffer_size: UVec2) {
+        let size = Self::image_size(backbuffer_size);
+        if size.cmpeq(UVec2::ZERO).any() {
+            return;
+        }
+        unsafe {
+            if size != self.size {
+                if self.framebuffer == 0 {
+                    gl::GenFramebuffers(1, &mut self.framebuffer);
+                    gl::GenRenderbuffers(1, &mut self.renderbuffer);
+                }
+                gl::BindRenderbuffer(gl::RENDERBUFFER, self.renderbuffer);
+                gl::RenderbufferStorage(gl::RENDERBUFFER, gl::RGBA8, size.x as _, size.y as _);
+                gl::BindRenderbuffer(gl::RENDERBUFFER, 0);
+                gl::BindFramebuffer(gl::FRAMEBUFFER, self.framebuffer);
+                gl::FramebufferRenderbuffer(
+                    gl::FRAMEBUFFER,
+                    gl::COLOR_ATTACHMENT0,
+                    gl::RENDERBUFFER,
+                    self.renderbuffer,
+                );
+                self.size = size;
+            }
+            gl::BindFramebuffer(gl::READ_FRAMEBUFFER, 0);
+            gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, self.framebuffer);
+            gl::BlitFramebuffer(
+                0,
+                0,
+                backbuffer_size.x as _,
+                backbuffer_size.y as _,
+                0,
+                0,
+                size.x as _,
+                size.y as _,
+                gl::COLOR_BUFFER_BIT,
+                gl::LINEAR,
+            );
+            gl::BindFramebuffer(gl::READ_FRAMEBUFFER, self.framebuffer);
+        }
+        let readback = Readback::framebuffer(UVec2::ZERO, size, ReadbackFormat::Rgba8);
+        unsafe { gl::BindFramebuffer(gl::FRAMEBUFFER, 0) };
+        match readback {
+            Ok(readback) => self.pending.push_back((readback, self.frame)),
+            Err(err) => tracing::warn!("Cannot read back frame image: {}", err),
+        }
+        if self.pending.len() > MAX_PENDING_FRAME_IMAGES {
+            self.pending.pop_front();
+        }
+        while let Some((readback, frame)) = self.pending.front_mut() {
+            let Some(pixels) = readback.poll() else { break; };
+            let size = readback.size();
+            let offset = (self.frame - *frame) as u8;
+            if let Some(client) = Client::running() {
+                client.frame_image(&pixels, size.x as _, size.y as _, offset, true);
+            }
+            self.pending.pop_front();
+        }
+        self.frame += 1;
+    }
+}
+
+#[cfg(feature = "tracy")]
+impl Drop for FrameImageCapture {
+    fn drop(&mut self) {
+        if self.framebuffer != 0 {
+            unsafe {
+                gl::DeleteFramebuffers(1, &self.framebuffer);
+                gl::DeleteRenderbuffers(1, &self.renderbuffer);
+            }
+        }
+    }
+}
@@ -0,0 +1,98 @@
+//! Handles to the meshes, materials and textures registered with the [`crate::Renderer`], which
+//! owns them.
+//! Meshes are submitted every frame by handle, see [`crate::Renderer::submit`], and so are
+//! sprites, by the handle of their texture, see [`crate::Renderer::submit_sprite`].
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct SlotKey {
+    index: u32,
+    generation: u32,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct MeshHandle(pub(crate) SlotKey);
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct MaterialHandle(pub(crate) SlotKey);
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct TextureHandle(pub(crate) SlotKey);
+
+/// Storage reusing the slots of removed values. Slots are versioned, so that keys of removed
+/// values never resolve to the value stored in their slot afterwards.
+#[derive(Debug)]
+pub(crate) struct Slots<T> {
+    entries: Vec<(u32, Option<T>)>,
+    free: Vec<u32>,
+}
+
+impl<T> Default for Slots<T> {
+    fn default() -> Self {
+        Self {
+            entries: vec![],
+            free: vec![],
+        }
+    }
+}
+
+impl<T> Slots<T> {
+    pub fn insert(&mut self, value: T) -> SlotKey {
+        if let Some(index) = self.free.pop() {
+            let (generation, slot) = &mut self.entries[index as usize];
+            *slot = Some(value);
+            SlotKey {
+                index,
+                generation: *generation,
+            }
+        } else {
+            self.entries.push((0, Some(value)));
+            SlotKey {
+                index: self.entries.len() as u32 - 1,
+                generation: 0,
+            }
+        }
+    }
+
+    pub fn get(&self, key: SlotKey) -> Option<&T> {
+        match self.entries.get(key.index as usize)? {
+            (generation, Some(value)) if *generation == key.generation => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn get_mut(&mut self, key: SlotKey) -> Option<&mut T> {
+        match self.entries.get_mut(key.index as usize)? {
+            (generation, Some(value)) if *generation == key.generation => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn remove(&mut self, key: SlotKey) -> Option<T> {
+        let (generation, slot) = self.entries.get_mut(key.index as usize)?;
+        if *generation != key.generation {
+            return None;
+        }
+        let value = slot.take()?;
+        *generation += 1;
+        self.free.push(key.index);
+        Some(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn removed_keys_stay_invalid() {
+        let mut slots = Slots::default();
+        let a = slots.insert("a");
+        let b = slots.insert("b");
+        assert_eq!(slots.remove(a), Some("a"));
+        assert_eq!(slots.remove(a), None);
+        let c = slots.insert("c");
+        assert_eq!(slots.get(a), None);
+        assert_eq!(slots.get(b), Some(&"b"));
+        assert_eq!(slots.get(c), Some(&"c"));
+    }
+}
@@ -2,47 +2,144 @@ use std::path::Path;
 use std::{
     any::Any,
     cell::RefCell,
-    collections::HashMap,
-    fmt, ops,
+    collections::{hash_map::DefaultHasher, HashMap},
+    fmt,
+    hash::{Hash, Hasher},
+    ops,
     rc::Rc,
     time::{Duration, Instant},
 };
 
+use crossbeam_channel::{Receiver, Sender};
 use eyre::Result;
-use glam::{vec2, UVec2, Vec3, Vec4Swizzles};
+use glam::{vec2, vec4, UVec2, UVec3, Vec2, Vec3, Vec4, Vec4Swizzles};
 use tracing::span::EnteredSpan;
 
-use gbuffers::GeometryBuffers;
+use frame_graph::{FrameGraph, GraphPass};
+use gbuffer_debug::{GBufferViewParams, GBufferViewer};
+use gbuffers::{GBufferSettings, GeometryBuffers};
 use material::Material;
-use postprocess::Postprocess;
+use postprocess::{FrameTexture, PostEffect, PostEffectChain, Postprocess, TexturePool};
 use rose_core::{
-    camera::{Camera, ViewUniform, ViewUniformBuffer},
-    light::{GpuLight, Light, LightBuffer},
+    atlas::AtlasId,
+    camera::{self, Camera, DepthMode, ViewUniform, ViewUniformBuffer},
+    color,
+    light::{Light, LightHandle, Lights},
     transform::Transformed,
-    utils::{reload_watcher::ReloadWatcher, thread_guard::ThreadGuard},
+    utils::{draw_counters, reload_watcher::ReloadWatcher, thread_guard::ThreadGuard},
 };
 use violette::{
     framebuffer::{ClearBuffer, DepthTestFunction, Framebuffer},
+    texture::Texture,
     Cull, FrontFace,
 };
 
-use crate::bones::Bone;
-pub use crate::postprocess::LensFlareParams;
-use crate::{env::Environment, material::MaterialInstance};
+use crate::bones::{Bone, Skinning};
+use crate::handles::Slots;
+pub use crate::handles::{MaterialHandle, MeshHandle, TextureHandle};
+use crate::morph::MorphTargets;
+pub use crate::postprocess::{
+    AutoExposureParams, BloomQuality, BloomResolution, ExposureDebugParams, ExposureView,
+    LensFlareParams, MeteringMode,
+};
+use crate::{
+    aov::Aovs,
+    atlas::{TextureAtlas, SPRITE_ATLAS_SIZE},
+    cookie::{LightCookie, LightCookies},
+    cubemap::{Cubemap, CUBEMAP_FAR, CUBEMAP_NEAR},
+    depth_prepass::DepthPrepass,
+    env::Environment,
+    environments::{Environments, DEFAULT_ENVIRONMENT},
+    fog::{Fog, FogParams},
+    gpu_profiler::GpuProfiler,
+    light_culling::{LightCulling, LightCullingParams},
+    material::MaterialInstance,
+    occlusion::OcclusionCulling,
+    outline::{Outline, OutlineParams},
+    polyline::{PolylinePoint, Polylines},
+    present::FrameCache,
+    probes::{IrradianceProbeGrid, IrradianceProbes},
+    queue::{RenderCommand, RenderQueue},
+    reflection_probes::{ReflectionProbe, ReflectionProbeId, ReflectionProbes},
+    refraction::{Refraction, RefractionParams},
+    render_target::{RenderTargetView, RenderTargets},
+    resolution::{ResolutionScaling, Upscaler},
+    sprites::{Billboard, Sprite, Sprites},
+    stats::{FrameStatsHistory, RenderFrameStats},
+    water::{Water, WaterParams},
+};
 
+pub mod aov;
+pub mod atlas;
+pub mod batching;
 pub mod bones;
+pub mod clip;
+pub mod cookie;
+pub mod cubemap;
+pub mod depth_prepass;
 pub mod env;
+pub mod environments;
+pub mod fog;
+pub mod frame_graph;
+pub mod gbuffer_debug;
 pub mod gbuffers;
+pub mod gpu_profiler;
+pub mod handles;
+pub mod light_culling;
+pub mod ltc;
 pub mod material;
+pub mod morph;
+pub mod occlusion;
+pub mod outline;
+pub mod polyline;
 pub mod postprocess;
 pub mod prelude;
+pub mod present;
+pub mod probes;
+pub mod queue;
+pub mod reflection_probes;
+pub mod refraction;
+pub mod render_target;
+pub mod resolution;
+pub mod shader_material;
+pub mod splat;
+pub mod sprites;
+pub mod stats;
+pub mod streaming;
+pub mod water;
 
 pub type InnerMesh = rose_core::mesh::Mesh<material::Vertex>;
 
+/// Spacing of the grid the render origin snaps to with camera-relative rendering, keeping model
+/// matrices the same from one frame to the next while the camera stays close.
+const ORIGIN_GRID_SIZE: f32 = 1024.;
+/// Render target the water reflections are rendered into.
+const WATER_REFLECTION_TARGET: &str = "water-reflection";
+
+/// Change to the view of every frame, applied once it is computed from the camera, e.g. to jitter
+/// the projection with [`ViewUniform::jitter`] or clip it with [`ViewUniform::clip_near_plane`].
+/// See [`Renderer::set_view_hook`].
+pub struct ViewHook(Box<dyn FnMut(&mut ViewUniform)>);
+
+impl ViewHook {
+    pub fn new(hook: impl 'static + FnMut(&mut ViewUniform)) -> Self {
+        Self(Box::new(hook))
+    }
+}
+
+impl fmt::Debug for ViewHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ViewHook").finish_non_exhaustive()
+    }
+}
+
 #[derive(Debug)]
 pub struct Mesh {
     inner: InnerMesh,
     pub root_bone: Option<Rc<Bone>>,
+    /// Blending of the bone transforms, when the mesh has bones.
+    pub skinning: Skinning,
+    pub morph_targets: Option<MorphTargets>,
 }
 
 impl From<InnerMesh> for Mesh {
@@ -50,6 +147,8 @@ impl From<InnerMesh> for Mesh {
         Self {
             inner: value,
             root_bone: None,
+            skinning: Skinning::default(),
+            morph_targets: None,
         }
     }
 }
@@ -62,6 +161,8 @@ impl Mesh {
         Ok(Self {
             inner: InnerMesh::new(vertices, indices)?,
             root_bone: None,
+            skinning: Skinning::default(),
+            morph_targets: None,
         })
     }
 }
@@ -75,10 +176,26 @@ impl ops::Deref for Mesh {
 }
 
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serialize", serde(default))]
 pub struct PostprocessInterface {
     pub exposure: f32,
+    pub auto_exposure: AutoExposureParams,
     pub bloom: BloomInterface,
     pub lens_flare: LensFlareParams,
+    pub debug: ExposureDebugParams,
+}
+
+impl Default for PostprocessInterface {
+    fn default() -> Self {
+        Self {
+            exposure: 1.5f32.exp2(),
+            auto_exposure: AutoExposureParams::default(),
+            bloom: BloomInterface::default(),
+            lens_flare: LensFlareParams::default(),
+            debug: ExposureDebugParams::default(),
+        }
+    }
 }
 
 impl PostprocessInterface {
@@ -126,12 +243,57 @@ impl PostprocessInterface {
                     )
                     .labelled_by(bloom_strength_label.id);
                     ui.end_row();
+
+                    let bloom_threshold_label = ui.label("Bloom threshold:");
+                    ui.add(
+                        egui::Slider::new(&mut self.bloom.threshold, 1e-3..=1e5)
+                            .logarithmic(true)
+                            .show_value(true)
+                            .suffix(" nits")
+                            .text("Bloom threshold"),
+                    )
+                    .labelled_by(bloom_threshold_label.id);
+                    ui.end_row();
+
+                    let bloom_quality_label = ui.label("Bloom quality:");
+                    ui.horizontal(|ui| {
+                        let quality = &mut self.bloom.quality;
+                        ui.selectable_value(quality, BloomQuality::LOW, "Low");
+                        ui.selectable_value(quality, BloomQuality::MEDIUM, "Medium");
+                        ui.selectable_value(quality, BloomQuality::HIGH, "High");
+                    })
+                    .response
+                    .labelled_by(bloom_quality_label.id);
+                    ui.end_row();
+
+                    let bloom_resolution_label = ui.label("Bloom resolution:");
+                    ui.horizontal(|ui| {
+                        let resolution = &mut self.bloom.quality.resolution;
+                        ui.selectable_value(resolution, BloomResolution::Half, "Half");
+                        ui.selectable_value(resolution, BloomResolution::Quarter, "Quarter");
+                    })
+                    .response
+                    .labelled_by(bloom_resolution_label.id);
+                    ui.end_row();
+
+                    let bloom_passes_label = ui.label("Bloom passes:");
+                    ui.add(DragValue::new(&mut self.bloom.quality.passes).clamp_range(1..=8))
+                        .labelled_by(bloom_passes_label.id);
+                    ui.end_row();
                 });
         });
+        ui.collapsing("Auto exposure", |ui| {
+            self.auto_exposure.ui(ui);
+        });
         ui.collapsing("Lens Flare", |ui| {
             Grid::new("postprocess-lens-flares")
                 .num_columns(2)
                 .show(ui, |ui| {
+                    let enabled_label = ui.label("Enabled").id;
+                    ui.checkbox(&mut self.lens_flare.enabled, "")
+                        .labelled_by(enabled_label);
+                    ui.end_row();
+
                     let strength_label = ui.label("Strength").id;
                     self.lens_flare.strength *= 100.;
                     ui.add(DragValue::new(&mut self.lens_flare.strength).suffix(" %"))
@@ -165,81 +327,358 @@ impl PostprocessInterface {
 }
 
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serialize", serde(default))]
 pub struct BloomInterface {
+    pub quality: BloomQuality,
     pub size: f32,
     pub strength: f32,
+    /// Scene luminance, in nits, above which pixels start contributing to the bloom. Compared
+    /// against the scene before exposure is applied, so bright emissive surfaces bloom the same
+    /// regardless of the current exposure.
+    pub threshold: f32,
+}
+
+impl Default for BloomInterface {
+    fn default() -> Self {
+        Self {
+            quality: BloomQuality::default(),
+            size: 1e-3,
+            strength: 4e-2,
+            threshold: 1.,
+        }
+    }
+}
+
+/// Renderer options which cannot change after its creation.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serialize", serde(default))]
+pub struct RendererConfig {
+    pub gbuffer: GBufferSettings,
+    /// Enable the depth pre-pass from the start, see [`Renderer::set_depth_prepass`].
+    pub depth_prepass: bool,
+    /// Enable occlusion culling from the start, see [`Renderer::set_occlusion_culling`].
+    pub occlusion_culling: bool,
+    /// Validate color spaces from the start, see [`Renderer::set_color_space_validation`].
+    pub validate_color_spaces: bool,
 }
 
 #[derive(Debug)]
 pub struct Renderer {
-    lights: LightBuffer,
+    lights: Lights,
+    light_cookies: LightCookies,
+    light_culling: LightCulling,
     geom_pass: Rc<RefCell<GeometryBuffers>>,
     material: Rc<RefCell<Material>>,
     post_process: Postprocess,
+    post_effects: PostEffectChain,
+    /// Intermediate textures of the post processing chain, shared by the upscaler and the post
+    /// effects.
+    texture_pool: TexturePool,
+    frame_graph: FrameGraph,
+    outline: Outline,
+    outline_params: OutlineParams,
+    hover_params: OutlineParams,
+    resolution_scaling: ResolutionScaling,
+    upscaler: Upscaler,
+    size: UVec2,
     post_process_iface: PostprocessInterface,
-    environment: Option<Box<dyn Environment>>,
+    /// Position of the luminance readout, see [`Self::set_luminance_probe`].
+    luminance_probe: Option<Vec2>,
+    environments: Environments,
+    fog: Fog,
+    fog_params: Option<FogParams>,
+    /// World space clip planes of the standard material, see [`clip`].
+    clip_planes: Vec<Vec4>,
+    water: Water,
+    refraction: Refraction,
+    sprites: Sprites,
+    sprite_atlas: TextureAtlas,
+    polylines: Polylines,
+    irradiance_probes: Option<IrradianceProbes>,
+    reflection_probes: Option<ReflectionProbes>,
+    /// Buffers of [`Self::render_cubemap`], kept across captures of the same resolution.
+    cubemap_capture: Option<GeometryBuffers>,
+    render_targets: RenderTargets,
+    depth_prepass: Option<DepthPrepass>,
+    occlusion_culling: Option<OcclusionCulling>,
+    depth_mode: DepthMode,
+    camera_relative: bool,
+    validate_color_spaces: bool,
     view_uniform: ViewUniform,
+    view_hook: Option<ViewHook>,
     camera_uniform: ThreadGuard<ViewUniformBuffer>,
-    queued_materials: Vec<Rc<dyn DrawMaterial>>,
-    queued_meshes: HashMap<usize, Vec<Transformed<Rc<Mesh>>>>,
+    meshes: Slots<Rc<Mesh>>,
+    materials: Slots<Rc<dyn DrawMaterial>>,
+    textures: Slots<Texture<[f32; 4]>>,
+    queued_meshes: HashMap<MaterialHandle, Vec<Transformed<MeshHandle>>>,
+    queued_outlines: Vec<Transformed<MeshHandle>>,
+    queued_hover_outlines: Vec<Transformed<MeshHandle>>,
+    queued_water: Vec<(Transformed<MeshHandle>, WaterParams)>,
+    queued_refractive: Vec<(Transformed<MeshHandle>, RefractionParams)>,
+    queued_sprites: HashMap<TextureHandle, Vec<Sprite>>,
+    queued_atlas_sprites: Vec<Sprite>,
+    queue: Receiver<RenderCommand>,
+    queue_sender: Sender<RenderCommand>,
+    frame_cache: FrameCache,
+    frame_hasher: DefaultHasher,
+    dirty_tracking: bool,
+    last_frame_reused: bool,
     render_span: ThreadGuard<Option<EnteredSpan>>,
     debug_window_open: bool,
+    gbuffer_view: GBufferViewParams,
+    /// Created the first time the debug panel is shown.
+    gbuffer_viewer: Option<Rc<RefCell<GBufferViewer>>>,
     begin_scene_at: Option<Instant>,
-    last_scene_duration: Option<Duration>,
-    last_render_duration: Option<Duration>,
-    last_render_submitted: usize,
-    last_render_rendered: usize,
+    /// Statistics of the frame being rendered.
+    frame_stats: RenderFrameStats,
+    stats_history: FrameStatsHistory,
+    gpu_profiler: Rc<GpuProfiler>,
     reload_watcher: ReloadWatcher,
 }
 
 impl Renderer {
     pub fn new(size: UVec2, base_dir: impl AsRef<Path>) -> Result<Self> {
+        Self::with_config(size, base_dir, RendererConfig::default())
+    }
+
+    pub fn with_config(
+        size: UVec2,
+        base_dir: impl AsRef<Path>,
+        config: RendererConfig,
+    ) -> Result<Self> {
         let reload_watcher = {
             let base_dir = base_dir.as_ref().join("res/shaders");
             ReloadWatcher::new(base_dir)
         };
-        let lights = LightBuffer::new();
-        let geom_pass = GeometryBuffers::new(size, &reload_watcher)?;
-        let post_process = Postprocess::new(size, &reload_watcher)?;
+        // Shaders reading or writing the G-Buffer include its configuration
+        config.gbuffer.register_shader_config();
+        let lights = Lights::new();
+        let geom_pass = GeometryBuffers::new(size, config.gbuffer, &reload_watcher)?;
+        let post_process = Postprocess::new(&reload_watcher)?;
+        let post_effects = PostEffectChain::new(size);
+        let outline = Outline::new(size, &reload_watcher)?;
+        let frame_cache = FrameCache::new(size, &reload_watcher)?;
+        let upscaler = Upscaler::new(&reload_watcher)?;
         let view_uniform = ViewUniform::default();
         let camera_uniform = view_uniform.create_buffer()?;
+        let (queue_sender, queue) = crossbeam_channel::unbounded();
 
-        Ok(Self {
+        let mut renderer = Self {
             lights,
+            light_cookies: LightCookies::new(),
+            light_culling: LightCulling::default(),
             geom_pass: Rc::new(RefCell::new(geom_pass)),
             material: Rc::new(RefCell::new(Material::create(
                 Some(&camera_uniform),
                 &reload_watcher,
             )?)),
             post_process,
-            post_process_iface: PostprocessInterface {
-                exposure: 1.5f32.exp2(),
-                bloom: BloomInterface {
-                    size: 1e-3,
-                    strength: 4e-2,
-                },
-                lens_flare: LensFlareParams::default(),
+            post_effects,
+            texture_pool: TexturePool::default(),
+            frame_graph: FrameGraph::default(),
+            outline,
+            outline_params: OutlineParams::default(),
+            hover_params: OutlineParams::hover(),
+            resolution_scaling: ResolutionScaling::default(),
+            upscaler,
+            size,
+            post_process_iface: PostprocessInterface::default(),
+            luminance_probe: None,
+            environments: Environments::default(),
+            fog: Fog::new(&reload_watcher)?,
+            fog_params: None,
+            clip_planes: vec![],
+            water: Water::new(&reload_watcher)?,
+            refraction: Refraction::new(&reload_watcher)?,
+            sprites: Sprites::new(&reload_watcher)?,
+            sprite_atlas: TextureAtlas::new(UVec2::splat(SPRITE_ATLAS_SIZE)),
+            polylines: Polylines::new(&reload_watcher)?,
+            irradiance_probes: None,
+            reflection_probes: None,
+            cubemap_capture: None,
+            render_targets: RenderTargets::default(),
+            depth_prepass: if config.depth_prepass {
+                Some(DepthPrepass::new(&reload_watcher)?)
+            } else {
+                None
             },
-            environment: None,
+            occlusion_culling: if config.occlusion_culling {
+                Some(OcclusionCulling::new(&reload_watcher)?)
+            } else {
+                None
+            },
+            depth_mode: DepthMode::Standard,
+            camera_relative: false,
             view_uniform,
+            view_hook: None,
             camera_uniform: ThreadGuard::new(camera_uniform),
-            queued_materials: vec![],
+            meshes: Slots::default(),
+            materials: Slots::default(),
+            textures: Slots::default(),
             queued_meshes: HashMap::default(),
+            queued_outlines: vec![],
+            queued_hover_outlines: vec![],
+            queued_water: vec![],
+            queued_refractive: vec![],
+            queued_sprites: HashMap::default(),
+            queued_atlas_sprites: vec![],
+            queue,
+            queue_sender,
+            frame_cache,
+            frame_hasher: DefaultHasher::new(),
+            dirty_tracking: false,
+            last_frame_reused: false,
             render_span: ThreadGuard::new(None),
             begin_scene_at: None,
-            last_scene_duration: None,
-            last_render_duration: None,
-            last_render_submitted: 0,
-            last_render_rendered: 0,
+            frame_stats: RenderFrameStats::default(),
+            stats_history: FrameStatsHistory::default(),
+            gpu_profiler: Rc::new(GpuProfiler::new()),
             debug_window_open: false,
+            gbuffer_view: GBufferViewParams::default(),
+            gbuffer_viewer: None,
+            validate_color_spaces: false,
             reload_watcher,
-        })
+        };
+        renderer.set_color_space_validation(config.validate_color_spaces);
+        Ok(renderer)
     }
 
     pub fn post_process_interface(&mut self) -> &mut PostprocessInterface {
         &mut self.post_process_iface
     }
 
+    /// Read the HDR luminance of the frame at the position, e.g. under the cursor, from (0, 0) at
+    /// the top-left to (1, 1) at the bottom-right corner of the frame. Only read while the cursor
+    /// readout of the exposure debug parameters is enabled.
+    pub fn set_luminance_probe(&mut self, position: Option<Vec2>) {
+        self.luminance_probe = position;
+    }
+
+    /// Luminance, in nits, read under the luminance probe on a recent frame.
+    pub fn probed_luminance(&self) -> Option<f32> {
+        self.post_process.probed_luminance()
+    }
+
+    pub fn outline_params_mut(&mut self) -> &mut OutlineParams {
+        &mut self.outline_params
+    }
+
+    pub fn hover_params_mut(&mut self) -> &mut OutlineParams {
+        &mut self.hover_params
+    }
+
+    /// Settings for rendering the scene at a lower resolution when frames take longer than the
+    /// target frame time. Disabled by default.
+    pub fn resolution_scaling_mut(&mut self) -> &mut ResolutionScaling {
+        &mut self.resolution_scaling
+    }
+
+    /// When enabled, frames where the camera, submitted meshes and renderer parameters are the same
+    /// as in the previous frame present the last rendered image instead of rendering the scene
+    /// again. Changes the renderer cannot see, such as material uniforms being modified, need to be
+    /// signaled with [`Self::mark_dirty`].
+    pub fn set_dirty_tracking(&mut self, enabled: bool) {
+        self.dirty_tracking = enabled;
+        self.frame_cache.invalidate();
+    }
+
+    /// When enabled, meshes drawn with the standard material first have their depth laid down by
+    /// a depth-only pass, and are then drawn into the G-Buffer with an equal depth test so that
+    /// only visible fragments are shaded. Reduces overdraw in scenes with fragment-bound
+    /// materials, at the cost of transforming those meshes twice.
+    pub fn set_depth_prepass(&mut self, enabled: bool) -> Result<()> {
+        match (enabled, self.depth_prepass.is_some()) {
+            (true, false) => self.depth_prepass = Some(DepthPrepass::new(&self.reload_watcher)?),
+            (false, true) => self.depth_prepass = None,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    pub fn depth_prepass(&self) -> bool {
+        self.depth_prepass.is_some()
+    }
+
+    /// When enabled, meshes drawn with the standard material which were hidden behind other
+    /// geometry in previous frames are skipped. See [`OcclusionCulling`].
+    pub fn set_occlusion_culling(&mut self, enabled: bool) -> Result<()> {
+        match (enabled, self.occlusion_culling.is_some()) {
+            (true, false) => {
+                self.occlusion_culling = Some(OcclusionCulling::new(&self.reload_watcher)?)
+            }
+            (false, true) => self.occlusion_culling = None,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    pub fn occlusion_culling(&self) -> bool {
+        self.occlusion_culling.is_some()
+    }
+
+    /// Mapping of distances to the depth buffer for the camera projection. Reversed depth keeps
+    /// the precision of the float depth buffer even over large view distances, and falls back to
+    /// the standard mapping when the context lacks `glClipControl`.
+    pub fn set_depth_mode(&mut self, mode: DepthMode) {
+        let mode = if mode.is_reversed() && !camera::reversed_depth_supported() {
+            tracing::warn!("Reversed depth is not supported by the OpenGL context");
+            DepthMode::Standard
+        } else {
+            mode
+        };
+        if mode != self.depth_mode {
+            self.depth_mode = mode;
+            self.frame_cache.invalidate();
+        }
+    }
+
+    pub fn depth_mode(&self) -> DepthMode {
+        self.depth_mode
+    }
+
+    /// When enabled, meshes are transformed relative to a point close to the camera instead of the
+    /// world origin, so that scenes spanning huge distances, such as planets at their real scale,
+    /// render without vertices jittering as the camera moves.
+    pub fn set_camera_relative(&mut self, enabled: bool) {
+        if enabled != self.camera_relative {
+            self.camera_relative = enabled;
+            self.frame_cache.invalidate();
+        }
+    }
+
+    pub fn camera_relative(&self) -> bool {
+        self.camera_relative
+    }
+
+    /// When enabled, warn about textures of the standard material stored in a color space other
+    /// than the one of their slot (see [`MaterialInstance::color_space_mismatches`]) as they are
+    /// first drawn, and about the backbuffer encoding the already encoded output to sRGB again.
+    pub fn set_color_space_validation(&mut self, enabled: bool) {
+        self.validate_color_spaces = enabled;
+        self.material
+            .borrow_mut()
+            .set_color_space_validation(enabled);
+        if enabled && color::backbuffer_encodes_srgb() {
+            tracing::warn!("The backbuffer encodes to sRGB, colors will be encoded twice");
+        }
+    }
+
+    pub fn color_space_validation(&self) -> bool {
+        self.validate_color_spaces
+    }
+
+    /// Force the next frame to be fully rendered when dirty tracking is enabled.
+    pub fn mark_dirty(&mut self) {
+        self.frame_cache.invalidate();
+    }
+
+    /// Whether the last flushed frame reused the previously rendered image.
+    pub fn last_frame_reused(&self) -> bool {
+        self.last_frame_reused
+    }
+
     pub fn reload_watcher(&self) -> &ReloadWatcher {
         &self.reload_watcher
     }
@@ -247,42 +686,330 @@ impl Renderer {
     #[tracing::instrument]
     pub fn resize(&mut self, size: UVec2) -> Result<()> {
         Framebuffer::viewport(0, 0, size.x as _, size.y as _);
-        self.geom_pass.borrow_mut().resize(size)?;
-        self.post_process.resize(size)?;
+        self.size = size;
+        self.geom_pass
+            .borrow_mut()
+            .resize(self.resolution_scaling.render_size(size))?;
+        self.post_effects.resize(size)?;
+        self.outline.resize(size)?;
+        self.frame_cache.resize(size)?;
         Ok(())
     }
 
-    #[tracing::instrument(skip(new_lights))]
-    pub fn add_lights(&mut self, new_lights: impl IntoIterator<Item = Light>) -> Result<()> {
-        let lights = if !self.lights.is_empty() {
-            let existing_lights = GpuLight::download_buffer(&self.lights)?;
-            existing_lights.into_iter().map(|gl| gl.into()).collect()
-        } else {
-            new_lights.into_iter().collect::<Vec<_>>()
+    pub fn add_lights(
+        &mut self,
+        new_lights: impl IntoIterator<Item = Light>,
+    ) -> Result<Vec<LightHandle>> {
+        new_lights
+            .into_iter()
+            .map(|light| self.add_light(light))
+            .collect()
+    }
+
+    pub fn add_light(&mut self, light: Light) -> Result<LightHandle> {
+        self.frame_cache.invalidate();
+        self.lights.add(light)
+    }
+
+    pub fn light(&self, handle: LightHandle) -> Option<&Light> {
+        self.lights.get(handle)
+    }
+
+    /// Change a light in place; the handle stays valid.
+    pub fn update_light(&mut self, handle: LightHandle, light: Light) -> Result<()> {
+        self.frame_cache.invalidate();
+        self.lights.update(handle, light)
+    }
+
+    /// Remove a light, returning it if the handle was valid. Handles of other lights stay valid.
+    pub fn remove_light(&mut self, handle: LightHandle) -> Result<Option<Light>> {
+        self.frame_cache.invalidate();
+        self.light_cookies.remove(&handle);
+        self.light_culling.remove(handle);
+        self.lights.remove(handle)
+    }
+
+    pub fn clear_lights(&mut self) {
+        self.frame_cache.invalidate();
+        self.lights.clear();
+        self.light_cookies.clear();
+        self.light_culling.clear();
+    }
+
+    /// Set or remove the cookie of the light, see [`cookie`]. Cookies whose projection does not
+    /// suit the light are ignored.
+    pub fn set_light_cookie(&mut self, handle: LightHandle, cookie: Option<LightCookie>) {
+        self.frame_cache.invalidate();
+        match cookie {
+            Some(cookie) => self.light_cookies.insert(handle, cookie),
+            None => self.light_cookies.remove(&handle),
         };
-        self.lights = GpuLight::create_buffer(lights)?;
-        Ok(())
     }
 
+    pub fn light_cookie(&self, handle: LightHandle) -> Option<&LightCookie> {
+        self.light_cookies.get(&handle)
+    }
+
+    /// Set the distance from the camera past which the light stops being shaded, or remove it to
+    /// only cull the light when it gets too small on screen, if enabled. See [`light_culling`].
+    pub fn set_light_max_distance(&mut self, handle: LightHandle, distance: Option<f32>) {
+        self.frame_cache.invalidate();
+        self.light_culling.set_max_distance(handle, distance);
+    }
+
+    pub fn light_culling_params(&self) -> LightCullingParams {
+        self.light_culling.params
+    }
+
+    pub fn set_light_culling_params(&mut self, params: LightCullingParams) {
+        if params != self.light_culling.params {
+            self.frame_cache.invalidate();
+            self.light_culling.params = params;
+        }
+    }
+
+    /// Replace the current environment right away, keeping the other registered environments.
     pub fn set_environment<E: Environment>(&mut self, env: impl FnOnce(&ReloadWatcher) -> E) {
-        self.environment
-            .replace(Box::new(env(&self.reload_watcher)));
+        self.frame_cache.invalidate();
+        let env = Box::new(env(&self.reload_watcher));
+        let name = self
+            .environments
+            .current_name()
+            .unwrap_or(DEFAULT_ENVIRONMENT)
+            .to_string();
+        self.environments.insert(name.as_str(), env);
+        self.environments.switch_to(&name, Duration::ZERO).unwrap();
     }
 
+    /// Current environment, if it is of type `E`.
     pub fn environment<E: Environment>(&self) -> Option<&E> {
-        self.environment
-            .as_deref()
-            .and_then(|b| b.as_any().downcast_ref())
+        self.environments
+            .current()
+            .and_then(|env| env.as_any().downcast_ref())
     }
 
     pub fn environment_mut<E: Environment>(&mut self) -> Option<&mut E> {
-        self.environment
-            .as_deref_mut()
-            .and_then(|b| b.as_any_mut().downcast_mut())
+        self.frame_cache.invalidate();
+        self.environments
+            .current_mut()
+            .and_then(|env| env.as_any_mut().downcast_mut())
+    }
+
+    /// Register an environment under `name`, replacing the one previously registered with it.
+    /// Switch to it with [`Self::switch_environment`]; the first environment registered is shown
+    /// right away.
+    pub fn add_environment<E: Environment>(
+        &mut self,
+        name: impl Into<String>,
+        env: impl FnOnce(&ReloadWatcher) -> E,
+    ) {
+        self.frame_cache.invalidate();
+        let env = Box::new(env(&self.reload_watcher));
+        self.environments.insert(name, env);
+    }
+
+    /// Cross-fade to the environment registered under `name` over `duration`.
+    pub fn switch_environment(&mut self, name: &str, duration: Duration) -> Result<()> {
+        self.frame_cache.invalidate();
+        self.environments.switch_to(name, duration)
+    }
+
+    pub fn environments(&self) -> &Environments {
+        &self.environments
+    }
+
+    pub fn environments_mut(&mut self) -> &mut Environments {
+        self.frame_cache.invalidate();
+        &mut self.environments
+    }
+
+    /// Enable height fog with the given parameters, or disable it with `None`.
+    pub fn set_fog(&mut self, params: Option<FogParams>) {
+        self.fog_params = params;
     }
 
-    pub fn set_light_buffer(&mut self, light_buffer: LightBuffer) {
-        self.lights = light_buffer;
+    pub fn fog(&self) -> Option<FogParams> {
+        self.fog_params
+    }
+
+    /// Change the view of every frame once it is computed from the camera, or stop with `None`.
+    pub fn set_view_hook(&mut self, hook: Option<ViewHook>) {
+        self.view_hook = hook;
+    }
+
+    /// View of the frame being rendered, after the [`ViewHook`], e.g. to compensate for the jitter
+    /// of the projection with [`ViewUniform::jitter_uv_offset`].
+    pub fn view(&self) -> &ViewUniform {
+        &self.view_uniform
+    }
+
+    /// Clip the meshes of the standard material, and of shader materials using the standard vertex
+    /// shader, against the plane equations, given in world space (see [`clip::plane_equation`]),
+    /// e.g. for section views. Only the first [`clip::MAX_CLIP_PLANES`] planes are used.
+    pub fn set_clip_planes(&mut self, planes: &[Vec4]) {
+        self.clip_planes.clear();
+        self.clip_planes
+            .extend_from_slice(&planes[..planes.len().min(clip::MAX_CLIP_PLANES)]);
+    }
+
+    pub fn clip_planes(&self) -> &[Vec4] {
+        &self.clip_planes
+    }
+
+    /// Set the normal map tiled over the water surfaces, or use procedural waves with `None`.
+    pub fn set_water_normal_map(&mut self, normal_map: Option<Texture<[f32; 3]>>) {
+        self.water.set_normal_map(normal_map);
+    }
+
+    /// Upload a baked probe grid, which will then be used as static indirect lighting in the
+    /// lighting pass. Calling this again with a rebaked grid replaces the previous data.
+    pub fn set_irradiance_probes(&mut self, grid: &IrradianceProbeGrid) -> Result<()> {
+        self.frame_cache.invalidate();
+        if let Some(probes) = &mut self.irradiance_probes {
+            probes.update(grid)
+        } else {
+            self.irradiance_probes
+                .replace(IrradianceProbes::new(grid, &self.reload_watcher)?);
+            Ok(())
+        }
+    }
+
+    /// Capture the scene around every probe of the grid with [`Self::render_cubemap`], store their
+    /// irradiance and use the grid for the indirect lighting. To be called between the submission
+    /// of the meshes and the flush of the frame. The probes in use light the captures, so baking
+    /// again adds a bounce of indirect light.
+    #[tracing::instrument(skip_all, fields(probes = grid.len()))]
+    pub fn bake_irradiance_probes(
+        &mut self,
+        grid: &mut IrradianceProbeGrid,
+        resolution: u32,
+    ) -> Result<()> {
+        let size = grid.resolution();
+        for z in 0..size.z {
+            for y in 0..size.y {
+                for x in 0..size.x {
+                    let ix = UVec3::new(x, y, z);
+                    let cubemap = self.render_cubemap(grid.probe_position(ix), resolution)?;
+                    grid.set_from_cubemap(ix, &cubemap);
+                }
+            }
+        }
+        self.set_irradiance_probes(grid)
+    }
+
+    pub fn clear_irradiance_probes(&mut self) {
+        self.frame_cache.invalidate();
+        self.irradiance_probes.take();
+    }
+
+    /// Add a reflection probe, which is captured from the meshes submitted on the next frame.
+    pub fn add_reflection_probe(&mut self, probe: ReflectionProbe) -> Result<ReflectionProbeId> {
+        self.frame_cache.invalidate();
+        if let Some(probes) = &mut self.reflection_probes {
+            probes.add(probe)
+        } else {
+            self.reflection_probes
+                .insert(ReflectionProbes::new(&self.reload_watcher)?)
+                .add(probe)
+        }
+    }
+
+    pub fn reflection_probe(&self, id: ReflectionProbeId) -> Option<&ReflectionProbe> {
+        self.reflection_probes.as_ref()?.get(id)
+    }
+
+    /// Change the parameters of a reflection probe, without capturing it again.
+    pub fn update_reflection_probe(
+        &mut self,
+        id: ReflectionProbeId,
+        probe: ReflectionProbe,
+    ) -> Result<()> {
+        if self.reflection_probes_mut()?.update(id, probe)? {
+            self.frame_cache.invalidate();
+        }
+        Ok(())
+    }
+
+    /// Capture the reflection probe again on the next frame.
+    pub fn bake_reflection_probe(&mut self, id: ReflectionProbeId) -> Result<()> {
+        self.reflection_probes_mut()?.bake(id)
+    }
+
+    pub fn remove_reflection_probe(&mut self, id: ReflectionProbeId) {
+        self.frame_cache.invalidate();
+        if let Some(probes) = &mut self.reflection_probes {
+            probes.remove(id);
+            if probes.is_empty() {
+                self.reflection_probes.take();
+            }
+        }
+    }
+
+    fn reflection_probes_mut(&mut self) -> Result<&mut ReflectionProbes> {
+        self.reflection_probes
+            .as_mut()
+            .ok_or_else(|| eyre::eyre!("No reflection probes were added"))
+    }
+
+    /// Append a custom post effect to the end of the chain. Effects run in the order they are added,
+    /// on the HDR frame before the built-in postprocessing.
+    pub fn add_post_effect<E: PostEffect>(
+        &mut self,
+        effect: impl FnOnce(&ReloadWatcher) -> Result<E>,
+    ) -> Result<()> {
+        let effect = effect(&self.reload_watcher)?;
+        self.frame_cache.invalidate();
+        self.post_effects.push(Box::new(effect))
+    }
+
+    pub fn insert_post_effect<E: PostEffect>(
+        &mut self,
+        index: usize,
+        effect: impl FnOnce(&ReloadWatcher) -> Result<E>,
+    ) -> Result<()> {
+        let effect = effect(&self.reload_watcher)?;
+        self.frame_cache.invalidate();
+        self.post_effects.insert(index, Box::new(effect))
+    }
+
+    pub fn post_effect<E: PostEffect>(&self) -> Option<&E> {
+        self.post_effects.get()
+    }
+
+    pub fn post_effect_mut<E: PostEffect>(&mut self) -> Option<&mut E> {
+        self.frame_cache.invalidate();
+        self.post_effects.get_mut()
+    }
+
+    pub fn post_effects_mut(&mut self) -> &mut PostEffectChain {
+        self.frame_cache.invalidate();
+        &mut self.post_effects
+    }
+
+    /// Add a pass to the frame graph, run after lighting and before the forward passes. See
+    /// [`frame_graph`] for the resources passes can use.
+    pub fn add_graph_pass<P: GraphPass>(
+        &mut self,
+        pass: impl FnOnce(&ReloadWatcher) -> Result<P>,
+    ) -> Result<()> {
+        let pass = pass(&self.reload_watcher)?;
+        self.frame_cache.invalidate();
+        self.frame_graph.push(Box::new(pass));
+        Ok(())
+    }
+
+    pub fn graph_pass<P: GraphPass>(&self) -> Option<&P> {
+        self.frame_graph.get()
+    }
+
+    pub fn graph_pass_mut<P: GraphPass>(&mut self) -> Option<&mut P> {
+        self.frame_cache.invalidate();
+        self.frame_graph.get_mut()
+    }
+
+    pub fn frame_graph_mut(&mut self) -> &mut FrameGraph {
+        self.frame_cache.invalidate();
+        &mut self.frame_graph
     }
 
     pub fn begin_render(&mut self, camera: &Camera) -> Result<()> {
@@ -292,70 +1019,446 @@ impl Renderer {
         tracing::trace!(message = "Begin render", ?now);
         self.begin_scene_at.replace(now);
 
-        self.last_render_rendered = 0;
-        self.last_render_submitted = 0;
+        self.frame_stats = RenderFrameStats::default();
+        draw_counters::take();
+        self.sprite_atlas.next_frame()?;
 
         self.post_process.luminance_bias = self.post_process_iface.exposure;
+        self.post_process.auto_exposure_params = self.post_process_iface.auto_exposure;
         self.post_process.bloom_radius = self.post_process_iface.bloom.size;
+        self.post_process.bloom_threshold = self.post_process_iface.bloom.threshold;
         self.post_process
             .set_bloom_strength(self.post_process_iface.bloom.strength)?;
+        self.post_process
+            .set_bloom_quality(self.post_process_iface.bloom.quality);
         self.post_process
             .set_lens_flare_parameters(self.post_process_iface.lens_flare)?;
+        let exposure_debug = self.post_process_iface.debug;
+        self.post_process.set_exposure_view(exposure_debug.view)?;
+        let luminance_probe = self
+            .luminance_probe
+            .filter(|_| exposure_debug.cursor_readout);
+        self.post_process.set_luminance_probe(luminance_probe);
+
+        let render_size = self.resolution_scaling.render_size(self.size);
+        if render_size != self.geom_pass.borrow().size() {
+            tracing::debug!(message = "Changing render resolution", %render_size);
+            self.geom_pass.borrow_mut().resize(render_size)?;
+        }
+
+        self.frame_hasher = DefaultHasher::new();
+        self.set_camera(camera)?;
+        let hasher = &mut self.frame_hasher;
+        let iface = self.post_process_iface;
+        let lens_flare = iface.lens_flare;
+        hash_floats(
+            hasher,
+            &[
+                iface.exposure,
+                iface.bloom.size,
+                iface.bloom.strength,
+                iface.bloom.threshold,
+                lens_flare.strength,
+                lens_flare.distortion,
+                lens_flare.threshold,
+                lens_flare.ghost_spacing,
+            ],
+        );
+        iface.bloom.quality.hash(hasher);
+        lens_flare.enabled.hash(hasher);
+        lens_flare.ghost_count.hash(hasher);
+        // The probe is read while post processing, which reused frames skip
+        iface.debug.hash(hasher);
+        if let Some(probe) = luminance_probe {
+            hash_floats(hasher, &probe.to_array());
+        }
+        let auto_exposure = iface.auto_exposure;
+        auto_exposure.metering.hash(hasher);
+        hash_floats(
+            hasher,
+            &[
+                auto_exposure.low_percentile,
+                auto_exposure.high_percentile,
+                auto_exposure.speed_up,
+                auto_exposure.speed_down,
+            ],
+        );
+        for params in [self.outline_params, self.hover_params] {
+            hash_floats(hasher, &params.color.to_array());
+            hash_floats(hasher, &[params.width, params.fill]);
+        }
+        Ok(())
+    }
 
-        self.view_uniform.update_from_camera(camera);
+    fn set_camera(&mut self, camera: &Camera) -> Result<()> {
+        let mut camera = camera.clone();
+        camera.projection.depth = self.depth_mode;
+        self.view_uniform.update_from_camera(&camera);
+        let render_size = self.geom_pass.borrow().size().as_vec2();
+        self.view_uniform.viewport = vec4(0., 0., render_size.x, render_size.y);
+        if let Some(hook) = &mut self.view_hook {
+            (hook.0)(&mut self.view_uniform);
+        }
+        let origin = if self.camera_relative {
+            let eye = self.view_uniform.inv_view.w_axis.xyz();
+            (eye / ORIGIN_GRID_SIZE).round() * ORIGIN_GRID_SIZE
+        } else {
+            Vec3::ZERO
+        };
+        self.view_uniform.set_origin(origin);
+        self.lights.set_origin(origin)?;
         self.view_uniform
             .update_uniform_buffer(&mut self.camera_uniform)?;
+        let hasher = &mut self.frame_hasher;
+        hash_floats(hasher, &self.view_uniform.mat_view.to_cols_array());
+        hash_floats(hasher, &self.view_uniform.mat_proj.to_cols_array());
+        hash_floats(hasher, &self.view_uniform.viewport.to_array());
         Ok(())
     }
 
-    pub fn submit_mesh_standard(
+    /// Queue to send commands to the renderer from other threads. They are applied when the frame
+    /// is flushed.
+    pub fn queue(&self) -> RenderQueue {
+        RenderQueue::new(self.queue_sender.clone(), self.lights.handle_allocator())
+    }
+
+    /// Apply the commands sent through [`Self::queue`] since the last frame.
+    fn drain_queue(&mut self) -> Result<()> {
+        while let Ok(command) = self.queue.try_recv() {
+            match command {
+                RenderCommand::Submit { material, mesh } => self.submit(material, mesh),
+                RenderCommand::SubmitOutline(mesh) => self.submit_outline(mesh),
+                RenderCommand::SetCamera(camera) => self.set_camera(&camera)?,
+                RenderCommand::AddLight(handle, light) => {
+                    self.frame_cache.invalidate();
+                    self.lights.insert(handle, light)?;
+                }
+                // The light may have been removed since the update was queued
+                RenderCommand::UpdateLight(handle, _) if self.lights.get(handle).is_none() => {
+                    tracing::warn!("Skipping queued update of removed light {:?}", handle);
+                }
+                RenderCommand::UpdateLight(handle, light) => self.update_light(handle, light)?,
+                RenderCommand::RemoveLight(handle) => {
+                    self.remove_light(handle)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Take ownership of the mesh, to submit it with the returned handle until it is unregistered.
+    pub fn register_mesh(&mut self, mesh: impl Into<Rc<Mesh>>) -> MeshHandle {
+        MeshHandle(self.meshes.insert(mesh.into()))
+    }
+
+    pub fn mesh(&self, handle: MeshHandle) -> Option<&Mesh> {
+        self.meshes.get(handle.0).map(|mesh| mesh.as_ref())
+    }
+
+    /// Set the weights of the morph targets of the mesh, see [`MorphTargets::set_weights`]. Call
+    /// it between [`Self::begin_render`] and the flush, so that changed weights are rendered.
+    pub fn set_morph_weights(&mut self, handle: MeshHandle, weights: &[f32]) {
+        let mesh = self.meshes.get(handle.0);
+        let Some(morph) = mesh.and_then(|mesh| mesh.morph_targets.as_ref()) else { return; };
+        morph.set_weights(weights);
+        hash_floats(&mut self.frame_hasher, &morph.weights());
+    }
+
+    /// Remove the mesh; its handle, and any copy of it, becomes invalid.
+    pub fn unregister_mesh(&mut self, handle: MeshHandle) -> Option<Rc<Mesh>> {
+        self.frame_cache.invalidate();
+        self.meshes.remove(handle.0)
+    }
+
+    pub fn register_material<M: DrawMaterial>(
         &mut self,
-        material: Rc<MaterialInstance>,
-        mesh: Transformed<Rc<Mesh>>,
-    ) {
-        self.submit_mesh(
-            Rc::new(StandardDrawMaterial {
-                material: self.material.clone(),
-                instance: material,
-            }),
-            mesh,
-        );
+        material: impl Into<Rc<M>>,
+    ) -> MaterialHandle {
+        let material: Rc<M> = material.into();
+        MaterialHandle(self.materials.insert(material))
     }
 
+    /// Register an instance of the standard material. Its uniforms can still be updated through
+    /// the instance, or [`Self::material_instance`].
+    pub fn register_material_standard(
+        &mut self,
+        instance: impl Into<Rc<MaterialInstance>>,
+    ) -> MaterialHandle {
+        self.register_material::<StandardDrawMaterial>(StandardDrawMaterial {
+            material: self.material.clone(),
+            instance: instance.into(),
+        })
+    }
+
+    pub fn material(&self, handle: MaterialHandle) -> Option<&dyn DrawMaterial> {
+        self.materials
+            .get(handle.0)
+            .map(|material| material.as_ref())
+    }
+
+    /// Instance of the standard material registered with this handle, if it is one.
+    pub fn material_instance(&self, handle: MaterialHandle) -> Option<&MaterialInstance> {
+        let material = self.material(handle)?.as_any();
+        let material = material.downcast_ref::<StandardDrawMaterial>()?;
+        Some(&material.instance)
+    }
+
+    /// Instance of the standard material registered with this handle, to replace its textures
+    /// without registering a new material. `None` when it is not one, or when the material or its
+    /// instance are shared outside of the renderer.
+    pub fn material_instance_mut(
+        &mut self,
+        handle: MaterialHandle,
+    ) -> Option<&mut MaterialInstance> {
+        self.frame_cache.invalidate();
+        let material = Rc::get_mut(self.materials.get_mut(handle.0)?)?.as_any_mut();
+        let material = material.downcast_mut::<StandardDrawMaterial>()?;
+        Rc::get_mut(&mut material.instance)
+    }
+
+    /// Remove the material; its handle, and any copy of it, becomes invalid.
+    pub fn unregister_material(&mut self, handle: MaterialHandle) -> Option<Rc<dyn DrawMaterial>> {
+        self.frame_cache.invalidate();
+        self.materials.remove(handle.0)
+    }
+
+    /// Register a texture to draw sprites with, see [`Self::submit_sprite`].
+    pub fn register_texture(&mut self, texture: Texture<[f32; 4]>) -> TextureHandle {
+        TextureHandle(self.textures.insert(texture))
+    }
+
+    /// Remove the texture; its handle, and any copy of it, becomes invalid.
+    pub fn unregister_texture(&mut self, handle: TextureHandle) -> Option<Texture<[f32; 4]>> {
+        self.frame_cache.invalidate();
+        self.textures.remove(handle.0)
+    }
+
+    /// Draw the mesh with the material this frame. Invalid handles are ignored.
     #[tracing::instrument(skip_all)]
-    pub fn submit_mesh<M: DrawMaterial>(&mut self, material: Rc<M>, mesh: Transformed<Rc<Mesh>>) {
-        let mesh_ptr = Rc::as_ptr(&mesh) as usize;
-        let material_ptr = Rc::as_ptr(&material) as usize;
-        self.last_render_submitted += 1;
-        tracing::debug!(message="Submitting mesh", %mesh_ptr, %material_ptr, mat_name=std::any::type_name::<M>());
-        let mat_ix = if let Some(ix) = self.queued_materials.iter().position(|mat| {
-            if let Some(std_draw_mat) = mat.as_any().downcast_ref::<M>() {
-                std_draw_mat.eq_key() == material.eq_key()
-            } else {
-                false
-            }
-        }) {
-            ix
-        } else {
-            let ix = self.queued_materials.len();
-            self.queued_materials.push(material);
-            ix
-        };
+    pub fn submit(&mut self, material: MaterialHandle, mesh: Transformed<MeshHandle>) {
+        if self.materials.get(material.0).is_none() || self.meshes.get(mesh.value.0).is_none() {
+            tracing::warn!(message = "Submitting unregistered mesh or material", ?material, mesh = ?mesh.value);
+            return;
+        }
+        self.frame_stats.submitted += 1;
+        tracing::debug!(message = "Submitting mesh", ?material, mesh = ?mesh.value);
+        let hasher = &mut self.frame_hasher;
+        mesh.value.hash(hasher);
+        material.hash(hasher);
+        hash_floats(hasher, &mesh.transform.matrix().to_cols_array());
+        self.queued_meshes.entry(material).or_default().push(mesh);
+    }
+
+    /// Draw an outline around this mesh on top of the final image, e.g. to show a selection. Meshes
+    /// submitted in the same frame share a single outline.
+    pub fn submit_outline(&mut self, mesh: Transformed<MeshHandle>) {
+        if self.meshes.get(mesh.value.0).is_none() {
+            return;
+        }
+        let hasher = &mut self.frame_hasher;
+        mesh.value.hash(hasher);
+        hash_floats(hasher, &mesh.transform.matrix().to_cols_array());
+        self.queued_outlines.push(mesh);
+    }
 
+    /// Highlight this mesh on top of the final image, e.g. to show the mesh under the cursor. Drawn
+    /// under the outlines of [`Self::submit_outline`].
+    pub fn submit_hover_outline(&mut self, mesh: Transformed<MeshHandle>) {
+        if self.meshes.get(mesh.value.0).is_none() {
+            return;
+        }
+        let hasher = &mut self.frame_hasher;
+        mesh.value.hash(hasher);
+        hash_floats(hasher, &mesh.transform.matrix().to_cols_array());
+        self.queued_hover_outlines.push(mesh);
+    }
+
+    /// Draw a sprite with the texture this frame, see [`sprites`]. Invalid handles are ignored.
+    pub fn submit_sprite(&mut self, texture: TextureHandle, sprite: Sprite) {
+        if self.textures.get(texture.0).is_none() {
+            return;
+        }
+        texture.hash(&mut self.frame_hasher);
+        hash_sprite(&mut self.frame_hasher, &sprite);
+        self.queued_sprites.entry(texture).or_default().push(sprite);
+    }
+
+    /// Atlas of small sprite images, drawn together with [`Self::submit_atlas_sprite`].
+    pub fn sprite_atlas(&self) -> &TextureAtlas {
+        &self.sprite_atlas
+    }
+
+    pub fn sprite_atlas_mut(&mut self) -> &mut TextureAtlas {
+        &mut self.sprite_atlas
+    }
+
+    /// Draw a sprite with the image of the sprite atlas this frame, replacing the texture
+    /// coordinates of the sprite. Images evicted from the atlas are ignored.
+    pub fn submit_atlas_sprite(&mut self, image: AtlasId, mut sprite: Sprite) {
+        let Some(uv_rect) = self.sprite_atlas.use_image(image) else { return; };
+        sprite.uv_rect = uv_rect;
+        image.hash(&mut self.frame_hasher);
+        hash_sprite(&mut self.frame_hasher, &sprite);
+        self.queued_atlas_sprites.push(sprite);
+    }
+
+    /// Draw a line through the points this frame, see [`polyline`].
+    pub fn submit_polyline(&mut self, points: &[PolylinePoint]) {
+        let hasher = &mut self.frame_hasher;
+        for point in points {
+            hash_floats(hasher, &point.position.to_array());
+            hash_floats(hasher, &point.color.to_array());
+            hash_floats(hasher, &[point.width]);
+        }
+        self.polylines.push(points);
+    }
+
+    /// Draw the mesh as a water surface this frame, see [`water`]. Frames with water are never
+    /// reused, as the waves are animated.
+    pub fn submit_water(&mut self, mesh: Transformed<MeshHandle>, params: WaterParams) {
+        if self.meshes.get(mesh.value.0).is_none() {
+            return;
+        }
+        self.queued_water.push((mesh, params));
+    }
+
+    /// Draw the mesh as a refractive surface this frame instead of in the G-Buffer, see
+    /// [`refraction`]. Invalid handles are ignored.
+    pub fn submit_refractive(&mut self, mesh: Transformed<MeshHandle>, params: RefractionParams) {
+        if self.meshes.get(mesh.value.0).is_none() {
+            return;
+        }
+        let hasher = &mut self.frame_hasher;
+        mesh.value.hash(hasher);
+        hash_floats(hasher, &mesh.transform.matrix().to_cols_array());
+        hash_floats(hasher, &params.tint.to_array());
+        hash_floats(hasher, &[params.ior, params.roughness, params.thickness]);
+        self.queued_refractive.push((mesh, params));
+    }
+
+    /// Resolve the handles of the meshes queued for the frame, grouped by material, with their
+    /// transforms relative to the origin of the view.
+    fn take_queued_meshes(&mut self) -> Vec<(Rc<dyn DrawMaterial>, Vec<Transformed<Rc<Mesh>>>)> {
+        let meshes = &self.meshes;
+        let origin = self.view_uniform.origin;
         self.queued_meshes
-            .entry(mat_ix)
-            .and_modify(|v| v.push(mesh.clone()))
-            .or_insert_with(|| vec![mesh]);
+            .drain()
+            .filter_map(|(material, queued)| {
+                let material = self.materials.get(material.0)?.clone();
+                let queued = queued
+                    .into_iter()
+                    .filter_map(|mesh| {
+                        Some(Transformed {
+                            value: meshes.get(mesh.value.0)?.clone(),
+                            transform: mesh.transform.relative_to(origin),
+                        })
+                    })
+                    .collect();
+                Some((material, queued))
+            })
+            .collect()
     }
 
-    #[tracing::instrument(skip(self))]
-    pub fn flush(&mut self, dt: Duration, clear_color: Vec3) -> Result<()> {
+    /// Render the meshes submitted since [`Self::begin_render`], returning the statistics of the
+    /// frame.
+    pub fn flush(&mut self, dt: Duration, clear_color: Vec3) -> Result<RenderFrameStats> {
+        self.flush_to(&Framebuffer::backbuffer(), dt, clear_color)
+    }
+
+    /// Render the meshes submitted since [`Self::begin_render`] into `frame`, which must be the
+    /// size of the renderer, instead of the window. Commands sent through [`Self::queue`] are
+    /// applied first.
+    #[tracing::instrument(skip(self, frame))]
+    pub fn flush_to(
+        &mut self,
+        frame: &Framebuffer,
+        dt: Duration,
+        clear_color: Vec3,
+    ) -> Result<RenderFrameStats> {
         let render_start = Instant::now();
+        self.drain_queue()?;
+        let profiler = self.gpu_profiler.clone();
+        let _frame_zone = profiler.zone("Frame");
+        let zone = profiler.zone("Reflection probes baking");
+        if self.bake_reflection_probes()? {
+            self.frame_cache.invalidate();
+        }
+        drop(zone);
+        if self.environments.reload_if_needed(&self.reload_watcher) {
+            self.frame_cache.invalidate();
+        }
+        if self.environments.advance(dt) {
+            self.frame_cache.invalidate();
+        }
+        // Reused frames skip metering, but the exposure keeps adapting to the last measure
+        if self.post_process.advance_exposure(dt) {
+            self.frame_cache.invalidate();
+        }
+        self.water.advance(dt.as_secs_f32());
+        if !self.queued_water.is_empty() {
+            self.frame_cache.invalidate();
+        }
+        self.refraction.reload_if_needed();
+        if let Some(occlusion) = &mut self.occlusion_culling {
+            // Meshes uncovered after the camera stopped need to show up
+            if occlusion.fetch_results() {
+                self.frame_cache.invalidate();
+            }
+        }
+        hash_floats(&mut self.frame_hasher, &clear_color.to_array());
+        if let Some(fog) = self.fog_params {
+            let hasher = &mut self.frame_hasher;
+            let (density, height, falloff) = (fog.density, fog.height, fog.height_falloff);
+            hash_floats(hasher, &[density, height, falloff, fog.max_distance]);
+            hash_floats(hasher, &fog.color.to_array());
+            fog.color_from_environment.hash(hasher);
+        }
+        for plane in &self.clip_planes {
+            hash_floats(&mut self.frame_hasher, &plane.to_array());
+        }
+        let frame_key = self.frame_hasher.finish();
+        self.last_frame_reused = self.dirty_tracking && self.frame_cache.is_valid(frame_key);
+        if self.last_frame_reused {
+            tracing::trace!(message = "Reusing last frame", %frame_key);
+            let _zone = profiler.zone("Present cached frame");
+            self.queued_meshes.clear();
+            self.queued_water.clear();
+            self.queued_refractive.clear();
+            self.queued_sprites.clear();
+            self.queued_atlas_sprites.clear();
+            self.polylines.clear();
+            // Targets keep the renders of the reused frame
+            self.render_targets.take_requests();
+            Framebuffer::viewport(0, 0, self.size.x as _, self.size.y as _);
+            Framebuffer::disable_depth_test();
+            Framebuffer::disable_blending();
+            self.frame_cache.present(frame)?;
+            self.draw_outlines(frame)?;
+            self.frame_stats.reused = true;
+            return Ok(self.end_frame(render_start));
+        }
+
+        let mut queued = self.take_queued_meshes();
+        let clip_planes = clip::relative_planes(&self.clip_planes, self.view_uniform.origin);
+        self.material.borrow_mut().set_clip_planes(&clip_planes);
+        if let Some(depth_prepass) = &mut self.depth_prepass {
+            depth_prepass.set_clip_planes(&clip_planes);
+        }
+        camera::set_depth_convention(self.view_uniform.reversed_depth());
+        let zone = profiler.zone("Water reflections");
+        let water_reflected = self.render_water_reflections(&queued)?;
+        drop(zone);
+        let zone = profiler.zone("Render targets");
+        for (name, view) in self.render_targets.take_requests() {
+            self.render_to_target(&name, view, &queued)?;
+        }
+        drop(zone);
+
         violette::set_front_face(FrontFace::CounterClockwise);
         violette::culling(Some(Cull::Back));
         let [w, h] = self.view_uniform.viewport.zw().as_ivec2().to_array();
         Framebuffer::viewport(0, 0, w, h);
-        Framebuffer::enable_depth_test(DepthTestFunction::Less);
+        Framebuffer::enable_depth_test(self.view_uniform.depth_test());
         Framebuffer::disable_scissor();
         Framebuffer::disable_blending();
         Framebuffer::clear_color([0., 0., 0., 0.]);
@@ -369,31 +1472,528 @@ impl Renderer {
         self.material
             .borrow_mut()
             .set_camera_uniform(&self.camera_uniform)?;
-        for (mat_ix, meshes) in self.queued_meshes.drain() {
-            let mat = self.queued_materials[mat_ix].clone();
+        // Custom materials may discard fragments or displace vertices, only the standard
+        // material is drawn in the depth pre-pass
+        let is_prepassed = |mat: &dyn DrawMaterial| mat.as_any().is::<StandardDrawMaterial>();
+        let standard_double_sided = |mat: &dyn DrawMaterial| {
+            let mat = mat.as_any().downcast_ref::<StandardDrawMaterial>()?;
+            Some(mat.instance.uniforms().double_sided)
+        };
+        // Meshes of the standard material, tested for occlusion once the G-Buffer is filled
+        let mut occlusion_groups = vec![];
+        if let Some(occlusion) = &mut self.occlusion_culling {
+            for (mat, meshes) in &mut queued {
+                let Some(double_sided) = standard_double_sided(&**mat) else { continue; };
+                occlusion_groups.push((double_sided, meshes.clone()));
+                meshes.retain(|mesh| occlusion.is_visible(mesh));
+            }
+        }
+        if let Some(depth_prepass) = &mut self.depth_prepass {
+            let _zone = profiler.zone("Depth pre-pass");
+            let groups = queued.iter().filter_map(|(mat, meshes)| {
+                Some((standard_double_sided(&**mat)?, meshes.as_slice()))
+            });
+            clip::enable_clip_distances(self.clip_planes.len());
+            depth_prepass.draw(
+                geom_pass.framebuffer(),
+                &self.camera_uniform,
+                self.view_uniform.depth_test(),
+                groups,
+            )?;
+        }
+        let zone = profiler.zone("G-Buffer");
+        for (mat, meshes) in &queued {
+            let depth_test = if self.depth_prepass.is_some() && is_prepassed(&**mat) {
+                DepthTestFunction::Equal
+            } else {
+                self.view_uniform.depth_test()
+            };
+            Framebuffer::enable_depth_test(depth_test);
+            clip::enable_clip_distances(mat.set_clip_planes(&clip_planes));
 
-            self.last_render_rendered += meshes.len();
-            let mut meshes = meshes
-                .into_iter()
-                .map(|m| m.map(|m| unsafe { &*Rc::as_ptr(&m) }));
+            self.frame_stats.instances += meshes.len();
+            let mut meshes = meshes.iter().map(|m| Transformed {
+                value: m.value.as_ref(),
+                transform: m.transform,
+            });
             mat.draw(geom_pass.framebuffer(), &self.camera_uniform, &mut meshes)?;
         }
+        clip::enable_clip_distances(0);
+        drop(zone);
+        if let Some(occlusion) = &mut self.occlusion_culling {
+            let _zone = profiler.zone("Occlusion queries");
+            let groups = occlusion_groups
+                .iter()
+                .map(|(double_sided, meshes)| (*double_sided, meshes.as_slice()));
+            let reversed_depth = self.view_uniform.reversed_depth();
+            occlusion.query(
+                geom_pass.framebuffer(),
+                &self.camera_uniform,
+                reversed_depth,
+                groups,
+            )?;
+        }
 
         Framebuffer::disable_depth_test();
         Framebuffer::clear_color(clear_color.extend(1.).to_array());
-        let backbuffer = Framebuffer::backbuffer();
-        backbuffer.do_clear(ClearBuffer::COLOR);
+        frame.do_clear(ClearBuffer::COLOR);
+        let zone = profiler.zone("Lighting");
+        self.light_culling.update(&self.lights, &self.view_uniform);
         let shaded_tex = geom_pass.process(
             &self.camera_uniform,
             &self.lights,
-            self.environment.as_deref_mut(),
+            &self.light_cookies,
+            Some(&self.light_culling),
+            Some(&mut self.environments),
+            self.irradiance_probes.as_ref(),
+            self.reflection_probes.as_ref(),
         )?;
+        drop(zone);
+        if !self.frame_graph.is_empty() {
+            let _zone = profiler.zone("Frame graph");
+            self.frame_graph.execute(
+                geom_pass.size(),
+                &geom_pass.graph_resources(),
+                &self.camera_uniform,
+                &self.view_uniform,
+                &profiler,
+            )?;
+            Framebuffer::viewport(0, 0, w, h);
+            Framebuffer::disable_depth_test();
+            Framebuffer::disable_blending();
+        }
+        if !self.queued_water.is_empty() {
+            let _zone = profiler.zone("Water");
+            let queued_water = std::mem::take(&mut self.queued_water);
+            let meshes = &self.meshes;
+            let origin = self.view_uniform.origin;
+            let water = queued_water.iter().filter_map(|(mesh, params)| {
+                let mesh = Transformed {
+                    value: meshes.get(mesh.value.0)?.as_ref(),
+                    transform: mesh.transform.relative_to(origin),
+                };
+                Some((mesh, *params))
+            });
+            let reflection = self
+                .render_targets
+                .output(WATER_REFLECTION_TARGET)
+                .filter(|_| water_reflected);
+            self.water.draw(
+                geom_pass.output_framebuffer(),
+                &self.camera_uniform,
+                geom_pass.depth(),
+                reflection,
+                water,
+            )?;
+        }
+        if !self.queued_refractive.is_empty() {
+            let _zone = profiler.zone("Refraction");
+            let queued_refractive = std::mem::take(&mut self.queued_refractive);
+            let meshes = &self.meshes;
+            let origin = self.view_uniform.origin;
+            let refractive = queued_refractive.iter().filter_map(|(mesh, params)| {
+                let mesh = Transformed {
+                    value: meshes.get(mesh.value.0)?.as_ref(),
+                    transform: mesh.transform.relative_to(origin),
+                };
+                Some((mesh, *params))
+            });
+            self.refraction.draw(
+                geom_pass.output_framebuffer(),
+                shaded_tex,
+                &self.camera_uniform,
+                geom_pass.depth(),
+                refractive,
+            )?;
+        }
+        if !self.queued_sprites.is_empty() || !self.queued_atlas_sprites.is_empty() {
+            let _zone = profiler.zone("Sprites");
+            let textures = &self.textures;
+            let batches = self
+                .queued_sprites
+                .iter()
+                .filter_map(|(handle, sprites)| {
+                    Some((Some(*handle), textures.get(handle.0)?, sprites.as_slice()))
+                })
+                .chain(
+                    self.sprite_atlas
+                        .texture()
+                        .map(|texture| (None, texture, self.queued_atlas_sprites.as_slice())),
+                );
+            self.sprites.draw(
+                geom_pass.output_framebuffer(),
+                &self.camera_uniform,
+                &self.view_uniform,
+                geom_pass.depth(),
+                batches,
+            )?;
+            self.queued_sprites.clear();
+            self.queued_atlas_sprites.clear();
+        }
+        if !self.polylines.is_empty() {
+            let _zone = profiler.zone("Polylines");
+            self.polylines.draw(
+                geom_pass.output_framebuffer(),
+                &self.camera_uniform,
+                geom_pass.depth(),
+            )?;
+        }
+        if let Some(fog) = &self.fog_params {
+            let _zone = profiler.zone("Fog");
+            let env_color = self.environments.fog_color();
+            let color = env_color
+                .filter(|_| fog.color_from_environment)
+                .unwrap_or(fog.color);
+            self.fog.draw(
+                geom_pass.output_framebuffer(),
+                &self.camera_uniform,
+                geom_pass.depth(),
+                fog,
+                color,
+            )?;
+        }
         Framebuffer::disable_blending();
-        self.post_process.draw(&backbuffer, shaded_tex, dt)?;
-        self.last_render_duration.replace(render_start.elapsed());
-        self.last_scene_duration
-            .replace(self.begin_scene_at.take().unwrap().elapsed());
+        let shaded_tex = if geom_pass.size() != self.size {
+            let _zone = profiler.zone("Upscaling");
+            let upscaled = self
+                .upscaler
+                .process(&mut self.texture_pool, shaded_tex, self.size)?;
+            FrameTexture::Pooled(upscaled)
+        } else {
+            FrameTexture::Borrowed(shaded_tex)
+        };
+        let zone = profiler.zone("Post effects");
+        let shaded_tex = self
+            .post_effects
+            .process(&mut self.texture_pool, shaded_tex)?;
+        drop(zone);
+        let zone = profiler.zone("Post processing");
+        let target = if self.dirty_tracking {
+            self.frame_cache.framebuffer()
+        } else {
+            frame
+        };
+        self.post_process.draw(
+            target,
+            shaded_tex.texture(),
+            &self.camera_uniform,
+            &self.view_uniform,
+            &profiler,
+        )?;
+        if self.dirty_tracking {
+            self.frame_cache.present(frame)?;
+            self.frame_cache.store(frame_key);
+        }
+        shaded_tex.release(&mut self.texture_pool);
+        self.texture_pool.end_frame();
+        drop(zone);
+        drop(geom_pass);
+        let zone = profiler.zone("Outlines");
+        self.draw_outlines(frame)?;
+        drop(zone);
+        if self.resolution_scaling.record_frame(dt) {
+            tracing::debug!(
+                message = "Resolution scale changed",
+                scale = self.resolution_scaling.scale()
+            );
+        }
+        if let Some(occlusion) = &self.occlusion_culling {
+            self.frame_stats.culled = occlusion.culled();
+        }
+        self.frame_stats.lights_culled = self.light_culling.culled_count();
+        self.frame_stats.lights = self.lights.len() - self.frame_stats.lights_culled;
+        Ok(self.end_frame(render_start))
+    }
+
+    fn end_frame(&mut self, render_start: Instant) -> RenderFrameStats {
+        // Other drawing, such as the UI, expects the standard depth range
+        camera::set_depth_convention(false);
+        let stats = &mut self.frame_stats;
+        stats.render_time = render_start.elapsed();
+        stats.scene_time = self.begin_scene_at.take().unwrap().elapsed();
+        stats.set_draw_counters(draw_counters::take());
+        self.stats_history.push(*stats);
+        self.gpu_profiler.end_frame(self.size);
         self.render_span.take();
+        *stats
+    }
+
+    /// Statistics of the last rendered frame.
+    pub fn frame_stats(&self) -> RenderFrameStats {
+        self.stats_history.last().copied().unwrap_or_default()
+    }
+
+    pub fn frame_stats_history(&self) -> &FrameStatsHistory {
+        &self.stats_history
+    }
+
+    pub fn gpu_profiler(&self) -> &GpuProfiler {
+        &self.gpu_profiler
+    }
+
+    /// Capture the reflection probes waiting to be baked from the meshes queued this frame. Returns
+    /// whether any probe was captured.
+    #[tracing::instrument(skip_all)]
+    fn bake_reflection_probes(&mut self) -> Result<bool> {
+        let Some(mut reflections) = self.reflection_probes.take() else { return Ok(false); };
+        let pending = reflections.take_pending();
+        // Bake without the probes, which are not reflected in their own captures
+        let result = self.bake_probes(&reflections, &pending);
+        self.reflection_probes = Some(reflections);
+        result.map(|()| !pending.is_empty())
+    }
+
+    fn bake_probes(
+        &mut self,
+        reflections: &ReflectionProbes,
+        pending: &[(ReflectionProbeId, ReflectionProbe)],
+    ) -> Result<()> {
+        if pending.is_empty() {
+            return Ok(());
+        }
+        for (id, probe) in pending {
+            tracing::debug!(message = "Baking reflection probe", ?id, position = %probe.position);
+            self.render_cube_faces(
+                reflections.capture_buffers(),
+                |face| probe.face_view(face),
+                |face, captured| reflections.store_face(*id, face, captured),
+            )?;
+        }
+        reflections.finish_bake()
+    }
+
+    /// Lit scene and G-Buffer channels of the last rendered frame, e.g. to export them with
+    /// [`Aovs::write_exr`]. Rendered at the render resolution, before post-processing.
+    pub fn capture_aovs(&self) -> Result<Aovs> {
+        self.geom_pass.borrow().download_aovs(&self.view_uniform)
+    }
+
+    /// Render the meshes submitted this frame into the six faces of a cube around `center`, each
+    /// face being `resolution` pixels wide, and download the lit faces. To be called between the
+    /// submission of the meshes and the flush of the frame.
+    #[tracing::instrument(skip(self))]
+    pub fn render_cubemap(&mut self, center: Vec3, resolution: u32) -> Result<Cubemap> {
+        let size = UVec2::splat(resolution);
+        let capture = match self.cubemap_capture.take() {
+            Some(mut capture) => {
+                if capture.size() != size {
+                    capture.resize(size)?;
+                }
+                capture
+            }
+            None => GeometryBuffers::new(size, GBufferSettings::default(), &self.reload_watcher)?,
+        };
+        let mut faces: [Vec<[f32; 3]>; 6] = Default::default();
+        let result = self.render_cube_faces(
+            &capture,
+            |face| cubemap::face_view(center, face, CUBEMAP_NEAR, CUBEMAP_FAR, resolution),
+            |face, captured| {
+                faces[face] = captured.mipmap(0).unwrap().download()?;
+                Ok(())
+            },
+        );
+        self.cubemap_capture = Some(capture);
+        result?;
+        Ok(Cubemap { resolution, faces })
+    }
+
+    /// Render and light the queued meshes into `capture` for each cube face, as seen from the view
+    /// of the face, handing the lit face over to `store`.
+    fn render_cube_faces(
+        &mut self,
+        capture: &GeometryBuffers,
+        face_view: impl Fn(usize) -> ViewUniform,
+        mut store: impl FnMut(usize, &Texture<[f32; 3]>) -> Result<()>,
+    ) -> Result<()> {
+        violette::set_front_face(FrontFace::CounterClockwise);
+        violette::culling(Some(Cull::Back));
+        Framebuffer::disable_scissor();
+        self.material
+            .borrow_mut()
+            .set_camera_uniform(&self.camera_uniform)?;
+        let [w, h] = capture.size().as_ivec2().to_array();
+        // Lights are uploaded relative to the origin of the camera
+        let origin = self.view_uniform.origin;
+        for face in 0..6 {
+            let mut view = face_view(face);
+            view.set_origin(origin);
+            view.update_uniform_buffer(&mut self.camera_uniform)?;
+            camera::set_depth_convention(view.reversed_depth());
+            Framebuffer::viewport(0, 0, w, h);
+            Framebuffer::enable_depth_test(view.depth_test());
+            Framebuffer::disable_blending();
+            Framebuffer::clear_color([0., 0., 0., 0.]);
+            capture
+                .framebuffer()
+                .do_clear(ClearBuffer::COLOR | ClearBuffer::DEPTH);
+            for (material, meshes) in &self.queued_meshes {
+                let Some(material) = self.materials.get(material.0) else { continue; };
+                let mut meshes = meshes.iter().filter_map(|m| {
+                    Some(Transformed {
+                        value: self.meshes.get(m.value.0)?.as_ref(),
+                        transform: m.transform.relative_to(origin),
+                    })
+                });
+                material.draw(capture.framebuffer(), &self.camera_uniform, &mut meshes)?;
+            }
+            Framebuffer::disable_depth_test();
+            let captured = capture.process(
+                &self.camera_uniform,
+                &self.lights,
+                &self.light_cookies,
+                None,
+                Some(&mut self.environments),
+                self.irradiance_probes.as_ref(),
+                self.reflection_probes.as_ref(),
+            )?;
+            store(face, captured)?;
+        }
+        Framebuffer::disable_blending();
+        // Captures happen outside of the main passes, which set the depth convention of the camera
+        camera::set_depth_convention(false);
+        self.view_uniform
+            .update_uniform_buffer(&mut self.camera_uniform)?;
+        Ok(())
+    }
+
+    /// Render the queued meshes mirrored across the first reflective water surface, at half
+    /// resolution. Returns whether reflections were rendered.
+    #[tracing::instrument(skip_all)]
+    fn render_water_reflections(
+        &mut self,
+        queued: &[(Rc<dyn DrawMaterial>, Vec<Transformed<Rc<Mesh>>>)],
+    ) -> Result<bool> {
+        let Some((surface, _)) = self.queued_water.iter().find(|(_, params)| params.reflections) else { return Ok(false); };
+        let (normal, point) = (
+            surface.transform.rotation * Vec3::Y,
+            surface.transform.position,
+        );
+        let size = (self.geom_pass.borrow().size() / 2).max(UVec2::ONE);
+        let view = RenderTargetView {
+            view: water::reflected_view(&self.view_uniform, normal, point),
+            size,
+            settings: GBufferSettings::low_bandwidth(),
+            mirrored: true,
+        };
+        self.render_to_target(WATER_REFLECTION_TARGET, view, queued)?;
+        Ok(true)
+    }
+
+    /// Render the scene into the offscreen target `name` on the next flush, as seen from `camera`,
+    /// at the given size. The lit result is then available from [`Self::render_targets`].
+    pub fn request_render_target(&mut self, name: impl Into<String>, camera: &Camera, size: UVec2) {
+        let mut camera = camera.clone();
+        camera.projection.depth = self.depth_mode;
+        self.frame_cache.invalidate();
+        self.render_targets.request(
+            name.into(),
+            RenderTargetView {
+                view: ViewUniform::new(&camera),
+                size: size.max(UVec2::ONE),
+                settings: GBufferSettings::low_bandwidth(),
+                mirrored: false,
+            },
+        );
+    }
+
+    pub fn render_targets(&self) -> &RenderTargets {
+        &self.render_targets
+    }
+
+    pub fn render_targets_mut(&mut self) -> &mut RenderTargets {
+        &mut self.render_targets
+    }
+
+    /// Render and light the queued meshes into the target `name` from its view.
+    fn render_to_target(
+        &mut self,
+        name: &str,
+        target_view: RenderTargetView,
+        queued: &[(Rc<dyn DrawMaterial>, Vec<Transformed<Rc<Mesh>>>)],
+    ) -> Result<()> {
+        let target = self
+            .render_targets
+            .take(name, &target_view, &self.reload_watcher)?;
+        let RenderTargetView {
+            mut view,
+            size,
+            mirrored,
+            ..
+        } = target_view;
+        // Queued model matrices are relative to the origin of the camera
+        view.set_origin(self.view_uniform.origin);
+        view.viewport = vec4(0., 0., size.x as _, size.y as _);
+        view.update_uniform_buffer(&mut self.camera_uniform)?;
+
+        // Mirroring flips the winding of the triangles
+        violette::set_front_face(if mirrored {
+            FrontFace::Clockwise
+        } else {
+            FrontFace::CounterClockwise
+        });
+        violette::culling(Some(Cull::Back));
+        Framebuffer::viewport(0, 0, size.x as _, size.y as _);
+        Framebuffer::enable_depth_test(view.depth_test());
+        Framebuffer::disable_scissor();
+        Framebuffer::disable_blending();
+        Framebuffer::clear_color([0., 0., 0., 0.]);
+        target
+            .framebuffer()
+            .do_clear(ClearBuffer::COLOR | ClearBuffer::DEPTH);
+        self.material
+            .borrow_mut()
+            .set_camera_uniform(&self.camera_uniform)?;
+        let clip_planes = clip::relative_planes(&self.clip_planes, self.view_uniform.origin);
+        for (material, meshes) in queued {
+            clip::enable_clip_distances(material.set_clip_planes(&clip_planes));
+            let mut meshes = meshes.iter().map(|m| Transformed {
+                value: m.value.as_ref(),
+                transform: m.transform,
+            });
+            material.draw(target.framebuffer(), &self.camera_uniform, &mut meshes)?;
+        }
+        clip::enable_clip_distances(0);
+        Framebuffer::disable_depth_test();
+        target.process(
+            &self.camera_uniform,
+            &self.lights,
+            &self.light_cookies,
+            None,
+            Some(&mut self.environments),
+            self.irradiance_probes.as_ref(),
+            None,
+        )?;
+        self.render_targets.put_back(name, target);
+        violette::set_front_face(FrontFace::CounterClockwise);
+        Framebuffer::disable_blending();
+        self.view_uniform
+            .update_uniform_buffer(&mut self.camera_uniform)
+    }
+
+    fn draw_outlines(&mut self, frame: &Framebuffer) -> Result<()> {
+        let hovered = std::mem::take(&mut self.queued_hover_outlines);
+        let selected = std::mem::take(&mut self.queued_outlines);
+        for (outlines, params) in [
+            (hovered, self.hover_params),
+            (selected, self.outline_params),
+        ] {
+            if outlines.is_empty() {
+                continue;
+            }
+            let meshes = &self.meshes;
+            let origin = self.view_uniform.origin;
+            self.outline.draw(
+                frame,
+                &self.camera_uniform,
+                params,
+                outlines.iter().filter_map(|m| {
+                    Some(Transformed {
+                        value: meshes.get(m.value.0)?.as_ref(),
+                        transform: m.transform.relative_to(origin),
+                    })
+                }),
+            )?;
+        }
         Ok(())
     }
 
@@ -404,6 +2004,86 @@ impl Renderer {
             let pp_iface = self.post_process_interface();
             pp_iface.ui(ui);
         });
+        ui.menu_button("Exposure debug", |ui| {
+            self.post_process_iface.debug.ui(ui);
+        });
+        ui.menu_button("Selection outline", |ui| {
+            self.outline_params.ui(ui, "selection-outline");
+        });
+        ui.menu_button("Hover highlight", |ui| {
+            self.hover_params.ui(ui, "hover-outline");
+        });
+        ui.menu_button("Resolution scaling", |ui| {
+            self.resolution_scaling.ui(ui);
+        });
+        ui.menu_button("Light culling", |ui| {
+            let mut params = self.light_culling_params();
+            params.ui(ui);
+            self.set_light_culling_params(params);
+        });
+        if !self.environments.is_empty() {
+            ui.menu_button("Environment", |ui| {
+                if self.environments.ui(ui) {
+                    self.frame_cache.invalidate();
+                }
+            });
+        }
+        let mut depth_prepass = self.depth_prepass();
+        if ui.checkbox(&mut depth_prepass, "Depth pre-pass").changed() {
+            if let Err(err) = self.set_depth_prepass(depth_prepass) {
+                tracing::error!("Cannot enable depth pre-pass: {:?}", err);
+            }
+        }
+        let mut camera_relative = self.camera_relative();
+        if ui
+            .checkbox(&mut camera_relative, "Camera-relative rendering")
+            .changed()
+        {
+            self.set_camera_relative(camera_relative);
+        }
+        ui.menu_button("Depth buffer", |ui| {
+            let mut depth_mode = self.depth_mode();
+            ui.radio_value(&mut depth_mode, DepthMode::Standard, "Standard");
+            ui.radio_value(&mut depth_mode, DepthMode::Reversed, "Reversed");
+            ui.radio_value(
+                &mut depth_mode,
+                DepthMode::ReversedInfinite,
+                "Reversed, infinite far plane",
+            );
+            self.set_depth_mode(depth_mode);
+        });
+        ui.menu_button("Frame statistics", |ui| {
+            self.stats_history.ui(ui);
+        });
+        ui.menu_button("Sprite atlas", |ui| {
+            self.sprite_atlas.ui(ui);
+        });
+        let mut occlusion_culling = self.occlusion_culling();
+        if ui
+            .checkbox(&mut occlusion_culling, "Occlusion culling")
+            .changed()
+        {
+            if let Err(err) = self.set_occlusion_culling(occlusion_culling) {
+                tracing::error!("Cannot enable occlusion culling: {:?}", err);
+            }
+        }
+        let mut validate_color_spaces = self.color_space_validation();
+        if ui
+            .checkbox(&mut validate_color_spaces, "Validate color spaces")
+            .changed()
+        {
+            self.set_color_space_validation(validate_color_spaces);
+        }
+        #[cfg(feature = "tracy")]
+        {
+            let mut frame_images = self.gpu_profiler.frame_images();
+            if ui
+                .checkbox(&mut frame_images, "Tracy frame images")
+                .changed()
+            {
+                self.gpu_profiler.set_frame_images(frame_images);
+            }
+        }
     }
 
     #[cfg(feature = "debug-ui")]
@@ -423,84 +2103,150 @@ impl Renderer {
 
     #[cfg(feature = "debug-ui")]
     pub fn ui_render_stats(&mut self, ui: &mut egui::Ui) {
+        let stats = self.frame_stats();
         ui.label(format!(
-            "{:3} Objects submitted | {:3} objects rendered",
-            self.last_render_submitted, self.last_render_rendered
+            "{:3} Objects submitted | {:3} objects rendered | {:3} culled",
+            stats.submitted, stats.instances, stats.culled
         ));
         ui.separator();
         ui.label(format!(
-            "Scene processing: {:5?}",
-            self.last_scene_duration.unwrap_or_default()
+            "{:4} draw calls | {:7} triangles",
+            stats.draw_calls, stats.triangles
         ));
         ui.separator();
+        ui.label(format!("Scene processing: {:5?}", stats.scene_time));
+        ui.separator();
+        ui.label(format!("Render time: {:5?}", stats.render_time));
+        ui.separator();
         ui.label(format!(
-            "Render time: {:5?}",
-            self.last_render_duration.unwrap_or_default()
+            "Render scale: {:3.0} %",
+            self.resolution_scaling.scale() * 100.
         ));
         ui.separator();
         ui.label(format!(
             "Average luminance: {:>2.2} EV",
             self.post_process.average_luminance().log2()
         ));
+        if let Some(luminance) = self.probed_luminance() {
+            ui.separator();
+            ui.label(format!(
+                "Under cursor: {:.3} nits ({:>+2.2} EV)",
+                luminance,
+                luminance.log2()
+            ));
+        }
+        ui.separator();
+        ui.label(format!(
+            "Post intermediates: {:.1} MiB",
+            self.texture_pool.memory_usage() as f64 / (1024. * 1024.)
+        ));
     }
 
     #[cfg(feature = "debug-ui")]
-    pub fn ui_debug_panel(&self, ui: &mut egui::Ui) {
-        use std::sync::Arc;
-
-        const GET_NAME: fn(usize) -> &'static str = |ix| match ix {
-            0 => "Position",
-            1 => "Albedo",
-            2 => "Normal",
-            3 => "Roughness/Metal",
-            4 => "Emission",
-            _ => "<None>",
+    pub fn ui_debug_panel(&mut self, ui: &mut egui::Ui) {
+        use gbuffer_debug::DebugSource;
+
+        let viewer = match &self.gbuffer_viewer {
+            Some(viewer) => viewer.clone(),
+            None => match GBufferViewer::new(&self.reload_watcher) {
+                Ok(viewer) => self
+                    .gbuffer_viewer
+                    .insert(Rc::new(RefCell::new(viewer)))
+                    .clone(),
+                Err(err) => {
+                    ui.label(format!("Cannot create G-Buffer viewer: {:?}", err));
+                    return;
+                }
+            },
         };
-        thread_local! {
-            static SELECTED_TEXTURE: RefCell<usize> = RefCell::new(usize::MAX);
-        }
-        SELECTED_TEXTURE.with(|key| {
-            let ix = ui
-                .horizontal(|ui| {
-                    let label = ui.label("Select debug texture");
-                    let ix = &mut *key.borrow_mut();
-                    egui::ComboBox::new("renderer-debug-texture", "Debug texture")
-                        .selected_text(GET_NAME(*ix))
-                        .show_index(ui, ix, 5, |ix| GET_NAME(ix).to_string())
-                        .labelled_by(label.id);
-                    *ix
-                })
-                .inner;
-            const SIDE: f32 = 256.;
-            let size = self.geom_pass.borrow().size().as_vec2();
-            let size = if size.x > size.y {
-                vec2(SIDE, size.y / size.x * SIDE)
-            } else {
-                vec2(SIDE * size.x / size.y, SIDE)
-            };
-            let (rect, _) = ui.allocate_at_least(
-                egui::vec2(size.x, size.y),
-                egui::Sense::focusable_noninteractive(),
-            );
-            let painter = ui.painter();
-            painter.rect_filled(rect, 0., egui::Rgba::from_gray(0.));
-            let geom_pass = self.geom_pass.clone();
-            painter.add(egui::PaintCallback {
-                rect,
-                callback: Arc::new(rose_ui::painter::UiCallback::new(move |_info, ui| {
-                    let geom_pass = geom_pass.borrow();
-                    let _ = match ix {
-                        0 => geom_pass.debug_position(ui.framebuffer()),
-                        1 => geom_pass.debug_albedo(ui.framebuffer()),
-                        2 => geom_pass.debug_normal(ui.framebuffer()),
-                        3 => geom_pass.debug_rough_metal(ui.framebuffer()),
-                        4 => geom_pass.debug_emission(ui.framebuffer()),
-                        _ => Ok(()),
-                    }
-                    .is_ok();
-                })),
-            });
+        self.gbuffer_view.ui(ui);
+        let params = self.gbuffer_view;
+        let sources = [Some(params.source), params.compare];
+        let views = sources.into_iter().flatten().count() as f32;
+
+        const SIDE: f32 = 256.;
+        let size = self.geom_pass.borrow().size().as_vec2();
+        let size = if size.x > size.y {
+            vec2(SIDE, size.y / size.x * SIDE)
+        } else {
+            vec2(SIDE * size.x / size.y, SIDE)
+        };
+        // Side by side views share the width of a single one
+        let size = size * (2. / (views + 1.));
+        let inv_proj = self.view_uniform.inv_proj;
+        let reverse_z = self.view_uniform.reversed_depth();
+        let mut hovered = None;
+        ui.horizontal(|ui| {
+            for source in sources.into_iter().flatten() {
+                let (rect, response) =
+                    ui.allocate_exact_size(egui::vec2(size.x, size.y), egui::Sense::hover());
+                ui.painter()
+                    .rect_filled(rect, 0., egui::Rgba::from_gray(0.));
+                if let Some(pointer) = response.hover_pos() {
+                    let uv = (pointer - rect.min) / rect.size();
+                    hovered = Some((source, vec2(uv.x, uv.y)));
+                }
+                let geom_pass = self.geom_pass.clone();
+                let viewer = viewer.clone();
+                rose_ui::callback::paint(ui, rect, move |ctx| {
+                    viewer.borrow().draw(
+                        ctx.framebuffer,
+                        &geom_pass.borrow(),
+                        source,
+                        &params,
+                        inv_proj,
+                        reverse_z,
+                    )
+                });
+            }
         });
+
+        let mut viewer = viewer.borrow_mut();
+        if let Err(err) = viewer.inspect(&self.geom_pass.borrow(), &self.view_uniform, hovered) {
+            tracing::warn!("Cannot inspect G-Buffer pixel: {:?}", err);
+        }
+        match viewer.inspected() {
+            Some(pixel) => {
+                let value = pixel.value;
+                let text = match pixel.source {
+                    DebugSource::Depth => format!("{:.3} m from the camera", value.x),
+                    _ => format!(
+                        "R {:.4} | G {:.4} | B {:.4} | A {:.4}",
+                        value.x, value.y, value.z, value.w
+                    ),
+                };
+                ui.monospace(format!(
+                    "{} ({}, {}): {}",
+                    pixel.source.name(),
+                    pixel.pixel.x,
+                    pixel.pixel.y,
+                    text
+                ));
+            }
+            None => {
+                ui.weak("Hover a view to inspect its pixels");
+            }
+        }
+    }
+}
+
+fn hash_sprite(hasher: &mut impl Hasher, sprite: &Sprite) {
+    hash_floats(hasher, &sprite.position.to_array());
+    hash_floats(hasher, &sprite.size.to_array());
+    hash_floats(hasher, &sprite.tint.to_array());
+    hash_floats(hasher, &sprite.uv_rect.to_array());
+    match sprite.billboard {
+        Billboard::FaceCamera => 0u8.hash(hasher),
+        Billboard::Axis(axis) => {
+            1u8.hash(hasher);
+            hash_floats(hasher, &axis.to_array());
+        }
+    }
+}
+
+fn hash_floats(hasher: &mut impl Hasher, values: &[f32]) {
+    for value in values {
+        value.to_bits().hash(hasher);
     }
 }
 
@@ -508,10 +2254,8 @@ impl Renderer {
 fn make_texture_frame(
     ui: &mut egui::Ui,
     name: &str,
-    draw: impl 'static + Fn(&Framebuffer) + Send + Sync,
+    draw: impl 'static + Fn(&Framebuffer) -> Result<()>,
 ) -> egui::Response {
-    use std::sync::Arc;
-
     ui.group(|ui| {
         let label = ui.label(name);
         let (rect, response) = ui.allocate_at_least(
@@ -519,18 +2263,15 @@ fn make_texture_frame(
             egui::Sense::focusable_noninteractive(),
         );
         response.labelled_by(label.id);
-        let painter = ui.painter();
-        painter.rect_filled(rect, 0., egui::Rgba::from_gray(0.));
-        painter.add(egui::PaintCallback {
-            rect,
-            callback: Arc::new(rose_ui::painter::UiCallback::new(move |_info, ui| {
-                draw(ui.framebuffer());
-            })),
-        });
+        ui.painter()
+            .rect_filled(rect, 0., egui::Rgba::from_gray(0.));
+        rose_ui::callback::paint(ui, rect, move |ctx| draw(ctx.framebuffer));
     })
     .response
 }
 
+/// Material drawing meshes into the G-Buffer. Fragment shaders must write every attachment listed
+/// in [`gbuffers::GBufferAttachment`]; see [`gbuffers::validate_gbuffer_outputs`].
 pub trait DrawMaterial: 'static + fmt::Debug {
     fn draw<'a>(
         &self,
@@ -539,9 +2280,17 @@ pub trait DrawMaterial: 'static + fmt::Debug {
         meshes: &mut dyn Iterator<Item = Transformed<&'a Mesh>>,
     ) -> Result<()>;
 
-    fn eq_key(&self) -> usize;
+    /// Clip the meshes drawn next against the planes of [`Renderer::set_clip_planes`], moved
+    /// relative to the origin of the view (see [`clip::relative_planes`]), returning the number of
+    /// clip distances the vertex shader writes. Materials whose vertex shader does not write them
+    /// are not clipped.
+    fn set_clip_planes(&self, _planes: &[Vec4]) -> usize {
+        0
+    }
 
     fn as_any(&self) -> &dyn Any;
+
+    fn as_any_mut(&mut self) -> &mut dyn Any;
 }
 
 #[derive(Debug)]
@@ -562,11 +2311,16 @@ impl DrawMaterial for StandardDrawMaterial {
             .draw_meshes(frame, view, &self.instance, meshes)
     }
 
-    fn eq_key(&self) -> usize {
-        Rc::as_ptr(&self.instance) as usize
+    fn set_clip_planes(&self, planes: &[Vec4]) -> usize {
+        self.material.borrow_mut().set_clip_planes(planes);
+        planes.len()
     }
 
     fn as_any(&self) -> &dyn Any {
         self
     }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
 }
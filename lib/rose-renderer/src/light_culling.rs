@@ -0,0 +1,252 @@
+//! Culling of the lights too far from the camera, or too small on screen, to be worth shading.
+//!
+//! Point and area lights are skipped by the lighting pass when they are further from the camera
+//! than their maximum distance, or, when enabled in [`LightCullingParams`], when their sphere of
+//! influence covers too little of the screen height. Culled lights only come back once they pass
+//! those limits by a margin, so that lights sitting at a limit don't pop on and off as the camera
+//! moves. Directional and ambient lights are never culled.
+
+use std::collections::{HashMap, HashSet};
+use std::f32::consts::FRAC_PI_4;
+
+use glam::{Vec3, Vec4Swizzles};
+
+use rose_core::{
+    camera::ViewUniform,
+    light::{AreaShape, Light, LightHandle, Lights},
+};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serialize", serde(default))]
+pub struct LightCullingParams {
+    /// Cull the lights too small on screen. Off by default, as it changes how existing scenes
+    /// render; lights with a maximum distance are culled past it either way.
+    pub enabled: bool,
+    /// Illuminance, in lux, under which a light is considered to have no influence. Sets the
+    /// radius of the sphere of influence of the lights.
+    pub influence_threshold: f32,
+    /// Fraction of the screen height under which the sphere of influence of a light gets culled.
+    pub min_screen_coverage: f32,
+    /// Relative margin by which a culled light has to be back within the limits to be shaded
+    /// again.
+    pub hysteresis: f32,
+}
+
+impl Default for LightCullingParams {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            influence_threshold: 1e-2,
+            min_screen_coverage: 1e-2,
+            hysteresis: 0.2,
+        }
+    }
+}
+
+impl LightCullingParams {
+    #[cfg(feature = "debug-ui")]
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        use egui::{DragValue, Grid};
+
+        ui.checkbox(&mut self.enabled, "Enabled");
+        Grid::new("light-culling-params")
+            .num_columns(2)
+            .show(ui, |ui| {
+                let threshold_label = ui.label("Influence threshold").id;
+                ui.add(
+                    DragValue::new(&mut self.influence_threshold)
+                        .clamp_range(1e-4..=10.)
+                        .speed(1e-3)
+                        .suffix(" lx"),
+                )
+                .labelled_by(threshold_label);
+                ui.end_row();
+
+                let coverage_label = ui.label("Min. screen coverage").id;
+                ui.add(
+                    DragValue::new(&mut self.min_screen_coverage)
+                        .clamp_range(0.0..=1.)
+                        .speed(1e-3),
+                )
+                .labelled_by(coverage_label);
+                ui.end_row();
+
+                let hysteresis_label = ui.label("Hysteresis").id;
+                ui.add(
+                    DragValue::new(&mut self.hysteresis)
+                        .clamp_range(0.0..=2.)
+                        .speed(1e-2),
+                )
+                .labelled_by(hysteresis_label);
+                ui.end_row();
+            });
+    }
+}
+
+/// Lights culled on the last update, along with the maximum distances set on lights.
+#[derive(Debug, Default)]
+pub struct LightCulling {
+    pub params: LightCullingParams,
+    max_distances: HashMap<LightHandle, f32>,
+    culled: HashSet<LightHandle>,
+}
+
+impl LightCulling {
+    /// Set the distance from the camera past which the light is culled, or remove it to only cull
+    /// the light by its screen coverage.
+    pub fn set_max_distance(&mut self, handle: LightHandle, distance: Option<f32>) {
+        match distance {
+            Some(distance) => self.max_distances.insert(handle, distance),
+            None => self.max_distances.remove(&handle),
+        };
+    }
+
+    pub fn max_distance(&self, handle: LightHandle) -> Option<f32> {
+        self.max_distances.get(&handle).copied()
+    }
+
+    pub fn remove(&mut self, handle: LightHandle) {
+        self.max_distances.remove(&handle);
+        self.culled.remove(&handle);
+    }
+
+    pub fn clear(&mut self) {
+        self.max_distances.clear();
+        self.culled.clear();
+    }
+
+    pub fn is_culled(&self, handle: LightHandle) -> bool {
+        self.culled.contains(&handle)
+    }
+
+    /// Number of lights culled on the last update.
+    pub fn culled_count(&self) -> usize {
+        self.culled.len()
+    }
+
+    /// Decide which lights to cull as seen from the view.
+    pub fn update(&mut self, lights: &Lights, view: &ViewUniform) {
+        if !self.params.enabled && self.max_distances.is_empty() {
+            self.culled.clear();
+            return;
+        }
+        let camera_pos = view.inv_view.w_axis.xyz();
+        // Projection scale of the screen height; orthographic projections don't divide by distance
+        let proj_scale = view.mat_proj.y_axis.y;
+        let perspective = view.mat_proj.w_axis.w == 0.;
+        let mut culled = HashSet::new();
+        for (handle, light) in lights.iter() {
+            let Some((position, reach)) = Self::light_bounds(light) else { continue; };
+            let distance = (camera_pos.distance(position) - reach).max(0.);
+            let radius = (Self::intensity(light) / self.params.influence_threshold).sqrt();
+            let coverage = if distance <= radius {
+                f32::INFINITY
+            } else if perspective {
+                radius * proj_scale / distance
+            } else {
+                radius * proj_scale
+            };
+            let was_culled = self.culled.contains(&handle);
+            let max_distance = self.max_distances.get(&handle).copied();
+            if should_cull(&self.params, was_culled, distance, max_distance, coverage) {
+                culled.insert(handle);
+            }
+        }
+        self.culled = culled;
+    }
+
+    /// Position of the light, and the distance its surface extends from it, for lights which can be
+    /// culled.
+    fn light_bounds(light: &Light) -> Option<(Vec3, f32)> {
+        match light {
+            &Light::Point { position, .. } => Some((position, 0.)),
+            &Light::Area { position, size, .. } => Some((position, size.length() / 2.)),
+            Light::Directional { .. } | Light::Ambient { .. } => None,
+        }
+    }
+
+    /// Luminous intensity of the light, whose color is the luminance of the surface for area
+    /// lights.
+    fn intensity(light: &Light) -> f32 {
+        let color = light.color().max_element();
+        match light {
+            &Light::Area { shape, size, .. } => {
+                let area = match shape {
+                    AreaShape::Rect => size.x * size.y,
+                    AreaShape::Disk => FRAC_PI_4 * size.x * size.y,
+                };
+                color * area
+            }
+            _ => color,
+        }
+    }
+}
+
+/// Whether the light should be culled, given whether it was culled on the previous update. Culled
+/// lights need to be within the limits by the hysteresis margin to be shaded again.
+fn should_cull(
+    params: &LightCullingParams,
+    was_culled: bool,
+    distance: f32,
+    max_distance: Option<f32>,
+    coverage: f32,
+) -> bool {
+    let margin = if was_culled {
+        1. + params.hysteresis
+    } else {
+        1.
+    };
+    let too_far = max_distance.is_some_and(|max| distance * margin > max);
+    let too_small = params.enabled && coverage < params.min_screen_coverage * margin;
+    too_far || too_small
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{Quat, Vec2};
+
+    use super::*;
+
+    #[test]
+    fn culls_past_max_distance_with_hysteresis() {
+        let params = LightCullingParams::default();
+        assert!(!should_cull(&params, false, 90., Some(100.), 1.));
+        assert!(should_cull(&params, false, 110., Some(100.), 1.));
+        // Back within the distance, but not by the margin yet
+        assert!(should_cull(&params, true, 90., Some(100.), 1.));
+        assert!(!should_cull(&params, true, 80., Some(100.), 1.));
+        assert!(!should_cull(&params, false, 1e6, None, 1.));
+    }
+
+    #[test]
+    fn culls_small_lights_with_hysteresis() {
+        let params = LightCullingParams {
+            enabled: true,
+            min_screen_coverage: 0.1,
+            ..Default::default()
+        };
+        assert!(should_cull(&params, false, 10., None, 0.05));
+        assert!(!should_cull(&params, false, 10., None, 0.11));
+        assert!(should_cull(&params, true, 10., None, 0.11));
+        assert!(!should_cull(&params, true, 10., None, 0.13));
+        // Only lights past their maximum distance are culled unless enabled
+        let disabled = LightCullingParams::default();
+        assert!(!should_cull(&disabled, false, 10., None, 0.05));
+        assert!(should_cull(&disabled, false, 110., Some(100.), 1.));
+    }
+
+    #[test]
+    fn area_lights_are_as_intense_as_their_surface_is_large() {
+        let panel = |size| Light::Area {
+            color: Vec3::ONE,
+            position: Vec3::ZERO,
+            rotation: Quat::IDENTITY,
+            shape: AreaShape::Rect,
+            size,
+            two_sided: false,
+        };
+        assert_eq!(LightCulling::intensity(&panel(Vec2::new(4., 2.))), 8.);
+        assert_eq!(LightCulling::intensity(&panel(Vec2::ONE)), 1.);
+    }
+}
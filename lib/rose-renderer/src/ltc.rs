@@ -0,0 +1,82 @@
+//! Linearly transformed cosine tables for the specular term of area lights, from "Real-Time
+//! Polygonal-Light Shading with Linearly Transformed Cosines" (Heitz et al. 2016).
+//!
+//! The tables are fitted offline against the GGX BRDF by the `ltc_fit` tool.
+
+use std::num::NonZeroU32;
+
+use eyre::Result;
+use violette::texture::{Dimension, SampleMode, Texture, TextureWrap};
+
+/// Resolution of the tables, along roughness and `sqrt(1 - cos(theta))` of the view direction.
+/// Must match `LTC_SIZE` in `area_light.glsl`.
+pub const LTC_SIZE: usize = 64;
+
+static LTC_GGX: &[u8] = include_bytes!("ltc_ggx.bin");
+
+/// Coefficients of the inverse transforms, followed by the magnitude and Fresnel terms.
+fn table() -> (Vec<[f32; 4]>, Vec<[f32; 2]>) {
+    let mut values = LTC_GGX
+        .chunks_exact(4)
+        .map(|bytes| f32::from_le_bytes(bytes.try_into().unwrap()));
+    let inverses = (0..LTC_SIZE * LTC_SIZE)
+        .map(|_| [(); 4].map(|_| values.next().unwrap()))
+        .collect();
+    let amplitudes = (0..LTC_SIZE * LTC_SIZE)
+        .map(|_| [(); 2].map(|_| values.next().unwrap()))
+        .collect();
+    (inverses, amplitudes)
+}
+
+#[derive(Debug)]
+pub struct LtcTables {
+    /// Non-constant coefficients of the inverse transforms.
+    pub inverse: Texture<[f32; 4]>,
+    /// Magnitude and Fresnel terms of the fitted lobes.
+    pub amplitude: Texture<[f32; 2]>,
+}
+
+impl LtcTables {
+    pub fn new() -> Result<Self> {
+        let (inverses, amplitudes) = table();
+        let size = NonZeroU32::new(LTC_SIZE as _).unwrap();
+        let nonzero_one = NonZeroU32::new(1).unwrap();
+
+        let inverse = Texture::new(size, size, nonzero_one, Dimension::D2);
+        inverse.filter_min(SampleMode::Linear)?;
+        inverse.filter_mag(SampleMode::Linear)?;
+        inverse.wrap_s(TextureWrap::ClampEdge)?;
+        inverse.wrap_t(TextureWrap::ClampEdge)?;
+        inverse.reserve_memory()?;
+        inverse.set_data(&inverses)?;
+
+        let amplitude = Texture::new(size, size, nonzero_one, Dimension::D2);
+        amplitude.filter_min(SampleMode::Linear)?;
+        amplitude.filter_mag(SampleMode::Linear)?;
+        amplitude.wrap_s(TextureWrap::ClampEdge)?;
+        amplitude.wrap_t(TextureWrap::ClampEdge)?;
+        amplitude.reserve_memory()?;
+        amplitude.set_data(&amplitudes)?;
+
+        Ok(Self { inverse, amplitude })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn table_is_complete_and_bounded() {
+        assert_eq!(LTC_GGX.len(), LTC_SIZE * LTC_SIZE * 6 * 4);
+        let (inverses, amplitudes) = table();
+        assert!(inverses.iter().flatten().all(|v| v.is_finite()));
+        for [magnitude, fresnel] in amplitudes {
+            assert!((0. ..=1.01).contains(&magnitude), "{magnitude}");
+            assert!((0. ..=magnitude + 1e-3).contains(&fresnel), "{fresnel}");
+        }
+        // The roughest lobe at normal incidence stays close to a rotationally symmetric cosine
+        let [m00, m02, m20, _] = inverses[LTC_SIZE - 1];
+        assert!((m00 - 1.).abs() < 1e-3 && m02.abs() < 1e-3 && m20.abs() < 1e-3);
+    }
+}
@@ -1,28 +1,36 @@
-use std::sync::RwLock;
+use std::{cell::Cell, path::PathBuf, sync::RwLock};
 
 use crevice::std140::AsStd140;
 use eyre::{Context, Result};
-use glam::{IVec4, UVec4, Vec2, Vec3, Vec4};
+use glam::{IVec4, UVec2, UVec4, Vec2, Vec3, Vec4};
 
 use rose_core::{
     camera::ViewUniformBuffer,
+    color::{self, ColorSpace},
+    sampler::{Sampler, SamplerCache, SamplerState},
     transform::Transformed,
-    utils::reload_watcher::{ReloadFileProxy, ReloadWatcher},
+    utils::{
+        draw_counters::CountBind,
+        reload_watcher::{ReloadFileProxy, ReloadWatcher},
+    },
 };
 use violette::{
     buffer::UniformBuffer,
     framebuffer::Framebuffer,
     gl,
     program::{Program, UniformBlockIndex, UniformLocation},
-    shader::{FragmentShader, VertexShader},
     texture::Texture,
+    Cull,
 };
 use violette_derive::VertexAttributes;
 
+use crate::clip::ClipLocations;
+use crate::morph::MorphLocations;
+use crate::shader_material::{link_program, ErrorProgram};
 use crate::Mesh;
 use crate::{bones::Std140GpuBone, DrawMaterial};
 
-#[derive(Debug, Default, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable, VertexAttributes)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable, VertexAttributes)]
 #[repr(C)]
 pub struct Vertex {
     pub position: Vec3,
@@ -30,6 +38,18 @@ pub struct Vertex {
     pub uv: Vec2,
     pub bones_ix: IVec4,
     pub bones_weights: Vec4,
+    /// Linear RGBA vertex color, multiplied with the albedo of the material.
+    pub color: Vec4,
+    /// Secondary UV set, typically used for lightmaps and detail maps.
+    pub uv2: Vec2,
+    #[attribute(ignore)]
+    _padding: Vec2,
+}
+
+impl Default for Vertex {
+    fn default() -> Self {
+        Self::new(Vec3::ZERO, Vec3::ZERO, Vec2::ZERO)
+    }
 }
 
 impl Vertex {
@@ -40,17 +60,114 @@ impl Vertex {
             uv,
             bones_ix: IVec4::splat(-1),
             bones_weights: Vec4::ZERO,
+            color: Vec4::ONE,
+            uv2: uv,
+            _padding: Vec2::ZERO,
         }
     }
 
+    pub fn with_color(mut self, color: Vec4) -> Self {
+        self.color = color;
+        self
+    }
+
+    pub fn with_uv2(mut self, uv2: Vec2) -> Self {
+        self.uv2 = uv2;
+        self
+    }
+
     pub fn attach_bones(mut self, bones_ix: UVec4, weights: Vec4) -> Self {
         self.bones_ix = bones_ix.as_ivec4();
         self.bones_weights = weights;
         self
     }
+
+    /// Attach the vertex to bones by their index in [`crate::bones::Bone::traverse`] order and
+    /// weight. Only the 4 strongest influences are kept, their weights normalized to sum to 1.
+    pub fn with_influences(mut self, influences: &[(u32, f32)]) -> Self {
+        let mut influences = influences
+            .iter()
+            .copied()
+            .filter(|(_, weight)| *weight > 0.)
+            .collect::<Vec<_>>();
+        influences.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+        influences.truncate(4);
+        let total = influences.iter().map(|(_, weight)| weight).sum::<f32>();
+        self.bones_ix = IVec4::splat(-1);
+        self.bones_weights = Vec4::ZERO;
+        for (i, (bone, weight)) in influences.into_iter().enumerate() {
+            self.bones_ix[i] = bone as i32;
+            self.bones_weights[i] = weight / total;
+        }
+        self
+    }
+}
+
+/// UV set a texture slot samples from.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum UvChannel {
+    #[default]
+    Primary = 0,
+    Secondary = 1,
+}
+
+/// Texture slots of the standard material.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum TextureSlot {
+    Color,
+    Normal,
+    RoughMetal,
+    Emission,
+    /// Clearcoat strength and roughness, in the red and green channels.
+    Clearcoat,
+    /// Anisotropy direction in the tangent space of the normal map, in the red and green channels
+    /// mapped to 0..1, and strength in the blue channel, as in `KHR_materials_anisotropy`.
+    Anisotropy,
+}
+
+impl TextureSlot {
+    /// Every slot, in the order of their texture units.
+    pub const ALL: [Self; 6] = [
+        Self::Color,
+        Self::Normal,
+        Self::RoughMetal,
+        Self::Emission,
+        Self::Clearcoat,
+        Self::Anisotropy,
+    ];
+
+    /// Texture unit the slot is bound to.
+    pub fn unit(self) -> u32 {
+        self as u32
+    }
+
+    /// Color space the texture of the slot is expected to be stored in.
+    pub fn color_space(self) -> ColorSpace {
+        match self {
+            Self::Color | Self::Emission => ColorSpace::Srgb,
+            Self::Normal | Self::RoughMetal | Self::Clearcoat | Self::Anisotropy => {
+                ColorSpace::Linear
+            }
+        }
+    }
+
+    fn uv_channel_mut(self, uniforms: &mut MaterialUniforms) -> &mut u32 {
+        match self {
+            Self::Color => &mut uniforms.uv_channels.x,
+            Self::Normal => &mut uniforms.uv_channels.y,
+            Self::RoughMetal => &mut uniforms.uv_channels.z,
+            Self::Emission => &mut uniforms.uv_channels.w,
+            Self::Clearcoat => &mut uniforms.layer_uv_channels.x,
+            Self::Anisotropy => &mut uniforms.layer_uv_channels.y,
+        }
+    }
 }
 
-#[derive(Debug, Copy, Clone, AsStd140)]
+/// Default emission strength, which is the luminance in nits (cd/m²) of a fully emissive surface
+/// with an emission factor of one.
+pub const DEFAULT_EMISSION_STRENGTH: f32 = 10.;
+
+#[derive(Debug, Copy, Clone, PartialEq, AsStd140)]
 pub struct MaterialUniforms {
     pub has_color: bool,
     pub color_factor: Vec3,
@@ -60,6 +177,24 @@ pub struct MaterialUniforms {
     pub rough_metal_factor: Vec2,
     pub has_emission: bool,
     pub emission_factor: Vec3,
+    /// Luminance of the emission, in nits, multiplied with the emission factor and texture.
+    pub emission_strength: f32,
+    /// Disables backface culling for this material, and flips the normals of back faces.
+    pub double_sided: bool,
+    /// UV set used by each texture slot, in the order color, normal, roughness/metal, emission.
+    pub uv_channels: UVec4,
+    /// Strength of a smooth dielectric coat over the base layer, see `KHR_materials_clearcoat`.
+    pub clearcoat: f32,
+    pub clearcoat_roughness: f32,
+    /// Stretching of the highlights along the anisotropy direction, see
+    /// `KHR_materials_anisotropy`.
+    pub anisotropy: f32,
+    /// Angle of the anisotropy direction from the U direction of the normal map UVs, in radians.
+    pub anisotropy_rotation: f32,
+    pub has_clearcoat_map: bool,
+    pub has_anisotropy_map: bool,
+    /// UV set used by the clearcoat and anisotropy slots.
+    pub layer_uv_channels: UVec2,
 }
 
 #[derive(Debug)]
@@ -68,13 +203,28 @@ pub struct Material {
     u_color: UniformLocation,
     u_normal: UniformLocation,
     u_rough_metal: UniformLocation,
+    u_clearcoat: UniformLocation,
+    u_anisotropy: UniformLocation,
     u_model: UniformLocation,
     u_uniforms: UniformBlockIndex,
     u_view: UniformBlockIndex,
     u_bones: UniformBlockIndex,
     bones_uniform: UniformBuffer<Std140GpuBone>,
+    u_morph: MorphLocations,
+    u_clip: ClipLocations,
+    /// World space clip planes, see [`crate::clip`].
+    clip_planes: Vec<Vec4>,
+    /// Samplers of the material instances overriding the sampling of their textures.
+    samplers: SamplerCache,
+    /// Warn about instances with textures in the wrong color space when they are first drawn.
+    validate_color_spaces: bool,
     reload_watcher: ReloadFileProxy,
     u_emission: UniformLocation,
+    base_path: PathBuf,
+    vert_path: PathBuf,
+    frag_path: PathBuf,
+    /// Stands in for the program while the shaders fail to compile.
+    error_program: Option<ErrorProgram>,
 }
 
 impl Material {
@@ -84,48 +234,20 @@ impl Material {
     ) -> Result<Self> {
         let vert_path = reload_watcher.base_path().join("mesh/mesh.vert.glsl");
         let frag_path = reload_watcher.base_path().join("mesh/mesh.frag.glsl");
-        let vert_files = glsl_preprocessor::load_and_parse(vert_path)
-            .with_context(|| "Parsing mesh vertex shader")?;
-        let frag_files = glsl_preprocessor::load_and_parse(frag_path)
-            .with_context(|| "Parsing mesh fragment shader")?;
-        let vert_shader = VertexShader::new_multiple(vert_files.iter().map(|(_, s)| s.as_str()))
-            .with_context(|| {
-                format!(
-                    "File map:\n{}",
-                    vert_files
-                        .iter()
-                        .map(|(p, _)| p.as_path())
-                        .enumerate()
-                        .map(|(ix, p)| format!("\t{} => {}", ix, p.display()))
-                        .collect::<Vec<_>>()
-                        .join("\n")
-                )
-            })?;
-        let frag_shader = FragmentShader::new_multiple(frag_files.iter().map(|(_, s)| s.as_str()))
-            .with_context(|| {
-                format!(
-                    "File map:\n{}",
-                    frag_files
-                        .iter()
-                        .map(|(p, _)| p.as_path())
-                        .enumerate()
-                        .map(|(ix, p)| format!("\t{} => {}", ix, p.display()))
-                        .collect::<Vec<_>>()
-                        .join("\n")
-                )
-            })?;
-        let program = Program::new()
-            .with_shader(vert_shader.id)
-            .with_shader(frag_shader.id)
-            .link()?;
+        let (program, files) =
+            link_program(&vert_path, &frag_path).context("Loading mesh material program")?;
         let u_color = program.uniform("map_color");
         let u_normal = program.uniform("map_normal");
         let u_rough_metal = program.uniform("map_rough_metal");
         let u_emission = program.uniform("map_emission");
+        let u_clearcoat = program.uniform("map_clearcoat");
+        let u_anisotropy = program.uniform("map_anisotropy");
         let u_uniforms = program.uniform_block("Uniforms");
         let u_model = program.uniform("model");
         let u_view = program.uniform_block("View");
         let u_bones = program.uniform_block("Bones");
+        let u_morph = MorphLocations::new(&program);
+        let u_clip = ClipLocations::new(&program);
 
         if let Some(buf) = camera_uniform {
             program.bind_block(&buf.slice(0..=0), u_view, 0)?;
@@ -136,76 +258,166 @@ impl Material {
             u_normal,
             u_rough_metal,
             u_emission,
+            u_clearcoat,
+            u_anisotropy,
             u_model,
             u_uniforms,
             u_view,
             u_bones,
             bones_uniform: UniformBuffer::new(),
-            reload_watcher: reload_watcher.proxy(
-                vert_files
-                    .iter()
-                    .map(|(p, _)| p.as_path())
-                    .chain(frag_files.iter().map(|(p, _)| p.as_path())),
-            ),
+            u_morph,
+            u_clip,
+            clip_planes: vec![],
+            samplers: SamplerCache::new(),
+            validate_color_spaces: false,
+            reload_watcher: reload_watcher.proxy(files.iter().map(|p| p.as_path())),
+            base_path: reload_watcher.base_path().to_path_buf(),
+            vert_path,
+            frag_path,
+            error_program: None,
         })
     }
 
     pub fn draw_meshes<'a>(
         &mut self,
         frame: &Framebuffer,
-        _view: &ViewUniformBuffer,
+        view: &ViewUniformBuffer,
         instance: &MaterialInstance,
         meshes: impl IntoIterator<Item = Transformed<&'a Mesh>>,
     ) -> Result<()> {
-        {
-            if self.reload_watcher.should_reload() {
-                let mut paths = self.reload_watcher.paths();
-                let vert_path = paths.next().unwrap();
-                let frag_path = paths.next().unwrap();
-                tracing::debug!(message="Reloading material shader", vert=%vert_path.display(), frag=%frag_path.display());
-                let vert_shader = VertexShader::load(vert_path)?;
-                let frag_shader = FragmentShader::load(frag_path)?;
-                *self.program.write().unwrap() = Program::new()
-                    .with_shader(vert_shader.id)
-                    .with_shader(frag_shader.id)
-                    .link()?;
+        self.reload_if_needed();
+        if self.validate_color_spaces && !instance.color_spaces_checked.replace(true) {
+            for slot in instance.color_space_mismatches() {
+                tracing::warn!(
+                    message = "Texture color space does not match its material slot",
+                    ?slot,
+                    expected = ?slot.color_space()
+                );
+            }
+        }
+        if let Some(error_program) = &self.error_program {
+            for mesh in meshes {
+                if let Some(root_bone) = &mesh.root_bone {
+                    root_bone.update_buffer(&mut self.bones_uniform, mesh.skinning)?;
+                }
+                error_program.bind(view, Some(&self.bones_uniform))?;
+                error_program.draw_mesh(frame, mesh)?;
             }
+            return Ok(());
         }
         let program = self.program();
         program.bind_block(&instance.buffer.slice(0..=0), self.u_uniforms, 1)?;
         program.bind_block(&self.bones_uniform.slice(..), self.u_bones, 2)?;
+        self.u_clip.set(&program, &self.clip_planes)?;
         if let Some(color) = instance.color.as_ref() {
-            program.set_uniform(self.u_color, color.as_uniform(0)?)?;
+            let unit = TextureSlot::Color.unit();
+            program.set_uniform(self.u_color, color.as_uniform(unit).counted()?)?;
         }
         if let Some(normal) = &instance.normal_map {
-            program.set_uniform(self.u_normal, normal.as_uniform(1)?)?;
+            let unit = TextureSlot::Normal.unit();
+            program.set_uniform(self.u_normal, normal.as_uniform(unit).counted()?)?;
         }
         if let Some(rough_metal) = &instance.roughness_metal {
-            program.set_uniform(self.u_rough_metal, rough_metal.as_uniform(2)?)?;
+            let unit = TextureSlot::RoughMetal.unit();
+            program.set_uniform(self.u_rough_metal, rough_metal.as_uniform(unit).counted()?)?;
         }
         if let Some(emission) = &instance.emission {
-            program.set_uniform(self.u_emission, emission.as_uniform(3)?)?;
+            let unit = TextureSlot::Emission.unit();
+            program.set_uniform(self.u_emission, emission.as_uniform(unit).counted()?)?;
+        }
+        if let Some(clearcoat) = &instance.clearcoat {
+            let unit = TextureSlot::Clearcoat.unit();
+            program.set_uniform(self.u_clearcoat, clearcoat.as_uniform(unit).counted()?)?;
+        }
+        if let Some(anisotropy) = &instance.anisotropy {
+            let unit = TextureSlot::Anisotropy.unit();
+            program.set_uniform(self.u_anisotropy, anisotropy.as_uniform(unit).counted()?)?;
         }
         drop(program);
+        for slot in TextureSlot::ALL {
+            if let Some(state) = instance.sampler(slot) {
+                self.samplers.get(&state).bind(slot.unit());
+            }
+        }
 
+        if instance.uniforms().double_sided {
+            violette::culling(None);
+        }
         for mesh in meshes {
             if let Some(root_bone) = &mesh.root_bone {
-                root_bone.update_buffer(&mut self.bones_uniform)?;
+                root_bone.update_buffer(&mut self.bones_uniform, mesh.skinning)?;
             }
             let program = self.program();
             program.set_uniform(self.u_model, mesh.transform.matrix())?;
+            self.u_morph.bind(&program, &mesh)?;
             mesh.draw(&program, frame, false)?;
         }
         unsafe { gl::BindTexture(gl::TEXTURE_2D, 0) }
+        for slot in TextureSlot::ALL {
+            if instance.sampler(slot).is_some() {
+                Sampler::unbind(slot.unit());
+            }
+        }
+        if instance.uniforms().double_sided {
+            violette::culling(Some(Cull::Back));
+        }
         Ok(())
     }
 
+    fn reload_if_needed(&mut self) {
+        let reloaded =
+            self.reload_watcher
+                .reload_shader("material shader", &self.frag_path, || {
+                    link_program(&self.vert_path, &self.frag_path)
+                });
+        match reloaded {
+            None => {}
+            Some(Ok(program)) => {
+                self.u_color = program.uniform("map_color");
+                self.u_normal = program.uniform("map_normal");
+                self.u_rough_metal = program.uniform("map_rough_metal");
+                self.u_emission = program.uniform("map_emission");
+                self.u_clearcoat = program.uniform("map_clearcoat");
+                self.u_anisotropy = program.uniform("map_anisotropy");
+                self.u_uniforms = program.uniform_block("Uniforms");
+                self.u_model = program.uniform("model");
+                self.u_view = program.uniform_block("View");
+                self.u_bones = program.uniform_block("Bones");
+                self.u_morph = MorphLocations::new(&program);
+                self.u_clip = ClipLocations::new(&program);
+                *self.program.get_mut().unwrap() = program;
+                self.error_program = None;
+            }
+            Some(Err(_)) => {
+                if self.error_program.is_none() {
+                    match ErrorProgram::load(&self.base_path) {
+                        Ok(program) => self.error_program = Some(program),
+                        Err(err) => tracing::error!("Cannot load error program: {:?}", err),
+                    }
+                }
+            }
+        }
+    }
+
+    /// Warn about material instances whose textures are stored in a color space other than the
+    /// one of their slot, see [`MaterialInstance::color_space_mismatches`].
+    pub fn set_color_space_validation(&mut self, enabled: bool) {
+        self.validate_color_spaces = enabled;
+    }
+
     pub fn set_camera_uniform(&self, buffer: &ViewUniformBuffer) -> Result<()> {
         self.program()
             .bind_block(&buffer.slice(0..=0), self.u_view, 0)?;
         Ok(())
     }
 
+    /// Clip the meshes drawn next against the planes, relative to the origin of the view (see
+    /// [`crate::clip::relative_planes`]).
+    pub fn set_clip_planes(&mut self, planes: &[Vec4]) {
+        self.clip_planes.clear();
+        self.clip_planes.extend_from_slice(planes);
+    }
+
     fn program(&self) -> impl '_ + Drop + std::ops::Deref<Target = Program> {
         self.program.read().unwrap()
     }
@@ -217,7 +429,15 @@ pub struct MaterialInstance {
     pub normal_map: Option<Texture<[f32; 3]>>,
     pub roughness_metal: Option<Texture<[f32; 2]>>,
     pub emission: Option<Texture<[f32; 3]>>,
-    uniforms: MaterialUniforms,
+    /// Set with [`Self::set_layer_maps`].
+    pub clearcoat: Option<Texture<[f32; 2]>>,
+    pub anisotropy: Option<Texture<[f32; 3]>>,
+    uniforms: Cell<MaterialUniforms>,
+    /// Sampling of each texture slot, overriding the one of the texture, in [`TextureSlot::ALL`]
+    /// order.
+    samplers: Cell<[Option<SamplerState>; TextureSlot::ALL.len()]>,
+    /// Whether the color spaces of the textures were validated, to only warn once.
+    color_spaces_checked: Cell<bool>,
     buffer: UniformBuffer<Std140MaterialUniforms>,
 }
 
@@ -241,6 +461,16 @@ impl MaterialInstance {
             rough_metal_factor: Vec2::ONE,
             has_emission: emission.is_some(),
             emission_factor: Vec3::ZERO,
+            emission_strength: DEFAULT_EMISSION_STRENGTH,
+            double_sided: false,
+            uv_channels: UVec4::ZERO,
+            clearcoat: 0.,
+            clearcoat_roughness: 0.,
+            anisotropy: 0.,
+            anisotropy_rotation: 0.,
+            has_clearcoat_map: false,
+            has_anisotropy_map: false,
+            layer_uv_channels: UVec2::ZERO,
         };
         let buffer = UniformBuffer::with_data(&[uniforms.as_std140()])?;
         Ok(Self {
@@ -248,19 +478,91 @@ impl MaterialInstance {
             normal_map,
             roughness_metal,
             emission,
-            uniforms,
+            clearcoat: None,
+            anisotropy: None,
+            uniforms: Cell::new(uniforms),
+            samplers: Cell::new([None; TextureSlot::ALL.len()]),
+            color_spaces_checked: Cell::new(false),
             buffer,
         })
     }
 
     pub fn uniforms(&self) -> MaterialUniforms {
-        self.uniforms
+        self.uniforms.get()
     }
 
-    pub fn update_uniforms(&mut self, func: impl FnOnce(&mut MaterialUniforms)) -> Result<()> {
-        func(&mut self.uniforms);
+    pub fn set_uv_channel(&self, slot: TextureSlot, channel: UvChannel) -> Result<()> {
+        self.update_uniforms(|uniforms| {
+            *slot.uv_channel_mut(uniforms) = channel as u32;
+        })
+    }
+
+    /// Modulate the clearcoat and anisotropy layers with textures, replacing the previous ones.
+    pub fn set_layer_maps(
+        &mut self,
+        clearcoat: Option<Texture<[f32; 2]>>,
+        anisotropy: Option<Texture<[f32; 3]>>,
+    ) -> Result<()> {
+        self.update_uniforms(|uniforms| {
+            uniforms.has_clearcoat_map = clearcoat.is_some();
+            uniforms.has_anisotropy_map = anisotropy.is_some();
+        })?;
+        self.clearcoat = clearcoat;
+        self.anisotropy = anisotropy;
+        Ok(())
+    }
+
+    /// Sampling of the texture slot, when it overrides the one of the texture.
+    pub fn sampler(&self, slot: TextureSlot) -> Option<SamplerState> {
+        self.samplers.get()[slot.unit() as usize]
+    }
+
+    /// Sample the texture slot with the given state instead of the filtering and wrapping of its
+    /// texture, or go back to those with `None`. Lets textures be shared by materials sampling them
+    /// differently.
+    pub fn set_sampler(&self, slot: TextureSlot, state: Option<SamplerState>) {
+        let mut samplers = self.samplers.get();
+        samplers[slot.unit() as usize] = state;
+        self.samplers.set(samplers);
+    }
+
+    /// Slots whose texture does not match the color space of the slot: sRGB textures in linear
+    /// slots, and 8-bit linear textures in color slots, which most likely hold sRGB colors uploaded
+    /// without their color space. Float textures in color slots are taken as linear HDR colors.
+    pub fn color_space_mismatches(&self) -> Vec<TextureSlot> {
+        let formats = [
+            self.color.as_ref().map(color::internal_format),
+            self.normal_map.as_ref().map(color::internal_format),
+            self.roughness_metal.as_ref().map(color::internal_format),
+            self.emission.as_ref().map(color::internal_format),
+            self.clearcoat.as_ref().map(color::internal_format),
+            self.anisotropy.as_ref().map(color::internal_format),
+        ];
+        TextureSlot::ALL
+            .into_iter()
+            .zip(formats)
+            .filter(|(slot, format)| match (slot.color_space(), format) {
+                (_, None) => false,
+                (ColorSpace::Linear, Some(format)) => {
+                    ColorSpace::of_internal_format(*format) == ColorSpace::Srgb
+                }
+                (ColorSpace::Srgb, Some(format)) => color::is_low_precision(*format),
+            })
+            .map(|(slot, _)| slot)
+            .collect()
+    }
+
+    /// Update the material parameters. The uniform buffer is only re-uploaded when the parameters
+    /// actually changed, so this can be called every frame.
+    pub fn update_uniforms(&self, func: impl FnOnce(&mut MaterialUniforms)) -> Result<()> {
+        let mut uniforms = self.uniforms.get();
+        func(&mut uniforms);
+        if uniforms == self.uniforms.get() {
+            return Ok(());
+        }
+        self.uniforms.set(uniforms);
         let mut slice = self.buffer.slice(0..=0);
-        slice.set(0, &self.uniforms.as_std140())?;
+        slice.set(0, &uniforms.as_std140())?;
         Ok(())
     }
 }
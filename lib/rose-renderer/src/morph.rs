@@ -0,0 +1,187 @@
+//! Morph targets (blend shapes), deforming meshes by weighted offsets of their vertices, e.g. for
+//! facial animation.
+//!
+//! The offsets of every target are packed in a float texture read by the mesh vertex shader, see
+//! [`pack_deltas`]. Targets are blended before the mesh is skinned by its bones.
+
+use std::{cell::RefCell, num::NonZeroU32};
+
+use eyre::Result;
+use glam::{UVec2, Vec3};
+
+use violette::{
+    program::{Program, UniformLocation},
+    texture::{Dimension, SampleMode, Texture},
+};
+
+use crate::Mesh;
+
+/// Number of targets the mesh vertex shader blends; targets past it are ignored.
+pub const MAX_MORPH_TARGETS: usize = 8;
+/// Width of the texture of the deltas, in texels. Same as `MORPH_TEXTURE_WIDTH` in the shader.
+pub const MORPH_TEXTURE_WIDTH: u32 = 1024;
+/// Texture unit of the deltas, above the units used by materials.
+const MORPH_TEXTURE_UNIT: u32 = 15;
+
+/// Offsets of the vertices of a mesh, by vertex index. Missing offsets are zero.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MorphTarget {
+    pub positions: Vec<Vec3>,
+    pub normals: Vec<Vec3>,
+}
+
+/// Pack the deltas of the targets in rows of [`MORPH_TEXTURE_WIDTH`] texels, returning the size of
+/// the texture and its texels. The position deltas of target `t` start at texel
+/// `2 * t * vertex_count`, followed by its normal deltas.
+pub fn pack_deltas(vertex_count: usize, targets: &[MorphTarget]) -> (UVec2, Vec<[f32; 3]>) {
+    let texel_count = (2 * vertex_count * targets.len()).max(1) as u32;
+    let size = UVec2::new(
+        MORPH_TEXTURE_WIDTH,
+        (texel_count + MORPH_TEXTURE_WIDTH - 1) / MORPH_TEXTURE_WIDTH,
+    );
+    let mut texels = Vec::with_capacity((size.x * size.y) as usize);
+    for target in targets {
+        for deltas in [&target.positions, &target.normals] {
+            texels.extend(
+                (0..vertex_count).map(|i| deltas.get(i).copied().unwrap_or_default().to_array()),
+            );
+        }
+    }
+    texels.resize((size.x * size.y) as usize, [0.; 3]);
+    (size, texels)
+}
+
+/// Morph targets of a [`Mesh`] on the GPU, along with their current weights.
+#[derive(Debug)]
+pub struct MorphTargets {
+    texture: Texture<[f32; 3]>,
+    vertex_count: u32,
+    count: usize,
+    weights: RefCell<Vec<f32>>,
+}
+
+impl MorphTargets {
+    pub fn new(vertex_count: usize, targets: &[MorphTarget]) -> Result<Self> {
+        if targets.len() > MAX_MORPH_TARGETS {
+            tracing::warn!(
+                message = "Too many morph targets, ignoring the last ones",
+                len = targets.len(),
+                max = MAX_MORPH_TARGETS
+            );
+        }
+        let targets = &targets[..targets.len().min(MAX_MORPH_TARGETS)];
+        let (size, texels) = pack_deltas(vertex_count, targets);
+        let texture = Texture::new(
+            NonZeroU32::new(size.x).unwrap(),
+            NonZeroU32::new(size.y).unwrap(),
+            NonZeroU32::new(1).unwrap(),
+            Dimension::D2,
+        );
+        texture.filter_min(SampleMode::Nearest)?;
+        texture.filter_mag(SampleMode::Nearest)?;
+        texture.reserve_memory()?;
+        texture.set_data(&texels)?;
+        Ok(Self {
+            texture,
+            vertex_count: vertex_count as u32,
+            count: targets.len(),
+            weights: RefCell::new(vec![0.; targets.len()]),
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    pub fn weights(&self) -> Vec<f32> {
+        self.weights.borrow().clone()
+    }
+
+    /// Set the weights of the targets, in order. Missing weights are zero, and extra ones ignored.
+    pub fn set_weights(&self, weights: &[f32]) {
+        let mut current = self.weights.borrow_mut();
+        for (i, weight) in current.iter_mut().enumerate() {
+            *weight = weights.get(i).copied().unwrap_or(0.);
+        }
+    }
+}
+
+/// Uniforms of the mesh vertex shader reading the morph targets.
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct MorphLocations {
+    deltas: UniformLocation,
+    count: UniformLocation,
+    vertex_count: UniformLocation,
+    weights: [UniformLocation; MAX_MORPH_TARGETS],
+}
+
+impl MorphLocations {
+    pub(crate) fn new(program: &Program) -> Self {
+        Self {
+            deltas: program.uniform("morph_deltas"),
+            count: program.uniform("morph_count"),
+            vertex_count: program.uniform("morph_vertex_count"),
+            weights: std::array::from_fn(|i| program.uniform(&format!("morph_weights[{}]", i))),
+        }
+    }
+
+    /// Set the morph targets of the mesh on the program, or disable morphing when it has none, as
+    /// the uniforms are kept between draws.
+    pub(crate) fn bind(&self, program: &Program, mesh: &Mesh) -> Result<()> {
+        let Some(morph) = &mesh.morph_targets else {
+            program.set_uniform(self.count, 0)?;
+            return Ok(());
+        };
+        program.set_uniform(
+            self.deltas,
+            morph.texture.as_uniform(MORPH_TEXTURE_UNIT).counted()?,
+        )?;
+        program.set_uniform(self.count, morph.count as i32)?;
+        program.set_uniform(self.vertex_count, morph.vertex_count as i32)?;
+        for (location, weight) in self.weights.iter().zip(morph.weights.borrow().iter()) {
+            program.set_uniform(*location, *weight)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packs_positions_then_normals_per_target() {
+        let targets = [
+            MorphTarget {
+                positions: vec![Vec3::X, Vec3::Y],
+                normals: vec![Vec3::Z],
+            },
+            MorphTarget {
+                positions: vec![Vec3::NEG_X, Vec3::NEG_Y],
+                normals: vec![],
+            },
+        ];
+        let (size, texels) = pack_deltas(2, &targets);
+        assert_eq!(size, UVec2::new(MORPH_TEXTURE_WIDTH, 1));
+        assert_eq!(texels.len(), MORPH_TEXTURE_WIDTH as usize);
+        assert_eq!(
+            texels[..8],
+            [
+                [1., 0., 0.],
+                [0., 1., 0.],
+                [0., 0., 1.],
+                [0., 0., 0.],
+                [-1., 0., 0.],
+                [0., -1., 0.],
+                [0., 0., 0.],
+                [0., 0., 0.],
+            ]
+        );
+        let (size, _) = pack_deltas(MORPH_TEXTURE_WIDTH as usize, &targets);
+        assert_eq!(size.y, 4);
+    }
+}
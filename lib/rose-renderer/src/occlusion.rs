@@ -0,0 +1,180 @@
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap, HashSet},
+    hash::{Hash, Hasher},
+    rc::Rc,
+};
+
+use eyre::Result;
+
+use rose_core::{
+    camera::ViewUniformBuffer, transform::Transformed, utils::reload_watcher::ReloadWatcher,
+};
+use violette::{
+    framebuffer::{DepthTestFunction, Framebuffer},
+    gl, Cull,
+};
+
+use crate::{depth_prepass::DepthPrepass, Mesh};
+
+/// `GL_ANY_SAMPLES_PASSED` query object.
+#[derive(Debug)]
+struct Query(u32);
+
+impl Query {
+    fn new() -> Self {
+        let mut id = 0;
+        unsafe { gl::GenQueries(1, &mut id) };
+        Self(id)
+    }
+
+    fn is_available(&self) -> bool {
+        let mut available = 0;
+        unsafe { gl::GetQueryObjectuiv(self.0, gl::QUERY_RESULT_AVAILABLE, &mut available) };
+        available != 0
+    }
+
+    fn any_samples_passed(&self) -> bool {
+        let mut result = 0;
+        unsafe { gl::GetQueryObjectuiv(self.0, gl::QUERY_RESULT, &mut result) };
+        result != 0
+    }
+}
+
+impl Drop for Query {
+    fn drop(&mut self) {
+        unsafe { gl::DeleteQueries(1, &self.0) };
+    }
+}
+
+/// Occlusion culling with hardware occlusion queries and temporal reuse.
+///
+/// After the G-Buffer is filled, every tested mesh is drawn depth-only against the scene depth
+/// inside a query. Results are read back without stalling on later frames, and meshes found
+/// hidden are skipped until a query finds them visible again. Meshes therefore appear one frame
+/// late when they get uncovered.
+///
+/// Meshes are identified by their pointer and transform, which makes moving meshes always visible.
+#[derive(Debug)]
+pub struct OcclusionCulling {
+    program: DepthPrepass,
+    visible: HashMap<u64, bool>,
+    pending: HashMap<u64, Query>,
+    culled: usize,
+}
+
+impl OcclusionCulling {
+    pub fn new(reload_watcher: &ReloadWatcher) -> Result<Self> {
+        Ok(Self {
+            program: DepthPrepass::new(reload_watcher)?,
+            visible: HashMap::new(),
+            pending: HashMap::new(),
+            culled: 0,
+        })
+    }
+
+    fn key(mesh: &Transformed<Rc<Mesh>>) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        (Rc::as_ptr(mesh) as usize).hash(&mut hasher);
+        for x in mesh.transform.matrix().to_cols_array() {
+            x.to_bits().hash(&mut hasher);
+        }
+        hasher.finish()
+    }
+
+    /// Read back the results of the queries which have completed, returning whether the
+    /// visibility of any mesh changed. Call once per frame before [`Self::is_visible`].
+    pub fn fetch_results(&mut self) -> bool {
+        self.culled = 0;
+        let visible = &mut self.visible;
+        let mut changed = false;
+        self.pending.retain(|key, query| {
+            if !query.is_available() {
+                return true;
+            }
+            let any_samples_passed = query.any_samples_passed();
+            let was_visible = visible.insert(*key, any_samples_passed).unwrap_or(true);
+            changed |= was_visible != any_samples_passed;
+            false
+        });
+        changed
+    }
+
+    /// Visibility of the mesh from its last completed query; meshes not tested yet are visible.
+    pub fn is_visible(&mut self, mesh: &Transformed<Rc<Mesh>>) -> bool {
+        let visible = self.visible.get(&Self::key(mesh)).copied().unwrap_or(true);
+        if !visible {
+            self.culled += 1;
+        }
+        visible
+    }
+
+    /// Number of meshes found hidden by [`Self::is_visible`] since the last fetch.
+    pub fn culled(&self) -> usize {
+        self.culled
+    }
+
+    /// Test the meshes against the depth of the framebuffer, given in groups along with whether
+    /// they are double-sided. `reversed_depth` tells whether the view uses reversed depth, where
+    /// closer fragments have a greater depth. Meshes no longer submitted are forgotten.
+    #[tracing::instrument(skip_all)]
+    pub fn query<'a>(
+        &mut self,
+        frame: &Framebuffer,
+        view: &ViewUniformBuffer,
+        reversed_depth: bool,
+        groups: impl IntoIterator<Item = (bool, &'a [Transformed<Rc<Mesh>>])>,
+    ) -> Result<()> {
+        Framebuffer::enable_depth_test(DepthTestFunction::Less);
+        // Visible meshes are already in the depth buffer and need to pass on equal depth
+        let depth_func = if reversed_depth {
+            gl::GEQUAL
+        } else {
+            gl::LEQUAL
+        };
+        unsafe {
+            gl::DepthFunc(depth_func);
+            gl::DepthMask(gl::FALSE);
+            gl::ColorMask(gl::FALSE, gl::FALSE, gl::FALSE, gl::FALSE);
+        }
+        let result = self.query_groups(frame, view, groups);
+        unsafe {
+            gl::ColorMask(gl::TRUE, gl::TRUE, gl::TRUE, gl::TRUE);
+            gl::DepthMask(gl::TRUE);
+        }
+        result
+    }
+
+    fn query_groups<'a>(
+        &mut self,
+        frame: &Framebuffer,
+        view: &ViewUniformBuffer,
+        groups: impl IntoIterator<Item = (bool, &'a [Transformed<Rc<Mesh>>])>,
+    ) -> Result<()> {
+        self.program.bind(view)?;
+        let mut submitted = HashSet::new();
+        for (double_sided, meshes) in groups {
+            if double_sided {
+                violette::culling(None);
+            }
+            for mesh in meshes {
+                let key = Self::key(mesh);
+                // Wait for the previous query of the mesh rather than piling up new ones
+                if !submitted.insert(key) || self.pending.contains_key(&key) {
+                    continue;
+                }
+                let query = Query::new();
+                unsafe { gl::BeginQuery(gl::ANY_SAMPLES_PASSED, query.0) };
+                let result = self.program.draw_mesh(frame, mesh);
+                unsafe { gl::EndQuery(gl::ANY_SAMPLES_PASSED) };
+                result?;
+                self.pending.insert(key, query);
+            }
+            if double_sided {
+                violette::culling(Some(Cull::Back));
+            }
+        }
+        self.visible.retain(|key, _| submitted.contains(key));
+        self.pending.retain(|key, _| submitted.contains(key));
+        Ok(())
+    }
+}
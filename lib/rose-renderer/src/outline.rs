@@ -0,0 +1,189 @@
+//! Selection outline. The selected meshes are drawn into a mask texture, which a screen pass
+//! dilates by the outline width and blends over the final image. Unlike a stencil outline, this
+//! needs no stencil attachment, which violette framebuffers do not support, and keeps the width
+//! in pixels regardless of the shape of the meshes.
+
+use std::num::NonZeroU32;
+
+use eyre::{Context, Result};
+use glam::{UVec2, Vec4};
+
+use rose_core::utils::draw_counters::CountBind;
+use rose_core::{
+    camera::ViewUniformBuffer, screen_draw::ScreenDraw, transform::Transformed,
+    utils::reload_watcher::ReloadWatcher,
+};
+use violette::{
+    framebuffer::{Blend, ClearBuffer, Framebuffer},
+    program::{Program, UniformBlockIndex, UniformLocation},
+    texture::{Dimension, SampleMode, Texture},
+};
+
+use crate::{shader_material::link_program, Mesh};
+
+#[derive(Debug, Copy, Clone)]
+pub struct OutlineParams {
+    /// Display color of the outline, alpha-blended over the final image.
+    pub color: Vec4,
+    /// Width of the outline, in pixels.
+    pub width: f32,
+    /// Opacity of the color over the meshes themselves, 0 to only draw the outline.
+    pub fill: f32,
+}
+
+impl Default for OutlineParams {
+    fn default() -> Self {
+        Self {
+            color: Vec4::new(1., 0.6, 0.1, 1.),
+            width: 2.,
+            fill: 0.,
+        }
+    }
+}
+
+impl OutlineParams {
+    /// Subtle highlight of the mesh under the cursor.
+    pub fn hover() -> Self {
+        Self {
+            color: Vec4::new(1., 1., 1., 0.5),
+            width: 1.,
+            fill: 0.15,
+        }
+    }
+
+    #[cfg(feature = "debug-ui")]
+    pub fn ui(&mut self, ui: &mut egui::Ui, id: &str) {
+        use egui::{DragValue, Grid};
+
+        Grid::new(id).num_columns(2).show(ui, |ui| {
+            let color_label = ui.label("Color").id;
+            let mut color = self.color.to_array();
+            ui.color_edit_button_rgba_unmultiplied(&mut color)
+                .labelled_by(color_label);
+            self.color = Vec4::from_array(color);
+            ui.end_row();
+
+            let width_label = ui.label("Width").id;
+            ui.add(
+                DragValue::new(&mut self.width)
+                    .clamp_range(0.5..=16.)
+                    .speed(0.1)
+                    .suffix(" px"),
+            )
+            .labelled_by(width_label);
+            ui.end_row();
+
+            let fill_label = ui.label("Fill").id;
+            ui.add(
+                DragValue::new(&mut self.fill)
+                    .clamp_range(0.0..=1.)
+                    .speed(0.01),
+            )
+            .labelled_by(fill_label);
+            ui.end_row();
+        });
+    }
+}
+
+/// Draws an outline around a set of meshes, by rendering their silhouette into a mask and dilating
+/// it over the final image.
+#[derive(Debug)]
+pub struct Outline {
+    mask: Texture<f32>,
+    fbo: Framebuffer,
+    mask_program: Program,
+    u_mask_view: UniformBlockIndex,
+    u_mask_model: UniformLocation,
+    draw: ScreenDraw,
+    u_mask: UniformLocation,
+    u_color: UniformLocation,
+    u_width: UniformLocation,
+    u_fill: UniformLocation,
+}
+
+impl Outline {
+    pub fn new(size: UVec2, reload_watcher: &ReloadWatcher) -> Result<Self> {
+        let Some(width) = NonZeroU32::new(size.x) else { eyre::bail!("Zero width resize"); };
+        let Some(height) = NonZeroU32::new(size.y) else { eyre::bail!("Zero height resize"); };
+        let mask = Texture::new(width, height, NonZeroU32::new(1).unwrap(), Dimension::D2);
+        mask.filter_min(SampleMode::Nearest)?;
+        mask.filter_mag(SampleMode::Nearest)?;
+        mask.reserve_memory()?;
+
+        let fbo = Framebuffer::new();
+        fbo.attach_color(0, mask.mipmap(0).unwrap())?;
+        fbo.enable_buffers([0])?;
+        fbo.assert_complete()?;
+
+        let (mask_program, _) = link_program(
+            &reload_watcher.base_path().join("mesh/mesh.vert.glsl"),
+            &reload_watcher.base_path().join("mesh/mask.frag.glsl"),
+        )
+        .context("Loading outline mask program")?;
+        let u_mask_view = mask_program.uniform_block("View");
+        let u_mask_model = mask_program.uniform("model");
+
+        let draw = ScreenDraw::load("screen/outline.glsl", reload_watcher)
+            .context("Loading outline shader")?;
+        let program = draw.program();
+        let u_mask = program.uniform("mask");
+        let u_color = program.uniform("color");
+        let u_width = program.uniform("width");
+        let u_fill = program.uniform("fill");
+        drop(program);
+
+        Ok(Self {
+            mask,
+            fbo,
+            mask_program,
+            u_mask_view,
+            u_mask_model,
+            draw,
+            u_mask,
+            u_color,
+            u_width,
+            u_fill,
+        })
+    }
+
+    pub fn resize(&mut self, size: UVec2) -> Result<()> {
+        let Some(width) = NonZeroU32::new(size.x) else { eyre::bail!("Zero width resize"); };
+        let Some(height) = NonZeroU32::new(size.y) else { eyre::bail!("Zero height resize"); };
+        self.mask
+            .clear_resize(width, height, NonZeroU32::new(1).unwrap())?;
+        Ok(())
+    }
+
+    #[tracing::instrument(skip_all)]
+    pub fn draw<'a>(
+        &self,
+        frame: &Framebuffer,
+        view: &ViewUniformBuffer,
+        params: OutlineParams,
+        meshes: impl IntoIterator<Item = Transformed<&'a Mesh>>,
+    ) -> Result<()> {
+        Framebuffer::disable_depth_test();
+        Framebuffer::disable_blending();
+        Framebuffer::clear_color([0., 0., 0., 0.]);
+        self.fbo.do_clear(ClearBuffer::COLOR);
+        self.mask_program
+            .bind_block(&view.slice(0..=0), self.u_mask_view, 0)?;
+        for mesh in meshes {
+            self.mask_program
+                .set_uniform(self.u_mask_model, mesh.transform.matrix())?;
+            mesh.draw(&self.mask_program, &self.fbo, false)?;
+        }
+
+        {
+            let program = self.draw.program();
+            program.set_uniform(self.u_mask, self.mask.as_uniform(0).counted()?)?;
+            program.set_uniform(self.u_color, params.color)?;
+            program.set_uniform(self.u_width, params.width)?;
+            program.set_uniform(self.u_fill, params.fill)?;
+        }
+        Framebuffer::enable_blending(Blend::SrcAlpha, Blend::OneMinusSrcAlpha);
+        self.draw.draw(frame)?;
+        Framebuffer::disable_blending();
+        Ok(())
+    }
+}
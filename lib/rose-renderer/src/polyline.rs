@@ -0,0 +1,268 @@
+//! Thick lines, for trajectories, splines and route guides.
+//!
+//! Lines are submitted every frame through [`crate::Renderer::submit_polyline`], and drawn unlit
+//! over the lit scene, all in a single draw call. They are expanded into quads in the vertex
+//! shader, so that their width is in pixels regardless of the distance to the camera.
+
+use std::path::PathBuf;
+
+use bytemuck::{Pod, Zeroable};
+use eyre::{Context, Result};
+use glam::{Vec3, Vec4};
+
+use rose_core::utils::draw_counters::CountBind;
+use rose_core::{
+    camera::ViewUniformBuffer,
+    mesh::Mesh,
+    utils::reload_watcher::{ReloadFileProxy, ReloadWatcher},
+};
+use violette::{
+    buffer::BufferUsageHint,
+    framebuffer::{Blend, Framebuffer},
+    program::{Program, UniformBlockIndex, UniformLocation},
+    texture::{DepthStencil, Texture},
+    Cull,
+};
+use violette_derive::VertexAttributes;
+
+use crate::shader_material::link_program;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PolylinePoint {
+    pub position: Vec3,
+    /// Width of the line at this point, in pixels.
+    pub width: f32,
+    /// Linear RGBA color of the line at this point.
+    pub color: Vec4,
+}
+
+impl PolylinePoint {
+    pub fn new(position: Vec3, width: f32, color: Vec4) -> Self {
+        Self {
+            position,
+            width,
+            color,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Pod, Zeroable, VertexAttributes)]
+#[repr(C)]
+pub struct PolylineVertex {
+    pub position: Vec3,
+    pub prev: Vec3,
+    pub next: Vec3,
+    /// Side of the line the vertex is pushed towards, -1 or 1.
+    pub side: f32,
+    pub width: f32,
+    #[attribute(ignore)]
+    _padding: f32,
+    pub color: Vec4,
+}
+
+/// Append the vertices and indices of the line through `points` to the buffers. Consecutive
+/// duplicate points are skipped, and lines of less than 2 points are ignored.
+pub fn expand_polyline(
+    points: &[PolylinePoint],
+    vertices: &mut Vec<PolylineVertex>,
+    indices: &mut Vec<u32>,
+) {
+    let mut points = points.to_vec();
+    points.dedup_by(|a, b| a.position == b.position);
+    // Lines folding back onto themselves have no side to push the vertices of the fold towards,
+    // so the fold ends one strip and starts the next
+    let mut start = 0;
+    for i in 1..points.len().saturating_sub(1) {
+        let before = (points[i].position - points[i - 1].position).normalize();
+        let after = (points[i + 1].position - points[i].position).normalize();
+        if before.dot(after) < -1. + 1e-4 {
+            expand_strip(&points[start..=i], vertices, indices);
+            start = i;
+        }
+    }
+    expand_strip(&points[start..], vertices, indices);
+}
+
+/// Vertices and indices of a line without duplicate points or folds.
+fn expand_strip(
+    points: &[PolylinePoint],
+    vertices: &mut Vec<PolylineVertex>,
+    indices: &mut Vec<u32>,
+) {
+    if points.len() < 2 {
+        return;
+    }
+    let base = vertices.len() as u32;
+    for (i, point) in points.iter().enumerate() {
+        // Endpoints are their own neighbor, which the vertex shader checks for
+        let prev = i.checked_sub(1).map_or(point, |i| &points[i]);
+        let next = points.get(i + 1).unwrap_or(point);
+        vertices.extend([-1., 1.].map(|side| PolylineVertex {
+            position: point.position,
+            prev: prev.position,
+            next: next.position,
+            side,
+            width: point.width,
+            _padding: 0.,
+            color: point.color,
+        }));
+    }
+    for segment in 0..points.len() as u32 - 1 {
+        let i = base + 2 * segment;
+        indices.extend([i, i + 1, i + 3, i, i + 3, i + 2]);
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+struct PolylineLocations {
+    view: UniformBlockIndex,
+    scene_depth: UniformLocation,
+}
+
+impl PolylineLocations {
+    fn new(program: &Program) -> Self {
+        Self {
+            view: program.uniform_block("View"),
+            scene_depth: program.uniform("scene_depth"),
+        }
+    }
+}
+
+/// Forward pass drawing the lines queued this frame over the lit scene.
+#[derive(Debug)]
+pub struct Polylines {
+    program: Program,
+    locations: PolylineLocations,
+    proxy: ReloadFileProxy,
+    vertex: PathBuf,
+    fragment: PathBuf,
+    mesh: Mesh<PolylineVertex>,
+    vertices: Vec<PolylineVertex>,
+    indices: Vec<u32>,
+}
+
+impl Polylines {
+    pub fn new(reload_watcher: &ReloadWatcher) -> Result<Self> {
+        let vertex = reload_watcher
+            .base_path()
+            .join("polyline/polyline.vert.glsl");
+        let fragment = reload_watcher
+            .base_path()
+            .join("polyline/polyline.frag.glsl");
+        let (program, files) =
+            link_program(&vertex, &fragment).context("Loading polyline program")?;
+        Ok(Self {
+            locations: PolylineLocations::new(&program),
+            program,
+            proxy: reload_watcher.proxy(files.iter().map(|p| p.as_path())),
+            vertex,
+            fragment,
+            mesh: Mesh::empty()?,
+            vertices: vec![],
+            indices: vec![],
+        })
+    }
+
+    /// Queue the line through the points for the next draw.
+    pub fn push(&mut self, points: &[PolylinePoint]) {
+        expand_polyline(points, &mut self.vertices, &mut self.indices);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.vertices.clear();
+        self.indices.clear();
+    }
+
+    fn reload_if_needed(&mut self) {
+        let reloaded = self
+            .proxy
+            .reload_shader("polyline shader", &self.fragment, || {
+                link_program(&self.vertex, &self.fragment)
+            });
+        if let Some(Ok(program)) = reloaded {
+            self.locations = PolylineLocations::new(&program);
+            self.program = program;
+        }
+    }
+
+    /// Blend the queued lines over `frame`, hiding them behind the geometry of `depth`, and clear
+    /// the queue.
+    #[tracing::instrument(skip_all)]
+    pub fn draw(
+        &mut self,
+        frame: &Framebuffer,
+        view: &ViewUniformBuffer,
+        depth: &Texture<DepthStencil<f32, ()>>,
+    ) -> Result<()> {
+        self.reload_if_needed();
+        if self.is_empty() {
+            return Ok(());
+        }
+        self.mesh
+            .vertices()
+            .set(&self.vertices, BufferUsageHint::Stream)?;
+        self.mesh
+            .indices()
+            .set(&self.indices, BufferUsageHint::Stream)?;
+        let program = &self.program;
+        program.bind_block(&view.slice(0..=0), self.locations.view, 0)?;
+        program.set_uniform(self.locations.scene_depth, depth.as_uniform(0).counted()?)?;
+        // The winding of the quads depends on the direction of the lines
+        violette::culling(None);
+        Framebuffer::enable_blending(Blend::SrcAlpha, Blend::OneMinusSrcAlpha);
+        self.mesh.draw(program, frame, false)?;
+        Framebuffer::disable_blending();
+        violette::culling(Some(Cull::Back));
+        self.clear();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expands_points_into_quads() {
+        let point = |x: f32| PolylinePoint::new(Vec3::new(x, 0., 0.), 2., Vec4::ONE);
+        let (mut vertices, mut indices) = (vec![], vec![]);
+        expand_polyline(&[point(0.)], &mut vertices, &mut indices);
+        assert!(vertices.is_empty());
+        expand_polyline(
+            &[point(0.), point(1.), point(1.), point(2.)],
+            &mut vertices,
+            &mut indices,
+        );
+        assert_eq!(vertices.len(), 6);
+        assert_eq!(indices, [0, 1, 3, 0, 3, 2, 2, 3, 5, 2, 5, 4]);
+        assert_eq!(vertices[0].prev, vertices[0].position);
+        assert_eq!(vertices[2].prev, Vec3::ZERO);
+        assert_eq!(vertices[2].next, Vec3::new(2., 0., 0.));
+        assert_eq!(vertices[5].next, vertices[5].position);
+    }
+
+    #[test]
+    fn splits_lines_at_folds() {
+        let point = |x: f32| PolylinePoint::new(Vec3::new(x, 0., 0.), 2., Vec4::ONE);
+        let (mut vertices, mut indices) = (vec![], vec![]);
+        expand_polyline(
+            &[point(0.), point(2.), point(1.), point(1.), point(3.)],
+            &mut vertices,
+            &mut indices,
+        );
+        // Three strips, each fold being the end of one and the start of the next
+        assert_eq!(vertices.len(), 12);
+        assert_eq!(indices.len(), 18);
+        for vertex in &vertices {
+            let to_prev = vertex.position - vertex.prev;
+            let to_next = vertex.next - vertex.position;
+            assert!(to_prev.dot(to_next) >= 0., "no vertex is on a fold");
+        }
+        assert_eq!(vertices[2].next, vertices[2].position);
+        assert_eq!(vertices[4].prev, vertices[4].position);
+    }
+}
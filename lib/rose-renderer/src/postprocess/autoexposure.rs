@@ -1,92 +1,354 @@
-use std::num::NonZeroU32;
+use std::any::Any;
+use std::time::Duration;
 
 use eyre::Result;
-use glam::{UVec2, Vec3};
+use glam::{UVec2, Vec2, Vec3};
 
+use rose_core::readback::{Readback, ReadbackFormat};
 use rose_core::screen_draw::ScreenDraw;
 use rose_core::utils::reload_watcher::ReloadWatcher;
+use violette::framebuffer::Framebuffer;
 use violette::program::UniformLocation;
-use violette::{
-    framebuffer::Framebuffer,
-    texture::{Dimension, SampleMode, Texture},
+
+use crate::frame_graph::{
+    GraphPass, GraphTexture, PassBuilder, PassContext, TextureDesc, TransientFormat,
 };
 
+const HISTOGRAM_BINS: usize = 128;
+const MIN_LOG_LUMINANCE: f32 = -12.;
+const MAX_LOG_LUMINANCE: f32 = 20.;
+/// Largest side of the luminance mip read back for building the histogram.
+const HISTOGRAM_RESOLUTION: u32 = 64;
+/// Smallest change of exposure, in EV, worth rendering a new frame for.
+const MIN_VISIBLE_EV_CHANGE: f32 = 1e-3;
+
+/// Which parts of the frame contribute to the measured scene luminance.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
+pub enum MeteringMode {
+    /// Every pixel contributes equally.
+    Average,
+    /// Pixels contribute less the further they are from the center of the frame.
+    CenterWeighted,
+    /// Only a small area at the center of the frame contributes.
+    Spot,
+}
+
+impl MeteringMode {
+    fn weight(self, uv: Vec2) -> f32 {
+        let dist = uv.distance(Vec2::splat(0.5));
+        match self {
+            Self::Average => 1.,
+            Self::CenterWeighted => (-dist * dist / 0.08).exp(),
+            Self::Spot => (dist < 0.1) as u8 as f32,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serialize", serde(default))]
+pub struct AutoExposureParams {
+    pub metering: MeteringMode,
+    /// Fraction of the (weighted) darkest pixels ignored when measuring the scene luminance.
+    pub low_percentile: f32,
+    /// Fraction of the (weighted) pixels, starting from the darkest, considered when measuring the
+    /// scene luminance; pixels brighter than this are ignored.
+    pub high_percentile: f32,
+    /// Adaptation speed when the scene gets brighter, in inverse seconds.
+    pub speed_up: f32,
+    /// Adaptation speed when the scene gets darker, in inverse seconds.
+    pub speed_down: f32,
+}
+
+impl Default for AutoExposureParams {
+    fn default() -> Self {
+        Self {
+            metering: MeteringMode::CenterWeighted,
+            low_percentile: 0.1,
+            high_percentile: 0.9,
+            speed_up: 3.,
+            speed_down: 1.,
+        }
+    }
+}
+
+impl AutoExposureParams {
+    #[cfg(feature = "debug-ui")]
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        use egui::{DragValue, Grid};
+
+        Grid::new("postprocess-auto-exposure")
+            .num_columns(2)
+            .show(ui, |ui| {
+                let metering_label = ui.label("Metering").id;
+                ui.horizontal(|ui| {
+                    ui.selectable_value(&mut self.metering, MeteringMode::Average, "Average");
+                    ui.selectable_value(
+                        &mut self.metering,
+                        MeteringMode::CenterWeighted,
+                        "Center-weighted",
+                    );
+                    ui.selectable_value(&mut self.metering, MeteringMode::Spot, "Spot");
+                })
+                .response
+                .labelled_by(metering_label);
+                ui.end_row();
+
+                let low_label = ui.label("Low percentile").id;
+                ui.add(
+                    DragValue::new(&mut self.low_percentile)
+                        .clamp_range(0.0..=self.high_percentile)
+                        .speed(0.01),
+                )
+                .labelled_by(low_label);
+                ui.end_row();
+
+                let high_label = ui.label("High percentile").id;
+                ui.add(
+                    DragValue::new(&mut self.high_percentile)
+                        .clamp_range(self.low_percentile..=1.0)
+                        .speed(0.01),
+                )
+                .labelled_by(high_label);
+                ui.end_row();
+
+                let up_label = ui.label("Speed up").id;
+                ui.add(
+                    DragValue::new(&mut self.speed_up)
+                        .clamp_range(0.01..=100.)
+                        .speed(0.05)
+                        .suffix(" /s"),
+                )
+                .labelled_by(up_label);
+                ui.end_row();
+
+                let down_label = ui.label("Speed down").id;
+                ui.add(
+                    DragValue::new(&mut self.speed_down)
+                        .clamp_range(0.01..=100.)
+                        .speed(0.05)
+                        .suffix(" /s"),
+                )
+                .labelled_by(down_label);
+                ui.end_row();
+            });
+    }
+}
+
+/// Measure of the scene luminance for the auto exposure, from a luminance transient of the post
+/// processing [`FrameGraph`](crate::frame_graph::FrameGraph) read back to the CPU.
 #[derive(Debug)]
 pub struct AutoExposure {
+    pub params: AutoExposureParams,
     screen_draw: ScreenDraw,
     uniform_in_texture: UniformLocation,
-    fbo: Framebuffer,
-    target: Texture<f32>,
     avg_luminance: f32,
+    /// Last metered scene luminance, which the average luminance adapts towards every frame.
+    target_luminance: Option<f32>,
+    /// Luminance mipmap being read back, metered on a later frame to not stall the GPU.
+    readback: Option<Readback>,
 }
 
 impl AutoExposure {
-    pub fn new(size: UVec2, reload_watcher: &ReloadWatcher) -> Result<Self> {
-        let Some(width) = NonZeroU32::new(size.x) else {
-            eyre::bail!("Non zero size");
-        };
-        let Some(height) = NonZeroU32::new(size.y) else {
-            eyre::bail!("Non zero size");
-        };
-        let depth = unsafe { NonZeroU32::new_unchecked(1) };
+    /// Frame graph transient the luminance is written to before being read back.
+    pub const LUMINANCE: &'static str = "luminance";
+
+    pub fn new(reload_watcher: &ReloadWatcher) -> Result<Self> {
         let screen_draw = ScreenDraw::load("screen/luminance-estimate.glsl", reload_watcher)?;
-        let target = Texture::new(width, height, depth, Dimension::D2);
-        target.filter_mag(SampleMode::Linear)?;
-        target.filter_min(SampleMode::Linear)?;
-        target.reserve_memory()?;
-        let fbo = Framebuffer::new();
-        fbo.attach_color(0, target.mipmap(0).unwrap())?;
-        fbo.assert_complete()?;
         let uniform_in_texture = screen_draw.program().uniform("in_texture");
         Ok(Self {
+            params: AutoExposureParams::default(),
             screen_draw,
             uniform_in_texture,
-            fbo,
-            target,
             avg_luminance: 0.5,
+            target_luminance: None,
+            readback: None,
         })
     }
 
-    pub fn resize(&mut self, size: UVec2) -> Result<()> {
-        let Some(width) = NonZeroU32::new(size.x) else {
-            eyre::bail!("Non zero size");
-        };
-        let Some(height) = NonZeroU32::new(size.y) else {
-            eyre::bail!("Non zero size");
-        };
-        let depth = unsafe { NonZeroU32::new_unchecked(1) };
-        self.target.clear_resize(width, height, depth)?;
-        Ok(())
-    }
-
     pub fn average_luminance(&self) -> f32 {
         self.avg_luminance
     }
 
-    #[tracing::instrument(skip_all)]
-    pub fn process(&mut self, in_texture: &Texture<[f32; 3]>, lerp: f32) -> Result<f32> {
-        self.screen_draw
-            .program()
-            .set_uniform(self.uniform_in_texture, in_texture.as_uniform(0)?)?;
+    /// Adapt the average luminance towards the last metered luminance. Called every frame,
+    /// including frames reused from the frame cache, which skip the metering. Returns whether the
+    /// exposure visibly changed, and the frame needs to be rendered again.
+    pub fn advance(&mut self, dt: Duration) -> bool {
+        let Some(target) = self.target_luminance else { return false; };
+        let previous = self.avg_luminance;
+        self.avg_luminance = adapt(previous, target, dt, &self.params);
+        (self.avg_luminance.log2() - previous.log2()).abs() > MIN_VISIBLE_EV_CHANGE
+    }
+
+    fn measure(&mut self, ctx: &PassContext) -> Result<()> {
+        // Meter the luminance read back on a previous frame, and only start over once it arrived
+        if let Some(readback) = &mut self.readback {
+            let Some(data) = readback.poll_pixels::<f32>() else {
+                return Ok(());
+            };
+            let mip_size = readback.size();
+            self.readback = None;
+            if let Some(luminance) = metered_luminance(&data, mip_size, &self.params) {
+                tracing::debug!(%luminance, ev=%luminance.log2());
+                self.target_luminance = Some(luminance);
+            }
+        }
+
+        ctx.texture(super::INPUT)?.set_uniform(
+            &self.screen_draw.program(),
+            self.uniform_in_texture,
+            0,
+        )?;
         Framebuffer::disable_blending();
         Framebuffer::disable_depth_test();
         Framebuffer::clear_color(Vec3::ZERO.extend(1.).to_array());
-        Framebuffer::disable_blending();
-        let (width, height, _) = in_texture.size();
-        Framebuffer::viewport(0, 0, width.get() as _, height.get() as _);
-        self.screen_draw.draw(&self.fbo)?;
-        self.target.generate_mipmaps()?;
-        let last_mipmap = self.target.num_mipmaps() - 1;
-        let mipmap = self.target.mipmap(last_mipmap).unwrap();
-        tracing::debug!(message="Sampling last mipmap for average", mipmap=%last_mipmap);
-        let luminance_data = mipmap.download()?;
-        let mut luminance_data = luminance_data[0];
-        if luminance_data.is_nan() {
-            luminance_data = 1.;
+        self.screen_draw.draw(ctx.target(Self::LUMINANCE)?)?;
+        let GraphTexture::R(target) = ctx.texture(Self::LUMINANCE)? else {
+            eyre::bail!("Luminance is not a single channel texture");
+        };
+        target.generate_mipmaps()?;
+
+        let size = target.size_vec().truncate();
+        let largest_side = size.max_element() as f32;
+        let last_mipmap = target.num_mipmaps() - 1;
+        let level = (largest_side / HISTOGRAM_RESOLUTION as f32)
+            .log2()
+            .ceil()
+            .max(0.);
+        let level = last_mipmap.min(level as _);
+        tracing::debug!(message="Sampling mipmap for histogram", mipmap=%level);
+        self.readback = Some(Readback::texture(target, level, ReadbackFormat::R32F)?);
+        Ok(())
+    }
+}
+
+impl GraphPass for AutoExposure {
+    fn name(&self) -> &str {
+        "Auto exposure"
+    }
+
+    fn setup(&mut self, builder: &mut PassBuilder) {
+        builder
+            .read(super::INPUT)
+            .create(Self::LUMINANCE, TextureDesc::full(TransientFormat::R32F));
+    }
+
+    fn execute(&mut self, ctx: &PassContext) -> Result<()> {
+        // A failed measure keeps the current exposure rather than failing the frame
+        if let Err(err) = self.measure(ctx) {
+            tracing::warn!("Cannot measure the scene luminance: {:#}", err);
+        }
+        Ok(())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// Build a weighted histogram of the log-luminance of the given pixels, and return the average
+/// luminance of the pixels between the low and high percentiles.
+fn metered_luminance(data: &[f32], size: UVec2, params: &AutoExposureParams) -> Option<f32> {
+    let mut histogram = [0f32; HISTOGRAM_BINS];
+    let bin_width = (MAX_LOG_LUMINANCE - MIN_LOG_LUMINANCE) / HISTOGRAM_BINS as f32;
+    for (ix, &luminance) in data.iter().enumerate() {
+        if !luminance.is_finite() {
+            continue;
         }
-        tracing::debug!(%luminance_data, ev=%luminance_data.log2());
-        self.avg_luminance += (luminance_data - self.avg_luminance) * lerp;
-        tracing::debug!(avg_luminance=?self.avg_luminance, luminance=?luminance_data);
-        Ok(self.avg_luminance)
+        let pixel = UVec2::new(ix as u32 % size.x, ix as u32 / size.x);
+        let uv = (pixel.as_vec2() + 0.5) / size.as_vec2();
+        let log_luminance = luminance.max(f32::MIN_POSITIVE).log2();
+        let bin = ((log_luminance - MIN_LOG_LUMINANCE) / bin_width).floor();
+        let bin = (bin.max(0.) as usize).min(HISTOGRAM_BINS - 1);
+        histogram[bin] += params.metering.weight(uv);
+    }
+
+    let total = histogram.iter().sum::<f32>();
+    if total <= 0. {
+        return None;
+    }
+    let low = total * params.low_percentile.clamp(0., 1.);
+    let high = total * params.high_percentile.clamp(0., 1.);
+    let mut accumulated = 0.;
+    let mut sum = 0.;
+    let mut count = 0.;
+    for (bin, weight) in histogram.into_iter().enumerate() {
+        let start = accumulated;
+        accumulated += weight;
+        let contribution = accumulated.min(high) - start.max(low);
+        if contribution <= 0. {
+            continue;
+        }
+        let log_luminance = MIN_LOG_LUMINANCE + (bin as f32 + 0.5) * bin_width;
+        sum += contribution * log_luminance;
+        count += contribution;
+    }
+    (count > 0.).then(|| (sum / count).exp2())
+}
+
+/// Move the current luminance towards the target in log space, at the speed configured for the
+/// direction of the change.
+fn adapt(current: f32, target: f32, dt: Duration, params: &AutoExposureParams) -> f32 {
+    let speed = if target > current {
+        params.speed_up
+    } else {
+        params.speed_down
+    };
+    let current_ev = current.max(f32::MIN_POSITIVE).log2();
+    let target_ev = target.log2();
+    let t = 1. - (-dt.as_secs_f32() * speed).exp();
+    (current_ev + (target_ev - current_ev) * t).exp2()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn histogram_ignores_bright_outliers() {
+        // 4x4 frame at 1 nit with a very bright top row, as from a sky
+        let mut data = vec![1f32; 16];
+        data[12..].fill(1e5);
+        let params = AutoExposureParams {
+            metering: MeteringMode::Average,
+            low_percentile: 0.,
+            high_percentile: 0.7,
+            ..Default::default()
+        };
+        let luminance = metered_luminance(&data, UVec2::new(4, 4), &params).unwrap();
+        assert!((luminance.log2()).abs() < 0.25, "{}", luminance);
+    }
+
+    #[test]
+    fn spot_metering_only_reads_center() {
+        let mut data = vec![1e3f32; 9];
+        data[4] = 1.;
+        let params = AutoExposureParams {
+            metering: MeteringMode::Spot,
+            low_percentile: 0.,
+            high_percentile: 1.,
+            ..Default::default()
+        };
+        let luminance = metered_luminance(&data, UVec2::new(3, 3), &params).unwrap();
+        assert!((luminance.log2()).abs() < 0.25, "{}", luminance);
+    }
+
+    #[test]
+    fn adaptation_speed_depends_on_direction() {
+        let params = AutoExposureParams {
+            speed_up: 10.,
+            speed_down: 0.1,
+            ..Default::default()
+        };
+        let dt = Duration::from_millis(100);
+        let brighter = adapt(1., 16., dt, &params);
+        let darker = adapt(16., 1., dt, &params);
+        assert!(brighter.log2() > 2.);
+        assert!(darker.log2() > 3.9);
     }
 }
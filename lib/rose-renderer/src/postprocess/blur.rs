@@ -1,4 +1,4 @@
-use std::num::NonZeroU32;
+use std::any::Any;
 
 use eyre::Result;
 use glam::UVec2;
@@ -8,150 +8,204 @@ use rose_core::utils::reload_watcher::ReloadWatcher;
 use violette::{
     framebuffer::{Blend, BlendFunction, Framebuffer},
     program::UniformLocation,
-    texture::{Dimension, SampleMode, Texture, TextureWrap},
 };
 
+use crate::frame_graph::{GraphPass, PassBuilder, PassContext, TextureDesc, TransientFormat};
+
+/// Resolution of the first mip of the bloom downsample chain, relative to the rendered frame.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
+pub enum BloomResolution {
+    Half,
+    Quarter,
+}
+
+impl BloomResolution {
+    fn divisor(self) -> u32 {
+        match self {
+            Self::Half => 2,
+            Self::Quarter => 4,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serialize", serde(default))]
+pub struct BloomQuality {
+    pub resolution: BloomResolution,
+    /// Number of downsample (and upsample) passes; each pass halves the resolution again.
+    pub passes: usize,
+}
+
+impl BloomQuality {
+    pub const LOW: Self = Self {
+        resolution: BloomResolution::Quarter,
+        passes: 3,
+    };
+    pub const MEDIUM: Self = Self {
+        resolution: BloomResolution::Half,
+        passes: 4,
+    };
+    pub const HIGH: Self = Self {
+        resolution: BloomResolution::Half,
+        passes: 5,
+    };
+}
+
+impl Default for BloomQuality {
+    fn default() -> Self {
+        Self::HIGH
+    }
+}
+
+/// Bloom of the post processing, blurring the bright parts of the frame through a chain of
+/// downsampled transients of the post processing [`FrameGraph`](crate::frame_graph::FrameGraph).
+/// The blurred frame is exported as [`Blur::OUTPUT`].
 #[derive(Debug)]
 pub struct Blur {
-    mip_chain: Vec<Texture<[f32; 3]>>,
-    fbo: Framebuffer,
+    pub quality: BloomQuality,
+    pub radius: f32,
+    /// Luminance above which the frame contributes to the bloom.
+    pub threshold: f32,
     draw_downsample: ScreenDraw,
     draw_upsample: ScreenDraw,
     uniform_down_tex: UniformLocation,
     uniform_down_size: UniformLocation,
+    uniform_down_threshold: UniformLocation,
+    uniform_down_first_mip: UniformLocation,
     uniform_up_tex: UniformLocation,
     uniform_up_radius: UniformLocation,
 }
 
 impl Blur {
-    pub fn new(size: UVec2, mip_chain_len: usize, reload_watcher: &ReloadWatcher) -> Result<Self> {
-        let sizef = size.as_vec2();
-        let max_chain_len = sizef.x.min(sizef.y).log2().floor() as usize - 1;
-        eyre::ensure!(
-            mip_chain_len <= max_chain_len,
-            "Cannot construct a chain longer than {} for  {}",
-            max_chain_len,
-            size
-        );
-        let Some(width) = NonZeroU32::new(size.x) else { eyre::bail!("Zero size"); };
-        let Some(height) = NonZeroU32::new(size.x) else { eyre::bail!("Zero size"); };
-        let depth = NonZeroU32::new(1).unwrap();
-
-        let mip_chain = (0..mip_chain_len).try_fold(vec![], |mut vec, _| {
-            let mip = Texture::new(depth, depth, depth, Dimension::D2);
-            mip.filter_min(SampleMode::Linear)?;
-            mip.filter_mag(SampleMode::Linear)?;
-            mip.wrap_r(TextureWrap::MirroredRepeat)?;
-            mip.wrap_s(TextureWrap::MirroredRepeat)?;
-            mip.wrap_t(TextureWrap::MirroredRepeat)?;
-            mip.reserve_memory()?;
-            vec.push(mip);
-            Ok::<_, eyre::Report>(vec)
-        })?;
+    /// Frame graph transient the bloom is written to.
+    pub const OUTPUT: &'static str = "bloom";
 
+    pub fn new(quality: BloomQuality, reload_watcher: &ReloadWatcher) -> Result<Self> {
         let draw_downsample = ScreenDraw::load("screen/blur/downsample.glsl", reload_watcher)?;
         let draw_upsample = ScreenDraw::load("screen/blur/upsample.glsl", reload_watcher)?;
-        let fbo = Framebuffer::new();
-        fbo.attach_color(0, mip_chain[0].mipmap(0).unwrap())?;
-        fbo.enable_buffers([0])?;
-        fbo.assert_complete()?;
 
         let downsample_pass = draw_downsample.program();
         let upsample_pass = draw_upsample.program();
         let uniform_down_tex = downsample_pass.uniform("in_texture");
         let uniform_down_size = downsample_pass.uniform("screen_size");
+        let uniform_down_threshold = downsample_pass.uniform("threshold");
+        let uniform_down_first_mip = downsample_pass.uniform("first_mip");
         let uniform_up_tex = upsample_pass.uniform("in_texture");
         let uniform_up_radius = upsample_pass.uniform("filter_radius");
         drop(downsample_pass);
         drop(upsample_pass);
 
-        let mut this = Self {
-            mip_chain,
-            fbo,
+        Ok(Self {
+            quality,
+            radius: 1e-3,
+            threshold: 1.,
             draw_downsample,
             draw_upsample,
             uniform_down_tex,
             uniform_down_size,
+            uniform_down_threshold,
+            uniform_down_first_mip,
             uniform_up_tex,
             uniform_up_radius,
-        };
-        this.resize(width, height)?;
-        Ok(this)
+        })
     }
 
-    pub fn process(&self, texture: &Texture<[f32; 3]>, radius: f32) -> Result<&Texture<[f32; 3]>> {
-        Framebuffer::disable_depth_test();
-        Framebuffer::disable_blending();
-        self.render_downsample(texture)?;
-        self.render_upsample(radius)?;
-        Ok(self.mip_chain.first().unwrap())
+    /// Number of mips of the chain at the render size. The number of passes is limited to what
+    /// the size allows.
+    fn chain_len(&self, render_size: UVec2) -> usize {
+        let first_mip_size = (render_size / self.quality.resolution.divisor()).max(UVec2::ONE);
+        let sizef = first_mip_size.as_vec2();
+        let max_chain_len = sizef.x.min(sizef.y).log2().floor() as usize;
+        self.quality.passes.clamp(1, max_chain_len.max(1))
     }
 
-    pub fn resize(&mut self, mut width: NonZeroU32, mut height: NonZeroU32) -> Result<()> {
-        let depth = NonZeroU32::new(1).unwrap();
-        self.mip_chain.iter_mut().try_for_each(|mip| {
-            width = NonZeroU32::new(width.get() / 2).unwrap();
-            height = NonZeroU32::new(height.get() / 2).unwrap();
-            mip.clear_resize(width, height, depth)?;
-            Ok::<_, eyre::Report>(())
-        })?;
-        Ok(())
+    /// Name of the transient of the mip, the first one being the output.
+    fn mip(level: usize) -> String {
+        match level {
+            0 => Self::OUTPUT.to_string(),
+            _ => format!("{}-mip{}", Self::OUTPUT, level),
+        }
     }
 
-    fn render_downsample(&self, texture: &Texture<[f32; 3]>) -> Result<()> {
-        let (w, h) = texture.mipmap_size(0)?;
-        let size = UVec2::new(w.get(), h.get()).as_vec2();
-        self.draw_downsample
-            .program()
-            .set_uniform(self.uniform_down_size, size)?;
-        self.draw_downsample
-            .program()
-            .set_uniform(self.uniform_down_tex, texture.as_uniform(0)?)?;
-
-        let mut first_mip = true;
-        for mip in &self.mip_chain {
-            let size = mip.size_vec().truncate();
-            Framebuffer::viewport(0, 0, size.x as _, size.y as _);
-            self.fbo.attach_color(0, mip.mipmap(0).unwrap())?;
-            self.draw_downsample.program().set_uniform(
-                self.draw_downsample.program().uniform("first_mip"),
-                first_mip,
-            )?;
-            self.draw_downsample.draw(&self.fbo)?;
+    fn render_downsample(&self, ctx: &PassContext, chain_len: usize) -> Result<()> {
+        let input = ctx.texture(super::INPUT)?;
+        {
+            let program = self.draw_downsample.program();
+            program.set_uniform(self.uniform_down_threshold, self.threshold)?;
+            program.set_uniform(self.uniform_down_size, input.size().as_vec2())?;
+            input.set_uniform(&program, self.uniform_down_tex, 0)?;
+        }
 
+        for level in 0..chain_len {
+            let mip = Self::mip(level);
             self.draw_downsample
                 .program()
-                .set_uniform(self.uniform_down_size, size.as_vec2())?;
-            self.draw_downsample
-                .program()
-                .set_uniform(self.uniform_down_tex, mip.as_uniform(0)?)?;
-            first_mip = false;
+                .set_uniform(self.uniform_down_first_mip, level == 0)?;
+            self.draw_downsample.draw(ctx.target(&mip)?)?;
+
+            let texture = ctx.texture(&mip)?;
+            let program = self.draw_downsample.program();
+            program.set_uniform(self.uniform_down_size, texture.size().as_vec2())?;
+            texture.set_uniform(&program, self.uniform_down_tex, 0)?;
         }
         Ok(())
     }
 
-    fn render_upsample(&self, radius: f32) -> Result<()> {
+    fn render_upsample(&self, ctx: &PassContext, chain_len: usize) -> Result<()> {
         self.draw_upsample
             .program()
-            .set_uniform(self.uniform_up_radius, radius)?;
+            .set_uniform(self.uniform_up_radius, self.radius)?;
         Framebuffer::enable_blending(Blend::One, Blend::One);
         Framebuffer::blend_equation(BlendFunction::Add);
 
-        // for window in self.mip_chain.windows(2).rev() {
-        for i in (1..self.mip_chain.len()).rev() {
-            let mip = &self.mip_chain[i];
-            let next_mip = &self.mip_chain[i - 1];
-            // let mip = &window[1];
-            // let next_mip = &window[0];
-            let size = next_mip.size_vec().truncate();
-
-            self.draw_upsample
-                .program()
-                .set_uniform(self.uniform_up_tex, mip.as_uniform(0)?)?;
-            Framebuffer::viewport(0, 0, size.x as _, size.y as _);
-            self.fbo.attach_color(0, next_mip.mipmap(0).unwrap())?;
-            self.draw_upsample.draw(&self.fbo)?;
+        for level in (1..chain_len).rev() {
+            ctx.texture(&Self::mip(level))?.set_uniform(
+                &self.draw_upsample.program(),
+                self.uniform_up_tex,
+                0,
+            )?;
+            let target = ctx.target(&Self::mip(level - 1))?;
+            self.draw_upsample.draw(target)?;
         }
+        Framebuffer::disable_blending();
         Ok(())
     }
 }
+
+impl GraphPass for Blur {
+    fn name(&self) -> &str {
+        "Bloom"
+    }
+
+    fn setup(&mut self, builder: &mut PassBuilder) {
+        let divisor = self.quality.resolution.divisor() as f32;
+        builder.read(super::INPUT);
+        for level in 0..self.chain_len(builder.render_size()) {
+            let scale = 1. / (divisor * 2f32.powi(level as _));
+            builder.create(
+                Self::mip(level),
+                TextureDesc::scaled(scale, TransientFormat::Rgb32F),
+            );
+        }
+    }
+
+    /// Blur the input, only keeping the parts of the first mip whose luminance is above the
+    /// threshold.
+    fn execute(&mut self, ctx: &PassContext) -> Result<()> {
+        let chain_len = self.chain_len(ctx.render_size);
+        Framebuffer::disable_depth_test();
+        Framebuffer::disable_blending();
+        self.render_downsample(ctx, chain_len)?;
+        self.render_upsample(ctx, chain_len)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
@@ -0,0 +1,163 @@
+use std::{any::Any, cell::Ref, fmt, path::Path};
+
+use eyre::{Context, Result};
+use glam::UVec2;
+
+use rose_core::utils::draw_counters::CountBind;
+use rose_core::{screen_draw::ScreenDraw, utils::reload_watcher::ReloadWatcher};
+use violette::{
+    framebuffer::Framebuffer,
+    program::{Program, UniformLocation},
+    texture::Texture,
+};
+
+use crate::postprocess::pool::{FrameTexture, TexturePool};
+
+/// User-provided fullscreen pass, run on the HDR frame after lighting and before the built-in
+/// bloom, lens flare and tonemapping passes.
+pub trait PostEffect: 'static + fmt::Debug {
+    /// Called when the effect is added to the renderer, and on every subsequent resize.
+    fn resize(&mut self, _size: UVec2) -> Result<()> {
+        Ok(())
+    }
+
+    /// Draw the effect into `output`, reading the previous pass from `input`. The viewport is
+    /// already set to cover the whole output.
+    fn draw(&mut self, input: &Texture<[f32; 3]>, output: &Framebuffer) -> Result<()>;
+
+    fn as_any(&self) -> &dyn Any;
+
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+/// Ordered list of [`PostEffect`]s, each one rendering into a target taken from the
+/// [`TexturePool`].
+#[derive(Debug)]
+pub struct PostEffectChain {
+    effects: Vec<Box<dyn PostEffect>>,
+    size: UVec2,
+}
+
+impl PostEffectChain {
+    pub fn new(size: UVec2) -> Self {
+        Self {
+            effects: vec![],
+            size,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.effects.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.effects.is_empty()
+    }
+
+    /// Insert an effect at the given position in the chain; effects run in ascending order.
+    pub fn insert(&mut self, index: usize, mut effect: Box<dyn PostEffect>) -> Result<()> {
+        effect.resize(self.size)?;
+        self.effects.insert(index, effect);
+        Ok(())
+    }
+
+    pub fn push(&mut self, effect: Box<dyn PostEffect>) -> Result<()> {
+        self.insert(self.effects.len(), effect)
+    }
+
+    pub fn remove(&mut self, index: usize) -> Box<dyn PostEffect> {
+        self.effects.remove(index)
+    }
+
+    pub fn clear(&mut self) {
+        self.effects.clear();
+    }
+
+    pub fn get<E: PostEffect>(&self) -> Option<&E> {
+        self.effects.iter().find_map(|e| e.as_any().downcast_ref())
+    }
+
+    pub fn get_mut<E: PostEffect>(&mut self) -> Option<&mut E> {
+        self.effects
+            .iter_mut()
+            .find_map(|e| e.as_any_mut().downcast_mut())
+    }
+
+    pub fn resize(&mut self, size: UVec2) -> Result<()> {
+        for effect in &mut self.effects {
+            effect.resize(size)?;
+        }
+        self.size = size;
+        Ok(())
+    }
+
+    /// Run every effect in order, returning the texture holding the result of the last one, or the
+    /// input itself when the chain is empty. Inputs taken from the pool are released as soon as
+    /// the effect reading them is done, so that the next effect can render into them.
+    #[tracing::instrument(skip_all, fields(effects = self.effects.len()))]
+    pub fn process<'a>(
+        &mut self,
+        pool: &mut TexturePool,
+        input: FrameTexture<'a>,
+    ) -> Result<FrameTexture<'a>> {
+        let mut current = input;
+        Framebuffer::viewport(0, 0, self.size.x as _, self.size.y as _);
+        for (ix, effect) in self.effects.iter_mut().enumerate() {
+            let target = pool.acquire(self.size)?;
+            effect
+                .draw(current.texture(), target.framebuffer())
+                .with_context(|| format!("Drawing post effect #{}", ix))?;
+            std::mem::replace(&mut current, FrameTexture::Pooled(target)).release(pool);
+        }
+        Ok(current)
+    }
+}
+
+/// [`PostEffect`] drawing a single fragment shader over the screen.
+///
+/// The shader receives the previous pass in `uniform sampler2D in_texture` and its size in pixels
+/// in `uniform vec2 screen_size`; any other uniform can be set through [`Self::program`].
+#[derive(Debug)]
+pub struct ScreenPostEffect {
+    draw: ScreenDraw,
+    u_texture: UniformLocation,
+    u_size: UniformLocation,
+}
+
+impl ScreenPostEffect {
+    pub fn load(file: impl AsRef<Path>, reload_watcher: &ReloadWatcher) -> Result<Self> {
+        let draw = ScreenDraw::load(file, reload_watcher)?;
+        let program = draw.program();
+        let u_texture = program.uniform("in_texture");
+        let u_size = program.uniform("screen_size");
+        drop(program);
+        Ok(Self {
+            draw,
+            u_texture,
+            u_size,
+        })
+    }
+
+    pub fn program(&self) -> Ref<Program> {
+        self.draw.program()
+    }
+}
+
+impl PostEffect for ScreenPostEffect {
+    fn draw(&mut self, input: &Texture<[f32; 3]>, output: &Framebuffer) -> Result<()> {
+        {
+            let program = self.draw.program();
+            program.set_uniform(self.u_texture, input.as_uniform(0).counted()?)?;
+            program.set_uniform(self.u_size, input.size_vec().truncate().as_vec2())?;
+        }
+        self.draw.draw(output)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
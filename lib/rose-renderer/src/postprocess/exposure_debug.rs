@@ -0,0 +1,119 @@
+//! Views of the HDR frame for tuning lights and exposure: a false color heatmap of the exposed
+//! luminance, zebra stripes over clipped and crushed pixels, and a readout of the luminance of the
+//! pixel under the cursor.
+
+use eyre::Result;
+use glam::{UVec2, Vec2, Vec3};
+
+use rose_core::readback::{Readback, ReadbackFormat};
+use violette::texture::Texture;
+
+/// How the tonemapped frame is shown.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
+pub enum ExposureView {
+    /// The final image.
+    #[default]
+    Normal,
+    /// Exposed luminance as a heatmap, from 5 stops under middle gray in blue to 5 stops over in
+    /// red.
+    FalseColor,
+    /// The final image with red stripes over clipped pixels and blue stripes over pixels too dark
+    /// to be told apart from black.
+    Zebra,
+}
+
+impl ExposureView {
+    /// Mode of the view in the postprocessing shader.
+    pub(crate) fn shader_mode(self) -> i32 {
+        match self {
+            Self::Normal => 0,
+            Self::FalseColor => 1,
+            Self::Zebra => 2,
+        }
+    }
+}
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serialize", serde(default))]
+pub struct ExposureDebugParams {
+    pub view: ExposureView,
+    /// Read back the luminance under the cursor, set with [`crate::Renderer::set_luminance_probe`].
+    pub cursor_readout: bool,
+}
+
+impl ExposureDebugParams {
+    #[cfg(feature = "debug-ui")]
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            let view = &mut self.view;
+            ui.selectable_value(view, ExposureView::Normal, "Normal");
+            ui.selectable_value(view, ExposureView::FalseColor, "False color");
+            ui.selectable_value(view, ExposureView::Zebra, "Zebra");
+        });
+        ui.checkbox(&mut self.cursor_readout, "EV under cursor");
+    }
+}
+
+/// Asynchronous readback of the HDR luminance of a single pixel.
+#[derive(Debug, Default)]
+pub(crate) struct LuminanceProbe {
+    /// Position of the probe, from (0, 0) at the top-left to (1, 1) at the bottom-right corner.
+    pub(crate) position: Option<Vec2>,
+    readback: Option<Readback>,
+    luminance: Option<f32>,
+}
+
+impl LuminanceProbe {
+    /// Luminance, in nits, last read under the probe.
+    pub(crate) fn luminance(&self) -> Option<f32> {
+        self.position.and(self.luminance)
+    }
+
+    /// Collect the pending readback if it arrived, and start reading `input` under the probe once
+    /// there is none pending.
+    pub(crate) fn read(&mut self, input: &Texture<[f32; 3]>) -> Result<()> {
+        if let Some(readback) = &mut self.readback {
+            let Some(pixels) = readback.poll_pixels::<[f32; 3]>() else { return Ok(()); };
+            self.readback = None;
+            self.luminance = pixels.first().map(|&rgb| luminance(rgb.into()));
+        }
+        let Some(position) = self.position else {
+            self.luminance = None;
+            return Ok(());
+        };
+        let size = input.size_vec().truncate();
+        if position.cmplt(Vec2::ZERO).any() || position.cmpge(Vec2::ONE).any() {
+            self.luminance = None;
+            return Ok(());
+        }
+        // Textures start at the bottom row
+        let pixel = (position * size.as_vec2()).as_uvec2().min(size - 1);
+        let pixel = UVec2::new(pixel.x, size.y - 1 - pixel.y);
+        self.readback = Some(Readback::texture_region(
+            input,
+            0,
+            pixel,
+            UVec2::ONE,
+            ReadbackFormat::Rgb32F,
+        )?);
+        Ok(())
+    }
+}
+
+/// Luminance of a linear Rec. 709 color, with the weights of the shaders.
+fn luminance(color: Vec3) -> f32 {
+    color.dot(Vec3::new(0.2125, 0.7154, 0.0721))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn luminance_of_white_is_one() {
+        assert!((luminance(Vec3::ONE) - 1.).abs() < 1e-6);
+        assert!(luminance(Vec3::Y) > luminance(Vec3::X));
+    }
+}
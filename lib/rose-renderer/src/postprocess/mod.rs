@@ -1,30 +1,50 @@
-use std::num::NonZeroU32;
+use std::collections::HashMap;
+use std::rc::Rc;
 use std::time::Duration;
 
 use eyre::Result;
-use glam::UVec2;
+use glam::{UVec2, Vec2};
 
+use rose_core::camera::{ViewUniform, ViewUniformBuffer};
 use rose_core::screen_draw::ScreenDraw;
+use rose_core::utils::draw_counters::CountBind;
 use rose_core::utils::reload_watcher::ReloadWatcher;
-use violette::texture::{SampleMode, TextureWrap};
 use violette::{framebuffer::Framebuffer, program::UniformLocation, texture::Texture};
 
+use crate::frame_graph::{FrameGraph, GraphTexture, ImportedResource};
+use crate::gpu_profiler::GpuProfiler;
 use crate::postprocess::autoexposure::AutoExposure;
 use crate::postprocess::blur::Blur;
+use crate::postprocess::exposure_debug::LuminanceProbe;
+
+pub use autoexposure::{AutoExposureParams, MeteringMode};
+pub use blur::{BloomQuality, BloomResolution};
+pub use effects::*;
+pub use exposure_debug::{ExposureDebugParams, ExposureView};
+pub use pool::{FrameTexture, PooledTexture, TexturePool};
 
 mod autoexposure;
 mod blur;
+mod effects;
+mod exposure_debug;
+mod pool;
+
+/// Frame graph resource of the frame the post processing passes read.
+const INPUT: &str = "input";
 
 #[derive(Debug)]
 pub struct Postprocess {
     pub bloom_radius: f32,
+    /// Luminance, in nits, above which pixels contribute to the bloom.
+    pub bloom_threshold: f32,
     pub luminance_bias: f32,
+    pub auto_exposure_params: AutoExposureParams,
     draw: ScreenDraw,
-    bloom: Blur,
-    auto_exposure: AutoExposure,
+    /// Passes run on the frame before it is tonemapped, importing it as [`INPUT`]: the bloom and
+    /// the luminance measure of the auto exposure.
+    graph: FrameGraph,
     u_texture: UniformLocation,
     u_avg_luminance: UniformLocation,
-    texture: Texture<[f32; 3]>,
     u_bloom_tex: UniformLocation,
     u_bloom_strength: UniformLocation,
     u_lens_flare_strength: UniformLocation,
@@ -32,20 +52,12 @@ pub struct Postprocess {
     u_distortion_amt: UniformLocation,
     u_ghost_spacing: UniformLocation,
     u_ghost_count: UniformLocation,
+    u_debug_view: UniformLocation,
+    luminance_probe: LuminanceProbe,
 }
 
 impl Postprocess {
-    pub fn new(size: UVec2, reload_watcher: &ReloadWatcher) -> Result<Self> {
-        let Some(width) = NonZeroU32::new(size.x) else { eyre::bail!("Zero width resize"); };
-        let Some(height) = NonZeroU32::new(size.y) else { eyre::bail!("Zero height resize"); };
-        let nonzero_one = NonZeroU32::new(1).unwrap();
-        let texture = Texture::new(width, height, nonzero_one, violette::texture::Dimension::D2);
-        texture.wrap_r(TextureWrap::MirroredRepeat)?;
-        texture.wrap_s(TextureWrap::MirroredRepeat)?;
-        texture.filter_min(SampleMode::Nearest)?;
-        texture.filter_mag(SampleMode::Linear)?;
-        texture.reserve_memory()?;
-
+    pub fn new(reload_watcher: &ReloadWatcher) -> Result<Self> {
         let draw = ScreenDraw::load("screen/postprocess.glsl", reload_watcher)?;
         let postprocess_program = draw.program();
         let draw_texture = postprocess_program.uniform("frame");
@@ -57,12 +69,19 @@ impl Postprocess {
         let u_distortion_amt = postprocess_program.uniform("distortion_amt");
         let u_ghost_spacing = postprocess_program.uniform("ghost_spacing");
         let u_ghost_count = postprocess_program.uniform("ghost_count");
+        let u_debug_view = postprocess_program.uniform("debug_view");
         drop(postprocess_program);
 
+        let bloom = Blur::new(BloomQuality::default(), reload_watcher)?;
+        let mut graph = FrameGraph::default();
+        graph.push(Box::new(bloom));
+        graph.push(Box::new(AutoExposure::new(reload_watcher)?));
+        graph.export(Blur::OUTPUT);
+        graph.export(AutoExposure::LUMINANCE);
+
         Ok(Self {
             draw,
-            bloom: Blur::new(size, 5, reload_watcher)?,
-            auto_exposure: AutoExposure::new(size, reload_watcher)?,
+            graph,
             u_texture: draw_texture,
             u_avg_luminance: avg_luminance,
             u_bloom_tex,
@@ -72,9 +91,12 @@ impl Postprocess {
             u_distortion_amt,
             u_ghost_spacing,
             u_ghost_count,
-            texture,
+            u_debug_view,
+            luminance_probe: LuminanceProbe::default(),
             luminance_bias: 1.5f32.exp2(),
+            auto_exposure_params: AutoExposureParams::default(),
             bloom_radius: 1e-3,
+            bloom_threshold: 1.,
         })
     }
 
@@ -85,61 +107,113 @@ impl Postprocess {
         Ok(())
     }
 
+    /// Change the resolution and pass count of the bloom chain, whose transients are allocated
+    /// by the frame graph on the next frame.
+    pub fn set_bloom_quality(&mut self, quality: BloomQuality) {
+        if let Some(bloom) = self.graph.get_mut::<Blur>() {
+            bloom.quality = quality;
+        }
+    }
+
     pub fn set_lens_flare_parameters(&self, params: LensFlareParams) -> Result<()> {
         let program = self.draw.program();
+        // The shader loops over the ghosts, so no ghosts means no lens flare work at all
+        let ghost_count = if params.enabled {
+            params.ghost_count
+        } else {
+            0
+        };
         program.set_uniform(self.u_lens_flare_strength, params.strength)?;
         program.set_uniform(self.u_lens_flare_threshold, params.threshold)?;
         program.set_uniform(self.u_distortion_amt, params.distortion)?;
         program.set_uniform(self.u_ghost_spacing, params.ghost_spacing)?;
-        program.set_uniform(self.u_ghost_count, params.ghost_count)?;
+        program.set_uniform(self.u_ghost_count, ghost_count)?;
         Ok(())
     }
 
-    pub fn resize(&mut self, size: UVec2) -> Result<()> {
-        let Some(width) = NonZeroU32::new(size.x) else { eyre::bail!("Zero width resize"); };
-        let Some(height) = NonZeroU32::new(size.y) else { eyre::bail!("Zero height resize"); };
-        self.texture
-            .clear_resize(width, height, NonZeroU32::new(1).unwrap())?;
-        self.auto_exposure.resize(size)?;
-        self.bloom.resize(width, height)?;
+    pub fn set_exposure_view(&self, view: ExposureView) -> Result<()> {
+        self.draw
+            .program()
+            .set_uniform(self.u_debug_view, view.shader_mode())?;
         Ok(())
     }
 
+    /// Read the luminance of the input at the position, from (0, 0) at the top-left to (1, 1) at
+    /// the bottom-right corner of the frame, or stop reading it.
+    pub fn set_luminance_probe(&mut self, position: Option<Vec2>) {
+        self.luminance_probe.position = position;
+    }
+
+    /// Luminance, in nits, of the input under the luminance probe, read a few frames ago.
+    pub fn probed_luminance(&self) -> Option<f32> {
+        self.luminance_probe.luminance()
+    }
+
     #[tracing::instrument(skip_all)]
     pub fn draw(
         &mut self,
         frame: &Framebuffer,
         input: &Texture<[f32; 3]>,
-        dt: Duration,
+        view: &ViewUniformBuffer,
+        view_uniform: &ViewUniform,
+        profiler: &Rc<GpuProfiler>,
     ) -> Result<()> {
         let (width, height) = input.mipmap_size(0).unwrap();
-        let accomodate = dt.as_secs_f32() * 5.;
-        let lerp = accomodate / (1. + accomodate);
-        tracing::debug!(?accomodate, ?lerp);
-        let avg_luminance = self
-            .auto_exposure
-            .process(input, lerp)
-            .unwrap_or_else(|_| self.auto_exposure.average_luminance());
+        if let Some(bloom) = self.graph.get_mut::<Blur>() {
+            bloom.radius = self.bloom_radius;
+            bloom.threshold = self.bloom_threshold;
+        }
+        if let Some(auto_exposure) = self.graph.get_mut::<AutoExposure>() {
+            auto_exposure.params = self.auto_exposure_params;
+        }
+        let imported = HashMap::from([(
+            INPUT,
+            ImportedResource {
+                texture: GraphTexture::Rgb(input),
+                framebuffer: None,
+            },
+        )]);
+        let size = UVec2::new(width.get(), height.get());
+        self.graph
+            .execute(size, &imported, view, view_uniform, profiler)?;
+        let avg_luminance = self.average_luminance();
         {
             let program = self.draw.program();
             program.set_uniform(self.u_avg_luminance, avg_luminance / self.luminance_bias)?;
-            let bloom = self.bloom.process(input, self.bloom_radius)?;
-            program.set_uniform(self.u_texture, input.as_uniform(0)?)?;
-            program.set_uniform(self.u_bloom_tex, bloom.as_uniform(1)?)?;
+            let Some(bloom) = self.graph.exported(Blur::OUTPUT) else {
+                eyre::bail!("Bloom was not rendered");
+            };
+            program.set_uniform(self.u_texture, input.as_uniform(0).counted()?)?;
+            bloom.set_uniform(&program, self.u_bloom_tex, 1)?;
         }
+        self.luminance_probe.read(input)?;
         Framebuffer::viewport(0, 0, width.get() as _, height.get() as _);
         self.draw.draw(frame)?;
         Ok(())
     }
 
-    #[cfg(feature = "debug-ui")]
+    /// Advance the exposure adaptation by `dt`, returning whether the exposure changed enough
+    /// that the frame needs to be rendered again.
+    pub fn advance_exposure(&mut self, dt: Duration) -> bool {
+        let params = self.auto_exposure_params;
+        let Some(auto_exposure) = self.graph.get_mut::<AutoExposure>() else { return false; };
+        auto_exposure.params = params;
+        auto_exposure.advance(dt)
+    }
+
+    /// Scene luminance the exposure adapted to.
     pub fn average_luminance(&self) -> f32 {
-        self.auto_exposure.average_luminance()
+        self.graph
+            .get::<AutoExposure>()
+            .map_or(0.5, AutoExposure::average_luminance)
     }
 }
 
 #[derive(Debug, Copy, Clone)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serialize", serde(default))]
 pub struct LensFlareParams {
+    pub enabled: bool,
     pub strength: f32,
     pub distortion: f32,
     pub threshold: f32,
@@ -150,6 +224,7 @@ pub struct LensFlareParams {
 impl Default for LensFlareParams {
     fn default() -> Self {
         Self {
+            enabled: true,
             strength: 2e-3,
             distortion: 2.,
             threshold: 1.,
@@ -0,0 +1,198 @@
+//! Intermediate textures of the post processing chain, shared between passes.
+//!
+//! Passes acquire their targets from the pool when they run and give them back as soon as the next
+//! pass has read them, so that at most two full-size intermediates are alive at the same time no
+//! matter how long the chain is. Textures are keyed by size, and those left unused for a whole
+//! frame are freed. The bloom and auto exposure targets are transients of the post processing
+//! frame graph instead, see [`Postprocess`](super::Postprocess).
+
+use std::num::NonZeroU32;
+
+use eyre::Result;
+use glam::UVec2;
+
+use violette::{
+    framebuffer::Framebuffer,
+    texture::{Dimension, SampleMode, Texture, TextureWrap},
+};
+
+/// Number of frames a released texture is kept around before being freed.
+const MAX_IDLE_FRAMES: u64 = 1;
+
+/// Bytes per texel of the intermediate textures.
+const TEXEL_SIZE: u64 = std::mem::size_of::<[f32; 3]>() as u64;
+
+/// Render target borrowed from a [`TexturePool`].
+#[derive(Debug)]
+pub struct PooledTexture {
+    texture: Texture<[f32; 3]>,
+    fbo: Framebuffer,
+}
+
+impl PooledTexture {
+    fn new(size: UVec2) -> Result<Self> {
+        let Some(width) = NonZeroU32::new(size.x) else { eyre::bail!("Zero width texture"); };
+        let Some(height) = NonZeroU32::new(size.y) else { eyre::bail!("Zero height texture"); };
+        let texture = Texture::new(width, height, NonZeroU32::new(1).unwrap(), Dimension::D2);
+        texture.wrap_r(TextureWrap::MirroredRepeat)?;
+        texture.wrap_s(TextureWrap::MirroredRepeat)?;
+        texture.filter_min(SampleMode::Linear)?;
+        texture.filter_mag(SampleMode::Linear)?;
+        texture.reserve_memory()?;
+
+        let fbo = Framebuffer::new();
+        fbo.attach_color(0, texture.mipmap(0).unwrap())?;
+        fbo.enable_buffers([0])?;
+        fbo.assert_complete()?;
+        Ok(Self { texture, fbo })
+    }
+
+    pub fn texture(&self) -> &Texture<[f32; 3]> {
+        &self.texture
+    }
+
+    pub fn framebuffer(&self) -> &Framebuffer {
+        &self.fbo
+    }
+
+    pub fn size(&self) -> UVec2 {
+        self.texture.size_vec().truncate()
+    }
+}
+
+/// Frame texture passed along the post processing chain: either a texture owned by another part
+/// of the renderer, or one borrowed from the pool which goes back to it once read.
+#[derive(Debug)]
+pub enum FrameTexture<'a> {
+    Borrowed(&'a Texture<[f32; 3]>),
+    Pooled(PooledTexture),
+}
+
+impl<'a> FrameTexture<'a> {
+    pub fn texture(&self) -> &Texture<[f32; 3]> {
+        match self {
+            Self::Borrowed(texture) => texture,
+            Self::Pooled(pooled) => pooled.texture(),
+        }
+    }
+
+    /// Give the texture back to the pool if it came from it.
+    pub fn release(self, pool: &mut TexturePool) {
+        if let Self::Pooled(pooled) = self {
+            pool.release(pooled);
+        }
+    }
+}
+
+/// Pool of the intermediate render targets of the post processing chain.
+#[derive(Debug, Default)]
+pub struct TexturePool {
+    free: FreeList<PooledTexture>,
+    /// Sizes of the textures currently acquired.
+    in_use: Vec<UVec2>,
+}
+
+impl TexturePool {
+    /// Take a free texture of the given size, allocating one if there is none. Its contents are
+    /// undefined.
+    pub fn acquire(&mut self, size: UVec2) -> Result<PooledTexture> {
+        let texture = match self.free.take(size) {
+            Some(texture) => texture,
+            None => PooledTexture::new(size)?,
+        };
+        self.in_use.push(size);
+        Ok(texture)
+    }
+
+    /// Give the texture back so that later passes can render into it.
+    pub fn release(&mut self, texture: PooledTexture) {
+        let size = texture.size();
+        if let Some(ix) = self.in_use.iter().position(|&s| s == size) {
+            self.in_use.swap_remove(ix);
+        }
+        self.free.put(size, texture);
+    }
+
+    /// Free the textures which were not acquired during the last frame.
+    pub fn end_frame(&mut self) {
+        self.free.end_frame();
+    }
+
+    /// Video memory taken by the textures of the pool, acquired or not, in bytes.
+    pub fn memory_usage(&self) -> u64 {
+        self.free
+            .sizes()
+            .chain(self.in_use.iter().copied())
+            .map(|size| size.x as u64 * size.y as u64 * TEXEL_SIZE)
+            .sum()
+    }
+}
+
+/// Released items along with their size and the frame they were last released on.
+#[derive(Debug)]
+struct FreeList<T> {
+    items: Vec<(UVec2, u64, T)>,
+    frame: u64,
+}
+
+impl<T> Default for FreeList<T> {
+    fn default() -> Self {
+        Self {
+            items: vec![],
+            frame: 0,
+        }
+    }
+}
+
+impl<T> FreeList<T> {
+    /// Take the most recently released item of the given size.
+    fn take(&mut self, size: UVec2) -> Option<T> {
+        let ix = self.items.iter().rposition(|(s, _, _)| *s == size)?;
+        Some(self.items.remove(ix).2)
+    }
+
+    fn put(&mut self, size: UVec2, item: T) {
+        self.items.push((size, self.frame, item));
+    }
+
+    fn sizes(&self) -> impl '_ + Iterator<Item = UVec2> {
+        self.items.iter().map(|(size, _, _)| *size)
+    }
+
+    fn end_frame(&mut self) {
+        let frame = self.frame;
+        self.items
+            .retain(|(_, last_used, _)| frame - last_used < MAX_IDLE_FRAMES);
+        self.frame += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reuses_released_items_of_the_same_size() {
+        let mut list = FreeList::default();
+        list.put(UVec2::new(1920, 1080), 0);
+        list.put(UVec2::new(960, 540), 1);
+        assert_eq!(list.take(UVec2::new(1280, 720)), None);
+        assert_eq!(list.take(UVec2::new(1920, 1080)), Some(0));
+        assert_eq!(list.take(UVec2::new(1920, 1080)), None);
+        assert_eq!(list.sizes().collect::<Vec<_>>(), [UVec2::new(960, 540)]);
+    }
+
+    #[test]
+    fn frees_items_idle_for_a_frame() {
+        let mut list = FreeList::default();
+        let size = UVec2::new(1920, 1080);
+        list.put(size, 0);
+        list.end_frame();
+        assert_eq!(list.sizes().count(), 1);
+        list.put(size, 1);
+        list.end_frame();
+        // The item released on the previous frame is dropped, the other one is kept
+        assert_eq!(list.take(size), Some(1));
+        assert_eq!(list.take(size), None);
+    }
+}
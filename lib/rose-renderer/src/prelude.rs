@@ -1,4 +1,18 @@
+pub use crate::aov::Aovs;
 pub use crate::bones::*;
+pub use crate::cookie::{CookieProjection, LightCookie};
+pub use crate::cubemap::Cubemap;
 pub use crate::env::*;
+pub use crate::environments::Environments;
+pub use crate::fog::FogParams;
+pub use crate::frame_graph::{GraphPass, PassBuilder, PassContext, TextureDesc, TransientFormat};
+pub use crate::gbuffers::GBufferAttachment;
 pub use crate::material::*;
-pub use crate::{BloomInterface, LensFlareParams, Mesh, PostprocessInterface};
+pub use crate::morph::{MorphTarget, MorphTargets};
+pub use crate::postprocess::{PostEffect, ScreenPostEffect};
+pub use crate::probes::{IrradianceProbeGrid, IrradianceProbes};
+pub use crate::refraction::RefractionParams;
+pub use crate::shader_material::{ShaderMaterial, ShaderMaterialBuilder};
+pub use crate::splat::{SplatLayer, SplatMaterial};
+pub use crate::water::WaterParams;
+pub use crate::{BloomInterface, LensFlareParams, Mesh, PostprocessInterface, ViewHook};
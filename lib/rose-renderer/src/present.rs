@@ -0,0 +1,84 @@
+use std::num::NonZeroU32;
+
+use eyre::{Context, Result};
+use glam::UVec2;
+
+use rose_core::utils::draw_counters::CountBind;
+use rose_core::{screen_draw::ScreenDraw, utils::reload_watcher::ReloadWatcher};
+use violette::{
+    framebuffer::Framebuffer,
+    program::UniformLocation,
+    texture::{Dimension, SampleMode, Texture},
+};
+
+/// Keeps the last post-processed frame around, so that it can be presented again without
+/// re-rendering the scene when nothing changed.
+#[derive(Debug)]
+pub struct FrameCache {
+    texture: Texture<[f32; 3]>,
+    fbo: Framebuffer,
+    blit: ScreenDraw,
+    u_texture: UniformLocation,
+    key: Option<u64>,
+}
+
+impl FrameCache {
+    pub fn new(size: UVec2, reload_watcher: &ReloadWatcher) -> Result<Self> {
+        let Some(width) = NonZeroU32::new(size.x) else { eyre::bail!("Zero width resize"); };
+        let Some(height) = NonZeroU32::new(size.y) else { eyre::bail!("Zero height resize"); };
+        let texture = Texture::new(width, height, NonZeroU32::new(1).unwrap(), Dimension::D2);
+        texture.filter_min(SampleMode::Nearest)?;
+        texture.filter_mag(SampleMode::Nearest)?;
+        texture.reserve_memory()?;
+
+        let fbo = Framebuffer::new();
+        fbo.attach_color(0, texture.mipmap(0).unwrap())?;
+        fbo.enable_buffers([0])?;
+        fbo.assert_complete()?;
+
+        let blit =
+            ScreenDraw::load("blit.glsl", reload_watcher).context("Cannot load blit program")?;
+        let u_texture = blit.program().uniform("in_texture");
+        Ok(Self {
+            texture,
+            fbo,
+            blit,
+            u_texture,
+            key: None,
+        })
+    }
+
+    pub fn resize(&mut self, size: UVec2) -> Result<()> {
+        let Some(width) = NonZeroU32::new(size.x) else { eyre::bail!("Zero width resize"); };
+        let Some(height) = NonZeroU32::new(size.y) else { eyre::bail!("Zero height resize"); };
+        self.texture
+            .clear_resize(width, height, NonZeroU32::new(1).unwrap())?;
+        self.invalidate();
+        Ok(())
+    }
+
+    pub fn framebuffer(&self) -> &Framebuffer {
+        &self.fbo
+    }
+
+    /// Whether the cached frame was rendered with the given key.
+    pub fn is_valid(&self, key: u64) -> bool {
+        self.key == Some(key)
+    }
+
+    pub fn store(&mut self, key: u64) {
+        self.key.replace(key);
+    }
+
+    pub fn invalidate(&mut self) {
+        self.key.take();
+    }
+
+    pub fn present(&self, frame: &Framebuffer) -> Result<()> {
+        self.blit
+            .program()
+            .set_uniform(self.u_texture, self.texture.as_uniform(0).counted()?)?;
+        self.blit.draw(frame)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,378 @@
+use std::{f32::consts::PI, num::NonZeroU32, thread::JoinHandle};
+
+use eyre::{Context, Result};
+use glam::{UVec3, Vec3};
+
+use rose_core::utils::draw_counters::CountBind;
+use rose_core::{
+    light::{AreaShape, Light},
+    screen_draw::ScreenDraw,
+    utils::reload_watcher::ReloadWatcher,
+};
+use violette::{
+    framebuffer::Framebuffer,
+    program::UniformLocation,
+    texture::{Dimension, SampleMode, Texture},
+};
+
+use crate::{cubemap::Cubemap, env::MaterialInfo};
+
+/// Number of spherical harmonics coefficients stored per probe (L2 basis).
+pub const SH_COEFFICIENTS: usize = 9;
+
+/// Number of directions used to integrate the sky function into each probe.
+const SKY_SAMPLES: usize = 256;
+
+/// Grid of irradiance probes covering an axis-aligned box of the scene, baked on the CPU.
+///
+/// Each probe stores the irradiance around it as L2 spherical harmonics, already convolved with
+/// the cosine lobe and divided by pi, so that evaluating it along a normal directly gives the
+/// diffuse lighting to multiply with the albedo.
+#[derive(Debug, Clone)]
+pub struct IrradianceProbeGrid {
+    pub min: Vec3,
+    pub max: Vec3,
+    resolution: UVec3,
+    coefficients: Vec<[Vec3; SH_COEFFICIENTS]>,
+}
+
+impl IrradianceProbeGrid {
+    pub fn new(min: Vec3, max: Vec3, resolution: UVec3) -> Self {
+        let resolution = resolution.max(UVec3::ONE);
+        let count = (resolution.x * resolution.y * resolution.z) as usize;
+        Self {
+            min,
+            max,
+            resolution,
+            coefficients: vec![[Vec3::ZERO; SH_COEFFICIENTS]; count],
+        }
+    }
+
+    pub fn resolution(&self) -> UVec3 {
+        self.resolution
+    }
+
+    pub fn len(&self) -> usize {
+        self.coefficients.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.coefficients.is_empty()
+    }
+
+    pub fn probe_position(&self, ix: UVec3) -> Vec3 {
+        let steps = (self.resolution - UVec3::ONE).max(UVec3::ONE).as_vec3();
+        let t = if self.resolution == UVec3::ONE {
+            Vec3::splat(0.5)
+        } else {
+            ix.as_vec3() / steps
+        };
+        self.min.lerp(self.max, t)
+    }
+
+    /// Bake direct lighting from the given lights, and optionally an environment given as a function
+    /// returning the incoming radiance for a world-space direction, into every probe of the grid.
+    ///
+    /// No visibility is computed, so probes only capture unoccluded lighting.
+    #[tracing::instrument(skip_all, fields(probes = self.len()))]
+    pub fn bake(&mut self, lights: &[Light], sky: Option<&dyn Fn(Vec3) -> Vec3>) {
+        let sky_coefficients = sky
+            .map(project_sky)
+            .unwrap_or([Vec3::ZERO; SH_COEFFICIENTS]);
+        for z in 0..self.resolution.z {
+            for y in 0..self.resolution.y {
+                for x in 0..self.resolution.x {
+                    let ix = UVec3::new(x, y, z);
+                    let position = self.probe_position(ix);
+                    let mut coeffs = sky_coefficients;
+                    for light in lights {
+                        accumulate_light(&mut coeffs, *light, position);
+                    }
+                    for (c, band) in coeffs.iter_mut().zip(BAND_FACTORS) {
+                        *c *= band;
+                    }
+                    let linear = self.linear_index(ix);
+                    self.coefficients[linear] = coeffs;
+                }
+            }
+        }
+    }
+
+    /// Store the irradiance of a lit capture of the scene around the probe, e.g. from
+    /// [`crate::Renderer::render_cubemap`], which unlike [`Self::bake`] includes the occlusion and
+    /// the indirect lighting.
+    pub fn set_from_cubemap(&mut self, ix: UVec3, cubemap: &Cubemap) {
+        let linear = self.linear_index(ix);
+        // Already convolved with the cosine lobe, so projected without the band factors
+        self.coefficients[linear] = project_sky(&|dir| cubemap.irradiance(dir));
+    }
+
+    /// Bake the grid on a separate thread, handing it back once done.
+    pub fn bake_in_background(
+        mut self,
+        lights: Vec<Light>,
+        sky: Option<Box<dyn Fn(Vec3) -> Vec3 + Send>>,
+    ) -> JoinHandle<Self> {
+        std::thread::spawn(move || {
+            self.bake(&lights, sky.as_deref().map(|f| f as &dyn Fn(Vec3) -> Vec3));
+            self
+        })
+    }
+
+    /// Evaluate the baked diffuse irradiance of a single probe along the given normal.
+    pub fn irradiance(&self, ix: UVec3, normal: Vec3) -> Vec3 {
+        let basis = sh_basis(normal.normalize());
+        self.coefficients[self.linear_index(ix)]
+            .iter()
+            .zip(basis)
+            .map(|(c, y)| *c * y)
+            .sum()
+    }
+
+    fn linear_index(&self, ix: UVec3) -> usize {
+        (ix.x + self.resolution.x * (ix.y + self.resolution.y * ix.z)) as usize
+    }
+
+    /// Pixel data for the GPU texture: one row per (y, z) pair, with the coefficients of each probe
+    /// along X laid out next to each other.
+    fn pixels(&self) -> Vec<[f32; 3]> {
+        self.coefficients
+            .iter()
+            .flat_map(|c| c.map(|v| v.to_array()))
+            .collect()
+    }
+}
+
+/// Irradiance probes uploaded to the GPU, applied as an additive screen pass over the G-Buffer.
+#[derive(Debug)]
+pub struct IrradianceProbes {
+    draw: ScreenDraw,
+    coefficients: Texture<[f32; 3]>,
+    min: Vec3,
+    max: Vec3,
+    resolution: UVec3,
+    u_position: UniformLocation,
+    u_albedo: UniformLocation,
+    u_normal: UniformLocation,
+    u_rough_metal: UniformLocation,
+    u_coefficients: UniformLocation,
+    u_grid_min: UniformLocation,
+    u_grid_max: UniformLocation,
+    u_grid_resolution: UniformLocation,
+}
+
+impl IrradianceProbes {
+    pub fn new(grid: &IrradianceProbeGrid, reload_watcher: &ReloadWatcher) -> Result<Self> {
+        let draw = ScreenDraw::load("screen/probes.glsl", reload_watcher)
+            .context("Loading irradiance probes shader")?;
+        let program = draw.program();
+        let u_position = program.uniform("frame_position");
+        let u_albedo = program.uniform("frame_albedo");
+        let u_normal = program.uniform("frame_normal");
+        let u_rough_metal = program.uniform("frame_rough_metal");
+        let u_coefficients = program.uniform("probe_coefficients");
+        let u_grid_min = program.uniform("grid_min");
+        let u_grid_max = program.uniform("grid_max");
+        let u_grid_resolution = program.uniform("grid_resolution");
+        drop(program);
+
+        let coefficients = Self::create_texture(grid)?;
+        Ok(Self {
+            draw,
+            coefficients,
+            min: grid.min,
+            max: grid.max,
+            resolution: grid.resolution,
+            u_position,
+            u_albedo,
+            u_normal,
+            u_rough_metal,
+            u_coefficients,
+            u_grid_min,
+            u_grid_max,
+            u_grid_resolution,
+        })
+    }
+
+    pub fn update(&mut self, grid: &IrradianceProbeGrid) -> Result<()> {
+        self.coefficients = Self::create_texture(grid)?;
+        self.min = grid.min;
+        self.max = grid.max;
+        self.resolution = grid.resolution;
+        Ok(())
+    }
+
+    /// Light the G-Buffer, whose positions are relative to `origin`, with the irradiance of the
+    /// probes.
+    pub fn draw(&self, frame: &Framebuffer, mat_info: MaterialInfo, origin: Vec3) -> Result<()> {
+        {
+            let program = self.draw.program();
+            program.set_uniform(self.u_position, mat_info.position.as_uniform(0).counted()?)?;
+            program.set_uniform(self.u_albedo, mat_info.albedo.as_uniform(1).counted()?)?;
+            program.set_uniform(
+                self.u_normal,
+                mat_info.normal_coverage.as_uniform(2).counted()?,
+            )?;
+            program.set_uniform(
+                self.u_rough_metal,
+                mat_info.roughness_metal.as_uniform(3).counted()?,
+            )?;
+            program.set_uniform(
+                self.u_coefficients,
+                self.coefficients.as_uniform(4).counted()?,
+            )?;
+            let relative = |p: Vec3| (p.as_dvec3() - origin.as_dvec3()).as_vec3();
+            program.set_uniform(self.u_grid_min, relative(self.min))?;
+            program.set_uniform(self.u_grid_max, relative(self.max))?;
+            program.set_uniform(self.u_grid_resolution, self.resolution.as_vec3())?;
+        }
+        self.draw.draw(frame)?;
+        Ok(())
+    }
+
+    fn create_texture(grid: &IrradianceProbeGrid) -> Result<Texture<[f32; 3]>> {
+        let width = NonZeroU32::new(grid.resolution.x * SH_COEFFICIENTS as u32).unwrap();
+        let height = NonZeroU32::new(grid.resolution.y * grid.resolution.z).unwrap();
+        let texture = Texture::new(width, height, NonZeroU32::new(1).unwrap(), Dimension::D2);
+        texture.filter_min(SampleMode::Nearest)?;
+        texture.filter_mag(SampleMode::Nearest)?;
+        texture.reserve_memory()?;
+        texture.set_data(&grid.pixels())?;
+        Ok(texture)
+    }
+}
+
+/// Cosine lobe convolution factors per band, divided by pi (Ramamoorthi & Hanrahan).
+const BAND_FACTORS: [f32; SH_COEFFICIENTS] =
+    [1., 2. / 3., 2. / 3., 2. / 3., 0.25, 0.25, 0.25, 0.25, 0.25];
+
+fn sh_basis(n: Vec3) -> [f32; SH_COEFFICIENTS] {
+    let Vec3 { x, y, z } = n;
+    [
+        0.282095,
+        0.488603 * y,
+        0.488603 * z,
+        0.488603 * x,
+        1.092548 * x * y,
+        1.092548 * y * z,
+        0.315392 * (3. * z * z - 1.),
+        1.092548 * x * z,
+        0.546274 * (x * x - y * y),
+    ]
+}
+
+fn accumulate_light(coeffs: &mut [Vec3; SH_COEFFICIENTS], light: Light, position: Vec3) {
+    match light {
+        Light::Ambient { color } => {
+            coeffs[0] += color * 0.282095 * 4. * PI;
+        }
+        Light::Directional { color, dir } => {
+            add_delta(coeffs, dir.normalize(), color);
+        }
+        Light::Point {
+            color,
+            position: light_pos,
+        } => {
+            let delta = light_pos - position;
+            let dist_sqr = delta.length_squared().max(1e-4);
+            add_delta(coeffs, delta.normalize_or_zero(), color / dist_sqr);
+        }
+        Light::Area {
+            color,
+            position: light_pos,
+            rotation,
+            shape,
+            size,
+            two_sided,
+        } => {
+            // Probes are far enough from lights to treat them as points
+            let area = match shape {
+                AreaShape::Rect => size.x * size.y,
+                AreaShape::Disk => PI * size.x * size.y / 4.,
+            };
+            let delta = light_pos - position;
+            let dist_sqr = delta.length_squared().max(1e-4);
+            let cos_light = (rotation * Vec3::Z).dot(-delta.normalize_or_zero());
+            let cos_light = if two_sided {
+                cos_light.abs()
+            } else {
+                cos_light.max(0.)
+            };
+            let intensity = color * area * cos_light;
+            add_delta(coeffs, delta.normalize_or_zero(), intensity / dist_sqr);
+        }
+    }
+}
+
+fn add_delta(coeffs: &mut [Vec3; SH_COEFFICIENTS], dir: Vec3, radiance: Vec3) {
+    for (c, y) in coeffs.iter_mut().zip(sh_basis(dir)) {
+        *c += radiance * y;
+    }
+}
+
+fn project_sky(sky: &dyn Fn(Vec3) -> Vec3) -> [Vec3; SH_COEFFICIENTS] {
+    let golden_angle = PI * (3. - 5f32.sqrt());
+    let weight = 4. * PI / SKY_SAMPLES as f32;
+    let mut coeffs = [Vec3::ZERO; SH_COEFFICIENTS];
+    for i in 0..SKY_SAMPLES {
+        let y = 1. - 2. * (i as f32 + 0.5) / SKY_SAMPLES as f32;
+        let r = (1. - y * y).sqrt();
+        let phi = golden_angle * i as f32;
+        let dir = Vec3::new(phi.cos() * r, y, phi.sin() * r);
+        add_delta(&mut coeffs, dir, sky(dir) * weight);
+    }
+    coeffs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn constant_sky_gives_constant_irradiance() {
+        let mut grid = IrradianceProbeGrid::new(Vec3::ZERO, Vec3::ONE, UVec3::ONE);
+        grid.bake(&[], Some(&|_| Vec3::ONE));
+        for n in [Vec3::X, Vec3::NEG_Y, Vec3::new(1., 1., -1.)] {
+            let e = grid.irradiance(UVec3::ZERO, n);
+            assert!((e - Vec3::ONE).abs().max_element() < 2e-2, "{e:?}");
+        }
+    }
+
+    #[test]
+    fn directional_light_lights_facing_side_only() {
+        let mut grid = IrradianceProbeGrid::new(Vec3::ZERO, Vec3::ONE, UVec3::ONE);
+        grid.bake(
+            &[Light::Directional {
+                color: Vec3::ONE,
+                dir: Vec3::Y,
+            }],
+            None,
+        );
+        let up = grid.irradiance(UVec3::ZERO, Vec3::Y);
+        let down = grid.irradiance(UVec3::ZERO, Vec3::NEG_Y);
+        assert!((up.x - 1. / PI).abs() < 0.05, "{up:?}");
+        assert!(down.x.abs() < 0.05, "{down:?}");
+    }
+
+    #[test]
+    fn cubemap_capture_gives_its_irradiance() {
+        let mut grid = IrradianceProbeGrid::new(Vec3::ZERO, Vec3::ONE, UVec3::ONE);
+        let cubemap = Cubemap {
+            resolution: 8,
+            faces: std::array::from_fn(|_| vec![[1., 0.5, 0.]; 64]),
+        };
+        grid.set_from_cubemap(UVec3::ZERO, &cubemap);
+        for n in [Vec3::X, Vec3::NEG_Y, Vec3::new(1., 1., -1.)] {
+            let e = grid.irradiance(UVec3::ZERO, n);
+            assert!(e.abs_diff_eq(Vec3::new(1., 0.5, 0.), 2e-2), "{e:?}");
+        }
+    }
+
+    #[test]
+    fn probe_positions_span_bounds() {
+        let grid = IrradianceProbeGrid::new(Vec3::ZERO, Vec3::splat(2.), UVec3::new(3, 2, 1));
+        assert_eq!(grid.probe_position(UVec3::ZERO).x, 0.);
+        assert_eq!(grid.probe_position(UVec3::new(2, 1, 0)).x, 2.);
+        assert_eq!(grid.probe_position(UVec3::new(2, 1, 0)).y, 2.);
+    }
+}
@@ -0,0 +1,81 @@
+//! Thread-safe command queue of the [`crate::Renderer`], to submit meshes and change lights and the
+//! camera from threads other than the render thread, e.g. from jobs.
+//!
+//! Commands are applied on the render thread when the frame is flushed, in the order they were
+//! sent. Meshes and materials still need to be registered on the render thread, as they own
+//! OpenGL objects; their handles can then be sent to other threads.
+
+use crossbeam_channel::Sender;
+
+use rose_core::{
+    camera::Camera,
+    light::{Light, LightHandle, LightHandleAllocator},
+    transform::Transformed,
+};
+
+use crate::{MaterialHandle, MeshHandle};
+
+#[derive(Debug, Clone)]
+pub enum RenderCommand {
+    Submit {
+        material: MaterialHandle,
+        mesh: Transformed<MeshHandle>,
+    },
+    SubmitOutline(Transformed<MeshHandle>),
+    /// Render the frame from this camera instead of the one given to
+    /// [`crate::Renderer::begin_render`].
+    SetCamera(Camera),
+    AddLight(LightHandle, Light),
+    UpdateLight(LightHandle, Light),
+    RemoveLight(LightHandle),
+}
+
+/// Sending end of the command queue of a renderer, see [`crate::Renderer::queue`]. Cheap to clone,
+/// and can be sent to other threads. Commands sent after the renderer is dropped are discarded.
+#[derive(Debug, Clone)]
+pub struct RenderQueue {
+    sender: Sender<RenderCommand>,
+    light_handles: LightHandleAllocator,
+}
+
+impl RenderQueue {
+    pub(crate) fn new(sender: Sender<RenderCommand>, light_handles: LightHandleAllocator) -> Self {
+        Self {
+            sender,
+            light_handles,
+        }
+    }
+
+    pub fn send(&self, command: RenderCommand) {
+        // Only fails when the renderer is gone, and there is nothing left to render then
+        self.sender.send(command).ok();
+    }
+
+    /// Draw the mesh with the material in the next flushed frame.
+    pub fn submit(&self, material: MaterialHandle, mesh: Transformed<MeshHandle>) {
+        self.send(RenderCommand::Submit { material, mesh });
+    }
+
+    pub fn submit_outline(&self, mesh: Transformed<MeshHandle>) {
+        self.send(RenderCommand::SubmitOutline(mesh));
+    }
+
+    pub fn set_camera(&self, camera: Camera) {
+        self.send(RenderCommand::SetCamera(camera));
+    }
+
+    /// Add a light, returning its handle right away; the light is added when the queue is drained.
+    pub fn add_light(&self, light: Light) -> LightHandle {
+        let handle = self.light_handles.allocate();
+        self.send(RenderCommand::AddLight(handle, light));
+        handle
+    }
+
+    pub fn update_light(&self, handle: LightHandle, light: Light) {
+        self.send(RenderCommand::UpdateLight(handle, light));
+    }
+
+    pub fn remove_light(&self, handle: LightHandle) {
+        self.send(RenderCommand::RemoveLight(handle));
+    }
+}
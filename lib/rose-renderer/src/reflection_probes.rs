@@ -0,0 +1,381 @@
+use std::num::NonZeroU32;
+
+use eyre::{Context, Result};
+use glam::{UVec2, Vec3};
+
+use rose_core::utils::draw_counters::CountBind;
+use rose_core::{
+    camera::{ViewUniform, ViewUniformBuffer},
+    screen_draw::ScreenDraw,
+    utils::reload_watcher::ReloadWatcher,
+};
+use violette::{
+    framebuffer::Framebuffer,
+    program::{UniformBlockIndex, UniformLocation},
+    texture::{Dimension, SampleMode, Texture},
+};
+
+use crate::{
+    cubemap,
+    env::MaterialInfo,
+    gbuffers::{GBufferSettings, GeometryBuffers},
+};
+
+/// Maximum number of reflection probes that can exist at once.
+pub const MAX_REFLECTION_PROBES: usize = 8;
+/// Side of a single captured cube face, in pixels.
+const FACE_RESOLUTION: u32 = 128;
+/// Mip levels past this one mix neighboring faces together in the atlas, and are not sampled.
+const MAX_LOD: f32 = 4.;
+
+/// Volume around a reflection probe inside which it is used. Reflections are parallax-corrected
+/// against the same volume, so it should match the surroundings the probe captures (e.g. the walls
+/// of a room).
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ProbeInfluence {
+    Box { half_extents: Vec3 },
+    Sphere { radius: f32 },
+}
+
+impl ProbeInfluence {
+    pub fn contains(&self, offset: Vec3) -> bool {
+        match *self {
+            Self::Box { half_extents } => offset.abs().cmple(half_extents).all(),
+            Self::Sphere { radius } => offset.length_squared() <= radius * radius,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ReflectionProbe {
+    pub position: Vec3,
+    pub influence: ProbeInfluence,
+    pub intensity: f32,
+    /// Near and far planes of the capture cameras.
+    pub near: f32,
+    pub far: f32,
+}
+
+impl Default for ReflectionProbe {
+    fn default() -> Self {
+        Self {
+            position: Vec3::ZERO,
+            influence: ProbeInfluence::Box {
+                half_extents: Vec3::splat(5.),
+            },
+            intensity: 1.,
+            near: 0.05,
+            far: 100.,
+        }
+    }
+}
+
+impl ReflectionProbe {
+    /// View of the capture camera for the given cube face, laid out in the atlas in the order of
+    /// [`cubemap::CUBE_FACES`].
+    pub fn face_view(&self, face: usize) -> ViewUniform {
+        cubemap::face_view(self.position, face, self.near, self.far, FACE_RESOLUTION)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct ReflectionProbeId(usize);
+
+#[derive(Debug)]
+struct Slot {
+    probe: ReflectionProbe,
+    baked: bool,
+    pending_bake: bool,
+}
+
+/// Reflection probes captured into a single atlas texture, where each probe takes a 3x2 block of
+/// cube faces. Applied as an additive screen pass over the G-Buffer, each pixel using the closest
+/// probe whose influence volume contains it.
+#[derive(Debug)]
+pub struct ReflectionProbes {
+    slots: Vec<Option<Slot>>,
+    atlas: Texture<[f32; 3]>,
+    atlas_fbo: Framebuffer,
+    params: Texture<[f32; 4]>,
+    capture: GeometryBuffers,
+    blit: ScreenDraw,
+    u_blit_texture: UniformLocation,
+    draw: ScreenDraw,
+    u_view: UniformBlockIndex,
+    u_position: UniformLocation,
+    u_albedo: UniformLocation,
+    u_normal: UniformLocation,
+    u_rough_metal: UniformLocation,
+    u_atlas: UniformLocation,
+    u_params: UniformLocation,
+    u_probe_count: UniformLocation,
+    u_max_lod: UniformLocation,
+}
+
+impl ReflectionProbes {
+    pub fn new(reload_watcher: &ReloadWatcher) -> Result<Self> {
+        let width = NonZeroU32::new(3 * FACE_RESOLUTION).unwrap();
+        let height = NonZeroU32::new(2 * FACE_RESOLUTION * MAX_REFLECTION_PROBES as u32).unwrap();
+        let atlas = Texture::new(width, height, NonZeroU32::new(1).unwrap(), Dimension::D2);
+        atlas.filter_min_mipmap(SampleMode::Linear, SampleMode::Linear)?;
+        atlas.filter_mag(SampleMode::Linear)?;
+        atlas.reserve_memory()?;
+
+        let atlas_fbo = Framebuffer::new();
+        atlas_fbo.attach_color(0, atlas.mipmap(0).unwrap())?;
+        atlas_fbo.enable_buffers([0])?;
+        atlas_fbo.assert_complete()?;
+
+        let params = Texture::new(
+            NonZeroU32::new(2).unwrap(),
+            NonZeroU32::new(MAX_REFLECTION_PROBES as _).unwrap(),
+            NonZeroU32::new(1).unwrap(),
+            Dimension::D2,
+        );
+        params.filter_min(SampleMode::Nearest)?;
+        params.filter_mag(SampleMode::Nearest)?;
+        params.reserve_memory()?;
+
+        // Captures are small, keep them at full precision
+        let capture = GeometryBuffers::new(
+            UVec2::splat(FACE_RESOLUTION),
+            GBufferSettings::default(),
+            reload_watcher,
+        )
+        .context("Creating reflection probe capture buffers")?;
+        let blit =
+            ScreenDraw::load("blit.glsl", reload_watcher).context("Cannot load blit program")?;
+        let u_blit_texture = blit.program().uniform("in_texture");
+
+        let draw = ScreenDraw::load("screen/reflection_probes.glsl", reload_watcher)
+            .context("Loading reflection probes shader")?;
+        let program = draw.program();
+        let u_view = program.uniform_block("View");
+        let u_position = program.uniform("frame_position");
+        let u_albedo = program.uniform("frame_albedo");
+        let u_normal = program.uniform("frame_normal");
+        let u_rough_metal = program.uniform("frame_rough_metal");
+        let u_atlas = program.uniform("probe_atlas");
+        let u_params = program.uniform("probe_params");
+        let u_probe_count = program.uniform("probe_count");
+        let u_max_lod = program.uniform("max_lod");
+        drop(program);
+
+        Ok(Self {
+            slots: vec![],
+            atlas,
+            atlas_fbo,
+            params,
+            capture,
+            blit,
+            u_blit_texture,
+            draw,
+            u_view,
+            u_position,
+            u_albedo,
+            u_normal,
+            u_rough_metal,
+            u_atlas,
+            u_params,
+            u_probe_count,
+            u_max_lod,
+        })
+    }
+
+    /// Add a probe, which will be baked on the next frame.
+    pub fn add(&mut self, probe: ReflectionProbe) -> Result<ReflectionProbeId> {
+        let slot = Slot {
+            probe,
+            baked: false,
+            pending_bake: true,
+        };
+        let ix = if let Some(ix) = self.slots.iter().position(Option::is_none) {
+            self.slots[ix] = Some(slot);
+            ix
+        } else {
+            eyre::ensure!(
+                self.slots.len() < MAX_REFLECTION_PROBES,
+                "Cannot have more than {} reflection probes",
+                MAX_REFLECTION_PROBES
+            );
+            self.slots.push(Some(slot));
+            self.slots.len() - 1
+        };
+        Ok(ReflectionProbeId(ix))
+    }
+
+    pub fn get(&self, id: ReflectionProbeId) -> Option<&ReflectionProbe> {
+        self.slots.get(id.0)?.as_ref().map(|slot| &slot.probe)
+    }
+
+    /// Change the parameters of a probe. This does not re-bake it; call [`Self::bake`] for that.
+    /// Returns whether anything changed.
+    pub fn update(&mut self, id: ReflectionProbeId, probe: ReflectionProbe) -> Result<bool> {
+        let slot = self.slot_mut(id)?;
+        let changed = slot.probe != probe;
+        slot.probe = probe;
+        Ok(changed)
+    }
+
+    /// Request the probe to be captured again on the next frame.
+    pub fn bake(&mut self, id: ReflectionProbeId) -> Result<()> {
+        self.slot_mut(id)?.pending_bake = true;
+        Ok(())
+    }
+
+    pub fn remove(&mut self, id: ReflectionProbeId) {
+        if let Some(slot) = self.slots.get_mut(id.0) {
+            slot.take();
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots.iter().all(Option::is_none)
+    }
+
+    /// Probes waiting to be baked, clearing the requests. They are considered baked from then on.
+    pub(crate) fn take_pending(&mut self) -> Vec<(ReflectionProbeId, ReflectionProbe)> {
+        self.slots
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(ix, slot)| {
+                let slot = slot.as_mut()?;
+                let pending = std::mem::take(&mut slot.pending_bake);
+                slot.baked |= pending;
+                pending.then_some((ReflectionProbeId(ix), slot.probe))
+            })
+            .collect()
+    }
+
+    /// Buffers the scene is rendered into when capturing a face.
+    pub(crate) fn capture_buffers(&self) -> &GeometryBuffers {
+        &self.capture
+    }
+
+    /// Copy a captured face into the atlas.
+    pub(crate) fn store_face(
+        &self,
+        id: ReflectionProbeId,
+        face: usize,
+        captured: &Texture<[f32; 3]>,
+    ) -> Result<()> {
+        let res = FACE_RESOLUTION as i32;
+        let column = (face % 3) as i32;
+        let row = (2 * id.0 + face / 3) as i32;
+        Framebuffer::viewport(column * res, row * res, res, res);
+        Framebuffer::disable_blending();
+        self.blit
+            .program()
+            .set_uniform(self.u_blit_texture, captured.as_uniform(0).counted()?)?;
+        self.blit.draw(&self.atlas_fbo)?;
+        Ok(())
+    }
+
+    /// Refresh the atlas mipmaps after faces have been stored.
+    pub(crate) fn finish_bake(&self) -> Result<()> {
+        self.atlas.generate_mipmaps()?;
+        Ok(())
+    }
+
+    /// Draw the weighted probe reflections additively into the first attachment of `frame`, and
+    /// the weights into its second attachment, for the environment to scale its own reflections.
+    /// Positions of the G-Buffer are relative to `origin`, the origin of the view.
+    #[tracing::instrument(skip_all)]
+    pub fn draw(
+        &self,
+        frame: &Framebuffer,
+        view: &ViewUniformBuffer,
+        mat_info: MaterialInfo,
+        origin: Vec3,
+    ) -> Result<()> {
+        let baked = self
+            .slots
+            .iter()
+            .enumerate()
+            .filter_map(|(ix, slot)| Some((ix, slot.as_ref().filter(|s| s.baked)?)))
+            .collect::<Vec<_>>();
+        if baked.is_empty() {
+            return Ok(());
+        }
+        let mut texels = baked
+            .iter()
+            .flat_map(|(ix, slot)| probe_texels(*ix, &slot.probe, origin))
+            .collect::<Vec<_>>();
+        // Only the first `probe_count` rows are read by the shader
+        texels.resize(2 * MAX_REFLECTION_PROBES, [0.; 4]);
+        self.params.set_data(&texels)?;
+
+        {
+            let program = self.draw.program();
+            program.bind_block(&view.slice(0..=0), self.u_view, 0)?;
+            program.set_uniform(self.u_position, mat_info.position.as_uniform(0).counted()?)?;
+            program.set_uniform(self.u_albedo, mat_info.albedo.as_uniform(1).counted()?)?;
+            program.set_uniform(
+                self.u_normal,
+                mat_info.normal_coverage.as_uniform(2).counted()?,
+            )?;
+            program.set_uniform(
+                self.u_rough_metal,
+                mat_info.roughness_metal.as_uniform(3).counted()?,
+            )?;
+            program.set_uniform(self.u_atlas, self.atlas.as_uniform(4).counted()?)?;
+            program.set_uniform(self.u_params, self.params.as_uniform(5).counted()?)?;
+            program.set_uniform(self.u_probe_count, baked.len() as i32)?;
+            program.set_uniform(self.u_max_lod, MAX_LOD)?;
+        }
+        self.draw.draw(frame)?;
+        Ok(())
+    }
+
+    fn slot_mut(&mut self, id: ReflectionProbeId) -> Result<&mut Slot> {
+        self.slots
+            .get_mut(id.0)
+            .and_then(Option::as_mut)
+            .ok_or_else(|| eyre::eyre!("Reflection probe {:?} does not exist", id))
+    }
+}
+
+/// Two texels describing a probe for the shader: its position relative to `origin` and atlas slot,
+/// then the extents of its influence (negative X for a sphere of radius `-x`) and its intensity.
+fn probe_texels(slot: usize, probe: &ReflectionProbe, origin: Vec3) -> [[f32; 4]; 2] {
+    let extents = match probe.influence {
+        ProbeInfluence::Box { half_extents } => half_extents,
+        ProbeInfluence::Sphere { radius } => Vec3::new(-radius, 0., 0.),
+    };
+    let position = (probe.position.as_dvec3() - origin.as_dvec3()).as_vec3();
+    [
+        position.extend(slot as f32).to_array(),
+        extents.extend(probe.intensity).to_array(),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn influence_volumes() {
+        let cube = ProbeInfluence::Box {
+            half_extents: Vec3::new(1., 2., 3.),
+        };
+        assert!(cube.contains(Vec3::new(-1., 1.9, 2.5)));
+        assert!(!cube.contains(Vec3::new(0., 0., 3.5)));
+
+        let sphere = ProbeInfluence::Sphere { radius: 2. };
+        assert!(sphere.contains(Vec3::new(1., 1., 1.)));
+        assert!(!sphere.contains(Vec3::new(1.5, 1.5, 0.)));
+    }
+
+    #[test]
+    fn face_views_look_along_axes() {
+        let probe = ReflectionProbe {
+            position: Vec3::new(1., 2., 3.),
+            ..Default::default()
+        };
+        for (face, (forward, _)) in cubemap::CUBE_FACES.iter().enumerate() {
+            let view = probe.face_view(face);
+            let ahead = view.mat_view.transform_point3(probe.position + *forward);
+            assert!(ahead.abs_diff_eq(Vec3::NEG_Z, 1e-5), "face {face}: {ahead}");
+        }
+    }
+}
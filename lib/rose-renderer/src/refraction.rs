@@ -0,0 +1,238 @@
+//! Refractive transparency, e.g. for glass and clear liquids, drawn over the lit scene.
+//!
+//! Refractive meshes are submitted separately from the other meshes with
+//! [`crate::Renderer::submit_refractive`], and are not part of the G-Buffer. Once the opaque
+//! scene is lit, it is copied into a mipmapped texture which the refractive surfaces sample with
+//! an offset given by their normal and index of refraction; rougher surfaces sample coarser mipmap
+//! levels, blurring what is seen through them. Surfaces only transmit light: they are not lit, do
+//! not reflect their surroundings and do not see each other, but transmit less at grazing angles.
+
+use std::{num::NonZeroU32, path::PathBuf};
+
+use eyre::{Context, Result};
+use glam::Vec3;
+
+use rose_core::utils::draw_counters::CountBind;
+use rose_core::{
+    camera::ViewUniformBuffer,
+    screen_draw::ScreenDraw,
+    transform::Transformed,
+    utils::reload_watcher::{ReloadFileProxy, ReloadWatcher},
+};
+use violette::{
+    framebuffer::Framebuffer,
+    program::{Program, UniformBlockIndex, UniformLocation},
+    texture::{DepthStencil, Dimension, SampleMode, Texture},
+};
+
+use crate::{shader_material::link_program, streaming::mip_count, Mesh};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct RefractionParams {
+    /// Color the light going through the surface is multiplied by.
+    pub tint: Vec3,
+    /// Index of refraction, 1.5 for glass and 1.33 for water.
+    pub ior: f32,
+    /// Blurs what is seen through the surface, from 0 for clear to 1 for frosted.
+    pub roughness: f32,
+    /// Distance, in world units, the light travels inside the object before coming out, which
+    /// scales how far the refraction displaces the background.
+    pub thickness: f32,
+}
+
+impl Default for RefractionParams {
+    fn default() -> Self {
+        Self {
+            tint: Vec3::ONE,
+            ior: 1.5,
+            roughness: 0.,
+            thickness: 0.1,
+        }
+    }
+}
+
+impl RefractionParams {
+    #[cfg(feature = "debug-ui")]
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        use egui::{DragValue, Grid, Slider};
+
+        Grid::new("refraction-params")
+            .num_columns(2)
+            .show(ui, |ui| {
+                let tint_label = ui.label("Tint").id;
+                ui.color_edit_button_rgb(self.tint.as_mut())
+                    .labelled_by(tint_label);
+                ui.end_row();
+
+                let ior_label = ui.label("IOR").id;
+                ui.add(
+                    DragValue::new(&mut self.ior)
+                        .clamp_range(1.0..=3.)
+                        .speed(1e-2),
+                )
+                .labelled_by(ior_label);
+                ui.end_row();
+
+                let roughness_label = ui.label("Roughness").id;
+                ui.add(Slider::new(&mut self.roughness, 0.0..=1.))
+                    .labelled_by(roughness_label);
+                ui.end_row();
+
+                let thickness_label = ui.label("Thickness").id;
+                ui.add(
+                    DragValue::new(&mut self.thickness)
+                        .clamp_range(0.0..=100.)
+                        .speed(1e-2),
+                )
+                .labelled_by(thickness_label);
+                ui.end_row();
+            });
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+struct RefractionLocations {
+    view: UniformBlockIndex,
+    model: UniformLocation,
+    scene_color: UniformLocation,
+    scene_depth: UniformLocation,
+    max_lod: UniformLocation,
+    tint: UniformLocation,
+    ior: UniformLocation,
+    roughness: UniformLocation,
+    thickness: UniformLocation,
+}
+
+impl RefractionLocations {
+    fn new(program: &Program) -> Self {
+        Self {
+            view: program.uniform_block("View"),
+            model: program.uniform("model"),
+            scene_color: program.uniform("scene_color"),
+            scene_depth: program.uniform("scene_depth"),
+            max_lod: program.uniform("max_lod"),
+            tint: program.uniform("tint"),
+            ior: program.uniform("ior"),
+            roughness: program.uniform("roughness"),
+            thickness: program.uniform("thickness"),
+        }
+    }
+}
+
+/// Forward pass drawing the refractive surfaces over the lit scene.
+#[derive(Debug)]
+pub struct Refraction {
+    program: Program,
+    locations: RefractionLocations,
+    proxy: ReloadFileProxy,
+    vertex: PathBuf,
+    fragment: PathBuf,
+    blit: ScreenDraw,
+    u_blit_texture: UniformLocation,
+    /// Copy of the lit scene with its mipmaps, created on the first frame with refractive surfaces.
+    scene_color: Option<(Texture<[f32; 3]>, Framebuffer)>,
+}
+
+impl Refraction {
+    pub fn new(reload_watcher: &ReloadWatcher) -> Result<Self> {
+        let vertex = reload_watcher.base_path().join("mesh/mesh.vert.glsl");
+        let fragment = reload_watcher.base_path().join("mesh/refraction.frag.glsl");
+        let (program, files) =
+            link_program(&vertex, &fragment).context("Loading refraction program")?;
+        let blit =
+            ScreenDraw::load("blit.glsl", reload_watcher).context("Cannot load blit program")?;
+        let u_blit_texture = blit.program().uniform("in_texture");
+        Ok(Self {
+            locations: RefractionLocations::new(&program),
+            program,
+            proxy: reload_watcher.proxy(files.iter().map(|p| p.as_path())),
+            vertex,
+            fragment,
+            blit,
+            u_blit_texture,
+            scene_color: None,
+        })
+    }
+
+    /// Reload the shaders if they changed.
+    pub fn reload_if_needed(&mut self) {
+        let reloaded = self
+            .proxy
+            .reload_shader("refraction shader", &self.fragment, || {
+                link_program(&self.vertex, &self.fragment)
+            });
+        if let Some(Ok(program)) = reloaded {
+            self.locations = RefractionLocations::new(&program);
+            self.program = program;
+        }
+    }
+
+    /// Copy `scene` into the mipmapped scene color texture, (re)creating it at the size of
+    /// `scene`.
+    fn capture_scene(&mut self, scene: &Texture<[f32; 3]>) -> Result<&Texture<[f32; 3]>> {
+        let size = scene.size_vec().truncate();
+        let Some(width) = NonZeroU32::new(size.x) else { eyre::bail!("Zero width scene"); };
+        let Some(height) = NonZeroU32::new(size.y) else { eyre::bail!("Zero height scene"); };
+        match &mut self.scene_color {
+            Some((texture, _)) if texture.size_vec().truncate() != size => {
+                texture.clear_resize(width, height, NonZeroU32::new(1).unwrap())?;
+            }
+            Some(_) => {}
+            None => {
+                let texture =
+                    Texture::new(width, height, NonZeroU32::new(1).unwrap(), Dimension::D2);
+                texture.filter_min_mipmap(SampleMode::Linear, SampleMode::Linear)?;
+                texture.filter_mag(SampleMode::Linear)?;
+                texture.reserve_memory()?;
+                let fbo = Framebuffer::new();
+                fbo.attach_color(0, texture.mipmap(0).unwrap())?;
+                fbo.enable_buffers([0])?;
+                fbo.assert_complete()?;
+                self.scene_color = Some((texture, fbo));
+            }
+        }
+        let (texture, fbo) = self.scene_color.as_ref().unwrap();
+        Framebuffer::viewport(0, 0, size.x as _, size.y as _);
+        Framebuffer::disable_blending();
+        self.blit
+            .program()
+            .set_uniform(self.u_blit_texture, scene.as_uniform(0).counted()?)?;
+        self.blit.draw(fbo)?;
+        texture.generate_mipmaps()?;
+        Ok(texture)
+    }
+
+    /// Draw the refractive surfaces over `frame`, which holds the lit `scene`, hiding them behind
+    /// the geometry of `depth`.
+    #[tracing::instrument(skip_all)]
+    pub fn draw<'a>(
+        &mut self,
+        frame: &Framebuffer,
+        scene: &Texture<[f32; 3]>,
+        view: &ViewUniformBuffer,
+        depth: &Texture<DepthStencil<f32, ()>>,
+        meshes: impl IntoIterator<Item = (Transformed<&'a Mesh>, RefractionParams)>,
+    ) -> Result<()> {
+        let scene_color = self.capture_scene(scene)?;
+        let max_lod = mip_count(scene_color.size_vec().truncate()) - 1;
+        let program = &self.program;
+        let locations = self.locations;
+        let [w, h] = scene_color.size_vec().truncate().to_array();
+        Framebuffer::viewport(0, 0, w as _, h as _);
+        program.bind_block(&view.slice(0..=0), locations.view, 0)?;
+        program.set_uniform(locations.scene_color, scene_color.as_uniform(0).counted()?)?;
+        program.set_uniform(locations.scene_depth, depth.as_uniform(1).counted()?)?;
+        program.set_uniform(locations.max_lod, max_lod as f32)?;
+        // Surfaces replace the scene behind them, which they already sample
+        Framebuffer::disable_blending();
+        for (mesh, params) in meshes {
+            program.set_uniform(locations.tint, params.tint)?;
+            program.set_uniform(locations.ior, params.ior.max(1.))?;
+            program.set_uniform(locations.roughness, params.roughness.clamp(0., 1.))?;
+            program.set_uniform(locations.thickness, params.thickness.max(0.))?;
+            program.set_uniform(locations.model, mesh.transform.matrix())?;
+            mesh.draw(program, frame, false)?;
+        }
+        Ok(())
+    }
+}
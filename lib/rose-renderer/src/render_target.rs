@@ -0,0 +1,78 @@
+//! Offscreen render targets, into which the queued meshes are rendered and lit from another view
+//! than the camera's, e.g. for planar reflections or in-world screens.
+//!
+//! Targets are named, created on their first render and kept across frames, resized to the size of
+//! the last render. Applications request renders with [`crate::Renderer::request_render_target`],
+//! and read the lit result with [`RenderTargets::output`] once the frame is flushed.
+
+use std::collections::HashMap;
+
+use eyre::{Context, Result};
+use glam::UVec2;
+
+use rose_core::{camera::ViewUniform, utils::reload_watcher::ReloadWatcher};
+use violette::texture::Texture;
+
+use crate::gbuffers::{GBufferSettings, GeometryBuffers};
+
+/// View of the scene to render into a target.
+#[derive(Debug, Copy, Clone)]
+pub struct RenderTargetView {
+    pub view: ViewUniform,
+    pub size: UVec2,
+    /// Formats of the target attachments, only used when the target is created.
+    pub settings: GBufferSettings,
+    /// Whether the view mirrors the scene, which flips the winding of the triangles.
+    pub mirrored: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct RenderTargets {
+    targets: HashMap<String, GeometryBuffers>,
+    requests: Vec<(String, RenderTargetView)>,
+}
+
+impl RenderTargets {
+    /// Lit color of the last render into the target, if it was rendered at all.
+    pub fn output(&self, name: &str) -> Option<&Texture<[f32; 3]>> {
+        self.targets.get(name).map(|target| target.output())
+    }
+
+    /// Free the buffers of the target.
+    pub fn remove(&mut self, name: &str) -> bool {
+        self.targets.remove(name).is_some()
+    }
+
+    pub(crate) fn request(&mut self, name: String, view: RenderTargetView) {
+        self.requests.retain(|(requested, _)| *requested != name);
+        self.requests.push((name, view));
+    }
+
+    pub(crate) fn take_requests(&mut self) -> Vec<(String, RenderTargetView)> {
+        std::mem::take(&mut self.requests)
+    }
+
+    /// Take the buffers of the target out to render into them, creating or resizing them as
+    /// needed. Give them back with [`Self::put_back`].
+    pub(crate) fn take(
+        &mut self,
+        name: &str,
+        view: &RenderTargetView,
+        reload_watcher: &ReloadWatcher,
+    ) -> Result<GeometryBuffers> {
+        match self.targets.remove(name) {
+            Some(mut target) => {
+                if target.size() != view.size {
+                    target.resize(view.size)?;
+                }
+                Ok(target)
+            }
+            None => GeometryBuffers::new(view.size, view.settings, reload_watcher)
+                .with_context(|| format!("Creating render target {}", name)),
+        }
+    }
+
+    pub(crate) fn put_back(&mut self, name: &str, target: GeometryBuffers) {
+        self.targets.insert(name.to_string(), target);
+    }
+}
@@ -0,0 +1,213 @@
+use std::time::Duration;
+
+use eyre::{Context, Result};
+use glam::UVec2;
+
+use rose_core::utils::draw_counters::CountBind;
+use rose_core::{screen_draw::ScreenDraw, utils::reload_watcher::ReloadWatcher};
+use violette::{framebuffer::Framebuffer, program::UniformLocation, texture::Texture};
+
+use crate::postprocess::{PooledTexture, TexturePool};
+
+/// Number of frames averaged before deciding to change the render scale.
+const SAMPLE_COUNT: usize = 30;
+
+/// Picks the fraction of the output resolution the scene is rendered at, from recent frame times.
+#[derive(Debug, Clone)]
+pub struct ResolutionScaling {
+    pub enabled: bool,
+    pub target_frame_time: Duration,
+    pub min_scale: f32,
+    pub max_scale: f32,
+    /// Amount the scale changes by at once. Changing the scale reallocates the G-Buffer, so this
+    /// should not be too small.
+    pub step: f32,
+    scale: f32,
+    samples: Vec<Duration>,
+}
+
+impl Default for ResolutionScaling {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            target_frame_time: Duration::from_secs_f32(1. / 60.),
+            min_scale: 0.5,
+            max_scale: 1.,
+            step: 0.1,
+            scale: 1.,
+            samples: Vec::with_capacity(SAMPLE_COUNT),
+        }
+    }
+}
+
+impl ResolutionScaling {
+    /// Current render scale, always 1 when disabled.
+    pub fn scale(&self) -> f32 {
+        if self.enabled {
+            self.scale
+        } else {
+            1.
+        }
+    }
+
+    pub fn render_size(&self, output_size: UVec2) -> UVec2 {
+        (output_size.as_vec2() * self.scale())
+            .round()
+            .as_uvec2()
+            .max(UVec2::ONE)
+    }
+
+    /// Record the duration of a frame, adjusting the scale once enough frames have been recorded.
+    /// Returns whether the scale changed.
+    pub fn record_frame(&mut self, frame_time: Duration) -> bool {
+        if !self.enabled {
+            self.samples.clear();
+            return false;
+        }
+        self.samples.push(frame_time);
+        if self.samples.len() < SAMPLE_COUNT {
+            return false;
+        }
+        let average = self.samples.drain(..).sum::<Duration>() / SAMPLE_COUNT as u32;
+        let ratio = average.as_secs_f32() / self.target_frame_time.as_secs_f32();
+        let scale = if ratio > 1.2 {
+            self.scale - self.step
+        } else if ratio < 1.05 {
+            self.scale + self.step
+        } else {
+            self.scale
+        }
+        .clamp(self.min_scale, self.max_scale);
+        let changed = (scale - self.scale).abs() > f32::EPSILON;
+        self.scale = scale;
+        changed
+    }
+
+    #[cfg(feature = "debug-ui")]
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        use egui::{DragValue, Grid};
+
+        Grid::new("resolution-scaling")
+            .num_columns(2)
+            .show(ui, |ui| {
+                let enabled_label = ui.label("Enabled").id;
+                ui.checkbox(&mut self.enabled, "")
+                    .labelled_by(enabled_label);
+                ui.end_row();
+
+                let target_label = ui.label("Target frame rate").id;
+                let mut fps = 1. / self.target_frame_time.as_secs_f32();
+                ui.add(
+                    DragValue::new(&mut fps)
+                        .clamp_range(10..=240)
+                        .suffix(" FPS"),
+                )
+                .labelled_by(target_label);
+                self.target_frame_time = Duration::from_secs_f32(1. / fps);
+                ui.end_row();
+
+                let min_label = ui.label("Minimum scale").id;
+                ui.add(
+                    DragValue::new(&mut self.min_scale)
+                        .clamp_range(0.1..=self.max_scale)
+                        .speed(0.01),
+                )
+                .labelled_by(min_label);
+                ui.end_row();
+
+                let max_label = ui.label("Maximum scale").id;
+                ui.add(
+                    DragValue::new(&mut self.max_scale)
+                        .clamp_range(self.min_scale..=1.)
+                        .speed(0.01),
+                )
+                .labelled_by(max_label);
+                ui.end_row();
+
+                ui.label("Current scale");
+                ui.label(format!("{:.0} %", self.scale() * 100.));
+                ui.end_row();
+            });
+    }
+}
+
+/// Bilinear upscale of the scaled-down lighting output back to the output resolution, into a
+/// texture taken from the post processing [`TexturePool`]. The input texture is expected to use
+/// linear filtering.
+#[derive(Debug)]
+pub struct Upscaler {
+    blit: ScreenDraw,
+    u_texture: UniformLocation,
+}
+
+impl Upscaler {
+    pub fn new(reload_watcher: &ReloadWatcher) -> Result<Self> {
+        let blit =
+            ScreenDraw::load("blit.glsl", reload_watcher).context("Cannot load blit program")?;
+        let u_texture = blit.program().uniform("in_texture");
+        Ok(Self { blit, u_texture })
+    }
+
+    #[tracing::instrument(skip_all)]
+    pub fn process(
+        &self,
+        pool: &mut TexturePool,
+        input: &Texture<[f32; 3]>,
+        size: UVec2,
+    ) -> Result<PooledTexture> {
+        let output = pool.acquire(size)?;
+        Framebuffer::viewport(0, 0, size.x as _, size.y as _);
+        self.blit
+            .program()
+            .set_uniform(self.u_texture, input.as_uniform(0).counted()?)?;
+        self.blit.draw(output.framebuffer())?;
+        Ok(output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn feed(scaling: &mut ResolutionScaling, frame_time: Duration) -> bool {
+        (0..SAMPLE_COUNT).any(|_| scaling.record_frame(frame_time))
+    }
+
+    #[test]
+    fn slow_frames_lower_scale_down_to_minimum() {
+        let mut scaling = ResolutionScaling {
+            enabled: true,
+            ..Default::default()
+        };
+        let slow = scaling.target_frame_time * 2;
+        for _ in 0..10 {
+            feed(&mut scaling, slow);
+        }
+        assert_eq!(scaling.scale(), scaling.min_scale);
+        assert_eq!(
+            scaling.render_size(UVec2::new(1920, 1080)),
+            UVec2::new(960, 540)
+        );
+    }
+
+    #[test]
+    fn fast_frames_raise_scale_back() {
+        let mut scaling = ResolutionScaling {
+            enabled: true,
+            ..Default::default()
+        };
+        let target = scaling.target_frame_time;
+        feed(&mut scaling, target * 2);
+        assert!(scaling.scale() < 1.);
+        assert!(feed(&mut scaling, target / 2));
+        assert_eq!(scaling.scale(), 1.);
+        assert!(!feed(&mut scaling, target / 2));
+    }
+
+    #[test]
+    fn disabled_keeps_full_resolution() {
+        let mut scaling = ResolutionScaling::default();
+        assert!(!feed(&mut scaling, Duration::from_secs(1)));
+        assert_eq!(scaling.scale(), 1.);
+    }
+}
@@ -0,0 +1,430 @@
+use std::{
+    any::Any,
+    cell::{Cell, RefCell},
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use crevice::std140::AsStd140;
+use eyre::{Context, Result};
+use glam::Vec4;
+
+use rose_core::{
+    camera::ViewUniformBuffer,
+    transform::Transformed,
+    utils::{
+        reload_watcher::{ReloadFileProxy, ReloadWatcher},
+        shader_errors::file_map,
+    },
+};
+use violette::{
+    buffer::UniformBuffer,
+    framebuffer::Framebuffer,
+    program::{Program, UniformBlockIndex, UniformLocation},
+    shader::{FragmentShader, VertexShader},
+    Cull, FrontFace,
+};
+
+use crate::{
+    bones::Std140GpuBone, clip::ClipLocations, gbuffers::validate_gbuffer_outputs,
+    morph::MorphLocations, DrawMaterial, Mesh,
+};
+
+/// Template the surface shader snippets are spliced into, relative to the shaders base path.
+const SURFACE_TEMPLATE: &str = "mesh/surface.glsl";
+/// Standard vertex shader of meshes, relative to the shaders base path.
+const MESH_VERTEX_SHADER: &str = "mesh/mesh.vert.glsl";
+
+/// Builder for [`ShaderMaterial`], a [`DrawMaterial`] made of a vertex and fragment shader and a
+/// typed uniform block.
+///
+/// The shaders get the `View` block bound at binding 0, the uniform struct bound at binding 1 under
+/// the configured block name (`Uniforms` by default), and the `model` matrix uniform set per mesh,
+/// relative to the origin of the view.
+///
+/// Materials using the standard vertex shader at `mesh/mesh.vert.glsl`, as surface shaders do,
+/// also get the `Bones` block bound at binding 2 and the morph targets of each mesh, and are
+/// clipped by the clip planes of the renderer. Custom vertex shaders are drawn without them.
+#[derive(Debug, Clone)]
+pub struct ShaderMaterialBuilder {
+    vertex: PathBuf,
+    fragment: PathBuf,
+    uniform_block: String,
+    front_face: FrontFace,
+    cull: Option<Cull>,
+    /// The fragment shader is a surface shader snippet, see [`Self::surface`].
+    surface: bool,
+}
+
+impl ShaderMaterialBuilder {
+    /// Create a builder from shader paths relative to the base path of the reload watcher.
+    pub fn new(vertex: impl Into<PathBuf>, fragment: impl Into<PathBuf>) -> Self {
+        Self {
+            vertex: vertex.into(),
+            fragment: fragment.into(),
+            uniform_block: "Uniforms".to_string(),
+            front_face: FrontFace::CounterClockwise,
+            cull: Some(Cull::Back),
+            surface: false,
+        }
+    }
+
+    /// Create a builder for a surface shader, from a snippet path relative to the base path of the
+    /// reload watcher. The snippet only computes the G-Buffer values of each fragment: it defines
+    ///
+    /// ```glsl
+    /// void surface(SurfaceInput i, inout Surface s)
+    /// ```
+    ///
+    /// and is spliced into the template at `mesh/surface.glsl`, which declares both structs,
+    /// includes the view and math helpers, and writes the G-Buffer. Meshes are transformed by the
+    /// standard vertex shader. The snippet declares its own uniform block and textures, and is
+    /// hot-reloaded along with the template. `mesh/surface_example.glsl` is a complete snippet,
+    /// reading a `Stripes { vec3 color; float frequency; }` uniform block:
+    /// `ShaderMaterialBuilder::surface("mesh/surface_example.glsl").uniform_block("Stripes")`.
+    pub fn surface(snippet: impl Into<PathBuf>) -> Self {
+        Self {
+            surface: true,
+            ..Self::new(MESH_VERTEX_SHADER, snippet)
+        }
+    }
+
+    pub fn uniform_block(mut self, name: impl Into<String>) -> Self {
+        self.uniform_block = name.into();
+        self
+    }
+
+    pub fn front_face(mut self, front_face: FrontFace) -> Self {
+        self.front_face = front_face;
+        self
+    }
+
+    pub fn culling(mut self, cull: Option<Cull>) -> Self {
+        self.cull = cull;
+        self
+    }
+
+    pub fn build<U: AsStd140 + fmt::Debug + 'static>(
+        self,
+        uniforms: U,
+        reload_watcher: &ReloadWatcher,
+    ) -> Result<ShaderMaterial<U>> {
+        let vertex = reload_watcher.base_path().join(&self.vertex);
+        let fragment = reload_watcher.base_path().join(&self.fragment);
+        let base_path = reload_watcher.base_path();
+        let (program, files) = if self.surface {
+            link_surface_program(base_path, &vertex, &fragment)?
+        } else {
+            link_gbuffer_program(&vertex, &fragment)?
+        };
+        let locations = ShaderLocations::new(&program, &self.uniform_block);
+        let buffer = UniformBuffer::with_data(&[uniforms.as_std140()])?;
+        Ok(ShaderMaterial {
+            program: RefCell::new(program),
+            locations: Cell::new(locations),
+            mesh_vertex: self.vertex == Path::new(MESH_VERTEX_SHADER),
+            bones_uniform: RefCell::new(UniformBuffer::new()),
+            clip_planes: RefCell::new(vec![]),
+            proxy: reload_watcher.proxy(files.iter().map(|p| p.as_path())),
+            base_path: reload_watcher.base_path().to_path_buf(),
+            error_program: RefCell::new(None),
+            buffer,
+            uniforms: RefCell::new(uniforms),
+            vertex,
+            fragment,
+            uniform_block: self.uniform_block,
+            front_face: self.front_face,
+            cull: self.cull,
+            surface: self.surface,
+        })
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+struct ShaderLocations {
+    view: UniformBlockIndex,
+    uniforms: UniformBlockIndex,
+    model: UniformLocation,
+    bones: UniformBlockIndex,
+    morph: MorphLocations,
+    clip: ClipLocations,
+}
+
+impl ShaderLocations {
+    fn new(program: &Program, uniform_block: &str) -> Self {
+        Self {
+            view: program.uniform_block("View"),
+            uniforms: program.uniform_block(uniform_block),
+            model: program.uniform("model"),
+            bones: program.uniform_block("Bones"),
+            morph: MorphLocations::new(program),
+            clip: ClipLocations::new(program),
+        }
+    }
+}
+
+/// Material drawing meshes with user-provided shaders and uniforms. Create it with a
+/// [`ShaderMaterialBuilder`].
+pub struct ShaderMaterial<U: AsStd140> {
+    program: RefCell<Program>,
+    locations: Cell<ShaderLocations>,
+    /// The vertex shader is the standard one, which skins, morphs and clips the meshes.
+    mesh_vertex: bool,
+    bones_uniform: RefCell<UniformBuffer<Std140GpuBone>>,
+    /// Clip planes relative to the origin of the view, see [`DrawMaterial::set_clip_planes`].
+    clip_planes: RefCell<Vec<Vec4>>,
+    proxy: ReloadFileProxy,
+    base_path: PathBuf,
+    /// Stands in for the program while the shaders fail to compile.
+    error_program: RefCell<Option<ErrorProgram>>,
+    buffer: UniformBuffer<U::Output>,
+    uniforms: RefCell<U>,
+    vertex: PathBuf,
+    fragment: PathBuf,
+    uniform_block: String,
+    front_face: FrontFace,
+    cull: Option<Cull>,
+    surface: bool,
+}
+
+impl<U: AsStd140 + fmt::Debug> fmt::Debug for ShaderMaterial<U> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ShaderMaterial")
+            .field("vertex", &self.vertex)
+            .field("fragment", &self.fragment)
+            .field("uniforms", &self.uniforms)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<U: AsStd140> ShaderMaterial<U> {
+    pub fn uniforms(&self) -> std::cell::Ref<U> {
+        self.uniforms.borrow()
+    }
+
+    /// Replace the uniform values and upload them to the GPU.
+    pub fn set_uniforms(&self, uniforms: U) -> Result<()> {
+        let mut slice = self.buffer.slice(0..=0);
+        slice.set(0, &uniforms.as_std140())?;
+        *self.uniforms.borrow_mut() = uniforms;
+        Ok(())
+    }
+
+    /// Modify the uniform values in place, and upload them to the GPU.
+    pub fn update_uniforms(&self, func: impl FnOnce(&mut U)) -> Result<()> {
+        let mut uniforms = self.uniforms.borrow_mut();
+        func(&mut uniforms);
+        let mut slice = self.buffer.slice(0..=0);
+        slice.set(0, &uniforms.as_std140())?;
+        Ok(())
+    }
+
+    fn reload_if_needed(&self) {
+        let reloaded = self
+            .proxy
+            .reload_shader("shader material", &self.fragment, || {
+                if self.surface {
+                    link_surface_program(&self.base_path, &self.vertex, &self.fragment)
+                } else {
+                    link_gbuffer_program(&self.vertex, &self.fragment)
+                }
+            });
+        match reloaded {
+            None => {}
+            Some(Ok(program)) => {
+                self.locations
+                    .set(ShaderLocations::new(&program, &self.uniform_block));
+                *self.program.borrow_mut() = program;
+                self.error_program.replace(None);
+            }
+            Some(Err(_)) => {
+                let mut error_program = self.error_program.borrow_mut();
+                if error_program.is_none() {
+                    match ErrorProgram::load(&self.base_path) {
+                        Ok(program) => *error_program = Some(program),
+                        Err(err) => tracing::error!("Cannot load error program: {:?}", err),
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<U: AsStd140 + fmt::Debug + 'static> DrawMaterial for ShaderMaterial<U> {
+    fn draw<'a>(
+        &self,
+        frame: &Framebuffer,
+        view: &ViewUniformBuffer,
+        meshes: &mut dyn Iterator<Item = Transformed<&'a Mesh>>,
+    ) -> Result<()> {
+        self.reload_if_needed();
+        let mut bones_uniform = self.bones_uniform.borrow_mut();
+        if let Some(error_program) = &*self.error_program.borrow() {
+            for mesh in meshes {
+                if let Some(root_bone) = &mesh.root_bone {
+                    root_bone.update_buffer(&mut bones_uniform, mesh.skinning)?;
+                }
+                error_program.bind(view, Some(&bones_uniform))?;
+                error_program.draw_mesh(frame, mesh)?;
+            }
+            return Ok(());
+        }
+        let program = self.program.borrow();
+        let locations = self.locations.get();
+        program.bind_block(&view.slice(0..=0), locations.view, 0)?;
+        program.bind_block(&self.buffer.slice(0..=0), locations.uniforms, 1)?;
+        if self.mesh_vertex {
+            program.bind_block(&bones_uniform.slice(..), locations.bones, 2)?;
+            locations.clip.set(&program, &self.clip_planes.borrow())?;
+        }
+        violette::set_front_face(self.front_face);
+        violette::culling(self.cull);
+        for mesh in meshes {
+            if self.mesh_vertex {
+                if let Some(root_bone) = &mesh.root_bone {
+                    root_bone.update_buffer(&mut bones_uniform, mesh.skinning)?;
+                }
+                locations.morph.bind(&program, &mesh)?;
+            }
+            program.set_uniform(locations.model, mesh.transform.matrix())?;
+            mesh.draw(&program, frame, false)?;
+        }
+        violette::set_front_face(FrontFace::CounterClockwise);
+        violette::culling(Some(Cull::Back));
+        Ok(())
+    }
+
+    fn set_clip_planes(&self, planes: &[Vec4]) -> usize {
+        if !self.mesh_vertex {
+            return 0;
+        }
+        let mut clip_planes = self.clip_planes.borrow_mut();
+        clip_planes.clear();
+        clip_planes.extend_from_slice(planes);
+        planes.len()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// Same as [`link_program`], additionally checking the fragment shader outputs against the G-Buffer
+/// layout.
+pub(crate) fn link_gbuffer_program(
+    vertex: &Path,
+    fragment: &Path,
+) -> Result<(Program, Vec<PathBuf>)> {
+    let frag_files = glsl_preprocessor::load_and_parse(fragment)
+        .with_context(|| format!("Parsing fragment shader {}", fragment.display()))?;
+    validate_gbuffer_outputs(frag_files.iter().map(|(_, s)| s.as_str()))
+        .with_context(|| format!("Validating fragment shader {}", fragment.display()))?;
+    link_program(vertex, fragment)
+}
+
+/// Splice the surface shader snippet into the surface template and link it with the vertex shader,
+/// see [`ShaderMaterialBuilder::surface`].
+pub(crate) fn link_surface_program(
+    base_path: &Path,
+    vertex: &Path,
+    snippet: &Path,
+) -> Result<(Program, Vec<PathBuf>)> {
+    let template = base_path.join(SURFACE_TEMPLATE);
+    let snippet_source = std::fs::read_to_string(snippet)
+        .with_context(|| format!("Reading surface shader {}", snippet.display()))?;
+    // The include is replaced by an empty line; number the snippet lines from its first line
+    let source = format!(
+        "#include \"{}\"\n#line 1\n{}",
+        template.display(),
+        snippet_source
+    );
+    let vert_files = glsl_preprocessor::load_and_parse(vertex)
+        .with_context(|| format!("Parsing vertex shader {}", vertex.display()))?;
+    let frag_files = glsl_preprocessor::parse_source(snippet, &source)
+        .with_context(|| format!("Parsing surface shader {}", snippet.display()))?;
+    validate_gbuffer_outputs(frag_files.iter().map(|(_, s)| s.as_str()))
+        .with_context(|| format!("Validating surface shader {}", snippet.display()))?;
+    link_sources(vert_files, frag_files)
+}
+
+/// Load, preprocess and link a vertex and fragment shader pair, returning the program along with
+/// every file that was included, for hot-reloading purposes.
+pub fn link_program(vertex: &Path, fragment: &Path) -> Result<(Program, Vec<PathBuf>)> {
+    let vert_files = glsl_preprocessor::load_and_parse(vertex)
+        .with_context(|| format!("Parsing vertex shader {}", vertex.display()))?;
+    let frag_files = glsl_preprocessor::load_and_parse(fragment)
+        .with_context(|| format!("Parsing fragment shader {}", fragment.display()))?;
+    link_sources(vert_files, frag_files)
+}
+
+fn link_sources(
+    vert_files: Vec<(PathBuf, String)>,
+    frag_files: Vec<(PathBuf, String)>,
+) -> Result<(Program, Vec<PathBuf>)> {
+    let vert_shader = VertexShader::new_multiple(vert_files.iter().map(|(_, s)| s.as_str()))
+        .with_context(|| file_map(vert_files.iter().map(|(p, _)| p)))?;
+    let frag_shader = FragmentShader::new_multiple(frag_files.iter().map(|(_, s)| s.as_str()))
+        .with_context(|| file_map(frag_files.iter().map(|(p, _)| p)))?;
+    let program = Program::new()
+        .with_shader(vert_shader.id)
+        .with_shader(frag_shader.id)
+        .link()?;
+    let files = vert_files
+        .into_iter()
+        .chain(frag_files)
+        .map(|(p, _)| p)
+        .collect();
+    Ok((program, files))
+}
+
+/// Program drawing meshes in an emissive checkerboard, in place of materials whose shaders failed to
+/// compile.
+#[derive(Debug)]
+pub(crate) struct ErrorProgram {
+    program: Program,
+    view: UniformBlockIndex,
+    bones: UniformBlockIndex,
+    model: UniformLocation,
+    morph: MorphLocations,
+}
+
+impl ErrorProgram {
+    pub(crate) fn load(base_path: &Path) -> Result<Self> {
+        let (program, _) = link_program(
+            &base_path.join("mesh/mesh.vert.glsl"),
+            &base_path.join("mesh/error.frag.glsl"),
+        )
+        .context("Loading error program")?;
+        Ok(Self {
+            view: program.uniform_block("View"),
+            bones: program.uniform_block("Bones"),
+            model: program.uniform("model"),
+            morph: MorphLocations::new(&program),
+            program,
+        })
+    }
+
+    pub(crate) fn bind(
+        &self,
+        view: &ViewUniformBuffer,
+        bones: Option<&UniformBuffer<Std140GpuBone>>,
+    ) -> Result<()> {
+        self.program.bind_block(&view.slice(0..=0), self.view, 0)?;
+        if let Some(bones) = bones {
+            self.program.bind_block(&bones.slice(..), self.bones, 2)?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn draw_mesh(&self, frame: &Framebuffer, mesh: Transformed<&Mesh>) -> Result<()> {
+        self.program
+            .set_uniform(self.model, mesh.transform.matrix())?;
+        self.morph.bind(&self.program, &mesh)?;
+        mesh.draw(&self.program, frame, false)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,315 @@
+//! Layered material for terrains and large floors, blending up to 4 layers of textures with weights
+//! read from a splat map, or from the vertex colors of the mesh.
+//!
+//! Each channel of the splat map (or vertex color) is the weight of the layer of the same index.
+//! Weights are normalized, and areas where they are all zero show the first layer.
+
+use std::{
+    any::Any,
+    cell::{Cell, RefCell},
+    path::PathBuf,
+};
+
+use crevice::std140::AsStd140;
+use eyre::{Context, Result};
+use glam::{UVec4, Vec4};
+
+use rose_core::{
+    camera::ViewUniformBuffer,
+    transform::Transformed,
+    utils::{
+        draw_counters::CountBind,
+        reload_watcher::{ReloadFileProxy, ReloadWatcher},
+    },
+};
+use violette::{
+    buffer::UniformBuffer,
+    framebuffer::Framebuffer,
+    gl,
+    program::{Program, UniformBlockIndex, UniformLocation},
+    texture::Texture,
+};
+
+use crate::{
+    shader_material::{link_gbuffer_program, ErrorProgram},
+    DrawMaterial, Mesh,
+};
+
+pub const MAX_SPLAT_LAYERS: usize = 4;
+
+/// Textures of a layer. Missing textures use the factors of the layer in [`SplatUniforms`] only.
+#[derive(Debug, Default)]
+pub struct SplatLayer {
+    pub color: Option<Texture<[f32; 3]>>,
+    pub normal: Option<Texture<[f32; 3]>>,
+    pub rough_metal: Option<Texture<[f32; 2]>>,
+}
+
+/// Parameters of the splat material, with one component per layer in each vector.
+#[derive(Debug, Copy, Clone, PartialEq, AsStd140)]
+pub struct SplatUniforms {
+    /// Read the layer weights from the vertex colors instead of the splat map.
+    pub weights_from_vertex_color: bool,
+    pub layer_count: u32,
+    /// Number of times the layer textures repeat over the UV range of the splat map.
+    pub tiling: Vec4,
+    pub roughness: Vec4,
+    pub metallic: Vec4,
+    pub normal_amount: Vec4,
+    pub has_color: UVec4,
+    pub has_normal: UVec4,
+    pub has_rough_metal: UVec4,
+}
+
+#[derive(Debug, Copy, Clone)]
+struct SplatLocations {
+    view: UniformBlockIndex,
+    uniforms: UniformBlockIndex,
+    model: UniformLocation,
+    splat: UniformLocation,
+    color: [UniformLocation; MAX_SPLAT_LAYERS],
+    normal: [UniformLocation; MAX_SPLAT_LAYERS],
+    rough_metal: [UniformLocation; MAX_SPLAT_LAYERS],
+}
+
+impl SplatLocations {
+    fn new(program: &Program) -> Self {
+        let layers = |name: &str| {
+            std::array::from_fn(|i| program.uniform(&format!("layer_{}[{}]", name, i)))
+        };
+        Self {
+            view: program.uniform_block("View"),
+            uniforms: program.uniform_block("Uniforms"),
+            model: program.uniform("model"),
+            splat: program.uniform("map_splat"),
+            color: layers("color"),
+            normal: layers("normal"),
+            rough_metal: layers("rough_metal"),
+        }
+    }
+}
+
+/// Material blending layers of textures, see the [module documentation](self).
+#[derive(Debug)]
+pub struct SplatMaterial {
+    splat: Option<Texture<[f32; 4]>>,
+    layers: Vec<SplatLayer>,
+    uniforms: Cell<SplatUniforms>,
+    buffer: UniformBuffer<Std140SplatUniforms>,
+    program: RefCell<Program>,
+    locations: Cell<SplatLocations>,
+    proxy: ReloadFileProxy,
+    base_path: PathBuf,
+    vertex: PathBuf,
+    fragment: PathBuf,
+    /// Stands in for the program while the shaders fail to compile.
+    error_program: RefCell<Option<ErrorProgram>>,
+}
+
+impl SplatMaterial {
+    /// Create the material from its layers. Without a splat map, the layer weights are read from
+    /// the vertex colors.
+    pub fn create(
+        splat: impl Into<Option<Texture<[f32; 4]>>>,
+        layers: Vec<SplatLayer>,
+        reload_watcher: &ReloadWatcher,
+    ) -> Result<Self> {
+        if layers.is_empty() || layers.len() > MAX_SPLAT_LAYERS {
+            eyre::bail!(
+                "Splat material needs 1 to {} layers, got {}",
+                MAX_SPLAT_LAYERS,
+                layers.len()
+            );
+        }
+        let splat = splat.into();
+        let has = |func: fn(&SplatLayer) -> bool| {
+            UVec4::from_array(std::array::from_fn(|i| {
+                layers.get(i).is_some_and(func) as u32
+            }))
+        };
+        let uniforms = SplatUniforms {
+            weights_from_vertex_color: splat.is_none(),
+            layer_count: layers.len() as u32,
+            tiling: Vec4::ONE,
+            roughness: Vec4::ONE,
+            metallic: Vec4::ZERO,
+            normal_amount: Vec4::ONE,
+            has_color: has(|layer| layer.color.is_some()),
+            has_normal: has(|layer| layer.normal.is_some()),
+            has_rough_metal: has(|layer| layer.rough_metal.is_some()),
+        };
+        let vertex = reload_watcher.base_path().join("mesh/mesh.vert.glsl");
+        let fragment = reload_watcher.base_path().join("mesh/splat.frag.glsl");
+        let (program, files) =
+            link_gbuffer_program(&vertex, &fragment).context("Loading splat material program")?;
+        Ok(Self {
+            splat,
+            layers,
+            uniforms: Cell::new(uniforms),
+            buffer: UniformBuffer::with_data(&[uniforms.as_std140()])?,
+            locations: Cell::new(SplatLocations::new(&program)),
+            program: RefCell::new(program),
+            proxy: reload_watcher.proxy(files.iter().map(|p| p.as_path())),
+            base_path: reload_watcher.base_path().to_path_buf(),
+            vertex,
+            fragment,
+            error_program: RefCell::new(None),
+        })
+    }
+
+    pub fn layers(&self) -> &[SplatLayer] {
+        &self.layers
+    }
+
+    pub fn uniforms(&self) -> SplatUniforms {
+        self.uniforms.get()
+    }
+
+    /// Update the material parameters. The uniform buffer is only re-uploaded when the parameters
+    /// actually changed, so this can be called every frame.
+    pub fn update_uniforms(&self, func: impl FnOnce(&mut SplatUniforms)) -> Result<()> {
+        let mut uniforms = self.uniforms.get();
+        func(&mut uniforms);
+        if uniforms == self.uniforms.get() {
+            return Ok(());
+        }
+        self.uniforms.set(uniforms);
+        let mut slice = self.buffer.slice(0..=0);
+        slice.set(0, &uniforms.as_std140())?;
+        Ok(())
+    }
+
+    fn reload_if_needed(&self) {
+        let reloaded = self
+            .proxy
+            .reload_shader("splat material", &self.fragment, || {
+                link_gbuffer_program(&self.vertex, &self.fragment)
+            });
+        match reloaded {
+            None => {}
+            Some(Ok(program)) => {
+                self.locations.set(SplatLocations::new(&program));
+                *self.program.borrow_mut() = program;
+                self.error_program.replace(None);
+            }
+            Some(Err(_)) => {
+                let mut error_program = self.error_program.borrow_mut();
+                if error_program.is_none() {
+                    match ErrorProgram::load(&self.base_path) {
+                        Ok(program) => *error_program = Some(program),
+                        Err(err) => tracing::error!("Cannot load error program: {:?}", err),
+                    }
+                }
+            }
+        }
+    }
+
+    #[cfg(feature = "debug-ui")]
+    pub fn ui(&self, ui: &mut egui::Ui) {
+        use egui::{DragValue, Grid};
+
+        let mut uniforms = self.uniforms();
+        ui.checkbox(
+            &mut uniforms.weights_from_vertex_color,
+            "Weights from vertex colors",
+        );
+        for i in 0..self.layers.len() {
+            ui.collapsing(format!("Layer {}", i + 1), |ui| {
+                Grid::new(("splat-layer", i)).num_columns(2).show(ui, |ui| {
+                    let tiling_label = ui.label("Tiling").id;
+                    ui.add(
+                        DragValue::new(&mut uniforms.tiling[i])
+                            .clamp_range(0.01..=1000.)
+                            .speed(0.1)
+                            .suffix("x"),
+                    )
+                    .labelled_by(tiling_label);
+                    ui.end_row();
+
+                    let roughness_label = ui.label("Roughness").id;
+                    ui.add(
+                        DragValue::new(&mut uniforms.roughness[i])
+                            .clamp_range(0.0..=1.)
+                            .speed(0.01),
+                    )
+                    .labelled_by(roughness_label);
+                    ui.end_row();
+
+                    let metallic_label = ui.label("Metallic").id;
+                    ui.add(
+                        DragValue::new(&mut uniforms.metallic[i])
+                            .clamp_range(0.0..=1.)
+                            .speed(0.01),
+                    )
+                    .labelled_by(metallic_label);
+                    ui.end_row();
+
+                    let normal_label = ui.label("Normal amount").id;
+                    ui.add(DragValue::new(&mut uniforms.normal_amount[i]).speed(0.01))
+                        .labelled_by(normal_label);
+                    ui.end_row();
+                });
+            });
+        }
+        if let Err(err) = self.update_uniforms(|u| *u = uniforms) {
+            tracing::error!("Cannot update splat material: {}", err);
+        }
+    }
+}
+
+impl DrawMaterial for SplatMaterial {
+    fn draw<'a>(
+        &self,
+        frame: &Framebuffer,
+        view: &ViewUniformBuffer,
+        meshes: &mut dyn Iterator<Item = Transformed<&'a Mesh>>,
+    ) -> Result<()> {
+        self.reload_if_needed();
+        if let Some(error_program) = &*self.error_program.borrow() {
+            error_program.bind(view, None)?;
+            for mesh in meshes {
+                error_program.draw_mesh(frame, mesh)?;
+            }
+            return Ok(());
+        }
+        let program = self.program.borrow();
+        let locations = self.locations.get();
+        program.bind_block(&view.slice(0..=0), locations.view, 0)?;
+        program.bind_block(&self.buffer.slice(0..=0), locations.uniforms, 1)?;
+        if let Some(splat) = &self.splat {
+            program.set_uniform(locations.splat, splat.as_uniform(0).counted()?)?;
+        }
+        // Texture units: splat map, then the colors, normals and roughness/metal maps of the layers
+        for (i, layer) in self.layers.iter().enumerate() {
+            let unit = 1 + i as u32;
+            if let Some(color) = &layer.color {
+                program.set_uniform(locations.color[i], color.as_uniform(unit).counted()?)?;
+            }
+            if let Some(normal) = &layer.normal {
+                let unit = unit + MAX_SPLAT_LAYERS as u32;
+                program.set_uniform(locations.normal[i], normal.as_uniform(unit).counted()?)?;
+            }
+            if let Some(rough_metal) = &layer.rough_metal {
+                let unit = unit + 2 * MAX_SPLAT_LAYERS as u32;
+                program.set_uniform(
+                    locations.rough_metal[i],
+                    rough_metal.as_uniform(unit).counted()?,
+                )?;
+            }
+        }
+        for mesh in meshes {
+            program.set_uniform(locations.model, mesh.transform.matrix())?;
+            mesh.draw(&program, frame, false)?;
+        }
+        unsafe { gl::BindTexture(gl::TEXTURE_2D, 0) }
+        Ok(())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
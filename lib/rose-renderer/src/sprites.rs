@@ -0,0 +1,247 @@
+//! Sprites: textured quads in 3D space turned towards the camera, for particles, icons and
+//! vegetation cards.
+//!
+//! Sprites are unlit, and blended over the lit scene. Their quads are built every frame and
+//! batched by texture, so that all the sprites sharing a texture are drawn in a single draw call.
+//! Small images can share the sprite atlas for the same reason, see [`crate::atlas`].
+//! Sprites are sorted back to front within a batch, but not across batches.
+
+use std::collections::{hash_map::Entry, HashMap, HashSet};
+use std::path::PathBuf;
+
+use bytemuck::{Pod, Zeroable};
+use eyre::{Context, Result};
+use glam::{vec2, Vec2, Vec3, Vec4, Vec4Swizzles};
+
+use rose_core::{
+    camera::{ViewUniform, ViewUniformBuffer},
+    mesh::Mesh,
+    utils::{
+        draw_counters::CountBind,
+        reload_watcher::{ReloadFileProxy, ReloadWatcher},
+    },
+};
+use violette::{
+    buffer::BufferUsageHint,
+    framebuffer::{Blend, Framebuffer},
+    program::{Program, UniformBlockIndex, UniformLocation},
+    texture::{DepthStencil, Texture},
+};
+use violette_derive::VertexAttributes;
+
+use crate::{handles::TextureHandle, shader_material::link_program};
+
+/// How sprites turn towards the camera.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Billboard {
+    /// Parallel to the screen.
+    FaceCamera,
+    /// Only rotating around the axis, which stays upright, e.g. for trees and grass.
+    Axis(Vec3),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Sprite {
+    /// World-space center of the sprite.
+    pub position: Vec3,
+    /// World-space width and height of the sprite.
+    pub size: Vec2,
+    /// Linear RGBA color multiplied with the texture.
+    pub tint: Vec4,
+    pub billboard: Billboard,
+    /// Texture coordinates of the image within the texture, as the minimum coordinates followed
+    /// by the maximum ones; [`Sprite::FULL_UV_RECT`] for the whole texture.
+    pub uv_rect: Vec4,
+}
+
+#[derive(Debug, Copy, Clone, Pod, Zeroable, VertexAttributes)]
+#[repr(C)]
+pub struct SpriteVertex {
+    pub position: Vec3,
+    pub uv: Vec2,
+    #[attribute(ignore)]
+    _padding: [f32; 3],
+    pub color: Vec4,
+}
+
+/// Triangles of a quad whose corners are in counter-clockwise order.
+const QUAD_INDICES: [u32; 6] = [0, 1, 2, 0, 2, 3];
+
+impl Sprite {
+    pub const FULL_UV_RECT: Vec4 = Vec4::new(0., 0., 1., 1.);
+
+    /// Corners of the sprite, in counter-clockwise order as seen from the camera, starting from
+    /// the bottom left. `right` and `up` are the world-space axes of the camera.
+    pub fn quad(&self, camera_pos: Vec3, right: Vec3, up: Vec3) -> [SpriteVertex; 4] {
+        let (right, up) = match self.billboard {
+            Billboard::FaceCamera => (right, up),
+            Billboard::Axis(axis) => {
+                let axis = axis.normalize_or_zero();
+                let facing = axis.cross(camera_pos - self.position).normalize_or_zero();
+                // Looking straight along the axis, there is no side to turn towards
+                if facing == Vec3::ZERO {
+                    (right, up)
+                } else {
+                    (facing, axis)
+                }
+            }
+        };
+        let half_size = self.size / 2.;
+        let (right, up) = (right * half_size.x, up * half_size.y);
+        let vertex = |corner: Vec3, uv: Vec2| SpriteVertex {
+            position: self.position + corner,
+            uv,
+            _padding: [0.; 3],
+            color: self.tint,
+        };
+        let (uv_min, uv_max) = (self.uv_rect.xy(), self.uv_rect.zw());
+        [
+            vertex(-right - up, vec2(uv_min.x, uv_max.y)),
+            vertex(right - up, uv_max),
+            vertex(right + up, vec2(uv_max.x, uv_min.y)),
+            vertex(-right + up, uv_min),
+        ]
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+struct SpriteLocations {
+    view: UniformBlockIndex,
+    scene_depth: UniformLocation,
+    sprite: UniformLocation,
+}
+
+impl SpriteLocations {
+    fn new(program: &Program) -> Self {
+        Self {
+            view: program.uniform_block("View"),
+            scene_depth: program.uniform("scene_depth"),
+            sprite: program.uniform("sprite"),
+        }
+    }
+}
+
+/// Sprites drawn with a texture, given by its handle or `None` for the sprite atlas.
+pub type SpriteBatch<'a> = (Option<TextureHandle>, &'a Texture<[f32; 4]>, &'a [Sprite]);
+
+/// Forward pass drawing the sprites over the lit scene.
+#[derive(Debug)]
+pub struct Sprites {
+    program: Program,
+    locations: SpriteLocations,
+    proxy: ReloadFileProxy,
+    vertex: PathBuf,
+    fragment: PathBuf,
+    /// Quads of each texture, `None` being the sprite atlas, whose buffers are reused across
+    /// frames.
+    batches: HashMap<Option<TextureHandle>, Mesh<SpriteVertex>>,
+}
+
+impl Sprites {
+    pub fn new(reload_watcher: &ReloadWatcher) -> Result<Self> {
+        let vertex = reload_watcher.base_path().join("sprite/sprite.vert.glsl");
+        let fragment = reload_watcher.base_path().join("sprite/sprite.frag.glsl");
+        let (program, files) =
+            link_program(&vertex, &fragment).context("Loading sprite program")?;
+        Ok(Self {
+            locations: SpriteLocations::new(&program),
+            program,
+            proxy: reload_watcher.proxy(files.iter().map(|p| p.as_path())),
+            vertex,
+            fragment,
+            batches: HashMap::new(),
+        })
+    }
+
+    fn reload_if_needed(&mut self) {
+        let reloaded = self
+            .proxy
+            .reload_shader("sprite shader", &self.fragment, || {
+                link_program(&self.vertex, &self.fragment)
+            });
+        if let Some(Ok(program)) = reloaded {
+            self.locations = SpriteLocations::new(&program);
+            self.program = program;
+        }
+    }
+
+    /// Blend the sprites over `frame`, hiding them behind the geometry of `depth`. `camera` is the
+    /// view uploaded into `view`.
+    #[tracing::instrument(skip_all)]
+    pub fn draw<'a>(
+        &mut self,
+        frame: &Framebuffer,
+        view: &ViewUniformBuffer,
+        camera: &ViewUniform,
+        depth: &Texture<DepthStencil<f32, ()>>,
+        batches: impl IntoIterator<Item = SpriteBatch<'a>>,
+    ) -> Result<()> {
+        self.reload_if_needed();
+        let camera_pos = camera.inv_view.w_axis.xyz();
+        let right = camera.inv_view.x_axis.xyz();
+        let up = camera.inv_view.y_axis.xyz();
+        let program = &self.program;
+        let locations = self.locations;
+        program.bind_block(&view.slice(0..=0), locations.view, 0)?;
+        program.set_uniform(locations.scene_depth, depth.as_uniform(0).counted()?)?;
+        Framebuffer::enable_blending(Blend::SrcAlpha, Blend::OneMinusSrcAlpha);
+        let mut drawn = HashSet::new();
+        for (handle, texture, sprites) in batches {
+            if sprites.is_empty() {
+                continue;
+            }
+            let mut sorted = sprites.iter().collect::<Vec<_>>();
+            sorted.sort_by(|a, b| {
+                let da = a.position.distance_squared(camera_pos);
+                let db = b.position.distance_squared(camera_pos);
+                db.total_cmp(&da)
+            });
+            let vertices = sorted
+                .iter()
+                .flat_map(|sprite| sprite.quad(camera_pos, right, up))
+                .collect::<Vec<_>>();
+            let indices = (0..sorted.len() as u32)
+                .flat_map(|i| QUAD_INDICES.map(|j| 4 * i + j))
+                .collect::<Vec<_>>();
+            let mesh = match self.batches.entry(handle) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => entry.insert(Mesh::empty()?),
+            };
+            mesh.vertices().set(&vertices, BufferUsageHint::Stream)?;
+            mesh.indices().set(&indices, BufferUsageHint::Stream)?;
+            program.set_uniform(locations.sprite, texture.as_uniform(1).counted()?)?;
+            mesh.draw(program, frame, false)?;
+            drawn.insert(handle);
+        }
+        Framebuffer::disable_blending();
+        // Drop the buffers of textures without sprites anymore
+        self.batches.retain(|handle, _| drawn.contains(handle));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn axis_billboards_stay_upright() {
+        let sprite = Sprite {
+            position: Vec3::ZERO,
+            size: vec2(2., 4.),
+            tint: Vec4::ONE,
+            billboard: Billboard::Axis(Vec3::Y),
+            uv_rect: Sprite::FULL_UV_RECT,
+        };
+        // Camera above and in front of the sprite, looking down at it
+        let camera_pos = Vec3::new(0., 5., 5.);
+        let up = Vec3::new(0., 1., -1.).normalize();
+        let quad = sprite.quad(camera_pos, Vec3::X, up);
+        assert_eq!(quad[0].position, Vec3::new(-1., -2., 0.));
+        assert_eq!(quad[2].position, Vec3::new(1., 2., 0.));
+        // Counter-clockwise when seen from the camera
+        let normal =
+            (quad[1].position - quad[0].position).cross(quad[2].position - quad[0].position);
+        assert!(normal.dot(camera_pos - sprite.position) > 0.);
+    }
+}
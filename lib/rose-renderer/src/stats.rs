@@ -0,0 +1,170 @@
+use std::{collections::VecDeque, time::Duration};
+
+use rose_core::utils::draw_counters::DrawCounters;
+
+/// Number of frames kept in [`FrameStatsHistory`].
+pub const HISTORY_LEN: usize = 300;
+
+/// Statistics of a frame rendered by [`crate::Renderer::flush`].
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct RenderFrameStats {
+    /// Meshes submitted this frame.
+    pub submitted: usize,
+    /// Mesh instances drawn into the G-Buffer.
+    pub instances: usize,
+    /// Mesh instances skipped by occlusion culling.
+    pub culled: usize,
+    /// Lights shaded by the deferred lighting pass.
+    pub lights: usize,
+    /// Lights skipped by the deferred lighting pass for being too far or too small on screen.
+    pub lights_culled: usize,
+    /// Whether the last frame was presented again instead of rendering the scene.
+    pub reused: bool,
+    pub draw_calls: usize,
+    pub triangles: usize,
+    /// Textures bound by materials and the lighting passes.
+    pub texture_binds: usize,
+    pub program_switches: usize,
+    /// Time from the start of the frame to the end of its rendering, including scene processing.
+    pub scene_time: Duration,
+    /// Time spent in [`crate::Renderer::flush`].
+    pub render_time: Duration,
+}
+
+impl RenderFrameStats {
+    pub(crate) fn set_draw_counters(&mut self, counters: DrawCounters) {
+        self.draw_calls = counters.draw_calls;
+        self.triangles = counters.triangles;
+        self.texture_binds = counters.texture_binds;
+        self.program_switches = counters.program_switches;
+    }
+
+    /// Summary of the frame, for the statistics display of the platform.
+    pub fn hud_lines(&self) -> Vec<String> {
+        vec![
+            format!("Draw calls: {} ({} tris)", self.draw_calls, self.triangles),
+            format!("Objects: {}/{}", self.instances, self.submitted),
+            format!("Lights: {} ({} culled)", self.lights, self.lights_culled),
+            format!("Render: {:.1} ms", self.render_time.as_secs_f32() * 1e3),
+        ]
+    }
+}
+
+/// Counter of [`RenderFrameStats`] which can be plotted over time.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum FrameStat {
+    DrawCalls,
+    Instances,
+    Triangles,
+    TextureBinds,
+    ProgramSwitches,
+    Lights,
+    CulledLights,
+    Culled,
+    RenderTime,
+}
+
+impl FrameStat {
+    pub const ALL: [Self; 9] = [
+        Self::DrawCalls,
+        Self::Instances,
+        Self::Triangles,
+        Self::TextureBinds,
+        Self::ProgramSwitches,
+        Self::Lights,
+        Self::CulledLights,
+        Self::Culled,
+        Self::RenderTime,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::DrawCalls => "Draw calls",
+            Self::Instances => "Instances",
+            Self::Triangles => "Triangles",
+            Self::TextureBinds => "Texture binds",
+            Self::ProgramSwitches => "Program switches",
+            Self::Lights => "Lights",
+            Self::CulledLights => "Culled lights",
+            Self::Culled => "Culled objects",
+            Self::RenderTime => "Render time (ms)",
+        }
+    }
+
+    pub fn value(self, stats: &RenderFrameStats) -> f64 {
+        match self {
+            Self::DrawCalls => stats.draw_calls as _,
+            Self::Instances => stats.instances as _,
+            Self::Triangles => stats.triangles as _,
+            Self::TextureBinds => stats.texture_binds as _,
+            Self::ProgramSwitches => stats.program_switches as _,
+            Self::Lights => stats.lights as _,
+            Self::CulledLights => stats.lights_culled as _,
+            Self::Culled => stats.culled as _,
+            Self::RenderTime => stats.render_time.as_secs_f64() * 1e3,
+        }
+    }
+}
+
+/// Statistics of the last [`HISTORY_LEN`] frames.
+#[derive(Debug, Clone)]
+pub struct FrameStatsHistory {
+    frames: VecDeque<RenderFrameStats>,
+    #[cfg(feature = "debug-ui")]
+    plotted: FrameStat,
+}
+
+impl Default for FrameStatsHistory {
+    fn default() -> Self {
+        Self {
+            frames: VecDeque::with_capacity(HISTORY_LEN),
+            #[cfg(feature = "debug-ui")]
+            plotted: FrameStat::DrawCalls,
+        }
+    }
+}
+
+impl FrameStatsHistory {
+    pub fn push(&mut self, stats: RenderFrameStats) {
+        if self.frames.len() == HISTORY_LEN {
+            self.frames.pop_front();
+        }
+        self.frames.push_back(stats);
+    }
+
+    pub fn last(&self) -> Option<&RenderFrameStats> {
+        self.frames.back()
+    }
+
+    /// Frames oldest first.
+    pub fn iter(&self) -> impl '_ + Iterator<Item = &RenderFrameStats> {
+        self.frames.iter()
+    }
+
+    #[cfg(feature = "debug-ui")]
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        use egui::plot::{Line, Plot};
+
+        egui::ComboBox::from_label("Statistic")
+            .selected_text(self.plotted.name())
+            .show_ui(ui, |ui| {
+                for stat in FrameStat::ALL {
+                    ui.selectable_value(&mut self.plotted, stat, stat.name());
+                }
+            });
+        let stat = self.plotted;
+        let points = self
+            .frames
+            .iter()
+            .enumerate()
+            .map(|(ix, stats)| [ix as f64, stat.value(stats)])
+            .collect::<Vec<_>>();
+        Plot::new("frame-stats")
+            .height(160.)
+            .include_x(HISTORY_LEN as f64)
+            .include_y(0.)
+            .allow_drag(false)
+            .allow_zoom(false)
+            .show(ui, |ui| ui.line(Line::new(points).name(stat.name())));
+    }
+}
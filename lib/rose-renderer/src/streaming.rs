@@ -0,0 +1,317 @@
+//! Residency of streamed textures within a video memory budget.
+//!
+//! Textures start with only their coarsest mipmap levels resident. Every frame, their users report
+//! how large they appear on screen, which gives the finest level worth having; textures then move
+//! one level at a time towards it, a few per frame. When the budget runs out, the finest levels of
+//! the least recently used textures are evicted first. Only the levels are tracked here; the owner
+//! of the textures uploads them as the resident levels change.
+
+use std::{collections::HashMap, hash::Hash};
+
+use glam::UVec2;
+
+/// Budget and pacing of [`TextureStreaming`].
+#[derive(Debug, Copy, Clone)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serialize", serde(default))]
+pub struct StreamingSettings {
+    /// Video memory the resident levels may take, in bytes.
+    pub budget: u64,
+    /// Largest side of the coarsest level, which is always resident.
+    pub min_resident_size: u32,
+    /// Textures getting a finer level each frame, to spread the uploads over several frames.
+    pub max_uploads_per_frame: usize,
+}
+
+impl Default for StreamingSettings {
+    fn default() -> Self {
+        Self {
+            budget: 512 << 20,
+            min_resident_size: 64,
+            max_uploads_per_frame: 4,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+struct StreamedTexture {
+    size: UVec2,
+    /// Memory of the base level alone.
+    base_bytes: u64,
+    /// Finest resident level.
+    resident: u32,
+    /// Finest level requested this frame.
+    requested: Option<u32>,
+    last_used: u64,
+}
+
+impl StreamedTexture {
+    fn coarsest_level(&self, min_resident_size: u32) -> u32 {
+        let mut level = 0;
+        while level + 1 < mip_count(self.size)
+            && level_size(self.size, level).max_element() > min_resident_size
+        {
+            level += 1;
+        }
+        level
+    }
+
+    /// Memory taken with `level` as the finest resident level.
+    fn bytes(&self, level: u32) -> u64 {
+        (level..mip_count(self.size))
+            .map(|level| self.base_bytes >> (2 * level))
+            .sum()
+    }
+}
+
+/// Number of mipmap levels of a texture of the given size.
+pub fn mip_count(size: UVec2) -> u32 {
+    u32::BITS - size.max_element().max(1).leading_zeros()
+}
+
+/// Size of the mipmap level of a texture of the given size.
+pub fn level_size(size: UVec2, level: u32) -> UVec2 {
+    (size >> level).max(UVec2::ONE)
+}
+
+/// Resident mipmap levels of textures identified by `K`, within a memory budget.
+#[derive(Debug, Clone)]
+pub struct TextureStreaming<K> {
+    settings: StreamingSettings,
+    textures: HashMap<K, StreamedTexture>,
+    frame: u64,
+}
+
+impl<K: Clone + Eq + Hash> TextureStreaming<K> {
+    pub fn new(settings: StreamingSettings) -> Self {
+        Self {
+            settings,
+            textures: HashMap::new(),
+            frame: 0,
+        }
+    }
+
+    pub fn settings(&self) -> &StreamingSettings {
+        &self.settings
+    }
+
+    /// Change the settings; a lower budget evicts levels on the next [`Self::update`].
+    pub fn settings_mut(&mut self) -> &mut StreamingSettings {
+        &mut self.settings
+    }
+
+    /// Stream a texture of `size` texels whose base level takes `base_bytes`, replacing any
+    /// texture with the same key. Returns its finest resident level, which is its coarsest level.
+    pub fn insert(&mut self, key: K, size: UVec2, base_bytes: u64) -> u32 {
+        let mut texture = StreamedTexture {
+            size,
+            base_bytes,
+            resident: 0,
+            requested: None,
+            last_used: self.frame,
+        };
+        texture.resident = texture.coarsest_level(self.settings.min_resident_size);
+        self.textures.insert(key, texture);
+        texture.resident
+    }
+
+    pub fn remove(&mut self, key: &K) -> bool {
+        self.textures.remove(key).is_some()
+    }
+
+    pub fn contains(&self, key: &K) -> bool {
+        self.textures.contains_key(key)
+    }
+
+    pub fn resident_level(&self, key: &K) -> Option<u32> {
+        self.textures.get(key).map(|texture| texture.resident)
+    }
+
+    /// Request the level for this frame, keeping the finest level requested by any user.
+    pub fn request(&mut self, key: &K, level: u32) {
+        let Some(texture) = self.textures.get_mut(key) else { return; };
+        texture.requested = Some(texture.requested.map_or(level, |r| r.min(level)));
+        texture.last_used = self.frame;
+    }
+
+    /// Request the level sampled when the texture covers `screen_size` pixels across, assuming
+    /// it is mapped once over the surface.
+    pub fn request_coverage(&mut self, key: &K, screen_size: f32) {
+        let Some(texture) = self.textures.get(key) else { return; };
+        let ratio = texture.size.max_element() as f32 / screen_size.max(1.);
+        let level = (ratio.log2().floor().max(0.) as u32).min(mip_count(texture.size) - 1);
+        self.request(key, level);
+    }
+
+    /// Memory taken by the resident levels, in bytes.
+    pub fn memory_used(&self) -> u64 {
+        self.textures
+            .values()
+            .map(|texture| texture.bytes(texture.resident))
+            .sum()
+    }
+
+    pub fn len(&self) -> usize {
+        self.textures.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.textures.is_empty()
+    }
+
+    /// Move the textures towards their requested levels within the budget, and start a new frame.
+    /// Returns the textures whose finest resident level changed, with that level.
+    pub fn update(&mut self) -> Vec<(K, u32)> {
+        let before = self
+            .textures
+            .iter()
+            .map(|(key, texture)| (key.clone(), texture.resident))
+            .collect::<HashMap<_, _>>();
+        let mut used = self.memory_used();
+
+        // Furthest from their requested level first
+        let mut upgrades = self
+            .textures
+            .iter()
+            .filter_map(|(key, texture)| {
+                let requested = texture.requested?;
+                (requested < texture.resident).then(|| (texture.resident - requested, key.clone()))
+            })
+            .collect::<Vec<_>>();
+        upgrades.sort_by_key(|(gap, _)| std::cmp::Reverse(*gap));
+        for (_, key) in upgrades
+            .into_iter()
+            .take(self.settings.max_uploads_per_frame)
+        {
+            let texture = self.textures[&key];
+            let extra = texture.bytes(texture.resident - 1) - texture.bytes(texture.resident);
+            while used + extra > self.settings.budget {
+                let Some(freed) = self.evict_one(Some(&key)) else { break; };
+                used -= freed;
+            }
+            if used + extra > self.settings.budget {
+                break;
+            }
+            self.textures.get_mut(&key).unwrap().resident -= 1;
+            used += extra;
+        }
+        // The budget may have been lowered
+        while used > self.settings.budget {
+            let Some(freed) = self.evict_one(None) else { break; };
+            used -= freed;
+        }
+
+        for texture in self.textures.values_mut() {
+            texture.requested = None;
+        }
+        self.frame += 1;
+        self.textures
+            .iter()
+            .filter(|(key, texture)| before.get(*key) != Some(&texture.resident))
+            .map(|(key, texture)| (key.clone(), texture.resident))
+            .collect()
+    }
+
+    /// Drop the finest level of the least recently used texture having levels to spare, returning
+    /// the freed memory. Making room for `upgrading` only evicts levels no longer needed, so
+    /// that textures in use do not take turns evicting each other.
+    fn evict_one(&mut self, upgrading: Option<&K>) -> Option<u64> {
+        let min_resident_size = self.settings.min_resident_size;
+        let frame = self.frame;
+        let (_, texture) = self
+            .textures
+            .iter_mut()
+            .filter(|(key, texture)| {
+                texture.resident < texture.coarsest_level(min_resident_size)
+                    && match upgrading {
+                        Some(upgrading) => {
+                            *key != upgrading
+                                && (texture.last_used < frame
+                                    || !matches!(texture.requested, Some(r) if r <= texture.resident))
+                        }
+                        None => true,
+                    }
+            })
+            .min_by_key(|(_, texture)| {
+                let bytes = texture.bytes(texture.resident);
+                (texture.last_used, std::cmp::Reverse(bytes))
+            })?;
+        let freed = texture.bytes(texture.resident) - texture.bytes(texture.resident + 1);
+        texture.resident += 1;
+        Some(freed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZE: UVec2 = UVec2::splat(1024);
+    /// One byte per texel
+    const BASE_BYTES: u64 = 1024 * 1024;
+
+    fn streaming(budget: u64) -> TextureStreaming<&'static str> {
+        TextureStreaming::new(StreamingSettings {
+            budget,
+            min_resident_size: 64,
+            max_uploads_per_frame: 4,
+        })
+    }
+
+    #[test]
+    fn streams_one_level_at_a_time() {
+        let mut streaming = streaming(u64::MAX);
+        assert_eq!(mip_count(SIZE), 11);
+        assert_eq!(streaming.insert("a", SIZE, BASE_BYTES), 4);
+        // 1024 texels over 200 pixels only need level 2
+        for expected in [3, 2] {
+            streaming.request_coverage(&"a", 200.);
+            assert_eq!(streaming.update(), [("a", expected)]);
+        }
+        streaming.request_coverage(&"a", 200.);
+        assert!(streaming.update().is_empty());
+        assert_eq!(streaming.resident_level(&"a"), Some(2));
+    }
+
+    #[test]
+    fn evicts_least_recently_used_within_budget() {
+        let texture = StreamedTexture {
+            size: SIZE,
+            base_bytes: BASE_BYTES,
+            resident: 0,
+            requested: None,
+            last_used: 0,
+        };
+        // Room for one texture down to level 1 and the other at its coarsest level
+        let budget = texture.bytes(1) + texture.bytes(4);
+        let mut streaming = streaming(budget);
+        streaming.insert("a", SIZE, BASE_BYTES);
+        streaming.insert("b", SIZE, BASE_BYTES);
+        for _ in 0..3 {
+            streaming.request(&"a", 1);
+            streaming.request(&"b", 4);
+            streaming.update();
+        }
+        assert_eq!(streaming.resident_level(&"a"), Some(1));
+
+        // While `a` is in use, `b` cannot take its levels
+        streaming.request(&"a", 1);
+        streaming.request(&"b", 1);
+        streaming.update();
+        assert_eq!(streaming.resident_level(&"a"), Some(1));
+        assert_eq!(streaming.resident_level(&"b"), Some(4));
+
+        // Once `a` is out of view, `b` takes its place
+        for _ in 0..6 {
+            streaming.request(&"b", 1);
+            streaming.update();
+        }
+        assert_eq!(streaming.resident_level(&"a"), Some(4));
+        assert_eq!(streaming.resident_level(&"b"), Some(1));
+        assert!(streaming.memory_used() <= budget);
+
+        streaming.settings_mut().budget = texture.bytes(4) * 2;
+        streaming.update();
+        assert_eq!(streaming.resident_level(&"b"), Some(4));
+    }
+}
@@ -0,0 +1,310 @@
+//! Water surfaces, drawn over the lit scene with animated normals, soft shores and planar
+//! reflections.
+//!
+//! Water meshes are submitted separately from the other meshes with
+//! [`crate::Renderer::submit_water`]. They are expected to be horizontal planes, and the
+//! reflections are rendered across the plane of the first water surface submitted in the frame,
+//! into a [`crate::render_target`].
+
+use std::path::PathBuf;
+
+use eyre::{Context, Result};
+use glam::{Mat4, Vec2, Vec3, Vec4};
+
+use rose_core::utils::draw_counters::CountBind;
+use rose_core::{
+    camera::{ViewUniform, ViewUniformBuffer},
+    transform::Transformed,
+    utils::reload_watcher::{ReloadFileProxy, ReloadWatcher},
+};
+use violette::{
+    framebuffer::{Blend, Framebuffer},
+    program::{Program, UniformBlockIndex, UniformLocation},
+    texture::{DepthStencil, Texture},
+};
+
+use crate::{shader_material::link_program, Mesh};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct WaterParams {
+    /// Color of the light scattered by the water body, shown where it is deep or seen from above.
+    pub color: Vec3,
+    /// Number of times the waves repeat per world unit.
+    pub wave_scale: f32,
+    /// Direction and speed the waves scroll at, in texture repeats per second.
+    pub wave_speed: Vec2,
+    /// Steepness of the waves.
+    pub wave_strength: f32,
+    /// Water depth, in world units, over which the surface fades in from the shore.
+    pub shore_fade: f32,
+    /// Water depth, in world units, at which the ground below is mostly hidden.
+    pub clarity: f32,
+    /// How much the waves distort the reflections.
+    pub distortion: f32,
+    /// Render the scene mirrored across the water for its reflections. Without, the water reflects
+    /// its own color.
+    pub reflections: bool,
+}
+
+impl Default for WaterParams {
+    fn default() -> Self {
+        Self {
+            color: Vec3::new(0.02, 0.08, 0.1),
+            wave_scale: 0.2,
+            wave_speed: Vec2::new(0.03, 0.02),
+            wave_strength: 0.2,
+            shore_fade: 0.5,
+            clarity: 2.,
+            distortion: 0.02,
+            reflections: true,
+        }
+    }
+}
+
+impl WaterParams {
+    #[cfg(feature = "debug-ui")]
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        use egui::{DragValue, Grid};
+
+        Grid::new("water-params").num_columns(2).show(ui, |ui| {
+            let color_label = ui.label("Color").id;
+            ui.color_edit_button_rgb(self.color.as_mut())
+                .labelled_by(color_label);
+            ui.end_row();
+
+            let scale_label = ui.label("Wave scale").id;
+            ui.add(
+                DragValue::new(&mut self.wave_scale)
+                    .clamp_range(1e-3..=100.)
+                    .speed(1e-2),
+            )
+            .labelled_by(scale_label);
+            ui.end_row();
+
+            let speed_label = ui.label("Wave speed").id;
+            ui.horizontal(|ui| {
+                ui.add(DragValue::new(&mut self.wave_speed.x).speed(1e-3));
+                ui.add(DragValue::new(&mut self.wave_speed.y).speed(1e-3));
+            })
+            .response
+            .labelled_by(speed_label);
+            ui.end_row();
+
+            let strength_label = ui.label("Wave strength").id;
+            ui.add(
+                DragValue::new(&mut self.wave_strength)
+                    .clamp_range(0.0..=10.)
+                    .speed(1e-2),
+            )
+            .labelled_by(strength_label);
+            ui.end_row();
+
+            let shore_label = ui.label("Shore fade").id;
+            ui.add(
+                DragValue::new(&mut self.shore_fade)
+                    .clamp_range(0.0..=100.)
+                    .speed(1e-2),
+            )
+            .labelled_by(shore_label);
+            ui.end_row();
+
+            let clarity_label = ui.label("Clarity").id;
+            ui.add(
+                DragValue::new(&mut self.clarity)
+                    .clamp_range(1e-3..=1000.)
+                    .speed(1e-2),
+            )
+            .labelled_by(clarity_label);
+            ui.end_row();
+
+            let distortion_label = ui.label("Distortion").id;
+            ui.add(
+                DragValue::new(&mut self.distortion)
+                    .clamp_range(0.0..=1.)
+                    .speed(1e-3),
+            )
+            .labelled_by(distortion_label);
+            ui.end_row();
+
+            let reflections_label = ui.label("Reflections").id;
+            ui.checkbox(&mut self.reflections, "")
+                .labelled_by(reflections_label);
+            ui.end_row();
+        });
+    }
+}
+
+/// Matrix mirroring points across the plane going through `point` with the given normal.
+pub fn reflection_matrix(normal: Vec3, point: Vec3) -> Mat4 {
+    let n = normal.normalize();
+    let d = -n.dot(point);
+    Mat4::from_cols(
+        Vec4::new(1. - 2. * n.x * n.x, -2. * n.x * n.y, -2. * n.x * n.z, 0.),
+        Vec4::new(-2. * n.y * n.x, 1. - 2. * n.y * n.y, -2. * n.y * n.z, 0.),
+        Vec4::new(-2. * n.z * n.x, -2. * n.z * n.y, 1. - 2. * n.z * n.z, 0.),
+        Vec4::new(-2. * d * n.x, -2. * d * n.y, -2. * d * n.z, 1.),
+    )
+}
+
+/// View mirrored across the plane, whose projection clips everything behind the plane so that
+/// objects under the water do not show up in the reflections.
+pub fn reflected_view(view: &ViewUniform, normal: Vec3, point: Vec3) -> ViewUniform {
+    let reflection = reflection_matrix(normal, point);
+    let mat_view = view.mat_view * reflection;
+    let mut reflected = ViewUniform {
+        mat_view,
+        inv_view: mat_view.inverse(),
+        ..*view
+    };
+    // Also moves the camera position to the reflected camera
+    reflected.set_origin(view.origin);
+    let normal = normal.normalize();
+    reflected.clip_near_plane(normal.extend(-normal.dot(point)));
+    reflected
+}
+
+#[derive(Debug, Copy, Clone)]
+struct WaterLocations {
+    view: UniformBlockIndex,
+    model: UniformLocation,
+    scene_depth: UniformLocation,
+    reflection: UniformLocation,
+    normal_map: UniformLocation,
+    has_reflection: UniformLocation,
+    has_normal_map: UniformLocation,
+    time: UniformLocation,
+    color: UniformLocation,
+    wave_scale: UniformLocation,
+    wave_speed: UniformLocation,
+    wave_strength: UniformLocation,
+    shore_fade: UniformLocation,
+    clarity: UniformLocation,
+    distortion: UniformLocation,
+}
+
+impl WaterLocations {
+    fn new(program: &Program) -> Self {
+        Self {
+            view: program.uniform_block("View"),
+            model: program.uniform("model"),
+            scene_depth: program.uniform("scene_depth"),
+            reflection: program.uniform("reflection"),
+            normal_map: program.uniform("normal_map"),
+            has_reflection: program.uniform("has_reflection"),
+            has_normal_map: program.uniform("has_normal_map"),
+            time: program.uniform("time"),
+            color: program.uniform("color"),
+            wave_scale: program.uniform("wave_scale"),
+            wave_speed: program.uniform("wave_speed"),
+            wave_strength: program.uniform("wave_strength"),
+            shore_fade: program.uniform("shore_fade"),
+            clarity: program.uniform("clarity"),
+            distortion: program.uniform("distortion"),
+        }
+    }
+}
+
+/// Forward pass drawing the water surfaces over the lit scene.
+#[derive(Debug)]
+pub struct Water {
+    program: Program,
+    locations: WaterLocations,
+    proxy: ReloadFileProxy,
+    vertex: PathBuf,
+    fragment: PathBuf,
+    /// Waves are procedural without a normal map.
+    normal_map: Option<Texture<[f32; 3]>>,
+    time: f32,
+}
+
+impl Water {
+    pub fn new(reload_watcher: &ReloadWatcher) -> Result<Self> {
+        let vertex = reload_watcher.base_path().join("mesh/mesh.vert.glsl");
+        let fragment = reload_watcher.base_path().join("mesh/water.frag.glsl");
+        let (program, files) = link_program(&vertex, &fragment).context("Loading water program")?;
+        Ok(Self {
+            locations: WaterLocations::new(&program),
+            program,
+            proxy: reload_watcher.proxy(files.iter().map(|p| p.as_path())),
+            vertex,
+            fragment,
+            normal_map: None,
+            time: 0.,
+        })
+    }
+
+    /// Set the tangent-space normal map the waves are made of, tiled over the water surfaces.
+    pub fn set_normal_map(&mut self, normal_map: Option<Texture<[f32; 3]>>) {
+        self.normal_map = normal_map;
+    }
+
+    /// Move the waves forward in time, and reload the shaders if they changed.
+    pub fn advance(&mut self, dt: f32) {
+        self.time += dt;
+        let reloaded = self
+            .proxy
+            .reload_shader("water shader", &self.fragment, || {
+                link_program(&self.vertex, &self.fragment)
+            });
+        if let Some(Ok(program)) = reloaded {
+            self.locations = WaterLocations::new(&program);
+            self.program = program;
+        }
+    }
+
+    /// Blend the water surfaces over `frame`, hiding them behind the geometry of `depth`.
+    /// `reflection` is the scene mirrored across the water, if it was rendered this frame.
+    #[tracing::instrument(skip_all)]
+    pub fn draw<'a>(
+        &self,
+        frame: &Framebuffer,
+        view: &ViewUniformBuffer,
+        depth: &Texture<DepthStencil<f32, ()>>,
+        reflection: Option<&Texture<[f32; 3]>>,
+        meshes: impl IntoIterator<Item = (Transformed<&'a Mesh>, WaterParams)>,
+    ) -> Result<()> {
+        let program = &self.program;
+        let locations = self.locations;
+        program.bind_block(&view.slice(0..=0), locations.view, 0)?;
+        program.set_uniform(locations.scene_depth, depth.as_uniform(0).counted()?)?;
+        if let Some(reflection) = reflection {
+            program.set_uniform(locations.reflection, reflection.as_uniform(1).counted()?)?;
+        }
+        if let Some(normal_map) = &self.normal_map {
+            program.set_uniform(locations.normal_map, normal_map.as_uniform(2).counted()?)?;
+        }
+        program.set_uniform(locations.has_normal_map, self.normal_map.is_some() as i32)?;
+        program.set_uniform(locations.time, self.time)?;
+        Framebuffer::enable_blending(Blend::SrcAlpha, Blend::OneMinusSrcAlpha);
+        for (mesh, params) in meshes {
+            program.set_uniform(
+                locations.has_reflection,
+                (reflection.is_some() && params.reflections) as i32,
+            )?;
+            program.set_uniform(locations.color, params.color)?;
+            program.set_uniform(locations.wave_scale, params.wave_scale)?;
+            program.set_uniform(locations.wave_speed, params.wave_speed)?;
+            program.set_uniform(locations.wave_strength, params.wave_strength)?;
+            program.set_uniform(locations.shore_fade, params.shore_fade)?;
+            program.set_uniform(locations.clarity, params.clarity)?;
+            program.set_uniform(locations.distortion, params.distortion)?;
+            program.set_uniform(locations.model, mesh.transform.matrix())?;
+            mesh.draw(program, frame, false)?;
+        }
+        Framebuffer::disable_blending();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reflects_across_plane() {
+        let reflection = reflection_matrix(Vec3::Y, Vec3::new(3., 1., 0.));
+        let reflected = reflection.transform_point3(Vec3::new(2., 5., -1.));
+        assert!(reflected.abs_diff_eq(Vec3::new(2., -3., -1.), 1e-6));
+        assert!(reflection.determinant() < 0.);
+    }
+}
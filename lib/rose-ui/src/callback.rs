@@ -0,0 +1,90 @@
+//! Custom OpenGL drawing inside egui panels, through egui paint callbacks.
+//!
+//! When a callback runs, the viewport covers the rect of the callback and the scissor test is
+//! enabled on its clip rect; blending is enabled for premultiplied alpha, and depth testing and
+//! face culling are disabled. Callbacks are free to change any OpenGL state: the painter restores
+//! the state it relies on afterwards.
+
+use std::sync::Arc;
+
+use eyre::Result;
+use glam::{IVec2, UVec2};
+use violette::framebuffer::Framebuffer;
+
+use rose_core::utils::thread_guard::ThreadGuard;
+
+/// Rect in framebuffer pixels, with the origin at the bottom-left as in OpenGL.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct PixelRect {
+    pub min: IVec2,
+    pub size: IVec2,
+}
+
+impl PixelRect {
+    /// Convert a rect of the UI, in points, to the pixels of a framebuffer of the given height.
+    pub fn from_ui_rect(rect: egui::Rect, framebuffer_height: f32, pixels_per_point: f32) -> Self {
+        let left_bottom = rect.left_bottom().to_vec2() * pixels_per_point;
+        let size = rect.size() * pixels_per_point;
+        Self {
+            min: IVec2::new(
+                left_bottom.x as _,
+                (framebuffer_height - left_bottom.y) as _,
+            ),
+            size: IVec2::new(size.x as _, size.y as _),
+        }
+    }
+
+    pub fn aspect_ratio(&self) -> f32 {
+        self.size.x as f32 / self.size.y as f32
+    }
+}
+
+/// Access given to a [`UiCallback`] while it draws.
+pub struct PaintContext<'a> {
+    /// Framebuffer the UI is drawn into, only valid during the callback.
+    pub framebuffer: &'a Framebuffer,
+    /// Rect allocated to the callback, which the viewport is set to.
+    pub viewport: PixelRect,
+    /// Visible part of the callback rect, which the scissor is set to.
+    pub clip: PixelRect,
+    pub pixels_per_point: f32,
+    /// Size of the framebuffer, in pixels.
+    pub screen_size: UVec2,
+}
+
+type CallbackFn = dyn 'static + Fn(&PaintContext) -> Result<()>;
+
+/// Callback drawing with OpenGL in a rect of the UI. It must be created on the render thread, as
+/// OpenGL objects cannot be shared with other threads.
+pub struct UiCallback(ThreadGuard<Box<CallbackFn>>);
+
+impl UiCallback {
+    pub fn new<F: 'static + Fn(&PaintContext) -> Result<()>>(func: F) -> Self {
+        Self(ThreadGuard::new(Box::new(func)))
+    }
+
+    /// Paint callback to add to an [`egui::Painter`], drawing in `rect`.
+    pub fn into_paint_callback(self, rect: egui::Rect) -> egui::PaintCallback {
+        egui::PaintCallback {
+            rect,
+            callback: Arc::new(self),
+        }
+    }
+
+    pub(crate) fn call(&self, ctx: &PaintContext) -> Result<()> {
+        let Some(func) = self.0.get() else {
+            eyre::bail!("Ui painter callback not created within the render thread -- this cannot work as OpenGL is not multithreaded");
+        };
+        func(ctx)
+    }
+}
+
+/// Draw in `rect` of the UI with OpenGL, see [`UiCallback`].
+pub fn paint(
+    ui: &egui::Ui,
+    rect: egui::Rect,
+    func: impl 'static + Fn(&PaintContext) -> Result<()>,
+) {
+    ui.painter()
+        .add(UiCallback::new(func).into_paint_callback(rect));
+}
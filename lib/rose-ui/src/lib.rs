@@ -4,28 +4,40 @@ use std::time::Duration;
 use egui::{epaint, os::OperatingSystem, Style, Visuals};
 use egui_winit::winit::event_loop::EventLoopWindowTarget;
 use eyre::Result;
-use winit::window::Window;
+use winit::{dpi::PhysicalPosition, window::Window};
 
 use rose_core::utils::reload_watcher::ReloadWatcher;
 use violette::framebuffer::Framebuffer;
 
 use self::painter::UiImpl;
 
+pub mod callback;
+pub mod overlay;
 pub mod painter;
+pub mod viewport;
 
 pub struct Ui {
+    /// Show an overlay listing the shaders which failed to hot-reload.
+    pub show_shader_errors: bool,
     ctx: egui::Context,
     winit: egui_winit::State,
+    scale_factor: f32,
+    ui_scale: f32,
+    /// IME is only allowed while a text field has focus, so that it does not capture key presses
+    /// meant for the application.
+    ime_allowed: bool,
     painter: UiImpl,
     shapes: Vec<epaint::ClippedShape>,
     tex_deltas: egui::TexturesDelta,
 }
 
 impl Ui {
+    /// Create the UI of the window, with `ui_scale` multiplying the window scale factor.
     pub fn new<E>(
         event_loop: &EventLoopWindowTarget<E>,
         window: &Window,
         reload_watcher: &ReloadWatcher,
+        ui_scale: f32,
     ) -> Result<Self> {
         let painter = UiImpl::new(reload_watcher)?;
         let ctx = egui::Context::default();
@@ -39,7 +51,7 @@ impl Ui {
         };
         tracing::info!("Window scale factor: {}", scale_factor);
         tracing::info!("OS: {:?}", os);
-        ctx.set_pixels_per_point(scale_factor);
+        window.set_ime_allowed(false);
         ctx.set_os(os);
         let visuals = match dark_light::detect() {
             dark_light::Mode::Light => Visuals::light(),
@@ -50,30 +62,87 @@ impl Ui {
             ..Default::default()
         });
 
-        Ok(Self {
+        let mut this = Self {
+            show_shader_errors: true,
             ctx,
             winit: egui_winit::State::new(event_loop),
+            scale_factor,
+            ui_scale,
+            ime_allowed: false,
             painter,
             shapes: Vec::new(),
             tex_deltas: Default::default(),
-        })
+        };
+        this.update_pixels_per_point();
+        Ok(this)
+    }
+
+    pub fn ui_scale(&self) -> f32 {
+        self.ui_scale
+    }
+
+    /// Set the user preference scaling the UI on top of the window scale factor.
+    pub fn set_ui_scale(&mut self, ui_scale: f32) {
+        self.ui_scale = ui_scale;
+        self.update_pixels_per_point();
+    }
+
+    /// Follow the scale factor of the window; `ScaleFactorChanged` events are not to be passed to
+    /// [`Self::on_event`], which would discard the UI scale.
+    pub fn set_scale_factor(&mut self, scale_factor: f32) {
+        self.scale_factor = scale_factor;
+        self.update_pixels_per_point();
+    }
+
+    fn update_pixels_per_point(&mut self) {
+        let pixels_per_point = self.scale_factor * self.ui_scale;
+        self.ctx.set_pixels_per_point(pixels_per_point);
+        self.winit.set_pixels_per_point(pixels_per_point);
     }
 
     pub fn on_event(&mut self, event: &winit::event::WindowEvent) -> egui_winit::EventResponse {
         self.winit.on_event(&self.ctx, event)
     }
 
-    pub fn run(&mut self, window: &Window, runner: impl FnMut(&egui::Context)) -> Duration {
+    /// Whether a widget, usually a text field, has keyboard focus. Keys the engine reacts to, like
+    /// Escape, should then go to the UI instead.
+    pub fn wants_keyboard_input(&self) -> bool {
+        self.ctx.wants_keyboard_input()
+    }
+
+    pub fn run(&mut self, window: &Window, mut runner: impl FnMut(&egui::Context)) -> Duration {
         let raw_input = self.winit.take_egui_input(window);
-        let output = self.ctx.run(raw_input, runner);
+        let show_shader_errors = self.show_shader_errors;
+        let output = self.ctx.run(raw_input, |ctx| {
+            runner(ctx);
+            if show_shader_errors {
+                overlay::shader_errors(ctx);
+            }
+        });
 
+        let text_cursor_pos = output.platform_output.text_cursor_pos;
+        // Copied text goes to the clipboard here
         self.winit
             .handle_platform_output(window, &self.ctx, output.platform_output);
+        self.update_ime(window, text_cursor_pos);
         self.shapes = output.shapes;
         self.tex_deltas.append(output.textures_delta);
         output.repaint_after
     }
 
+    fn update_ime(&mut self, window: &Window, text_cursor_pos: Option<egui::Pos2>) {
+        let wants_keyboard_input = self.ctx.wants_keyboard_input();
+        if wants_keyboard_input != self.ime_allowed {
+            window.set_ime_allowed(wants_keyboard_input);
+            self.ime_allowed = wants_keyboard_input;
+        }
+        // egui-winit places the candidate window in logical units, which ignores the UI scale
+        if let Some(pos) = text_cursor_pos {
+            let pos = pos.to_vec2() * self.ctx.pixels_per_point();
+            window.set_ime_position(PhysicalPosition::new(pos.x, pos.y));
+        }
+    }
+
     pub fn draw(&mut self, window: &Window) -> Result<()> {
         for (id, delta) in &self.tex_deltas.set {
             self.painter.set_texture(*id, delta)?;
@@ -0,0 +1,32 @@
+use egui::{Align2, Color32, Frame, RichText, Stroke};
+
+use rose_core::utils::shader_errors;
+
+/// Overlay listing the shaders which failed to hot-reload, shown until they compile again.
+pub fn shader_errors(ctx: &egui::Context) {
+    let errors = shader_errors::errors();
+    if errors.is_empty() {
+        return;
+    }
+    egui::Area::new("shader-errors-overlay")
+        .anchor(Align2::LEFT_BOTTOM, [8., -8.])
+        .interactable(false)
+        .show(ctx, |ui| {
+            Frame::popup(ui.style())
+                .fill(Color32::from_black_alpha(220))
+                .stroke(Stroke::new(1., Color32::RED))
+                .show(ui, |ui| {
+                    ui.set_max_width(ctx.available_rect().width() * 0.6);
+                    ui.label(
+                        RichText::new(format!("{} shader(s) failed to compile", errors.len()))
+                            .color(Color32::RED)
+                            .strong(),
+                    );
+                    for error in &errors {
+                        ui.separator();
+                        ui.label(RichText::new(error.location()).monospace().strong());
+                        ui.label(RichText::new(&error.message).monospace().small());
+                    }
+                });
+        });
+}
@@ -4,11 +4,15 @@ use std::{collections::HashMap, num::NonZeroU32};
 use bytemuck::{offset_of, Pod, Zeroable};
 use egui::epaint::{self, Primitive};
 use eyre::Result;
-use glam::{vec2, IVec2, Vec2};
+use glam::{IVec2, UVec2, Vec2};
 use winit::dpi::PhysicalSize;
 
-use rose_core::utils::reload_watcher::{ReloadFileProxy, ReloadWatcher};
-use rose_core::{mesh::Mesh, utils::thread_guard::ThreadGuard};
+use rose_core::mesh::Mesh;
+use rose_core::utils::draw_counters::CountBind;
+use rose_core::utils::{
+    reload_watcher::{ReloadFileProxy, ReloadWatcher},
+    shader_errors,
+};
 use violette::framebuffer::BlendFunction;
 use violette::{
     framebuffer::{Blend, Framebuffer},
@@ -18,6 +22,9 @@ use violette::{
     vertex::{VertexAttributes, VertexDesc},
 };
 
+use crate::callback::{PaintContext, PixelRect, UiCallback};
+use crate::viewport;
+
 pub type UiTexture = Texture<f32>;
 
 #[derive(Debug, Clone, Copy, Pod, Zeroable)]
@@ -35,23 +42,14 @@ impl VertexAttributes for Vertex {
     }
 }
 
-#[allow(clippy::type_complexity)]
-pub struct UiCallback(ThreadGuard<Box<dyn 'static + Fn(egui::PaintCallbackInfo, &UiImpl)>>);
-
-impl UiCallback {
-    pub fn new<F: 'static + Fn(egui::PaintCallbackInfo, &UiImpl)>(func: F) -> Self {
-        Self(ThreadGuard::new(Box::new(func)))
-    }
-}
-
 pub struct UiImpl {
     program: Program,
     uniform_screen_size: UniformLocation,
     uniform_sampler: UniformLocation,
+    uniform_color_texture: UniformLocation,
     mesh: Mesh<Vertex>,
     textures: HashMap<egui::TextureId, UiTexture>,
     tex_trash_bin: Vec<UiTexture>,
-    current_fbo: Option<*const Framebuffer>,
     reload_watcher: ReloadFileProxy,
 }
 
@@ -59,17 +57,17 @@ impl UiImpl {
     pub fn new(reload_watcher: &ReloadWatcher) -> Result<Self> {
         let vert_shader_path = reload_watcher.base_path().join("ui/ui.vert.glsl");
         let frag_shader_path = reload_watcher.base_path().join("ui/ui.frag.glsl");
-        let (program, uniform_screen_size, uniform_sampler) =
+        let (program, uniform_screen_size, uniform_sampler, uniform_color_texture) =
             Self::create_program(&vert_shader_path, &frag_shader_path)?;
         let mesh = Mesh::empty()?;
         Ok(Self {
             program,
             uniform_screen_size,
             uniform_sampler,
+            uniform_color_texture,
             mesh,
             textures: HashMap::new(),
             tex_trash_bin: Vec::default(),
-            current_fbo: None,
             reload_watcher: reload_watcher
                 .proxy([vert_shader_path.as_path(), frag_shader_path.as_path()]),
         })
@@ -78,7 +76,7 @@ impl UiImpl {
     fn create_program(
         vert_shader_path: &Path,
         frag_shader_path: &Path,
-    ) -> Result<(Program, UniformLocation, UniformLocation)> {
+    ) -> Result<(Program, UniformLocation, UniformLocation, UniformLocation)> {
         let program = Program::load(
             vert_shader_path,
             Some(&frag_shader_path),
@@ -86,7 +84,13 @@ impl UiImpl {
         )?;
         let uniform_screen_size = program.uniform("u_screen_size");
         let uniform_sampler = program.uniform("u_sampler");
-        Ok((program, uniform_screen_size, uniform_sampler))
+        let uniform_color_texture = program.uniform("u_color_texture");
+        Ok((
+            program,
+            uniform_screen_size,
+            uniform_sampler,
+            uniform_color_texture,
+        ))
     }
 
     #[tracing::instrument(skip_all)]
@@ -99,28 +103,33 @@ impl UiImpl {
     ) -> Result<()> {
         tracing::trace!(message="Egui draw", primitices=%primitives.len());
         if self.reload_watcher.should_reload() {
-            let mut paths = self.reload_watcher.paths();
-            let vert_path = paths.next().unwrap();
-            let frag_path = paths.next().unwrap();
+            let paths = self.reload_watcher.paths();
+            let (vert_path, frag_path) = (paths[0].as_path(), paths[1].as_path());
             let result = Self::create_program(vert_path, frag_path);
             match result {
-                Ok((new_program, u_screen_size, u_sampler)) => {
+                Ok((new_program, u_screen_size, u_sampler, u_color_texture)) => {
                     self.program = new_program;
                     self.uniform_screen_size = u_screen_size;
                     self.uniform_sampler = u_sampler;
+                    self.uniform_color_texture = u_color_texture;
+                    shader_errors::resolve(frag_path);
                 }
                 Err(err) => {
-                    tracing::warn!("Error while reloading UI shaders: {}", err);
+                    tracing::warn!(
+                        shader_reload = true,
+                        "Error while reloading UI shaders: {}",
+                        err
+                    );
+                    shader_errors::report(frag_path, &err);
                 }
             }
         }
-        self.current_fbo.replace(frame);
         let _ = self.prepare_painting(size, ppp)?;
-        let sizef = size.cast();
+        let height = size.height as f32;
 
         for prim in primitives {
-            let (x, y, w, h) = to_gl_rect(prim.clip_rect, sizef, ppp);
-            Framebuffer::enable_scissor(x, y, w, h);
+            let clip = PixelRect::from_ui_rect(prim.clip_rect, height, ppp);
+            Framebuffer::enable_scissor(clip.min.x, clip.min.y, clip.size.x, clip.size.y);
 
             match &prim.primitive {
                 Primitive::Mesh(mesh) => {
@@ -128,23 +137,27 @@ impl UiImpl {
                 }
                 Primitive::Callback(callback) => {
                     if callback.rect.is_positive() {
-                        let (x, y, w, h) = to_gl_rect(callback.rect, sizef, ppp);
-                        Framebuffer::viewport(x, y, w, h);
+                        let viewport = PixelRect::from_ui_rect(callback.rect, height, ppp);
+                        Framebuffer::viewport(
+                            viewport.min.x,
+                            viewport.min.y,
+                            viewport.size.x,
+                            viewport.size.y,
+                        );
 
-                        let info = egui::PaintCallbackInfo {
-                            viewport: callback.rect,
-                            clip_rect: prim.clip_rect,
+                        let ctx = PaintContext {
+                            framebuffer: frame,
+                            viewport,
+                            clip,
                             pixels_per_point: ppp,
-                            screen_size_px: [size.width, size.height],
+                            screen_size: UVec2::new(size.width, size.height),
                         };
                         if let Some(callback) = callback.callback.downcast_ref::<UiCallback>() {
-                            if let Some(cb) = callback.0.get() {
-                                cb(info, self);
-                            } else {
-                                tracing::error!("Ui painter callback not created within the render thread -- this cannot work as OpenGL is not multithreaded")
+                            if let Err(err) = callback.call(&ctx) {
+                                tracing::error!("Error in UI paint callback: {:?}", err);
                             }
                         }
-                        // Framebuffer::viewport(0, 0, size.width as _, size.height as _);
+                        // Restore the state the UI is drawn with, callbacks may have changed it
                         let _ = self.prepare_painting(size, ppp)?;
                     }
                 }
@@ -153,7 +166,6 @@ impl UiImpl {
         Framebuffer::disable_scissor();
         Framebuffer::viewport(0, 0, size.width as _, size.height as _);
         self.tex_trash_bin.clear();
-        self.current_fbo.take();
         Ok(())
     }
 
@@ -170,10 +182,21 @@ impl UiImpl {
         self.mesh
             .indices()
             .set(&mesh.indices, violette::buffer::BufferUsageHint::Stream)?;
-        if let Some(texture) = self.texture(mesh.texture_id) {
+        // Viewport textures are rendered scenes, in color and already gamma-corrected
+        let is_viewport = if let Some(texture) = self.texture(mesh.texture_id) {
             self.program
-                .set_uniform(self.uniform_sampler, texture.as_uniform(0)?)?;
-        }
+                .set_uniform(self.uniform_sampler, texture.as_uniform(0).counted()?)?;
+            false
+        } else {
+            viewport::with_texture(mesh.texture_id, |texture| {
+                self.program
+                    .set_uniform(self.uniform_sampler, texture.as_uniform(0).counted()?)
+            })
+            .transpose()?
+            .is_some()
+        };
+        self.program
+            .set_uniform(self.uniform_color_texture, is_viewport as i32)?;
         self.mesh.draw(&self.program, frame, false)
     }
 
@@ -249,11 +272,6 @@ impl UiImpl {
         }
     }
 
-    pub fn framebuffer(&self) -> &Framebuffer {
-        // Safety: This is valid because the FBO is removed at the end of the draw call
-        unsafe { &*self.current_fbo.unwrap() }
-    }
-
     fn prepare_painting(&self, size: PhysicalSize<u32>, ppp: f32) -> Result<PhysicalSize<u32>> {
         violette::culling(None);
         Framebuffer::disable_depth_test();
@@ -270,25 +288,3 @@ impl UiImpl {
         Ok(size)
     }
 }
-
-fn to_gl_rect(rect: egui::Rect, size: PhysicalSize<f32>, ppp: f32) -> (i32, i32, i32, i32) {
-    // let min = LogicalPosition::new(rect.min.x, rect.min.y).to_physical::<f32>(ppp as _);
-    // let max = LogicalPosition::new(rect.max.x, rect.max.y).to_physical::<f32>(ppp as _);
-    // let min = Vec2::from_array(min.into())
-    //     .clamp(Vec2::ZERO, vec2(size.width, size.height))
-    //     .as_ivec2();
-    // let max = Vec2::from_array(max.into())
-    //     .clamp(Vec2::ZERO, vec2(size.width, size.height))
-    //     .as_ivec2();
-
-    // (min.x, min.y, max.x, max.y)
-    let pos = Vec2::from_array(rect.left_bottom().into()) * ppp;
-    let bias = Vec2::Y * size.height;
-    let scale = vec2(1., -1.);
-    let pos = pos * scale + bias;
-    let size = Vec2::from_array(rect.size().into()) * ppp;
-
-    let pos = pos.as_ivec2();
-    let size = size.as_ivec2();
-    (pos.x, pos.y, size.x, size.y)
-}
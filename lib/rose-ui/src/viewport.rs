@@ -0,0 +1,122 @@
+//! Render targets shown in the UI as images, to embed scene views in panels instead of drawing the
+//! scene behind the UI.
+
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    num::NonZeroU32,
+    rc::Rc,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use eyre::Result;
+use glam::UVec2;
+use rose_core::readback::{Readback, ReadbackFormat};
+use violette::{
+    framebuffer::Framebuffer,
+    texture::{Dimension, SampleMode, Texture},
+};
+
+pub type ViewportColor = Texture<[f32; 3]>;
+
+/// User texture IDs below this one are allocated by [`crate::painter::UiImpl::insert_texture`].
+const FIRST_VIEWPORT_ID: u64 = 1 << 32;
+
+static NEXT_VIEWPORT_ID: AtomicU64 = AtomicU64::new(FIRST_VIEWPORT_ID);
+
+thread_local! {
+    // OpenGL objects live on the render thread, which is also the one painting the UI
+    static TEXTURES: RefCell<HashMap<egui::TextureId, Rc<ViewportColor>>> =
+        RefCell::new(HashMap::new());
+}
+
+/// Run the function with the viewport texture of this ID, if there is one.
+pub(crate) fn with_texture<R>(
+    id: egui::TextureId,
+    func: impl FnOnce(&ViewportColor) -> R,
+) -> Option<R> {
+    let texture = TEXTURES.with(|textures| textures.borrow().get(&id).cloned())?;
+    Some(func(&texture))
+}
+
+/// Color texture rendered into through [`Self::framebuffer`], and displayed with [`Self::image`].
+/// Textures are sized in pixels; multiply the size of the UI rect by the pixels per point.
+#[derive(Debug)]
+pub struct ViewportTexture {
+    id: egui::TextureId,
+    size: UVec2,
+    fbo: Rc<Framebuffer>,
+}
+
+impl ViewportTexture {
+    pub fn new(size: UVec2) -> Result<Self> {
+        let fbo = Framebuffer::new();
+        let id = egui::TextureId::User(NEXT_VIEWPORT_ID.fetch_add(1, Ordering::Relaxed));
+        let mut this = Self {
+            id,
+            size: UVec2::ZERO,
+            fbo: Rc::new(fbo),
+        };
+        this.resize(size)?;
+        this.fbo.enable_buffers([0])?;
+        this.fbo.assert_complete()?;
+        Ok(this)
+    }
+
+    pub fn id(&self) -> egui::TextureId {
+        self.id
+    }
+
+    pub fn size(&self) -> UVec2 {
+        self.size
+    }
+
+    /// Framebuffer rendering into the texture, which can be handed over to the renderer.
+    pub fn framebuffer(&self) -> Rc<Framebuffer> {
+        self.fbo.clone()
+    }
+
+    /// Resize the texture, if the size changed. Returns whether it was resized.
+    pub fn resize(&mut self, size: UVec2) -> Result<bool> {
+        if size == self.size {
+            return Ok(false);
+        }
+        let Some(width) = NonZeroU32::new(size.x) else { eyre::bail!("Zero width resize"); };
+        let Some(height) = NonZeroU32::new(size.y) else { eyre::bail!("Zero height resize"); };
+        let texture = Texture::new(width, height, NonZeroU32::new(1).unwrap(), Dimension::D2);
+        texture.filter_min(SampleMode::Linear)?;
+        texture.filter_mag(SampleMode::Linear)?;
+        texture.reserve_memory()?;
+        self.fbo.attach_color(0, texture.mipmap(0).unwrap())?;
+        TEXTURES.with(|textures| textures.borrow_mut().insert(self.id, Rc::new(texture)));
+        self.size = size;
+        Ok(true)
+    }
+
+    /// Pixels of the last frame rendered into the texture, row by row starting from the bottom.
+    pub fn download(&self) -> Result<Vec<[f32; 3]>> {
+        with_texture(self.id, |texture| texture.mipmap(0).unwrap().download())
+            .unwrap_or_else(|| Err(eyre::eyre!("Viewport texture was not created")))
+    }
+
+    /// Start reading the last frame rendered into the texture without waiting for it, see
+    /// [`Self::download`].
+    pub fn readback(&self) -> Result<Readback> {
+        with_texture(self.id, |texture| {
+            Readback::texture(texture, 0, ReadbackFormat::Rgb32F)
+        })
+        .unwrap_or_else(|| Err(eyre::eyre!("Viewport texture was not created")))
+    }
+
+    /// Image showing the texture, flipped as OpenGL textures start at the bottom.
+    pub fn image(&self, size: impl Into<egui::Vec2>) -> egui::Image {
+        let uv = egui::Rect::from_min_max(egui::pos2(0., 1.), egui::pos2(1., 0.));
+        egui::Image::new(self.id, size).uv(uv)
+    }
+}
+
+impl Drop for ViewportTexture {
+    fn drop(&mut self) {
+        TEXTURES.with(|textures| textures.borrow_mut().remove(&self.id));
+    }
+}
@@ -2,7 +2,9 @@
 
 use eyre::Result;
 
-use rose_platform::{Application, PhysicalSize, RenderContext};
+use rose_platform::{
+    config::LaunchConfig, window::WindowSize, Application, PhysicalSize, RenderContext,
+};
 
 use crate::tests::IntegrationTest;
 
@@ -11,8 +13,8 @@ pub mod tests;
 struct TestRunner(PhysicalSize<f32>);
 
 impl Application for TestRunner {
-    fn new(size: PhysicalSize<f32>, _scale_factor: f64) -> Result<Self> {
-        Ok(Self(size))
+    fn new(size: WindowSize, _config: &LaunchConfig) -> Result<Self> {
+        Ok(Self(size.physical.cast()))
     }
 
     fn render(&mut self, mut ctx: RenderContext) -> Result<()> {
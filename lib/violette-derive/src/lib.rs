@@ -11,6 +11,8 @@ struct Attribute {
     ty: syn::Type,
     #[darling(default)]
     ignore: bool,
+    #[darling(default)]
+    normalized: bool,
 }
 
 #[derive(Debug, FromDeriveInput)]
@@ -19,7 +21,7 @@ struct VertexInput {
     data: ast::Data<(), Attribute>,
 }
 
-#[proc_macro_derive(VertexAttributes)]
+#[proc_macro_derive(VertexAttributes, attributes(attribute))]
 pub fn derive_vertex_attributes(item: proc_macro::TokenStream) -> proc_macro::TokenStream {
     let input = parse_macro_input!(item);
     let input = VertexInput::from_derive_input(&input).expect("Cannot parse non-derive input");
@@ -39,7 +41,12 @@ pub fn derive_vertex_attributes(item: proc_macro::TokenStream) -> proc_macro::To
     let input = attributes.iter().enumerate().map(|(_i, attr)| {
         let ty = &attr.ty;
         let ident = attr.ident.clone().expect("Tuple structs are not supported");
-        quote!(::violette::vertex::VertexDesc::from_gl_type::<#ty>(::bytemuck::offset_of!(Self, #ident)))
+        let desc = quote!(::violette::vertex::VertexDesc::from_gl_type::<#ty>(::bytemuck::offset_of!(Self, #ident)));
+        if attr.normalized {
+            quote!(#desc.normalized())
+        } else {
+            desc
+        }
     }).collect::<Vec<_>>();
 
     quote!(
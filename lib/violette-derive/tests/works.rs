@@ -22,3 +22,25 @@ fn works() {
     actual.sort_by_key(|d| d.offset);
     assert_eq!(actual, expected);
 }
+
+#[test]
+fn normalized_and_ignored() {
+    #[derive(Debug, Default, Clone, Copy, Pod, Zeroable, VertexAttributes)]
+    #[repr(C)]
+    struct TestVertex {
+        pos: [f32; 3],
+        #[attribute(normalized)]
+        color: [u8; 4],
+        #[attribute(ignore)]
+        _padding: u32,
+    }
+
+    let mut expected = vec![
+        VertexDesc::from_gl_type::<[f32; 3]>(0),
+        VertexDesc::from_gl_type::<[u8; 4]>(offset_of!(TestVertex, color)).normalized(),
+    ];
+    let mut actual = TestVertex::attributes().to_vec();
+    expected.sort_by_key(|d| d.offset);
+    actual.sort_by_key(|d| d.offset);
+    assert_eq!(actual, expected);
+}
@@ -0,0 +1,114 @@
+// Requires "pbr.glsl" and "uniforms/light.glsl" to be included first.
+
+/* Area lights, using the polygon integration from "Real-Time Polygonal-Light Shading with Linearly
+ * Transformed Cosines" (Heitz et al. 2016). The diffuse term is the LTC with an identity transform
+ * (a clamped cosine) and as such needs no fitted table. The specular term uses the GGX fits of
+ * `ltc_inverse` and `ltc_amplitude`, made offline by the `ltc_fit` tool. Disks are integrated as
+ * regular octagons of the same area. */
+
+uniform sampler2D ltc_inverse;// <- non-constant coefficients of the inverse transforms
+uniform sampler2D ltc_amplitude;// <- magnitude and Fresnel terms
+
+// Must match `ltc::LTC_SIZE`
+const float LTC_SIZE = 64.0;
+const float LTC_SCALE = (LTC_SIZE - 1.0) / LTC_SIZE;
+const float LTC_BIAS = 0.5 / LTC_SIZE;
+
+const int AREA_DISK_SIDES = 8;
+// Ratio between the circumradius of an octagon and the radius of a disk of the same area
+const float AREA_DISK_SCALE = 1.0539;
+
+vec3 integrate_edge(vec3 v1, vec3 v2) {
+    float x = dot(v1, v2);
+    float y = abs(x);
+    float a = 0.8543985 + (0.4965155 + 0.0145206 * y) * y;
+    float b = 3.4175940 + (4.1616724 + y) * y;
+    float v = a / b;
+    float theta_sintheta = (x > 0.0) ? v : 0.5 * inversesqrt(max(1.0 - x * x, 1e-7)) - v;
+    return cross(v1, v2) * theta_sintheta;
+}
+
+// Vector form factor of the light polygon transformed by `Minv`, as seen from `position`; its length
+// is the form factor of the polygon, and it points towards the polygon.
+vec3 area_vector_form_factor(mat3 Minv, vec3 position, vec3 center, vec3 ex, vec3 ey, bool disk) {
+    vec3 sum = vec3(0);
+    if (disk) {
+        vec3 first = normalize(Minv * (center + AREA_DISK_SCALE * ex - position));
+        vec3 prev = first;
+        for (int i = 1; i < AREA_DISK_SIDES; ++i) {
+            float angle = M_TAU * float(i) / float(AREA_DISK_SIDES);
+            vec3 p = center + AREA_DISK_SCALE * (cos(angle) * ex + sin(angle) * ey);
+            vec3 next = normalize(Minv * (p - position));
+            sum += integrate_edge(prev, next);
+            prev = next;
+        }
+        sum += integrate_edge(prev, first);
+    } else {
+        vec3 p0 = normalize(Minv * (center - ex - ey - position));
+        vec3 p1 = normalize(Minv * (center + ex - ey - position));
+        vec3 p2 = normalize(Minv * (center + ex + ey - position));
+        vec3 p3 = normalize(Minv * (center - ex + ey - position));
+        sum += integrate_edge(p0, p1);
+        sum += integrate_edge(p1, p2);
+        sum += integrate_edge(p2, p3);
+        sum += integrate_edge(p3, p0);
+    }
+    sum /= M_TAU;
+    // The sign depends on the winding of the polygon as seen from the shaded point
+    return dot(sum, Minv * (center - position)) < 0.0 ? -sum : sum;
+}
+
+// Integral of the clamped cosine around Z over the polygon transformed by `Minv`. The part of the
+// polygon below the horizon is accounted for by treating it as a sphere of the same vector form
+// factor, whose length is the squared sine of its angular radius.
+float area_integral(mat3 Minv, vec3 position, vec3 center, vec3 ex, vec3 ey, bool disk) {
+    vec3 form_factor = area_vector_form_factor(Minv, position, center, ex, ey, disk);
+    float len = length(form_factor);
+    if (len < 1e-6) {
+        return 0.0;
+    }
+    float z = form_factor.z / len;
+    float sin_alpha = sqrt(min(len, 1.0));
+    if (z < sin_alpha) {
+        // Hermite spline from 0 below the horizon to the unclipped cosine above it
+        z = max(z, -sin_alpha);
+        z = (sin_alpha + z) * (sin_alpha + z) / (4.0 * sin_alpha);
+    }
+    return len * z;
+}
+
+vec3 area_light(vec3 position, vec3 N, vec3 V, vec3 albedo, float roughness, float metallic) {
+    vec3 center = light.pos_dir;
+    vec3 ex = light.extent_x;
+    vec3 ey = light.extent_y;
+    bool disk = light.kind == LIGHT_KIND_DISK;
+    vec3 light_normal = normalize(cross(ex, ey));
+    bool front = dot(position - center, light_normal) > 0.0;
+    if (!front && light.two_sided == 0u) {
+        return vec3(0);
+    }
+
+    // Frame around the normal with the view direction in its XZ plane, which the fits assume
+    float NdotV = clamp(dot(N, V), 0.0, 1.0);
+    vec3 T1 = V - N * dot(V, N);
+    if (dot(T1, T1) < 1e-8) {
+        T1 = cross(N, abs(N.z) < 0.999 ? vec3(0, 0, 1) : vec3(1, 0, 0));
+    }
+    T1 = normalize(T1);
+    vec3 T2 = cross(N, T1);
+    mat3 to_local = transpose(mat3(T1, T2, N));
+
+    float diffuse = area_integral(to_local, position, center, ex, ey, disk);
+
+    vec2 uv = vec2(roughness, sqrt(1.0 - NdotV)) * LTC_SCALE + LTC_BIAS;
+    vec4 t1 = texture(ltc_inverse, uv);
+    vec2 t2 = texture(ltc_amplitude, uv).rg;
+    mat3 Minv = mat3(vec3(t1.x, 0, t1.y), vec3(0, 1, 0), vec3(t1.z, 0, t1.w));
+    float specular = area_integral(Minv * to_local, position, center, ex, ey, disk);
+    // Schlick Fresnel integrated over the lobe
+    vec3 specular_color = F0 * t2.x + (1.0 - F0) * t2.y;
+
+    vec3 kS = fresnel_roughness(NdotV, F0, roughness);
+    vec3 kD = (vec3(1.0) - kS) * (1.0 - metallic);
+    return light.color * (kD * albedo * diffuse + specular_color * specular);
+}
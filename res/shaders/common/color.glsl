@@ -3,3 +3,10 @@ float desaturate(vec3 color) {
     return dot(color, to_luma);
 }
 
+
+// sRGB encoding of linear colors, for displays and 8-bit outputs
+vec3 linear_to_srgb(vec3 color) {
+    vec3 lower = color * 12.92;
+    vec3 higher = 1.055 * pow(color, vec3(1.0 / 2.4)) - 0.055;
+    return mix(higher, lower, vec3(lessThanEqual(color, vec3(0.0031308))));
+}
@@ -0,0 +1,24 @@
+#include "gbuffer_normal.glsl"
+
+// G-Buffer outputs, mirrors `rose_renderer::gbuffers::GBufferAttachment`.
+layout(location=0) out vec3 frame_position;// <- relative to `view.origin`
+layout(location=1) out vec3 frame_albedo;
+layout(location=2) out vec4 frame_normal;// <- world space normal, coverage
+layout(location=3) out vec2 frame_rough_metal;
+layout(location=4) out vec3 frame_emission;
+layout(location=5) out vec4 frame_coat_aniso;// <- clearcoat, clearcoat roughness, anisotropy, anisotropy direction
+
+void write_gbuffer(vec3 position, vec3 albedo, vec3 normal, float roughness, float metallic, vec3 emission) {
+    frame_position = position;
+    frame_albedo = albedo;
+    frame_normal = vec4(encode_normal(normal), 1);
+    frame_rough_metal = vec2(roughness, metallic);
+    frame_emission = emission;
+    frame_coat_aniso = vec4(0);
+}
+
+// Add clearcoat and anisotropy layers, after `write_gbuffer`. The anisotropy direction is in world
+// space.
+void write_gbuffer_layers(vec3 normal, float clearcoat, float clearcoat_roughness, float anisotropy, vec3 anisotropy_dir) {
+    frame_coat_aniso = vec4(clearcoat, clearcoat_roughness, anisotropy, encode_anisotropy_direction(normal, anisotropy_dir));
+}
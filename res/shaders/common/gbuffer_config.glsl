@@ -0,0 +1,2 @@
+// Default G-Buffer configuration, replaced by the renderer with one generated from its
+// `GBufferSettings`.
@@ -0,0 +1,51 @@
+// Normal encoding of the G-Buffer, configured from `rose_renderer::gbuffers::GBufferSettings`.
+// Normals are remapped to [0, 1] to fit normalized formats, optionally octahedron-encoded into the
+// first two components.
+#include <gbuffer_config.glsl>
+
+vec2 octahedral_wrap(vec2 v) {
+    return (1.0 - abs(v.yx)) * vec2(v.x >= 0.0 ? 1.0 : -1.0, v.y >= 0.0 ? 1.0 : -1.0);
+}
+
+vec3 encode_normal(vec3 n) {
+#ifdef GBUFFER_OCTAHEDRAL_NORMALS
+    n /= abs(n.x) + abs(n.y) + abs(n.z);
+    vec2 e = n.z >= 0.0 ? n.xy : octahedral_wrap(n.xy);
+    return vec3(e * 0.5 + 0.5, 0.0);
+#else
+    return n * 0.5 + 0.5;
+#endif
+}
+
+vec3 decode_normal(vec3 e) {
+#ifdef GBUFFER_OCTAHEDRAL_NORMALS
+    vec2 f = e.xy * 2.0 - 1.0;
+    vec3 n = vec3(f, 1.0 - abs(f.x) - abs(f.y));
+    float t = max(-n.z, 0.0);
+    n.xy += vec2(n.x >= 0.0 ? -t : t, n.y >= 0.0 ? -t : t);
+    return normalize(n);
+#else
+    return normalize(e * 2.0 - 1.0);
+#endif
+}
+
+// Tangent of the normal from which anisotropy directions are measured, see
+// `encode_anisotropy_direction`.
+vec3 reference_tangent(vec3 n) {
+    vec3 up = abs(n.z) < 0.999 ? vec3(0.0, 0.0, 1.0) : vec3(1.0, 0.0, 0.0);
+    return normalize(cross(up, n));
+}
+
+// Anisotropy directions are stored as their angle around the normal in [0, 1], over half a turn as
+// the direction and its opposite stretch highlights the same way.
+float encode_anisotropy_direction(vec3 n, vec3 dir) {
+    vec3 t = reference_tangent(n);
+    float angle = atan(dot(dir, cross(n, t)), dot(dir, t));
+    return fract(angle / 3.14159265 + 1.0);
+}
+
+vec3 decode_anisotropy_direction(vec3 n, float e) {
+    vec3 t = reference_tangent(n);
+    float angle = e * 3.14159265;
+    return cos(angle) * t + sin(angle) * cross(n, t);
+}
@@ -138,3 +138,61 @@ vec3 get_lighting(Lighting l) {
     float NdotL = max(0.0, l.NdotL);
     return (kD * l.albedo / M_PI + specular) * radiance * NdotL;
 }
+
+// Clearcoat and anisotropy of the material, following `KHR_materials_clearcoat` and
+// `KHR_materials_anisotropy`.
+struct MaterialLayers {
+    float clearcoat, clearcoat_roughness;
+    float anisotropy;
+    vec3 T, B;// <- anisotropy direction and its bitangent, world space
+};
+
+float ggx_dist_isotropic(float NdotH, float a) {
+    float a2 = a * a;
+    float denom = NdotH * NdotH * (a2 - 1.0) + 1.0;
+    return a2 / max(M_PI * denom * denom, 1e-4);
+}
+
+// GGX stretched along the tangent, from Burley 2012 "Physically-Based Shading at Disney"
+float ggx_dist_anisotropic(float NdotH, float TdotH, float BdotH, float at, float ab) {
+    float a2 = at * ab;
+    vec3 d = vec3(ab * TdotH, at * BdotH, a2 * NdotH);
+    float b2 = a2 / max(dot(d, d), 1e-7);
+    return a2 * b2 * b2 / M_PI;
+}
+
+// `get_lighting` with the specular lobe stretched along the anisotropy direction, and a clearcoat
+// lobe on top attenuating the base layer. `V` points towards the camera.
+vec3 get_layered_lighting(Lighting l, MaterialLayers layers, vec3 V) {
+    vec3 L = l.source.dir;
+    vec3 H = normalize(V + L);
+    float NdotL = max(0.0, l.NdotL);
+    vec3 radiance = diffuse_brdf(l.source);
+
+    vec3 base;
+    if (layers.anisotropy > 0.0) {
+        vec3 kS = fresnel_roughness(max(0.0, l.HdotV), F0, l.mat.metallic);
+        vec3 kD = (vec3(1.0) - kS) * (1.0 - l.mat.metallic);
+        float alpha = max(l.mat.rough_sqr, 1e-3);
+        float at = mix(alpha, 1.0, layers.anisotropy * layers.anisotropy);
+        float D = ggx_dist_anisotropic(max(dot(l.N, H), 0.0), dot(layers.T, H), dot(layers.B, H), at, alpha);
+        float NdotV = max(0.0, l.NdotV);
+        float G = ggx_geom(NdotV, l.mat.roughness) * ggx_geom(NdotL, l.mat.roughness);
+        vec3 F = fresnel_roughness(NdotV, F0, l.mat.roughness);
+        vec3 specular = l.source.specular * D * G * F / max(4.0 * NdotV * NdotL * l.source.distance, 1e-4);
+        base = (kD * l.albedo / M_PI + specular) * radiance * NdotL;
+    } else {
+        base = get_lighting(l);
+    }
+
+    if (layers.clearcoat > 0.0) {
+        float a = max(layers.clearcoat_roughness * layers.clearcoat_roughness, 1e-3);
+        float Dc = ggx_dist_isotropic(max(dot(l.N, H), 0.0), a);
+        float LdotH = max(dot(L, H), 1e-2);
+        // Kelemen visibility, cheap and good enough for a thin smooth coat
+        float Vc = 0.25 / (LdotH * LdotH);
+        float Fc = fresnel(LdotH, vec3(0.04)).x * layers.clearcoat;
+        base = base * (1.0 - Fc) + Dc * Vc * Fc * radiance * NdotL;
+    }
+    return base;
+}
@@ -1,4 +1,10 @@
 struct Bone {
     mat4 transform;
-    mat4 padding[3];
+    // Rigid part of the transform as a unit dual quaternion
+    vec4 real;
+    vec4 dual;
+    // Blend the dual quaternions instead of the matrices, same for all the bones of a mesh
+    uint dual_quaternion;
+    // Elements of uniform buffers are 256 bytes apart
+    vec4 padding[9];
 };
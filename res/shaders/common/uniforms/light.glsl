@@ -1,9 +1,14 @@
 const uint LIGHT_KIND_POINT = 0u;
 const uint LIGHT_KIND_DIRECTIONAL = 1u;
 const uint LIGHT_KIND_AMBIENT = 2u;
+const uint LIGHT_KIND_RECT = 3u;
+const uint LIGHT_KIND_DISK = 4u;
 
 layout(std140) uniform Light {
     uint kind;
-    vec3 pos_dir;// <- world space
+    vec3 pos_dir;// <- position relative to `view.origin`, or world space direction
     vec3 color;
+    vec3 extent_x;// <- area lights only, half size along the local X axis
+    vec3 extent_y;// <- area lights only, half size along the local Y axis
+    uint two_sided;
 } light;
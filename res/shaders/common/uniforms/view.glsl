@@ -3,6 +3,28 @@ layout(std140) uniform View {
     mat4 mat_proj;
     mat4 inv_view;
     mat4 inv_proj;
+    // View matrix of positions relative to `origin`
+    mat4 mat_view_relative;
     vec4 viewport;
+    // Relative to `origin`, like the positions of the G-Buffer
     vec3 camera_pos;
+    // 1 with reversed depth, where the near plane is at 1 and the far plane at 0
+    float reverse_z;
+    // World position which model matrices, and the positions shaded from them, are relative to
+    vec3 origin;
 } view;
+
+// Depth buffer value in normalized device coordinates, for unprojecting with `view.inv_proj`
+float depth_to_ndc(float depth_value) {
+    return view.reverse_z > 0.5 ? depth_value : depth_value * 2. - 1.;
+}
+
+// Whether depth buffer value `a` is closer to the camera than `b`
+bool depth_closer(float a, float b) {
+    return view.reverse_z > 0.5 ? a > b : a < b;
+}
+
+// Whether the depth buffer value is at the far plane, i.e. nothing was drawn there
+bool depth_is_far(float depth_value) {
+    return view.reverse_z > 0.5 ? depth_value <= 0. : depth_value >= 1.;
+}
@@ -0,0 +1,11 @@
+uniform sampler2D in_texture;
+
+in vec2 v_uv;
+
+out vec4 out_color;
+
+void main() {
+    // Image rows go from top to bottom
+    vec4 color = texture(in_texture, vec2(v_uv.x, 1. - v_uv.y));
+    out_color = vec4(color.rgb * color.a, color.a);
+}
@@ -0,0 +1,3 @@
+// Depth pre-pass: only depth is written, color writes are masked out by the renderer.
+
+void main() {}
@@ -0,0 +1,13 @@
+#include "../common/gbuffer.glsl"
+
+// Stand-in for materials whose shaders failed to compile: emissive magenta and black checkerboard.
+
+in vec3 vs_position;
+in vec3 vs_normal;
+
+void main() {
+    ivec3 cell = ivec3(floor(vs_position * 4.));
+    bool odd = ((cell.x + cell.y + cell.z) & 1) == 1;
+    vec3 color = odd ? vec3(1, 0, 1) : vec3(0);
+    write_gbuffer(vs_position, vec3(0), normalize(vs_normal), 1., 0., color);
+}
@@ -1,12 +1,10 @@
+#include "../common/gbuffer.glsl"
+
 in vec3 vs_position;
 in vec2 vs_uv;
 in vec3 vs_normal;
-
-layout(location=0) out vec3 frame_position;
-layout(location=1) out vec3 frame_albedo;
-layout(location=2) out vec4 frame_normal;
-layout(location=3) out vec2 frame_rough_metal;
-layout(location=4) out vec3 frame_emission;
+in vec4 vs_color;
+in vec2 vs_uv2;
 
 layout(std140) uniform Uniforms {
     bool has_color;
@@ -17,12 +15,24 @@ layout(std140) uniform Uniforms {
     vec2 rough_metal_factor;
     bool has_emission;
     vec3 emission_factor;
+    float emission_strength;
+    bool double_sided;
+    uvec4 uv_channels;// <- color, normal, rough_metal, emission
+    float clearcoat;
+    float clearcoat_roughness;
+    float anisotropy;
+    float anisotropy_rotation;// <- radians, from the U direction of the normal map UVs
+    bool has_clearcoat_map;
+    bool has_anisotropy_map;
+    uvec2 layer_uv_channels;// <- clearcoat, anisotropy
 } uniforms;
 
 uniform sampler2D map_color;
 uniform sampler2D map_normal;
 uniform sampler2D map_rough_metal;
 uniform sampler2D map_emission;
+uniform sampler2D map_clearcoat;// <- strength, roughness
+uniform sampler2D map_anisotropy;// <- tangent space direction, strength
 
 mat3 cotangent_frame(vec3 pos, vec3 normal, vec2 uv) {
     vec3 dp1 = dFdx(pos);
@@ -37,30 +47,63 @@ mat3 cotangent_frame(vec3 pos, vec3 normal, vec2 uv) {
     return mat3(T * invmax, B * invmax, normal);
 }
 
+vec2 slot_uv(uint channel) {
+    return channel == 0u ? vs_uv : vs_uv2;
+}
+
 void main() {
     frame_position = vs_position;
 
-    frame_albedo = uniforms.color_factor;
+    frame_albedo = uniforms.color_factor * vs_color.rgb;
     if (uniforms.has_color)
-    frame_albedo *= texture(map_color, vs_uv).rgb;
+    frame_albedo *= texture(map_color, slot_uv(uniforms.uv_channels.x)).rgb;
+
+    vec3 normal = vs_normal;
+    if (uniforms.double_sided && !gl_FrontFacing)
+        normal = -normal;
 
     vec3 out_normal;
     if (uniforms.has_normal) {
         float normal_amount = uniforms.normal_amount;
-        mat3 tbn = cotangent_frame(vs_position, vs_normal, vs_uv);
-        vec3 tangent_map = (texture(map_normal, vs_uv).xyz * 2. - 1.) * vec3(normal_amount, normal_amount, 1.);
+        vec2 normal_uv = slot_uv(uniforms.uv_channels.y);
+        mat3 tbn = cotangent_frame(vs_position, normal, normal_uv);
+        vec3 tangent_map = (texture(map_normal, normal_uv).xyz * 2. - 1.) * vec3(normal_amount, normal_amount, 1.);
         out_normal = normalize(tbn * tangent_map);// <- world space
     } else {
-        out_normal = vs_normal;
+        out_normal = normal;
     }
 
-    frame_emission = uniforms.emission_factor * 10;
+    frame_emission = uniforms.emission_factor * uniforms.emission_strength;
     if(uniforms.has_emission)
-        frame_emission *= texture(map_emission, vs_uv).rgb;
+        frame_emission *= texture(map_emission, slot_uv(uniforms.uv_channels.w)).rgb;
 
-    frame_normal = vec4(out_normal, 1);
+    frame_normal = vec4(encode_normal(out_normal), 1);
 
     frame_rough_metal = uniforms.rough_metal_factor;
     if (uniforms.has_rough_metal)
-    frame_rough_metal *= texture(map_rough_metal, vs_uv).rg;
+    frame_rough_metal *= texture(map_rough_metal, slot_uv(uniforms.uv_channels.z)).rg;
+
+    float clearcoat = uniforms.clearcoat;
+    float clearcoat_roughness = uniforms.clearcoat_roughness;
+    if (uniforms.has_clearcoat_map) {
+        vec2 coat = texture(map_clearcoat, slot_uv(uniforms.layer_uv_channels.x)).rg;
+        clearcoat *= coat.r;
+        clearcoat_roughness *= coat.g;
+    }
+    frame_coat_aniso = vec4(clearcoat, clearcoat_roughness, 0, 0);
+    if (uniforms.anisotropy > 0.) {
+        float anisotropy = uniforms.anisotropy;
+        vec2 direction = vec2(1, 0);
+        if (uniforms.has_anisotropy_map) {
+            vec3 texel = texture(map_anisotropy, slot_uv(uniforms.layer_uv_channels.y)).rgb;
+            direction = texel.rg * 2. - 1.;
+            anisotropy *= texel.b;
+        }
+        float c = cos(uniforms.anisotropy_rotation);
+        float s = sin(uniforms.anisotropy_rotation);
+        direction = mat2(c, s, -s, c) * direction;
+        mat3 tbn = cotangent_frame(vs_position, out_normal, slot_uv(uniforms.uv_channels.y));
+        vec3 anisotropy_dir = normalize(tbn * vec3(direction, 0.));
+        write_gbuffer_layers(out_normal, clearcoat, clearcoat_roughness, anisotropy, anisotropy_dir);
+    }
 }
\ No newline at end of file
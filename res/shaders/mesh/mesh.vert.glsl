@@ -1,6 +1,7 @@
 #include "../common/uniforms/view.glsl"
 #include "../common/uniforms/bone.glsl"
 
+// Same as `bones::MAX_BONES`
 const int MAX_BONES = 32;
 
 in vec3 position;
@@ -8,41 +9,133 @@ in vec3 normal;
 in vec2 uv;
 in ivec4 bone_ix;
 in vec4 bone_w;
+in vec4 color;
+in vec2 uv2;
 
 layout(std140) uniform Bones {
     Bone bones[MAX_BONES];
 };
+// Relative to `view.origin`
 uniform mat4 model;
 
+// Same as `morph::MAX_MORPH_TARGETS` and `morph::MORPH_TEXTURE_WIDTH`
+const int MAX_MORPH_TARGETS = 8;
+const int MORPH_TEXTURE_WIDTH = 1024;
+// Position then normal deltas of each target, by vertex index, in rows of MORPH_TEXTURE_WIDTH
+uniform sampler2D morph_deltas;
+uniform int morph_count;
+uniform int morph_vertex_count;
+uniform float morph_weights[MAX_MORPH_TARGETS];
+
+// Same as `clip::MAX_CLIP_PLANES`
+const int MAX_CLIP_PLANES = 4;
+// Plane equations relative to `view.origin`; points on the negative side of a plane are clipped
+uniform int clip_plane_count;
+uniform vec4 clip_planes[MAX_CLIP_PLANES];
+out float gl_ClipDistance[MAX_CLIP_PLANES];
+
+// Shared with the depth pre-pass, which needs both passes to compute the exact same depth
+invariant gl_Position;
+
 out vec3 vs_position;
 out vec2 vs_uv;
 out vec3 vs_normal;
+out vec4 vs_color;
+out vec2 vs_uv2;
+
+vec3 morph_delta(int target, int kind) {
+    int i = (2 * target + kind) * morph_vertex_count + gl_VertexID;
+    return texelFetch(morph_deltas, ivec2(i % MORPH_TEXTURE_WIDTH, i / MORPH_TEXTURE_WIDTH), 0).rgb;
+}
+
+void morph(inout vec3 p, inout vec3 n) {
+    for (int t = 0; t < morph_count; t++) {
+        float w = morph_weights[t];
+        if (w == 0.0) continue;
+        p += morph_delta(t, 0) * w;
+        n += morph_delta(t, 1) * w;
+    }
+}
+
+// Weights of the valid influences of the vertex, summing to 1, or all zero without influences
+vec4 bone_weights() {
+    vec4 w = mix(vec4(0), bone_w, greaterThanEqual(bone_ix, ivec4(0)));
+    float total = dot(w, vec4(1));
+    return total > 0.0 ? w / total : vec4(0);
+}
+
+Bone influence(int i) {
+    return bones[min(bone_ix[i], MAX_BONES - 1)];
+}
+
+mat4 bone_matrix(vec4 w) {
+    mat4 m = mat4(0);
+    for (int i = 0; i < 4; i++) {
+        if (w[i] > 0.0) m += influence(i).transform * w[i];
+    }
+    return m;
+}
+
+// Blended dual quaternion, as the real part in the first column and the dual part in the second
+mat2x4 bone_dual_quaternion(vec4 w) {
+    vec4 real = vec4(0);
+    vec4 dual = vec4(0);
+    for (int i = 0; i < 4; i++) {
+        if (w[i] <= 0.0) continue;
+        Bone bone = influence(i);
+        // Blend in the same hemisphere as the bones so far, taking the shortest path
+        float k = dot(real, bone.real) < 0.0 ? -w[i] : w[i];
+        real += bone.real * k;
+        dual += bone.dual * k;
+    }
+    float len = length(real);
+    return mat2x4(real / len, dual / len);
+}
+
+vec3 quat_rotate(vec4 q, vec3 v) {
+    return v + 2.0 * cross(q.xyz, cross(q.xyz, v) + q.w * v);
+}
 
-vec4 bone_transform_pos() {
-    vec4 p = vec4(position, 1);
-    if (all(lessThan(bone_ix, ivec4(0)))) return p;
-    return bones[0].transform * p * bone_w[0]
-    + bones[1].transform * p * bone_w[1]
-    + bones[2].transform * p * bone_w[2]
-    + bones[3].transform * p * bone_w[3];
+// Translation of the unit dual quaternion, 2 * dual * conjugate(real)
+vec3 dual_quaternion_translation(mat2x4 dq) {
+    vec4 r = dq[0];
+    vec4 d = dq[1];
+    return 2.0 * (r.w * d.xyz - d.w * r.xyz + cross(r.xyz, d.xyz));
 }
 
-vec4 bone_transform_normal() {
-    vec4 n = vec4(normal, 0);
-    if (all(lessThan(bone_ix, ivec4(0)))) return n;
-    return bones[0].transform * n * bone_w[0]
-    + bones[1].transform * n * bone_w[1]
-    + bones[2].transform * n * bone_w[2]
-    + bones[3].transform * n * bone_w[3];
+void skin(vec3 position, vec3 normal, out vec4 p, out vec4 n) {
+    p = vec4(position, 1);
+    n = vec4(normal, 0);
+    vec4 w = bone_weights();
+    if (w == vec4(0)) return;
+    if (bones[0].dual_quaternion != 0u) {
+        mat2x4 dq = bone_dual_quaternion(w);
+        p.xyz = quat_rotate(dq[0], position) + dual_quaternion_translation(dq);
+        n.xyz = quat_rotate(dq[0], normal);
+    } else {
+        mat4 m = bone_matrix(w);
+        p = m * p;
+        n = m * n;
+    }
 }
 
 void main() {
-    mat4 view_proj = view.mat_proj * view.mat_view;
+    mat4 view_proj = view.mat_proj * view.mat_view_relative;
     mat4 transform = view_proj * model;
-    gl_Position = model * bone_transform_pos();
-    vs_position = gl_Position.xyz/gl_Position.w;// <- world space
+    vec3 morphed_position = position;
+    vec3 morphed_normal = normal;
+    morph(morphed_position, morphed_normal);
+    vec4 skinned_position, skinned_normal;
+    skin(morphed_position, morphed_normal, skinned_position, skinned_normal);
+    gl_Position = model * skinned_position;
+    vs_position = gl_Position.xyz/gl_Position.w;// <- relative to `view.origin`
+    for (int i = 0; i < MAX_CLIP_PLANES; i++) {
+        gl_ClipDistance[i] = i < clip_plane_count ? dot(clip_planes[i], vec4(vs_position, 1)) : 1.0;
+    }
     vs_uv = uv;
-    vec4 pnormal = model * normalize(bone_transform_normal());
+    vs_color = color;
+    vs_uv2 = uv2;
+    vec4 pnormal = model * normalize(skinned_normal);
     gl_Position = view_proj * gl_Position;
     vs_normal = pnormal.xyz;
 }
@@ -0,0 +1,54 @@
+#include "../common/uniforms/view.glsl"
+
+// Refractive surface, drawn over the lit scene which it samples through `scene_color`.
+
+in vec3 vs_position;
+in vec2 vs_uv;
+in vec3 vs_normal;
+in vec4 vs_color;
+in vec2 vs_uv2;
+
+out vec4 out_color;
+
+uniform sampler2D scene_color;
+uniform sampler2D scene_depth;
+uniform float max_lod;
+uniform vec3 tint;
+uniform float ior;
+uniform float roughness;
+uniform float thickness;
+
+float linear_depth(float depth_value) {
+    vec4 view_pos = view.inv_proj * vec4(0, 0, depth_to_ndc(depth_value), 1);
+    return -view_pos.z / view_pos.w;
+}
+
+vec2 project_to_screen(vec3 position) {
+    vec4 clip = view.mat_proj * view.mat_view_relative * vec4(position, 1);
+    return clip.xy / clip.w * 0.5 + 0.5;
+}
+
+void main() {
+    vec2 screen_uv = gl_FragCoord.xy / view.viewport.zw;
+    float surface_depth = linear_depth(gl_FragCoord.z);
+    // Manual depth test, as the depth buffer is read rather than attached
+    if (linear_depth(texture(scene_depth, screen_uv).r) < surface_depth)
+        discard;
+
+    vec3 normal = normalize(vs_normal);
+    vec3 view_dir = normalize(view.camera_pos - vs_position);
+    // Back faces of double-sided meshes refract out of the object
+    if (!gl_FrontFacing)
+        normal = -normal;
+    vec3 refracted = refract(-view_dir, normal, 1. / ior);
+    vec2 refracted_uv = project_to_screen(vs_position + refracted * thickness);
+    // Geometry in front of the surface must not show through it
+    float refracted_depth = linear_depth(texture(scene_depth, refracted_uv).r);
+    if (any(lessThan(refracted_uv, vec2(0))) || any(greaterThan(refracted_uv, vec2(1))) || refracted_depth < surface_depth)
+        refracted_uv = screen_uv;
+
+    vec3 transmitted = textureLod(scene_color, refracted_uv, roughness * max_lod).rgb;
+    float f0 = pow((ior - 1.) / (ior + 1.), 2.);
+    float fresnel = f0 + (1. - f0) * pow(1. - max(dot(normal, view_dir), 0.), 5.);
+    out_color = vec4(transmitted * tint * vs_color.rgb * (1. - fresnel), 1);
+}
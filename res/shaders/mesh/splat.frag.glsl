@@ -0,0 +1,95 @@
+#include "../common/gbuffer.glsl"
+
+// Layered material, blending up to 4 layers with weights read from a splat map or vertex colors.
+
+in vec3 vs_position;
+in vec2 vs_uv;
+in vec3 vs_normal;
+in vec4 vs_color;
+in vec2 vs_uv2;
+
+layout(std140) uniform Uniforms {
+    bool weights_from_vertex_color;
+    uint layer_count;
+    vec4 tiling;
+    vec4 roughness;
+    vec4 metallic;
+    vec4 normal_amount;
+    uvec4 has_color;
+    uvec4 has_normal;
+    uvec4 has_rough_metal;
+} uniforms;
+
+uniform sampler2D map_splat;
+uniform sampler2D layer_color[4];
+uniform sampler2D layer_normal[4];
+uniform sampler2D layer_rough_metal[4];
+
+struct Layer {
+    vec3 albedo;
+    vec3 tangent_normal;
+    vec2 rough_metal;
+};
+
+mat3 cotangent_frame(vec3 pos, vec3 normal, vec2 uv) {
+    vec3 dp1 = dFdx(pos);
+    vec3 dp2 = dFdy(pos);
+    vec2 duv1 = dFdx(uv);
+    vec2 duv2 = dFdy(uv);
+    vec3 dp2perp = cross(dp2, normal);
+    vec3 dp1perp = cross(normal, dp1);
+    vec3 T = dp2perp * duv1.x + dp1perp * duv2.x;
+    vec3 B = dp2perp * duv1.y + dp1perp * duv2.y;
+    float invmax = inversesqrt(max(dot(T, T), dot(B, B)));
+    return mat3(T * invmax, B * invmax, normal);
+}
+
+// Samplers are passed in as sampler arrays can only be indexed with constants
+Layer sample_layer(int i, sampler2D color, sampler2D normal, sampler2D rough_metal) {
+    vec2 uv = vs_uv * uniforms.tiling[i];
+    Layer layer;
+    layer.albedo = uniforms.has_color[i] != 0u ? texture(color, uv).rgb : vec3(1);
+    layer.tangent_normal = vec3(0, 0, 1);
+    if (uniforms.has_normal[i] != 0u) {
+        float amount = uniforms.normal_amount[i];
+        layer.tangent_normal = (texture(normal, uv).xyz * 2. - 1.) * vec3(amount, amount, 1.);
+    }
+    layer.rough_metal = vec2(uniforms.roughness[i], uniforms.metallic[i]);
+    if (uniforms.has_rough_metal[i] != 0u)
+        layer.rough_metal *= texture(rough_metal, uv).rg;
+    return layer;
+}
+
+vec4 splat_weights() {
+    vec4 weights = uniforms.weights_from_vertex_color ? vs_color : texture(map_splat, vs_uv);
+    for (uint i = uniforms.layer_count; i < 4u; i++)
+        weights[i] = 0.;
+    float total = dot(weights, vec4(1));
+    // Unpainted areas show the first layer
+    return total > 0. ? weights / total : vec4(1, 0, 0, 0);
+}
+
+void main() {
+    vec4 weights = splat_weights();
+    Layer layers[4];
+    layers[0] = sample_layer(0, layer_color[0], layer_normal[0], layer_rough_metal[0]);
+    layers[1] = sample_layer(1, layer_color[1], layer_normal[1], layer_rough_metal[1]);
+    layers[2] = sample_layer(2, layer_color[2], layer_normal[2], layer_rough_metal[2]);
+    layers[3] = sample_layer(3, layer_color[3], layer_normal[3], layer_rough_metal[3]);
+
+    vec3 albedo = vec3(0);
+    vec3 tangent_normal = vec3(0);
+    vec2 rough_metal = vec2(0);
+    for (int i = 0; i < 4; i++) {
+        albedo += layers[i].albedo * weights[i];
+        tangent_normal += layers[i].tangent_normal * weights[i];
+        rough_metal += layers[i].rough_metal * weights[i];
+    }
+
+    if (!uniforms.weights_from_vertex_color)
+        albedo *= vs_color.rgb;
+
+    mat3 tbn = cotangent_frame(vs_position, vs_normal, vs_uv);
+    vec3 normal = normalize(tbn * tangent_normal);// <- world space
+    write_gbuffer(vs_position, albedo, normal, rough_metal.x, rough_metal.y, vec3(0));
+}
@@ -0,0 +1,44 @@
+// Template of surface shaders, see `rose_renderer::shader_material::ShaderMaterialBuilder::surface`.
+// The snippet of the material follows this file and implements `surface`.
+#include "../common/math.glsl"
+#include "../common/uniforms/view.glsl"
+#include "../common/gbuffer.glsl"
+
+in vec3 vs_position;
+in vec2 vs_uv;
+in vec3 vs_normal;
+in vec4 vs_color;
+in vec2 vs_uv2;
+
+// Interpolated attributes of the fragment.
+struct SurfaceInput {
+    vec3 position;// <- relative to `view.origin`, add it for world space
+    vec3 normal;// <- world space, normalized
+    vec2 uv;
+    vec2 uv2;
+    vec4 color;
+};
+
+// G-Buffer values of the fragment, starting out as a rough dielectric of the vertex color.
+struct Surface {
+    vec3 albedo;// <- linear
+    vec3 normal;// <- world space
+    float roughness;
+    float metallic;
+    vec3 emission;// <- luminance in nits
+    float clearcoat;
+    float clearcoat_roughness;
+    float anisotropy;
+    vec3 anisotropy_direction;// <- world space, along which highlights stretch
+};
+
+void surface(SurfaceInput i, inout Surface s);
+
+void main() {
+    SurfaceInput i = SurfaceInput(vs_position, normalize(vs_normal), vs_uv, vs_uv2, vs_color);
+    Surface s = Surface(vs_color.rgb, i.normal, 0.5, 0., vec3(0), 0., 0., 0., vec3(0));
+    surface(i, s);
+    vec3 normal = normalize(s.normal);
+    write_gbuffer(i.position, s.albedo, normal, s.roughness, s.metallic, s.emission);
+    write_gbuffer_layers(normal, s.clearcoat, s.clearcoat_roughness, s.anisotropy, s.anisotropy_direction);
+}
@@ -0,0 +1,13 @@
+// Example surface shader snippet, see `rose_renderer::shader_material::ShaderMaterialBuilder::surface`.
+// Paints stripes along the first UV coordinate, alternating the material color with the vertex color.
+
+layout(std140) uniform Stripes {
+    vec3 color;// <- linear
+    float frequency;// <- stripes per UV unit
+};
+
+void surface(SurfaceInput i, inout Surface s) {
+    float stripe = step(0.5, fract(i.uv.x * frequency));
+    s.albedo = mix(s.albedo, color, stripe);
+    s.roughness = mix(0.6, 0.3, stripe);
+}
@@ -0,0 +1,69 @@
+#include "../common/uniforms/view.glsl"
+
+// Water surface, blended over the lit scene. Surfaces are expected to be horizontal.
+
+in vec3 vs_position;
+in vec2 vs_uv;
+in vec3 vs_normal;
+in vec4 vs_color;
+in vec2 vs_uv2;
+
+out vec4 out_color;
+
+uniform sampler2D scene_depth;
+uniform sampler2D reflection;
+uniform sampler2D normal_map;
+uniform bool has_reflection;
+uniform bool has_normal_map;
+uniform float time;
+uniform vec3 color;
+uniform float wave_scale;
+uniform vec2 wave_speed;
+uniform float wave_strength;
+uniform float shore_fade;
+uniform float clarity;
+uniform float distortion;
+
+float linear_depth(float depth_value) {
+    vec4 view_pos = view.inv_proj * vec4(0, 0, depth_to_ndc(depth_value), 1);
+    return -view_pos.z / view_pos.w;
+}
+
+// Slope of the surface along X and Z, from two layers of waves scrolling in different directions
+vec2 wave_slope(vec2 pos) {
+    vec2 uv1 = pos * wave_scale + wave_speed * time;
+    vec2 uv2 = pos * wave_scale * 0.63 - wave_speed.yx * time * 0.81;
+    if (has_normal_map) {
+        vec3 n1 = texture(normal_map, uv1).xyz * 2. - 1.;
+        vec3 n2 = texture(normal_map, uv2).xyz * 2. - 1.;
+        return (n1.xy + n2.xy) * 0.5;
+    }
+    // Procedural waves: derivatives of sums of sines
+    vec2 slope = vec2(cos(uv1.x * 6.28) * 0.5, cos(uv1.y * 6.28 + 1.3) * 0.5);
+    slope += vec2(cos(uv2.x * 11.7 + uv2.y * 3.1), cos(uv2.y * 9.3 - uv2.x * 2.3)) * 0.25;
+    return slope;
+}
+
+void main() {
+    vec2 screen_uv = gl_FragCoord.xy / view.viewport.zw;
+    float surface_depth = linear_depth(gl_FragCoord.z);
+    float floor_depth = linear_depth(texture(scene_depth, screen_uv).r);
+    // Manual depth test, as the depth buffer is read rather than attached
+    if (floor_depth < surface_depth)
+        discard;
+    float thickness = floor_depth - surface_depth;
+
+    // Waves stay in place in the world as the origin of the view moves
+    vec2 slope = wave_slope(vs_position.xz + view.origin.xz) * wave_strength;
+    vec3 normal = normalize(vec3(-slope.x, 1, -slope.y));
+    vec3 view_dir = normalize(view.camera_pos - vs_position);
+    float fresnel = 0.02 + 0.98 * pow(1. - max(dot(normal, view_dir), 0.), 5.);
+
+    vec3 reflected = color;
+    if (has_reflection)
+        reflected = texture(reflection, screen_uv + normal.xz * distortion).rgb;
+
+    float shore = clamp(thickness / max(shore_fade, 1e-4), 0., 1.);
+    float absorption = 1. - exp(-thickness / max(clarity, 1e-4));
+    out_color = vec4(mix(color, reflected, fresnel), shore * max(absorption, fresnel));
+}
@@ -0,0 +1,17 @@
+#include "../common/uniforms/view.glsl"
+
+// Unlit line, blended over the lit scene.
+
+in vec4 vs_color;
+
+out vec4 out_color;
+
+uniform sampler2D scene_depth;
+
+void main() {
+    // Manual depth test, as the depth buffer is read rather than attached
+    vec2 screen_uv = gl_FragCoord.xy / view.viewport.zw;
+    if (depth_closer(texture(scene_depth, screen_uv).r, gl_FragCoord.z))
+        discard;
+    out_color = vs_color;
+}
@@ -0,0 +1,57 @@
+#include "../common/uniforms/view.glsl"
+
+// Lines are expanded into quads in screen space: each point is emitted twice, on both sides of the
+// line, and pushed apart along the screen-space normal of the line.
+
+in vec3 position;
+in vec3 prev;
+in vec3 next;
+in float side;
+in float width;
+in vec4 color;
+
+out vec4 vs_color;
+
+vec2 to_screen(vec4 clip) {
+    return clip.xy / clip.w * view.viewport.zw * 0.5;
+}
+
+vec2 perpendicular(vec2 v) {
+    return vec2(-v.y, v.x);
+}
+
+// Zero instead of NaN for segments shrunk to a point by the projection, e.g. seen end-on
+vec2 safe_normalize(vec2 v) {
+    float len = length(v);
+    return len > 1e-6 ? v / len : vec2(0);
+}
+
+void main() {
+    mat4 view_proj = view.mat_proj * view.mat_view;
+    vec4 clip = view_proj * vec4(position, 1);
+    vec2 current = to_screen(clip);
+    vec2 to_prev = current - to_screen(view_proj * vec4(prev, 1));
+    vec2 to_next = to_screen(view_proj * vec4(next, 1)) - current;
+
+    // Endpoints have their neighbor on the missing side set to themselves
+    vec2 dir_prev = prev == position ? vec2(0) : safe_normalize(to_prev);
+    vec2 dir_next = next == position ? vec2(0) : safe_normalize(to_next);
+    if (dir_prev == vec2(0)) {
+        dir_prev = dir_next;
+    } else if (dir_next == vec2(0)) {
+        dir_next = dir_prev;
+    }
+    vec2 before = perpendicular(dir_prev);
+    vec2 normal = safe_normalize(before + perpendicular(dir_next));
+    // Lines folding back in screen space only have the side of the previous segment
+    if (normal == vec2(0)) {
+        normal = before;
+    }
+    // Keep the thickness constant through the corner, up to a limit for sharp corners
+    float miter = 1. / max(dot(normal, before), 0.25);
+
+    vec2 offset = normal * side * width * 0.5 * miter;
+    clip.xy += offset / (view.viewport.zw * 0.5) * clip.w;
+    gl_Position = clip;
+    vs_color = color;
+}
@@ -3,6 +3,7 @@ in vec2 v_uv;
 uniform sampler2D in_texture;
 uniform vec2 screen_size;
 uniform bool first_mip = false;
+uniform float threshold = 0.0;
 
 layout (location = 0) out vec3 downsample;
 
@@ -26,6 +27,17 @@ float avg_karis(vec3 col)
     return 1.0f / (1.0f + luma);
 }
 
+// Soft-knee threshold on the luminance, so that only bright enough areas contribute to the bloom.
+vec3 apply_threshold(vec3 col)
+{
+    float knee = 0.5 * threshold;
+    float lum = rgb2luminance(col);
+    float soft = clamp(lum - threshold + knee, 0.0, 2.0 * knee);
+    soft = soft * soft / (4.0 * knee + 1e-4);
+    float contrib = max(soft, lum - threshold) / max(lum, 1e-4);
+    return col * contrib;
+}
+
 void main() {
     vec2 texel_size = 1 / screen_size;
     float x = texel_size.x;
@@ -48,17 +60,22 @@ void main() {
     vec3 m = texture(in_texture, vec2(v_uv.x + x, v_uv.y - y)).rgb;
 
     if (first_mip) {
+        // Threshold and Karis-weight each group on its plain average, then apply the box weights
         vec3 groups[5];
-        groups[0] = (a+b+d+e) * (0.125f/4.0f);
-        groups[1] = (b+c+e+f) * (0.125f/4.0f);
-        groups[2] = (d+e+g+h) * (0.125f/4.0f);
-        groups[3] = (e+f+h+i) * (0.125f/4.0f);
-        groups[4] = (j+k+l+m) * (0.5f/4.0f);
-        groups[0] *= avg_karis(groups[0]);
-        groups[1] *= avg_karis(groups[1]);
-        groups[2] *= avg_karis(groups[2]);
-        groups[3] *= avg_karis(groups[3]);
-        groups[4] *= avg_karis(groups[4]);
+        groups[0] = (a+b+d+e) * 0.25f;
+        groups[1] = (b+c+e+f) * 0.25f;
+        groups[2] = (d+e+g+h) * 0.25f;
+        groups[3] = (e+f+h+i) * 0.25f;
+        groups[4] = (j+k+l+m) * 0.25f;
+        for (int n = 0; n < 5; n++) {
+            groups[n] = apply_threshold(groups[n]);
+            groups[n] *= avg_karis(groups[n]);
+        }
+        groups[0] *= 0.125f;
+        groups[1] *= 0.125f;
+        groups[2] *= 0.125f;
+        groups[3] *= 0.125f;
+        groups[4] *= 0.5f;
         downsample = groups[0]+groups[1]+groups[2]+groups[3]+groups[4];
     } else {
         downsample = e*0.125;
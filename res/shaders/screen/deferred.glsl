@@ -1,6 +1,8 @@
+#include "../common/gbuffer_normal.glsl"
 #include "../common/uniforms/light.glsl"
 #include "../common/uniforms/view.glsl"
 #include "../common/pbr.glsl"
+#include "../common/area_light.glsl"
 
 in vec2 v_uv;
 
@@ -9,6 +11,41 @@ uniform sampler2D frame_albedo;
 uniform sampler2D frame_normal;
 uniform sampler2D frame_rough_metal;
 uniform sampler2D frame_emission;
+uniform sampler2D frame_coat_aniso;
+
+// Same as `LightCookie::shader_kind`
+const int COOKIE_NONE = 0;
+const int COOKIE_PLANAR = 1;
+const int COOKIE_CUBE = 2;
+uniform int cookie_kind;
+uniform sampler2D cookie;
+uniform mat4 cookie_rotation;// <- world to cookie space
+uniform vec2 cookie_size;// <- world units, planar cookies only
+
+// Must match the face order of `cubemap::CUBE_FACES`
+const vec3 FACE_FORWARD[6] = vec3[6](vec3(1, 0, 0), vec3(-1, 0, 0), vec3(0, 1, 0), vec3(0, -1, 0), vec3(0, 0, 1), vec3(0, 0, -1));
+const vec3 FACE_UP[6] = vec3[6](vec3(0, 1, 0), vec3(0, 1, 0), vec3(0, 0, -1), vec3(0, 0, 1), vec3(0, 1, 0), vec3(0, 1, 0));
+
+// Color the cookie of the light multiplies its light with at this position
+vec3 cookie_color(vec3 position) {
+    if (cookie_kind == COOKIE_PLANAR) {
+        vec2 uv = (mat3(cookie_rotation) * (position + view.origin)).xy / cookie_size;
+        return texture(cookie, uv).rgb;
+    }
+    vec3 dir = mat3(cookie_rotation) * (position - light.pos_dir);
+    vec3 a = abs(dir);
+    int face = a.x >= a.y && a.x >= a.z ? (dir.x > 0.0 ? 0 : 1)
+             : a.y >= a.z ? (dir.y > 0.0 ? 2 : 3)
+             : (dir.z > 0.0 ? 4 : 5);
+    vec3 forward = FACE_FORWARD[face];
+    vec3 up = FACE_UP[face];
+    vec3 right = cross(forward, up);
+    vec2 uv = vec2(dot(dir, right), dot(dir, up)) / dot(dir, forward) * 0.5 + 0.5;
+    // Keep bilinear filtering from reading into neighboring faces
+    float face_texels = float(textureSize(cookie, 0).x) / 3.0;
+    uv = clamp(uv, 0.5 / face_texels, 1.0 - 0.5 / face_texels);
+    return textureLod(cookie, (vec2(face % 3, face / 3) + uv) / vec2(3, 2), 0.0).rgb;
+}
 
 out vec4 out_color;
 
@@ -18,7 +55,7 @@ void main() {
 
     vec3 position = texture(frame_position, v_uv).rgb;
     vec3 albedo = texture(frame_albedo, v_uv).rgb;
-    vec3 normal = nc.rgb;
+    vec3 normal = decode_normal(nc.rgb);
     vec3 rough_metal = texture(frame_rough_metal, v_uv).rgb;
 
     float roughness = rough_metal.r;
@@ -29,18 +66,38 @@ void main() {
         return;
     }
 
+    if (light.kind == LIGHT_KIND_RECT || light.kind == LIGHT_KIND_DISK) {
+        vec3 V = normalize(view.camera_pos - position);
+        vec3 radiance = area_light(position, normal, V, albedo, roughness, metallic);
+        out_color = vec4(radiance + texture(frame_emission, v_uv).rgb, 1.0);
+        return;
+    }
+
+    vec3 light_color = light.color;
+    if (cookie_kind != COOKIE_NONE)
+        light_color *= cookie_color(position);
+
     LightSource src;
     if (light.kind == LIGHT_KIND_POINT) {
         float d = distance(light.pos_dir, position);// <- nominal
-        vec3 dir = normalize(light.pos_dir - position);// <- nominal, world space
-        src = create_light_source(dir, light.color, d);
+        vec3 dir = normalize(light.pos_dir - position);// <- nominal
+        src = create_light_source(dir, light_color, d);
     } else {
-        src = create_light_source(light.pos_dir, light.color, 1);
+        src = create_light_source(light.pos_dir, light_color, 1);
     }
 
+    vec4 coat_aniso = texture(frame_coat_aniso, v_uv);
+    MaterialLayers layers;
+    layers.clearcoat = coat_aniso.r;
+    layers.clearcoat_roughness = coat_aniso.g;
+    layers.anisotropy = coat_aniso.b;
+    layers.T = decode_anisotropy_direction(normal, coat_aniso.a);
+    layers.B = cross(normal, layers.T);
+
+    vec3 V = normalize(view.camera_pos - position);
     LightingMaterial mat = create_material(metallic, roughness);
-    Lighting l = create_lighting(src, mat, normalize(view.camera_pos - position), normal, albedo);
+    Lighting l = create_lighting(src, mat, V, normal, albedo);
 
-    vec3 reflectance = get_lighting(l) + texture(frame_emission, v_uv).rgb;
+    vec3 reflectance = get_layered_lighting(l, layers, V) + texture(frame_emission, v_uv).rgb;
     out_color = vec4(reflectance, 1.0);
 }
\ No newline at end of file
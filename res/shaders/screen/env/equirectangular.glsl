@@ -1,3 +1,4 @@
+#include "../../common/gbuffer_normal.glsl"
 #include "../../common/math.glsl"
 #include "../../common/pbr.glsl"
 #include "../../common/uniforms/view.glsl"
@@ -10,6 +11,8 @@ uniform sampler2D frame_rough_metal;
 uniform sampler2D env_map;
 uniform sampler2D irradiance_map;
 uniform sampler2D specular_map;
+// Reflection probes replace this much of the specular reflection of the environment
+uniform sampler2D reflection_weight;
 
 out vec4 out_color;
 
@@ -35,12 +38,13 @@ vec3 illuminate(vec3 normal) {
     vec3 light = reflect(view, normal);
     vec3 diffuse_color = texture(irradiance_map, normal_to_polar(normal)).rgb;
     vec3 specular_color = textureLod(specular_map, normal_to_polar(light), (rough_metal.r)*10).rgb;
+    specular_color *= 1.0 - texture(reflection_weight, v_uv).r;
 
     return albedo * ((1 - rough_metal.g)*diffuse_color + specular_color);
 }
 
 void main() {
     vec4 nc = texture(frame_normal, v_uv);
-    vec3 color = nc.a <= 0.5 ? background() : illuminate(nc.xyz);
+    vec3 color = nc.a <= 0.5 ? background() : illuminate(decode_normal(nc.xyz));
     out_color = vec4(color, 1);
 }
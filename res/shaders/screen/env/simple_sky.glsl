@@ -1,3 +1,4 @@
+#include "../../common/gbuffer_normal.glsl"
 #include "../../common/math.glsl"
 #include "../../common/uniforms/view.glsl"
 
@@ -5,6 +6,8 @@ in vec2 v_uv;
 
 uniform sampler2D albedo;
 uniform sampler2D normal_map;
+// Reflection probes replace this much of the reflected sky
+uniform sampler2D reflection_weight;
 uniform vec3 horizon_color;
 uniform vec3 ground_color;
 uniform vec3 zenith_color;
@@ -52,9 +55,9 @@ void main() {
         out_color = gradient(lat_pc);
     } else {
         vec3 albedo = texture(albedo, v_uv).rgb;
-        vec3 normal = nc.xyz;
+        vec3 normal = decode_normal(nc.xyz);
         vec3 refl_dir = reflect(get_ray_dir(), normal);
         float lat_pc = refl_dir.y / M_PI;
-        out_color = albedo * gradient(lat_pc);
+        out_color = albedo * gradient(lat_pc) * (1.0 - texture(reflection_weight, v_uv).r);
     }
 }
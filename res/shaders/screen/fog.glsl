@@ -0,0 +1,39 @@
+#include "../common/uniforms/view.glsl"
+
+in vec2 v_uv;
+out vec4 out_color;
+
+uniform sampler2D depth;
+uniform float density = 0.02;
+uniform float height = 0;
+uniform float height_falloff = 0.2;
+uniform vec3 color = vec3(0.6, 0.7, 0.8);
+uniform float max_distance = 1000;
+
+vec3 reconstruct_world(float depth_value) {
+    vec4 ndc = vec4(v_uv * 2.0 - 1.0, depth_to_ndc(depth_value), 1.0);
+    vec4 view_pos = view.inv_proj * ndc;
+    return (view.inv_view * vec4(view_pos.xyz / view_pos.w, 1.0)).xyz;
+}
+
+void main() {
+    vec3 camera = (view.inv_view * vec4(0, 0, 0, 1)).xyz;
+    float d = texture(depth, v_uv).r;
+    vec3 ray;
+    if (depth_is_far(d)) {
+        // No geometry: fog the sky as if it was at the maximum distance. The direction is taken
+        // halfway, as the far plane can be at infinity.
+        ray = normalize(reconstruct_world(0.5) - camera) * max_distance;
+    } else {
+        ray = reconstruct_world(d) - camera;
+        float len = length(ray);
+        ray *= min(1.0, max_distance / max(len, 1e-6));
+    }
+    float distance = length(ray);
+
+    // Optical depth of the fog along the ray, with density(y) = density * exp(-falloff * (y - height))
+    float falloff_dy = height_falloff * ray.y;
+    float factor = abs(falloff_dy) > 1e-4 ? (1.0 - exp(-falloff_dy)) / falloff_dy : 1.0;
+    float optical_depth = density * exp(-height_falloff * (camera.y - height)) * distance * factor;
+    out_color = vec4(color, 1.0 - exp(-optical_depth));
+}
@@ -0,0 +1,44 @@
+#include "../common/gbuffer_normal.glsl"
+
+// Visualization of a G-Buffer attachment or of the depth buffer in the renderer debug panel.
+
+in vec2 v_uv;
+out vec3 out_color;
+
+uniform sampler2D in_texture;
+// 0: color values, 1: depth buffer, 2: encoded normals
+uniform int source_kind = 0;
+// 0: RGB, 1 to 4: R, G, B or A alone
+uniform int channel = 0;
+uniform float range_min = 0;
+uniform float range_max = 1;
+uniform bool linearize_depth = true;
+uniform float depth_near = 0.1;
+uniform float depth_far = 100;
+uniform mat4 inv_proj;
+uniform bool reverse_z = false;
+uniform bool remap_normals = true;
+
+float view_distance(float depth_value) {
+    float ndc_depth = reverse_z ? depth_value : depth_value * 2. - 1.;
+    vec4 view_pos = inv_proj * vec4(0, 0, ndc_depth, 1);
+    return -view_pos.z / view_pos.w;
+}
+
+void main() {
+    vec4 value = texture(in_texture, v_uv);
+    if (source_kind == 1) {
+        float depth = value.r;
+        if (linearize_depth)
+            depth = (view_distance(depth) - depth_near) / max(depth_far - depth_near, 1e-6);
+        out_color = vec3(clamp(depth, 0, 1));
+        return;
+    }
+    if (source_kind == 2) {
+        // Uncovered pixels hold no normal
+        vec3 n = value.a > 0 ? decode_normal(value.xyz) : vec3(0);
+        value.xyz = remap_normals ? n * 0.5 + 0.5 : n;
+    }
+    vec3 color = channel == 0 ? value.rgb : vec3(value[channel - 1]);
+    out_color = clamp((color - range_min) / max(range_max - range_min, 1e-6), 0, 1);
+}
@@ -5,7 +5,8 @@ uniform sampler2D in_texture;
 in vec2 v_uv;
 out float out_color;
 
-/* Ideas taken adapted from https://bruop.github.io/exposure/ */
+/* Ideas taken adapted from https://bruop.github.io/exposure/
+ * The histogram itself is built on the CPU from a downsampled mip of this output. */
 void main() {
-    out_color = mix(0.5, desaturate(texture(in_texture, v_uv).rgb), 0.5);
+    out_color = desaturate(texture(in_texture, v_uv).rgb);
 }
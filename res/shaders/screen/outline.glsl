@@ -0,0 +1,30 @@
+in vec2 v_uv;
+
+uniform sampler2D mask;
+uniform vec4 color;
+uniform float width;
+uniform float fill;
+
+out vec4 out_color;
+
+void main() {
+    if (texture(mask, v_uv).r > 0.5) {
+        if (fill <= 0.0) discard;
+        out_color = vec4(color.rgb, color.a * fill);
+        return;
+    }
+
+    vec2 texel = 1.0 / vec2(textureSize(mask, 0));
+    int radius = int(ceil(width));
+    float coverage = 0.0;
+    for (int y = -radius; y <= radius; y++) {
+        for (int x = -radius; x <= radius; x++) {
+            vec2 offset = vec2(x, y);
+            float falloff = clamp(width - length(offset) + 0.5, 0.0, 1.0);
+            if (falloff <= 0.0) continue;
+            coverage = max(coverage, falloff * texture(mask, v_uv + offset * texel).r);
+        }
+    }
+    if (coverage <= 0.0) discard;
+    out_color = vec4(color.rgb, color.a * coverage);
+}
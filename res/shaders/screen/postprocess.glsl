@@ -12,6 +12,8 @@ uniform float lens_flare_threshold = 20;
 uniform float distortion_amt = 2;
 uniform float ghost_spacing = 0.8;
 uniform int ghost_count = 5;
+// 0: final image, 1: false color, 2: zebra stripes
+uniform int debug_view = 0;
 
 vec3 reinhard(vec3 col) {
     return col / (1.0 + desaturate(col));
@@ -77,9 +79,40 @@ vec3 lens_flare() {
     return ghosts;
 }
 
+// Blue to red heatmap of t in [0, 1]
+vec3 heatmap(float t) {
+    t = clamp(t, 0, 1);
+    return clamp(vec3(1.5) - abs(4 * t - vec3(3, 2, 1)), 0, 1);
+}
+
+// Exposed luminance, from 5 stops under to 5 stops over middle gray
+vec3 false_color(vec3 exposed) {
+    float stops = log2(max(desaturate(exposed), 1e-6) / 0.18);
+    return heatmap((stops + 5) / 10);
+}
+
+// Diagonal stripes over clipped (red) and crushed (blue) pixels of the tonemapped image
+vec3 zebra(vec3 display) {
+    bool stripe = fract((gl_FragCoord.x + gl_FragCoord.y) / 16) < 0.5;
+    if (!stripe)
+        return display;
+    if (any(greaterThanEqual(display, vec3(0.99))))
+        return vec3(1, 0, 0);
+    if (desaturate(display) <= 2e-3)
+        return vec3(0, 0, 1);
+    return display;
+}
+
 void main() {
     vec3 blur = texture(bloom_tex, v_uv).rgb;
     vec3 flare = lens_flare();
     vec3 linear_out = texture(frame, v_uv).rgb + bloom_strength * blur + flare * lens_flare_strength;
-    out_color = vec4(aces(scale_levels(linear_out)), 1);
+    vec3 exposed = scale_levels(linear_out);
+    vec3 display = aces(exposed);
+    if (debug_view == 1)
+        display = false_color(exposed);
+    else if (debug_view == 2)
+        display = zebra(display);
+    // The backbuffer and the viewport textures hold display values, which are sRGB encoded
+    out_color = vec4(linear_to_srgb(display), 1);
 }
\ No newline at end of file
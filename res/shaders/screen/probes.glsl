@@ -0,0 +1,67 @@
+#include "../common/gbuffer_normal.glsl"
+#include "../common/math.glsl"
+
+in vec2 v_uv;
+
+uniform sampler2D frame_position;
+uniform sampler2D frame_albedo;
+uniform sampler2D frame_normal;
+uniform sampler2D frame_rough_metal;
+uniform sampler2D probe_coefficients;
+// Relative to the origin of the view, like the positions of the G-Buffer
+uniform vec3 grid_min;
+uniform vec3 grid_max;
+uniform vec3 grid_resolution;
+
+out vec4 out_color;
+
+vec3 probe_irradiance(ivec3 probe, vec3 n) {
+    ivec3 res = ivec3(grid_resolution);
+    ivec2 base = ivec2(probe.x * 9, probe.y + probe.z * res.y);
+    float basis[9] = float[9](
+        0.282095,
+        0.488603 * n.y,
+        0.488603 * n.z,
+        0.488603 * n.x,
+        1.092548 * n.x * n.y,
+        1.092548 * n.y * n.z,
+        0.315392 * (3.0 * n.z * n.z - 1.0),
+        1.092548 * n.x * n.z,
+        0.546274 * (n.x * n.x - n.y * n.y)
+    );
+    vec3 ret = vec3(0);
+    for (int i = 0; i < 9; i++) {
+        ret += texelFetch(probe_coefficients, base + ivec2(i, 0), 0).rgb * basis[i];
+    }
+    return max(ret, vec3(0));
+}
+
+vec3 sample_grid(vec3 position, vec3 n) {
+    ivec3 res = ivec3(grid_resolution);
+    vec3 extent = max(grid_max - grid_min, vec3(1e-4));
+    vec3 t = clamp((position - grid_min) / extent, 0.0, 1.0) * max(grid_resolution - 1.0, vec3(0));
+    ivec3 p0 = ivec3(floor(t));
+    ivec3 p1 = min(p0 + 1, res - 1);
+    vec3 f = fract(t);
+
+    vec3 ret = vec3(0);
+    for (int i = 0; i < 8; i++) {
+        ivec3 corner = ivec3(i & 1, (i >> 1) & 1, (i >> 2) & 1);
+        ivec3 probe = mix(p0, p1, bvec3(corner));
+        vec3 w3 = mix(1.0 - f, f, vec3(corner));
+        ret += probe_irradiance(probe, n) * w3.x * w3.y * w3.z;
+    }
+    return ret;
+}
+
+void main() {
+    vec4 nc = texture(frame_normal, v_uv);
+    if (nc.a <= 0.5) discard;
+
+    vec3 position = texture(frame_position, v_uv).rgb;
+    vec3 albedo = texture(frame_albedo, v_uv).rgb;
+    float metallic = texture(frame_rough_metal, v_uv).g;
+
+    vec3 irradiance = sample_grid(position, decode_normal(nc.rgb));
+    out_color = vec4((1.0 - metallic) * albedo * irradiance, 1.0);
+}
@@ -0,0 +1,113 @@
+#include "../common/gbuffer_normal.glsl"
+#include "../common/pbr.glsl"
+#include "../common/uniforms/view.glsl"
+
+in vec2 v_uv;
+
+uniform sampler2D frame_position;
+uniform sampler2D frame_albedo;
+uniform sampler2D frame_normal;
+uniform sampler2D frame_rough_metal;
+uniform sampler2D probe_atlas;
+// Two texels per probe: (position relative to `view.origin`, atlas slot), then (box half extents or
+// -radius, intensity)
+uniform sampler2D probe_params;
+uniform int probe_count;
+uniform float max_lod = 4.0;
+
+layout(location=0) out vec4 out_color;
+// Weight of the probe over the environment, which reads it to scale down its own reflections
+layout(location=1) out float out_weight;
+
+// Must match the face order of `reflection_probes.rs`
+const vec3 FACE_FORWARD[6] = vec3[6](vec3(1, 0, 0), vec3(-1, 0, 0), vec3(0, 1, 0), vec3(0, -1, 0), vec3(0, 0, 1), vec3(0, 0, -1));
+const vec3 FACE_UP[6] = vec3[6](vec3(0, 1, 0), vec3(0, 1, 0), vec3(0, 0, -1), vec3(0, 0, 1), vec3(0, 1, 0), vec3(0, 1, 0));
+
+bool in_influence(vec3 offset, vec3 extents) {
+    if (extents.x < 0.0) return dot(offset, offset) <= extents.x * extents.x;
+    return all(lessThanEqual(abs(offset), extents));
+}
+
+// Weight of the probe, fading out over the outer part of the influence volume so that the
+// environment takes over without a seam
+float influence_weight(vec3 offset, vec3 extents) {
+    const float FADE = 0.1;
+    if (extents.x < 0.0) {
+        float radius = -extents.x;
+        return 1.0 - smoothstep((1.0 - FADE) * radius, radius, length(offset));
+    }
+    vec3 inner = (extents - abs(offset)) / (FADE * extents);
+    return smoothstep(0.0, 1.0, clamp(min(min(inner.x, inner.y), inner.z), 0.0, 1.0));
+}
+
+// Direction from the probe center to where the reflected ray leaves the influence volume
+vec3 parallax_correct(vec3 offset, vec3 dir, vec3 extents) {
+    float t;
+    if (extents.x < 0.0) {
+        float b = dot(offset, dir);
+        float c = dot(offset, offset) - extents.x * extents.x;
+        t = -b + sqrt(max(b * b - c, 0.0));
+    } else {
+        vec3 planes = (sign(dir) * extents - offset) / dir;
+        t = min(min(planes.x, planes.y), planes.z);
+    }
+    return offset + dir * max(t, 0.0);
+}
+
+vec3 sample_probe(float slot, vec3 dir, float lod) {
+    vec3 a = abs(dir);
+    int face = a.x >= a.y && a.x >= a.z ? (dir.x > 0.0 ? 0 : 1)
+             : a.y >= a.z ? (dir.y > 0.0 ? 2 : 3)
+             : (dir.z > 0.0 ? 4 : 5);
+    vec3 forward = FACE_FORWARD[face];
+    vec3 up = FACE_UP[face];
+    vec3 right = cross(forward, up);
+    float ma = dot(dir, forward);
+    vec2 uv = vec2(dot(dir, right), dot(dir, up)) / ma * 0.5 + 0.5;
+
+    // Keep bilinear filtering from reading into neighboring faces
+    vec2 atlas_size = vec2(textureSize(probe_atlas, 0));
+    float face_texels = atlas_size.x / 3.0 / exp2(lod);
+    uv = clamp(uv, 0.5 / face_texels, 1.0 - 0.5 / face_texels);
+
+    vec2 cell = vec2(face % 3, slot * 2.0 + float(face / 3));
+    vec2 atlas_uv = (cell + uv) / vec2(3.0, atlas_size.y * 3.0 / atlas_size.x);
+    return textureLod(probe_atlas, atlas_uv, lod).rgb;
+}
+
+void main() {
+    vec4 nc = texture(frame_normal, v_uv);
+    if (nc.a <= 0.5) discard;
+
+    vec3 position = texture(frame_position, v_uv).rgb;
+    int probe = -1;
+    float best_distance = 1e30;
+    for (int i = 0; i < probe_count; i++) {
+        vec4 center = texelFetch(probe_params, ivec2(0, i), 0);
+        vec3 extents = texelFetch(probe_params, ivec2(1, i), 0).xyz;
+        vec3 offset = position - center.xyz;
+        float dist = dot(offset, offset);
+        if (in_influence(offset, extents) && dist < best_distance) {
+            probe = i;
+            best_distance = dist;
+        }
+    }
+    if (probe < 0) discard;
+
+    vec4 center = texelFetch(probe_params, ivec2(0, probe), 0);
+    vec4 extents_intensity = texelFetch(probe_params, ivec2(1, probe), 0);
+
+    vec3 albedo = texture(frame_albedo, v_uv).rgb;
+    vec2 rough_metal = texture(frame_rough_metal, v_uv).rg;
+    vec3 N = decode_normal(nc.rgb);
+    vec3 V = normalize(view.camera_pos - position);
+    vec3 R = reflect(-V, N);
+
+    vec3 dir = parallax_correct(position - center.xyz, R, extents_intensity.xyz);
+    vec3 radiance = sample_probe(center.w, dir, rough_metal.r * max_lod);
+    vec3 F = fresnel_roughness(max(dot(N, V), 0.0), mix(F0, albedo, rough_metal.g), rough_metal.r);
+    // Blended additively with the environment, which scales its reflections by `1 - weight`
+    float weight = influence_weight(position - center.xyz, extents_intensity.xyz);
+    out_color = vec4(weight * F * radiance * extents_intensity.w, 1.0);
+    out_weight = weight;
+}
@@ -1,5 +1,6 @@
 #include "../common/math.glsl"
 #include "../common/uniforms/view.glsl"
+#include "../common/gbuffer.glsl"
 
 const float MAX = 1e3;
 
@@ -16,12 +17,6 @@ in vec3 vs_position;
 in vec2 vs_uv;
 in vec3 vs_normal;
 
-layout(location=0) out vec3 frame_position;
-layout(location=1) out vec3 frame_albedo;
-layout(location=2) out vec4 frame_normal;
-layout(location=3) out vec2 frame_rough_metal;
-layout(location=4) out vec3 frame_emission;
-
 struct Ray {
     vec3 pos, dir;
 };
@@ -68,8 +63,10 @@ float phase_ray(float cc) {
 const int INSCATTER = 4;
 const int OUTSCATTER = 4;
 
+// Positions and distances are in units of the ground radius, so that the planet looks the same at
+// any scale.
 float density(vec3 p, float ph) {
-    return exp(-max(length(p) - atmosphere.ground_radius, 0) / ph);
+    return exp(-max(length(p) - 1.0, 0) / ph);
 }
 
 float accumulate_dentisy(vec3 a, vec3 b, float ph) {
@@ -107,7 +104,7 @@ vec3 in_scatter(Ray ray, vec2 e, vec3 light) {
         n_mie0 += density_mie;
 
         Ray out_ray = Ray(v, light);
-        vec2 f = ray_sphere_isect(out_ray, atmosphere.ground_radius);
+        vec2 f = ray_sphere_isect(out_ray, 1.0);
         vec3 u = ray_at(out_ray, f.y);
         float n_ray1 = accumulate_dentisy(v, u, ph_ray);
         float n_mie1 = accumulate_dentisy(v, u, ph_mie);
@@ -143,24 +140,20 @@ vec3 get_ray_pos() {
 Ray ray_primary() {
     vec3 dir = get_ray_dir();
     //    vec3 pos = view.camera_pos - atmosphere.center;
-    vec3 pos = get_ray_pos() - atmosphere.center;
+    vec3 pos = (get_ray_pos() - atmosphere.center) / atmosphere.ground_radius;
     return Ray(pos, dir);
 }
 
 vec3 get_atmosphere() {
     Ray primary = ray_primary();
-    vec2 e = ray_sphere_isect(primary, atmosphere.atmosphere_radius);
+    vec2 e = ray_sphere_isect(primary, atmosphere.atmosphere_radius / atmosphere.ground_radius);
     if (e.x > e.y) return vec3(0);
 
-    vec2 f = ray_sphere_isect(primary, atmosphere.ground_radius);
+    vec2 f = ray_sphere_isect(primary, 1.0);
     e.y = min(e.y, f.x);
     return in_scatter(primary, e, atmosphere.sun_dir);
 }
 
 void main() {
-    frame_position = vs_position;
-    frame_normal = vec4(-vs_normal, 1);
-    frame_albedo = vec3(0);
-    frame_rough_metal = vec2(1, 0);
-    frame_emission = get_atmosphere();
+    write_gbuffer(vs_position, vec3(0), -vs_normal, 1., 0., get_atmosphere());
 }
\ No newline at end of file
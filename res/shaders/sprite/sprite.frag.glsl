@@ -0,0 +1,21 @@
+#include "../common/uniforms/view.glsl"
+
+// Unlit sprite, blended over the lit scene.
+
+in vec2 vs_uv;
+in vec4 vs_color;
+
+out vec4 out_color;
+
+uniform sampler2D scene_depth;
+uniform sampler2D sprite;
+
+void main() {
+    // Manual depth test, as the depth buffer is read rather than attached
+    vec2 screen_uv = gl_FragCoord.xy / view.viewport.zw;
+    if (depth_closer(texture(scene_depth, screen_uv).r, gl_FragCoord.z))
+        discard;
+    out_color = texture(sprite, vs_uv) * vs_color;
+    if (out_color.a < 1e-3)
+        discard;
+}
@@ -0,0 +1,16 @@
+#include "../common/uniforms/view.glsl"
+
+// Sprite quads are built in world space on the CPU, see `rose_renderer::sprites`.
+
+in vec3 position;
+in vec2 uv;
+in vec4 color;
+
+out vec2 vs_uv;
+out vec4 vs_color;
+
+void main() {
+    vs_uv = uv;
+    vs_color = color;
+    gl_Position = view.mat_proj * view.mat_view * vec4(position, 1);
+}
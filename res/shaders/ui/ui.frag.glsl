@@ -1,5 +1,7 @@
 #version 330
 uniform sampler2D u_sampler;
+// Sampling a viewport texture, in color, rather than the coverage of a font or image
+uniform bool u_color_texture;
 
 in vec4 v_rgba_in_gamma;
 in vec2 v_tc;
@@ -21,6 +23,10 @@ float gamma_from_linear(float col) {
 //}
 
 void main() {
+    if (u_color_texture) {
+        f_color = v_rgba_in_gamma * vec4(texture(u_sampler, v_tc).rgb, 1.0);
+        return;
+    }
 //    vec4 texture_in_gamma = srgba_gamma_from_linear(texture(u_sampler, v_tc).r);
     float texture_in_gamma = gamma_from_linear(texture(u_sampler, v_tc).r);
 